@@ -121,11 +121,15 @@ impl DaemonService {
                 "tcp" => ProtocolConfig::Tcp {
                     local_port,
                     remote_port: None,
+                    hostname: None,
                 },
                 "tls" => ProtocolConfig::Tls {
                     local_port,
                     sni_hostnames: custom_domain.clone().map(|d| vec![d]).unwrap_or_default(),
                     http_port: None,
+                    terminate: false,
+                    reencrypt: false,
+                    alpn: vec![],
                 },
                 other => {
                     return DaemonResponse::Error {
@@ -472,11 +476,15 @@ async fn handle_request(
                 "tcp" => ProtocolConfig::Tcp {
                     local_port,
                     remote_port: None,
+                    hostname: None,
                 },
                 "tls" => ProtocolConfig::Tls {
                     local_port,
                     sni_hostnames: custom_domain.clone().map(|d| vec![d]).unwrap_or_default(),
                     http_port: None,
+                    terminate: false,
+                    reencrypt: false,
+                    alpn: vec![],
                 },
                 other => {
                     return DaemonResponse::Error {
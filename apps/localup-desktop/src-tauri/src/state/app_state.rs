@@ -266,6 +266,7 @@ pub fn build_protocol_config(
         "tcp" => Ok(ProtocolConfig::Tcp {
             local_port,
             remote_port: None,
+            hostname: None,
         }),
         "tls" => Ok(ProtocolConfig::Tls {
             local_port,
@@ -275,6 +276,9 @@ pub fn build_protocol_config(
                 .map(|d| vec![d])
                 .unwrap_or_default(),
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }),
         other => Err(format!("Unknown protocol: {}", other)),
     }
@@ -147,6 +147,27 @@ fn create_logout_cookie(is_https: bool) -> String {
     )
 }
 
+/// Build an API error response
+fn api_error(status: StatusCode, error: &str, code: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            code: Some(code.to_string()),
+        }),
+    )
+}
+
+/// Log a database error and hide it behind a generic 500
+fn api_db_error(e: sea_orm::DbErr) -> (StatusCode, Json<ErrorResponse>) {
+    error!("Database error in API handler: {}", e);
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error",
+        "DB_ERROR",
+    )
+}
+
 /// List all tunnels (active and optionally inactive)
 #[utoipa::path(
     get,
@@ -202,14 +223,27 @@ pub async fn list_tunnels(
                                         .unwrap_or_else(|| "unknown".to_string()),
                                 }
                             }
-                            localup_proto::Protocol::Tcp { port } => {
-                                TunnelProtocol::Tcp { port: *port }
-                            }
+                            localup_proto::Protocol::Tcp { port } => TunnelProtocol::Tcp {
+                                port: *port,
+                                hostname: None,
+                            },
+                            localup_proto::Protocol::TcpNamed { hostname } => TunnelProtocol::Tcp {
+                                port: e.port.unwrap_or(0),
+                                hostname: Some(hostname.clone()),
+                            },
                             localup_proto::Protocol::Tls {
-                                port: _,
-                                sni_patterns,
+                                sni_patterns, alpn, ..
                             } => TunnelProtocol::Tls {
                                 domains: sni_patterns.clone(),
+                                terminated: false,
+                                alpn: alpn.clone(),
+                            },
+                            localup_proto::Protocol::TlsTerminated {
+                                sni_patterns, alpn, ..
+                            } => TunnelProtocol::Tls {
+                                domains: sni_patterns.clone(),
+                                terminated: true,
+                                alpn: alpn.clone(),
                             },
                         },
                         public_url: e.public_url.clone(),
@@ -337,14 +371,27 @@ pub async fn get_tunnel(
                         localup_proto::Protocol::Https { subdomain, .. } => TunnelProtocol::Https {
                             subdomain: subdomain.clone().unwrap_or_else(|| "unknown".to_string()),
                         },
-                        localup_proto::Protocol::Tcp { port } => {
-                            TunnelProtocol::Tcp { port: *port }
-                        }
+                        localup_proto::Protocol::Tcp { port } => TunnelProtocol::Tcp {
+                            port: *port,
+                            hostname: None,
+                        },
+                        localup_proto::Protocol::TcpNamed { hostname } => TunnelProtocol::Tcp {
+                            port: e.port.unwrap_or(0),
+                            hostname: Some(hostname.clone()),
+                        },
                         localup_proto::Protocol::Tls {
-                            port: _,
-                            sni_patterns,
+                            sni_patterns, alpn, ..
+                        } => TunnelProtocol::Tls {
+                            domains: sni_patterns.clone(),
+                            terminated: false,
+                            alpn: alpn.clone(),
+                        },
+                        localup_proto::Protocol::TlsTerminated {
+                            sni_patterns, alpn, ..
                         } => TunnelProtocol::Tls {
                             domains: sni_patterns.clone(),
+                            terminated: true,
+                            alpn: alpn.clone(),
                         },
                     },
                     public_url: e.public_url.clone(),
@@ -408,6 +455,7 @@ pub async fn get_tunnel(
             endpoints.push(TunnelEndpoint {
                 protocol: TunnelProtocol::Tcp {
                     port: tcp_conn.target_port as u16,
+                    hostname: None,
                 },
                 public_url: format!("tcp://relay:{}", tcp_conn.target_port),
                 port: Some(tcp_conn.target_port as u16),
@@ -2456,7 +2504,7 @@ pub async fn request_acme_certificate(
 // ============================================================================
 
 use chrono::Duration;
-use localup_auth::{hash_password, verify_password, JwtClaims, JwtValidator};
+use localup_auth::{hash_password, verify_password, JwtClaims};
 use localup_relay_db::entities::{prelude::User as UserEntity, user};
 use uuid::Uuid;
 
@@ -2597,7 +2645,6 @@ pub async fn register(
     use sha2::{Digest, Sha256};
 
     let auth_token_id = Uuid::new_v4();
-    let jwt_secret = state.jwt_secret.as_bytes();
 
     // Generate JWT auth token (never expires for default token)
     let auth_claims = JwtClaims::new(
@@ -2609,7 +2656,7 @@ pub async fn register(
     .with_user_id(user_id.to_string())
     .with_token_type("auth".to_string());
 
-    let auth_token_jwt = JwtValidator::encode(jwt_secret, &auth_claims).map_err(|e| {
+    let auth_token_jwt = state.jwt_signer.encode(&auth_claims).map_err(|e| {
         tracing::error!("JWT encoding error for auth token: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -2657,7 +2704,6 @@ pub async fn register(
     tracing::info!("Created default auth token for user {}", user_id);
 
     // Generate session token (7 days validity)
-    let jwt_secret = state.jwt_secret.as_bytes();
     let user_role_str = match user.role {
         user::UserRole::Admin => "admin",
         user::UserRole::User => "user",
@@ -2671,7 +2717,7 @@ pub async fn register(
     .with_user_id(user_id.to_string())
     .with_user_role(user_role_str.to_string())
     .with_token_type("session".to_string());
-    let token = JwtValidator::encode(jwt_secret, &claims).map_err(|e| {
+    let token = state.jwt_signer.encode(&claims).map_err(|e| {
         tracing::error!("JWT encoding error: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -2801,7 +2847,6 @@ pub async fn login(
 
     if existing_default_token.is_none() {
         let auth_token_id = Uuid::new_v4();
-        let jwt_secret = state.jwt_secret.as_bytes();
         let now = chrono::Utc::now();
 
         // Generate JWT auth token (never expires for default token)
@@ -2814,7 +2859,7 @@ pub async fn login(
         .with_user_id(user.id.to_string())
         .with_token_type("auth".to_string());
 
-        if let Ok(auth_token_jwt) = JwtValidator::encode(jwt_secret, &auth_claims) {
+        if let Ok(auth_token_jwt) = state.jwt_signer.encode(&auth_claims) {
             // Hash the auth token using SHA-256
             let mut hasher = Sha256::new();
             hasher.update(auth_token_jwt.as_bytes());
@@ -2852,7 +2897,6 @@ pub async fn login(
     }
 
     // Generate session token (7 days validity)
-    let jwt_secret = state.jwt_secret.as_bytes();
     let now = chrono::Utc::now();
     let user_role_str = match user.role {
         user::UserRole::Admin => "admin",
@@ -2867,7 +2911,7 @@ pub async fn login(
     .with_user_id(user.id.to_string())
     .with_user_role(user_role_str.to_string())
     .with_token_type("session".to_string());
-    let token = JwtValidator::encode(jwt_secret, &claims).map_err(|e| {
+    let token = state.jwt_signer.encode(&claims).map_err(|e| {
         tracing::error!("JWT encoding error: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -3070,6 +3114,7 @@ use sha2::{Digest, Sha256};
     request_body = CreateAuthTokenRequest,
     responses(
         (status = 201, description = "Auth token created successfully", body = CreateAuthTokenResponse),
+        (status = 400, description = "Invalid name or scope", body = ErrorResponse),
         (status = 401, description = "Unauthorized", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
@@ -3112,8 +3157,6 @@ pub async fn create_auth_token(
     let expires_at = req.expires_in_days.map(|days| now + Duration::days(days));
 
     // Generate JWT auth token
-    let jwt_secret_bytes = state.jwt_secret.as_bytes();
-
     let mut claims = JwtClaims::new(
         token_id.to_string(),
         "localup-relay".to_string(),
@@ -3131,7 +3174,44 @@ pub async fn create_auth_token(
         claims = claims.with_team_id(team_id_str.clone());
     }
 
-    let token = JwtValidator::encode(jwt_secret_bytes, &claims).map_err(|e| {
+    // Optional scopes, enforced by the relay when the tunnel connects
+    let invalid_scope = |error: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error,
+                code: Some("INVALID_SCOPE".to_string()),
+            }),
+        )
+    };
+    if let Some(protocols) = req.protocols.clone() {
+        if let Some(unknown) = protocols
+            .iter()
+            .find(|p| !["http", "https", "tcp", "tls"].contains(&p.as_str()))
+        {
+            return Err(invalid_scope(format!("Unknown protocol '{}'", unknown)));
+        }
+        claims = claims.with_protocols(protocols);
+    }
+    if let Some(subdomains) = req.allowed_subdomains.clone() {
+        claims = claims.with_allowed_subdomains(subdomains);
+    }
+    if let Some(domains) = req.allowed_domains.clone() {
+        claims = claims.with_allowed_domains(domains);
+    }
+    if let Some(ref ports) = req.allowed_ports {
+        let ranges = ports
+            .iter()
+            .map(|p| p.parse::<localup_auth::PortRange>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid_scope)?;
+        claims = claims.with_allowed_ports(ranges);
+    }
+    if let Some(max) = req.max_tunnels {
+        claims = claims.with_max_tunnels(max);
+    }
+
+    let token = state.jwt_signer.encode(&claims).map_err(|e| {
         tracing::error!("JWT encoding error: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -3546,32 +3626,1335 @@ pub async fn delete_auth_token(
     Ok(StatusCode::NO_CONTENT)
 }
 
-/// Get available transport protocols (well-known endpoint)
+// ============================================================================
+// Token Revocation Handlers
+// ============================================================================
+
+use localup_relay_db::entities::{prelude::RevokedToken as RevokedTokenEntity, revoked_token};
+
+fn revocation_from_model(revocation: revoked_token::Model) -> TokenRevocation {
+    TokenRevocation {
+        jti: revocation.jti,
+        user_id: revocation.user_id.map(|id| id.to_string()),
+        reason: revocation.reason,
+        revoked_by: revocation.revoked_by.map(|id| id.to_string()),
+        expires_at: revocation.expires_at,
+        revoked_at: revocation.revoked_at,
+    }
+}
+
+/// Revoke a token before it expires
 ///
-/// This endpoint is used by clients to discover which transport protocols
-/// are available on this relay (QUIC, WebSocket, HTTP/2).
+/// Users revoke their own tokens by sending the token; admins may also revoke
+/// any token by its `jti`. The relay refuses the token from then on and
+/// disconnects tunnels that are connected with it when it next re-validates
+/// them.
 #[utoipa::path(
-    get,
-    path = "/.well-known/localup-protocols",
+    post,
+    path = "/api/token-revocations",
+    request_body = RevokeTokenRequest,
     responses(
-        (status = 200, description = "Protocol discovery response", body = ProtocolDiscoveryResponse),
-        (status = 204, description = "Protocol discovery not configured")
+        (status = 201, description = "Token revoked", body = TokenRevocation),
+        (status = 200, description = "Token was already revoked", body = TokenRevocation),
+        (status = 400, description = "Invalid request or token", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Token belongs to another user", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
-    tag = "discovery"
+    tag = "auth-tokens",
+    security(("bearer_auth" = []))
 )]
-pub async fn protocol_discovery(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    match &state.protocol_discovery {
-        Some(discovery) => {
-            debug!(
-                "Protocol discovery request, returning {} transports",
-                discovery.transports.len()
-            );
-            Json(discovery.clone()).into_response()
+pub async fn revoke_token(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(req): Json<RevokeTokenRequest>,
+) -> Result<(StatusCode, Json<TokenRevocation>), (StatusCode, Json<ErrorResponse>)> {
+    let user_id = parse_user_id(&auth_user)?;
+    let is_admin = auth_user.role == "admin";
+
+    let (jti, owner_id, expires_at) = match (req.token, req.jti) {
+        (Some(token), _) => {
+            let claims = state.jwt_validator.validate(&token).map_err(|e| {
+                api_error(
+                    StatusCode::BAD_REQUEST,
+                    &format!("Invalid token: {}", e),
+                    "INVALID_TOKEN",
+                )
+            })?;
+            let owner_id = claims
+                .user_id
+                .as_deref()
+                .and_then(|id| Uuid::parse_str(id).ok());
+            if owner_id != Some(user_id) && !is_admin {
+                return Err(api_error(
+                    StatusCode::FORBIDDEN,
+                    "You can only revoke your own tokens",
+                    "FORBIDDEN",
+                ));
+            }
+            let jti = claims.jti.clone().ok_or_else(|| {
+                api_error(
+                    StatusCode::BAD_REQUEST,
+                    "Token has no ID (jti) and can't be revoked; delete or deactivate it instead",
+                    "TOKEN_WITHOUT_JTI",
+                )
+            })?;
+            let expires_at = chrono::DateTime::<Utc>::from_timestamp(claims.exp, 0);
+            (jti, owner_id, expires_at)
         }
-        None => {
-            // Return default QUIC-only response if not configured
-            let default_discovery = localup_proto::ProtocolDiscoveryResponse::quic_only(4443);
-            Json(default_discovery).into_response()
+        (None, Some(jti)) => {
+            if !is_admin {
+                return Err(api_error(
+                    StatusCode::FORBIDDEN,
+                    "Only admins can revoke tokens by ID",
+                    "ADMIN_REQUIRED",
+                ));
+            }
+            (jti, None, None)
+        }
+        (None, None) => {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                "Either token or jti is required",
+                "INVALID_REQUEST",
+            ));
         }
+    };
+
+    if let Some(existing) = RevokedTokenEntity::find()
+        .filter(revoked_token::Column::Jti.eq(&jti))
+        .one(&state.db)
+        .await
+        .map_err(api_db_error)?
+    {
+        return Ok((StatusCode::OK, Json(revocation_from_model(existing))));
+    }
+
+    let revocation = revoked_token::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        jti: Set(jti),
+        user_id: Set(owner_id),
+        reason: Set(req.reason.filter(|reason| !reason.trim().is_empty())),
+        revoked_by: Set(Some(user_id)),
+        expires_at: Set(expires_at),
+        revoked_at: Set(Utc::now()),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(api_db_error)?;
+
+    info!("Token {} revoked by user {}", revocation.jti, user_id);
+    Ok((StatusCode::CREATED, Json(revocation_from_model(revocation))))
+}
+
+/// List revoked tokens
+///
+/// Admins see every revocation; other users see those of their own tokens.
+#[utoipa::path(
+    get,
+    path = "/api/token-revocations",
+    responses(
+        (status = 200, description = "List of revoked tokens", body = TokenRevocationList),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth-tokens",
+    security(("bearer_auth" = []))
+)]
+pub async fn list_token_revocations(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<Json<TokenRevocationList>, (StatusCode, Json<ErrorResponse>)> {
+    use sea_orm::QueryOrder;
+
+    let user_id = parse_user_id(&auth_user)?;
+    let mut select = RevokedTokenEntity::find();
+    if auth_user.role != "admin" {
+        select = select.filter(revoked_token::Column::UserId.eq(user_id));
+    }
+
+    let revocations: Vec<TokenRevocation> = select
+        .order_by_desc(revoked_token::Column::RevokedAt)
+        .all(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .into_iter()
+        .map(revocation_from_model)
+        .collect();
+    let total = revocations.len();
+
+    Ok(Json(TokenRevocationList { revocations, total }))
+}
+
+// ============================================================================
+// Share Link Handlers
+// ============================================================================
+
+use localup_http_auth::{ShareLinkClaims, ShareLinkSigner, SHARE_LINK_PARAM};
+use localup_relay_db::entities::{prelude::ShareLink as ShareLinkEntity, share_link};
+
+/// Default share link lifetime
+const DEFAULT_SHARE_LINK_HOURS: i64 = 24;
+
+/// Longest allowed share link lifetime (30 days)
+const MAX_SHARE_LINK_HOURS: i64 = 24 * 30;
+
+fn share_link_from_model(link: share_link::Model) -> ShareLink {
+    let used_up = link.max_uses.is_some_and(|max| link.use_count >= max);
+    ShareLink {
+        id: link.id.to_string(),
+        is_active: link.is_active() && !used_up,
+        tunnel_id: link.localup_id,
+        path_prefix: link.path_prefix,
+        name: link.name,
+        max_uses: link.max_uses,
+        use_count: link.use_count,
+        last_used_at: link.last_used_at,
+        expires_at: link.expires_at,
+        revoked_at: link.revoked_at,
+        created_at: link.created_at,
+    }
+}
+
+/// Find a share link owned by the authenticated user
+async fn find_owned_share_link(
+    state: &AppState,
+    auth_user: &AuthUser,
+    id: &str,
+) -> Result<share_link::Model, (StatusCode, Json<ErrorResponse>)> {
+    let link_id = Uuid::parse_str(id).map_err(|_| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            "Invalid share link ID format",
+            "INVALID_ID",
+        )
+    })?;
+
+    let link = ShareLinkEntity::find_by_id(link_id)
+        .one(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Share link not found", "NOT_FOUND"))?;
+
+    if link.user_id.to_string() != auth_user.user_id {
+        return Err(api_error(
+            StatusCode::FORBIDDEN,
+            "You don't have permission to access this share link",
+            "FORBIDDEN",
+        ));
+    }
+    Ok(link)
+}
+
+/// Create a signed, expiring share link for a tunnel
+#[utoipa::path(
+    post,
+    path = "/api/tunnels/{id}/share-links",
+    params(
+        ("id" = String, Path, description = "Tunnel ID")
+    ),
+    request_body = CreateShareLinkRequest,
+    responses(
+        (status = 201, description = "Share link created", body = CreateShareLinkResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Tunnel not connected, not yours or has no HTTP endpoint", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "share-links",
+    security(("bearer_auth" = []))
+)]
+pub async fn create_share_link(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<String>,
+    Json(req): Json<CreateShareLinkRequest>,
+) -> Result<(StatusCode, Json<CreateShareLinkResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let user_id = Uuid::parse_str(&auth_user.user_id).map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Invalid user ID format",
+            "INVALID_USER_ID",
+        )
+    })?;
+
+    let path_prefix = req.path_prefix.unwrap_or_else(|| "/".to_string());
+    if !path_prefix.starts_with('/') || path_prefix.contains(['?', '#']) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Path prefix must start with '/' and cannot contain '?' or '#'",
+            "INVALID_PATH",
+        ));
+    }
+
+    let hours = req.expires_in_hours.unwrap_or(DEFAULT_SHARE_LINK_HOURS);
+    if !(1..=MAX_SHARE_LINK_HOURS).contains(&hours) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "Expiry must be between 1 and {} hours",
+                MAX_SHARE_LINK_HOURS
+            ),
+            "INVALID_EXPIRY",
+        ));
+    }
+
+    if req.max_uses.is_some_and(|max| max < 1) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Maximum uses must be at least 1",
+            "INVALID_MAX_USES",
+        ));
+    }
+
+    // Share links bypass the tunnel's own authentication, so only its owner or
+    // a member of its team may create one. Others get the same 404 as for a
+    // tunnel that isn't connected, so tunnel IDs can't be probed.
+    let not_found = || {
+        api_error(
+            StatusCode::NOT_FOUND,
+            "Tunnel is not connected or has no HTTP endpoint",
+            "TUNNEL_NOT_FOUND",
+        )
+    };
+    let owner = state
+        .localup_manager
+        .get_owner(&id)
+        .await
+        .ok_or_else(not_found)?;
+    let is_owner = owner.user_id.as_deref() == Some(auth_user.user_id.as_str());
+    let is_team_member = match owner.team_id.as_deref().map(Uuid::parse_str) {
+        Some(Ok(team_id)) if !is_owner => user_team_roles(&state, user_id)
+            .await?
+            .iter()
+            .any(|(member_of, _)| *member_of == team_id),
+        _ => false,
+    };
+    if !is_owner && !is_team_member {
+        return Err(not_found());
+    }
+
+    // The link points at the tunnel's public HTTP(S) URL
+    let public_url = state
+        .localup_manager
+        .get_endpoints(&id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .find(|e| {
+            matches!(
+                e.protocol,
+                localup_proto::Protocol::Http { .. } | localup_proto::Protocol::Https { .. }
+            )
+        })
+        .map(|e| e.public_url)
+        .ok_or_else(not_found)?;
+
+    let now = Utc::now();
+    let link = share_link::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(user_id),
+        localup_id: Set(id.clone()),
+        path_prefix: Set(path_prefix.clone()),
+        name: Set(req.name),
+        max_uses: Set(req.max_uses),
+        use_count: Set(0),
+        last_used_at: Set(None),
+        expires_at: Set(now + Duration::hours(hours)),
+        revoked_at: Set(None),
+        created_at: Set(now),
+    }
+    .insert(&state.db)
+    .await
+    .map_err(api_db_error)?;
+
+    let token = ShareLinkSigner::new(&state.jwt_secret).sign_link(&ShareLinkClaims {
+        id: link.id.to_string(),
+        tunnel: id.clone(),
+        path: path_prefix.clone(),
+        exp: link.expires_at.timestamp() as u64,
+    });
+    let url = format!(
+        "{}{}?{}={}",
+        public_url.trim_end_matches('/'),
+        path_prefix,
+        SHARE_LINK_PARAM,
+        token
+    );
+
+    info!(
+        "Share link {} created for tunnel {} (path {}, expires {})",
+        link.id, id, path_prefix, link.expires_at
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateShareLinkResponse {
+            link: share_link_from_model(link),
+            url,
+        }),
+    ))
+}
+
+/// List the authenticated user's share links
+#[utoipa::path(
+    get,
+    path = "/api/share-links",
+    params(
+        ("tunnel_id" = Option<String>, Query, description = "Only list links for this tunnel")
+    ),
+    responses(
+        (status = 200, description = "List of share links", body = ShareLinkList),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "share-links",
+    security(("bearer_auth" = []))
+)]
+pub async fn list_share_links(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<ShareLinkQuery>,
+) -> Result<Json<ShareLinkList>, (StatusCode, Json<ErrorResponse>)> {
+    use sea_orm::QueryOrder;
+
+    let user_id = Uuid::parse_str(&auth_user.user_id).map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Invalid user ID format",
+            "INVALID_USER_ID",
+        )
+    })?;
+
+    let mut select = ShareLinkEntity::find().filter(share_link::Column::UserId.eq(user_id));
+    if let Some(tunnel_id) = query.tunnel_id {
+        select = select.filter(share_link::Column::LocalupId.eq(tunnel_id));
+    }
+
+    let links: Vec<ShareLink> = select
+        .order_by_desc(share_link::Column::CreatedAt)
+        .all(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .into_iter()
+        .map(share_link_from_model)
+        .collect();
+    let total = links.len();
+
+    Ok(Json(ShareLinkList { links, total }))
+}
+
+/// Get share link details
+#[utoipa::path(
+    get,
+    path = "/api/share-links/{id}",
+    params(
+        ("id" = String, Path, description = "Share link ID")
+    ),
+    responses(
+        (status = 200, description = "Share link details", body = ShareLink),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "share-links",
+    security(("bearer_auth" = []))
+)]
+pub async fn get_share_link(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<String>,
+) -> Result<Json<ShareLink>, (StatusCode, Json<ErrorResponse>)> {
+    let link = find_owned_share_link(&state, &auth_user, &id).await?;
+    Ok(Json(share_link_from_model(link)))
+}
+
+/// Revoke a share link
+///
+/// Clients that already opened the link lose access once the relay rechecks
+/// it (within a few seconds).
+#[utoipa::path(
+    delete,
+    path = "/api/share-links/{id}",
+    params(
+        ("id" = String, Path, description = "Share link ID")
+    ),
+    responses(
+        (status = 204, description = "Share link revoked"),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "share-links",
+    security(("bearer_auth" = []))
+)]
+pub async fn revoke_share_link(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let link = find_owned_share_link(&state, &auth_user, &id).await?;
+    if link.revoked_at.is_some() {
+        return Ok(StatusCode::NO_CONTENT);
     }
+
+    let mut active_link: share_link::ActiveModel = link.into();
+    active_link.revoked_at = Set(Some(Utc::now()));
+    active_link.update(&state.db).await.map_err(api_db_error)?;
+
+    info!("Share link {} revoked", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ============================================================================
+// TCP Port Reservation Handlers
+// ============================================================================
+
+use localup_control::port_assignments::DEFAULT_RETENTION_DAYS;
+use localup_control::quotas::{load_limits, QuotaAccount};
+use localup_relay_db::entities::{
+    prelude::{
+        TcpPortAssignment as TcpPortAssignmentEntity, Team as TeamEntity,
+        TeamMember as TeamMemberEntity,
+    },
+    tcp_port_assignment, team_member,
+};
+
+fn tcp_port_from_model(assignment: tcp_port_assignment::Model) -> TcpPortAssignment {
+    TcpPortAssignment {
+        port: assignment.port as u16,
+        reserved: assignment.reserved,
+        team_id: assignment.team_id.map(|id| id.to_string()),
+        local_port: assignment.local_port.map(|p| p as u16),
+        name: assignment.name,
+        tunnel_id: assignment.localup_id,
+        last_used_at: assignment.last_used_at,
+        created_at: assignment.created_at,
+    }
+}
+
+/// Whether an assignment still belongs to its owner (see `DbPortAllocator`)
+fn tcp_port_is_held(assignment: &tcp_port_assignment::Model) -> bool {
+    let last_used = assignment.last_used_at.unwrap_or(assignment.created_at);
+    assignment.reserved || last_used + Duration::days(DEFAULT_RETENTION_DAYS) > Utc::now()
+}
+
+/// Teams the user belongs to, with their role in each
+async fn user_team_roles(
+    state: &AppState,
+    user_id: Uuid,
+) -> Result<Vec<(Uuid, team_member::TeamRole)>, (StatusCode, Json<ErrorResponse>)> {
+    Ok(TeamMemberEntity::find()
+        .filter(team_member::Column::UserId.eq(user_id))
+        .all(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .into_iter()
+        .map(|m| (m.team_id, m.role))
+        .collect())
+}
+
+fn parse_user_id(auth_user: &AuthUser) -> Result<Uuid, (StatusCode, Json<ErrorResponse>)> {
+    Uuid::parse_str(&auth_user.user_id).map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Invalid user ID format",
+            "INVALID_USER_ID",
+        )
+    })
+}
+
+/// List TCP ports held by the authenticated user or their teams
+#[utoipa::path(
+    get,
+    path = "/api/tcp-ports",
+    responses(
+        (status = 200, description = "List of TCP port assignments", body = TcpPortAssignmentList),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "tcp-ports",
+    security(("bearer_auth" = []))
+)]
+pub async fn list_tcp_ports(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+) -> Result<Json<TcpPortAssignmentList>, (StatusCode, Json<ErrorResponse>)> {
+    use sea_orm::{Condition, QueryOrder};
+
+    let user_id = parse_user_id(&auth_user)?;
+    let team_ids: Vec<Uuid> = user_team_roles(&state, user_id)
+        .await?
+        .into_iter()
+        .map(|(team_id, _)| team_id)
+        .collect();
+
+    let ports: Vec<TcpPortAssignment> = TcpPortAssignmentEntity::find()
+        .filter(
+            Condition::any()
+                .add(tcp_port_assignment::Column::UserId.eq(user_id))
+                .add(tcp_port_assignment::Column::TeamId.is_in(team_ids)),
+        )
+        .order_by_asc(tcp_port_assignment::Column::Port)
+        .all(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .into_iter()
+        .map(tcp_port_from_model)
+        .collect();
+    let total = ports.len();
+
+    Ok(Json(TcpPortAssignmentList { ports, total }))
+}
+
+/// Reserve a TCP port
+///
+/// Reserved ports are only handed to tunnels authenticated as the reserving user
+/// and are kept until released. Without an explicit port, the port last used for
+/// `local_port` is converted into a reservation, or a free port is picked.
+#[utoipa::path(
+    post,
+    path = "/api/tcp-ports",
+    request_body = ReserveTcpPortRequest,
+    responses(
+        (status = 201, description = "Port reserved", body = TcpPortAssignment),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Not a team member or quota reached", body = ErrorResponse),
+        (status = 404, description = "TCP ports are not enabled on this relay", body = ErrorResponse),
+        (status = 409, description = "Port is held by someone else or already reserved", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "tcp-ports",
+    security(("bearer_auth" = []))
+)]
+pub async fn reserve_tcp_port(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(req): Json<ReserveTcpPortRequest>,
+) -> Result<(StatusCode, Json<TcpPortAssignment>), (StatusCode, Json<ErrorResponse>)> {
+    let policy = state.tcp_port_policy.clone().ok_or_else(|| {
+        api_error(
+            StatusCode::NOT_FOUND,
+            "TCP ports are not enabled on this relay",
+            "TCP_PORTS_DISABLED",
+        )
+    })?;
+    let user_id = parse_user_id(&auth_user)?;
+
+    let team_id = req
+        .team_id
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(|_| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid team ID format",
+                "INVALID_TEAM_ID",
+            )
+        })?;
+
+    // Quota: the account's quota or plan, the team's own, or the relay default
+    let account = match team_id {
+        Some(team_id) => QuotaAccount::team(team_id),
+        None => QuotaAccount::user(user_id),
+    };
+    let planned = load_limits(&state.db, &account)
+        .await
+        .map_err(api_db_error)?
+        .max_reserved_ports;
+    let quota = match team_id {
+        Some(team_id) => {
+            let is_member = user_team_roles(&state, user_id)
+                .await?
+                .iter()
+                .any(|(id, _)| *id == team_id);
+            if !is_member {
+                return Err(api_error(
+                    StatusCode::FORBIDDEN,
+                    "You are not a member of this team",
+                    "NOT_TEAM_MEMBER",
+                ));
+            }
+            let team = TeamEntity::find_by_id(team_id)
+                .one(&state.db)
+                .await
+                .map_err(api_db_error)?
+                .ok_or_else(|| {
+                    api_error(StatusCode::NOT_FOUND, "Team not found", "TEAM_NOT_FOUND")
+                })?;
+            planned
+                .or(team.tcp_port_quota.map(|quota| quota.max(0) as u32))
+                .or(policy.default_quota)
+        }
+        None => planned.or(policy.default_quota),
+    };
+
+    let assignments = TcpPortAssignmentEntity::find()
+        .all(&state.db)
+        .await
+        .map_err(api_db_error)?;
+
+    if let Some(quota) = quota {
+        let reserved = assignments
+            .iter()
+            .filter(|a| a.reserved)
+            .filter(|a| match team_id {
+                Some(team_id) => a.team_id == Some(team_id),
+                None => a.team_id.is_none() && a.user_id == Some(user_id),
+            })
+            .count();
+        if reserved >= quota as usize {
+            return Err(api_error(
+                StatusCode::FORBIDDEN,
+                &format!("Reservation quota of {} TCP ports reached", quota),
+                "QUOTA_EXCEEDED",
+            ));
+        }
+    }
+
+    let in_range = |port: u16| (policy.range_start..=policy.range_end).contains(&port);
+    let (port, existing) = match req.port {
+        Some(port) => {
+            if !in_range(port) {
+                return Err(api_error(
+                    StatusCode::BAD_REQUEST,
+                    &format!(
+                        "Port must be between {} and {}",
+                        policy.range_start, policy.range_end
+                    ),
+                    "PORT_OUT_OF_RANGE",
+                ));
+            }
+            (port, assignments.iter().find(|a| a.port == port as i32))
+        }
+        None => {
+            // Prefer the port the user's tunnel for this local port had last time
+            let sticky = req.local_port.and_then(|local_port| {
+                assignments.iter().find(|a| {
+                    a.user_id == Some(user_id)
+                        && !a.reserved
+                        && a.local_port == Some(local_port as i32)
+                        && in_range(a.port as u16)
+                })
+            });
+            match sticky {
+                Some(assignment) => (assignment.port as u16, Some(assignment)),
+                None => {
+                    let port = (policy.range_start..=policy.range_end)
+                        .find(|&port| {
+                            !assignments
+                                .iter()
+                                .any(|a| a.port == port as i32 && tcp_port_is_held(a))
+                        })
+                        .ok_or_else(|| {
+                            api_error(
+                                StatusCode::CONFLICT,
+                                "No free TCP ports left on this relay",
+                                "NO_FREE_PORTS",
+                            )
+                        })?;
+                    (port, assignments.iter().find(|a| a.port == port as i32))
+                }
+            }
+        }
+    };
+
+    let now = Utc::now();
+    let assignment = match existing {
+        Some(a) if a.user_id == Some(user_id) => {
+            if a.reserved {
+                return Err(api_error(
+                    StatusCode::CONFLICT,
+                    &format!("Port {} is already reserved", a.port),
+                    "ALREADY_RESERVED",
+                ));
+            }
+            let mut model: tcp_port_assignment::ActiveModel = a.clone().into();
+            model.reserved = Set(true);
+            model.team_id = Set(team_id);
+            if req.local_port.is_some() {
+                model.local_port = Set(req.local_port.map(i32::from));
+            }
+            model.name = Set(req.name);
+            model.update(&state.db).await
+        }
+        Some(a) if tcp_port_is_held(a) => {
+            return Err(api_error(
+                StatusCode::CONFLICT,
+                &format!("Port {} is held by another user", a.port),
+                "PORT_TAKEN",
+            ));
+        }
+        Some(a) => {
+            // Stale assignment of someone else
+            let mut model: tcp_port_assignment::ActiveModel = a.clone().into();
+            model.user_id = Set(Some(user_id));
+            model.team_id = Set(team_id);
+            model.local_port = Set(req.local_port.map(i32::from));
+            model.localup_id = Set(None);
+            model.reserved = Set(true);
+            model.name = Set(req.name);
+            model.last_used_at = Set(None);
+            model.created_at = Set(now);
+            model.update(&state.db).await
+        }
+        None => {
+            tcp_port_assignment::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                port: Set(port as i32),
+                user_id: Set(Some(user_id)),
+                team_id: Set(team_id),
+                local_port: Set(req.local_port.map(i32::from)),
+                localup_id: Set(None),
+                reserved: Set(true),
+                name: Set(req.name),
+                last_used_at: Set(None),
+                created_at: Set(now),
+            }
+            .insert(&state.db)
+            .await
+        }
+    }
+    .map_err(api_db_error)?;
+
+    info!(
+        "TCP port {} reserved by user {}",
+        assignment.port, auth_user.user_id
+    );
+    Ok((StatusCode::CREATED, Json(tcp_port_from_model(assignment))))
+}
+
+/// Release a TCP port
+///
+/// Works for reservations and for sticky ports remembered from earlier tunnels.
+/// Team owners and admins can release reservations made for their team.
+#[utoipa::path(
+    delete,
+    path = "/api/tcp-ports/{port}",
+    params(
+        ("port" = u16, Path, description = "Public TCP port")
+    ),
+    responses(
+        (status = 204, description = "Port released"),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Port belongs to someone else", body = ErrorResponse),
+        (status = 404, description = "Port not assigned", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "tcp-ports",
+    security(("bearer_auth" = []))
+)]
+pub async fn release_tcp_port(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(port): Path<u16>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = parse_user_id(&auth_user)?;
+
+    let assignment = TcpPortAssignmentEntity::find()
+        .filter(tcp_port_assignment::Column::Port.eq(port as i32))
+        .one(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Port not assigned", "NOT_FOUND"))?;
+
+    let is_owner = assignment.user_id == Some(user_id);
+    let is_team_admin = match assignment.team_id {
+        Some(team_id) => user_team_roles(&state, user_id)
+            .await?
+            .iter()
+            .any(|(id, role)| {
+                *id == team_id
+                    && matches!(
+                        role,
+                        team_member::TeamRole::Owner | team_member::TeamRole::Admin
+                    )
+            }),
+        None => false,
+    };
+    if !is_owner && !is_team_admin {
+        return Err(api_error(
+            StatusCode::FORBIDDEN,
+            "You don't have permission to release this port",
+            "FORBIDDEN",
+        ));
+    }
+
+    TcpPortAssignmentEntity::delete_by_id(assignment.id)
+        .exec(&state.db)
+        .await
+        .map_err(api_db_error)?;
+
+    info!("TCP port {} released by user {}", port, auth_user.user_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ============================================================================
+// Quota Handlers
+// ============================================================================
+
+/// Get quota limits and current usage
+///
+/// Tunnels opened with a team token count against the team, all others against
+/// the token's user.
+#[utoipa::path(
+    get,
+    path = "/api/quotas/usage",
+    params(
+        ("team_id" = Option<String>, Query, description = "Show the quota of this team instead of your own")
+    ),
+    responses(
+        (status = 200, description = "Quota limits and usage", body = QuotaUsageReport),
+        (status = 400, description = "Invalid team ID", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Not a team member", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "quotas",
+    security(("bearer_auth" = []))
+)]
+pub async fn get_quota_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<QuotaUsageQuery>,
+) -> Result<Json<QuotaUsageReport>, (StatusCode, Json<ErrorResponse>)> {
+    use localup_control::quotas::{current_period, load_usage};
+    use localup_relay_db::entities::quota::QuotaSubject;
+
+    let user_id = parse_user_id(&auth_user)?;
+    let team_id = query
+        .team_id
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(|_| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid team ID format",
+                "INVALID_TEAM_ID",
+            )
+        })?;
+
+    let account = match team_id {
+        Some(team_id) => {
+            let is_member = user_team_roles(&state, user_id)
+                .await?
+                .iter()
+                .any(|(id, _)| *id == team_id);
+            if !is_member {
+                return Err(api_error(
+                    StatusCode::FORBIDDEN,
+                    "You are not a member of this team",
+                    "NOT_TEAM_MEMBER",
+                ));
+            }
+            QuotaAccount::team(team_id)
+        }
+        None => QuotaAccount::user(user_id),
+    };
+
+    let limits = load_limits(&state.db, &account)
+        .await
+        .map_err(api_db_error)?;
+    let period = current_period();
+    let (mut bytes_in, mut bytes_out) = load_usage(&state.db, &account, &period)
+        .await
+        .map_err(api_db_error)?;
+
+    let reserved_ports = TcpPortAssignmentEntity::find()
+        .filter(tcp_port_assignment::Column::Reserved.eq(true))
+        .all(&state.db)
+        .await
+        .map_err(api_db_error)?
+        .iter()
+        .filter(|a| match team_id {
+            Some(team_id) => a.team_id == Some(team_id),
+            None => a.team_id.is_none() && a.user_id == Some(user_id),
+        })
+        .count();
+
+    // Traffic of connected tunnels that is not yet in the database
+    let live = state.localup_manager.account_usage(&account).await;
+    if let Some(ref usage) = live {
+        let (pending_in, pending_out) = usage.pending_bytes();
+        bytes_in += pending_in;
+        bytes_out += pending_out;
+    }
+
+    Ok(Json(QuotaUsageReport {
+        subject_type: match account.subject {
+            QuotaSubject::User => "user".to_string(),
+            QuotaSubject::Team => "team".to_string(),
+        },
+        subject_id: account.id.to_string(),
+        period,
+        limits: QuotaLimitsInfo {
+            max_tunnels: limits.max_tunnels,
+            max_custom_domains: limits.max_custom_domains,
+            max_reserved_ports: limits.max_reserved_ports,
+            monthly_bytes: limits.monthly_bytes,
+            max_connections: limits.max_connections,
+        },
+        tunnels: live.as_ref().map_or(0, |u| u.tunnels()),
+        custom_domains: live
+            .as_ref()
+            .map(|u| u.custom_domains())
+            .unwrap_or_default(),
+        reserved_ports,
+        bytes_in,
+        bytes_out,
+        connections: live.as_ref().map_or(0, |u| u.connections()),
+    }))
+}
+
+// ============================================================================
+// Usage Metering Handlers
+// ============================================================================
+
+use localup_control::metering::{load_rollups, MeterCounts, UsageSample, UsageScope};
+use std::collections::BTreeMap;
+
+/// Tunnels whose traffic a usage query covers
+async fn usage_scope(
+    state: &AppState,
+    auth_user: &AuthUser,
+    query: &UsageQuery,
+) -> Result<UsageScope, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = parse_user_id(auth_user)?;
+    let is_admin = auth_user.role == "admin";
+    let forbidden = |error: &str, code: &str| api_error(StatusCode::FORBIDDEN, error, code);
+
+    if let Some(ref team_id) = query.team_id {
+        let team_id = Uuid::parse_str(team_id).map_err(|_| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid team ID format",
+                "INVALID_TEAM_ID",
+            )
+        })?;
+        let is_member = user_team_roles(state, user_id)
+            .await?
+            .iter()
+            .any(|(id, _)| *id == team_id);
+        if !is_member && !is_admin {
+            return Err(forbidden(
+                "You are not a member of this team",
+                "NOT_TEAM_MEMBER",
+            ));
+        }
+        return Ok(UsageScope::Team(team_id));
+    }
+
+    if let Some(ref other_id) = query.user_id {
+        let other_id = Uuid::parse_str(other_id).map_err(|_| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid user ID format",
+                "INVALID_USER_ID",
+            )
+        })?;
+        if other_id != user_id && !is_admin {
+            return Err(forbidden(
+                "Only admins can view the usage of other users",
+                "ADMIN_REQUIRED",
+            ));
+        }
+        return Ok(UsageScope::User(other_id));
+    }
+
+    if query.all {
+        if !is_admin {
+            return Err(forbidden(
+                "Only admins can view the usage of all tunnels",
+                "ADMIN_REQUIRED",
+            ));
+        }
+        return Ok(UsageScope::All);
+    }
+
+    Ok(UsageScope::User(user_id))
+}
+
+type UsageGroupKey = (
+    Option<chrono::DateTime<Utc>>,
+    Option<String>,
+    Option<String>,
+    Option<Uuid>,
+    Option<Uuid>,
+);
+
+/// Group a sample by hour, tunnel, token hash, user and team as `group_by` asks
+fn usage_group_key(sample: &UsageSample, group_by: UsageGroupBy) -> UsageGroupKey {
+    let key = &sample.key;
+    match group_by {
+        UsageGroupBy::Hour => (Some(sample.hour), None, None, None, None),
+        UsageGroupBy::Tunnel => (
+            None,
+            Some(key.localup_id.clone()),
+            None,
+            key.user_id,
+            key.team_id,
+        ),
+        UsageGroupBy::Token => (None, None, key.token_hash.clone(), key.user_id, key.team_id),
+        UsageGroupBy::User => (None, None, None, key.user_id, None),
+        UsageGroupBy::Team => (None, None, None, None, key.team_id),
+    }
+}
+
+fn usage_record(counts: &MeterCounts) -> UsageRecord {
+    UsageRecord {
+        bytes_in: counts.bytes_in,
+        bytes_out: counts.bytes_out,
+        requests: counts.requests,
+        connections: counts.connections,
+        connection_seconds: counts.connection_millis as f64 / 1000.0,
+        ..Default::default()
+    }
+}
+
+/// Metered traffic from the database plus what the relay has not written yet
+async fn usage_report(
+    state: &AppState,
+    auth_user: &AuthUser,
+    query: &UsageQuery,
+) -> Result<UsageReport, (StatusCode, Json<ErrorResponse>)> {
+    use chrono::{Datelike, TimeZone};
+
+    let scope = usage_scope(state, auth_user, query).await?;
+    let now = Utc::now();
+    let to = query.to.unwrap_or(now);
+    let from = query.from.unwrap_or_else(|| {
+        Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+            .single()
+            .unwrap_or(now)
+    });
+    if from >= to {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "'from' must be before 'to'",
+            "INVALID_RANGE",
+        ));
+    }
+
+    let mut samples = load_rollups(&state.db, scope, from, to)
+        .await
+        .map_err(api_db_error)?;
+    if let Some(ref meter) = state.traffic_meter {
+        samples.extend(
+            meter
+                .pending()
+                .into_iter()
+                .filter(|s| scope.matches(&s.key) && s.hour >= from && s.hour < to),
+        );
+    }
+
+    let mut totals = MeterCounts::default();
+    let mut groups: BTreeMap<UsageGroupKey, MeterCounts> = BTreeMap::new();
+    for sample in &samples {
+        totals.add(&sample.counts);
+        groups
+            .entry(usage_group_key(sample, query.group_by))
+            .or_default()
+            .add(&sample.counts);
+    }
+
+    // Show tokens by ID and name when they are still stored
+    let token_hashes: Vec<String> = groups.keys().filter_map(|k| k.2.clone()).collect();
+    let tokens: std::collections::HashMap<String, auth_token::Model> = if token_hashes.is_empty() {
+        Default::default()
+    } else {
+        AuthTokenEntity::find()
+            .filter(auth_token::Column::TokenHash.is_in(token_hashes))
+            .all(&state.db)
+            .await
+            .map_err(api_db_error)?
+            .into_iter()
+            .map(|token| (token.token_hash.clone(), token))
+            .collect()
+    };
+
+    let records = groups
+        .iter()
+        .map(
+            |((hour, tunnel_id, token_hash, user_id, team_id), counts)| {
+                let token = token_hash.as_ref().and_then(|hash| tokens.get(hash));
+                UsageRecord {
+                    hour: *hour,
+                    tunnel_id: tunnel_id.clone(),
+                    token_id: token.map(|t| t.id.to_string()),
+                    token_name: token.map(|t| t.name.clone()),
+                    user_id: user_id.map(|id| id.to_string()),
+                    team_id: team_id.map(|id| id.to_string()),
+                    ..usage_record(counts)
+                }
+            },
+        )
+        .collect();
+
+    Ok(UsageReport {
+        from,
+        to,
+        group_by: query.group_by,
+        records,
+        totals: usage_record(&totals),
+    })
+}
+
+/// Get metered traffic for a time range
+///
+/// Traffic is counted per hour for each tunnel, auth token, user and team,
+/// independent of request capture.
+#[utoipa::path(
+    get,
+    path = "/api/usage",
+    params(
+        ("from" = Option<String>, Query, description = "Start of the range (RFC 3339, default: start of the current month)"),
+        ("to" = Option<String>, Query, description = "End of the range, exclusive (RFC 3339, default: now)"),
+        ("group_by" = Option<UsageGroupBy>, Query, description = "hour, tunnel (default), token, user or team"),
+        ("team_id" = Option<String>, Query, description = "Show the traffic of this team's tunnels"),
+        ("user_id" = Option<String>, Query, description = "Show the traffic of this user's tunnels (admins only)"),
+        ("all" = Option<bool>, Query, description = "Show the traffic of every tunnel (admins only)")
+    ),
+    responses(
+        (status = 200, description = "Metered traffic", body = UsageReport),
+        (status = 400, description = "Invalid range or ID", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Not allowed to view this usage", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "usage",
+    security(("bearer_auth" = []))
+)]
+pub async fn get_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageReport>, (StatusCode, Json<ErrorResponse>)> {
+    Ok(Json(usage_report(&state, &auth_user, &query).await?))
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Export metered traffic for a time range as CSV
+#[utoipa::path(
+    get,
+    path = "/api/usage/export",
+    params(
+        ("from" = Option<String>, Query, description = "Start of the range (RFC 3339, default: start of the current month)"),
+        ("to" = Option<String>, Query, description = "End of the range, exclusive (RFC 3339, default: now)"),
+        ("group_by" = Option<UsageGroupBy>, Query, description = "hour, tunnel (default), token, user or team"),
+        ("team_id" = Option<String>, Query, description = "Export the traffic of this team's tunnels"),
+        ("user_id" = Option<String>, Query, description = "Export the traffic of this user's tunnels (admins only)"),
+        ("all" = Option<bool>, Query, description = "Export the traffic of every tunnel (admins only)")
+    ),
+    responses(
+        (status = 200, description = "Metered traffic as CSV", content_type = "text/csv", body = String),
+        (status = 400, description = "Invalid range or ID", body = ErrorResponse),
+        (status = 401, description = "Unauthorized", body = ErrorResponse),
+        (status = 403, description = "Not allowed to view this usage", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "usage",
+    security(("bearer_auth" = []))
+)]
+pub async fn export_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Query(query): Query<UsageQuery>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let report = usage_report(&state, &auth_user, &query).await?;
+
+    let mut csv = String::from(
+        "hour,tunnel_id,token_id,token_name,user_id,team_id,bytes_in,bytes_out,requests,connections,connection_seconds\n",
+    );
+    for record in &report.records {
+        let fields = [
+            record.hour.map(|h| h.to_rfc3339()).unwrap_or_default(),
+            record.tunnel_id.clone().unwrap_or_default(),
+            record.token_id.clone().unwrap_or_default(),
+            record.token_name.clone().unwrap_or_default(),
+            record.user_id.clone().unwrap_or_default(),
+            record.team_id.clone().unwrap_or_default(),
+            record.bytes_in.to_string(),
+            record.bytes_out.to_string(),
+            record.requests.to_string(),
+            record.connections.to_string(),
+            format!("{:.3}", record.connection_seconds),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&line.join(","));
+        csv.push('\n');
+    }
+
+    let filename = format!(
+        "usage-{}-{}.csv",
+        report.from.format("%Y%m%dT%H%M%SZ"),
+        report.to.format("%Y%m%dT%H%M%SZ")
+    );
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        "text/csv; charset=utf-8".parse().unwrap(),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}\"", filename)
+            .parse()
+            .unwrap(),
+    );
+    Ok((headers, csv))
+}
+
+/// Get available transport protocols (well-known endpoint)
+///
+/// This endpoint is used by clients to discover which transport protocols
+/// are available on this relay (QUIC, WebSocket, HTTP/2).
+#[utoipa::path(
+    get,
+    path = "/.well-known/localup-protocols",
+    responses(
+        (status = 200, description = "Protocol discovery response", body = ProtocolDiscoveryResponse),
+        (status = 204, description = "Protocol discovery not configured")
+    ),
+    tag = "discovery"
+)]
+pub async fn protocol_discovery(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match &state.protocol_discovery {
+        Some(discovery) => {
+            debug!(
+                "Protocol discovery request, returning {} transports",
+                discovery.transports.len()
+            );
+            Json(discovery.clone()).into_response()
+        }
+        None => {
+            // Return default QUIC-only response if not configured
+            let default_discovery = localup_proto::ProtocolDiscoveryResponse::quic_only(4443);
+            Json(default_discovery).into_response()
+        }
+    }
+}
+
+/// Public keys for verifying tokens signed by this relay (JWKS, RFC 7517)
+///
+/// Empty when the relay signs with a shared secret only.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "JSON Web Key Set", body = Object)
+    ),
+    tag = "discovery"
+)]
+pub async fn jwks(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.jwt_validator.jwks())
 }
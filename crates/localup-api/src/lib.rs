@@ -7,7 +7,7 @@ use axum::{
     http::{header, HeaderValue, Method, Response, StatusCode},
     middleware as axum_middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
 use rust_embed::RustEmbed;
@@ -17,8 +17,9 @@ use tracing::info;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+use localup_auth::{JwtSigner, JwtValidator};
 use localup_cert::AcmeClient;
-use localup_control::TunnelConnectionManager;
+use localup_control::{TrafficMeter, TunnelConnectionManager};
 use sea_orm::DatabaseConnection;
 use tokio::sync::RwLock;
 
@@ -36,6 +37,10 @@ pub struct AppState {
     pub allow_signup: bool,
     /// JWT secret for signing/validating tokens (required)
     pub jwt_secret: String,
+    /// Key that signs session and auth tokens (the JWT secret unless replaced)
+    pub jwt_signer: Arc<JwtSigner>,
+    /// Validator for session tokens; its public keys are published as a JWKS
+    pub jwt_validator: Arc<JwtValidator>,
     /// Protocol discovery response for clients
     pub protocol_discovery: Option<localup_proto::ProtocolDiscoveryResponse>,
     /// Whether the server is running with HTTPS (for Secure cookie flag)
@@ -46,6 +51,22 @@ pub struct AppState {
     pub acme_client: Option<Arc<RwLock<AcmeClient>>>,
     /// HTTP-01 challenge responses (token -> key_authorization)
     pub acme_challenges: Arc<RwLock<std::collections::HashMap<String, String>>>,
+    /// TCP port range and reservation quota (None = TCP port reservations disabled)
+    pub tcp_port_policy: Option<TcpPortPolicy>,
+    /// Traffic meter whose unwritten counts are added to usage reports
+    pub traffic_meter: Option<Arc<TrafficMeter>>,
+}
+
+/// Limits for TCP port reservations made through the API
+#[derive(Debug, Clone)]
+pub struct TcpPortPolicy {
+    /// First port of the relay's TCP range
+    pub range_start: u16,
+    /// Last port of the relay's TCP range
+    pub range_end: u16,
+    /// Reservations allowed per team (and per user for personal reservations)
+    /// when the team sets no quota of its own (None = unlimited)
+    pub default_quota: Option<u32>,
 }
 
 /// OpenAPI documentation
@@ -95,7 +116,20 @@ pub struct AppState {
         handlers::get_auth_token,
         handlers::update_auth_token,
         handlers::delete_auth_token,
+        handlers::revoke_token,
+        handlers::list_token_revocations,
+        handlers::create_share_link,
+        handlers::list_share_links,
+        handlers::get_share_link,
+        handlers::revoke_share_link,
+        handlers::list_tcp_ports,
+        handlers::reserve_tcp_port,
+        handlers::release_tcp_port,
+        handlers::get_quota_usage,
+        handlers::get_usage,
+        handlers::export_usage,
         handlers::protocol_discovery,
+        handlers::jwks,
     ),
     components(
         schemas(
@@ -143,6 +177,24 @@ pub struct AppState {
             models::AuthToken,
             models::AuthTokenList,
             models::UpdateAuthTokenRequest,
+            models::RevokeTokenRequest,
+            models::TokenRevocation,
+            models::TokenRevocationList,
+            models::CreateShareLinkRequest,
+            models::CreateShareLinkResponse,
+            models::ShareLink,
+            models::ShareLinkList,
+            models::ShareLinkQuery,
+            models::ReserveTcpPortRequest,
+            models::TcpPortAssignment,
+            models::TcpPortAssignmentList,
+            models::QuotaUsageQuery,
+            models::QuotaLimitsInfo,
+            models::QuotaUsageReport,
+            models::UsageQuery,
+            models::UsageGroupBy,
+            models::UsageRecord,
+            models::UsageReport,
             models::AuthConfig,
             models::RelayConfig,
             models::ProtocolDiscoveryResponse,
@@ -156,6 +208,10 @@ pub struct AppState {
         (name = "domains", description = "Custom domain management endpoints"),
         (name = "auth", description = "Authentication and user management endpoints"),
         (name = "auth-tokens", description = "Auth token (API key) management endpoints"),
+        (name = "share-links", description = "Signed, expiring tunnel share link endpoints"),
+        (name = "tcp-ports", description = "TCP port reservation endpoints"),
+        (name = "quotas", description = "User and team quota endpoints"),
+        (name = "usage", description = "Metered traffic and billing export endpoints"),
         (name = "system", description = "System health and info endpoints"),
         (name = "discovery", description = "Protocol discovery endpoints")
     )
@@ -200,11 +256,15 @@ impl ApiServer {
             db,
             allow_signup,
             jwt_secret: config.jwt_secret.clone(),
+            jwt_signer: Arc::new(JwtSigner::hmac(config.jwt_secret.as_bytes())),
+            jwt_validator: Arc::new(JwtValidator::new(config.jwt_secret.as_bytes())),
             protocol_discovery: None,
             is_https,
             relay_config: None,
             acme_client: None,
             acme_challenges: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            tcp_port_policy: None,
+            traffic_meter: None,
         });
 
         Self { config, state }
@@ -224,11 +284,15 @@ impl ApiServer {
             db,
             allow_signup,
             jwt_secret: config.jwt_secret.clone(),
+            jwt_signer: Arc::new(JwtSigner::hmac(config.jwt_secret.as_bytes())),
+            jwt_validator: Arc::new(JwtValidator::new(config.jwt_secret.as_bytes())),
             protocol_discovery: Some(protocol_discovery),
             is_https,
             relay_config: None,
             acme_client: None,
             acme_challenges: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            tcp_port_policy: None,
+            traffic_meter: None,
         });
 
         Self { config, state }
@@ -249,11 +313,15 @@ impl ApiServer {
             db,
             allow_signup,
             jwt_secret: config.jwt_secret.clone(),
+            jwt_signer: Arc::new(JwtSigner::hmac(config.jwt_secret.as_bytes())),
+            jwt_validator: Arc::new(JwtValidator::new(config.jwt_secret.as_bytes())),
             protocol_discovery,
             is_https,
             relay_config: Some(relay_config),
             acme_client: None,
             acme_challenges: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            tcp_port_policy: None,
+            traffic_meter: None,
         });
 
         Self { config, state }
@@ -275,23 +343,58 @@ impl ApiServer {
             db,
             allow_signup,
             jwt_secret: config.jwt_secret.clone(),
+            jwt_signer: Arc::new(JwtSigner::hmac(config.jwt_secret.as_bytes())),
+            jwt_validator: Arc::new(JwtValidator::new(config.jwt_secret.as_bytes())),
             protocol_discovery,
             is_https,
             relay_config,
             acme_client: Some(Arc::new(RwLock::new(acme_client))),
             acme_challenges: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            tcp_port_policy: None,
+            traffic_meter: None,
         });
 
         Self { config, state }
     }
 
+    /// Enable TCP port reservations within the relay's TCP port range
+    pub fn with_tcp_port_policy(mut self, policy: TcpPortPolicy) -> Self {
+        Arc::get_mut(&mut self.state)
+            .expect("API state is not shared before the server starts")
+            .tcp_port_policy = Some(policy);
+        self
+    }
+
+    /// Include traffic not yet written to the database in usage reports
+    pub fn with_traffic_meter(mut self, meter: Arc<TrafficMeter>) -> Self {
+        Arc::get_mut(&mut self.state)
+            .expect("API state is not shared before the server starts")
+            .traffic_meter = Some(meter);
+        self
+    }
+
+    /// Sign tokens with `signer` and accept session tokens that `validator` accepts
+    ///
+    /// `validator` should accept tokens from `signer` and from any previous
+    /// keys that still have tokens in use. Its asymmetric public keys are
+    /// served at `/.well-known/jwks.json`.
+    pub fn with_jwt_keys(mut self, signer: Arc<JwtSigner>, validator: Arc<JwtValidator>) -> Self {
+        let state = Arc::get_mut(&mut self.state)
+            .expect("API state is not shared before the server starts");
+        state.jwt_signer = signer;
+        state.jwt_validator = validator;
+        self
+    }
+
     /// Build the router with all routes
     pub fn build_router(&self) -> Router {
         // Get the OpenAPI spec
         let api_doc = ApiDoc::openapi();
 
-        // Create JWT state for authentication middleware using configured secret
-        let jwt_state = Arc::new(middleware::JwtState::new(self.state.jwt_secret.as_bytes()));
+        // Create JWT state for authentication middleware using the configured keys
+        let jwt_state = Arc::new(middleware::JwtState {
+            validator: self.state.jwt_validator.clone(),
+        });
 
         // Build PUBLIC routes (no authentication required)
         let public_router = Router::new()
@@ -305,6 +408,8 @@ impl ApiServer {
                 "/.well-known/localup-protocols",
                 get(handlers::protocol_discovery),
             )
+            // Public keys for verifying tokens this relay signs
+            .route("/.well-known/jwks.json", get(handlers::jwks))
             // ACME HTTP-01 challenge endpoint (must be accessible without auth)
             .route(
                 "/.well-known/acme-challenge/{token}",
@@ -388,6 +493,30 @@ impl ApiServer {
                     .patch(handlers::update_auth_token)
                     .delete(handlers::delete_auth_token),
             )
+            // Revoke tokens before they expire; tunnels using them are disconnected
+            .route(
+                "/api/token-revocations",
+                get(handlers::list_token_revocations).post(handlers::revoke_token),
+            )
+            // Share link management routes
+            .route(
+                "/api/tunnels/{id}/share-links",
+                post(handlers::create_share_link),
+            )
+            .route("/api/share-links", get(handlers::list_share_links))
+            .route(
+                "/api/share-links/{id}",
+                get(handlers::get_share_link).delete(handlers::revoke_share_link),
+            )
+            // TCP port reservation routes
+            .route(
+                "/api/tcp-ports",
+                get(handlers::list_tcp_ports).post(handlers::reserve_tcp_port),
+            )
+            .route("/api/tcp-ports/{port}", delete(handlers::release_tcp_port))
+            .route("/api/quotas/usage", get(handlers::get_quota_usage))
+            .route("/api/usage", get(handlers::get_usage))
+            .route("/api/usage/export", get(handlers::export_usage))
             .with_state(self.state.clone())
             .layer(axum_middleware::from_fn_with_state(
                 jwt_state.clone(),
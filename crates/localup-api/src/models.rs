@@ -20,11 +20,21 @@ pub enum TunnelProtocol {
     Tcp {
         /// Local port to forward
         port: u16,
+        /// Hostname for tunnels reached through the relay's CONNECT/SOCKS5 proxy
+        /// (`port` is then the proxy port)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hostname: Option<String>,
     },
     /// TLS tunnel with SNI (supports multiple domains/patterns)
     Tls {
         /// Domains/patterns for SNI routing (can include wildcards like *.example.com)
         domains: Vec<String>,
+        /// Whether the relay terminates TLS and forwards plaintext to the client
+        #[serde(default)]
+        terminated: bool,
+        /// ALPN protocols the tunnel is restricted to (empty means all)
+        #[serde(default)]
+        alpn: Vec<String>,
     },
 }
 
@@ -646,6 +656,21 @@ pub struct CreateAuthTokenRequest {
     /// Team ID if this is a team token (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub team_id: Option<String>,
+    /// Tunnel protocols the token may open (http, https, tcp, tls; null = all)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocols: Option<Vec<String>>,
+    /// Subdomain patterns the token may use, `*` as wildcard (null = any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_subdomains: Option<Vec<String>>,
+    /// Custom domain / SNI patterns the token may use (null = any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_domains: Option<Vec<String>>,
+    /// Public TCP ports the token may use, e.g. "15000-15100" or "5432" (null = any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_ports: Option<Vec<String>>,
+    /// Maximum number of tunnels connected with the token at once (null = unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tunnels: Option<u32>,
 }
 
 /// Response after creating an auth token
@@ -700,6 +725,321 @@ pub struct AuthTokenList {
     pub total: usize,
 }
 
+/// Request to create a share link for a tunnel
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateShareLinkRequest {
+    /// Label shown when listing links (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Path prefix the link is limited to (default: "/", the whole tunnel)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    /// Link lifetime in hours (default: 24)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in_hours: Option<i64>,
+    /// How many times the link may be opened (null = unlimited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<i32>,
+}
+
+/// Share link information (without the signed URL)
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ShareLink {
+    /// Share link ID
+    pub id: String,
+    /// Tunnel the link grants access to
+    pub tunnel_id: String,
+    /// Path prefix the link is limited to
+    pub path_prefix: String,
+    /// Link label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// How many times the link may be opened (null = unlimited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uses: Option<i32>,
+    /// How many times the link has been opened
+    pub use_count: i32,
+    /// When the link was last opened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// When the link expires
+    pub expires_at: DateTime<Utc>,
+    /// When the link was revoked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Whether the link can still be used (not expired, revoked or used up)
+    pub is_active: bool,
+    /// When the link was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// Response after creating a share link
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateShareLinkResponse {
+    /// The created link
+    #[serde(flatten)]
+    pub link: ShareLink,
+    /// Signed URL to hand out
+    pub url: String,
+}
+
+/// List of share links
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ShareLinkList {
+    /// Share links
+    pub links: Vec<ShareLink>,
+    /// Total count
+    pub total: usize,
+}
+
+/// Query parameters for listing share links
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ShareLinkQuery {
+    /// Only list links for this tunnel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel_id: Option<String>,
+}
+
+/// Request to reserve a TCP port on the relay
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReserveTcpPortRequest {
+    /// Port to reserve (default: the port last used for `local_port`, or any free port)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Team the reservation counts against (default: personal reservation)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+    /// Only hand the port to tunnels forwarding this local port (default: any of your tunnels)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
+    /// Label shown when listing reservations (optional)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// A TCP port held by a user, either reserved or remembered from a previous tunnel
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TcpPortAssignment {
+    /// Public port on the relay
+    pub port: u16,
+    /// Whether the port is explicitly reserved (sticky ports are released after a period of disuse)
+    pub reserved: bool,
+    /// Team the reservation counts against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+    /// Local port the assignment is tied to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_port: Option<u16>,
+    /// Reservation label
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Tunnel that last held the port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel_id: Option<String>,
+    /// When a tunnel last held the port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// When the port was assigned
+    pub created_at: DateTime<Utc>,
+}
+
+/// List of TCP port assignments
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TcpPortAssignmentList {
+    /// Port assignments
+    pub ports: Vec<TcpPortAssignment>,
+    /// Total count
+    pub total: usize,
+}
+
+/// Query parameters for quota usage
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuotaUsageQuery {
+    /// Show the quota of this team instead of your own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+}
+
+/// Limits of a user's or team's quota (absent = unlimited)
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuotaLimitsInfo {
+    /// Concurrently connected tunnels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tunnels: Option<u32>,
+    /// Custom domains in use by connected tunnels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_custom_domains: Option<u32>,
+    /// Reserved TCP ports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_reserved_ports: Option<u32>,
+    /// Bytes in and out per calendar month (UTC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monthly_bytes: Option<u64>,
+    /// Concurrent visitor connections across all tunnels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
+}
+
+/// Current usage of a user's or team's quota
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuotaUsageReport {
+    /// "user" or "team"
+    pub subject_type: String,
+    /// User or team ID
+    pub subject_id: String,
+    /// Calendar month traffic is counted in ("2026-10")
+    pub period: String,
+    /// Limits that apply
+    pub limits: QuotaLimitsInfo,
+    /// Connected tunnels
+    pub tunnels: usize,
+    /// Custom domains in use by connected tunnels
+    pub custom_domains: Vec<String>,
+    /// Reserved TCP ports
+    pub reserved_ports: usize,
+    /// Bytes from visitors to tunnels this month
+    pub bytes_in: u64,
+    /// Bytes from tunnels to visitors this month
+    pub bytes_out: u64,
+    /// Open visitor connections
+    pub connections: u32,
+}
+
+/// How metered traffic is grouped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageGroupBy {
+    /// One row per hour
+    Hour,
+    /// One row per tunnel
+    #[default]
+    Tunnel,
+    /// One row per auth token
+    Token,
+    /// One row per user
+    User,
+    /// One row per team
+    Team,
+}
+
+/// Query parameters for metered traffic
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UsageQuery {
+    /// Start of the range (RFC 3339, default: start of the current month);
+    /// hours are included if they start in the range
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    /// End of the range, exclusive (RFC 3339, default: now)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+    /// How rows are grouped (default: tunnel)
+    #[serde(default)]
+    pub group_by: UsageGroupBy,
+    /// Show the traffic of this team's tunnels instead of your own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+    /// Show the traffic of this user's tunnels (admins only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Show the traffic of every tunnel on the relay (admins only)
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Metered traffic of one group
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UsageRecord {
+    /// Start of the hour (grouped by hour)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hour: Option<DateTime<Utc>>,
+    /// Tunnel ID (grouped by tunnel)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel_id: Option<String>,
+    /// Auth token ID (grouped by token, when the token is still stored)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<String>,
+    /// Auth token name (grouped by token, when the token is still stored)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_name: Option<String>,
+    /// User ID (grouped by tunnel, token or user)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Team ID (grouped by tunnel, token or team)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<String>,
+    /// Bytes from visitors to tunnels
+    pub bytes_in: u64,
+    /// Bytes from tunnels to visitors
+    pub bytes_out: u64,
+    /// HTTP requests handled by the relay
+    pub requests: u64,
+    /// Visitor connections opened
+    pub connections: u64,
+    /// Time visitor connections were open, summed over connections
+    pub connection_seconds: f64,
+}
+
+/// Metered traffic for a time range
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UsageReport {
+    /// Start of the range
+    pub from: DateTime<Utc>,
+    /// End of the range (exclusive)
+    pub to: DateTime<Utc>,
+    /// How rows are grouped
+    pub group_by: UsageGroupBy,
+    /// Traffic per group
+    pub records: Vec<UsageRecord>,
+    /// Traffic of all groups
+    pub totals: UsageRecord,
+}
+
+/// Request to revoke a token before it expires
+///
+/// Either the token itself or (for admins) its `jti` must be given.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RevokeTokenRequest {
+    /// The token to revoke
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// ID (`jti` claim) of the token to revoke (admins only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Why the token is revoked; sent to tunnels using it when they are disconnected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A revoked token
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenRevocation {
+    /// ID (`jti` claim) of the revoked token
+    pub jti: String,
+    /// User the token was issued to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    /// Why the token was revoked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// User who revoked the token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_by: Option<String>,
+    /// When the token would have expired
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the token was revoked
+    pub revoked_at: DateTime<Utc>,
+}
+
+/// List of revoked tokens
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenRevocationList {
+    /// Revoked tokens, most recent first
+    pub revocations: Vec<TokenRevocation>,
+    /// Total count
+    pub total: usize,
+}
+
 /// Authentication configuration
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AuthConfig {
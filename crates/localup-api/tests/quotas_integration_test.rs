@@ -0,0 +1,189 @@
+//! Integration tests for quota endpoints
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::Utc;
+use localup_api::{models::*, ApiServer, ApiServerConfig, TcpPortPolicy};
+use localup_control::TunnelConnectionManager;
+use localup_relay_db::entities::{plan, quota, quota_usage};
+use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, Set};
+use sea_orm_migration::MigratorTrait;
+use serde_json::json;
+use std::sync::Arc;
+use tower::ServiceExt; // For `oneshot` method
+use uuid::Uuid;
+
+async fn create_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create in-memory database");
+
+    localup_relay_db::migrator::Migrator::up(&db, None)
+        .await
+        .expect("Failed to run migrations");
+
+    db
+}
+
+/// Test router with TCP ports 30000-30009 and a relay default of 5 reservations
+fn create_test_app(db: DatabaseConnection) -> Router {
+    let config = ApiServerConfig {
+        http_addr: Some("127.0.0.1:0".parse().unwrap()),
+        https_addr: None,
+        enable_cors: true,
+        cors_origins: None,
+        jwt_secret: "test-secret".to_string(),
+        tls_cert_path: None,
+        tls_key_path: None,
+    };
+
+    ApiServer::new(config, Arc::new(TunnelConnectionManager::new()), db, true)
+        .with_tcp_port_policy(TcpPortPolicy {
+            range_start: 30000,
+            range_end: 30009,
+            default_quota: Some(5),
+        })
+        .build_router()
+}
+
+async fn register(app: &Router, email: &str) -> (Uuid, String) {
+    let request = Request::builder()
+        .uri("/api/auth/register")
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "email": email, "password": "SecurePassword123!" }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let data: RegisterResponse = serde_json::from_slice(&body).unwrap();
+    (Uuid::parse_str(&data.user.id).unwrap(), data.token)
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn usage(app: &Router, token: &str, query: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .uri(format!("/api/quotas/usage{}", query))
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    send(app, request).await
+}
+
+async fn reserve(app: &Router, token: &str, port: u16) -> StatusCode {
+    let request = Request::builder()
+        .uri("/api/tcp-ports")
+        .method("POST")
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::from(json!({ "port": port }).to_string()))
+        .unwrap();
+    send(app, request).await.0
+}
+
+async fn create_plan(db: &DatabaseConnection, name: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    plan::ActiveModel {
+        id: Set(id),
+        name: Set(name.to_string()),
+        max_tunnels: Set(Some(3)),
+        max_custom_domains: Set(Some(1)),
+        max_reserved_ports: Set(Some(1)),
+        monthly_bytes: Set(Some(1_000_000)),
+        max_connections: Set(None),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await
+    .unwrap();
+    id
+}
+
+#[tokio::test]
+async fn test_quota_usage_reports_plan_limits_and_traffic() {
+    let db = create_test_db().await;
+    let app = create_test_app(db.clone());
+    let (user_id, token) = register(&app, "alice@example.com").await;
+
+    // No plans configured: everything is unlimited
+    let (status, body) = usage(&app, &token, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let report: QuotaUsageReport = serde_json::from_value(body).unwrap();
+    assert_eq!(report.subject_type, "user");
+    assert_eq!(report.subject_id, user_id.to_string());
+    assert!(report.limits.max_tunnels.is_none());
+    assert_eq!(report.bytes_in + report.bytes_out, 0);
+
+    create_plan(&db, plan::DEFAULT_PLAN).await;
+    quota_usage::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        subject_type: Set(quota::QuotaSubject::User),
+        subject_id: Set(user_id),
+        period: Set(Utc::now().format("%Y-%m").to_string()),
+        bytes_in: Set(1200),
+        bytes_out: Set(3400),
+        updated_at: Set(Utc::now()),
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+
+    let (_, body) = usage(&app, &token, "").await;
+    let report: QuotaUsageReport = serde_json::from_value(body).unwrap();
+    assert_eq!(report.limits.max_tunnels, Some(3));
+    assert_eq!(report.limits.monthly_bytes, Some(1_000_000));
+    assert_eq!((report.bytes_in, report.bytes_out), (1200, 3400));
+    assert_eq!(report.tunnels, 0);
+}
+
+#[tokio::test]
+async fn test_plan_limits_reserved_ports() {
+    let db = create_test_db().await;
+    let app = create_test_app(db.clone());
+    let (_, token) = register(&app, "alice@example.com").await;
+
+    // The default plan overrides the relay default of 5
+    create_plan(&db, plan::DEFAULT_PLAN).await;
+    assert_eq!(reserve(&app, &token, 30001).await, StatusCode::CREATED);
+    assert_eq!(reserve(&app, &token, 30002).await, StatusCode::FORBIDDEN);
+
+    let (_, body) = usage(&app, &token, "").await;
+    let report: QuotaUsageReport = serde_json::from_value(body).unwrap();
+    assert_eq!(report.reserved_ports, 1);
+}
+
+#[tokio::test]
+async fn test_team_quota_requires_membership() {
+    let db = create_test_db().await;
+    let app = create_test_app(db);
+    let (_, token) = register(&app, "alice@example.com").await;
+
+    let (status, _) = usage(&app, &token, &format!("?team_id={}", Uuid::new_v4())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = usage(&app, &token, "?team_id=not-a-uuid").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let request = Request::builder()
+        .uri("/api/quotas/usage")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::UNAUTHORIZED);
+}
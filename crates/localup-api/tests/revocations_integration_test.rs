@@ -0,0 +1,159 @@
+//! Integration tests for token revocation endpoints
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use localup_api::{models::*, ApiServer, ApiServerConfig};
+use localup_control::TunnelConnectionManager;
+use sea_orm::{Database, DatabaseConnection};
+use sea_orm_migration::MigratorTrait;
+use serde_json::json;
+use std::sync::Arc;
+use tower::ServiceExt; // For `oneshot` method
+
+async fn create_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create in-memory database");
+
+    localup_relay_db::migrator::Migrator::up(&db, None)
+        .await
+        .expect("Failed to run migrations");
+
+    db
+}
+
+fn create_test_app(db: DatabaseConnection) -> Router {
+    let config = ApiServerConfig {
+        http_addr: Some("127.0.0.1:0".parse().unwrap()),
+        https_addr: None,
+        enable_cors: true,
+        cors_origins: None,
+        jwt_secret: "test-secret".to_string(),
+        tls_cert_path: None,
+        tls_key_path: None,
+    };
+
+    ApiServer::new(config, Arc::new(TunnelConnectionManager::new()), db, true).build_router()
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    token: &str,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .uri(uri)
+        .method(method)
+        .header("authorization", format!("Bearer {}", token))
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+    )
+}
+
+/// Register a user, returning their session token
+async fn register(app: &Router, email: &str) -> String {
+    let request = Request::builder()
+        .uri("/api/auth/register")
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "email": email, "password": "SecurePassword123!" }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice::<RegisterResponse>(&body)
+        .unwrap()
+        .token
+}
+
+#[tokio::test]
+async fn test_users_revoke_their_own_tokens() {
+    let db = create_test_db().await;
+    let app = create_test_app(db);
+    let alice = register(&app, "alice@example.com").await;
+    let bob = register(&app, "bob@example.com").await;
+
+    let (status, created) = send(
+        &app,
+        "POST",
+        &alice,
+        "/api/auth-tokens",
+        Some(json!({ "name": "laptop", "expires_in_days": 30 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let auth_token = created["token"].as_str().unwrap().to_string();
+
+    // Only the owner may revoke a token, and only admins may revoke by ID
+    let (status, _) = send(
+        &app,
+        "POST",
+        &bob,
+        "/api/token-revocations",
+        Some(json!({ "token": auth_token })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(
+        &app,
+        "POST",
+        &bob,
+        "/api/token-revocations",
+        Some(json!({ "jti": "anything" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, revocation) = send(
+        &app,
+        "POST",
+        &alice,
+        "/api/token-revocations",
+        Some(json!({ "token": auth_token, "reason": "laptop stolen" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let revocation: TokenRevocation = serde_json::from_value(revocation).unwrap();
+    assert_eq!(revocation.reason.as_deref(), Some("laptop stolen"));
+    assert!(revocation.expires_at.is_some());
+
+    // Revoking again is a no-op
+    let (status, again) = send(
+        &app,
+        "POST",
+        &alice,
+        "/api/token-revocations",
+        Some(json!({ "token": auth_token })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(again["jti"], revocation.jti.as_str());
+
+    let (status, list) = send(&app, "GET", &alice, "/api/token-revocations", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let list: TokenRevocationList = serde_json::from_value(list).unwrap();
+    assert_eq!(list.total, 1);
+    assert_eq!(list.revocations[0].jti, revocation.jti);
+
+    let (_, list) = send(&app, "GET", &bob, "/api/token-revocations", None).await;
+    assert_eq!(list["total"], 0);
+}
@@ -0,0 +1,169 @@
+//! Integration tests for share link endpoints
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use localup_api::{models::*, ApiServer, ApiServerConfig};
+use localup_control::{TunnelConnectionManager, TunnelOwner};
+use localup_proto::{Endpoint, Protocol};
+use localup_transport::{TransportConnector, TransportListener};
+use localup_transport_quic::{QuicConfig, QuicConnector, QuicListener};
+use sea_orm::{Database, DatabaseConnection};
+use sea_orm_migration::MigratorTrait;
+use serde_json::json;
+use std::sync::Arc;
+use tower::ServiceExt; // For `oneshot` method
+
+async fn create_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create in-memory database");
+
+    localup_relay_db::migrator::Migrator::up(&db, None)
+        .await
+        .expect("Failed to run migrations");
+
+    db
+}
+
+fn create_test_app(
+    db: DatabaseConnection,
+    localup_manager: Arc<TunnelConnectionManager>,
+) -> Router {
+    let config = ApiServerConfig {
+        http_addr: Some("127.0.0.1:0".parse().unwrap()),
+        https_addr: None,
+        enable_cors: true,
+        cors_origins: None,
+        jwt_secret: "test-secret".to_string(),
+        tls_cert_path: None,
+        tls_key_path: None,
+    };
+
+    ApiServer::new(config, localup_manager, db, true).build_router()
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    token: &str,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .uri(uri)
+        .method(method)
+        .header("authorization", format!("Bearer {}", token))
+        .header("content-type", "application/json")
+        .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+    )
+}
+
+/// Register a user, returning their user ID and session token
+async fn register(app: &Router, email: &str) -> (String, String) {
+    let request = Request::builder()
+        .uri("/api/auth/register")
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "email": email, "password": "SecurePassword123!" }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let registered: RegisterResponse = serde_json::from_slice(&body).unwrap();
+    (registered.user.id, registered.token)
+}
+
+/// Register an HTTP tunnel opened by `user_id` over a loopback QUIC connection
+async fn connect_tunnel(manager: &TunnelConnectionManager, localup_id: &str, user_id: &str) {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let listener = QuicListener::new(
+        "127.0.0.1:0".parse().unwrap(),
+        Arc::new(QuicConfig::server_self_signed().unwrap()),
+    )
+    .unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure())).unwrap();
+    let (accepted, client) = tokio::join!(listener.accept(), connector.connect(addr, "localhost"));
+    let (connection, _) = accepted.unwrap();
+    // Keep the client side open for the rest of the test
+    std::mem::forget(client.unwrap());
+
+    manager
+        .register(
+            localup_id.to_string(),
+            vec![Endpoint {
+                protocol: Protocol::Https {
+                    subdomain: Some("alice-app".to_string()),
+                    custom_domain: None,
+                },
+                public_url: "https://alice-app.localup.test".to_string(),
+                port: Some(443),
+            }],
+            Arc::new(connection),
+        )
+        .await;
+    manager
+        .set_owner(
+            localup_id,
+            TunnelOwner {
+                user_id: Some(user_id.to_string()),
+                team_id: None,
+            },
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn test_only_the_tunnel_owner_can_share_it() {
+    let manager = Arc::new(TunnelConnectionManager::new());
+    let app = create_test_app(create_test_db().await, manager.clone());
+    let (alice_id, alice) = register(&app, "alice@example.com").await;
+    let (_, bob) = register(&app, "bob@example.com").await;
+    connect_tunnel(&manager, "alice-tunnel", &alice_id).await;
+
+    // Someone else's tunnel looks the same as one that isn't connected
+    let uri = "/api/tunnels/alice-tunnel/share-links";
+    let (status, body) = send(&app, "POST", &bob, uri, Some(json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "TUNNEL_NOT_FOUND");
+    let (status, _) = send(
+        &app,
+        "POST",
+        &bob,
+        "/api/tunnels/no-such-tunnel/share-links",
+        Some(json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        &alice,
+        uri,
+        Some(json!({ "path_prefix": "/docs" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(body["url"]
+        .as_str()
+        .unwrap()
+        .starts_with("https://alice-app.localup.test/docs?"));
+}
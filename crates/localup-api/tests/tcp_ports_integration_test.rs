@@ -0,0 +1,197 @@
+//! Integration tests for TCP port reservation endpoints
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::Utc;
+use localup_api::{models::*, ApiServer, ApiServerConfig, TcpPortPolicy};
+use localup_control::TunnelConnectionManager;
+use localup_relay_db::entities::{team, team_member};
+use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, Set};
+use sea_orm_migration::MigratorTrait;
+use serde_json::json;
+use std::sync::Arc;
+use tower::ServiceExt; // For `oneshot` method
+use uuid::Uuid;
+
+/// Helper to create an in-memory database with migrations applied
+async fn create_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create in-memory database");
+
+    localup_relay_db::migrator::Migrator::up(&db, None)
+        .await
+        .expect("Failed to run migrations");
+
+    db
+}
+
+/// Helper to create a test router with TCP ports 30000-30009 and a default quota of 2
+fn create_test_app(db: DatabaseConnection) -> Router {
+    let localup_manager = Arc::new(TunnelConnectionManager::new());
+    let config = ApiServerConfig {
+        http_addr: Some("127.0.0.1:0".parse().unwrap()),
+        https_addr: None,
+        enable_cors: true,
+        cors_origins: None,
+        jwt_secret: "test-secret".to_string(),
+        tls_cert_path: None,
+        tls_key_path: None,
+    };
+
+    ApiServer::new(config, localup_manager, db, true)
+        .with_tcp_port_policy(TcpPortPolicy {
+            range_start: 30000,
+            range_end: 30009,
+            default_quota: Some(2),
+        })
+        .build_router()
+}
+
+/// Register a user, returning their ID and session token
+async fn register(app: &Router, email: &str) -> (Uuid, String) {
+    let request = Request::builder()
+        .uri("/api/auth/register")
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "email": email, "password": "SecurePassword123!" }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let data: RegisterResponse = serde_json::from_slice(&body).unwrap();
+    (Uuid::parse_str(&data.user.id).unwrap(), data.token)
+}
+
+async fn reserve(
+    app: &Router,
+    token: &str,
+    body: serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .uri("/api/tcp-ports")
+        .method("POST")
+        .header("content-type", "application/json")
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+#[tokio::test]
+async fn test_reserve_list_and_release_port() {
+    let db = create_test_db().await;
+    let app = create_test_app(db);
+    let (_, alice) = register(&app, "alice@example.com").await;
+    let (_, bob) = register(&app, "bob@example.com").await;
+
+    let (status, body) = reserve(&app, &alice, json!({ "port": 30005, "name": "db" })).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let reserved: TcpPortAssignment = serde_json::from_value(body).unwrap();
+    assert_eq!(reserved.port, 30005);
+    assert!(reserved.reserved);
+
+    // Someone else cannot take it
+    let (status, body) = reserve(&app, &bob, json!({ "port": 30005 })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "PORT_TAKEN");
+
+    // Out of range
+    let (status, _) = reserve(&app, &bob, json!({ "port": 40000 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let request = Request::builder()
+        .uri("/api/tcp-ports")
+        .header("authorization", format!("Bearer {}", alice))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let list: TcpPortAssignmentList = serde_json::from_slice(&body).unwrap();
+    assert_eq!(list.total, 1);
+    assert_eq!(list.ports[0].name.as_deref(), Some("db"));
+
+    // Only the owner can release it
+    let release = |token: &str| {
+        Request::builder()
+            .uri("/api/tcp-ports/30005")
+            .method("DELETE")
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(release(&bob)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app.clone().oneshot(release(&alice)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let (status, _) = reserve(&app, &bob, json!({ "port": 30005 })).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_team_quota() {
+    let db = create_test_db().await;
+    let app = create_test_app(db.clone());
+    let (alice_id, alice) = register(&app, "alice@example.com").await;
+    let (_, bob) = register(&app, "bob@example.com").await;
+
+    let team_id = Uuid::new_v4();
+    team::ActiveModel {
+        id: Set(team_id),
+        name: Set("Acme".to_string()),
+        slug: Set("acme".to_string()),
+        owner_id: Set(alice_id),
+        tcp_port_quota: Set(Some(1)),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+    team_member::ActiveModel {
+        team_id: Set(team_id),
+        user_id: Set(alice_id),
+        role: Set(team_member::TeamRole::Owner),
+        joined_at: Set(Utc::now()),
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+
+    // The team's own quota (1) overrides the relay default (2)
+    let (status, _) = reserve(&app, &alice, json!({ "team_id": team_id.to_string() })).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, body) = reserve(&app, &alice, json!({ "team_id": team_id.to_string() })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "QUOTA_EXCEEDED");
+
+    // Personal reservations use the relay default
+    for _ in 0..2 {
+        let (status, _) = reserve(&app, &alice, json!({})).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    let (status, _) = reserve(&app, &alice, json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Non-members cannot reserve for the team
+    let (status, body) = reserve(&app, &bob, json!({ "team_id": team_id.to_string() })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "NOT_TEAM_MEMBER");
+}
@@ -0,0 +1,234 @@
+//! Integration tests for usage metering endpoints
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use localup_api::{models::*, ApiServer, ApiServerConfig};
+use localup_control::{MeterKey, TrafficMeter, TunnelConnectionManager};
+use localup_relay_db::entities::traffic_rollup;
+use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, Set};
+use sea_orm_migration::MigratorTrait;
+use serde_json::json;
+use std::sync::Arc;
+use tower::ServiceExt; // For `oneshot` method
+use uuid::Uuid;
+
+async fn create_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:")
+        .await
+        .expect("Failed to create in-memory database");
+
+    localup_relay_db::migrator::Migrator::up(&db, None)
+        .await
+        .expect("Failed to run migrations");
+
+    db
+}
+
+fn create_test_app(db: DatabaseConnection, meter: Arc<TrafficMeter>) -> Router {
+    let config = ApiServerConfig {
+        http_addr: Some("127.0.0.1:0".parse().unwrap()),
+        https_addr: None,
+        enable_cors: true,
+        cors_origins: None,
+        jwt_secret: "test-secret".to_string(),
+        tls_cert_path: None,
+        tls_key_path: None,
+    };
+
+    ApiServer::new(config, Arc::new(TunnelConnectionManager::new()), db, true)
+        .with_traffic_meter(meter)
+        .build_router()
+}
+
+async fn register(app: &Router, email: &str) -> (Uuid, String) {
+    let request = Request::builder()
+        .uri("/api/auth/register")
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "email": email, "password": "SecurePassword123!" }).to_string(),
+        ))
+        .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let data: RegisterResponse = serde_json::from_slice(&body).unwrap();
+    (Uuid::parse_str(&data.user.id).unwrap(), data.token)
+}
+
+async fn get(app: &Router, token: &str, uri: &str) -> (StatusCode, String) {
+    let request = Request::builder()
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn insert_rollup(
+    db: &DatabaseConnection,
+    hour: DateTime<Utc>,
+    localup_id: &str,
+    user_id: Uuid,
+    bytes_in: i64,
+) {
+    let key = MeterKey {
+        localup_id: localup_id.to_string(),
+        token_hash: None,
+        user_id: Some(user_id),
+        team_id: None,
+    };
+    traffic_rollup::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        hour: Set(hour),
+        series: Set(key.series()),
+        localup_id: Set(localup_id.to_string()),
+        token_hash: Set(None),
+        user_id: Set(Some(user_id)),
+        team_id: Set(None),
+        bytes_in: Set(bytes_in),
+        bytes_out: Set(2 * bytes_in),
+        requests: Set(1),
+        connections: Set(1),
+        connection_millis: Set(1500),
+        updated_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_usage_grouped_by_tunnel_and_hour() {
+    let db = create_test_db().await;
+    let meter = Arc::new(TrafficMeter::new(db.clone()));
+    let app = create_test_app(db.clone(), meter.clone());
+    let (user_id, token) = register(&app, "alice@example.com").await;
+    let (other_id, _) = register(&app, "bob@example.com").await;
+
+    let hour = Utc::now().duration_trunc(Duration::hours(1)).unwrap() - Duration::hours(3);
+    insert_rollup(&db, hour, "api", user_id, 100).await;
+    insert_rollup(&db, hour + Duration::hours(1), "api", user_id, 50).await;
+    insert_rollup(&db, hour, "web", user_id, 10).await;
+    insert_rollup(&db, hour, "other", other_id, 1000).await;
+
+    // Traffic the relay has not written yet is included
+    let pending = meter.tunnel(MeterKey {
+        localup_id: "web".to_string(),
+        token_hash: None,
+        user_id: Some(user_id),
+        team_id: None,
+    });
+    pending.record(5, 0);
+
+    let from = (hour - Duration::hours(1)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let (status, body) = get(&app, &token, &format!("/api/usage?from={}", from)).await;
+    assert_eq!(status, StatusCode::OK);
+    let report: UsageReport = serde_json::from_str(&body).unwrap();
+    assert_eq!(report.group_by, UsageGroupBy::Tunnel);
+    assert_eq!(report.records.len(), 2);
+    assert_eq!(report.records[0].tunnel_id.as_deref(), Some("api"));
+    assert_eq!(report.records[0].bytes_in, 150);
+    assert_eq!(report.records[1].bytes_in, 15);
+    assert_eq!(report.totals.bytes_in, 165);
+    assert_eq!(report.totals.requests, 3);
+    assert_eq!(report.totals.connection_seconds, 4.5);
+
+    // Ranges select whole hours
+    let to = (hour + Duration::hours(1)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let (_, body) = get(
+        &app,
+        &token,
+        &format!("/api/usage?from={}&to={}&group_by=hour", from, to),
+    )
+    .await;
+    let report: UsageReport = serde_json::from_str(&body).unwrap();
+    assert_eq!(report.records.len(), 1);
+    assert_eq!(report.records[0].hour, Some(hour));
+    assert_eq!(report.records[0].bytes_in, 110);
+}
+
+#[tokio::test]
+async fn test_usage_csv_export() {
+    let db = create_test_db().await;
+    let app = create_test_app(db.clone(), Arc::new(TrafficMeter::new(db.clone())));
+    let (user_id, token) = register(&app, "alice@example.com").await;
+
+    let hour = Utc::now().duration_trunc(Duration::hours(1)).unwrap();
+    insert_rollup(&db, hour, "api", user_id, 100).await;
+
+    let request = Request::builder()
+        .uri("/api/usage/export?group_by=user")
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let csv = String::from_utf8(body.to_vec()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "hour,tunnel_id,token_id,token_name,user_id,team_id,bytes_in,bytes_out,requests,connections,connection_seconds"
+    );
+    assert_eq!(lines[1], format!(",,,,{},,100,200,1,1,1.500", user_id));
+}
+
+#[tokio::test]
+async fn test_usage_scope_requires_permission() {
+    let db = create_test_db().await;
+    let app = create_test_app(db.clone(), Arc::new(TrafficMeter::new(db)));
+    let (_, token) = register(&app, "alice@example.com").await;
+
+    let (status, _) = get(&app, &token, "/api/usage?all=true").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = get(
+        &app,
+        &token,
+        &format!("/api/usage?user_id={}", Uuid::new_v4()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = get(
+        &app,
+        &token,
+        &format!("/api/usage?team_id={}", Uuid::new_v4()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = get(
+        &app,
+        &token,
+        "/api/usage?from=2026-10-02T00:00:00Z&to=2026-10-01T00:00:00Z",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let request = Request::builder()
+        .uri("/api/usage")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
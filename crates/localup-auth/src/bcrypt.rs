@@ -0,0 +1,113 @@
+//! bcrypt password hash verification
+//!
+//! Only verification is supported: new hashes use Argon2id (see
+//! [`hash_password`](crate::password::hash_password)), but existing
+//! `htpasswd -B` style hashes (`$2a$`, `$2b$`, `$2y$`) keep working.
+
+use crate::password::PasswordError;
+
+/// Highest accepted bcrypt cost
+///
+/// Each step doubles the work, so a hash with a very high cost would let a
+/// single login attempt occupy a CPU for hours.
+const MAX_COST: u32 = 14;
+
+/// Check whether `hash` looks like a bcrypt hash
+pub(crate) fn is_bcrypt_hash(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
+
+/// Check that a bcrypt hash is well-formed and within [`MAX_COST`]
+pub(crate) fn check(hash: &str) -> Result<(), PasswordError> {
+    let invalid = || PasswordError::InvalidHashFormat("malformed bcrypt hash".to_string());
+
+    // $2b$12$<22 chars salt><31 chars hash>
+    let parts: Vec<&str> = hash.split('$').collect();
+    let [_, version, cost, _] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    if *version == "2x" {
+        return Err(PasswordError::VerificationFailed(
+            "$2x$ bcrypt hashes are not supported".to_string(),
+        ));
+    }
+    let cost: u32 = cost.parse().map_err(|_| invalid())?;
+    if cost > MAX_COST {
+        return Err(PasswordError::VerificationFailed(format!(
+            "bcrypt cost {} exceeds the maximum of {}",
+            cost, MAX_COST
+        )));
+    }
+    Ok(())
+}
+
+/// Verify a password against a bcrypt hash
+pub(crate) fn verify(password: &str, hash: &str) -> Result<bool, PasswordError> {
+    check(hash)?;
+    ::bcrypt::verify(password, hash).map_err(|e| PasswordError::InvalidHashFormat(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_known_hashes() {
+        assert!(verify(
+            "hunter2",
+            "$2b$04$Zm/1Z6tfmUG7dI.sRQWplujqvWa8NU4A8d7KbYuGT.nOeQ8FVcjFe"
+        )
+        .unwrap());
+        assert!(verify(
+            "U*U",
+            "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW"
+        )
+        .unwrap());
+        assert!(verify(
+            "",
+            "$2y$04$EIEr3/CL9XHH72Lx1Z8i8.Oo7KHxiErlDWskoBi/ZmXOLQRRXsfES"
+        )
+        .unwrap());
+        assert!(!verify(
+            "hunter3",
+            "$2b$04$Zm/1Z6tfmUG7dI.sRQWplujqvWa8NU4A8d7KbYuGT.nOeQ8FVcjFe"
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_long_passwords_truncated() {
+        let hash = "$2b$04$CCCCCCCCCCCCCCCCCCCCC.mDyzbhj.9K0apb9O/AGMWwoLXiYAu6u";
+        assert!(verify(&"a".repeat(72), hash).unwrap());
+        assert!(verify(&"a".repeat(80), hash).unwrap());
+        assert!(!verify(&"a".repeat(71), hash).unwrap());
+    }
+
+    #[test]
+    fn test_malformed_hashes() {
+        assert!(verify("pw", "$2b$04$short").is_err());
+        assert!(verify(
+            "pw",
+            "$2b$04$CCCCCCCCCCCCCCCCCCCCC!mDyzbhj.9K0apb9O/AGMWwoLXiYAu6u"
+        )
+        .is_err());
+        assert!(is_bcrypt_hash("$2y$10$abc"));
+        assert!(!is_bcrypt_hash("$argon2id$v=19$"));
+    }
+
+    #[test]
+    fn test_cost_is_capped() {
+        for cost in ["15", "31", "99"] {
+            let hash = format!(
+                "$2b${}$CCCCCCCCCCCCCCCCCCCCC.mDyzbhj.9K0apb9O/AGMWwoLXiYAu6u",
+                cost
+            );
+            assert!(matches!(
+                verify("pw", &hash),
+                Err(PasswordError::VerificationFailed(_))
+            ));
+        }
+    }
+}
@@ -2,10 +2,16 @@
 
 use async_trait::async_trait;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, EncodingKey, Header, Validation};
+use localup_proto::{Protocol, TcpLimits};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use thiserror::Error;
 
+use crate::jwks::{JwksFile, VerificationKey};
+use crate::scope::PortRange;
 use crate::validator::{AuthError, AuthResult, AuthValidator};
 
 /// JWT claims for tunnel authentication
@@ -21,11 +27,14 @@ pub struct JwtClaims {
     pub iss: String,
     /// Audience
     pub aud: String,
+    /// Unique token ID, used to revoke a single token before it expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
     /// Custom: allowed protocols
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub protocols: Vec<String>,
     /// Custom: allowed regions
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub regions: Vec<String>,
     /// Custom: whether client can request reverse tunnels (agent-to-client connections)
     /// Default: None (backward compatibility - assume allowed if not specified)
@@ -57,6 +66,31 @@ pub struct JwtClaims {
     /// Token type: "session" (web UI) or "auth" (API key for tunnels)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token_type: Option<String>,
+    /// Connection limits applied to TCP tunnels opened with this token
+    /// The relay keeps the stricter of these and the limits requested by the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp_limits: Option<TcpLimits>,
+    /// Subdomain patterns HTTP(S) and hostname-routed TCP tunnels may use ("myapp-*")
+    /// None allows any subdomain; an empty list allows none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_subdomains: Option<Vec<String>>,
+    /// Custom domain patterns tunnels may use, including TLS SNI patterns ("*.example.com")
+    /// None allows any domain; an empty list allows none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_domains: Option<Vec<String>>,
+    /// Public TCP ports tunnels may use ("10000-10100")
+    /// None allows any port in the relay's range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_ports: Option<Vec<PortRange>>,
+    /// How many tunnels may be connected with this token at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tunnels: Option<u32>,
+}
+
+/// Random token ID, kept short since tokens are also sent as SOCKS5 passwords (max 255 bytes)
+fn new_jti() -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    URL_SAFE_NO_PAD.encode(uuid::Uuid::new_v4().as_bytes())
 }
 
 impl JwtClaims {
@@ -70,6 +104,7 @@ impl JwtClaims {
             exp: exp.timestamp(),
             iss: issuer,
             aud: audience,
+            jti: Some(new_jti()),
             protocols: Vec::new(),
             regions: Vec::new(),
             reverse_tunnel: None,
@@ -80,9 +115,20 @@ impl JwtClaims {
             user_role: None,
             team_role: None,
             token_type: None,
+            tcp_limits: None,
+            allowed_subdomains: None,
+            allowed_domains: None,
+            allowed_ports: None,
+            max_tunnels: None,
         }
     }
 
+    /// Override the generated token ID
+    pub fn with_jti(mut self, jti: String) -> Self {
+        self.jti = Some(jti);
+        self
+    }
+
     pub fn with_protocols(mut self, protocols: Vec<String>) -> Self {
         self.protocols = protocols;
         self
@@ -153,6 +199,71 @@ impl JwtClaims {
         self
     }
 
+    /// Limit connections to TCP tunnels opened with this token
+    pub fn with_tcp_limits(mut self, limits: TcpLimits) -> Self {
+        self.tcp_limits = Some(limits);
+        self
+    }
+
+    /// Restrict the subdomains tunnels may use (patterns may contain `*`)
+    pub fn with_allowed_subdomains(mut self, patterns: Vec<String>) -> Self {
+        self.allowed_subdomains = Some(patterns);
+        self
+    }
+
+    /// Restrict the custom domains and SNI hostnames tunnels may use (patterns may contain `*`)
+    pub fn with_allowed_domains(mut self, patterns: Vec<String>) -> Self {
+        self.allowed_domains = Some(patterns);
+        self
+    }
+
+    /// Restrict the public TCP ports tunnels may use
+    pub fn with_allowed_ports(mut self, ports: Vec<PortRange>) -> Self {
+        self.allowed_ports = Some(ports);
+        self
+    }
+
+    /// Limit how many tunnels may be connected with this token at once
+    pub fn with_max_tunnels(mut self, max: u32) -> Self {
+        self.max_tunnels = Some(max);
+        self
+    }
+
+    /// Whether the token allows a public TCP port
+    pub fn is_port_allowed(&self, port: u16) -> bool {
+        self.to_auth_result().is_port_allowed(port)
+    }
+
+    /// Check a tunnel endpoint against the token's scopes
+    ///
+    /// See [`AuthResult::validate_endpoint`].
+    pub fn validate_endpoint(&self, protocol: &Protocol) -> Result<(), String> {
+        self.to_auth_result().validate_endpoint(protocol)
+    }
+
+    /// Identity, permissions and scopes carried by the token
+    pub fn to_auth_result(&self) -> AuthResult {
+        AuthResult {
+            localup_id: self.sub.clone(),
+            user_id: self.user_id.clone(),
+            allowed_protocols: self.protocols.clone(),
+            allowed_regions: self.regions.clone(),
+            metadata: [
+                ("iss".to_string(), self.iss.clone()),
+                ("aud".to_string(), self.aud.clone()),
+                ("exp".to_string(), self.exp.to_string()),
+            ]
+            .into(),
+            team_id: self.team_id.clone(),
+            allowed_subdomains: self.allowed_subdomains.clone(),
+            allowed_domains: self.allowed_domains.clone(),
+            allowed_ports: self.allowed_ports.clone(),
+            max_tunnels: self.max_tunnels,
+            tcp_limits: self.tcp_limits,
+            token_id: self.jti.clone(),
+        }
+    }
+
     pub fn is_expired(&self) -> bool {
         Utc::now().timestamp() > self.exp
     }
@@ -256,11 +367,36 @@ pub enum JwtError {
 
     #[error("Invalid token")]
     InvalidToken,
+
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+}
+
+/// Expired tokens stay expired; anything else is not a valid JWT for this
+/// validator, letting an `AuthValidatorChain` try the next one
+impl From<JwtError> for AuthError {
+    fn from(e: JwtError) -> Self {
+        match e {
+            JwtError::TokenExpired => AuthError::TokenExpired,
+            JwtError::EncodingError(ref inner)
+                if matches!(inner.kind(), ErrorKind::ExpiredSignature) =>
+            {
+                AuthError::TokenExpired
+            }
+            e => AuthError::InvalidToken(format!("Invalid JWT token: {}", e)),
+        }
+    }
 }
 
 /// JWT validator
+///
+/// Holds one or more verification keys. A token is checked against the keys
+/// whose algorithm matches its `alg` header and whose key ID matches its
+/// `kid` header (see [`VerificationKey`]), so secrets and key pairs can be
+/// rotated without invalidating tokens that are still in use.
 pub struct JwtValidator {
-    decoding_key: DecodingKey,
+    keys: Vec<VerificationKey>,
+    jwks_file: Option<Arc<JwksFile>>,
     validation: Validation,
 }
 
@@ -277,17 +413,7 @@ impl JwtValidator {
     /// - Not-before claim
     /// - Any other claims
     pub fn new(secret: &[u8]) -> Self {
-        let mut validation = Validation::new(Algorithm::HS256);
-        // Only validate expiration - skip all other claims
-        validation.validate_exp = true;
-        validation.validate_aud = false;
-        validation.validate_nbf = false;
-        // Note: Issuer validation is disabled by default (only enabled if set_issuer() is called)
-
-        Self {
-            decoding_key: DecodingKey::from_secret(secret),
-            validation,
-        }
+        Self::from_keys(vec![VerificationKey::hmac(None, secret)])
     }
 
     /// Create a new JWT validator using RSA public key (asymmetric)
@@ -304,18 +430,56 @@ impl JwtValidator {
     /// - Not-before claim
     /// - Any other claims
     pub fn from_rsa_pem(public_key_pem: &[u8]) -> Result<Self, JwtError> {
-        let mut validation = Validation::new(Algorithm::RS256);
+        Ok(Self::from_keys(vec![VerificationKey::from_pem(
+            None,
+            Algorithm::RS256,
+            public_key_pem,
+        )?]))
+    }
+
+    /// Create a JWT validator that accepts tokens signed with any of `keys`
+    ///
+    /// Validates the same claims as [`JwtValidator::new`].
+    pub fn from_keys(keys: Vec<VerificationKey>) -> Self {
+        let mut validation = Validation::new(Algorithm::HS256);
         // Only validate expiration - skip all other claims
         validation.validate_exp = true;
         validation.validate_aud = false;
         validation.validate_nbf = false;
         // Note: Issuer validation is disabled by default (only enabled if set_issuer() is called)
 
-        Ok(Self {
-            decoding_key: DecodingKey::from_rsa_pem(public_key_pem)
-                .map_err(JwtError::EncodingError)?,
+        Self {
+            keys,
+            jwks_file: None,
             validation,
-        })
+        }
+    }
+
+    /// Also accept tokens signed with `key`
+    pub fn with_key(mut self, key: VerificationKey) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// Also accept tokens signed with the keys currently in a JWKS file
+    pub fn with_jwks_file(mut self, file: Arc<JwksFile>) -> Self {
+        self.jwks_file = Some(file);
+        self
+    }
+
+    /// Public keys of the configured asymmetric keys, for publishing as a JWKS
+    ///
+    /// Keys read from a JWKS file belong to another issuer and are not included.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .keys
+                .iter()
+                .filter_map(|key| key.jwk())
+                .filter(|jwk| !matches!(jwk.algorithm, AlgorithmParameters::OctetKey(_)))
+                .cloned()
+                .collect(),
+        }
     }
 
     pub fn with_audience(mut self, audience: String) -> Self {
@@ -329,13 +493,36 @@ impl JwtValidator {
     }
 
     pub fn validate(&self, token: &str) -> Result<JwtClaims, JwtError> {
-        let token_data = decode::<JwtClaims>(token, &self.decoding_key, &self.validation)?;
-
-        if token_data.claims.is_expired() {
-            return Err(JwtError::TokenExpired);
+        let header = decode_header(token).map_err(|_| JwtError::InvalidToken)?;
+        let file_keys = self.jwks_file.as_ref().map(|file| file.keys());
+        let candidates = self
+            .keys
+            .iter()
+            .chain(file_keys.iter().flat_map(|keys| keys.iter()))
+            .filter(|key| key.matches(header.kid.as_deref(), header.alg));
+
+        let mut result = Err(JwtError::InvalidToken);
+        for key in candidates {
+            let mut validation = self.validation.clone();
+            validation.algorithms = vec![key.algorithm()];
+
+            match decode::<JwtClaims>(token, key.decoding_key(), &validation) {
+                Ok(token_data) => {
+                    if token_data.claims.is_expired() {
+                        return Err(JwtError::TokenExpired);
+                    }
+                    return Ok(token_data.claims);
+                }
+                // Signed with a different key; try the next one
+                Err(e) if matches!(e.kind(), ErrorKind::InvalidSignature) => {
+                    result = Err(e.into());
+                }
+                // The signature checked out but the claims did not
+                Err(e) => return Err(e.into()),
+            }
         }
 
-        Ok(token_data.claims)
+        result
     }
 
     /// Encode JWT using HMAC-SHA256 (symmetric secret)
@@ -363,24 +550,9 @@ impl JwtValidator {
 impl AuthValidator for JwtValidator {
     async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
         // Validate JWT using existing method
-        let claims = self.validate(token).map_err(|e| match e {
-            JwtError::TokenExpired => AuthError::TokenExpired,
-            JwtError::InvalidToken => AuthError::InvalidToken("Invalid JWT".to_string()),
-            JwtError::EncodingError(e) => AuthError::AuthenticationFailed(e.to_string()),
-        })?;
+        let claims = self.validate(token)?;
 
-        // Convert JWT claims to AuthResult
-        let mut result = AuthResult::new(claims.sub.clone())
-            .with_protocols(claims.protocols.clone())
-            .with_regions(claims.regions.clone());
-
-        // Add issuer and audience as metadata
-        result = result
-            .with_metadata("iss".to_string(), claims.iss.clone())
-            .with_metadata("aud".to_string(), claims.aud.clone())
-            .with_metadata("exp".to_string(), claims.exp.to_string());
-
-        Ok(result)
+        Ok(claims.to_auth_result())
     }
 }
 
@@ -410,6 +582,9 @@ mod tests {
         assert_eq!(decoded_claims.sub, claims.sub);
         assert_eq!(decoded_claims.iss, claims.iss);
         assert_eq!(decoded_claims.aud, claims.aud);
+        assert!(decoded_claims.jti.is_some());
+        assert_eq!(decoded_claims.jti, claims.jti);
+        assert_eq!(decoded_claims.to_auth_result().token_id, claims.jti);
     }
 
     #[test]
@@ -432,6 +607,111 @@ mod tests {
         assert_eq!(decoded.protocols, vec!["tcp", "https"]);
     }
 
+    #[test]
+    fn test_jwt_with_tcp_limits() {
+        let limits = TcpLimits {
+            max_connections: Some(5),
+            idle_timeout_secs: Some(300),
+            ..Default::default()
+        };
+        let claims = JwtClaims::new(
+            "localup-789".to_string(),
+            "issuer".to_string(),
+            "audience".to_string(),
+            Duration::hours(1),
+        )
+        .with_tcp_limits(limits);
+
+        let token = JwtValidator::encode(TEST_SECRET, &claims).unwrap();
+        let decoded_claims = JwtValidator::new(TEST_SECRET)
+            .with_issuer("issuer".to_string())
+            .with_audience("audience".to_string())
+            .validate(&token)
+            .unwrap();
+
+        assert_eq!(decoded_claims.tcp_limits, Some(limits));
+    }
+
+    #[test]
+    fn test_validate_endpoint_scopes() {
+        let claims = JwtClaims::new(
+            "localup-789".to_string(),
+            "issuer".to_string(),
+            "audience".to_string(),
+            Duration::hours(1),
+        )
+        .with_protocols(vec!["https".to_string(), "tcp".to_string()])
+        .with_allowed_subdomains(vec!["myapp-*".to_string()])
+        .with_allowed_domains(vec!["*.example.com".to_string()])
+        .with_allowed_ports(vec!["15000-15010".parse().unwrap()]);
+
+        let https = |subdomain: Option<&str>, custom_domain: Option<&str>| Protocol::Https {
+            subdomain: subdomain.map(str::to_string),
+            custom_domain: custom_domain.map(str::to_string),
+        };
+        assert!(claims
+            .validate_endpoint(&https(Some("myapp-dev"), None))
+            .is_ok());
+        assert!(claims
+            .validate_endpoint(&https(Some("other"), None))
+            .is_err());
+        assert!(claims
+            .validate_endpoint(&https(None, Some("api.example.com")))
+            .is_ok());
+        assert!(claims
+            .validate_endpoint(&https(None, Some("api.evil.com")))
+            .is_err());
+
+        let err = claims
+            .validate_endpoint(&Protocol::Http {
+                subdomain: Some("myapp-dev".to_string()),
+                custom_domain: None,
+            })
+            .unwrap_err();
+        assert!(err.contains("does not allow http tunnels"));
+
+        assert!(claims.validate_endpoint(&Protocol::Tcp { port: 0 }).is_ok());
+        assert!(claims
+            .validate_endpoint(&Protocol::Tcp { port: 15005 })
+            .is_ok());
+        assert!(claims
+            .validate_endpoint(&Protocol::Tcp { port: 22 })
+            .is_err());
+        assert!(claims
+            .validate_endpoint(&Protocol::TcpNamed {
+                hostname: "db.example.com".to_string()
+            })
+            .is_ok());
+
+        // Scopes survive encoding
+        let token = JwtValidator::encode(TEST_SECRET, &claims.clone().with_max_tunnels(2)).unwrap();
+        let decoded = JwtValidator::new(TEST_SECRET)
+            .with_issuer("issuer".to_string())
+            .with_audience("audience".to_string())
+            .validate(&token)
+            .unwrap();
+        assert_eq!(decoded.allowed_ports, claims.allowed_ports);
+        assert_eq!(decoded.max_tunnels, Some(2));
+    }
+
+    #[test]
+    fn test_unscoped_token_allows_everything() {
+        let claims = JwtClaims::new(
+            "localup-789".to_string(),
+            "issuer".to_string(),
+            "audience".to_string(),
+            Duration::hours(1),
+        );
+        assert!(claims
+            .validate_endpoint(&Protocol::Tls {
+                port: 8443,
+                sni_patterns: vec!["*".to_string()],
+                alpn: vec![],
+            })
+            .is_ok());
+        assert!(claims.is_port_allowed(22));
+    }
+
     #[test]
     fn test_expired_token() {
         let claims = JwtClaims::new(
@@ -1,14 +1,21 @@
 //! Authentication and authorization for tunnel system
 
+mod bcrypt;
+pub mod jwks;
 pub mod jwt;
 pub mod password;
+pub mod scope;
 pub mod token;
 pub mod validator;
 
+pub use jwks::{decode_signed, JwksFile, JwtSigner, VerificationKey};
 pub use jwt::{JwtClaims, JwtError, JwtValidator};
-pub use password::{hash_password, verify_password, PasswordError};
+pub use password::{
+    check_password_hash, hash_password, is_password_hash, verify_password, PasswordError,
+};
+pub use scope::PortRange;
 pub use token::{Token, TokenError, TokenGenerator};
-pub use validator::{AuthError, AuthResult, AuthValidator};
+pub use validator::{AuthError, AuthResult, AuthValidator, AuthValidatorChain};
 
 // Re-export useful types
 pub use async_trait::async_trait;
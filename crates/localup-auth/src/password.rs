@@ -1,11 +1,27 @@
 //! Password hashing and verification using Argon2id
+//!
+//! Verification also accepts bcrypt hashes so credentials copied from an
+//! existing htpasswd file keep working.
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    Argon2, Params,
 };
 use thiserror::Error;
 
+/// Highest accepted Argon2 memory cost, in KiB (64 MiB)
+///
+/// Verification uses the parameters stored in the hash, so without these
+/// limits a crafted hash could make every login allocate gigabytes or run
+/// for seconds.
+const MAX_ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+
+/// Highest accepted Argon2 time cost (iterations)
+const MAX_ARGON2_TIME_COST: u32 = 10;
+
+/// Highest accepted Argon2 parallelism
+const MAX_ARGON2_PARALLELISM: u32 = 4;
+
 /// Error types for password operations
 #[derive(Error, Debug)]
 pub enum PasswordError {
@@ -62,7 +78,8 @@ pub fn hash_password(password: &str) -> Result<String, PasswordError> {
 ///
 /// # Arguments
 /// * `password` - The plain text password to verify
-/// * `hash` - The PHC-formatted hash string (from database)
+/// * `hash` - The PHC-formatted hash string (from database), or a bcrypt
+///   hash (`$2a$`, `$2b$`, `$2y$`)
 ///
 /// # Returns
 /// * `Ok(true)` - Password matches hash
@@ -78,8 +95,11 @@ pub fn hash_password(password: &str) -> Result<String, PasswordError> {
 /// assert!(!verify_password("WrongPassword", &hash).unwrap());
 /// ```
 pub fn verify_password(password: &str, hash: &str) -> Result<bool, PasswordError> {
-    let parsed_hash =
-        PasswordHash::new(hash).map_err(|e| PasswordError::InvalidHashFormat(e.to_string()))?;
+    if crate::bcrypt::is_bcrypt_hash(hash) {
+        return crate::bcrypt::verify(password, hash);
+    }
+
+    let parsed_hash = parse_argon2_hash(hash)?;
 
     let argon2 = Argon2::default();
 
@@ -90,6 +110,67 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool, PasswordError
     }
 }
 
+/// Check that a password hash can be verified without excessive cost
+///
+/// Rejects malformed hashes, bcrypt costs above 14 and Argon2 parameters
+/// above 64 MiB of memory, 10 iterations or 4 lanes. [`verify_password`]
+/// applies the same check; call this when accepting configured hashes to
+/// report them up front.
+///
+/// # Example
+/// ```
+/// use localup_auth::password::{check_password_hash, hash_password};
+///
+/// assert!(check_password_hash(&hash_password("pw").unwrap()).is_ok());
+/// assert!(check_password_hash("$argon2id$v=19$m=4194304,t=100,p=1$c2FsdHNhbHQ$aGFzaA").is_err());
+/// ```
+pub fn check_password_hash(hash: &str) -> Result<(), PasswordError> {
+    if crate::bcrypt::is_bcrypt_hash(hash) {
+        return crate::bcrypt::check(hash);
+    }
+    parse_argon2_hash(hash).map(|_| ())
+}
+
+/// Parse an Argon2 PHC string, enforcing the parameter limits
+fn parse_argon2_hash(hash: &str) -> Result<PasswordHash<'_>, PasswordError> {
+    let parsed_hash =
+        PasswordHash::new(hash).map_err(|e| PasswordError::InvalidHashFormat(e.to_string()))?;
+    let params = Params::try_from(&parsed_hash)
+        .map_err(|e| PasswordError::InvalidHashFormat(e.to_string()))?;
+
+    if params.m_cost() > MAX_ARGON2_MEMORY_KIB
+        || params.t_cost() > MAX_ARGON2_TIME_COST
+        || params.p_cost() > MAX_ARGON2_PARALLELISM
+    {
+        return Err(PasswordError::VerificationFailed(format!(
+            "Argon2 parameters m={},t={},p={} exceed the maximum of m={},t={},p={}",
+            params.m_cost(),
+            params.t_cost(),
+            params.p_cost(),
+            MAX_ARGON2_MEMORY_KIB,
+            MAX_ARGON2_TIME_COST,
+            MAX_ARGON2_PARALLELISM
+        )));
+    }
+    Ok(parsed_hash)
+}
+
+/// Check whether a string is a password hash accepted by [`verify_password`]
+///
+/// Used to tell hashed credentials apart from plain text ones.
+///
+/// # Example
+/// ```
+/// use localup_auth::password::is_password_hash;
+///
+/// assert!(is_password_hash("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA"));
+/// assert!(is_password_hash("$2b$12$hR2Jtlz18VDR5Yl0j.zs0.m/oiTtp32xydyy6KtW03IrPCZyEvvJy"));
+/// assert!(!is_password_hash("hunter2"));
+/// ```
+pub fn is_password_hash(value: &str) -> bool {
+    value.starts_with("$argon2") || crate::bcrypt::is_bcrypt_hash(value)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,4 +253,44 @@ mod tests {
         assert!(!verify_password("testpassword123!", &hash).unwrap());
         assert!(!verify_password("TESTPASSWORD123!", &hash).unwrap());
     }
+
+    #[test]
+    fn test_verify_password_bcrypt() {
+        let hash = "$2b$04$Zm/1Z6tfmUG7dI.sRQWplujqvWa8NU4A8d7KbYuGT.nOeQ8FVcjFe";
+        assert!(verify_password("hunter2", hash).unwrap());
+        assert!(!verify_password("hunter3", hash).unwrap());
+    }
+
+    #[test]
+    fn test_argon2_params_are_capped() {
+        let salt = "c2FsdHNhbHRzYWx0";
+        let hash = "aGFzaGhhc2hoYXNoaGFzaGhhc2hoYXNoaGFzaGhhc2g";
+
+        for params in ["m=4194304,t=1,p=1", "m=19456,t=100,p=1", "m=65536,t=2,p=16"] {
+            let crafted = format!("$argon2id$v=19${}${}${}", params, salt, hash);
+            assert!(matches!(
+                check_password_hash(&crafted),
+                Err(PasswordError::VerificationFailed(_))
+            ));
+            // Refused before any hashing work
+            assert!(verify_password("pw", &crafted).is_err());
+        }
+
+        let at_limit = format!("$argon2id$v=19$m=65536,t=10,p=4${}${}", salt, hash);
+        assert!(check_password_hash(&at_limit).is_ok());
+        assert!(check_password_hash(&hash_password("pw").unwrap()).is_ok());
+        assert!(check_password_hash(
+            "$2b$31$Zm/1Z6tfmUG7dI.sRQWplujqvWa8NU4A8d7KbYuGT.nOeQ8FVcjFe"
+        )
+        .is_err());
+        assert!(check_password_hash("not-a-hash").is_err());
+    }
+
+    #[test]
+    fn test_is_password_hash() {
+        let hash = hash_password("pw").unwrap();
+        assert!(is_password_hash(&hash));
+        assert!(!is_password_hash("pw"));
+        assert!(!is_password_hash(""));
+    }
 }
@@ -0,0 +1,145 @@
+//! Tunnel scopes carried in JWT claims
+
+use localup_proto::Protocol;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Inclusive range of TCP ports, written as "10000-10100" or "5432"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    pub fn contains(&self, port: u16) -> bool {
+        (self.start..=self.end).contains(&port)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |p: &str| {
+            p.trim()
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid port range '{}'", s))
+        };
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => {
+                let port = parse(s)?;
+                (port, port)
+            }
+        };
+        if start == 0 || start > end {
+            return Err(format!("Invalid port range '{}'", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PortRange> for String {
+    fn from(range: PortRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+/// Name of a tunnel protocol in the `protocols` claim
+pub fn protocol_scope(protocol: &Protocol) -> &'static str {
+    match protocol {
+        Protocol::Http { .. } => "http",
+        Protocol::Https { .. } => "https",
+        Protocol::Tcp { .. } | Protocol::TcpNamed { .. } => "tcp",
+        Protocol::Tls { .. } | Protocol::TlsTerminated { .. } => "tls",
+    }
+}
+
+/// Match a hostname against a pattern where `*` stands for any run of characters
+/// (case-insensitive, e.g. "myapp-*" or "*.example.com")
+pub fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let value = value.to_ascii_lowercase();
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_range_parse() {
+        assert_eq!(
+            "10000-10100".parse::<PortRange>().unwrap(),
+            PortRange {
+                start: 10000,
+                end: 10100
+            }
+        );
+        assert_eq!(
+            "5432".parse::<PortRange>().unwrap(),
+            PortRange {
+                start: 5432,
+                end: 5432
+            }
+        );
+        assert!("20-10".parse::<PortRange>().is_err());
+        assert!("0".parse::<PortRange>().is_err());
+        assert!("abc".parse::<PortRange>().is_err());
+
+        let json = serde_json::to_string(&"22-23".parse::<PortRange>().unwrap()).unwrap();
+        assert_eq!(json, "\"22-23\"");
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("api", "api"));
+        assert!(matches_pattern("api", "API"));
+        assert!(!matches_pattern("api", "api2"));
+        assert!(matches_pattern("myapp-*", "myapp-staging"));
+        assert!(!matches_pattern("myapp-*", "other"));
+        assert!(matches_pattern("*.example.com", "api.example.com"));
+        assert!(!matches_pattern("*.example.com", "example.com"));
+        assert!(matches_pattern("*-pr-*", "web-pr-42"));
+        assert!(matches_pattern("*", "anything"));
+    }
+}
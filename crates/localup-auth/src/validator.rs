@@ -4,10 +4,14 @@
 //! implement custom authentication logic (JWT, API keys, OAuth, database lookup, etc.)
 
 use async_trait::async_trait;
+use localup_proto::{Protocol, TcpLimits};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use thiserror::Error;
 
+use crate::scope::{matches_pattern, protocol_scope, PortRange};
+
 /// Authentication result containing validated identity and claims
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AuthResult {
@@ -25,6 +29,34 @@ pub struct AuthResult {
 
     /// Custom metadata (plan tier, rate limits, etc.)
     pub metadata: HashMap<String, String>,
+
+    /// Team the tunnel belongs to (used for team port quotas and reservations)
+    #[serde(default)]
+    pub team_id: Option<String>,
+
+    /// Subdomain patterns tunnels may use (None = any)
+    #[serde(default)]
+    pub allowed_subdomains: Option<Vec<String>>,
+
+    /// Custom domain and SNI patterns tunnels may use (None = any)
+    #[serde(default)]
+    pub allowed_domains: Option<Vec<String>>,
+
+    /// Public TCP ports tunnels may use (None = any port in the relay's range)
+    #[serde(default)]
+    pub allowed_ports: Option<Vec<PortRange>>,
+
+    /// How many tunnels may be connected with the same token at once
+    #[serde(default)]
+    pub max_tunnels: Option<u32>,
+
+    /// Connection limits applied to TCP tunnels
+    #[serde(default)]
+    pub tcp_limits: Option<TcpLimits>,
+
+    /// ID of the token that was validated (JWT `jti`), checked against the revocation list
+    #[serde(default)]
+    pub token_id: Option<String>,
 }
 
 impl AuthResult {
@@ -36,6 +68,13 @@ impl AuthResult {
             allowed_protocols: Vec::new(),
             allowed_regions: Vec::new(),
             metadata: HashMap::new(),
+            team_id: None,
+            allowed_subdomains: None,
+            allowed_domains: None,
+            allowed_ports: None,
+            max_tunnels: None,
+            tcp_limits: None,
+            token_id: None,
         }
     }
 
@@ -63,6 +102,53 @@ impl AuthResult {
         self
     }
 
+    /// Add team ID
+    pub fn with_team_id(mut self, team_id: String) -> Self {
+        self.team_id = Some(team_id);
+        self
+    }
+
+    /// Restrict the subdomains tunnels may use (patterns may contain `*`)
+    pub fn with_allowed_subdomains(mut self, patterns: Vec<String>) -> Self {
+        self.allowed_subdomains = Some(patterns);
+        self
+    }
+
+    /// Restrict the custom domains and SNI hostnames tunnels may use (patterns may contain `*`)
+    pub fn with_allowed_domains(mut self, patterns: Vec<String>) -> Self {
+        self.allowed_domains = Some(patterns);
+        self
+    }
+
+    /// Restrict the public TCP ports tunnels may use
+    pub fn with_allowed_ports(mut self, ports: Vec<PortRange>) -> Self {
+        self.allowed_ports = Some(ports);
+        self
+    }
+
+    /// Limit how many tunnels may be connected with the same token at once
+    pub fn with_max_tunnels(mut self, max: u32) -> Self {
+        self.max_tunnels = Some(max);
+        self
+    }
+
+    /// Limit connections to TCP tunnels
+    pub fn with_tcp_limits(mut self, limits: TcpLimits) -> Self {
+        self.tcp_limits = Some(limits);
+        self
+    }
+
+    /// Record the ID of the validated token
+    pub fn with_token_id(mut self, token_id: String) -> Self {
+        self.token_id = Some(token_id);
+        self
+    }
+
+    /// Identity used for sticky domains and port assignments (user ID, else the validated ID)
+    pub fn identity(&self) -> &str {
+        self.user_id.as_deref().unwrap_or(&self.localup_id)
+    }
+
     /// Check if a protocol is allowed
     pub fn is_protocol_allowed(&self, protocol: &str) -> bool {
         self.allowed_protocols.is_empty() || self.allowed_protocols.contains(&protocol.to_string())
@@ -77,6 +163,100 @@ impl AuthResult {
     pub fn get_metadata(&self, key: &str) -> Option<&String> {
         self.metadata.get(key)
     }
+
+    /// Whether a public TCP port is allowed
+    pub fn is_port_allowed(&self, port: u16) -> bool {
+        self.allowed_ports
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|r| r.contains(port)))
+    }
+
+    /// Check a tunnel endpoint against the allowed protocols, hostnames and ports
+    ///
+    /// Returns Err with a reason suitable for a `Disconnect` message. TCP endpoints
+    /// requesting port 0 pass the port check; check the allocated port with
+    /// [`AuthResult::is_port_allowed`].
+    pub fn validate_endpoint(&self, protocol: &Protocol) -> Result<(), String> {
+        let scope = protocol_scope(protocol);
+        if !self.is_protocol_allowed(scope) {
+            return Err(format!(
+                "Token does not allow {} tunnels (allowed: {})",
+                scope,
+                self.allowed_protocols.join(", ")
+            ));
+        }
+
+        match protocol {
+            Protocol::Http {
+                subdomain,
+                custom_domain,
+            }
+            | Protocol::Https {
+                subdomain,
+                custom_domain,
+            } => {
+                if let Some(domain) = custom_domain.as_deref().filter(|d| !d.is_empty()) {
+                    self.check_domain(domain)
+                } else if let Some(subdomain) = subdomain.as_deref() {
+                    self.check_subdomain(subdomain)
+                } else {
+                    Ok(())
+                }
+            }
+            Protocol::TcpNamed { hostname } if hostname.contains('.') => {
+                self.check_domain(hostname)
+            }
+            Protocol::TcpNamed { hostname } => self.check_subdomain(hostname),
+            Protocol::Tcp { port } if *port != 0 && !self.is_port_allowed(*port) => {
+                Err(self.port_error(*port))
+            }
+            Protocol::Tcp { .. } => Ok(()),
+            Protocol::Tls { sni_patterns, .. } | Protocol::TlsTerminated { sni_patterns, .. } => {
+                sni_patterns
+                    .iter()
+                    .try_for_each(|sni| self.check_domain(sni))
+            }
+        }
+    }
+
+    /// Reason for rejecting a TCP port outside the allowed ranges
+    pub fn port_error(&self, port: u16) -> String {
+        let ranges: Vec<String> = self
+            .allowed_ports
+            .iter()
+            .flatten()
+            .map(|r| r.to_string())
+            .collect();
+        format!(
+            "Token does not allow TCP port {} (allowed: {})",
+            port,
+            ranges.join(", ")
+        )
+    }
+
+    fn check_subdomain(&self, subdomain: &str) -> Result<(), String> {
+        match &self.allowed_subdomains {
+            Some(patterns) if !patterns.iter().any(|p| matches_pattern(p, subdomain)) => {
+                Err(format!(
+                    "Token does not allow subdomain '{}' (allowed: {})",
+                    subdomain,
+                    patterns.join(", ")
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_domain(&self, domain: &str) -> Result<(), String> {
+        match &self.allowed_domains {
+            Some(patterns) if !patterns.iter().any(|p| matches_pattern(p, domain)) => Err(format!(
+                "Token does not allow domain '{}' (allowed: {})",
+                domain,
+                patterns.join(", ")
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Authentication errors
@@ -139,6 +319,58 @@ pub trait AuthValidator: Send + Sync {
     async fn validate(&self, token: &str) -> Result<AuthResult, AuthError>;
 }
 
+/// Tries several validators in order, accepting the first one that accepts the token
+///
+/// A validator that returns [`AuthError::InvalidToken`] doesn't recognise the token,
+/// so the next one is tried. Any other error (expired, revoked, unauthorized) is final.
+///
+/// ```ignore
+/// let validator = AuthValidatorChain::new()
+///     .with(Arc::new(JwtValidator::new(secret)))
+///     .with(Arc::new(ApiKeyValidator::new(keys)));
+/// ```
+#[derive(Clone, Default)]
+pub struct AuthValidatorChain {
+    validators: Vec<Arc<dyn AuthValidator>>,
+}
+
+impl AuthValidatorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a validator to the chain
+    pub fn with(mut self, validator: Arc<dyn AuthValidator>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+}
+
+#[async_trait]
+impl AuthValidator for AuthValidatorChain {
+    async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
+        let mut last_error = AuthError::InvalidToken("No validator accepted the token".to_string());
+        for validator in &self.validators {
+            match validator.validate(token).await {
+                Ok(result) => return Ok(result),
+                Err(AuthError::InvalidToken(reason)) => {
+                    last_error = AuthError::InvalidToken(reason);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,6 +393,99 @@ mod tests {
         assert_eq!(result.get_metadata("plan"), Some(&"pro".to_string()));
     }
 
+    struct StaticValidator {
+        token: &'static str,
+        result: Result<&'static str, fn() -> AuthError>,
+    }
+
+    #[async_trait]
+    impl AuthValidator for StaticValidator {
+        async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
+            if token != self.token {
+                return Err(AuthError::InvalidToken("unknown token".to_string()));
+            }
+            match self.result {
+                Ok(id) => Ok(AuthResult::new(id.to_string())),
+                Err(error) => Err(error()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validator_chain() {
+        let chain = AuthValidatorChain::new()
+            .with(Arc::new(StaticValidator {
+                token: "key-a",
+                result: Ok("first"),
+            }))
+            .with(Arc::new(StaticValidator {
+                token: "key-b",
+                result: Ok("second"),
+            }))
+            .with(Arc::new(StaticValidator {
+                token: "revoked",
+                result: Err(|| AuthError::Unauthorized("revoked".to_string())),
+            }))
+            .with(Arc::new(StaticValidator {
+                token: "revoked",
+                result: Ok("never"),
+            }));
+
+        assert_eq!(chain.validate("key-a").await.unwrap().localup_id, "first");
+        assert_eq!(chain.validate("key-b").await.unwrap().localup_id, "second");
+        // A definitive rejection stops the chain
+        assert!(matches!(
+            chain.validate("revoked").await,
+            Err(AuthError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            chain.validate("unknown").await,
+            Err(AuthError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            AuthValidatorChain::new().validate("key-a").await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_endpoint_scopes() {
+        let result = AuthResult::new("localup-123".to_string())
+            .with_protocols(vec!["http".to_string(), "tcp".to_string()])
+            .with_allowed_subdomains(vec!["myapp-*".to_string()])
+            .with_allowed_ports(vec![PortRange {
+                start: 10000,
+                end: 10100,
+            }]);
+
+        let http = |subdomain: &str| Protocol::Http {
+            subdomain: Some(subdomain.to_string()),
+            custom_domain: None,
+        };
+        assert!(result.validate_endpoint(&http("myapp-dev")).is_ok());
+        assert!(result.validate_endpoint(&http("other")).is_err());
+        assert!(result
+            .validate_endpoint(&Protocol::Tcp { port: 10050 })
+            .is_ok());
+        assert!(result
+            .validate_endpoint(&Protocol::Tcp { port: 22 })
+            .is_err());
+        assert!(result
+            .validate_endpoint(&Protocol::Https {
+                subdomain: Some("myapp-dev".to_string()),
+                custom_domain: None,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_identity() {
+        let result = AuthResult::new("localup-123".to_string());
+        assert_eq!(result.identity(), "localup-123");
+        let result = result.with_user_id("user-456".to_string());
+        assert_eq!(result.identity(), "user-456");
+    }
+
     #[test]
     fn test_empty_allowed_means_all_allowed() {
         let result = AuthResult::new("localup-123".to_string());
@@ -204,6 +204,8 @@ mod tests {
                 preferred_transport: None,
                 http_auth: HttpAuthConfig::None,
                 ip_allowlist: Vec::new(),
+                tcp_limits: Default::default(),
+                relay_directory: None,
             },
         }
     }
@@ -1,7 +1,7 @@
 //! Tunnel CLI - Command-line interface for creating tunnels
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::net::TcpListener;
@@ -10,8 +10,8 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use localup_cli::{config, daemon, localup_store, service};
 use localup_client::{
-    ExitNodeConfig, MetricsServer, ProtocolConfig, ReverseTunnelClient, ReverseTunnelConfig,
-    TunnelClient, TunnelConfig,
+    ExitNodeConfig, MetricsServer, ProtocolConfig, RelayDirectoryConfig, ReverseTunnelClient,
+    ReverseTunnelConfig, TunnelClient, TunnelConfig,
 };
 use localup_proto::HttpAuthConfig;
 
@@ -43,12 +43,14 @@ struct Cli {
     token: Option<String>,
 
     /// Subdomain for HTTP/HTTPS tunnels (standalone mode only)
+    /// For TCP: reach the tunnel by this name through the relay's CONNECT/SOCKS5 proxy
     #[arg(short, long)]
     subdomain: Option<String>,
 
     /// Custom domain for HTTP/HTTPS/TLS tunnels (standalone mode only)
     /// For HTTP/HTTPS: Requires DNS pointing to relay and valid TLS certificate.
     /// For TLS: No validation - relay routes based on SNI match (you manage certificates).
+    /// For TCP: hostname for the relay's CONNECT/SOCKS5 proxy instead of a public port.
     /// Can be specified multiple times for TLS tunnels.
     /// Supports wildcard domains (e.g., *.mycompany.com) for multi-subdomain tunnels.
     /// Example: --custom-domain api.mycompany.com
@@ -65,6 +67,29 @@ struct Cli {
     #[arg(long)]
     transport: Option<String>,
 
+    /// Region to connect to when no --relay is given (standalone mode only)
+    /// Repeat to register the tunnel on a relay in each region at once, under the
+    /// same subdomain, for DNS or anycast failover.
+    /// Example: --region eu-west --region us-east
+    #[arg(long = "region", value_name = "REGION", conflicts_with = "relay")]
+    regions: Vec<String>,
+
+    /// Relay directory URL or file used when no --relay is given (standalone mode only)
+    /// Cached in ~/.localup/relay-directory; the built-in list is used if neither is available.
+    /// Example: --relay-directory https://orchestrator.example.com/relays.jws
+    #[arg(long, env = "LOCALUP_RELAY_DIRECTORY", value_name = "URL_OR_PATH")]
+    relay_directory: Option<String>,
+
+    /// JWKS file the relay directory must be signed with (standalone mode only)
+    /// Download it from the orchestrator's /.well-known/jwks.json
+    #[arg(
+        long,
+        env = "LOCALUP_RELAY_DIRECTORY_JWKS",
+        value_name = "PATH",
+        requires = "relay_directory"
+    )]
+    relay_directory_jwks: Option<String>,
+
     /// Remote port for TCP/TLS tunnels (standalone mode only)
     #[arg(long)]
     remote_port: Option<u16>,
@@ -76,6 +101,21 @@ struct Cli {
     #[arg(long)]
     http_port: Option<u16>,
 
+    /// Terminate TLS at the relay and forward plaintext to --port (TLS tunnels, standalone mode only)
+    /// The relay serves its own certificate for the SNI hostname (custom domain or ACME)
+    #[arg(long)]
+    terminate_tls: bool,
+
+    /// Re-encrypt terminated TLS traffic to the local service (requires --terminate-tls)
+    #[arg(long, requires = "terminate_tls")]
+    reencrypt: bool,
+
+    /// Only take TLS connections offering this ALPN protocol (repeatable, TLS tunnels only)
+    /// Lets another tunnel serve the same hostname for other protocols
+    /// Example: --alpn mqtt
+    #[arg(long = "alpn")]
+    alpn: Vec<String>,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, default_value = "info")]
     log_level: String,
@@ -90,6 +130,7 @@ struct Cli {
 
     /// HTTP Basic Authentication credentials in "user:password" format (standalone mode only)
     /// Can be specified multiple times for multiple users.
+    /// The password may be a hash from `localup config hash-password`.
     /// Example: --basic-auth "admin:secret" --basic-auth "user:pass"
     #[arg(long = "basic-auth", value_name = "USER:PASS")]
     basic_auth: Vec<String>,
@@ -100,11 +141,234 @@ struct Cli {
     #[arg(long = "auth-token", value_name = "TOKEN")]
     auth_tokens: Vec<String>,
 
+    /// Show browsers a login page instead of the Basic auth prompt (standalone mode only)
+    /// Browsers log in with the --basic-auth credentials and get a session
+    /// cookie; API clients can still send Basic or --auth-token credentials.
+    #[arg(long, requires = "basic_auth")]
+    login_form: bool,
+
     /// Allowed IP addresses or CIDR ranges for the tunnel (standalone mode only)
     /// Can be specified multiple times. If not specified, all IPs are allowed.
     /// Examples: --allow-ip "192.168.1.0/24" --allow-ip "10.0.0.1"
     #[arg(long = "allow-ip", value_name = "IP_OR_CIDR")]
     allow_ips: Vec<String>,
+
+    #[command(flatten)]
+    tcp_limits: TcpLimitArgs,
+}
+
+/// Connection limits the relay enforces on TCP tunnels
+#[derive(Args, Debug, Clone, Default)]
+struct TcpLimitArgs {
+    /// Maximum concurrent connections to a TCP tunnel
+    #[arg(long, value_name = "N")]
+    max_connections: Option<u32>,
+
+    /// Maximum concurrent connections to a TCP tunnel from one IP address
+    #[arg(long, value_name = "N")]
+    max_connections_per_ip: Option<u32>,
+
+    /// Close TCP connections idle for this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    idle_timeout: Option<u64>,
+
+    /// Close TCP connections open for longer than this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    max_lifetime: Option<u64>,
+}
+
+impl TcpLimitArgs {
+    fn limits(&self) -> localup_proto::TcpLimits {
+        localup_proto::TcpLimits {
+            max_connections: self.max_connections,
+            max_connections_per_ip: self.max_connections_per_ip,
+            idle_timeout_secs: self.idle_timeout,
+            max_lifetime_secs: self.max_lifetime,
+        }
+    }
+}
+
+/// What tunnels opened with a generated token may do
+#[derive(Args, Debug, Clone, Default)]
+struct TokenScopeArgs {
+    /// Allowed tunnel protocols (repeatable: http, https, tcp, tls); all if not specified
+    #[arg(long = "allowed-protocol", value_name = "PROTOCOL", value_parser = ["http", "https", "tcp", "tls"])]
+    allowed_protocols: Vec<String>,
+
+    /// Allowed subdomain patterns (repeatable, `*` wildcard, e.g. "myapp-*")
+    #[arg(long = "allowed-subdomain", value_name = "PATTERN")]
+    allowed_subdomains: Vec<String>,
+
+    /// Allowed custom domain / SNI patterns (repeatable, e.g. "*.example.com")
+    #[arg(long = "allowed-domain", value_name = "PATTERN")]
+    allowed_domains: Vec<String>,
+
+    /// Allowed public TCP ports (repeatable, e.g. 15000-15100 or 5432)
+    #[arg(long = "allowed-ports", value_name = "RANGE")]
+    allowed_ports: Vec<localup_auth::PortRange>,
+
+    /// Maximum number of tunnels connected with the token at once
+    #[arg(long, value_name = "N")]
+    max_tunnels: Option<u32>,
+}
+
+impl TokenScopeArgs {
+    fn apply(self, mut claims: localup_auth::JwtClaims) -> localup_auth::JwtClaims {
+        if !self.allowed_protocols.is_empty() {
+            claims = claims.with_protocols(self.allowed_protocols);
+        }
+        if !self.allowed_subdomains.is_empty() {
+            claims = claims.with_allowed_subdomains(self.allowed_subdomains);
+        }
+        if !self.allowed_domains.is_empty() {
+            claims = claims.with_allowed_domains(self.allowed_domains);
+        }
+        if !self.allowed_ports.is_empty() {
+            claims = claims.with_allowed_ports(self.allowed_ports);
+        }
+        if let Some(max) = self.max_tunnels {
+            claims = claims.with_max_tunnels(max);
+        }
+        claims
+    }
+}
+
+/// Additional keys for verifying and signing JWTs on the relay, so secrets
+/// and keys can be rotated without invalidating issued tokens
+#[derive(Args, Debug, Clone, Default)]
+struct JwtKeyArgs {
+    /// Previous JWT secret whose tokens are still accepted (repeatable)
+    #[arg(long = "jwt-previous-secret", value_name = "SECRET")]
+    jwt_previous_secrets: Vec<String>,
+
+    /// Private key (PEM: Ed25519, P-256, P-384 or RSA) that signs tokens issued by the API
+    /// instead of --jwt-secret; its public key is published at /.well-known/jwks.json
+    #[arg(long, value_name = "PATH")]
+    jwt_signing_key: Option<std::path::PathBuf>,
+
+    /// Previous signing key whose tokens are still accepted and published (repeatable)
+    #[arg(long = "jwt-previous-signing-key", value_name = "PATH")]
+    jwt_previous_signing_keys: Vec<std::path::PathBuf>,
+
+    /// JWKS file with public keys of other token issuers, re-read when it changes
+    #[arg(long, value_name = "PATH")]
+    jwt_jwks_file: Option<std::path::PathBuf>,
+}
+
+impl JwtKeyArgs {
+    /// How often the JWKS file is checked for changes
+    const JWKS_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+    fn is_configured(&self) -> bool {
+        !self.jwt_previous_secrets.is_empty()
+            || self.jwt_signing_key.is_some()
+            || !self.jwt_previous_signing_keys.is_empty()
+            || self.jwt_jwks_file.is_some()
+    }
+
+    /// Signer for tokens the relay issues, and a validator accepting `secret`
+    /// plus every configured key
+    ///
+    /// Must be called from within the runtime when a JWKS file is configured.
+    fn load(
+        &self,
+        secret: &str,
+    ) -> Result<(
+        std::sync::Arc<localup_auth::JwtSigner>,
+        std::sync::Arc<localup_auth::JwtValidator>,
+    )> {
+        use localup_auth::{JwksFile, JwtSigner, JwtValidator, VerificationKey};
+        use std::sync::Arc;
+
+        let read_signer = |path: &std::path::Path| -> Result<JwtSigner> {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read JWT signing key {}", path.display()))?;
+            JwtSigner::from_pem(&pem)
+                .with_context(|| format!("Invalid JWT signing key {}", path.display()))
+        };
+
+        let mut validator = JwtValidator::new(secret.as_bytes());
+        for previous in &self.jwt_previous_secrets {
+            validator = validator.with_key(VerificationKey::hmac(None, previous.as_bytes()));
+        }
+        for path in &self.jwt_previous_signing_keys {
+            validator = validator.with_key(read_signer(path)?.verification_key());
+        }
+
+        let signer = match &self.jwt_signing_key {
+            Some(path) => {
+                let signer = read_signer(path)?;
+                info!(
+                    "✅ Signing tokens with {:?} key {}",
+                    signer.algorithm(),
+                    signer.kid().unwrap_or_default()
+                );
+                validator = validator.with_key(signer.verification_key());
+                signer
+            }
+            None => JwtSigner::hmac(secret.as_bytes()),
+        };
+
+        if let Some(path) = &self.jwt_jwks_file {
+            let file = Arc::new(
+                JwksFile::load(path)
+                    .with_context(|| format!("Failed to load JWKS file {}", path.display()))?,
+            );
+            info!(
+                "✅ Loaded {} JWT verification keys from {}",
+                file.keys().len(),
+                path.display()
+            );
+            file.spawn_reload_task(Self::JWKS_RELOAD_INTERVAL);
+            validator = validator.with_jwks_file(file);
+        }
+
+        Ok((Arc::new(signer), Arc::new(validator)))
+    }
+}
+
+/// Cluster mode: relays sharing a database serve each other's tunnels
+#[derive(Args, Debug, Clone, Default)]
+struct ClusterArgs {
+    /// Join a relay cluster under this unique node ID (requires a shared --database-url)
+    #[arg(long, env = "LOCALUP_CLUSTER_NODE_ID", requires = "cluster_secret")]
+    cluster_node_id: Option<String>,
+
+    /// Bind address of the inter-node QUIC link
+    #[arg(long, default_value = "0.0.0.0:4450")]
+    cluster_bind_addr: String,
+
+    /// Address other nodes dial for the inter-node link (defaults to the bind address)
+    #[arg(long)]
+    cluster_advertise_addr: Option<String>,
+
+    /// Secret shared by all nodes of the cluster, authenticating inter-node links
+    #[arg(long, env = "LOCALUP_CLUSTER_SECRET")]
+    cluster_secret: Option<String>,
+}
+
+impl ClusterArgs {
+    fn config(&self) -> Result<Option<localup_control::ClusterConfig>> {
+        let Some(ref node_id) = self.cluster_node_id else {
+            return Ok(None);
+        };
+        let secret = self
+            .cluster_secret
+            .clone()
+            .context("--cluster-secret is required in cluster mode")?;
+        let bind_addr = self
+            .cluster_bind_addr
+            .parse()
+            .with_context(|| format!("Invalid --cluster-bind-addr {}", self.cluster_bind_addr))?;
+        let mut config = localup_control::ClusterConfig::new(node_id, bind_addr, secret);
+        if let Some(ref addr) = self.cluster_advertise_addr {
+            config = config.with_advertise_addr(
+                addr.parse()
+                    .with_context(|| format!("Invalid --cluster-advertise-addr {}", addr))?,
+            );
+        }
+        Ok(Some(config))
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -152,6 +416,15 @@ enum Commands {
         /// Example: --port 9443 --http-port 9080 (HTTPS to 9443, HTTP to 9080)
         #[arg(long)]
         http_port: Option<u16>,
+        /// Terminate TLS at the relay and forward plaintext to --port (TLS tunnels only)
+        #[arg(long)]
+        terminate_tls: bool,
+        /// Re-encrypt terminated TLS traffic to the local service (requires --terminate-tls)
+        #[arg(long, requires = "terminate_tls")]
+        reencrypt: bool,
+        /// Only take TLS connections offering this ALPN protocol (repeatable, TLS tunnels only)
+        #[arg(long = "alpn")]
+        alpn: Vec<String>,
         /// Auto-enable (start with daemon)
         #[arg(long)]
         enabled: bool,
@@ -160,6 +433,8 @@ enum Commands {
         /// Examples: --allow-ip "192.168.1.0/24" --allow-ip "10.0.0.1"
         #[arg(long = "allow-ip", value_name = "IP_OR_CIDR")]
         allow_ips: Vec<String>,
+        #[command(flatten)]
+        tcp_limits: TcpLimitArgs,
     },
     /// List all tunnel configurations
     List,
@@ -335,6 +610,9 @@ enum Commands {
         #[arg(long = "allowed-address")]
         allowed_addresses: Vec<String>,
 
+        #[command(flatten)]
+        scopes: TokenScopeArgs,
+
         /// Output only the JWT token (useful for scripts)
         #[arg(long)]
         token_only: bool,
@@ -356,6 +634,49 @@ enum Commands {
     },
     /// Stop tunnels from .localup.yml config file
     Down,
+    /// Manage share links for tunnels on a relay
+    Share {
+        /// Relay API URL
+        #[arg(long, env = "LOCALUP_API_URL", default_value = "http://localhost:3080")]
+        api_url: String,
+        /// Session token for the relay API (from the web portal login)
+        #[arg(long, env = "LOCALUP_SESSION_TOKEN")]
+        session_token: String,
+        #[command(subcommand)]
+        command: ShareCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ShareCommands {
+    /// Create a share link for a connected HTTP/HTTPS tunnel
+    Create {
+        /// Tunnel ID to share
+        tunnel_id: String,
+        /// Only share this path prefix of the tunnel
+        #[arg(long, default_value = "/")]
+        path: String,
+        /// Hours until the link expires
+        #[arg(long, default_value = "24")]
+        expires_in_hours: i64,
+        /// Maximum number of times the link can be opened
+        #[arg(long)]
+        max_uses: Option<i32>,
+        /// Name to remember the link by
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// List your share links
+    List {
+        /// Only list links for this tunnel
+        #[arg(long)]
+        tunnel: Option<String>,
+    },
+    /// Revoke a share link
+    Revoke {
+        /// Share link ID
+        id: String,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -370,6 +691,19 @@ enum ConfigCommands {
     GetToken,
     /// Clear the default authentication token
     ClearToken,
+    /// Print the JSON Schema for .localup.yml
+    Schema,
+    /// Hash a password for HTTP Basic auth credentials
+    ///
+    /// Prints "USER:HASH", usable with --basic-auth and in .localup.yml.
+    /// The password is read from stdin when not given.
+    HashPassword {
+        /// Username to prefix the hash with
+        username: String,
+        /// Password to hash (visible in shell history, prefer stdin)
+        #[arg(long)]
+        password: Option<String>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -384,6 +718,17 @@ enum RelayCommands {
         #[arg(long, default_value = "10000-20000")]
         tcp_port_range: String,
 
+        /// Default number of TCP ports each team (or user) may reserve through the API
+        /// (requires --database-url; teams can override it with their own quota)
+        #[arg(long)]
+        tcp_port_quota: Option<u32>,
+
+        /// HTTP CONNECT / SOCKS5 proxy bind address for hostname-routed TCP tunnels
+        /// (tunnels started with --subdomain/--custom-domain use no public port)
+        /// Example: --tcp-connect-addr 0.0.0.0:8080
+        #[arg(long)]
+        tcp_connect_addr: Option<String>,
+
         /// Public domain name for this relay
         #[arg(long, default_value = "localhost")]
         domain: String,
@@ -392,6 +737,12 @@ enum RelayCommands {
         #[arg(long, env = "JWT_SECRET")]
         jwt_secret: Option<String>,
 
+        #[command(flatten)]
+        jwt_keys: JwtKeyArgs,
+
+        #[command(flatten)]
+        cluster: ClusterArgs,
+
         /// Log level (trace, debug, info, warn, error)
         #[arg(long, default_value = "info")]
         log_level: String,
@@ -475,6 +826,17 @@ enum RelayCommands {
         #[arg(long)]
         http_passthrough_addr: Option<String>,
 
+        /// Tunnel that receives TLS connections whose SNI matches no route
+        /// (or that send no SNI at all)
+        #[arg(long)]
+        tls_default_tunnel: Option<String>,
+
+        /// Optional PostgreSQL listener shared by TLS tunnels (e.g. 0.0.0.0:5432)
+        /// Answers the client's SSLRequest and routes on the SNI of the ClientHello
+        /// that follows, so clients must connect with sslmode=require or stricter
+        #[arg(long)]
+        postgres_addr: Option<String>,
+
         /// Public domain name for this relay
         #[arg(long, default_value = "localhost")]
         domain: String,
@@ -483,6 +845,12 @@ enum RelayCommands {
         #[arg(long, env = "JWT_SECRET")]
         jwt_secret: Option<String>,
 
+        #[command(flatten)]
+        jwt_keys: JwtKeyArgs,
+
+        #[command(flatten)]
+        cluster: ClusterArgs,
+
         /// Log level (trace, debug, info, warn, error)
         #[arg(long, default_value = "info")]
         log_level: String,
@@ -568,6 +936,12 @@ enum RelayCommands {
         #[arg(long, env = "JWT_SECRET")]
         jwt_secret: Option<String>,
 
+        #[command(flatten)]
+        jwt_keys: JwtKeyArgs,
+
+        #[command(flatten)]
+        cluster: ClusterArgs,
+
         /// Log level (trace, debug, info, warn, error)
         #[arg(long, default_value = "info")]
         log_level: String,
@@ -620,6 +994,11 @@ enum RelayCommands {
         #[arg(long, default_value = "/localup")]
         websocket_path: String,
 
+        /// Serve the tunnel control plane on the HTTPS port instead of --localup-addr:
+        /// QUIC on its UDP side, WebSocket and HTTP/2 split from HTTPS by TLS ALPN
+        #[arg(long, requires = "https_addr")]
+        single_port: bool,
+
         /// ACME email address for Let's Encrypt (enables automatic SSL certificates)
         #[arg(long, env = "ACME_EMAIL")]
         acme_email: Option<String>,
@@ -758,8 +1137,12 @@ async fn main() -> Result<()> {
             transport,
             remote_port,
             http_port,
+            terminate_tls,
+            reencrypt,
+            alpn,
             enabled,
             allow_ips,
+            tcp_limits,
         }) => handle_add_tunnel(
             name,
             port,
@@ -772,8 +1155,12 @@ async fn main() -> Result<()> {
             transport,
             remote_port,
             http_port,
+            terminate_tls,
+            reencrypt,
+            alpn,
             enabled,
             allow_ips,
+            tcp_limits.limits(),
         ),
         Some(Commands::List) => handle_list_tunnels(),
         Some(Commands::Show { name }) => handle_show_tunnel(name),
@@ -855,6 +1242,7 @@ async fn main() -> Result<()> {
             reverse_tunnel,
             allowed_agents,
             allowed_addresses,
+            scopes,
             token_only,
         }) => {
             handle_generate_token_command(
@@ -865,6 +1253,7 @@ async fn main() -> Result<()> {
                 reverse_tunnel,
                 allowed_agents,
                 allowed_addresses,
+                scopes,
                 token_only,
             )
             .await
@@ -874,6 +1263,11 @@ async fn main() -> Result<()> {
         Some(Commands::Init) => handle_init_command().await,
         Some(Commands::Up { tunnels }) => handle_up_command(tunnels).await,
         Some(Commands::Down) => handle_down_command().await,
+        Some(Commands::Share {
+            ref api_url,
+            ref session_token,
+            ref command,
+        }) => handle_share_command(api_url, session_token, command).await,
         None => {
             // Standalone mode - run a single tunnel
             run_standalone(cli).await
@@ -1282,8 +1676,12 @@ fn handle_add_tunnel(
     transport: Option<String>,
     remote_port: Option<u16>,
     http_port: Option<u16>,
+    terminate_tls: bool,
+    reencrypt: bool,
+    alpn: Vec<String>,
     enabled: bool,
     allow_ips: Vec<String>,
+    tcp_limits: localup_proto::TcpLimits,
 ) -> Result<()> {
     let store = localup_store::TunnelStore::new()?;
 
@@ -1309,6 +1707,9 @@ fn handle_add_tunnel(
         custom_domains,
         remote_port,
         http_port,
+        terminate_tls,
+        reencrypt,
+        alpn,
     )?;
 
     // Parse exit node
@@ -1340,6 +1741,8 @@ fn handle_add_tunnel(
         preferred_transport,
         http_auth: localup_proto::HttpAuthConfig::None,
         ip_allowlist: allow_ips,
+        tcp_limits,
+        relay_directory: None,
     };
 
     let stored_tunnel = localup_store::StoredTunnel {
@@ -1417,9 +1820,12 @@ fn handle_list_tunnels() -> Result<()> {
                 ProtocolConfig::Tcp {
                     local_port,
                     remote_port,
+                    hostname,
                 } => {
                     print!("    Protocol: TCP, Port: {}", local_port);
-                    if let Some(remote) = remote_port {
+                    if let Some(hostname) = hostname {
+                        print!(" → Hostname: {} (via relay proxy)", hostname);
+                    } else if let Some(remote) = remote_port {
                         print!(" → Remote: {}", remote);
                     }
                     println!();
@@ -1428,14 +1834,26 @@ fn handle_list_tunnels() -> Result<()> {
                     local_port,
                     sni_hostnames,
                     http_port,
+                    terminate,
+                    reencrypt,
+                    alpn,
                 } => {
                     print!("    Protocol: TLS, Port: {}", local_port);
                     if let Some(hp) = http_port {
                         print!(", HTTP Port: {}", hp);
                     }
+                    if *terminate {
+                        print!(
+                            ", Terminated at relay{}",
+                            if *reencrypt { " (re-encrypted)" } else { "" }
+                        );
+                    }
                     if !sni_hostnames.is_empty() {
                         print!(", SNI: {}", sni_hostnames.join(", "));
                     }
+                    if !alpn.is_empty() {
+                        print!(", ALPN: {}", alpn.join(", "));
+                    }
                     println!();
                 }
             }
@@ -1568,6 +1986,9 @@ async fn run_standalone(cli: Cli) -> Result<()> {
         cli.custom_domain.clone(),
         cli.remote_port,
         cli.http_port,
+        cli.terminate_tls,
+        cli.reencrypt,
+        cli.alpn.clone(),
     )?;
 
     // Parse exit node configuration
@@ -1576,9 +1997,38 @@ async fn run_standalone(cli: Cli) -> Result<()> {
         validate_relay_addr(&relay_addr)?;
         ExitNodeConfig::Custom(relay_addr)
     } else {
-        info!("Using automatic relay selection");
-        ExitNodeConfig::Auto
+        let regions = cli
+            .regions
+            .iter()
+            .map(|region| region.parse::<localup_client::Region>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!(e))?;
+        match regions.as_slice() {
+            [] => {
+                info!("Using automatic relay selection");
+                ExitNodeConfig::Auto
+            }
+            [region] => {
+                info!("Using a relay in region {}", region.as_str());
+                ExitNodeConfig::Specific(*region)
+            }
+            _ => {
+                info!("Connecting to a relay in each of {} regions", regions.len());
+                ExitNodeConfig::MultiRegion(regions)
+            }
+        }
     };
+    let relay_directory = cli.relay_directory.clone().map(|source| {
+        info!("Using relay directory: {}", source);
+        let mut directory = RelayDirectoryConfig::new(source);
+        if let Some(jwks) = &cli.relay_directory_jwks {
+            directory = directory.with_jwks_file(jwks);
+        }
+        if let Some(home) = dirs::home_dir() {
+            directory = directory.with_cache_file(home.join(".localup").join("relay-directory"));
+        }
+        directory
+    });
 
     // Parse preferred transport
     let preferred_transport =
@@ -1591,7 +2041,16 @@ async fn run_standalone(cli: Cli) -> Result<()> {
         };
 
     // Build HTTP authentication configuration from CLI arguments
-    let http_auth = if !cli.basic_auth.is_empty() {
+    let http_auth = if cli.login_form {
+        info!(
+            "🔐 Login form enabled ({} credential(s), {} token(s))",
+            cli.basic_auth.len(),
+            cli.auth_tokens.len()
+        );
+        let mut login = localup_proto::LoginFormConfig::new(cli.basic_auth.clone());
+        login.bearer_tokens = cli.auth_tokens.clone();
+        HttpAuthConfig::LoginForm(Box::new(login))
+    } else if !cli.basic_auth.is_empty() {
         info!(
             "🔐 HTTP Basic Authentication enabled ({} credential(s))",
             cli.basic_auth.len()
@@ -1625,6 +2084,8 @@ async fn run_standalone(cli: Cli) -> Result<()> {
         preferred_transport,
         http_auth,
         ip_allowlist: cli.allow_ips.clone(),
+        tcp_limits: cli.tcp_limits.limits(),
+        relay_directory,
     };
 
     // Create cancellation token for Ctrl+C
@@ -1701,13 +2162,28 @@ async fn run_standalone(cli: Cli) -> Result<()> {
                         local_scheme, local_host_display, local_port
                     );
                     println!("🌐 Public: {}", url);
+                    let relays = client.endpoints();
+                    if relays.len() > 1 {
+                        for relay in relays {
+                            let region = relay.region.map(|r| r.as_str()).unwrap_or("-");
+                            match relay.endpoints.first() {
+                                Some(endpoint) => println!(
+                                    "   {} ({}): {}",
+                                    relay.relay, region, endpoint.public_url
+                                ),
+                                None => {
+                                    println!("   {} ({}): {:?}", relay.relay, region, relay.status)
+                                }
+                            }
+                        }
+                    }
                     println!();
                 }
 
                 // Start metrics server if enabled (only once)
                 if !cli.no_metrics && !metrics_server_started {
                     let metrics = client.metrics().clone();
-                    let endpoints = client.endpoints().to_vec();
+                    let endpoints = client.public_endpoints();
 
                     // Try to bind to requested port, fallback to any available port
                     let requested_addr = format!("127.0.0.1:{}", cli.metrics_port);
@@ -1841,6 +2317,7 @@ async fn run_standalone(cli: Cli) -> Result<()> {
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn parse_protocol(
     protocol: &str,
     port: u16,
@@ -1848,7 +2325,17 @@ fn parse_protocol(
     custom_domains: Vec<String>,
     remote_port: Option<u16>,
     http_port: Option<u16>,
+    terminate_tls: bool,
+    reencrypt: bool,
+    alpn: Vec<String>,
 ) -> Result<ProtocolConfig> {
+    if terminate_tls && protocol.to_lowercase() != "tls" {
+        anyhow::bail!("--terminate-tls is only supported for TLS tunnels");
+    }
+    if !alpn.is_empty() && protocol.to_lowercase() != "tls" {
+        anyhow::bail!("--alpn is only supported for TLS tunnels");
+    }
+
     match protocol.to_lowercase().as_str() {
         "http" => Ok(ProtocolConfig::Http {
             local_port: port,
@@ -1865,6 +2352,8 @@ fn parse_protocol(
         "tcp" => Ok(ProtocolConfig::Tcp {
             local_port: port,
             remote_port,
+            // A name routes through the relay's CONNECT/SOCKS5 proxy instead of a port
+            hostname: custom_domains.into_iter().next().or(subdomain),
         }),
         "tls" => {
             // For TLS, use all custom_domains as SNI patterns
@@ -1878,6 +2367,9 @@ fn parse_protocol(
                 local_port: port,
                 sni_hostnames,
                 http_port,
+                terminate: terminate_tls,
+                reencrypt,
+                alpn,
             })
         }
         _ => Err(anyhow::anyhow!(
@@ -2239,8 +2731,12 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
         RelayCommands::Tcp {
             localup_addr,
             tcp_port_range,
+            tcp_port_quota,
+            tcp_connect_addr,
             domain,
             jwt_secret,
+            jwt_keys,
+            cluster,
             log_level,
             api_http_addr,
             api_https_addr,
@@ -2284,6 +2780,13 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
                 None,                   // http_redirect_addr (not used for TCP)
                 443,                    // https_redirect_port (default)
                 None,                   // http_passthrough_addr (not used for TCP)
+                None,                   // tls_default_tunnel (not used for TCP)
+                tcp_connect_addr,       // CONNECT/SOCKS5 proxy for hostname TCP tunnels
+                None,                   // postgres_addr (not used for TCP)
+                tcp_port_quota,         // Default TCP port reservation quota
+                jwt_keys,
+                cluster,
+                false, // single_port (HTTP relay only)
             )
             .await
         }
@@ -2293,8 +2796,12 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
             http_redirect_addr,
             https_redirect_port,
             http_passthrough_addr,
+            tls_default_tunnel,
+            postgres_addr,
             domain,
             jwt_secret,
+            jwt_keys,
+            cluster,
             log_level,
             api_http_addr,
             api_https_addr,
@@ -2338,6 +2845,13 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
                 http_redirect_addr,     // HTTP redirect server
                 https_redirect_port,    // HTTPS port to redirect to
                 http_passthrough_addr,  // HTTP passthrough server (Host-based routing)
+                tls_default_tunnel,     // Catch-all tunnel for unmatched SNI
+                None,                   // tcp_connect_addr (not used for TLS)
+                postgres_addr,          // Postgres SSLRequest listener for TLS tunnels
+                None,                   // tcp_port_quota (not used for TLS)
+                jwt_keys,
+                cluster,
+                false, // single_port (HTTP relay only)
             )
             .await
         }
@@ -2349,6 +2863,8 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
             tls_key,
             domain,
             jwt_secret,
+            jwt_keys,
+            cluster,
             log_level,
             api_http_addr,
             api_https_addr,
@@ -2362,6 +2878,7 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
             allow_signup,
             transport,
             websocket_path,
+            single_port,
             acme_email,
             acme_staging,
             acme_cert_dir,
@@ -2395,6 +2912,13 @@ async fn handle_relay_subcommand(command: RelayCommands) -> Result<()> {
                 None, // http_redirect_addr (not used for HTTP relay)
                 443,  // https_redirect_port (default)
                 None, // http_passthrough_addr (not used for HTTP relay)
+                None, // tls_default_tunnel (not used for HTTP relay)
+                None, // tcp_connect_addr (not used for HTTP relay)
+                None, // postgres_addr (not used for HTTP relay)
+                None, // tcp_port_quota (not used for HTTP relay)
+                jwt_keys,
+                cluster,
+                single_port,
             )
             .await
         }
@@ -2431,18 +2955,26 @@ async fn handle_relay_command(
     http_redirect_addr: Option<String>,
     https_redirect_port: u16,
     http_passthrough_addr: Option<String>,
+    tls_default_tunnel: Option<String>,
+    tcp_connect_addr: Option<String>,
+    postgres_addr: Option<String>,
+    tcp_port_quota: Option<u32>,
+    jwt_keys: JwtKeyArgs,
+    cluster_args: ClusterArgs,
+    single_port: bool,
 ) -> Result<()> {
-    use localup_auth::JwtValidator;
     use localup_control::{
-        AgentRegistry, PortAllocator as PortAllocatorTrait, TunnelConnectionManager, TunnelHandler,
+        AgentRegistry, ClusterNode, ForwardKind, PortAllocator as PortAllocatorTrait,
+        TunnelConnectionManager, TunnelHandler,
     };
+    use localup_lib::{FrontListener, Lane};
     use localup_router::RouteRegistry;
-    use localup_server_https::{HttpsServer, HttpsServerConfig};
+    use localup_server_https::{CustomCertResolver, HttpsServer, HttpsServerConfig};
     use localup_server_tcp::{TcpServer, TcpServerConfig};
     use localup_server_tls::{
-        HttpPassthroughConfig, HttpPassthroughServer, TlsServer, TlsServerConfig,
+        HttpPassthroughConfig, HttpPassthroughServer, TlsPreamble, TlsServer, TlsServerConfig,
     };
-    use localup_transport::TransportListener;
+    use localup_transport::{TcpIncoming, TransportListener};
     use localup_transport_quic::QuicListener;
     use std::net::SocketAddr;
     use std::sync::Arc;
@@ -2451,6 +2983,19 @@ async fn handle_relay_command(
     let _ = tracing_subscriber::EnvFilter::try_from_default_env()
         .or_else(|_| tracing_subscriber::EnvFilter::try_new(&log_level));
 
+    // A single-port relay moves the control plane onto the HTTPS address; its TCP
+    // side is shared with the HTTPS server through a front listener
+    let localup_addr = match (single_port, &https_addr) {
+        (false, _) => localup_addr,
+        (true, Some(https_addr)) => https_addr.clone(),
+        (true, None) => anyhow::bail!("--single-port requires --https-addr"),
+    };
+    let mut front = if single_port {
+        Some(FrontListener::new(localup_addr.parse()?))
+    } else {
+        None
+    };
+
     info!("🚀 Starting tunnel exit node");
     if !http_addr.is_empty() {
         info!("HTTP endpoint: {}", http_addr);
@@ -2468,6 +3013,7 @@ async fn handle_relay_command(
     }
 
     // Initialize database connection
+    let persistent_db = database_url.is_some();
     let db_url = database_url.unwrap_or_else(|| "sqlite::memory:".to_string());
     info!("Connecting to database: {}", db_url);
     let db = localup_relay_db::connect(&db_url).await?;
@@ -2524,6 +3070,7 @@ async fn handle_relay_command(
                 name: Set(team_name.clone()),
                 slug: Set(full_name.to_lowercase().replace(' ', "-")),
                 owner_id: Set(user_id),
+                tcp_port_quota: Set(None),
                 created_at: Set(chrono::Utc::now()),
                 updated_at: Set(chrono::Utc::now()),
             };
@@ -2563,34 +3110,62 @@ async fn handle_relay_command(
             end,
             end - start + 1
         );
-        Some(Arc::new(PortAllocator::new(start, end)))
+        if persistent_db {
+            // Ports stay with their owners across restarts
+            info!("✅ TCP port assignments persisted in the database");
+            Some(Arc::new(localup_control::DbPortAllocator::new(
+                db.clone(),
+                start,
+                end,
+            )) as Arc<dyn PortAllocatorTrait>)
+        } else {
+            let allocator = Arc::new(PortAllocator::new(start, end));
+
+            // Start cleanup task for expired port reservations
+            let allocator_clone = allocator.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60)); // Check every minute
+                loop {
+                    interval.tick().await;
+                    allocator_clone.cleanup_expired();
+                }
+            });
+            info!("✅ Port reservation cleanup task started (checks every 60s)");
+            Some(allocator as Arc<dyn PortAllocatorTrait>)
+        }
     } else {
         None
     };
 
-    // Start cleanup task for expired port reservations
-    if let Some(ref allocator) = port_allocator {
-        let allocator_clone = allocator.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60)); // Check every minute
-            loop {
-                interval.tick().await;
-                allocator_clone.cleanup_expired();
-            }
-        });
-        info!("✅ Port reservation cleanup task started (checks every 60s)");
-    }
+    // Port reservations are only honoured by the database-backed allocator
+    let tcp_port_policy = match tcp_port_range {
+        Some(ref tcp_range) if persistent_db => {
+            let (range_start, range_end) = parse_port_range(tcp_range)?;
+            Some(localup_api::TcpPortPolicy {
+                range_start,
+                range_end,
+                default_quota: tcp_port_quota,
+            })
+        }
+        _ => None,
+    };
 
     // Create shared route registry
     let registry = Arc::new(RouteRegistry::new());
     info!("✅ Route registry initialized");
 
+    // Share links are signed by the API and checked by the HTTP relays,
+    // so both need the same secret
+    let api_secret = jwt_secret
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
     // Create JWT validator for tunnel authentication
     // Note: Only validates signature and expiration (no issuer/audience validation)
-    let jwt_validator = if let Some(ref jwt_secret) = jwt_secret {
-        let validator = Arc::new(JwtValidator::new(jwt_secret.as_bytes()));
+    let (jwt_signer, relay_jwt_validator) = jwt_keys.load(&api_secret)?;
+    let jwt_validator = if jwt_secret.is_some() || jwt_keys.is_configured() {
         info!("✅ JWT authentication enabled (signature only)");
-        Some(validator)
+        Some(relay_jwt_validator.clone())
     } else {
         info!("⚠️  Running without JWT authentication (not recommended for production)");
         None
@@ -2615,6 +3190,18 @@ async fn handle_relay_command(
     // Create pending requests tracker
     let pending_requests = Arc::new(localup_control::PendingRequests::new());
 
+    let share_links = Arc::new(localup_control::ShareLinkGate::new(&api_secret, db.clone()));
+
+    // Relays sharing the database claim routes cluster-wide and forward visitors
+    // of tunnels connected to other nodes
+    let cluster = match cluster_args.config()? {
+        Some(_) if !persistent_db => {
+            anyhow::bail!("Cluster mode requires a --database-url shared by all nodes")
+        }
+        Some(config) => Some(Arc::new(ClusterNode::new(config, db.clone()))),
+        None => None,
+    };
+
     // Start HTTP server (only if address is not empty)
     let mut http_port: Option<u16> = None;
     let http_handle = if !http_addr.is_empty() {
@@ -2623,10 +3210,15 @@ async fn handle_relay_command(
         let http_config = TcpServerConfig {
             bind_addr: http_addr_parsed,
         };
-        let http_server = TcpServer::new(http_config, registry.clone())
+        let mut http_server = TcpServer::new(http_config, registry.clone())
             .with_localup_manager(localup_manager.clone())
             .with_pending_requests(pending_requests.clone())
-            .with_database(db.clone());
+            .with_database(db.clone())
+            .with_share_links(share_links.clone());
+        if let Some(ref cluster) = cluster {
+            http_server = http_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Http, http_server.cluster_ingress());
+        }
 
         Some(tokio::spawn(async move {
             info!("Starting HTTP relay server");
@@ -2638,6 +3230,14 @@ async fn handle_relay_command(
         None
     };
 
+    // Certificates shared by the HTTPS server and TLS termination on the SNI server
+    let cert_resolver = match (&tls_cert, &tls_key) {
+        (Some(cert_path), Some(key_path)) if https_addr.is_some() || tls_addr.is_some() => Some(
+            Arc::new(CustomCertResolver::from_files(cert_path, key_path)?),
+        ),
+        _ => None,
+    };
+
     // Start HTTPS server if configured
     let mut https_port: Option<u16> = None;
     let https_handle = if let Some(ref https_addr) = https_addr {
@@ -2656,14 +3256,27 @@ async fn handle_relay_command(
             key_path: key_path.clone(),
         };
 
-        let https_server = HttpsServer::new(https_config, registry.clone())
+        let mut https_server = HttpsServer::new(https_config, registry.clone())
             .with_localup_manager(localup_manager.clone())
             .with_pending_requests(pending_requests.clone())
-            .with_database(db.clone());
+            .with_database(db.clone())
+            .with_share_links(share_links.clone());
+        if let Some(ref resolver) = cert_resolver {
+            https_server = https_server.with_cert_resolver(resolver.clone());
+        }
+        if let Some(ref cluster) = cluster {
+            https_server = https_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Https, https_server.cluster_ingress());
+        }
 
+        let incoming = front.as_mut().map(|f| f.lane(Lane::Https));
         Some(tokio::spawn(async move {
             info!("Starting HTTPS relay server");
-            if let Err(e) = https_server.start().await {
+            let result = match incoming {
+                Some(incoming) => https_server.serve(incoming, https_addr_parsed).await,
+                None => https_server.start().await,
+            };
+            if let Err(e) = result {
                 error!("HTTPS server error: {}", e);
             }
         }))
@@ -2681,8 +3294,21 @@ async fn handle_relay_command(
             bind_addr: tls_addr_parsed,
         };
 
-        let tls_server = TlsServer::new(tls_config, registry.clone())
+        let mut tls_server = TlsServer::new(tls_config, registry.clone())
             .with_localup_manager(localup_manager.clone());
+        if let Some(ref resolver) = cert_resolver {
+            tls_server = tls_server.with_cert_resolver(resolver.clone());
+        } else {
+            info!("TLS termination disabled for SNI tunnels (requires --tls-cert and --tls-key)");
+        }
+        if let Some(ref default_tunnel) = tls_default_tunnel {
+            info!("Unmatched SNI connections go to tunnel {}", default_tunnel);
+            tls_server = tls_server.with_default_tunnel(default_tunnel);
+        }
+        if let Some(ref cluster) = cluster {
+            tls_server = tls_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Tls, tls_server.cluster_ingress());
+        }
         info!("✅ TLS/SNI server configured (routes based on Server Name Indication)");
 
         let tls_addr_display = tls_addr_str.clone();
@@ -2696,6 +3322,35 @@ async fn handle_relay_command(
         None
     };
 
+    // Start PostgreSQL listener sharing the TLS tunnels' SNI routes
+    let _postgres_handle = if let Some(ref postgres_addr_str) = postgres_addr {
+        let bind_addr: SocketAddr = postgres_addr_str.parse()?;
+        let mut postgres_server = TlsServer::new(TlsServerConfig { bind_addr }, registry.clone())
+            .with_localup_manager(localup_manager.clone())
+            .with_preamble(TlsPreamble::Postgres);
+        if let Some(ref resolver) = cert_resolver {
+            postgres_server = postgres_server.with_cert_resolver(resolver.clone());
+        }
+        if let Some(ref cluster) = cluster {
+            postgres_server = postgres_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Postgres, postgres_server.cluster_ingress());
+        }
+
+        Some(tokio::spawn(async move {
+            info!("Starting PostgreSQL SNI relay server on {}", bind_addr);
+            if let Err(e) = postgres_server.start().await {
+                error!("PostgreSQL server error: {}", e);
+            }
+        }))
+    } else {
+        None
+    };
+
+    // The CONNECT/SOCKS5 proxy starts once the tunnel handler is configured
+    let tcp_connect_bind_addr: Option<SocketAddr> =
+        tcp_connect_addr.as_deref().map(str::parse).transpose()?;
+    let tcp_connect_port = tcp_connect_bind_addr.map(|addr| addr.port());
+
     // Start HTTP redirect server for TLS (redirects HTTP to HTTPS)
     let _http_redirect_handle = if let Some(ref http_redirect_addr_str) = http_redirect_addr {
         use axum::{
@@ -2812,11 +3467,14 @@ async fn handle_relay_command(
         info!("📡 Configuring TLS relay port: {}", port);
         localup_handler = localup_handler.with_tls_port(port);
     }
+    if let Some(port) = tcp_connect_port {
+        info!("📡 Configuring TCP CONNECT proxy port: {}", port);
+        localup_handler = localup_handler.with_tcp_connect_port(port);
+    }
 
     // Add port allocator if TCP range was provided
     if let Some(ref allocator) = port_allocator {
-        localup_handler =
-            localup_handler.with_port_allocator(allocator.clone() as Arc<dyn PortAllocatorTrait>);
+        localup_handler = localup_handler.with_port_allocator(allocator.clone());
         info!("✅ TCP port allocator configured");
 
         // Add TCP proxy spawner
@@ -2864,7 +3522,60 @@ async fn handle_relay_command(
         info!("✅ TCP proxy spawner configured");
     }
 
+    // Quotas are stored per user, team and plan in the relay database
+    if persistent_db {
+        let quotas = Arc::new(localup_control::QuotaEnforcer::new(db.clone()));
+        quotas.spawn_flush_task(std::time::Duration::from_secs(30));
+        localup_handler = localup_handler.with_quotas(quotas);
+        info!("✅ User and team quotas enforced");
+    }
+
+    // Traffic is metered into hourly rollups for billing, independent of request capture
+    let traffic_meter = if persistent_db {
+        let meter = Arc::new(localup_control::TrafficMeter::new(db.clone()));
+        meter.spawn_flush_task(std::time::Duration::from_secs(60));
+        localup_handler = localup_handler.with_traffic_meter(meter.clone());
+        info!("✅ Traffic metering enabled");
+        Some(meter)
+    } else {
+        None
+    };
+
+    // Tokens revoked through the API are refused, and tunnels connected with
+    // revoked, expired or deactivated tokens are disconnected
+    localup_handler = localup_handler
+        .with_revocations(Arc::new(localup_control::RevocationList::new(db.clone())));
+
+    if let Some(ref cluster) = cluster {
+        localup_handler = localup_handler.with_cluster(cluster.clone());
+    }
+
     let localup_handler = Arc::new(localup_handler);
+    localup_handler.spawn_session_revalidation_task(std::time::Duration::from_secs(30));
+
+    // Start CONNECT/SOCKS5 proxy for hostname-routed TCP tunnels
+    let _tcp_connect_handle = if let Some(bind_addr) = tcp_connect_bind_addr {
+        use localup_server_tcp_proxy::{TcpConnectServer, TcpConnectServerConfig};
+
+        let mut connect_server = TcpConnectServer::new(
+            TcpConnectServerConfig { bind_addr },
+            registry.clone(),
+            localup_manager.clone(),
+        )
+        .with_database(db.clone());
+        // Proxy clients are held to the same token checks as tunnels
+        if let Some(validator) = localup_handler.token_validator() {
+            connect_server = connect_server.with_auth_validator(validator);
+        }
+
+        Some(tokio::spawn(async move {
+            if let Err(e) = connect_server.start().await {
+                error!("TCP CONNECT proxy error: {}", e);
+            }
+        }))
+    } else {
+        None
+    };
 
     // Start tunnel listener (QUIC)
     info!("🔧 Attempting to bind tunnel control to {}", localup_addr);
@@ -2881,6 +3592,17 @@ async fn handle_relay_command(
         config
     };
 
+    // The inter-node link reuses the control plane's certificate; nodes
+    // authenticate each other with the cluster secret
+    if let Some(ref cluster) = cluster {
+        let cluster_addr = cluster.start(quic_config.clone()).await?;
+        info!(
+            "🔗 Relay node {} joined the cluster (inter-node link on {})",
+            cluster.node_id(),
+            cluster_addr
+        );
+    }
+
     let localup_addr_parsed: SocketAddr = localup_addr.parse()?;
 
     // Start the transport listener based on selected transport type
@@ -2935,7 +3657,13 @@ async fn handle_relay_command(
             ws_config.path = websocket_path.clone();
             let ws_config = Arc::new(ws_config);
 
-            let listener = WebSocketListener::new(localup_addr_parsed, ws_config)?;
+            let listener = match front.as_mut() {
+                Some(f) => WebSocketListener::from_incoming(
+                    TcpIncoming::channel(f.lane(Lane::WebSocket), localup_addr_parsed),
+                    ws_config,
+                )?,
+                None => WebSocketListener::new(localup_addr_parsed, ws_config)?,
+            };
             info!(
                 "🔌 Tunnel control listening on wss://{}{} (WebSocket/TCP)",
                 localup_addr, websocket_path
@@ -2978,7 +3706,13 @@ async fn handle_relay_command(
 
             let h2_config = Arc::new(h2_config);
 
-            let listener = H2Listener::new(localup_addr_parsed, h2_config)?;
+            let listener = match front.as_mut() {
+                Some(f) => H2Listener::from_incoming(
+                    TcpIncoming::channel(f.lane(Lane::H2), localup_addr_parsed),
+                    h2_config,
+                )?,
+                None => H2Listener::new(localup_addr_parsed, h2_config)?,
+            };
             info!(
                 "🔌 Tunnel control listening on {} (HTTP/2/TCP)",
                 localup_addr
@@ -3007,13 +3741,26 @@ async fn handle_relay_command(
         }
     };
 
+    // Start the front listener once every lane has a receiver
+    let front_handle = front.map(|front| {
+        info!(
+            "🔀 Single-port relay on {} (HTTPS and tunnel control)",
+            front.bind_addr()
+        );
+        tokio::spawn(async move {
+            if let Err(e) = front.run().await {
+                error!("Front listener error: {}", e);
+            }
+        })
+    });
+
     // Start API server for dashboard/management
     let api_handle = if !no_api {
         // JWT secret is required for API server
-        let jwt_secret_value = jwt_secret.clone().unwrap_or_else(|| {
+        if jwt_secret.is_none() {
             warn!("No JWT secret provided, using random generated secret");
-            uuid::Uuid::new_v4().to_string()
-        });
+        }
+        let jwt_secret_value = api_secret.clone();
 
         // Parse API addresses
         let api_http_addr_parsed: Option<SocketAddr> = api_http_addr
@@ -3178,6 +3925,15 @@ async fn handle_relay_command(
                     relay_config,
                 )
             };
+            let server = match tcp_port_policy {
+                Some(policy) => server.with_tcp_port_policy(policy),
+                None => server,
+            };
+            let server = match traffic_meter {
+                Some(meter) => server.with_traffic_meter(meter),
+                None => server,
+            };
+            let server = server.with_jwt_keys(jwt_signer, relay_jwt_validator);
 
             if let Err(e) = server.start().await {
                 error!("API server error: {}", e);
@@ -3219,7 +3975,15 @@ async fn handle_relay_command(
     if let Some(handle) = api_handle {
         handle.abort();
     }
+    if let Some(handle) = front_handle {
+        handle.abort();
+    }
     localup_handle.abort();
+    if let Some(cluster) = cluster {
+        if let Err(e) = cluster.leave().await {
+            warn!("Failed to leave the relay cluster: {}", e);
+        }
+    }
     info!("✅ Tunnel exit node stopped");
 
     Ok(())
@@ -3336,6 +4100,7 @@ async fn handle_generate_token_command(
     reverse_tunnel: bool,
     allowed_agents: Vec<String>,
     allowed_addresses: Vec<String>,
+    scopes: TokenScopeArgs,
     token_only: bool,
 ) -> Result<()> {
     use chrono::Duration;
@@ -3369,6 +4134,8 @@ async fn handle_generate_token_command(
         }
     }
 
+    claims = scopes.apply(claims);
+
     // Encode the token
     let token = JwtValidator::encode(secret.as_bytes(), &claims)?;
 
@@ -3407,6 +4174,22 @@ async fn handle_generate_token_command(
                 println!("  - Allowed addresses: all");
             }
         }
+        if !claims.protocols.is_empty() {
+            println!("  - Allowed protocols: {}", claims.protocols.join(", "));
+        }
+        if let Some(ref subdomains) = claims.allowed_subdomains {
+            println!("  - Allowed subdomains: {}", subdomains.join(", "));
+        }
+        if let Some(ref domains) = claims.allowed_domains {
+            println!("  - Allowed domains: {}", domains.join(", "));
+        }
+        if let Some(ref ports) = claims.allowed_ports {
+            let ports: Vec<String> = ports.iter().map(|r| r.to_string()).collect();
+            println!("  - Allowed TCP ports: {}", ports.join(", "));
+        }
+        if let Some(max) = claims.max_tunnels {
+            println!("  - Max tunnels: {}", max);
+        }
         println!();
         println!("Use this token in your client configuration:");
         println!("  localup --token {}", token);
@@ -3446,7 +4229,145 @@ async fn handle_config_command(command: &ConfigCommands) -> Result<()> {
             println!("✅ Auth token cleared successfully!");
             Ok(())
         }
+        ConfigCommands::Schema => {
+            println!(
+                "{}",
+                localup_cli::project_config::ProjectConfig::json_schema()
+            );
+            Ok(())
+        }
+        ConfigCommands::HashPassword { username, password } => {
+            let password = match password {
+                Some(password) => password.clone(),
+                None => {
+                    eprint!("Password: ");
+                    let mut line = String::new();
+                    std::io::stdin().read_line(&mut line)?;
+                    line.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            if password.is_empty() {
+                return Err(anyhow::anyhow!("Password must not be empty"));
+            }
+
+            let hash = localup_auth::hash_password(&password)?;
+            println!("{}:{}", username, hash);
+            Ok(())
+        }
+    }
+}
+
+async fn handle_share_command(
+    api_url: &str,
+    session_token: &str,
+    command: &ShareCommands,
+) -> Result<()> {
+    use localup_api::models::{CreateShareLinkRequest, CreateShareLinkResponse, ShareLinkList};
+
+    let client = reqwest::Client::new();
+    let api_url = api_url.trim_end_matches('/');
+
+    match command {
+        ShareCommands::Create {
+            tunnel_id,
+            path,
+            expires_in_hours,
+            max_uses,
+            name,
+        } => {
+            let response = client
+                .post(format!("{}/api/tunnels/{}/share-links", api_url, tunnel_id))
+                .bearer_auth(session_token)
+                .json(&CreateShareLinkRequest {
+                    name: name.clone(),
+                    path_prefix: Some(path.clone()),
+                    expires_in_hours: Some(*expires_in_hours),
+                    max_uses: *max_uses,
+                })
+                .send()
+                .await
+                .context("Failed to reach the relay API")?;
+            let created: CreateShareLinkResponse = api_response(response).await?;
+
+            println!("✅ Share link created (ID: {})", created.link.id);
+            println!("   Expires: {}", created.link.expires_at);
+            if let Some(max_uses) = created.link.max_uses {
+                println!("   Max uses: {}", max_uses);
+            }
+            println!();
+            println!("{}", created.url);
+            Ok(())
+        }
+        ShareCommands::List { tunnel } => {
+            let mut request = client
+                .get(format!("{}/api/share-links", api_url))
+                .bearer_auth(session_token);
+            if let Some(tunnel) = tunnel {
+                request = request.query(&[("tunnel_id", tunnel)]);
+            }
+            let response = request
+                .send()
+                .await
+                .context("Failed to reach the relay API")?;
+            let list: ShareLinkList = api_response(response).await?;
+
+            if list.links.is_empty() {
+                println!("No share links");
+                return Ok(());
+            }
+            for link in list.links {
+                let uses = match link.max_uses {
+                    Some(max) => format!("{}/{}", link.use_count, max),
+                    None => link.use_count.to_string(),
+                };
+                println!(
+                    "{}  {}  {}{}  uses: {}  expires: {}  {}",
+                    link.id,
+                    if link.is_active { "active " } else { "expired" },
+                    link.tunnel_id,
+                    link.path_prefix,
+                    uses,
+                    link.expires_at,
+                    link.name.unwrap_or_default()
+                );
+            }
+            Ok(())
+        }
+        ShareCommands::Revoke { id } => {
+            let response = client
+                .delete(format!("{}/api/share-links/{}", api_url, id))
+                .bearer_auth(session_token)
+                .send()
+                .await
+                .context("Failed to reach the relay API")?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+            println!("✅ Share link {} revoked", id);
+            Ok(())
+        }
+    }
+}
+
+/// Decode a relay API response, turning error responses into errors
+async fn api_response<T: serde::de::DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    if !response.status().is_success() {
+        return Err(api_error(response).await);
     }
+    response
+        .json()
+        .await
+        .context("Invalid response from the relay API")
+}
+
+async fn api_error(response: reqwest::Response) -> anyhow::Error {
+    let status = response.status();
+    let message = response
+        .json::<localup_api::models::ErrorResponse>()
+        .await
+        .map(|e| e.error)
+        .unwrap_or_else(|_| status.to_string());
+    anyhow::anyhow!("Relay API error ({}): {}", status, message)
 }
 
 fn init_logging(log_level: &str) -> Result<()> {
@@ -3583,8 +4504,9 @@ impl PortAllocator {
     }
 }
 
+#[localup_control::async_trait]
 impl localup_control::PortAllocator for PortAllocator {
-    fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String> {
+    async fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String> {
         let mut available = self.available_ports.lock().unwrap();
         let mut allocated = self.allocated_ports.lock().unwrap();
 
@@ -3729,7 +4651,7 @@ impl localup_control::PortAllocator for PortAllocator {
         Err("No available ports in range (all ports in use)".to_string())
     }
 
-    fn deallocate(&self, localup_id: &str) {
+    async fn deallocate(&self, localup_id: &str) {
         let mut allocated = self.allocated_ports.lock().unwrap();
 
         // Instead of immediately freeing, mark as reserved for reconnection
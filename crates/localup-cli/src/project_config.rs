@@ -5,7 +5,8 @@
 
 use anyhow::{Context, Result};
 use localup_client::{ExitNodeConfig, ProtocolConfig, TunnelConfig};
-use localup_proto::{HttpAuthConfig, TransportProtocol};
+use localup_proto::{HttpAuthConfig, TcpLimits, TransportProtocol};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -14,7 +15,10 @@ use tracing::info;
 use crate::config::ConfigManager;
 
 /// Project-level configuration file format
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+///
+/// The JSON Schema for this format is printed by `localup config schema`
+/// and kept in `docs/localup.schema.json`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct ProjectConfig {
     /// Global default settings applied to all tunnels
     #[serde(default)]
@@ -26,7 +30,7 @@ pub struct ProjectConfig {
 }
 
 /// Default settings applied to all tunnels unless overridden
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct ProjectDefaults {
     /// Default relay server address
     pub relay: Option<String>,
@@ -55,7 +59,7 @@ fn default_timeout() -> u64 {
 }
 
 /// A single tunnel definition in the project config
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProjectTunnel {
     /// Tunnel name (required, must be unique)
     pub name: String,
@@ -68,6 +72,8 @@ pub struct ProjectTunnel {
     pub protocol: String,
 
     /// Subdomain for HTTP/HTTPS/TLS tunnels
+    /// For TCP tunnels, a name reached through the relay's CONNECT/SOCKS5 proxy
+    /// instead of a public port
     pub subdomain: Option<String>,
 
     /// Custom domain for HTTP/HTTPS tunnels (e.g., "api.example.com" or "*.example.com")
@@ -90,6 +96,22 @@ pub struct ProjectTunnel {
     /// forwarded to this port instead of the main port.
     pub http_port: Option<u16>,
 
+    /// Terminate TLS at the relay for TLS tunnels
+    /// The relay serves its own certificate for the SNI hostnames and
+    /// forwards plaintext to the local port.
+    #[serde(default)]
+    pub terminate_tls: bool,
+
+    /// Re-encrypt terminated TLS traffic to the local service
+    #[serde(default)]
+    pub reencrypt: bool,
+
+    /// ALPN protocols this TLS tunnel serves (e.g. "mqtt", "acme-tls/1")
+    /// Connections offering other protocols go to the tunnel registered
+    /// for the hostname without ALPN.
+    #[serde(default)]
+    pub alpn: Vec<String>,
+
     /// Override relay server for this tunnel
     pub relay: Option<String>,
 
@@ -110,6 +132,51 @@ pub struct ProjectTunnel {
     /// If empty or not specified, all IPs are allowed
     #[serde(default, rename = "allow_ips")]
     pub ip_allowlist: Vec<String>,
+
+    /// Authentication for incoming HTTP requests: a single method
+    /// (e.g. `Basic`) or a `Policy` combining methods per path or method.
+    /// Strings support ${ENV_VAR} expansion.
+    #[serde(default, with = "auth_as_map")]
+    #[schemars(with = "Option<HttpAuthConfig>")]
+    pub http_auth: Option<HttpAuthConfig>,
+
+    /// Connection limits for TCP tunnels: max_connections, max_connections_per_ip,
+    /// idle_timeout_secs and max_lifetime_secs (each optional)
+    #[serde(default)]
+    pub tcp_limits: Option<TcpLimits>,
+}
+
+/// (De)serialize `HttpAuthConfig` enums as single-key maps (`Basic: {...}`)
+///
+/// serde_yaml otherwise expects YAML tags (`!Basic`), which the JSON Schema
+/// cannot describe.
+mod auth_as_map {
+    use localup_proto::HttpAuthConfig;
+    use serde::{
+        de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    pub fn serialize<S>(config: &Option<HttpAuthConfig>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        config
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<HttpAuthConfig>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<serde_json::Value>::deserialize(deserializer)?
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(D::Error::custom)
+    }
 }
 
 fn default_protocol() -> String {
@@ -131,12 +198,17 @@ impl Default for ProjectTunnel {
             remote_port: None,
             sni_hostnames: Vec::new(),
             http_port: None,
+            terminate_tls: false,
+            reencrypt: false,
+            alpn: vec![],
             relay: None,
             token: None,
             transport: None,
             enabled: true,
             local_host: None,
             ip_allowlist: Vec::new(),
+            http_auth: None,
+            tcp_limits: None,
         }
     }
 }
@@ -233,6 +305,22 @@ impl ProjectConfig {
                     tunnel.name
                 );
             }
+
+            if tunnel.terminate_tls && protocol != "tls" {
+                anyhow::bail!(
+                    "Tunnel '{}': terminate_tls is only supported for tls tunnels",
+                    tunnel.name
+                );
+            }
+            if tunnel.reencrypt && !tunnel.terminate_tls {
+                anyhow::bail!("Tunnel '{}': reencrypt requires terminate_tls", tunnel.name);
+            }
+            if !tunnel.alpn.is_empty() && protocol != "tls" {
+                anyhow::bail!(
+                    "Tunnel '{}': alpn is only supported for tls tunnels",
+                    tunnel.name
+                );
+            }
         }
 
         Ok(())
@@ -248,6 +336,12 @@ impl ProjectConfig {
         self.tunnels.iter().find(|t| t.name == name)
     }
 
+    /// Generate the JSON Schema describing the config file format
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(ProjectConfig);
+        serde_json::to_string_pretty(&schema).expect("schema serializes to JSON")
+    }
+
     /// Generate a template config file content
     pub fn template() -> String {
         r#"# Localup Project Configuration
@@ -287,6 +381,56 @@ tunnels:
   #   port: 8080
   #   protocol: https
   #   custom_domain: "*.example.com"
+
+  # TCP without a public port: clients reach it by name through the relay's
+  # CONNECT/SOCKS5 proxy (e.g. curl -x relay.example.com:8080 ...)
+  # - name: postgres
+  #   port: 5432
+  #   protocol: tcp
+  #   subdomain: db
+
+  # TLS terminated at the relay (relay certificate, plaintext to the local port)
+  # - name: mqtt
+  #   port: 1883
+  #   protocol: tls
+  #   sni_hostnames: ["mqtt.example.com"]
+  #   terminate_tls: true
+
+  # Same hostname, split by ALPN: MQTT clients go here, everything else
+  # to the tunnel registered for the hostname without alpn
+  # - name: broker
+  #   port: 8883
+  #   protocol: tls
+  #   sni_hostnames: ["iot.example.com"]
+  #   alpn: ["mqtt"]
+
+  # Login page example: browsers sign in once and get a session cookie,
+  # scripts can still use Basic auth (logout at /_localup/logout)
+  # - name: dashboard
+  #   port: 3000
+  #   protocol: https
+  #   http_auth:
+  #     LoginForm:
+  #       credentials: ["admin:${ADMIN_PASSWORD}"]
+  #       team_accounts: true
+
+  # Per-path authentication example (see `localup config schema`)
+  # - name: webhooks
+  #   port: 4000
+  #   protocol: https
+  #   http_auth:
+  #     Policy:
+  #       PathMatch:
+  #         - paths: ["/webhooks/*"]
+  #           policy: Allow
+  #         - paths: ["/admin/*"]
+  #           policy:
+  #             AllOf:
+  #               - Auth: { Basic: { credentials: ["admin:${ADMIN_PASSWORD}"] } }
+  #               - IpAllowlist: ["10.0.0.0/8"]
+  #         - paths: ["*"]
+  #           policy:
+  #             Auth: { BearerToken: { tokens: ["${API_TOKEN}"] } }
 "#
         .to_string()
     }
@@ -351,11 +495,19 @@ impl ProjectTunnel {
             "tcp" => ProtocolConfig::Tcp {
                 local_port: self.port,
                 remote_port: self.remote_port,
+                // Named TCP tunnels go through the relay's CONNECT/SOCKS5 proxy
+                hostname: self
+                    .custom_domain
+                    .clone()
+                    .or_else(|| self.subdomain.clone()),
             },
             "tls" => ProtocolConfig::Tls {
                 local_port: self.port,
                 sni_hostnames: self.sni_hostnames.clone(),
                 http_port: self.http_port,
+                terminate: self.terminate_tls,
+                reencrypt: self.reencrypt,
+                alpn: self.alpn.clone(),
             },
             _ => anyhow::bail!("Unknown protocol: {}", self.protocol),
         };
@@ -380,8 +532,15 @@ impl ProjectTunnel {
             failover: true,
             connection_timeout: Duration::from_secs(defaults.timeout_seconds),
             preferred_transport,
-            http_auth: HttpAuthConfig::None,
+            http_auth: self
+                .http_auth
+                .as_ref()
+                .map(expand_env_vars_in_auth)
+                .transpose()?
+                .unwrap_or(HttpAuthConfig::None),
             ip_allowlist: self.ip_allowlist.clone(),
+            tcp_limits: self.tcp_limits.unwrap_or_default(),
+            relay_directory: None,
         })
     }
 }
@@ -410,6 +569,22 @@ pub fn expand_env_vars(input: &str) -> String {
     result
 }
 
+/// Expand `${VAR}` in every string of an auth configuration
+fn expand_env_vars_in_auth(config: &HttpAuthConfig) -> Result<HttpAuthConfig> {
+    fn expand(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => *s = expand_env_vars(s),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(expand),
+            serde_json::Value::Object(map) => map.values_mut().for_each(expand),
+            _ => {}
+        }
+    }
+
+    let mut value = serde_json::to_value(config).context("Failed to serialize http_auth")?;
+    expand(&mut value);
+    serde_json::from_value(value).context("Invalid http_auth after variable expansion")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -628,12 +803,17 @@ tunnels:
             remote_port: None,
             sni_hostnames: Vec::new(),
             http_port: None,
+            terminate_tls: false,
+            reencrypt: false,
+            alpn: vec![],
             relay: None,
             token: None,
             transport: None,
             enabled: true,
             local_host: None,
             ip_allowlist: Vec::new(),
+            http_auth: None,
+            tcp_limits: None,
         };
 
         let config = tunnel.to_tunnel_config(&defaults).unwrap();
@@ -668,12 +848,17 @@ tunnels:
             remote_port: Some(15432),
             sni_hostnames: Vec::new(),
             http_port: None,
+            terminate_tls: false,
+            reencrypt: false,
+            alpn: vec![],
             relay: Some("custom-relay:4443".to_string()),
             token: Some("custom-token".to_string()),
             transport: Some("quic".to_string()),
             enabled: true,
             local_host: Some("127.0.0.1".to_string()),
             ip_allowlist: Vec::new(),
+            http_auth: None,
+            tcp_limits: None,
         };
 
         let config = tunnel.to_tunnel_config(&defaults).unwrap();
@@ -685,15 +870,37 @@ tunnels:
         if let ProtocolConfig::Tcp {
             local_port,
             remote_port,
+            hostname,
         } = &config.protocols[0]
         {
             assert_eq!(*local_port, 5432);
             assert_eq!(*remote_port, Some(15432));
+            assert_eq!(*hostname, None);
         } else {
             panic!("Expected TCP protocol");
         }
     }
 
+    #[test]
+    fn test_to_tunnel_config_tcp_hostname() {
+        let yaml = r#"
+tunnels:
+  - name: postgres
+    port: 5432
+    protocol: tcp
+    subdomain: db
+"#;
+        let config = ProjectConfig::parse(yaml).unwrap();
+        let tunnel_config = config.tunnels[0]
+            .to_tunnel_config(&ProjectDefaults::default())
+            .unwrap();
+
+        match &tunnel_config.protocols[0] {
+            ProtocolConfig::Tcp { hostname, .. } => assert_eq!(hostname.as_deref(), Some("db")),
+            _ => panic!("Expected TCP protocol"),
+        }
+    }
+
     #[test]
     fn test_to_tunnel_config_with_env_var_token() {
         std::env::set_var("MY_TOKEN", "secret-from-env");
@@ -712,12 +919,17 @@ tunnels:
             remote_port: None,
             sni_hostnames: Vec::new(),
             http_port: None,
+            terminate_tls: false,
+            reencrypt: false,
+            alpn: vec![],
             relay: None,
             token: None,
             transport: None,
             enabled: true,
             local_host: None,
             ip_allowlist: Vec::new(),
+            http_auth: None,
+            tcp_limits: None,
         };
 
         let config = tunnel.to_tunnel_config(&defaults).unwrap();
@@ -904,12 +1116,17 @@ tunnels:
             remote_port: None,
             sni_hostnames: Vec::new(),
             http_port: None,
+            terminate_tls: false,
+            reencrypt: false,
+            alpn: vec![],
             relay: None,
             token: None,
             transport: None,
             enabled: true,
             local_host: None,
             ip_allowlist: Vec::new(),
+            http_auth: None,
+            tcp_limits: None,
         };
 
         let config = tunnel.to_tunnel_config(&defaults).unwrap();
@@ -1029,6 +1246,77 @@ tunnels:
         }
     }
 
+    #[test]
+    fn test_tls_protocol_terminated() {
+        let yaml = r#"
+tunnels:
+  - name: mqtt
+    port: 1883
+    protocol: tls
+    sni_hostnames:
+      - mqtt.example.com
+    terminate_tls: true
+    reencrypt: true
+"#;
+        let config = ProjectConfig::parse(yaml).unwrap();
+        let tunnel_config = config.tunnels[0]
+            .to_tunnel_config(&ProjectDefaults::default())
+            .unwrap();
+
+        match &tunnel_config.protocols[0] {
+            ProtocolConfig::Tls {
+                terminate,
+                reencrypt,
+                ..
+            } => {
+                assert!(*terminate);
+                assert!(*reencrypt);
+            }
+            _ => panic!("Expected TLS protocol"),
+        }
+
+        let invalid = r#"
+tunnels:
+  - name: web
+    port: 3000
+    protocol: http
+    terminate_tls: true
+"#;
+        assert!(ProjectConfig::parse(invalid).is_err());
+    }
+
+    #[test]
+    fn test_tls_protocol_alpn() {
+        let yaml = r#"
+tunnels:
+  - name: broker
+    port: 8883
+    protocol: tls
+    sni_hostnames:
+      - iot.example.com
+    alpn:
+      - mqtt
+"#;
+        let config = ProjectConfig::parse(yaml).unwrap();
+        let tunnel_config = config.tunnels[0]
+            .to_tunnel_config(&ProjectDefaults::default())
+            .unwrap();
+
+        match &tunnel_config.protocols[0] {
+            ProtocolConfig::Tls { alpn, .. } => assert_eq!(alpn, &vec!["mqtt".to_string()]),
+            _ => panic!("Expected TLS protocol"),
+        }
+
+        let invalid = r#"
+tunnels:
+  - name: web
+    port: 3000
+    protocol: http
+    alpn: ["h2"]
+"#;
+        assert!(ProjectConfig::parse(invalid).is_err());
+    }
+
     #[test]
     fn test_tls_protocol_mixed_wildcards_and_specific() {
         let yaml = r#"
@@ -1122,4 +1410,54 @@ tunnels:
             ));
         }
     }
+
+    #[test]
+    fn test_http_auth_policy() {
+        std::env::set_var("POLICY_TEST_TOKEN", "from-env");
+        let yaml = r#"
+tunnels:
+  - name: api
+    port: 3000
+    http_auth:
+      Policy:
+        PathMatch:
+          - paths: ["/webhooks/*"]
+            policy: Allow
+          - paths: ["/admin/*"]
+            policy:
+              AllOf:
+                - Auth: { Basic: { credentials: ["admin:secret"] } }
+                - IpAllowlist: ["10.0.0.0/8"]
+          - paths: ["*"]
+            policy:
+              Auth: { BearerToken: { tokens: ["${POLICY_TEST_TOKEN}"] } }
+"#;
+        let config = ProjectConfig::parse(yaml).unwrap();
+        let tunnel_config = config.tunnels[0]
+            .to_tunnel_config(&config.defaults)
+            .unwrap();
+        std::env::remove_var("POLICY_TEST_TOKEN");
+
+        let HttpAuthConfig::Policy(policy) = tunnel_config.http_auth else {
+            panic!("Expected a policy");
+        };
+        let localup_proto::AuthPolicy::PathMatch(rules) = *policy else {
+            panic!("Expected path rules");
+        };
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].policy, localup_proto::AuthPolicy::Allow);
+        assert_eq!(
+            rules[2].policy,
+            localup_proto::AuthPolicy::Auth(HttpAuthConfig::BearerToken {
+                tokens: vec!["from-env".to_string()]
+            })
+        );
+    }
+
+    #[test]
+    fn test_schema_file_is_current() {
+        // Regenerate with: localup config schema > docs/localup.schema.json
+        let committed = include_str!("../../../docs/localup.schema.json");
+        assert_eq!(committed.trim(), ProjectConfig::json_schema().trim());
+    }
 }
@@ -28,6 +28,8 @@ fn create_test_tunnel(name: &str, port: u16, enabled: bool) -> StoredTunnel {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     }
 }
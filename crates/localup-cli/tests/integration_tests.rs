@@ -25,6 +25,8 @@ fn create_test_config(name: &str, port: u16) -> StoredTunnel {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     }
 }
@@ -266,6 +268,8 @@ fn test_localup_store_protocol_types() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
     store.save(&http_tunnel).unwrap();
@@ -288,6 +292,8 @@ fn test_localup_store_protocol_types() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
     store.save(&https_tunnel).unwrap();
@@ -301,6 +307,7 @@ fn test_localup_store_protocol_types() {
             protocols: vec![ProtocolConfig::Tcp {
                 local_port: 5432,
                 remote_port: Some(5432),
+                hostname: None,
             }],
             auth_token: "test-token".to_string(),
             exit_node: ExitNodeConfig::Auto,
@@ -309,6 +316,8 @@ fn test_localup_store_protocol_types() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
     store.save(&tcp_tunnel).unwrap();
@@ -323,6 +332,9 @@ fn test_localup_store_protocol_types() {
                 local_port: 9000,
                 sni_hostnames: vec!["tls-test.example.com".to_string()],
                 http_port: None,
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             }],
             auth_token: "test-token".to_string(),
             exit_node: ExitNodeConfig::Auto,
@@ -331,6 +343,8 @@ fn test_localup_store_protocol_types() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
     store.save(&tls_tunnel).unwrap();
@@ -387,6 +401,8 @@ fn test_localup_store_exit_node_configs() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
     store.save(&auto_tunnel).unwrap();
@@ -409,6 +425,8 @@ fn test_localup_store_exit_node_configs() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
     store.save(&custom_tunnel).unwrap();
@@ -443,6 +461,8 @@ fn test_localup_store_serialization_roundtrip() {
             preferred_transport: None,
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(),
+            tcp_limits: Default::default(),
+            relay_directory: None,
         },
     };
 
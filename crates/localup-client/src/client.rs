@@ -3,7 +3,8 @@
 use crate::config::TunnelConfig;
 use crate::localup::{TunnelConnection, TunnelConnector};
 use crate::metrics::MetricsStore;
-use localup_proto::Endpoint;
+use crate::multi_relay::{MultiRelayTunnel, RelayEndpoints, RelayStatus};
+use localup_proto::{Endpoint, ExitNodeConfig};
 use thiserror::Error;
 
 /// Tunnel client errors
@@ -44,43 +45,94 @@ impl TunnelError {
 }
 
 /// Tunnel client
+///
+/// Connects to a single relay, or with [`ExitNodeConfig::MultiRegion`] to one
+/// relay per region at once (see [`crate::multi_relay`]).
 pub struct TunnelClient {
-    connection: TunnelConnection,
+    inner: ClientInner,
+}
+
+enum ClientInner {
+    Single(Box<TunnelConnection>),
+    Multi(MultiRelayTunnel),
 }
 
 impl TunnelClient {
     /// Connect to tunnel service
     pub async fn connect(config: TunnelConfig) -> Result<Self, TunnelError> {
-        // Use TunnelConnector to establish connection
-        let connector = TunnelConnector::new(config);
-        let connection = connector.connect().await?;
+        let inner = match &config.exit_node {
+            ExitNodeConfig::MultiRegion(regions) if regions.len() > 1 => {
+                let regions = regions.clone();
+                ClientInner::Multi(MultiRelayTunnel::connect(config, &regions).await?)
+            }
+            _ => {
+                // Use TunnelConnector to establish connection
+                let connector = TunnelConnector::new(config);
+                ClientInner::Single(Box::new(connector.connect().await?))
+            }
+        };
+
+        Ok(Self { inner })
+    }
 
-        Ok(Self { connection })
+    /// Get the public endpoints on each relay, with the relay's status
+    pub fn endpoints(&self) -> Vec<RelayEndpoints> {
+        match &self.inner {
+            ClientInner::Single(connection) => vec![RelayEndpoints {
+                relay: connection.relay().to_string(),
+                region: None,
+                status: if connection.is_connected() {
+                    RelayStatus::Connected
+                } else {
+                    RelayStatus::Closed
+                },
+                endpoints: connection.endpoints().to_vec(),
+            }],
+            ClientInner::Multi(tunnel) => tunnel.endpoints(),
+        }
     }
 
-    /// Get the public endpoints
-    pub fn endpoints(&self) -> &[Endpoint] {
-        self.connection.endpoints()
+    /// Get the public endpoints of every relay
+    pub fn public_endpoints(&self) -> Vec<Endpoint> {
+        self.endpoints()
+            .into_iter()
+            .flat_map(|relay| relay.endpoints)
+            .collect()
     }
 
     /// Get the first public URL (convenience method)
     pub fn public_url(&self) -> Option<&str> {
-        self.connection.public_url()
+        match &self.inner {
+            ClientInner::Single(connection) => connection.public_url(),
+            ClientInner::Multi(tunnel) => tunnel.public_url(),
+        }
     }
 
     /// Get the tunnel ID
     pub fn localup_id(&self) -> &str {
-        self.connection.localup_id()
+        match &self.inner {
+            ClientInner::Single(connection) => connection.localup_id(),
+            ClientInner::Multi(tunnel) => tunnel.localup_id(),
+        }
     }
 
-    /// Get access to metrics store
+    /// Get access to metrics store (shared by every relay connection)
     pub fn metrics(&self) -> &MetricsStore {
-        self.connection.metrics()
+        match &self.inner {
+            ClientInner::Single(connection) => connection.metrics(),
+            ClientInner::Multi(tunnel) => tunnel.metrics(),
+        }
     }
 
     /// Send graceful disconnect message (does not consume self)
     pub async fn disconnect(&self) -> Result<(), TunnelError> {
-        self.connection.disconnect().await
+        match &self.inner {
+            ClientInner::Single(connection) => connection.disconnect().await,
+            ClientInner::Multi(tunnel) => {
+                tunnel.disconnect();
+                Ok(())
+            }
+        }
     }
 
     /// Get a handle that can send disconnect without owning the client
@@ -88,26 +140,55 @@ impl TunnelClient {
     pub fn disconnect_handle(
         &self,
     ) -> impl std::future::Future<Output = Result<(), TunnelError>> + Send + 'static {
-        let connection = self.connection.clone();
-        async move { connection.disconnect().await }
+        let inner = match &self.inner {
+            ClientInner::Single(connection) => Ok(connection.as_ref().clone()),
+            ClientInner::Multi(tunnel) => Err(tunnel.shutdown_handle()),
+        };
+        async move {
+            match inner {
+                Ok(connection) => connection.disconnect().await,
+                Err(shutdown) => {
+                    shutdown.send_replace(true);
+                    Ok(())
+                }
+            }
+        }
     }
 
     /// Wait for tunnel to close
+    ///
+    /// With several relays, each one reconnects on its own and this returns
+    /// once all of them are disconnected or rejected the tunnel for good.
     pub async fn wait(self) -> Result<(), TunnelError> {
-        // Run the tunnel connection loop
-        self.connection.run().await
+        match self.inner {
+            // Run the tunnel connection loop
+            ClientInner::Single(connection) => connection.run().await,
+            ClientInner::Multi(tunnel) => tunnel.wait().await,
+        }
     }
 
     /// Close the tunnel gracefully
     pub async fn close(self) -> Result<(), TunnelError> {
-        // Send disconnect message to exit node for immediate cleanup
-        self.connection.disconnect().await?;
-
-        // Give the disconnect message time to be sent
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-
-        // The connection will be dropped, closing the socket
-        Ok(())
+        match self.inner {
+            ClientInner::Single(connection) => {
+                // Send disconnect message to exit node for immediate cleanup
+                connection.disconnect().await?;
+
+                // Give the disconnect message time to be sent
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+                // The connection will be dropped, closing the socket
+                Ok(())
+            }
+            ClientInner::Multi(tunnel) => {
+                tunnel.disconnect();
+                tokio::time::timeout(tokio::time::Duration::from_secs(10), tunnel.wait())
+                    .await
+                    .map_err(|_| {
+                        TunnelError::TunnelClosed("Timed out disconnecting relays".to_string())
+                    })?
+            }
+        }
     }
 }
 
@@ -115,7 +196,6 @@ impl TunnelClient {
 mod tests {
     use super::*;
     use crate::config::ProtocolConfig;
-    use localup_proto::ExitNodeConfig;
 
     #[tokio::test]
     #[ignore] // Requires a running exit node
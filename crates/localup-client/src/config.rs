@@ -1,6 +1,7 @@
 //! Client configuration
 
-use localup_proto::{ExitNodeConfig, HttpAuthConfig, TransportProtocol};
+use crate::relay_discovery::RelayDirectoryConfig;
+use localup_proto::{ExitNodeConfig, HttpAuthConfig, TcpLimits, TransportProtocol};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -11,6 +12,10 @@ pub enum ProtocolConfig {
     Tcp {
         local_port: u16,
         remote_port: Option<u16>,
+        /// Reach the tunnel by hostname through the relay's HTTP CONNECT / SOCKS5
+        /// listener instead of a public port (`remote_port` is then ignored)
+        #[serde(default)]
+        hostname: Option<String>,
     },
     /// TLS/SNI-based routing
     /// Routes incoming TLS connections based on Server Name Indication (SNI)
@@ -28,6 +33,18 @@ pub enum ProtocolConfig {
         /// If not set, HTTP passthrough traffic goes to local_port
         #[serde(default)]
         http_port: Option<u16>,
+        /// Terminate TLS at the relay with its own certificates and forward
+        /// plaintext to local_port (for MQTT, Redis or other raw TCP services)
+        #[serde(default)]
+        terminate: bool,
+        /// With `terminate`, connect to the local service over TLS again
+        /// The local certificate is not verified (it is usually self-signed)
+        #[serde(default)]
+        reencrypt: bool,
+        /// Only take connections offering one of these ALPN protocols
+        /// (e.g. "mqtt", "acme-tls/1"); empty takes every connection for the hostnames
+        #[serde(default)]
+        alpn: Vec<String>,
     },
     /// HTTP with host-based routing
     Http {
@@ -69,6 +86,13 @@ pub struct TunnelConfig {
     /// Empty list means all IPs are allowed
     #[serde(default)]
     pub ip_allowlist: Vec<String>,
+    /// Connection limits the relay enforces on TCP endpoints
+    #[serde(default)]
+    pub tcp_limits: TcpLimits,
+    /// Live relay directory used for automatic relay selection
+    /// (None = the directory embedded at build time)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_directory: Option<RelayDirectoryConfig>,
 }
 
 /// Helper module for serializing Duration as seconds
@@ -104,6 +128,8 @@ impl Default for TunnelConfig {
             preferred_transport: None, // Auto-discover
             http_auth: HttpAuthConfig::None,
             ip_allowlist: Vec::new(), // Empty = allow all
+            tcp_limits: TcpLimits::default(),
+            relay_directory: None,
         }
     }
 }
@@ -157,6 +183,18 @@ impl TunnelConfigBuilder {
         self
     }
 
+    /// Limit connections to TCP endpoints
+    pub fn tcp_limits(mut self, limits: TcpLimits) -> Self {
+        self.config.tcp_limits = limits;
+        self
+    }
+
+    /// Select relays from a live directory instead of the embedded one
+    pub fn relay_directory(mut self, directory: RelayDirectoryConfig) -> Self {
+        self.config.relay_directory = Some(directory);
+        self
+    }
+
     pub fn build(self) -> Result<TunnelConfig, String> {
         if self.config.auth_token.is_empty() {
             return Err("auth_token is required".to_string());
@@ -266,6 +304,9 @@ mod tests {
                 local_port: 443,
                 sni_hostnames: vec!["api.example.com".to_string()],
                 http_port: None,
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             })
             .auth_token("test-token".to_string())
             .build()
@@ -276,6 +317,7 @@ mod tests {
                 local_port,
                 sni_hostnames,
                 http_port,
+                ..
             } => {
                 assert_eq!(*local_port, 443);
                 assert_eq!(sni_hostnames.len(), 1);
@@ -297,6 +339,9 @@ mod tests {
                     "admin.example.com".to_string(),
                 ],
                 http_port: None,
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             })
             .auth_token("test-token".to_string())
             .build()
@@ -329,6 +374,9 @@ mod tests {
                     "api.specific.com".to_string(),
                 ],
                 http_port: None,
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             })
             .auth_token("test-token".to_string())
             .build()
@@ -353,6 +401,9 @@ mod tests {
                 local_port: 443,
                 sni_hostnames: vec![],
                 http_port: None,
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             })
             .auth_token("test-token".to_string())
             .build()
@@ -376,6 +427,9 @@ mod tests {
                     "api.production.com".to_string(),
                 ],
                 http_port: Some(8080),
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             })
             .auth_token("test-token".to_string())
             .build()
@@ -392,6 +446,7 @@ mod tests {
                 local_port,
                 sni_hostnames,
                 http_port,
+                ..
             } => {
                 assert_eq!(*local_port, 8443);
                 assert_eq!(sni_hostnames.len(), 2);
@@ -410,6 +465,9 @@ mod tests {
                 local_port: 9443,
                 sni_hostnames: vec!["*.example.com".to_string()],
                 http_port: Some(9080),
+                terminate: false,
+                reencrypt: false,
+                alpn: vec![],
             })
             .auth_token("test-token".to_string())
             .build()
@@ -420,6 +478,7 @@ mod tests {
                 local_port,
                 sni_hostnames,
                 http_port,
+                ..
             } => {
                 assert_eq!(*local_port, 9443);
                 assert_eq!(sni_hostnames.len(), 1);
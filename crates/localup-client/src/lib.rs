@@ -10,6 +10,7 @@ pub mod metrics;
 pub mod metrics_db;
 pub mod metrics_server;
 pub mod metrics_service;
+pub mod multi_relay;
 pub mod relay_discovery;
 
 pub use client::{TunnelClient, TunnelError};
@@ -18,9 +19,12 @@ pub use metrics::{
     BodyContent, BodyData, HttpMetric, MetricsStats, MetricsStore, TcpConnectionState, TcpMetric,
 };
 pub use metrics_server::MetricsServer;
-pub use relay_discovery::{RelayDiscovery, RelayEndpoint, RelayError, RelayInfo};
+pub use multi_relay::{RelayEndpoints, RelayStatus};
+pub use relay_discovery::{
+    RelayDirectoryConfig, RelayDiscovery, RelayEndpoint, RelayError, RelayInfo,
+};
 
-pub use localup_proto::{Endpoint, ExitNodeConfig, Protocol, Region};
+pub use localup_proto::{Endpoint, ExitNodeConfig, Protocol, Region, TcpLimits};
 #[cfg(feature = "db-metrics")]
 pub use metrics_db::DbMetricsStore;
 
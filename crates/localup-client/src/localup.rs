@@ -3,7 +3,7 @@
 use crate::config::{ProtocolConfig, TunnelConfig};
 use crate::http_proxy::HttpProxy;
 use crate::metrics::MetricsStore;
-use crate::relay_discovery::RelayDiscovery;
+use crate::relay_discovery::{RelayDiscovery, DEFAULT_PROBE_TIMEOUT};
 use crate::transport_discovery::TransportDiscoverer;
 use crate::TunnelError;
 use localup_proto::{Endpoint, Protocol, TransportProtocol, TunnelMessage};
@@ -18,6 +18,11 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tracing::{debug, error, info, warn};
 
+/// Connection to a local service: plain TCP or re-encrypted TLS
+trait LocalStream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send {}
+
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send> LocalStream for T {}
+
 /// HTTP request data for processing
 struct HttpRequestData {
     method: String,
@@ -453,19 +458,37 @@ fn generate_localup_id_from_token_and_protocols(
             ProtocolConfig::Tcp {
                 local_port,
                 remote_port,
+                hostname,
             } => {
                 "tcp".hash(&mut hasher);
                 local_port.hash(&mut hasher);
                 remote_port.hash(&mut hasher);
+                // Only hostname tunnels mix it in so port tunnel IDs stay stable
+                if let Some(hostname) = hostname {
+                    hostname.hash(&mut hasher);
+                }
             }
             ProtocolConfig::Tls {
                 local_port,
                 sni_hostnames,
                 http_port,
+                terminate,
+                reencrypt,
+                alpn,
             } => {
                 "tls".hash(&mut hasher);
                 local_port.hash(&mut hasher);
                 http_port.hash(&mut hasher);
+                // Only terminated tunnels mix in the mode so passthrough IDs stay stable
+                if *terminate {
+                    "terminate".hash(&mut hasher);
+                    reencrypt.hash(&mut hasher);
+                }
+                // Likewise ALPN-restricted tunnels only, so they don't collide with
+                // the tunnel serving the rest of the hostname's traffic
+                for protocol in alpn {
+                    protocol.hash(&mut hasher);
+                }
                 // Hash all SNI hostnames to differentiate TLS tunnels
                 for hostname in sni_hostnames {
                     hostname.hash(&mut hasher);
@@ -488,14 +511,35 @@ fn generate_localup_id_from_token_and_protocols(
     )
 }
 
+/// Relay endpoint protocol ("https" or "tcp") used to select relays for a tunnel
+pub(crate) fn relay_protocol(config: &TunnelConfig) -> Result<&'static str, TunnelError> {
+    match config.protocols.first() {
+        Some(ProtocolConfig::Http { .. }) | Some(ProtocolConfig::Https { .. }) => Ok("https"),
+        Some(ProtocolConfig::Tcp { .. }) | Some(ProtocolConfig::Tls { .. }) => Ok("tcp"),
+        None => Err(TunnelError::ConnectionError(
+            "No protocol configured".to_string(),
+        )),
+    }
+}
+
 /// Tunnel connector - handles the tunnel protocol with the exit node
 pub struct TunnelConnector {
     config: TunnelConfig,
+    metrics: Option<MetricsStore>,
 }
 
 impl TunnelConnector {
     pub fn new(config: TunnelConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            metrics: None,
+        }
+    }
+
+    /// Record the connection's traffic into an existing metrics store
+    pub fn with_metrics(mut self, metrics: MetricsStore) -> Self {
+        self.metrics = Some(metrics);
+        self
     }
 
     /// Parse relay address from various formats
@@ -585,35 +629,41 @@ impl TunnelConnector {
             | localup_proto::ExitNodeConfig::MultiRegion(_) => {
                 info!("Using automatic relay selection");
 
-                // Initialize relay discovery
-                let discovery = RelayDiscovery::new().map_err(|e| {
-                    TunnelError::ConnectionError(format!(
-                        "Failed to initialize relay discovery: {}",
-                        e
-                    ))
-                })?;
+                // Load the live relay directory, falling back to the embedded one
+                let discovery = RelayDiscovery::load(self.config.relay_directory.as_ref())
+                    .await
+                    .map_err(|e| {
+                        TunnelError::ConnectionError(format!(
+                            "Failed to initialize relay discovery: {}",
+                            e
+                        ))
+                    })?;
 
                 // Determine protocol for relay selection based on tunnel protocol
-                let relay_protocol = match self.config.protocols.first() {
-                    Some(ProtocolConfig::Http { .. }) | Some(ProtocolConfig::Https { .. }) => {
-                        "https"
-                    }
-                    Some(ProtocolConfig::Tcp { .. }) | Some(ProtocolConfig::Tls { .. }) => "tcp",
-                    None => {
-                        return Err(TunnelError::ConnectionError(
-                            "No protocol configured".to_string(),
-                        ))
+                let relay_protocol = relay_protocol(&self.config)?;
+
+                // Restrict to the requested region, then pick the fastest relay
+                // (TunnelClient connects MultiRegion tunnels to every region; a
+                // connector on its own uses the first)
+                let preferred_region = match &self.config.exit_node {
+                    localup_proto::ExitNodeConfig::Specific(region) => Some(region.as_str()),
+                    localup_proto::ExitNodeConfig::MultiRegion(regions) => {
+                        regions.first().map(|region| region.as_str())
                     }
+                    _ => None,
                 };
-
-                // Select relay using auto policy
-                // TODO: Implement region-aware selection for Nearest, Specific, MultiRegion variants
-                let relay_addr =
-                    discovery
-                        .select_relay(relay_protocol, None, None)
-                        .map_err(|e| {
-                            TunnelError::ConnectionError(format!("Failed to select relay: {}", e))
-                        })?;
+                let relay_addr = discovery
+                    .select_relay_probed(
+                        relay_protocol,
+                        preferred_region,
+                        None,
+                        self.config.preferred_transport,
+                        DEFAULT_PROBE_TIMEOUT,
+                    )
+                    .await
+                    .map_err(|e| {
+                        TunnelError::ConnectionError(format!("Failed to select relay: {}", e))
+                    })?;
 
                 info!(
                     "Auto-selected relay: {} (protocol: {})",
@@ -757,6 +807,12 @@ impl TunnelConnector {
                     subdomain: subdomain.clone(),
                     custom_domain: custom_domain.clone(),
                 },
+                ProtocolConfig::Tcp {
+                    hostname: Some(hostname),
+                    ..
+                } => Protocol::TcpNamed {
+                    hostname: hostname.clone(),
+                },
                 ProtocolConfig::Tcp { remote_port, .. } => Protocol::Tcp {
                     // 0 means auto-allocate, specific port means request that port
                     port: remote_port.unwrap_or(0),
@@ -766,15 +822,31 @@ impl TunnelConnector {
                     local_port: _,
                     sni_hostnames,
                     http_port: _,
-                } => Protocol::Tls {
-                    port: 8443, // TLS server port (SNI-based routing)
+                    terminate,
+                    reencrypt,
+                    alpn,
+                } => {
                     // Use all provided SNI patterns, or default to "*" if none
-                    sni_patterns: if sni_hostnames.is_empty() {
+                    let sni_patterns = if sni_hostnames.is_empty() {
                         vec!["*".to_string()]
                     } else {
                         sni_hostnames.clone()
-                    },
-                },
+                    };
+                    if *terminate {
+                        Protocol::TlsTerminated {
+                            port: 8443, // TLS server port (SNI-based routing)
+                            sni_patterns,
+                            reencrypt: *reencrypt,
+                            alpn: alpn.clone(),
+                        }
+                    } else {
+                        Protocol::Tls {
+                            port: 8443, // TLS server port (SNI-based routing)
+                            sni_patterns,
+                            alpn: alpn.clone(),
+                        }
+                    }
+                }
             })
             .collect();
 
@@ -785,7 +857,13 @@ impl TunnelConnector {
             protocols: protocols.clone(),
             config: localup_proto::TunnelConfig {
                 local_host: self.config.local_host.clone(),
-                local_port: None,
+                // Lets the relay hand back the same public port for the same local service
+                local_port: self.config.protocols.first().map(|p| match p {
+                    ProtocolConfig::Tcp { local_port, .. }
+                    | ProtocolConfig::Tls { local_port, .. }
+                    | ProtocolConfig::Http { local_port, .. }
+                    | ProtocolConfig::Https { local_port, .. } => *local_port,
+                }),
                 local_https: false,
                 exit_node: self.config.exit_node.clone(),
                 failover: self.config.failover,
@@ -793,6 +871,7 @@ impl TunnelConnector {
                 enable_compression: false,
                 enable_multiplexing: true,
                 http_auth: self.config.http_auth.clone(),
+                tcp_limits: self.config.tcp_limits,
             },
         };
 
@@ -825,8 +904,9 @@ impl TunnelConnector {
                     shutdown_tx: Arc::new(tokio::sync::Mutex::new(None)),
                     localup_id: tid,
                     endpoints,
+                    relay: relay_addr_str,
                     config: self.config,
-                    metrics: MetricsStore::default(),
+                    metrics: self.metrics.unwrap_or_default(),
                     connection_semaphore,
                 })
             }
@@ -899,6 +979,14 @@ impl ConnectionWrapper {
             }
         }
     }
+
+    fn is_closed(&self) -> bool {
+        use localup_transport::TransportConnection;
+        match self {
+            ConnectionWrapper::Quic(conn) => conn.is_closed(),
+            ConnectionWrapper::H2(conn) => conn.is_closed(),
+        }
+    }
 }
 
 /// Wrapper for different transport stream types
@@ -974,6 +1062,8 @@ pub struct TunnelConnection {
     shutdown_tx: Arc<tokio::sync::Mutex<Option<tokio::sync::mpsc::Sender<()>>>>,
     localup_id: String,
     endpoints: Vec<Endpoint>,
+    /// Relay address the tunnel is registered on
+    relay: String,
     config: TunnelConfig,
     metrics: MetricsStore,
     /// Semaphore to limit concurrent connections to local server
@@ -994,6 +1084,16 @@ impl TunnelConnection {
         self.endpoints.first().map(|e| e.public_url.as_str())
     }
 
+    /// Address of the relay the tunnel is registered on
+    pub fn relay(&self) -> &str {
+        &self.relay
+    }
+
+    /// Whether the connection to the relay is still open
+    pub fn is_connected(&self) -> bool {
+        !self._connection.is_closed()
+    }
+
     /// Get access to the metrics store
     pub fn metrics(&self) -> &MetricsStore {
         &self.metrics
@@ -1209,6 +1309,7 @@ impl TunnelConnection {
                                     &config_clone,
                                     &metrics_clone,
                                     stream_id,
+                                    sni,
                                     client_hello,
                                 )
                                 .await;
@@ -1817,6 +1918,7 @@ impl TunnelConnection {
         config: &TunnelConfig,
         _metrics: &MetricsStore,
         stream_id: u32,
+        sni: String,
         client_hello: Vec<u8>,
     ) {
         // Extract the inner QUIC stream
@@ -1833,12 +1935,14 @@ impl TunnelConnection {
             ProtocolConfig::Tls {
                 local_port,
                 http_port,
+                terminate,
+                reencrypt,
                 ..
-            } => Some((*local_port, *http_port)),
+            } => Some((*local_port, *http_port, *terminate && *reencrypt)),
             _ => None,
         });
 
-        let (tls_port, http_port) = match tls_config {
+        let (tls_port, http_port, reencrypt) = match tls_config {
             Some(config) => config,
             None => {
                 error!("No TLS protocol configured");
@@ -1900,8 +2004,27 @@ impl TunnelConnection {
             local_addr
         );
 
+        // The relay terminated TLS: optionally encrypt again towards the local service
+        let local_socket: Box<dyn LocalStream> = if reencrypt && !is_http {
+            match Self::reencrypt_local(local_socket, &sni, &config.local_host).await {
+                Ok(tls) => Box::new(tls),
+                Err(e) => {
+                    error!(
+                        "TLS handshake with local service at {} failed: {}",
+                        local_addr, e
+                    );
+                    let _ = stream
+                        .send_message(&TunnelMessage::TlsClose { stream_id })
+                        .await;
+                    return;
+                }
+            }
+        } else {
+            Box::new(local_socket)
+        };
+
         // Split both streams for bidirectional communication WITHOUT MUTEXES
-        let (mut local_read, mut local_write) = local_socket.into_split();
+        let (mut local_read, mut local_write) = tokio::io::split(local_socket);
         let (mut quic_send, mut quic_recv) = stream.split();
 
         // Task to read from local TLS and send to QUIC stream
@@ -2003,6 +2126,24 @@ impl TunnelConnection {
         debug!("TLS stream handler finished (stream {})", stream_id);
     }
 
+    /// Open a TLS connection to the local service for relay-terminated tunnels
+    ///
+    /// Local services usually run with self-signed certificates, so the
+    /// certificate is not verified; the relay already authenticated itself to
+    /// the public client.
+    async fn reencrypt_local(
+        socket: TcpStream,
+        sni: &str,
+        local_host: &str,
+    ) -> std::io::Result<tokio_rustls::client::TlsStream<TcpStream>> {
+        let connector = crate::transport_discovery::build_insecure_tls_connector()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        let server_name = rustls::pki_types::ServerName::try_from(sni.to_string())
+            .or_else(|_| rustls::pki_types::ServerName::try_from(local_host.to_string()))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        connector.connect(server_name, socket).await
+    }
+
     async fn handle_http_request_static(
         config: &TunnelConfig,
         metrics: &MetricsStore,
@@ -2626,12 +2767,18 @@ mod tests {
             local_port: 443,
             sni_hostnames: vec!["api.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let protocols2 = vec![ProtocolConfig::Tls {
             local_port: 443,
             sni_hostnames: vec!["web.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let id1 = generate_localup_id_from_token_and_protocols(token, &protocols1);
@@ -2650,11 +2797,13 @@ mod tests {
         let protocols1 = vec![ProtocolConfig::Tcp {
             local_port: 8080,
             remote_port: Some(10000),
+            hostname: None,
         }];
 
         let protocols2 = vec![ProtocolConfig::Tcp {
             local_port: 8080,
             remote_port: Some(10001),
+            hostname: None,
         }];
 
         let id1 = generate_localup_id_from_token_and_protocols(token, &protocols1);
@@ -2714,12 +2863,18 @@ mod tests {
             local_port: 443,
             sni_hostnames: vec!["api.example.com".to_string(), "web.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let protocols2 = vec![ProtocolConfig::Tls {
             local_port: 443,
             sni_hostnames: vec!["api.example.com".to_string(), "web.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let id1 = generate_localup_id_from_token_and_protocols(token, &protocols1);
@@ -2735,6 +2890,9 @@ mod tests {
             local_port: 443,
             sni_hostnames: vec!["web.example.com".to_string(), "api.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let id3 = generate_localup_id_from_token_and_protocols(token, &protocols3);
@@ -2779,12 +2937,18 @@ mod tests {
             local_port: 443,
             sni_hostnames: vec!["api.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let protocols2 = vec![ProtocolConfig::Tls {
             local_port: 8443,
             sni_hostnames: vec!["api.example.com".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let id1 = generate_localup_id_from_token_and_protocols(token, &protocols1);
@@ -2805,12 +2969,18 @@ mod tests {
             local_port: 443,
             sni_hostnames: vec!["api.example.com".to_string()],
             http_port: Some(8080),
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let protocols2 = vec![ProtocolConfig::Tls {
             local_port: 443,
             sni_hostnames: vec!["api.example.com".to_string()],
             http_port: Some(9090),
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }];
 
         let id1 = generate_localup_id_from_token_and_protocols(token, &protocols1);
@@ -2830,11 +3000,13 @@ mod tests {
         let protocols1 = vec![ProtocolConfig::Tcp {
             local_port: 8080,
             remote_port: Some(10000),
+            hostname: None,
         }];
 
         let protocols2 = vec![ProtocolConfig::Tcp {
             local_port: 9090,
             remote_port: Some(10000),
+            hostname: None,
         }];
 
         let id1 = generate_localup_id_from_token_and_protocols(token, &protocols1);
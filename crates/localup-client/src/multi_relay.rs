@@ -0,0 +1,659 @@
+//! Active-active tunnels over several relays
+//!
+//! With [`ExitNodeConfig::MultiRegion`], a tunnel registers on one relay per
+//! region under the same subdomain (or TCP port), so DNS-level or anycast
+//! failover can move traffic between relays. Each relay connection is
+//! supervised on its own and reconnects with backoff; all of them record into
+//! one [`MetricsStore`].
+
+use crate::client::TunnelError;
+use crate::config::{ProtocolConfig, TunnelConfig};
+use crate::localup::{relay_protocol, TunnelConnection, TunnelConnector};
+use crate::metrics::MetricsStore;
+use crate::relay_discovery::{RelayDiscovery, DEFAULT_PROBE_TIMEOUT};
+use futures::future::join_all;
+use localup_proto::{Endpoint, ExitNodeConfig, Protocol, Region};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// Connection state of one relay
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RelayStatus {
+    /// First connection attempt in progress
+    Connecting,
+    /// Registered and serving traffic
+    Connected,
+    /// Waiting to reconnect after losing the relay or failing to connect
+    Reconnecting { attempt: u32, error: String },
+    /// Rejected for good (e.g. authentication failed); not retried
+    Failed { error: String },
+    /// Disconnected on request, or, for a single-relay tunnel (which does not
+    /// reconnect by itself), after losing the relay
+    Closed,
+}
+
+/// A tunnel's public endpoints on one relay
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayEndpoints {
+    /// Relay address
+    pub relay: String,
+    /// Region the relay was selected for
+    pub region: Option<Region>,
+    pub status: RelayStatus,
+    /// Endpoints of the relay's last successful registration
+    pub endpoints: Vec<Endpoint>,
+}
+
+type SharedRelay = Arc<RwLock<RelayEndpoints>>;
+
+/// Tunnel registered on several relays at once
+pub(crate) struct MultiRelayTunnel {
+    localup_id: String,
+    /// Endpoints on the first relay that accepted the tunnel
+    primary_endpoints: Vec<Endpoint>,
+    relays: Vec<SharedRelay>,
+    metrics: MetricsStore,
+    shutdown: Arc<watch::Sender<bool>>,
+    supervisors: Vec<JoinHandle<()>>,
+}
+
+impl MultiRelayTunnel {
+    /// Register the tunnel on the best relay of each region
+    pub(crate) async fn connect(
+        config: TunnelConfig,
+        regions: &[Region],
+    ) -> Result<Self, TunnelError> {
+        let relays = resolve_relays(&config, regions).await?;
+        Self::connect_to(config, relays).await
+    }
+
+    /// Register the tunnel on each of `relays`
+    ///
+    /// The first relay to accept the tunnel decides the subdomain and TCP port
+    /// the others are asked for. Fails if no relay accepts the tunnel, or if
+    /// any relay rejects it for good or refuses the pinned name; relays that
+    /// are only unreachable keep retrying in the background.
+    pub(crate) async fn connect_to(
+        config: TunnelConfig,
+        relays: Vec<(Option<Region>, String)>,
+    ) -> Result<Self, TunnelError> {
+        let metrics = MetricsStore::default();
+        let relay_config = |address: &str, config: &TunnelConfig| {
+            let mut config = config.clone();
+            config.exit_node = ExitNodeConfig::Custom(address.to_string());
+            config
+        };
+        let connector = |address: &str, config: &TunnelConfig| {
+            TunnelConnector::new(relay_config(address, config)).with_metrics(metrics.clone())
+        };
+
+        let mut outcomes: Vec<Option<Result<TunnelConnection, TunnelError>>> =
+            relays.iter().map(|_| None).collect();
+        let mut primary = None;
+        let mut last_error = None;
+        for (i, (_, address)) in relays.iter().enumerate() {
+            match connector(address, &config).connect().await {
+                Ok(connection) => {
+                    primary = Some((i, connection));
+                    break;
+                }
+                Err(e) if e.is_non_recoverable() => return Err(e),
+                Err(e) => {
+                    warn!("Relay {} unavailable: {}", address, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        let Some((primary_index, primary)) = primary else {
+            return Err(last_error.unwrap_or_else(|| {
+                TunnelError::ConnectionError("No relays to connect to".to_string())
+            }));
+        };
+
+        let pinned = pin_to_endpoints(&config, primary.endpoints());
+        let localup_id = primary.localup_id().to_string();
+        let primary_endpoints = primary.endpoints().to_vec();
+        outcomes[primary_index] = Some(Ok(primary));
+
+        // Register on the remaining relays concurrently, retrying earlier failures
+        let pending: Vec<usize> = (0..relays.len()).filter(|&i| i != primary_index).collect();
+        let results = join_all(
+            pending
+                .iter()
+                .map(|&i| connector(&relays[i].1, &pinned).connect()),
+        )
+        .await;
+        for (i, result) in pending.into_iter().zip(results) {
+            match result {
+                Err(e) if rejects_pinned(&e) => {
+                    // Dropping the accepted connections unregisters the tunnel
+                    error!("❌ Relay {} rejected the tunnel: {}", relays[i].1, e);
+                    return Err(e);
+                }
+                result => outcomes[i] = Some(result),
+            }
+        }
+
+        let (shutdown, _) = watch::channel(false);
+        let shutdown = Arc::new(shutdown);
+        let mut shared = Vec::with_capacity(relays.len());
+        let mut supervisors = Vec::with_capacity(relays.len());
+        for ((region, address), outcome) in relays.into_iter().zip(outcomes) {
+            let outcome = outcome.expect("every relay was attempted");
+            // Report accepted registrations right away rather than once the
+            // supervisor has started
+            let (status, endpoints) = match &outcome {
+                Ok(connection) => (RelayStatus::Connected, connection.endpoints().to_vec()),
+                Err(_) => (RelayStatus::Connecting, Vec::new()),
+            };
+            let relay = Arc::new(RwLock::new(RelayEndpoints {
+                relay: address.clone(),
+                region,
+                status,
+                endpoints,
+            }));
+            shared.push(relay.clone());
+            supervisors.push(tokio::spawn(supervise(
+                relay_config(&address, &pinned),
+                metrics.clone(),
+                outcome,
+                relay,
+                shutdown.subscribe(),
+            )));
+        }
+
+        Ok(Self {
+            localup_id,
+            primary_endpoints,
+            relays: shared,
+            metrics,
+            shutdown,
+            supervisors,
+        })
+    }
+
+    pub(crate) fn localup_id(&self) -> &str {
+        &self.localup_id
+    }
+
+    pub(crate) fn public_url(&self) -> Option<&str> {
+        self.primary_endpoints
+            .first()
+            .map(|e| e.public_url.as_str())
+    }
+
+    pub(crate) fn endpoints(&self) -> Vec<RelayEndpoints> {
+        self.relays
+            .iter()
+            .map(|relay| relay.read().unwrap().clone())
+            .collect()
+    }
+
+    pub(crate) fn metrics(&self) -> &MetricsStore {
+        &self.metrics
+    }
+
+    /// Handle that disconnects every relay
+    pub(crate) fn shutdown_handle(&self) -> Arc<watch::Sender<bool>> {
+        self.shutdown.clone()
+    }
+
+    pub(crate) fn disconnect(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Wait until every relay connection is closed or has failed for good
+    pub(crate) async fn wait(self) -> Result<(), TunnelError> {
+        for supervisor in self.supervisors {
+            if let Err(e) = supervisor.await {
+                error!("Relay supervisor panicked: {}", e);
+            }
+        }
+
+        let failures: Vec<String> = self
+            .relays
+            .iter()
+            .filter_map(|relay| match &relay.read().unwrap().status {
+                RelayStatus::Failed { error } => Some(error.clone()),
+                _ => None,
+            })
+            .collect();
+        if failures.len() == self.relays.len() {
+            return Err(TunnelError::ConnectionError(failures.join("; ")));
+        }
+        Ok(())
+    }
+}
+
+/// Pick the relay for each region
+///
+/// Regions without an active relay are skipped, and a relay chosen for
+/// several regions is used once.
+async fn resolve_relays(
+    config: &TunnelConfig,
+    regions: &[Region],
+) -> Result<Vec<(Option<Region>, String)>, TunnelError> {
+    let discovery = RelayDiscovery::load(config.relay_directory.as_ref())
+        .await
+        .map_err(|e| {
+            TunnelError::ConnectionError(format!("Failed to initialize relay discovery: {}", e))
+        })?;
+    let protocol = relay_protocol(config)?;
+
+    let mut seen = HashSet::new();
+    let mut relays = Vec::new();
+    for region in regions {
+        if discovery.relays_by_region(region.as_str()).is_empty() {
+            warn!("No active relay in region {}, skipping it", region.as_str());
+            continue;
+        }
+        let address = discovery
+            .select_relay_probed(
+                protocol,
+                Some(region.as_str()),
+                None,
+                config.preferred_transport,
+                DEFAULT_PROBE_TIMEOUT,
+            )
+            .await
+            .map_err(|e| TunnelError::ConnectionError(format!("Failed to select relay: {}", e)))?;
+        if seen.insert(address.clone()) {
+            info!("Selected relay {} for region {}", address, region.as_str());
+            relays.push((Some(*region), address));
+        }
+    }
+
+    if relays.is_empty() {
+        return Err(TunnelError::ConnectionError(format!(
+            "No relays available in regions: {}",
+            regions
+                .iter()
+                .map(|r| r.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    Ok(relays)
+}
+
+/// Ask for the subdomain and TCP port the relay assigned, so every relay
+/// serves the tunnel under the same name
+fn pin_to_endpoints(config: &TunnelConfig, endpoints: &[Endpoint]) -> TunnelConfig {
+    let assigned_subdomain = endpoints.iter().find_map(|e| match &e.protocol {
+        Protocol::Http { subdomain, .. } | Protocol::Https { subdomain, .. } => subdomain.clone(),
+        _ => None,
+    });
+    let assigned_port = endpoints.iter().find_map(|e| match e.protocol {
+        Protocol::Tcp { .. } => e.port,
+        _ => None,
+    });
+
+    let mut config = config.clone();
+    for protocol in &mut config.protocols {
+        match protocol {
+            ProtocolConfig::Http {
+                subdomain: subdomain @ None,
+                custom_domain: None,
+                ..
+            }
+            | ProtocolConfig::Https {
+                subdomain: subdomain @ None,
+                custom_domain: None,
+                ..
+            } => *subdomain = assigned_subdomain.clone(),
+            ProtocolConfig::Tcp {
+                remote_port: remote_port @ None,
+                hostname: None,
+                ..
+            } => *remote_port = assigned_port,
+            _ => {}
+        }
+    }
+    config
+}
+
+/// Whether `error` means a relay will not serve the tunnel under the pinned
+/// subdomain or port, so retrying would leave the relays out of step
+fn rejects_pinned(error: &TunnelError) -> bool {
+    match error {
+        TunnelError::ConnectionError(reason) => {
+            reason.contains("Subdomain is already in use")
+                || reason.contains("Route already exists")
+                || reason.contains("is not available")
+        }
+        error => error.is_non_recoverable(),
+    }
+}
+
+/// Resolves once shutdown is requested
+async fn stopped(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+/// Keep one relay connected until shutdown
+async fn supervise(
+    config: TunnelConfig,
+    metrics: MetricsStore,
+    first_attempt: Result<TunnelConnection, TunnelError>,
+    relay: SharedRelay,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let address = relay.read().unwrap().relay.clone();
+    let set_status = |status: RelayStatus| relay.write().unwrap().status = status;
+    let mut next = Some(first_attempt);
+    let mut attempt = 0u32;
+
+    loop {
+        let result = match next.take() {
+            Some(result) => result,
+            None => {
+                tokio::select! {
+                    result = TunnelConnector::new(config.clone())
+                        .with_metrics(metrics.clone())
+                        .connect() => result,
+                    _ = stopped(&mut shutdown) => break,
+                }
+            }
+        };
+
+        let error = match result {
+            Ok(connection) => {
+                attempt = 0;
+                {
+                    let mut relay = relay.write().unwrap();
+                    relay.status = RelayStatus::Connected;
+                    relay.endpoints = connection.endpoints().to_vec();
+                }
+                info!("✅ Tunnel registered on relay {}", address);
+
+                let handle = connection.clone();
+                let mut run = tokio::spawn(connection.run());
+                tokio::select! {
+                    result = &mut run => match result {
+                        Ok(Ok(())) => "Tunnel closed".to_string(),
+                        Ok(Err(e)) => e.to_string(),
+                        Err(e) => format!("Tunnel task panicked: {}", e),
+                    },
+                    _ = stopped(&mut shutdown) => {
+                        if let Err(e) = handle.disconnect().await {
+                            warn!("Failed to disconnect from relay {}: {}", address, e);
+                        }
+                        let _ = tokio::time::timeout(Duration::from_secs(5), run).await;
+                        break;
+                    }
+                }
+            }
+            Err(e) if e.is_non_recoverable() => {
+                error!("❌ Relay {} rejected the tunnel: {}", address, e);
+                set_status(RelayStatus::Failed {
+                    error: e.to_string(),
+                });
+                return;
+            }
+            Err(e) => e.to_string(),
+        };
+
+        if *shutdown.borrow() {
+            break;
+        }
+        attempt += 1;
+        warn!(
+            "🔄 Relay {} lost ({}), reconnecting (attempt {})",
+            address, error, attempt
+        );
+        set_status(RelayStatus::Reconnecting { attempt, error });
+
+        // Exponential backoff: 1s, 2s, 4s, 8s, 16s, max 30s
+        let backoff = Duration::from_secs(std::cmp::min(2u64.pow((attempt - 1).min(5)), 30));
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = stopped(&mut shutdown) => break,
+        }
+    }
+
+    set_status(RelayStatus::Closed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use localup_proto::TunnelMessage;
+    use localup_transport::{TransportConnection, TransportListener, TransportStream};
+    use localup_transport_quic::{QuicConfig, QuicListener};
+    use std::net::SocketAddr;
+    use tokio::sync::mpsc;
+
+    /// Relay accepting one tunnel, reporting the subdomain it was asked for
+    ///
+    /// Assigns `assigned` when no subdomain is requested, and drops the
+    /// connection when `drop_rx` fires.
+    fn fake_relay(
+        assigned: &'static str,
+    ) -> (SocketAddr, mpsc::Receiver<Option<String>>, mpsc::Sender<()>) {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let config = Arc::new(QuicConfig::server_self_signed().unwrap());
+        let listener = QuicListener::new("127.0.0.1:0".parse().unwrap(), config).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (requested_tx, requested_rx) = mpsc::channel(1);
+        let (drop_tx, mut drop_rx) = mpsc::channel::<()>(1);
+
+        tokio::spawn(async move {
+            let (connection, _) = listener.accept().await.unwrap();
+            let mut control = connection.accept_stream().await.unwrap().unwrap();
+            let Some(TunnelMessage::Connect {
+                localup_id,
+                protocols,
+                ..
+            }) = control.recv_message().await.unwrap()
+            else {
+                panic!("expected Connect");
+            };
+            let requested = match &protocols[0] {
+                Protocol::Http { subdomain, .. } => subdomain.clone(),
+                other => panic!("unexpected protocol {:?}", other),
+            };
+            let subdomain = requested.clone().unwrap_or_else(|| assigned.to_string());
+            requested_tx.send(requested).await.unwrap();
+            control
+                .send_message(&TunnelMessage::Connected {
+                    localup_id: localup_id.clone(),
+                    endpoints: vec![Endpoint {
+                        protocol: Protocol::Http {
+                            subdomain: Some(subdomain.clone()),
+                            custom_domain: None,
+                        },
+                        public_url: format!("https://{}.localup.test", subdomain),
+                        port: Some(443),
+                    }],
+                })
+                .await
+                .unwrap();
+
+            tokio::select! {
+                Ok(Some(TunnelMessage::Disconnect { .. })) = control.recv_message() => {
+                    let _ = control
+                        .send_message(&TunnelMessage::DisconnectAck { localup_id })
+                        .await;
+                }
+                _ = drop_rx.recv() => {}
+            }
+            connection.close(0, "relay stopped").await;
+            // Refuse reconnects by dropping the listener
+            drop(listener);
+        });
+
+        (addr, requested_rx, drop_tx)
+    }
+
+    /// Relay refusing every tunnel with `reason`
+    fn rejecting_relay(reason: &'static str) -> SocketAddr {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let config = Arc::new(QuicConfig::server_self_signed().unwrap());
+        let listener = QuicListener::new("127.0.0.1:0".parse().unwrap(), config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (connection, _) = listener.accept().await.unwrap();
+            let mut control = connection.accept_stream().await.unwrap().unwrap();
+            control.recv_message().await.unwrap();
+            control
+                .send_message(&TunnelMessage::Disconnect {
+                    reason: reason.to_string(),
+                })
+                .await
+                .unwrap();
+            // Give the client time to read the reason before closing
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        });
+
+        addr
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tunnel_registers_same_subdomain_on_every_relay() {
+        let (addr_a, mut requested_a, _drop_a) = fake_relay("happy-fox");
+        let (addr_b, mut requested_b, drop_b) = fake_relay("other-name");
+
+        let config = TunnelConfig::builder()
+            .protocol(ProtocolConfig::Http {
+                local_port: 3000,
+                subdomain: None,
+                custom_domain: None,
+            })
+            .auth_token("test-token".to_string())
+            .build()
+            .unwrap();
+        let tunnel = MultiRelayTunnel::connect_to(
+            config,
+            vec![
+                (Some(Region::EuWest), addr_a.to_string()),
+                (Some(Region::UsEast), addr_b.to_string()),
+            ],
+        )
+        .await
+        .unwrap();
+
+        // The second relay is asked for the subdomain the first one assigned
+        assert_eq!(requested_a.recv().await.unwrap(), None);
+        assert_eq!(
+            requested_b.recv().await.unwrap().as_deref(),
+            Some("happy-fox")
+        );
+        assert_eq!(tunnel.public_url(), Some("https://happy-fox.localup.test"));
+
+        // Both relays come up independently
+        let wait_for = |status: fn(&RelayStatus) -> bool, index: usize| {
+            let relay = tunnel.relays[index].clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    while !status(&relay.read().unwrap().status) {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                    }
+                })
+                .await
+                .expect("relay status did not change")
+            }
+        };
+        wait_for(|s| *s == RelayStatus::Connected, 0).await;
+        wait_for(|s| *s == RelayStatus::Connected, 1).await;
+        let endpoints = tunnel.endpoints();
+        assert_eq!(endpoints[1].region, Some(Region::UsEast));
+        assert_eq!(
+            endpoints[1].endpoints[0].public_url,
+            "https://happy-fox.localup.test"
+        );
+
+        // Losing one relay leaves the other connected
+        drop_b.send(()).await.unwrap();
+        wait_for(|s| matches!(s, RelayStatus::Reconnecting { .. }), 1).await;
+        assert_eq!(tunnel.endpoints()[0].status, RelayStatus::Connected);
+
+        tunnel.disconnect();
+        let relays = tunnel.relays.clone();
+        tokio::time::timeout(Duration::from_secs(10), tunnel.wait())
+            .await
+            .unwrap()
+            .unwrap();
+        for relay in relays {
+            assert_eq!(relay.read().unwrap().status, RelayStatus::Closed);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rejected_pinned_subdomain_fails_connect() {
+        let (addr_a, _requested_a, _drop_a) = fake_relay("happy-fox");
+        let addr_b = rejecting_relay("Subdomain is already in use by another tunnel");
+
+        let config = TunnelConfig::builder()
+            .protocol(ProtocolConfig::Http {
+                local_port: 3000,
+                subdomain: None,
+                custom_domain: None,
+            })
+            .auth_token("test-token".to_string())
+            .build()
+            .unwrap();
+        let result = MultiRelayTunnel::connect_to(
+            config,
+            vec![(None, addr_a.to_string()), (None, addr_b.to_string())],
+        )
+        .await;
+
+        match result {
+            Err(TunnelError::ConnectionError(reason)) => {
+                assert!(reason.contains("already in use"), "{}", reason)
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("connect succeeded"),
+        }
+    }
+
+    #[test]
+    fn test_pin_to_endpoints() {
+        let config = TunnelConfig::builder()
+            .protocol(ProtocolConfig::Tcp {
+                local_port: 5432,
+                remote_port: None,
+                hostname: None,
+            })
+            .auth_token("test-token".to_string())
+            .build()
+            .unwrap();
+        let endpoints = vec![Endpoint {
+            protocol: Protocol::Tcp { port: 0 },
+            public_url: "tcp://relay.test:31000".to_string(),
+            port: Some(31000),
+        }];
+
+        let pinned = pin_to_endpoints(&config, &endpoints);
+        assert!(matches!(
+            pinned.protocols[0],
+            ProtocolConfig::Tcp {
+                remote_port: Some(31000),
+                ..
+            }
+        ));
+
+        // Explicit choices are kept
+        let custom = TunnelConfig::builder()
+            .protocol(ProtocolConfig::Https {
+                local_port: 3000,
+                subdomain: None,
+                custom_domain: Some("api.example.com".to_string()),
+            })
+            .auth_token("test-token".to_string())
+            .build()
+            .unwrap();
+        let pinned = pin_to_endpoints(&custom, &[]);
+        assert!(matches!(
+            &pinned.protocols[0],
+            ProtocolConfig::Https { subdomain: None, custom_domain: Some(d), .. } if d == "api.example.com"
+        ));
+    }
+}
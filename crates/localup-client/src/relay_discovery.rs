@@ -2,10 +2,37 @@
 //!
 //! This module handles discovering available relay servers and selecting
 //! the best one based on region, protocol, and availability.
-
+//!
+//! The relay directory is embedded at compile time. A live directory can be
+//! loaded at runtime from a URL or file (see [`RelayDirectoryConfig`]), such as
+//! the signed one served by the exit node orchestrator at `/relays.jws`. The
+//! last directory loaded is cached on disk and used while the source is
+//! unreachable; the embedded directory is the last resort.
+//!
+//! [`RelayDiscovery::select_relay_probed`] measures the latency of candidate
+//! relays (QUIC handshake, or TCP connect for WebSocket and HTTP/2) and
+//! ranks them by latency and load.
+
+use futures::future::join_all;
+use localup_auth::{decode_signed, JwksFile, VerificationKey};
+use localup_proto::relay_directory::SignedRelayDirectory;
+use localup_proto::TransportProtocol;
+use localup_transport::{TransportConnection, TransportConnector};
+use localup_transport_quic::{QuicConfig, QuicConnector};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use tracing::{debug, info, warn};
+
+pub use localup_proto::relay_directory::{
+    GlobalConfig, Location, RegionGroup, RelayConfig, RelayEndpoint, RelayInfo, RelayLoad,
+    SelectionPolicy,
+};
+
+/// How long to wait for a relay to answer a latency probe
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Relay configuration embedded at compile time
 /// The path is determined by the LOCALUP_RELAYS_CONFIG environment variable at build time,
@@ -25,75 +52,195 @@ pub enum RelayError {
 
     #[error("Invalid protocol: {0}")]
     InvalidProtocol(String),
-}
 
-/// Root relay configuration
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct RelayConfig {
-    pub version: u32,
-    pub config: GlobalConfig,
-    pub relays: Vec<RelayInfo>,
-    pub region_groups: Vec<RegionGroup>,
-    pub selection_policies: HashMap<String, SelectionPolicy>,
+    #[error("Failed to fetch relay directory: {0}")]
+    FetchError(String),
+
+    #[error("Relay directory signature rejected: {0}")]
+    SignatureError(String),
 }
 
-/// Global configuration
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct GlobalConfig {
-    pub default_protocol: String,
-    pub connection_timeout: u64,
-    pub health_check_interval: u64,
+/// Where to load a live relay directory from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayDirectoryConfig {
+    /// URL (http:// or https://) or file path of the directory
+    pub source: String,
+    /// JWKS file with the keys the directory must be signed with
+    ///
+    /// Without one, only an unsigned YAML or JSON directory is accepted.
+    #[serde(default)]
+    pub jwks_file: Option<PathBuf>,
+    /// Where the last directory loaded is kept for when the source is unreachable
+    #[serde(default)]
+    pub cache_file: Option<PathBuf>,
 }
 
-/// Relay server definition
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct RelayInfo {
-    pub id: String,
-    pub name: String,
-    pub region: String,
-    pub location: Location,
-    pub endpoints: Vec<RelayEndpoint>,
-    pub status: String,
-    pub tags: Vec<String>,
+impl RelayDirectoryConfig {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            jwks_file: None,
+            cache_file: None,
+        }
+    }
+
+    /// Require the directory to be signed by a key in this JWKS file
+    pub fn with_jwks_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.jwks_file = Some(path.into());
+        self
+    }
+
+    /// Cache the directory in this file
+    pub fn with_cache_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_file = Some(path.into());
+        self
+    }
+
+    fn keys(&self) -> Result<Option<Arc<Vec<VerificationKey>>>, RelayError> {
+        self.jwks_file
+            .as_ref()
+            .map(|path| {
+                JwksFile::load(path)
+                    .map(|file| file.keys())
+                    .map_err(|e| RelayError::SignatureError(e.to_string()))
+            })
+            .transpose()
+    }
+
+    async fn read_source(&self) -> Result<String, RelayError> {
+        if self.source.starts_with("http://") || self.source.starts_with("https://") {
+            let response = reqwest::get(&self.source)
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| RelayError::FetchError(e.to_string()))?;
+            response
+                .text()
+                .await
+                .map_err(|e| RelayError::FetchError(e.to_string()))
+        } else {
+            tokio::fs::read_to_string(&self.source)
+                .await
+                .map_err(|e| RelayError::FetchError(format!("{}: {}", self.source, e)))
+        }
+    }
 }
 
-/// Geographic location
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Location {
-    pub city: String,
-    pub state: String,
-    pub country: String,
-    pub continent: String,
+/// Parse a relay directory, verifying its signature when `keys` are given
+///
+/// A signed directory is a compact JWS whose payload is a [`SignedRelayDirectory`];
+/// an unsigned one is the YAML (or JSON) [`RelayConfig`].
+fn parse_directory(
+    body: &str,
+    keys: Option<&[VerificationKey]>,
+) -> Result<RelayConfig, RelayError> {
+    let body = body.trim();
+    let is_jws = body.split('.').count() == 3 && !body.contains(char::is_whitespace);
+
+    match (keys, is_jws) {
+        (Some(keys), true) => decode_signed::<SignedRelayDirectory>(body, keys)
+            .map(|signed| signed.directory)
+            .map_err(|e| RelayError::SignatureError(e.to_string())),
+        (Some(_), false) => Err(RelayError::SignatureError(
+            "directory is not signed".to_string(),
+        )),
+        (None, true) => Err(RelayError::SignatureError(
+            "directory is signed but no JWKS file is configured".to_string(),
+        )),
+        (None, false) => Ok(serde_yaml::from_str(body)?),
+    }
 }
 
-/// Relay endpoint
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct RelayEndpoint {
-    pub protocol: String,
-    pub address: String,
-    pub capacity: u32,
-    pub priority: u32,
+/// Measure how long a relay takes to accept a connection
+///
+/// QUIC relays are probed with a QUIC handshake; WebSocket and HTTP/2 relays
+/// with a TCP connect. Without a preferred transport, a failed QUIC handshake
+/// falls back to a TCP connect. Returns `None` if the relay is unreachable.
+pub async fn probe_relay(
+    address: &str,
+    transport: Option<TransportProtocol>,
+    timeout: Duration,
+) -> Option<Duration> {
+    let host = address
+        .rsplit_once(':')
+        .map(|(host, _)| host)
+        .unwrap_or(address);
+    let addrs: Vec<std::net::SocketAddr> =
+        tokio::time::timeout(timeout, tokio::net::lookup_host(address))
+            .await
+            .ok()?
+            .ok()?
+            .collect();
+    let addr = addrs
+        .iter()
+        .find(|addr| addr.is_ipv4())
+        .or_else(|| addrs.first())
+        .copied()?;
+
+    if matches!(transport, None | Some(TransportProtocol::Quic)) {
+        if let Some(rtt) = probe_quic(addr, host, timeout).await {
+            return Some(rtt);
+        }
+        if transport.is_some() {
+            return None;
+        }
+    }
+
+    let started = Instant::now();
+    match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(addr)).await {
+        Ok(Ok(_)) => Some(started.elapsed()),
+        _ => None,
+    }
 }
 
-/// Region group for fallback
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct RegionGroup {
-    pub name: String,
-    pub regions: Vec<String>,
-    pub fallback_order: Vec<String>,
+async fn probe_quic(addr: std::net::SocketAddr, host: &str, timeout: Duration) -> Option<Duration> {
+    let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure())).ok()?;
+    let started = Instant::now();
+    let connection = tokio::time::timeout(timeout, connector.connect(addr, host))
+        .await
+        .ok()?
+        .ok()?;
+    let rtt = started.elapsed();
+    connection.close(0, "probe").await;
+    Some(rtt)
 }
 
-/// Relay selection policy
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct SelectionPolicy {
-    pub prefer_same_region: bool,
-    pub fallback_to_nearest: bool,
-    pub consider_capacity: bool,
-    pub only_active: bool,
-    #[serde(default)]
-    pub include_tags: Vec<String>,
-    #[serde(default)]
-    pub exclude_tags: Vec<String>,
+/// Order probed candidates, best first
+///
+/// Reachable relays come first, by latency weighted with their load when the
+/// policy considers capacity: a relay at half capacity counts as 1.5 times
+/// slower. Ties and unreachable relays keep their order (priority, then
+/// capacity).
+fn rank_by_latency<'a>(
+    probed: Vec<(&'a RelayInfo, Option<Duration>)>,
+    protocol: &str,
+    consider_capacity: bool,
+) -> Vec<&'a RelayInfo> {
+    let score = |relay: &RelayInfo, rtt: Duration| {
+        let mut score = rtt.as_secs_f64();
+        if consider_capacity {
+            let capacity = relay
+                .endpoints
+                .iter()
+                .find(|e| e.protocol == protocol)
+                .map(|e| e.capacity)
+                .unwrap_or(0);
+            if let (Some(load), true) = (relay.load, capacity > 0) {
+                score *= 1.0 + (load.tunnels as f64 / capacity as f64).min(1.0);
+            }
+        }
+        score
+    };
+
+    let (mut reachable, unreachable): (Vec<_>, Vec<_>) =
+        probed.into_iter().partition(|(_, rtt)| rtt.is_some());
+    reachable.sort_by(|(a, a_rtt), (b, b_rtt)| {
+        score(a, a_rtt.unwrap()).total_cmp(&score(b, b_rtt.unwrap()))
+    });
+    reachable
+        .into_iter()
+        .chain(unreachable)
+        .map(|(relay, _)| relay)
+        .collect()
 }
 
 /// Relay discovery and selection
@@ -108,6 +255,85 @@ impl RelayDiscovery {
         Ok(Self { config })
     }
 
+    /// Discovery over an already loaded directory
+    pub fn from_config(config: RelayConfig) -> Self {
+        Self { config }
+    }
+
+    /// Load the live directory, or the embedded one if none is configured
+    ///
+    /// Falls back to the cached directory, then to the embedded one, when the
+    /// live directory cannot be fetched or verified.
+    pub async fn load(directory: Option<&RelayDirectoryConfig>) -> Result<Self, RelayError> {
+        let Some(directory) = directory else {
+            return Self::new();
+        };
+
+        match Self::fetch(directory).await {
+            Ok(discovery) => return Ok(discovery),
+            Err(e) => warn!("Relay directory {} unavailable: {}", directory.source, e),
+        }
+        match Self::load_cached(directory).await {
+            Ok(Some(discovery)) => {
+                info!("Using cached relay directory");
+                return Ok(discovery);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Cached relay directory unusable: {}", e),
+        }
+        info!("Using the embedded relay directory");
+        Self::new()
+    }
+
+    /// Fetch and verify the live directory, caching it on success
+    pub async fn fetch(directory: &RelayDirectoryConfig) -> Result<Self, RelayError> {
+        let keys = directory.keys()?;
+        let body = directory.read_source().await?;
+        let config = parse_directory(&body, keys.as_deref().map(Vec::as_slice))?;
+        info!(
+            "Loaded {} relay(s) from {}",
+            config.relays.len(),
+            directory.source
+        );
+
+        if let Some(cache) = &directory.cache_file {
+            if let Some(parent) = cache.parent() {
+                let _ = tokio::fs::create_dir_all(parent).await;
+            }
+            if let Err(e) = tokio::fs::write(cache, body.as_bytes()).await {
+                warn!(
+                    "Failed to cache relay directory at {}: {}",
+                    cache.display(),
+                    e
+                );
+            }
+        }
+        Ok(Self { config })
+    }
+
+    /// Read the cached directory, verified like a fetched one
+    ///
+    /// A signed directory past its expiry is rejected.
+    pub async fn load_cached(directory: &RelayDirectoryConfig) -> Result<Option<Self>, RelayError> {
+        let Some(cache) = &directory.cache_file else {
+            return Ok(None);
+        };
+        let body = match tokio::fs::read_to_string(cache).await {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(RelayError::FetchError(format!(
+                    "{}: {}",
+                    cache.display(),
+                    e
+                )))
+            }
+        };
+        let keys = directory.keys()?;
+        let config = parse_directory(&body, keys.as_deref().map(Vec::as_slice))?;
+        Ok(Some(Self { config }))
+    }
+
     /// Get all available relays
     pub fn all_relays(&self) -> &[RelayInfo] {
         &self.config.relays
@@ -131,19 +357,13 @@ impl RelayDiscovery {
             .collect()
     }
 
-    /// Select best relay automatically
-    pub fn select_relay(
+    /// Relays the policy allows for `protocol`, best first by priority and capacity
+    fn candidates(
         &self,
         protocol: &str,
         preferred_region: Option<&str>,
-        policy_name: Option<&str>,
-    ) -> Result<String, RelayError> {
-        let policy = self
-            .config
-            .selection_policies
-            .get(policy_name.unwrap_or("auto"))
-            .ok_or(RelayError::NoMatchingRelay)?;
-
+        policy: &SelectionPolicy,
+    ) -> Vec<&RelayInfo> {
         // Filter relays by policy
         let mut candidates: Vec<&RelayInfo> = self
             .config
@@ -172,10 +392,6 @@ impl RelayDiscovery {
             })
             .collect();
 
-        if candidates.is_empty() {
-            return Err(RelayError::NoMatchingRelay);
-        }
-
         // Prefer same region if specified and policy allows
         if let Some(region) = preferred_region {
             if policy.prefer_same_region {
@@ -210,15 +426,79 @@ impl RelayDiscovery {
             }
         });
 
-        // Get the best relay
-        let best_relay = candidates.first().ok_or(RelayError::NoMatchingRelay)?;
-        let endpoint = best_relay
+        candidates
+    }
+
+    fn policy(&self, policy_name: Option<&str>) -> Result<&SelectionPolicy, RelayError> {
+        self.config
+            .selection_policies
+            .get(policy_name.unwrap_or("auto"))
+            .ok_or(RelayError::NoMatchingRelay)
+    }
+
+    fn endpoint_address(relay: &RelayInfo, protocol: &str) -> Result<String, RelayError> {
+        relay
             .endpoints
             .iter()
             .find(|e| e.protocol == protocol)
-            .ok_or_else(|| RelayError::InvalidProtocol(protocol.to_string()))?;
+            .map(|e| e.address.clone())
+            .ok_or_else(|| RelayError::InvalidProtocol(protocol.to_string()))
+    }
 
-        Ok(endpoint.address.clone())
+    /// Select best relay automatically
+    pub fn select_relay(
+        &self,
+        protocol: &str,
+        preferred_region: Option<&str>,
+        policy_name: Option<&str>,
+    ) -> Result<String, RelayError> {
+        let policy = self.policy(policy_name)?;
+        let candidates = self.candidates(protocol, preferred_region, policy);
+
+        // Get the best relay
+        let best_relay = candidates.first().ok_or(RelayError::NoMatchingRelay)?;
+        Self::endpoint_address(best_relay, protocol)
+    }
+
+    /// Select the best relay by measured latency
+    ///
+    /// Candidates are filtered like in [`select_relay`](Self::select_relay),
+    /// probed concurrently with [`probe_relay`] and ranked by latency and
+    /// load. If no candidate answers the probe, the static choice is kept.
+    pub async fn select_relay_probed(
+        &self,
+        protocol: &str,
+        preferred_region: Option<&str>,
+        policy_name: Option<&str>,
+        transport: Option<TransportProtocol>,
+        timeout: Duration,
+    ) -> Result<String, RelayError> {
+        let policy = self.policy(policy_name)?;
+        let candidates = self.candidates(protocol, preferred_region, policy);
+        match candidates.len() {
+            0 => return Err(RelayError::NoMatchingRelay),
+            1 => return Self::endpoint_address(candidates[0], protocol),
+            _ => {}
+        }
+
+        let rtts = join_all(candidates.iter().map(|relay| async move {
+            let address = Self::endpoint_address(relay, protocol).ok()?;
+            probe_relay(&address, transport, timeout).await
+        }))
+        .await;
+        for (relay, rtt) in candidates.iter().zip(&rtts) {
+            debug!("Relay {} latency: {:?}", relay.id, rtt);
+        }
+        if rtts.iter().all(Option::is_none) {
+            warn!("No relay answered the latency probe, using the configured priority");
+        }
+
+        let ranked = rank_by_latency(
+            candidates.into_iter().zip(rtts).collect(),
+            protocol,
+            policy.consider_capacity,
+        );
+        Self::endpoint_address(ranked[0], protocol)
     }
 
     /// Get default protocol
@@ -367,4 +647,164 @@ mod tests {
         let result = discovery.select_relay("invalid", None, None);
         assert!(result.is_err());
     }
+
+    fn directory(ids: &[&str]) -> RelayConfig {
+        let mut config: RelayConfig = serde_yaml::from_str(RELAY_CONFIG).unwrap();
+        let template = config.relays[0].clone();
+        config.relays = ids
+            .iter()
+            .map(|id| RelayInfo {
+                id: id.to_string(),
+                endpoints: vec![RelayEndpoint {
+                    protocol: "https".to_string(),
+                    address: format!("{}.example.com:4443", id),
+                    capacity: 100,
+                    priority: 1,
+                }],
+                ..template.clone()
+            })
+            .collect();
+        config
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("localup-{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_parse_signed_directory() {
+        let signer = localup_auth::JwtSigner::hmac(b"directory-secret");
+        let keys = vec![signer.verification_key()];
+        let now = chrono::Utc::now().timestamp();
+        let signed = signer
+            .sign(&SignedRelayDirectory {
+                directory: directory(&["relay-a"]),
+                iat: now,
+                exp: now + 3600,
+            })
+            .unwrap();
+
+        let config = parse_directory(&signed, Some(&keys)).unwrap();
+        assert_eq!(config.relays[0].id, "relay-a");
+
+        // Signatures are required once keys are configured, and checked
+        assert!(parse_directory(RELAY_CONFIG, Some(&keys)).is_err());
+        assert!(parse_directory(&signed, None).is_err());
+        let other = localup_auth::JwtSigner::hmac(b"other-secret");
+        assert!(parse_directory(&signed, Some(&[other.verification_key()])).is_err());
+        let expired = signer
+            .sign(&SignedRelayDirectory {
+                directory: directory(&["relay-a"]),
+                iat: now - 7200,
+                exp: now - 3600,
+            })
+            .unwrap();
+        assert!(parse_directory(&expired, Some(&keys)).is_err());
+
+        // Unsigned directories are YAML or JSON
+        let json = serde_json::to_string(&directory(&["relay-b"])).unwrap();
+        assert_eq!(
+            parse_directory(&json, None).unwrap().relays[0].id,
+            "relay-b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_falls_back_to_cache_then_embedded() {
+        let source = temp_path("relays.json");
+        let cache = temp_path("relay-cache");
+        std::fs::write(
+            &source,
+            serde_json::to_string(&directory(&["relay-a", "relay-b"])).unwrap(),
+        )
+        .unwrap();
+        let config =
+            RelayDirectoryConfig::new(source.display().to_string()).with_cache_file(&cache);
+
+        let discovery = RelayDiscovery::load(Some(&config)).await.unwrap();
+        assert_eq!(discovery.all_relays().len(), 2);
+        assert!(cache.exists());
+
+        // The source is gone: the cached copy is used
+        std::fs::remove_file(&source).unwrap();
+        let discovery = RelayDiscovery::load(Some(&config)).await.unwrap();
+        assert_eq!(discovery.all_relays()[1].id, "relay-b");
+
+        // Neither is available: the embedded directory is used
+        std::fs::remove_file(&cache).unwrap();
+        let discovery = RelayDiscovery::load(Some(&config)).await.unwrap();
+        assert_eq!(discovery.all_relays()[0].id, "eu-west-1");
+    }
+
+    #[test]
+    fn test_rank_by_latency() {
+        let mut config = directory(&["slow", "fast", "busy", "down"]);
+        config.relays[2].load = Some(RelayLoad {
+            tunnels: 100,
+            agents: 0,
+        });
+        let [slow, fast, busy, down] = [0, 1, 2, 3].map(|i| &config.relays[i]);
+        let probed = || {
+            vec![
+                (down, None),
+                (slow, Some(Duration::from_millis(80))),
+                (busy, Some(Duration::from_millis(30))),
+                (fast, Some(Duration::from_millis(20))),
+            ]
+        };
+
+        let ids = |ranked: Vec<&RelayInfo>| ranked.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+        // A full relay counts as twice as slow
+        assert_eq!(
+            ids(rank_by_latency(probed(), "https", true)),
+            vec!["fast", "busy", "slow", "down"]
+        );
+        assert_eq!(
+            ids(rank_by_latency(
+                vec![
+                    (slow, Some(Duration::from_millis(50))),
+                    (busy, Some(Duration::from_millis(30))),
+                ],
+                "https",
+                true
+            )),
+            vec!["slow", "busy"]
+        );
+        assert_eq!(
+            ids(rank_by_latency(probed(), "https", false)),
+            vec!["fast", "busy", "slow", "down"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select_relay_probed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let mut config = directory(&["unreachable", "reachable"]);
+        config.relays[0].endpoints[0].address = format!("127.0.0.1:{}", closed);
+        config.relays[1].endpoints[0].address = format!("127.0.0.1:{}", port);
+        let discovery = RelayDiscovery::from_config(config);
+
+        // Without probing, the first relay wins on order alone
+        assert_eq!(
+            discovery.select_relay("https", None, None).unwrap(),
+            format!("127.0.0.1:{}", closed)
+        );
+        let selected = discovery
+            .select_relay_probed(
+                "https",
+                None,
+                None,
+                Some(TransportProtocol::WebSocket),
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(selected, format!("127.0.0.1:{}", port));
+    }
 }
@@ -412,7 +412,8 @@ fn build_tls_connector() -> Result<tokio_rustls::TlsConnector, TransportDiscover
     )))
 }
 
-fn build_insecure_tls_connector() -> Result<tokio_rustls::TlsConnector, TransportDiscoveryError> {
+pub(crate) fn build_insecure_tls_connector(
+) -> Result<tokio_rustls::TlsConnector, TransportDiscoveryError> {
     ensure_crypto_provider();
 
     let config = rustls::ClientConfig::builder()
@@ -0,0 +1,826 @@
+//! Relay clustering
+//!
+//! Relays sharing a database form a cluster. Each node records itself in
+//! `relay_nodes`, and claims the HTTP hosts and SNI names of the tunnels
+//! connected to it in `cluster_routes`, so a name is served by one node
+//! cluster-wide. A visitor reaching a node that doesn't hold the tunnel is
+//! forwarded over a QUIC link to the node that does, where the public
+//! server it would have reached there (see [`ForwardKind`]) serves it.
+//!
+//! Links are authenticated with a secret shared by all nodes: each side
+//! proves it knows the secret with an HMAC over keying material exported
+//! from the QUIC session, so proofs can't be replayed or relayed.
+//!
+//! Nodes heartbeat into the database, and any node removes nodes that
+//! stopped heartbeating along with their routes, freeing the names for
+//! tunnels reconnecting elsewhere. TCP port and hostname-routed TCP tunnels
+//! are served by the node they connect to only.
+
+use chrono::{Duration as ChronoDuration, Utc};
+use hmac::{Hmac, Mac};
+use localup_relay_db::entities::{
+    cluster_route,
+    prelude::{ClusterRoute, RelayNode},
+    relay_node,
+};
+use localup_router::{extract_parent_wildcard, RouteKey, WildcardPattern};
+use localup_transport::{TransportConnector, TransportListener};
+use localup_transport_quic::{QuicConfig, QuicConnector, QuicListener};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, info, warn};
+
+/// Largest control frame exchanged on a link
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Label for the keying material the link proofs are computed over
+const EXPORTER_LABEL: &[u8] = b"EXPORTER-localup-cluster";
+
+/// How long dialing and authenticating a link may take
+const LINK_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Cluster errors
+#[derive(Debug, Error)]
+pub enum ClusterError {
+    #[error("Database error: {0}")]
+    Database(#[from] DbErr),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Cluster link error: {0}")]
+    Link(String),
+
+    #[error("Cluster link authentication with {0} failed")]
+    Authentication(String),
+
+    #[error("Route {route_key} already exists on relay node {node_id}")]
+    RouteTaken { route_key: String, node_id: String },
+
+    #[error("Cluster node has not been started")]
+    NotStarted,
+}
+
+/// Cluster membership configuration
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    /// Unique name of this node
+    pub node_id: String,
+    /// Address the inter-node QUIC link listens on
+    pub bind_addr: SocketAddr,
+    /// Address other nodes dial; defaults to the bound address
+    pub advertise_addr: Option<SocketAddr>,
+    /// Secret shared by all nodes of the cluster
+    pub secret: String,
+    /// How often the node heartbeats
+    pub heartbeat_interval: Duration,
+    /// How long after its last heartbeat a node is considered dead
+    pub node_timeout: Duration,
+}
+
+impl ClusterConfig {
+    pub fn new(
+        node_id: impl Into<String>,
+        bind_addr: SocketAddr,
+        secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            node_id: node_id.into(),
+            bind_addr,
+            advertise_addr: None,
+            secret: secret.into(),
+            heartbeat_interval: Duration::from_secs(5),
+            node_timeout: Duration::from_secs(15),
+        }
+    }
+
+    pub fn with_advertise_addr(mut self, addr: SocketAddr) -> Self {
+        self.advertise_addr = Some(addr);
+        self
+    }
+
+    pub fn with_heartbeat(mut self, interval: Duration, node_timeout: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self.node_timeout = node_timeout;
+        self
+    }
+}
+
+/// Public server a forwarded visitor is handed to on the owning node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ForwardKind {
+    /// Plain HTTP; the request is forwarded as received
+    Http,
+    /// HTTPS, decrypted by the receiving node (the link itself is encrypted)
+    Https,
+    /// TLS passthrough; the ClientHello is forwarded as received
+    Tls,
+    /// PostgreSQL TLS; the SSLRequest was already answered by the receiving node
+    Postgres,
+}
+
+/// Visitor connection forwarded from another node
+pub type ForwardedStream = tokio::io::Join<quinn::RecvStream, quinn::SendStream>;
+
+/// Serves visitors forwarded from other nodes, given their original address
+pub type ClusterIngress = Arc<
+    dyn Fn(ForwardedStream, SocketAddr) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync,
+>;
+
+/// Node serving a route claimed in the cluster
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterPeer {
+    pub node_id: String,
+    pub cluster_addr: SocketAddr,
+}
+
+/// Messages exchanged on inter-node links
+#[derive(Debug, Serialize, Deserialize)]
+enum LinkMessage {
+    /// First message of the dialing node
+    Hello { node_id: String, proof: Vec<u8> },
+    /// Answer of the dialed node
+    Welcome { node_id: String, proof: Vec<u8> },
+    /// Opens each forwarded visitor stream
+    Forward {
+        kind: ForwardKind,
+        peer_addr: SocketAddr,
+    },
+}
+
+/// Cluster key shared in the database for a route, if routes of its kind are shared
+pub fn cluster_key(key: &RouteKey) -> Option<String> {
+    match key {
+        RouteKey::HttpHost(host) => Some(format!("http:{}", host)),
+        RouteKey::TlsSni(sni) => Some(format!("tls:{}", sni)),
+        RouteKey::TlsSniAlpn(sni, alpn) => Some(format!("tls:{}#{}", sni, alpn)),
+        RouteKey::TcpPort(_) | RouteKey::TcpHost(_) => None,
+    }
+}
+
+/// Cluster keys that may serve a route, most specific first
+fn candidate_keys(keys: &[RouteKey]) -> Vec<String> {
+    let mut candidates = Vec::new();
+    for key in keys {
+        candidates.extend(cluster_key(key));
+        let wildcard = match key {
+            RouteKey::HttpHost(host) if !WildcardPattern::is_wildcard_pattern(host) => {
+                extract_parent_wildcard(host).map(RouteKey::HttpHost)
+            }
+            RouteKey::TlsSni(sni) if !WildcardPattern::is_wildcard_pattern(sni) => {
+                extract_parent_wildcard(sni).map(RouteKey::TlsSni)
+            }
+            RouteKey::TlsSniAlpn(sni, alpn) if !WildcardPattern::is_wildcard_pattern(sni) => {
+                extract_parent_wildcard(sni)
+                    .map(|parent| RouteKey::TlsSniAlpn(parent, alpn.clone()))
+            }
+            _ => None,
+        };
+        candidates.extend(wildcard.as_ref().and_then(cluster_key));
+    }
+    candidates
+}
+
+/// This relay's membership in a cluster
+pub struct ClusterNode {
+    config: ClusterConfig,
+    db: DatabaseConnection,
+    /// Routes claimed by this node's tunnels, restored if the node was reaped
+    claims: Mutex<HashMap<String, String>>,
+    ingress: RwLock<HashMap<ForwardKind, ClusterIngress>>,
+    /// Authenticated links to other nodes
+    links: tokio::sync::Mutex<HashMap<String, quinn::Connection>>,
+    connector: OnceLock<QuicConnector>,
+    advertise_addr: OnceLock<SocketAddr>,
+}
+
+impl ClusterNode {
+    pub fn new(config: ClusterConfig, db: DatabaseConnection) -> Self {
+        Self {
+            config,
+            db,
+            claims: Mutex::new(HashMap::new()),
+            ingress: RwLock::new(HashMap::new()),
+            links: tokio::sync::Mutex::new(HashMap::new()),
+            connector: OnceLock::new(),
+            advertise_addr: OnceLock::new(),
+        }
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.config.node_id
+    }
+
+    /// Serve visitors of `kind` forwarded by other nodes
+    pub fn set_ingress(&self, kind: ForwardKind, ingress: ClusterIngress) {
+        self.ingress.write().unwrap().insert(kind, ingress);
+    }
+
+    /// Listen for links from other nodes, join the cluster and start heartbeating
+    ///
+    /// Returns the address the link listens on.
+    pub async fn start(
+        self: &Arc<Self>,
+        quic: Arc<QuicConfig>,
+    ) -> Result<SocketAddr, ClusterError> {
+        let listener = QuicListener::new(self.config.bind_addr, quic)
+            .map_err(|e| ClusterError::Link(e.to_string()))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| ClusterError::Link(e.to_string()))?;
+        let advertise_addr = self.config.advertise_addr.unwrap_or(local_addr);
+        if advertise_addr.ip().is_unspecified() {
+            warn!(
+                "Cluster link advertised on {}, other nodes can't reach it; set an advertise address",
+                advertise_addr
+            );
+        }
+        let _ = self.advertise_addr.set(advertise_addr);
+
+        let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure()))
+            .map_err(|e| ClusterError::Link(e.to_string()))?;
+        let _ = self.connector.set(connector);
+
+        self.join().await?;
+        info!(
+            "Relay node {} joined the cluster (link on {}, advertised as {})",
+            self.config.node_id, local_addr, advertise_addr
+        );
+
+        let node = Arc::downgrade(self);
+        tokio::spawn(async move {
+            while let Ok((connection, remote)) = listener.accept().await {
+                let Some(node) = node.upgrade() else {
+                    break;
+                };
+                tokio::spawn(async move {
+                    if let Err(e) = node.serve_link(connection.inner().clone()).await {
+                        debug!("Cluster link from {} closed: {}", remote, e);
+                    }
+                });
+            }
+        });
+        self.spawn_heartbeat_task();
+
+        Ok(local_addr)
+    }
+
+    /// Record this node as alive, dropping routes left by a previous run
+    async fn join(&self) -> Result<(), DbErr> {
+        let now = Utc::now();
+        let cluster_addr = self
+            .advertise_addr
+            .get()
+            .copied()
+            .or(self.config.advertise_addr)
+            .unwrap_or(self.config.bind_addr)
+            .to_string();
+        RelayNode::insert(relay_node::ActiveModel {
+            node_id: Set(self.config.node_id.clone()),
+            cluster_addr: Set(cluster_addr),
+            started_at: Set(now),
+            heartbeat_at: Set(now),
+        })
+        .on_conflict(
+            OnConflict::column(relay_node::Column::NodeId)
+                .update_columns([
+                    relay_node::Column::ClusterAddr,
+                    relay_node::Column::StartedAt,
+                    relay_node::Column::HeartbeatAt,
+                ])
+                .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+
+        let claims: HashMap<String, String> = self.claims.lock().unwrap().clone();
+        ClusterRoute::delete_many()
+            .filter(cluster_route::Column::NodeId.eq(&self.config.node_id))
+            .filter(cluster_route::Column::RouteKey.is_not_in(claims.keys().cloned()))
+            .exec(&self.db)
+            .await?;
+        for (route_key, localup_id) in claims {
+            if let Err(e) = self.claim_key(&route_key, &localup_id).await {
+                warn!("Failed to restore cluster route {}: {}", route_key, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Refresh this node's heartbeat and remove dead nodes
+    ///
+    /// A node that was itself removed (e.g. after stalling longer than the node
+    /// timeout) rejoins and claims its routes again.
+    pub async fn heartbeat(&self) -> Result<u64, DbErr> {
+        let result = RelayNode::update_many()
+            .col_expr(relay_node::Column::HeartbeatAt, Expr::value(Utc::now()))
+            .filter(relay_node::Column::NodeId.eq(&self.config.node_id))
+            .exec(&self.db)
+            .await?;
+        if result.rows_affected == 0 {
+            warn!(
+                "Relay node {} was removed from the cluster, rejoining",
+                self.config.node_id
+            );
+            self.join().await?;
+        }
+        self.reap_dead_nodes().await
+    }
+
+    /// Remove nodes that stopped heartbeating, and their routes
+    pub async fn reap_dead_nodes(&self) -> Result<u64, DbErr> {
+        let dead = RelayNode::find()
+            .filter(relay_node::Column::HeartbeatAt.lt(self.liveness_cutoff()))
+            .filter(relay_node::Column::NodeId.ne(&self.config.node_id))
+            .all(&self.db)
+            .await?;
+        for node in &dead {
+            let routes = ClusterRoute::delete_many()
+                .filter(cluster_route::Column::NodeId.eq(&node.node_id))
+                .exec(&self.db)
+                .await?;
+            RelayNode::delete_by_id(node.node_id.clone())
+                .exec(&self.db)
+                .await?;
+            self.links.lock().await.remove(&node.node_id);
+            info!(
+                "Removed dead relay node {} and its {} routes",
+                node.node_id, routes.rows_affected
+            );
+        }
+        Ok(dead.len() as u64)
+    }
+
+    /// Heartbeat every `heartbeat_interval` until the node is dropped
+    pub fn spawn_heartbeat_task(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let node = Arc::downgrade(self);
+        let interval = self.config.heartbeat_interval;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(node) = node.upgrade() else {
+                    break;
+                };
+                if let Err(e) = node.heartbeat().await {
+                    warn!("Cluster heartbeat failed: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Leave the cluster, releasing every route of this node
+    pub async fn leave(&self) -> Result<(), DbErr> {
+        self.claims.lock().unwrap().clear();
+        ClusterRoute::delete_many()
+            .filter(cluster_route::Column::NodeId.eq(&self.config.node_id))
+            .exec(&self.db)
+            .await?;
+        RelayNode::delete_by_id(self.config.node_id.clone())
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Claim a route for a tunnel connected to this node
+    ///
+    /// Fails if a live node already serves the route. Routes of dead nodes are
+    /// taken over, and routes of kinds that aren't shared always succeed.
+    pub async fn claim(&self, key: &RouteKey, localup_id: &str) -> Result<(), ClusterError> {
+        let Some(route_key) = cluster_key(key) else {
+            return Ok(());
+        };
+        self.claim_key(&route_key, localup_id).await?;
+        self.claims
+            .lock()
+            .unwrap()
+            .insert(route_key, localup_id.to_string());
+        Ok(())
+    }
+
+    async fn claim_key(&self, route_key: &str, localup_id: &str) -> Result<(), ClusterError> {
+        let inserted = cluster_route::ActiveModel {
+            route_key: Set(route_key.to_string()),
+            node_id: Set(self.config.node_id.clone()),
+            localup_id: Set(localup_id.to_string()),
+            claimed_at: Set(Utc::now()),
+        }
+        .insert(&self.db)
+        .await;
+        let insert_error = match inserted {
+            Ok(_) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let Some(existing) = ClusterRoute::find_by_id(route_key.to_string())
+            .one(&self.db)
+            .await?
+        else {
+            return Err(insert_error.into());
+        };
+        if existing.node_id != self.config.node_id && self.is_alive(&existing.node_id).await? {
+            return Err(ClusterError::RouteTaken {
+                route_key: route_key.to_string(),
+                node_id: existing.node_id,
+            });
+        }
+
+        // A tunnel reconnecting to this node, or a route left by a dead node
+        let result = ClusterRoute::update_many()
+            .col_expr(
+                cluster_route::Column::NodeId,
+                Expr::value(self.config.node_id.clone()),
+            )
+            .col_expr(
+                cluster_route::Column::LocalupId,
+                Expr::value(localup_id.to_string()),
+            )
+            .col_expr(cluster_route::Column::ClaimedAt, Expr::value(Utc::now()))
+            .filter(cluster_route::Column::RouteKey.eq(route_key))
+            .filter(cluster_route::Column::NodeId.eq(&existing.node_id))
+            .exec(&self.db)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(ClusterError::RouteTaken {
+                route_key: route_key.to_string(),
+                node_id: "another node".to_string(),
+            });
+        }
+        if existing.node_id != self.config.node_id {
+            info!(
+                "Took over route {} from dead relay node {}",
+                route_key, existing.node_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Release every route claimed for a tunnel
+    pub async fn release(&self, localup_id: &str) -> Result<u64, DbErr> {
+        self.claims
+            .lock()
+            .unwrap()
+            .retain(|_, claimed_by| claimed_by != localup_id);
+        let result = ClusterRoute::delete_many()
+            .filter(cluster_route::Column::NodeId.eq(&self.config.node_id))
+            .filter(cluster_route::Column::LocalupId.eq(localup_id))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// Live node other than this one serving any of `keys`, trying each key's
+    /// parent wildcard after it
+    pub async fn owner_of(&self, keys: &[RouteKey]) -> Option<ClusterPeer> {
+        let candidates = candidate_keys(keys);
+        if candidates.is_empty() {
+            return None;
+        }
+        let routes = match ClusterRoute::find()
+            .filter(cluster_route::Column::RouteKey.is_in(candidates.clone()))
+            .filter(cluster_route::Column::NodeId.ne(&self.config.node_id))
+            .all(&self.db)
+            .await
+        {
+            Ok(routes) => routes,
+            Err(e) => {
+                warn!("Failed to look up cluster routes: {}", e);
+                return None;
+            }
+        };
+
+        for candidate in &candidates {
+            let Some(route) = routes.iter().find(|r| &r.route_key == candidate) else {
+                continue;
+            };
+            let node = match RelayNode::find_by_id(route.node_id.clone())
+                .one(&self.db)
+                .await
+            {
+                Ok(Some(node)) if node.heartbeat_at >= self.liveness_cutoff() => node,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("Failed to look up relay node {}: {}", route.node_id, e);
+                    return None;
+                }
+            };
+            match node.cluster_addr.parse() {
+                Ok(cluster_addr) => {
+                    return Some(ClusterPeer {
+                        node_id: node.node_id,
+                        cluster_addr,
+                    })
+                }
+                Err(_) => warn!(
+                    "Relay node {} has an invalid cluster address: {}",
+                    node.node_id, node.cluster_addr
+                ),
+            }
+        }
+        None
+    }
+
+    /// Forward a visitor connection to the node serving its route
+    ///
+    /// `initial` holds what was already read from the visitor; the rest is
+    /// relayed in both directions until either side closes.
+    pub async fn forward<S>(
+        &self,
+        owner: &ClusterPeer,
+        kind: ForwardKind,
+        peer_addr: SocketAddr,
+        initial: &[u8],
+        mut visitor: S,
+    ) -> Result<(), ClusterError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let link = self.link(owner).await?;
+        let (mut send, recv) = match link.open_bi().await {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.links.lock().await.remove(&owner.node_id);
+                return Err(ClusterError::Link(e.to_string()));
+            }
+        };
+        write_frame(&mut send, &LinkMessage::Forward { kind, peer_addr }).await?;
+        AsyncWriteExt::write_all(&mut send, initial).await?;
+        debug!(
+            "Forwarding {:?} visitor {} to relay node {}",
+            kind, peer_addr, owner.node_id
+        );
+
+        let mut stream = tokio::io::join(recv, send);
+        tokio::io::copy_bidirectional(&mut visitor, &mut stream).await?;
+        Ok(())
+    }
+
+    /// Authenticated link to a node, dialing it if needed
+    async fn link(&self, owner: &ClusterPeer) -> Result<quinn::Connection, ClusterError> {
+        let mut links = self.links.lock().await;
+        if let Some(link) = links.get(&owner.node_id) {
+            if link.close_reason().is_none() {
+                return Ok(link.clone());
+            }
+        }
+
+        let connector = self.connector.get().ok_or(ClusterError::NotStarted)?;
+        let link = tokio::time::timeout(LINK_HANDSHAKE_TIMEOUT, async {
+            let connection = connector
+                .connect(owner.cluster_addr, "localhost")
+                .await
+                .map_err(|e| ClusterError::Link(e.to_string()))?
+                .inner()
+                .clone();
+            let (mut send, mut recv) = connection
+                .open_bi()
+                .await
+                .map_err(|e| ClusterError::Link(e.to_string()))?;
+            let hello = LinkMessage::Hello {
+                node_id: self.config.node_id.clone(),
+                proof: self.proof(&connection, b"dialer")?,
+            };
+            write_frame(&mut send, &hello).await?;
+            match read_frame(&mut recv).await? {
+                LinkMessage::Welcome { node_id, proof }
+                    if node_id == owner.node_id
+                        && self.verify(&connection, b"listener", &proof)? => {}
+                _ => {
+                    connection.close(1u32.into(), b"authentication failed");
+                    return Err(ClusterError::Authentication(owner.node_id.clone()));
+                }
+            }
+            let _ = send.finish();
+            Ok::<_, ClusterError>(connection)
+        })
+        .await
+        .map_err(|_| ClusterError::Link(format!("Timed out dialing {}", owner.node_id)))??;
+
+        info!(
+            "Cluster link to relay node {} ({}) established",
+            owner.node_id, owner.cluster_addr
+        );
+        links.insert(owner.node_id.clone(), link.clone());
+        Ok(link)
+    }
+
+    /// Authenticate a link dialed by another node, then serve its forwarded visitors
+    async fn serve_link(
+        self: Arc<Self>,
+        connection: quinn::Connection,
+    ) -> Result<(), ClusterError> {
+        let remote_node = tokio::time::timeout(LINK_HANDSHAKE_TIMEOUT, async {
+            let (mut send, mut recv) = connection
+                .accept_bi()
+                .await
+                .map_err(|e| ClusterError::Link(e.to_string()))?;
+            let LinkMessage::Hello { node_id, proof } = read_frame(&mut recv).await? else {
+                return Err(ClusterError::Link("Expected Hello".to_string()));
+            };
+            if !self.verify(&connection, b"dialer", &proof)? {
+                connection.close(1u32.into(), b"authentication failed");
+                return Err(ClusterError::Authentication(node_id));
+            }
+            let welcome = LinkMessage::Welcome {
+                node_id: self.config.node_id.clone(),
+                proof: self.proof(&connection, b"listener")?,
+            };
+            write_frame(&mut send, &welcome).await?;
+            let _ = send.finish();
+            Ok(node_id)
+        })
+        .await
+        .map_err(|_| ClusterError::Link("Timed out authenticating link".to_string()))??;
+        info!("Cluster link from relay node {} accepted", remote_node);
+
+        loop {
+            let (send, mut recv) = connection
+                .accept_bi()
+                .await
+                .map_err(|e| ClusterError::Link(e.to_string()))?;
+            let node = self.clone();
+            let remote_node = remote_node.clone();
+            tokio::spawn(async move {
+                let (kind, peer_addr) = match read_frame(&mut recv).await {
+                    Ok(LinkMessage::Forward { kind, peer_addr }) => (kind, peer_addr),
+                    Ok(other) => {
+                        debug!("Unexpected message from {}: {:?}", remote_node, other);
+                        return;
+                    }
+                    Err(e) => {
+                        debug!("Bad forwarded stream from {}: {}", remote_node, e);
+                        return;
+                    }
+                };
+                let ingress = node.ingress.read().unwrap().get(&kind).cloned();
+                match ingress {
+                    Some(ingress) => {
+                        debug!(
+                            "Serving {:?} visitor {} forwarded by {}",
+                            kind, peer_addr, remote_node
+                        );
+                        ingress(tokio::io::join(recv, send), peer_addr).await;
+                    }
+                    None => warn!(
+                        "Relay node {} forwarded a {:?} visitor, but this node has no such server",
+                        remote_node, kind
+                    ),
+                }
+            });
+        }
+    }
+
+    /// Oldest heartbeat of a live node
+    fn liveness_cutoff(&self) -> chrono::DateTime<Utc> {
+        Utc::now()
+            - ChronoDuration::from_std(self.config.node_timeout).unwrap_or(ChronoDuration::MAX)
+    }
+
+    async fn is_alive(&self, node_id: &str) -> Result<bool, DbErr> {
+        Ok(RelayNode::find_by_id(node_id.to_string())
+            .one(&self.db)
+            .await?
+            .is_some_and(|node| node.heartbeat_at >= self.liveness_cutoff()))
+    }
+
+    /// HMAC proving knowledge of the cluster secret, bound to this QUIC session
+    fn proof(&self, connection: &quinn::Connection, role: &[u8]) -> Result<Vec<u8>, ClusterError> {
+        Ok(self.mac(connection, role)?.finalize().into_bytes().to_vec())
+    }
+
+    fn verify(
+        &self,
+        connection: &quinn::Connection,
+        role: &[u8],
+        proof: &[u8],
+    ) -> Result<bool, ClusterError> {
+        Ok(self.mac(connection, role)?.verify_slice(proof).is_ok())
+    }
+
+    fn mac(
+        &self,
+        connection: &quinn::Connection,
+        role: &[u8],
+    ) -> Result<Hmac<Sha256>, ClusterError> {
+        let mut keying_material = [0u8; 32];
+        connection
+            .export_keying_material(&mut keying_material, EXPORTER_LABEL, role)
+            .map_err(|_| ClusterError::Link("Failed to export keying material".to_string()))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.secret.as_bytes())
+            .map_err(|e| ClusterError::Link(e.to_string()))?;
+        mac.update(&keying_material);
+        Ok(mac)
+    }
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &LinkMessage,
+) -> Result<(), ClusterError> {
+    let bytes = bincode::serialize(message).map_err(|e| ClusterError::Link(e.to_string()))?;
+    writer.write_u32(bytes.len() as u32).await?;
+    writer.write_all(&bytes).await?;
+    Ok(())
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<LinkMessage, ClusterError> {
+    let len = reader.read_u32().await? as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(ClusterError::Link(format!(
+            "Frame too large: {} bytes",
+            len
+        )));
+    }
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes).await?;
+    bincode::deserialize(&bytes).map_err(|e| ClusterError::Link(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn node(db: &DatabaseConnection, node_id: &str) -> ClusterNode {
+        let config = ClusterConfig::new(node_id, "127.0.0.1:0".parse().unwrap(), "secret")
+            .with_advertise_addr("127.0.0.1:7000".parse().unwrap());
+        let node = ClusterNode::new(config, db.clone());
+        node.join().await.unwrap();
+        node
+    }
+
+    fn http(host: &str) -> RouteKey {
+        RouteKey::HttpHost(host.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_routes_are_claimed_once_and_freed_by_dead_nodes() {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+        let a = node(&db, "a").await;
+        let b = node(&db, "b").await;
+
+        b.claim(&http("app.example.com"), "tunnel-1").await.unwrap();
+        b.claim(&http("*.wild.example.com"), "tunnel-1")
+            .await
+            .unwrap();
+        assert!(matches!(
+            a.claim(&http("app.example.com"), "tunnel-2").await,
+            Err(ClusterError::RouteTaken { .. })
+        ));
+        // TCP routes are local to each node
+        a.claim(&RouteKey::TcpPort(9000), "tunnel-2").await.unwrap();
+
+        let owner = a.owner_of(&[http("app.example.com")]).await.unwrap();
+        assert_eq!(owner.node_id, "b");
+        assert_eq!(owner.cluster_addr, "127.0.0.1:7000".parse().unwrap());
+        assert_eq!(
+            a.owner_of(&[http("api.wild.example.com")])
+                .await
+                .unwrap()
+                .node_id,
+            "b"
+        );
+        assert!(b.owner_of(&[http("app.example.com")]).await.is_none());
+        assert!(a.owner_of(&[http("other.example.com")]).await.is_none());
+
+        // b stops heartbeating
+        RelayNode::update_many()
+            .col_expr(
+                relay_node::Column::HeartbeatAt,
+                Expr::value(Utc::now() - ChronoDuration::minutes(1)),
+            )
+            .filter(relay_node::Column::NodeId.eq("b"))
+            .exec(&db)
+            .await
+            .unwrap();
+        assert!(a.owner_of(&[http("app.example.com")]).await.is_none());
+        a.claim(&http("app.example.com"), "tunnel-2").await.unwrap();
+
+        assert_eq!(a.reap_dead_nodes().await.unwrap(), 1);
+        assert_eq!(ClusterRoute::find().all(&db).await.unwrap().len(), 1);
+
+        // b comes back and restores the routes nobody took over
+        b.heartbeat().await.unwrap();
+        assert_eq!(
+            a.owner_of(&[http("api.wild.example.com")])
+                .await
+                .unwrap()
+                .node_id,
+            "b"
+        );
+        assert_eq!(a.release("tunnel-2").await.unwrap(), 1);
+    }
+}
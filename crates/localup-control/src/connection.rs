@@ -1,11 +1,15 @@
 //! Tunnel connection management
 
-use localup_http_auth::HttpAuthenticator;
-use localup_proto::{Endpoint, HttpAuthConfig};
+use localup_http_auth::{AccountVerifier, HttpAuthenticator};
+use localup_proto::{Endpoint, HttpAuthConfig, TcpLimits};
 use localup_transport_quic::QuicConnection;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
+
+use crate::metering::TunnelMeter;
+use crate::quotas::{AccountUsage, QuotaAccount, QuotaExceeded, VisitorPermit};
+use crate::tcp_limits::TcpConnectionLimiter;
 
 /// Callback for handling TCP data from tunnel to proxy
 pub type TcpDataCallback = Arc<
@@ -14,6 +18,15 @@ pub type TcpDataCallback = Arc<
         + Sync,
 >;
 
+/// Account a tunnel was opened by
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TunnelOwner {
+    /// User the tunnel's token identifies
+    pub user_id: Option<String>,
+    /// Team the tunnel's token belongs to
+    pub team_id: Option<String>,
+}
+
 /// Represents an active tunnel connection
 pub struct TunnelConnection {
     pub localup_id: String,
@@ -22,8 +35,21 @@ pub struct TunnelConnection {
     pub tcp_data_callback: Option<TcpDataCallback>,
     /// HTTP authentication configuration for this tunnel
     pub http_auth: HttpAuthConfig,
+    /// Authenticator built from `http_auth`, shared across requests so that
+    /// provider state (sessions, cached identity provider keys) persists
+    pub http_authenticator: Arc<HttpAuthenticator>,
     /// The auth token used to create this tunnel (for /_localup/token endpoint)
     pub auth_token: Option<String>,
+    /// Account the auth token belongs to
+    pub owner: TunnelOwner,
+    /// Connection limits for the tunnel's TCP endpoints
+    pub tcp_limiter: Arc<TcpConnectionLimiter>,
+    /// Usage of the account the tunnel counts against, if quotas apply
+    pub quota: Option<Arc<AccountUsage>>,
+    /// Meter for the tunnel's traffic, if the relay meters traffic
+    pub meter: Option<TunnelMeter>,
+    /// Asks the tunnel's control stream to disconnect the client with a reason
+    pub disconnect: Option<mpsc::UnboundedSender<String>>,
 }
 
 /// Manages all active tunnel connections
@@ -75,14 +101,39 @@ impl TunnelConnectionManager {
         connection: Arc<QuicConnection>,
         http_auth: HttpAuthConfig,
         auth_token: Option<String>,
+    ) {
+        self.register_with_accounts(
+            localup_id, endpoints, connection, http_auth, auth_token, None,
+        )
+        .await;
+    }
+
+    /// Register a new tunnel connection whose login forms can check relay
+    /// accounts with `accounts`
+    pub async fn register_with_accounts(
+        &self,
+        localup_id: String,
+        endpoints: Vec<Endpoint>,
+        connection: Arc<QuicConnection>,
+        http_auth: HttpAuthConfig,
+        auth_token: Option<String>,
+        accounts: Option<Arc<dyn AccountVerifier>>,
     ) {
         let localup_conn = TunnelConnection {
             localup_id: localup_id.clone(),
             endpoints,
             connection,
             tcp_data_callback: None,
+            http_authenticator: Arc::new(HttpAuthenticator::from_config_with_accounts(
+                &http_auth, accounts,
+            )),
             http_auth,
             auth_token,
+            owner: TunnelOwner::default(),
+            tcp_limiter: Arc::new(TcpConnectionLimiter::new(TcpLimits::default())),
+            quota: None,
+            meter: None,
+            disconnect: None,
         };
 
         self.connections
@@ -107,6 +158,115 @@ impl TunnelConnectionManager {
             .and_then(|conn| conn.tcp_data_callback.clone())
     }
 
+    /// Set the connection limits for a tunnel's TCP endpoints
+    pub async fn set_tcp_limits(&self, localup_id: &str, limits: TcpLimits) {
+        if let Some(conn) = self.connections.write().await.get_mut(localup_id) {
+            conn.tcp_limiter = Arc::new(TcpConnectionLimiter::new(limits));
+        }
+    }
+
+    /// Get the connection limiter for a tunnel's TCP endpoints
+    pub async fn get_tcp_limiter(&self, localup_id: &str) -> Option<Arc<TcpConnectionLimiter>> {
+        self.connections
+            .read()
+            .await
+            .get(localup_id)
+            .map(|conn| conn.tcp_limiter.clone())
+    }
+
+    /// Record the account a tunnel was opened by
+    pub async fn set_owner(&self, localup_id: &str, owner: TunnelOwner) {
+        if let Some(conn) = self.connections.write().await.get_mut(localup_id) {
+            conn.owner = owner;
+        }
+    }
+
+    /// Get the account a tunnel was opened by
+    pub async fn get_owner(&self, localup_id: &str) -> Option<TunnelOwner> {
+        self.connections
+            .read()
+            .await
+            .get(localup_id)
+            .map(|conn| conn.owner.clone())
+    }
+
+    /// Count a tunnel's visitors and traffic against an account's quota
+    pub async fn set_quota(&self, localup_id: &str, usage: Arc<AccountUsage>) {
+        if let Some(conn) = self.connections.write().await.get_mut(localup_id) {
+            conn.quota = Some(usage);
+        }
+    }
+
+    /// Meter a tunnel's visitor traffic
+    pub async fn set_meter(&self, localup_id: &str, meter: TunnelMeter) {
+        if let Some(conn) = self.connections.write().await.get_mut(localup_id) {
+            conn.meter = Some(meter);
+        }
+    }
+
+    /// Set the channel that disconnects a tunnel's client
+    pub async fn set_disconnect(&self, localup_id: &str, sender: mpsc::UnboundedSender<String>) {
+        if let Some(conn) = self.connections.write().await.get_mut(localup_id) {
+            conn.disconnect = Some(sender);
+        }
+    }
+
+    /// Disconnect a tunnel's client, telling it why
+    ///
+    /// Returns false if the tunnel isn't connected or can't be disconnected.
+    pub async fn disconnect(&self, localup_id: &str, reason: String) -> bool {
+        self.connections
+            .read()
+            .await
+            .get(localup_id)
+            .and_then(|conn| conn.disconnect.as_ref())
+            .is_some_and(|sender| sender.send(reason).is_ok())
+    }
+
+    /// Connected tunnels and the auth tokens they were opened with
+    pub async fn authenticated_tunnels(&self) -> Vec<(String, String)> {
+        self.connections
+            .read()
+            .await
+            .values()
+            .filter_map(|conn| {
+                conn.auth_token
+                    .clone()
+                    .map(|token| (conn.localup_id.clone(), token))
+            })
+            .collect()
+    }
+
+    /// Admit a visitor connection to a tunnel
+    ///
+    /// Returns `Ok(None)` for tunnels without a quota or meter.
+    pub async fn admit_visitor(
+        &self,
+        localup_id: &str,
+    ) -> Result<Option<VisitorPermit>, QuotaExceeded> {
+        let (quota, meter) = match self.connections.read().await.get(localup_id) {
+            Some(conn) => (conn.quota.clone(), conn.meter.clone()),
+            None => (None, None),
+        };
+        let permit = quota.map(|usage| usage.try_connection()).transpose()?;
+        Ok(match (permit, meter) {
+            (Some(permit), Some(meter)) => Some(permit.with_meter(meter.open_connection())),
+            (None, Some(meter)) => Some(VisitorPermit::metered(meter.open_connection())),
+            (permit, None) => permit,
+        })
+    }
+
+    /// Live usage of an account with connected tunnels
+    pub async fn account_usage(&self, account: &QuotaAccount) -> Option<Arc<AccountUsage>> {
+        self.connections
+            .read()
+            .await
+            .values()
+            .filter_map(|conn| conn.quota.as_ref())
+            .find(|usage| usage.account() == *account)
+            .cloned()
+    }
+
     /// Unregister a tunnel connection
     pub async fn unregister(&self, localup_id: &str) {
         self.connections.write().await.remove(localup_id);
@@ -137,14 +297,14 @@ impl TunnelConnectionManager {
 
     /// Get the HTTP authenticator for a tunnel
     ///
-    /// Returns an `HttpAuthenticator` configured with the tunnel's authentication settings.
+    /// Returns the `HttpAuthenticator` configured with the tunnel's authentication settings.
     /// If no auth is configured, returns an authenticator that allows all requests.
-    pub async fn get_http_authenticator(&self, localup_id: &str) -> Option<HttpAuthenticator> {
+    pub async fn get_http_authenticator(&self, localup_id: &str) -> Option<Arc<HttpAuthenticator>> {
         self.connections
             .read()
             .await
             .get(localup_id)
-            .map(|conn| HttpAuthenticator::from_config(&conn.http_auth))
+            .map(|conn| conn.http_authenticator.clone())
     }
 
     /// Get the raw HTTP auth configuration for a tunnel
@@ -1,35 +1,81 @@
 //! Tunnel connection handler for exit nodes
 
+use async_trait::async_trait;
 use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 
-use localup_auth::JwtValidator;
+use localup_auth::{AuthError, AuthResult, AuthValidator, JwtValidator, PortRange};
 use localup_proto::{Endpoint, IpFilter, Protocol, TunnelMessage};
 use localup_relay_db::entities::{
-    auth_token,
     custom_domain::{self, DomainStatus},
-    prelude::{AuthToken as AuthTokenEntity, CustomDomain as CustomDomainEntity},
+    prelude::CustomDomain as CustomDomainEntity,
 };
 use localup_router::{
     extract_parent_wildcard, RouteKey, RouteRegistry, RouteTarget, WildcardPattern,
+    TLS_TERMINATE_METADATA,
 };
 use localup_transport::{TransportConnection, TransportStream};
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use sha2::{Digest, Sha256};
 
 use crate::agent_registry::{AgentRegistry, RegisteredAgent};
-use crate::connection::TunnelConnectionManager;
+use crate::cluster::ClusterNode;
+use crate::connection::{TunnelConnectionManager, TunnelOwner};
 use crate::domain_provider::{DomainContext, DomainProvider};
+use crate::metering::{MeterKey, TrafficMeter};
 use crate::pending_requests::PendingRequests;
+use crate::quotas::{QuotaAccount, QuotaEnforcer};
+use crate::revocation::{RevocationCheckingValidator, RevocationList};
 use crate::task_tracker::TaskTracker;
+use crate::team_accounts::TeamAccountVerifier;
+use crate::token_validator::RelayTokenValidator;
+use crate::tunnel_slots::TunnelSlots;
+
+/// Owner of a TCP tunnel, passed to port allocators that remember ports across restarts
+#[derive(Clone, Debug, Default)]
+pub struct PortOwner {
+    /// User ID from the tunnel's auth token
+    pub user_id: Option<String>,
+
+    /// Team ID from the tunnel's auth token
+    pub team_id: Option<String>,
+
+    /// Local port being tunneled (user_id + local_port = sticky key)
+    pub local_port: Option<u16>,
+
+    /// Public ports the token is scoped to (None = any port in the range)
+    pub allowed_ports: Option<Vec<PortRange>>,
+}
+
+impl PortOwner {
+    /// Whether the owner's token allows a public port
+    pub fn allows(&self, port: u16) -> bool {
+        self.allowed_ports
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|r| r.contains(port)))
+    }
+}
 
 /// Trait for port allocation (TCP tunnels)
+#[async_trait]
 pub trait PortAllocator: Send + Sync {
     /// Allocate a port for the given localup_id
     /// If requested_port is Some, try to allocate that specific port
     /// If requested_port is None or unavailable, allocate any available port
-    fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String>;
-    fn deallocate(&self, localup_id: &str);
+    async fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String>;
+
+    /// Allocate a port for a tunnel whose owner is known
+    /// Persistent allocators give the same owner the same port again; the default ignores the owner
+    async fn allocate_for(
+        &self,
+        localup_id: &str,
+        requested_port: Option<u16>,
+        _owner: &PortOwner,
+    ) -> Result<u16, String> {
+        self.allocate(localup_id, requested_port).await
+    }
+
+    async fn deallocate(&self, localup_id: &str);
     fn get_allocated_port(&self, localup_id: &str) -> Option<u16>;
 }
 
@@ -49,6 +95,8 @@ pub struct TunnelHandler {
     connection_manager: Arc<TunnelConnectionManager>,
     route_registry: Arc<RouteRegistry>,
     jwt_validator: Option<Arc<JwtValidator>>,
+    /// Replaces the built-in relay token validation when set
+    auth_validator: Option<Arc<dyn AuthValidator>>,
     db: Option<DatabaseConnection>,
     domain: String,
     domain_provider: Option<Arc<dyn DomainProvider>>,
@@ -64,8 +112,20 @@ pub struct TunnelHandler {
     http_port: Option<u16>,
     /// Actual HTTPS port the relay is listening on
     https_port: Option<u16>,
+    /// Port of the relay's CONNECT/SOCKS5 listener for hostname-routed TCP tunnels
+    tcp_connect_port: Option<u16>,
     /// Tracks TCP proxy server tasks to allow cleanup on disconnect
     task_tracker: Arc<TaskTracker>,
+    /// Tunnels connected per auth token, for the token's `max_tunnels` claim
+    tunnel_slots: Arc<TunnelSlots>,
+    /// Per-user and per-team quotas from the relay database
+    quotas: Option<Arc<QuotaEnforcer>>,
+    /// Traffic meter writing hourly rollups to the relay database
+    traffic_meter: Option<Arc<TrafficMeter>>,
+    /// Tokens revoked before they expire
+    revocations: Option<Arc<RevocationList>>,
+    /// Cluster this relay shares its routes with
+    cluster: Option<Arc<ClusterNode>>,
 }
 
 impl TunnelHandler {
@@ -80,6 +140,7 @@ impl TunnelHandler {
             connection_manager,
             route_registry,
             jwt_validator,
+            auth_validator: None,
             db: None,
             domain,
             domain_provider: None,
@@ -91,7 +152,13 @@ impl TunnelHandler {
             tls_port: None,
             http_port: None,
             https_port: None,
+            tcp_connect_port: None,
             task_tracker: Arc::new(TaskTracker::new()),
+            tunnel_slots: Arc::new(TunnelSlots::new()),
+            quotas: None,
+            traffic_meter: None,
+            revocations: None,
+            cluster: None,
         }
     }
 
@@ -101,6 +168,37 @@ impl TunnelHandler {
         self
     }
 
+    /// Authenticate tunnels and agents with a custom validator (or an `AuthValidatorChain`)
+    /// instead of the relay's own JWT auth tokens
+    pub fn with_auth_validator(mut self, validator: Arc<dyn AuthValidator>) -> Self {
+        self.auth_validator = Some(validator);
+        self
+    }
+
+    /// Enforce the user and team quotas stored in the relay database
+    pub fn with_quotas(mut self, quotas: Arc<QuotaEnforcer>) -> Self {
+        self.quotas = Some(quotas);
+        self
+    }
+
+    /// Meter visitor traffic per tunnel, token, user and team
+    pub fn with_traffic_meter(mut self, meter: Arc<TrafficMeter>) -> Self {
+        self.traffic_meter = Some(meter);
+        self
+    }
+
+    /// Refuse tokens whose `jti` has been revoked
+    pub fn with_revocations(mut self, revocations: Arc<RevocationList>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Claim routes cluster-wide, refusing names served by another relay node
+    pub fn with_cluster(mut self, cluster: Arc<ClusterNode>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
     pub fn with_port_allocator(mut self, port_allocator: Arc<dyn PortAllocator>) -> Self {
         self.port_allocator = Some(port_allocator);
         self
@@ -126,6 +224,11 @@ impl TunnelHandler {
         self
     }
 
+    pub fn with_tcp_connect_port(mut self, port: u16) -> Self {
+        self.tcp_connect_port = Some(port);
+        self
+    }
+
     pub fn with_http_port(mut self, port: u16) -> Self {
         self.http_port = Some(port);
         self
@@ -359,8 +462,8 @@ impl TunnelHandler {
         debug!("Received Connect from localup_id: {}", localup_id);
 
         // Validate authentication with enhanced auth token validation
-        let user_id = match self.validate_auth_token(&auth_token).await {
-            Ok(user_id) => user_id,
+        let auth = match self.validate_auth_token(&auth_token).await {
+            Ok(validated) => validated,
             Err(e) => {
                 error!("Authentication failed for tunnel {}: {}", localup_id, e);
                 let _ = control_stream
@@ -375,12 +478,112 @@ impl TunnelHandler {
             }
         };
 
-        debug!("Tunnel {} authenticated for user {}", localup_id, user_id);
+        let identity = auth.as_ref().map(|a| a.identity().to_string());
+        debug!(
+            "Tunnel {} authenticated for user {}",
+            localup_id,
+            identity.as_deref().unwrap_or("anonymous")
+        );
+
+        // The relay verifies configured password hashes on every request, so
+        // refuse ones that would be too expensive before registering anything
+        if let Err(e) = localup_http_auth::HttpAuthenticator::validate_config(&config.http_auth) {
+            let reason = format!("Invalid HTTP auth config: {}", e);
+            warn!("Tunnel {} rejected: {}", localup_id, reason);
+            let _ = control_stream
+                .send_message(&TunnelMessage::Disconnect {
+                    reason: reason.clone(),
+                })
+                .await;
+            let _ = control_stream.finish().await;
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+            return Err(reason);
+        }
 
         // Build endpoints based on requested protocols
         let mut endpoints = self
-            .build_endpoints(&localup_id, &protocols, &config, peer_addr)
+            .build_endpoints(
+                &localup_id,
+                identity.as_deref(),
+                &protocols,
+                &config,
+                peer_addr,
+            )
             .await;
+        // Enforce the token's scopes before anything is registered
+        let mut _tunnel_slot = None;
+        if let Some(ref auth) = auth {
+            if let Err(reason) = endpoints
+                .iter()
+                .try_for_each(|e| auth.validate_endpoint(&e.protocol))
+            {
+                warn!("Tunnel {} rejected: {}", localup_id, reason);
+                let _ = control_stream
+                    .send_message(&TunnelMessage::Disconnect {
+                        reason: reason.clone(),
+                    })
+                    .await;
+                let _ = control_stream.finish().await;
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                return Err(reason);
+            }
+
+            if let Some(max) = auth.max_tunnels {
+                let token_key = format!("{:x}", Sha256::digest(auth_token.as_bytes()));
+                match self.tunnel_slots.try_claim(&token_key, &localup_id, max) {
+                    Ok(slot) => _tunnel_slot = Some(slot),
+                    Err(connected) => {
+                        let reason = format!(
+                            "Token allows at most {} connected tunnels ({} already connected)",
+                            max, connected
+                        );
+                        warn!("Tunnel {} rejected: {}", localup_id, reason);
+                        let _ = control_stream
+                            .send_message(&TunnelMessage::Disconnect {
+                                reason: reason.clone(),
+                            })
+                            .await;
+                        let _ = control_stream.finish().await;
+                        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                        return Err(reason);
+                    }
+                }
+            }
+        }
+
+        // Count the tunnel against its user's or team's quota
+        let mut quota_lease = None;
+        let account = QuotaAccount::for_tunnel(
+            identity.as_deref(),
+            auth.as_ref().and_then(|a| a.team_id.as_deref()),
+        );
+        if let (Some(quotas), Some(account)) = (&self.quotas, account) {
+            match quotas
+                .admit(account, &localup_id, self.custom_domains(&endpoints))
+                .await
+            {
+                Ok(lease) => quota_lease = Some(lease),
+                Err(e) => {
+                    let reason = format!("Quota exceeded: {}", e);
+                    warn!("Tunnel {} rejected for {}: {}", localup_id, account, reason);
+                    let _ = control_stream
+                        .send_message(&TunnelMessage::Disconnect {
+                            reason: reason.clone(),
+                        })
+                        .await;
+                    let _ = control_stream.finish().await;
+                    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+                    return Err(reason);
+                }
+            }
+        }
+
+        let port_owner = PortOwner {
+            user_id: identity.clone(),
+            team_id: auth.as_ref().and_then(|a| a.team_id.clone()),
+            local_port: config.local_port,
+            allowed_ports: auth.as_ref().and_then(|a| a.allowed_ports.clone()),
+        };
         debug!(
             "Built {} endpoints for tunnel {}",
             endpoints.len(),
@@ -401,10 +604,20 @@ impl TunnelHandler {
 
         for endpoint in &mut endpoints {
             debug!("Registering endpoint: protocol={:?}", endpoint.protocol);
-            match self
-                .register_route(&localup_id, endpoint, ip_filter.clone())
+            let registered = match self
+                .register_route(&localup_id, endpoint, ip_filter.clone(), &port_owner)
                 .await
             {
+                Ok(port) => match self.claim_cluster_routes(&localup_id, endpoint).await {
+                    Ok(()) => Ok(port),
+                    Err(e) => {
+                        self.unregister_route(&localup_id, endpoint).await;
+                        Err(e)
+                    }
+                },
+                Err(e) => Err(e),
+            };
+            match registered {
                 Ok(Some(allocated_port)) => {
                     // Update TCP endpoint with allocated port
                     endpoint.public_url = format!("tcp://{}:{}", self.domain, allocated_port);
@@ -468,19 +681,69 @@ impl TunnelHandler {
         // Register the tunnel connection (optional - only for QUIC connections)
         // Note: Connection manager is primarily used for reverse tunnels and TCP proxies.
         // For HTTP/HTTPS tunnels, routing is handled by route_registry instead.
+        let (disconnect_tx, mut disconnect_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
         let quic_conn = connection.clone();
         if let Ok(quic_conn) = (quic_conn as Arc<dyn std::any::Any + Send + Sync>)
             .downcast::<localup_transport_quic::QuicConnection>()
         {
+            // Login forms may accept the relay accounts of the owner's teams
+            let owner_id = identity.as_deref().map(uuid::Uuid::parse_str);
+            let accounts = match (&self.db, owner_id) {
+                (Some(db), Some(Ok(owner_id))) => {
+                    Some(Arc::new(TeamAccountVerifier::new(db.clone(), owner_id))
+                        as Arc<dyn localup_http_auth::AccountVerifier>)
+                }
+                _ => None,
+            };
             self.connection_manager
-                .register_with_auth_and_token(
+                .register_with_accounts(
                     localup_id.clone(),
                     endpoints.clone(),
                     quic_conn,
                     config.http_auth.clone(),
                     Some(auth_token.clone()),
+                    accounts,
+                )
+                .await;
+            // Tokens may cap the limits the client asked for
+            let mut tcp_limits = config.tcp_limits;
+            if let Some(token_limits) = auth.as_ref().and_then(|a| a.tcp_limits.as_ref()) {
+                tcp_limits = tcp_limits.capped_by(token_limits);
+            }
+            if !tcp_limits.is_unlimited() {
+                debug!("TCP limits for tunnel {}: {:?}", localup_id, tcp_limits);
+                self.connection_manager
+                    .set_tcp_limits(&localup_id, tcp_limits)
+                    .await;
+            }
+            self.connection_manager
+                .set_disconnect(&localup_id, disconnect_tx)
+                .await;
+            self.connection_manager
+                .set_owner(
+                    &localup_id,
+                    TunnelOwner {
+                        user_id: identity.clone(),
+                        team_id: auth.as_ref().and_then(|a| a.team_id.clone()),
+                    },
                 )
                 .await;
+            if let Some(ref lease) = quota_lease {
+                self.connection_manager
+                    .set_quota(&localup_id, lease.usage())
+                    .await;
+            }
+            if let Some(ref meter) = self.traffic_meter {
+                let key = MeterKey::for_tunnel(
+                    &localup_id,
+                    auth.is_some().then_some(auth_token.as_str()),
+                    identity.as_deref(),
+                    auth.as_ref().and_then(|a| a.team_id.as_deref()),
+                );
+                self.connection_manager
+                    .set_meter(&localup_id, meter.tunnel(key))
+                    .await;
+            }
             debug!(
                 "Registered QUIC connection in connection manager for tunnel {}",
                 localup_id
@@ -495,7 +758,8 @@ impl TunnelHandler {
         // Log endpoint details including SNI patterns for TLS
         for endpoint in &endpoints {
             match &endpoint.protocol {
-                Protocol::Tls { sni_patterns, .. } => {
+                Protocol::Tls { sni_patterns, .. }
+                | Protocol::TlsTerminated { sni_patterns, .. } => {
                     info!(
                         "  📍 Endpoint: {} with {} SNI patterns: {:?}",
                         endpoint.public_url,
@@ -564,6 +828,16 @@ impl TunnelHandler {
                         break;
                     }
 
+                    // The relay disconnects the client, e.g. because its token was revoked
+                    Some(reason) = disconnect_rx.recv() => {
+                        info!("Disconnecting tunnel {}: {}", localup_id_heartbeat, reason);
+                        let _ = control_stream.send_message(&TunnelMessage::Disconnect { reason }).await;
+                        // Gracefully close the stream and give QUIC time to transmit
+                        let _ = control_stream.finish().await;
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        break;
+                    }
+
                     // Receive messages (always ready to receive)
                     result = control_stream.recv_message() => {
                         match result {
@@ -615,6 +889,7 @@ impl TunnelHandler {
         for endpoint in &endpoints {
             self.unregister_route(&localup_id, endpoint).await;
         }
+        self.release_cluster_routes(&localup_id).await;
 
         info!("Tunnel {} disconnected", localup_id);
         Ok(())
@@ -1426,8 +1701,13 @@ impl TunnelHandler {
         );
 
         // Validate authentication
-        if let Some(ref validator) = self.jwt_validator {
-            if let Err(e) = validator.validate(&auth_token) {
+        let validator = self.auth_validator.clone().or_else(|| {
+            self.jwt_validator
+                .clone()
+                .map(|v| v as Arc<dyn AuthValidator>)
+        });
+        if let Some(validator) = validator {
+            if let Err(e) = validator.validate(&auth_token).await {
                 error!("Authentication failed for agent {}: {}", agent_id, e);
                 let _ = control_stream
                     .send_message(&TunnelMessage::AgentRejected {
@@ -1598,96 +1878,107 @@ impl TunnelHandler {
         info!("Agent {} disconnected", agent_id);
     }
 
-    /// Validate an auth token and return the user_id
+    /// Validate a tunnel's auth token
     ///
-    /// This method performs enhanced authentication by:
-    /// 1. Validating JWT signature and expiration
-    /// 2. Verifying token type is "auth" (not "session")
-    /// 3. Hashing the token and looking it up in the database
-    /// 4. Verifying the token is active (not revoked)
-    /// 5. Updating the last_used_at timestamp
+    /// Uses the custom validator if one is set, otherwise the relay's own auth
+    /// tokens (JWT plus the database, when configured). Returns None when the
+    /// relay doesn't require authentication.
+    async fn validate_auth_token(&self, token: &str) -> Result<Option<AuthResult>, String> {
+        self.authenticate(token).await.map_err(|e| e.to_string())
+    }
+
+    /// The validator tunnel tokens are checked with, including revocations
     ///
-    /// Returns the user_id if authentication succeeds, otherwise returns an error
-    async fn validate_auth_token(&self, token: &str) -> Result<String, String> {
-        // Step 1: Validate JWT signature and expiration
-        let claims = if let Some(ref validator) = self.jwt_validator {
-            validator
-                .validate(token)
-                .map_err(|e| format!("Invalid JWT token: {}", e))?
+    /// `None` when the relay doesn't require authentication.
+    pub fn token_validator(&self) -> Option<Arc<dyn AuthValidator>> {
+        let inner: Arc<dyn AuthValidator> = if let Some(ref validator) = self.auth_validator {
+            validator.clone()
+        } else if let Some(ref jwt) = self.jwt_validator {
+            let mut validator = RelayTokenValidator::new(jwt.clone());
+            if let Some(ref db) = self.db {
+                validator = validator.with_database(db.clone());
+            }
+            Arc::new(validator)
         } else {
-            // No JWT validator configured - skip database validation too
-            return Ok("anonymous".to_string());
+            return None;
         };
 
-        // Step 2: Verify token type is "auth" (not "session")
-        match &claims.token_type {
-            Some(token_type) if token_type == "auth" => {
-                // Valid auth token, continue
-            }
-            Some(token_type) => {
-                return Err(format!(
-                    "Invalid token type '{}'. Expected 'auth' token for tunnel authentication",
-                    token_type
-                ));
-            }
-            None => {
-                // Legacy token without token_type - allow for backward compatibility
-                debug!("Token missing 'token_type' claim, treating as legacy auth token");
-            }
+        let mut validator = RevocationCheckingValidator::new(inner);
+        if let Some(ref revocations) = self.revocations {
+            validator = validator.with_revocations(revocations.clone());
         }
+        Some(Arc::new(validator))
+    }
 
-        // Extract user_id from claims (will be verified against database)
-        let claimed_user_id = claims.user_id.ok_or_else(|| {
-            "Token missing 'user_id' claim. Auth tokens must include user_id".to_string()
-        })?;
-
-        // Step 3-5: Database validation (if database is available)
-        if let Some(ref db) = self.db {
-            // Hash the token using SHA-256 (same as when storing)
-            let mut hasher = Sha256::new();
-            hasher.update(token.as_bytes());
-            let token_hash = format!("{:x}", hasher.finalize());
-
-            // Look up token in database by hash
-            let token_record = AuthTokenEntity::find()
-                .filter(auth_token::Column::TokenHash.eq(&token_hash))
-                .one(db)
-                .await
-                .map_err(|e| format!("Database error during authentication: {}", e))?
-                .ok_or_else(|| "Auth token not found or has been revoked".to_string())?;
+    /// Validate a token and check that it hasn't been revoked
+    async fn authenticate(&self, token: &str) -> Result<Option<AuthResult>, AuthError> {
+        match self.token_validator() {
+            Some(validator) => validator.validate(token).await.map(Some),
+            // No validator configured - tunnels are anonymous
+            None => Ok(None),
+        }
+    }
 
-            // Verify token is active
-            if !token_record.is_active {
-                return Err("Auth token has been deactivated".to_string());
-            }
+    /// Re-validate the tokens of connected tunnels
+    ///
+    /// Tunnels whose token was revoked, expired or deactivated since they
+    /// connected are disconnected with the reason. Returns how many were
+    /// disconnected.
+    pub async fn revalidate_sessions(&self) -> usize {
+        if self.auth_validator.is_none() && self.jwt_validator.is_none() {
+            return 0;
+        }
 
-            // Check if token is expired
-            if let Some(expires_at) = token_record.expires_at {
-                let now = chrono::Utc::now();
-                if expires_at < now {
-                    return Err("Auth token has expired".to_string());
+        let mut disconnected = 0;
+        for (localup_id, token) in self.connection_manager.authenticated_tunnels().await {
+            let reason = match self.authenticate(&token).await {
+                Ok(_) => continue,
+                Err(AuthError::InternalError(e)) => {
+                    // Don't drop tunnels because the database is unavailable
+                    warn!(
+                        "Could not re-validate token of tunnel {}: {}",
+                        localup_id, e
+                    );
+                    continue;
                 }
+                Err(AuthError::Unauthorized(reason)) => reason,
+                Err(e) => e.to_string(),
+            };
+            warn!("Disconnecting tunnel {}: {}", localup_id, reason);
+            if self
+                .connection_manager
+                .disconnect(&localup_id, reason)
+                .await
+            {
+                disconnected += 1;
             }
+        }
 
-            // Verify user_id matches (ensure JWT wasn't tampered with)
-            if token_record.user_id.to_string() != claimed_user_id {
-                return Err("Token user_id mismatch - possible JWT tampering".to_string());
+        if let Some(ref revocations) = self.revocations {
+            if let Err(e) = revocations.prune_expired().await {
+                warn!("Failed to prune expired token revocations: {}", e);
             }
+        }
+        disconnected
+    }
 
-            // Update last_used_at timestamp
-            let mut active_model: auth_token::ActiveModel = token_record.clone().into();
-            active_model.last_used_at = Set(Some(chrono::Utc::now()));
-            if let Err(e) = ActiveModelTrait::update(active_model, db).await {
-                // Log error but don't fail authentication
-                warn!("Failed to update last_used_at for token: {}", e);
+    /// Re-validate connected tunnels every `interval` until the handler is dropped
+    pub fn spawn_session_revalidation_task(
+        self: &Arc<Self>,
+        interval: std::time::Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let handler = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(handler) = handler.upgrade() else {
+                    break;
+                };
+                handler.revalidate_sessions().await;
             }
-
-            Ok(claimed_user_id)
-        } else {
-            // No database configured - rely only on JWT validation
-            debug!("Database not configured, skipping token database validation");
-            Ok(claimed_user_id)
-        }
+        })
     }
 
     /// Generate a deterministic subdomain from localup_id and peer IP hash
@@ -1722,11 +2013,35 @@ impl TunnelHandler {
         subdomain
     }
 
+    /// Domains outside the relay's own domain that a tunnel's endpoints serve
+    fn custom_domains(&self, endpoints: &[Endpoint]) -> Vec<String> {
+        let relay_suffix = format!(".{}", self.domain);
+        let mut domains: Vec<String> = endpoints
+            .iter()
+            .flat_map(|endpoint| match &endpoint.protocol {
+                Protocol::Http { custom_domain, .. } | Protocol::Https { custom_domain, .. } => {
+                    custom_domain.iter().cloned().collect::<Vec<_>>()
+                }
+                Protocol::Tls { sni_patterns, .. }
+                | Protocol::TlsTerminated { sni_patterns, .. } => sni_patterns
+                    .iter()
+                    .filter(|p| *p != &self.domain && !p.ends_with(&relay_suffix))
+                    .cloned()
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        domains.sort();
+        domains.dedup();
+        domains
+    }
+
     async fn build_endpoints(
         &self,
         localup_id: &str,
+        client_id: Option<&str>,
         protocols: &[Protocol],
-        _config: &localup_proto::TunnelConfig,
+        config: &localup_proto::TunnelConfig,
         peer_addr: std::net::SocketAddr,
     ) -> Vec<Endpoint> {
         let mut endpoints = Vec::new();
@@ -1786,17 +2101,10 @@ impl TunnelHandler {
                     }
 
                     // No custom domain - use subdomain logic
-                    // Extract local port if available for sticky domain context
-                    let local_port = match protocol {
-                        Protocol::Http { .. } => None,
-                        Protocol::Https { .. } => None,
-                        _ => None,
-                    };
-
-                    // Build domain context for custom domain providers
+                    // Sticky domains are keyed by the authenticated identity and local port
                     let domain_context = DomainContext::new()
-                        .with_client_id(localup_id.to_string())
-                        .with_local_port(local_port.unwrap_or(0))
+                        .with_client_id(client_id.unwrap_or(localup_id).to_string())
+                        .with_local_port(config.local_port.unwrap_or(0))
                         .with_protocol(protocol_name.to_string());
 
                     // Use provided subdomain or generate via domain provider
@@ -1907,15 +2215,28 @@ impl TunnelHandler {
                         port: Some(*port),
                     });
                 }
-                Protocol::Tls { port, sni_patterns } => {
+                Protocol::Tls {
+                    port,
+                    sni_patterns,
+                    alpn,
+                }
+                | Protocol::TlsTerminated {
+                    port,
+                    sni_patterns,
+                    alpn,
+                    ..
+                } => {
                     // TLS endpoint - use actual relay TLS port if configured, otherwise use client's requested port
                     let actual_port = self.tls_port.unwrap_or(*port);
                     debug!(
                         "Building TLS endpoint: relay_port={:?}, client_port={}, actual_port={}, patterns={:?}",
                         self.tls_port, port, actual_port, sni_patterns
                     );
-                    // Display all SNI patterns
-                    let patterns_display = sni_patterns.join(", ");
+                    // Display all SNI patterns (and ALPN protocols when restricted)
+                    let mut patterns_display = sni_patterns.join(", ");
+                    if !alpn.is_empty() {
+                        patterns_display.push_str(&format!(", ALPN: {}", alpn.join(", ")));
+                    }
                     endpoints.push(Endpoint {
                         protocol: protocol.clone(),
                         public_url: format!(
@@ -1925,6 +2246,25 @@ impl TunnelHandler {
                         port: Some(actual_port),
                     });
                 }
+                Protocol::TcpNamed { hostname } => {
+                    // Bare names become subdomains of the relay domain
+                    let hostname = if hostname.contains('.') {
+                        hostname.to_lowercase()
+                    } else {
+                        format!("{}.{}", hostname.to_lowercase(), self.domain)
+                    };
+                    let public_url = match self.tcp_connect_port {
+                        Some(port) => {
+                            format!("tcp://{} (via proxy {}:{})", hostname, self.domain, port)
+                        }
+                        None => format!("tcp://{} (no CONNECT proxy on relay)", hostname),
+                    };
+                    endpoints.push(Endpoint {
+                        protocol: Protocol::TcpNamed { hostname },
+                        public_url,
+                        port: self.tcp_connect_port,
+                    });
+                }
             }
         }
 
@@ -1936,6 +2276,7 @@ impl TunnelHandler {
         localup_id: &str,
         endpoint: &Endpoint,
         ip_filter: IpFilter,
+        port_owner: &PortOwner,
     ) -> Result<Option<u16>, String> {
         match &endpoint.protocol {
             Protocol::Http {
@@ -2121,7 +2462,24 @@ impl TunnelHandler {
                     // Allocate a port for this TCP tunnel
                     // If port is 0, auto-allocate; otherwise try to allocate the specific port
                     let requested_port = if *port == 0 { None } else { Some(*port) };
-                    let allocated_port = allocator.allocate(localup_id, requested_port)?;
+                    let allocated_port = allocator
+                        .allocate_for(localup_id, requested_port, port_owner)
+                        .await?;
+                    if !port_owner.allows(allocated_port) {
+                        // Allocator does not know about token scopes
+                        allocator.deallocate(localup_id).await;
+                        return Err(format!(
+                            "TCP port {} is not available to this token (allowed: {})",
+                            allocated_port,
+                            port_owner
+                                .allowed_ports
+                                .iter()
+                                .flatten()
+                                .map(|r| r.to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ));
+                    }
 
                     if requested_port.is_some() {
                         info!(
@@ -2166,16 +2524,45 @@ impl TunnelHandler {
                     Err("TCP tunnels not supported (no port allocator)".to_string())
                 }
             }
-            Protocol::Tls { sni_patterns, .. } => {
+            Protocol::Tls {
+                sni_patterns, alpn, ..
+            }
+            | Protocol::TlsTerminated {
+                sni_patterns, alpn, ..
+            } => {
+                // Terminated routes are decrypted by the TLS server before entering the tunnel
+                let metadata = if matches!(endpoint.protocol, Protocol::TlsTerminated { .. }) {
+                    TLS_TERMINATE_METADATA
+                } else {
+                    "via-tunnel"
+                };
+
                 // Register TLS routes for all SNI patterns (supports multiple patterns including wildcards)
                 for sni_pattern in sni_patterns {
                     let route_target = RouteTarget {
                         localup_id: localup_id.to_string(),
                         target_addr: format!("tunnel:{}", localup_id), // Special marker for tunnel routing
-                        metadata: Some("via-tunnel".to_string()),
+                        metadata: Some(metadata.to_string()),
                         ip_filter: ip_filter.clone(),
                     };
 
+                    // ALPN-restricted tunnels only receive connections offering one of their
+                    // protocols; wildcard patterns are stored as-is and matched by the SNI router
+                    if !alpn.is_empty() {
+                        for protocol in alpn {
+                            let route_key =
+                                RouteKey::TlsSniAlpn(sni_pattern.clone(), protocol.clone());
+                            self.route_registry
+                                .register(route_key, route_target.clone())
+                                .map_err(|e| e.to_string())?;
+                            debug!(
+                                "Registered TLS route for SNI {} with ALPN {} -> tunnel:{}",
+                                sni_pattern, protocol, localup_id
+                            );
+                        }
+                        continue;
+                    }
+
                     // Check if this is a wildcard pattern (e.g., *.example.com)
                     if WildcardPattern::is_wildcard_pattern(sni_pattern) {
                         // Register as wildcard for pattern matching
@@ -2216,6 +2603,115 @@ impl TunnelHandler {
                 }
                 Ok(None)
             }
+            Protocol::TcpNamed { hostname } => {
+                if self.tcp_connect_port.is_none() {
+                    return Err(
+                        "Hostname TCP tunnels not supported (no CONNECT proxy on relay)"
+                            .to_string(),
+                    );
+                }
+
+                let route_key = RouteKey::TcpHost(hostname.clone());
+                if let Ok(existing_target) = self.route_registry.lookup(&route_key) {
+                    if existing_target.localup_id != localup_id {
+                        return Err(format!(
+                            "TCP hostname '{}' is already taken by another tunnel",
+                            hostname
+                        ));
+                    }
+                    // Same tunnel reconnecting
+                    let _ = self.route_registry.unregister(&route_key);
+                }
+
+                let route_target = RouteTarget {
+                    localup_id: localup_id.to_string(),
+                    target_addr: format!("tunnel:{}", localup_id),
+                    metadata: Some("via-tunnel".to_string()),
+                    ip_filter,
+                };
+                self.route_registry
+                    .register(route_key, route_target)
+                    .map_err(|e| e.to_string())?;
+                info!(
+                    "✅ Registered TCP hostname route: {} -> tunnel:{}",
+                    hostname, localup_id
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Routes of an endpoint shared with the cluster
+    fn cluster_route_keys(&self, endpoint: &Endpoint) -> Vec<RouteKey> {
+        match &endpoint.protocol {
+            Protocol::Http {
+                subdomain,
+                custom_domain,
+            }
+            | Protocol::Https {
+                subdomain,
+                custom_domain,
+            } => custom_domain
+                .clone()
+                .or_else(|| {
+                    subdomain
+                        .as_ref()
+                        .map(|sub| format!("{}.{}", sub, self.domain))
+                })
+                .map(RouteKey::HttpHost)
+                .into_iter()
+                .collect(),
+            Protocol::Tls {
+                sni_patterns, alpn, ..
+            }
+            | Protocol::TlsTerminated {
+                sni_patterns, alpn, ..
+            } => sni_patterns
+                .iter()
+                .flat_map(|sni| {
+                    if alpn.is_empty() {
+                        vec![RouteKey::TlsSni(sni.clone())]
+                    } else {
+                        alpn.iter()
+                            .map(|protocol| RouteKey::TlsSniAlpn(sni.clone(), protocol.clone()))
+                            .collect()
+                    }
+                })
+                .collect(),
+            Protocol::Tcp { .. } | Protocol::TcpNamed { .. } => Vec::new(),
+        }
+    }
+
+    /// Claim an endpoint's routes in the cluster, releasing the tunnel's claims on failure
+    async fn claim_cluster_routes(
+        &self,
+        localup_id: &str,
+        endpoint: &Endpoint,
+    ) -> Result<(), String> {
+        let Some(ref cluster) = self.cluster else {
+            return Ok(());
+        };
+        for key in self.cluster_route_keys(endpoint) {
+            if let Err(e) = cluster.claim(&key, localup_id).await {
+                warn!(
+                    "Failed to claim cluster route {:?} for tunnel {}: {}",
+                    key, localup_id, e
+                );
+                self.release_cluster_routes(localup_id).await;
+                return Err(e.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    async fn release_cluster_routes(&self, localup_id: &str) {
+        if let Some(ref cluster) = self.cluster {
+            if let Err(e) = cluster.release(localup_id).await {
+                warn!(
+                    "Failed to release cluster routes of tunnel {}: {}",
+                    localup_id, e
+                );
+            }
         }
     }
 
@@ -2281,14 +2777,26 @@ impl TunnelHandler {
 
                 // 3. NOW deallocate the port - socket should be released by now
                 if let Some(ref allocator) = self.port_allocator {
-                    allocator.deallocate(localup_id);
+                    allocator.deallocate(localup_id).await;
                     info!("Deallocated TCP port for tunnel {}", localup_id);
                 }
             }
-            Protocol::Tls { sni_patterns, .. } => {
+            Protocol::Tls {
+                sni_patterns, alpn, ..
+            }
+            | Protocol::TlsTerminated {
+                sni_patterns, alpn, ..
+            } => {
                 // Unregister all SNI patterns for this tunnel
                 for sni_pattern in sni_patterns {
-                    if WildcardPattern::is_wildcard_pattern(sni_pattern) {
+                    if !alpn.is_empty() {
+                        for protocol in alpn {
+                            let route_key =
+                                RouteKey::TlsSniAlpn(sni_pattern.clone(), protocol.clone());
+                            let _ = self.route_registry.unregister(&route_key);
+                        }
+                        debug!("Unregistered TLS ALPN routes for SNI: {}", sni_pattern);
+                    } else if WildcardPattern::is_wildcard_pattern(sni_pattern) {
                         // Unregister wildcard pattern
                         let _ = self.route_registry.unregister_wildcard(sni_pattern);
                         debug!(
@@ -2303,6 +2811,18 @@ impl TunnelHandler {
                     }
                 }
             }
+            Protocol::TcpNamed { hostname } => {
+                let route_key = RouteKey::TcpHost(hostname.clone());
+                if let Ok(target) = self.route_registry.lookup(&route_key) {
+                    if target.localup_id == localup_id {
+                        let _ = self.route_registry.unregister(&route_key);
+                        info!(
+                            "🗑️  Unregistered TCP hostname route: {} (tunnel: {})",
+                            hostname, localup_id
+                        );
+                    }
+                }
+            }
         }
     }
 }
@@ -2392,7 +2912,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2425,7 +2945,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2466,7 +2986,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2496,7 +3016,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2504,6 +3024,72 @@ mod tests {
         assert_eq!(endpoints[0].port, Some(8080));
     }
 
+    #[tokio::test]
+    async fn test_tcp_named_route() {
+        let connection_manager = Arc::new(TunnelConnectionManager::new());
+        let route_registry = Arc::new(RouteRegistry::new());
+        let pending_requests = Arc::new(PendingRequests::new());
+
+        let handler = TunnelHandler::new(
+            connection_manager,
+            route_registry.clone(),
+            None,
+            "tunnel.test".to_string(),
+            pending_requests,
+        )
+        .with_tcp_connect_port(8080);
+
+        let protocols = vec![Protocol::TcpNamed {
+            hostname: "DB".to_string(),
+        }];
+        let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
+        let endpoints = handler
+            .build_endpoints(
+                "db-tunnel",
+                None,
+                &protocols,
+                &TunnelConfig::default(),
+                mock_peer_addr,
+            )
+            .await;
+
+        assert_eq!(
+            endpoints[0].protocol,
+            Protocol::TcpNamed {
+                hostname: "db.tunnel.test".to_string()
+            }
+        );
+        assert_eq!(endpoints[0].port, Some(8080));
+
+        let result = handler
+            .register_route(
+                "db-tunnel",
+                &endpoints[0],
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
+            .await;
+        assert_eq!(result.unwrap(), None);
+        let target = route_registry
+            .lookup(&RouteKey::TcpHost("db.tunnel.test".to_string()))
+            .unwrap();
+        assert_eq!(target.target_addr, "tunnel:db-tunnel");
+
+        // Another tunnel cannot take the same hostname
+        assert!(handler
+            .register_route(
+                "other-tunnel",
+                &endpoints[0],
+                IpFilter::new(),
+                &PortOwner::default()
+            )
+            .await
+            .is_err());
+
+        handler.unregister_route("db-tunnel", &endpoints[0]).await;
+        assert_eq!(route_registry.count(), 0);
+    }
+
     #[tokio::test]
     async fn test_build_endpoints_multiple_protocols() {
         let connection_manager = Arc::new(TunnelConnectionManager::new());
@@ -2534,7 +3120,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 3);
@@ -2561,12 +3147,13 @@ mod tests {
         let protocols = vec![Protocol::Tls {
             port: 443,
             sni_patterns: vec!["*.example.com".to_string()],
+            alpn: vec![],
         }];
         let config = TunnelConfig::default();
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2577,15 +3164,16 @@ mod tests {
     #[test]
     fn test_handler_with_port_allocator() {
         struct MockPortAllocator;
+        #[async_trait]
         impl PortAllocator for MockPortAllocator {
-            fn allocate(
+            async fn allocate(
                 &self,
                 _localup_id: &str,
                 _requested_port: Option<u16>,
             ) -> Result<u16, String> {
                 Ok(9000)
             }
-            fn deallocate(&self, _localup_id: &str) {}
+            async fn deallocate(&self, _localup_id: &str) {}
             fn get_allocated_port(&self, _localup_id: &str) -> Option<u16> {
                 Some(9000)
             }
@@ -2652,7 +3240,12 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), None); // HTTP doesn't return allocated port
@@ -2686,7 +3279,12 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result.is_ok());
 
@@ -2716,7 +3314,12 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("not supported"));
@@ -2725,15 +3328,16 @@ mod tests {
     #[tokio::test]
     async fn test_register_route_tcp_with_allocator() {
         struct MockPortAllocator;
+        #[async_trait]
         impl PortAllocator for MockPortAllocator {
-            fn allocate(
+            async fn allocate(
                 &self,
                 _localup_id: &str,
                 _requested_port: Option<u16>,
             ) -> Result<u16, String> {
                 Ok(9000)
             }
-            fn deallocate(&self, _localup_id: &str) {}
+            async fn deallocate(&self, _localup_id: &str) {}
             fn get_allocated_port(&self, _localup_id: &str) -> Option<u16> {
                 Some(9000)
             }
@@ -2760,12 +3364,167 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), Some(9000));
     }
 
+    #[tokio::test]
+    async fn test_register_route_tcp_outside_token_ports() {
+        struct MockPortAllocator {
+            released: std::sync::atomic::AtomicBool,
+        }
+        #[async_trait]
+        impl PortAllocator for MockPortAllocator {
+            async fn allocate(
+                &self,
+                _localup_id: &str,
+                _requested_port: Option<u16>,
+            ) -> Result<u16, String> {
+                Ok(9000)
+            }
+            async fn deallocate(&self, _localup_id: &str) {
+                self.released
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+            fn get_allocated_port(&self, _localup_id: &str) -> Option<u16> {
+                None
+            }
+        }
+
+        let allocator = Arc::new(MockPortAllocator {
+            released: std::sync::atomic::AtomicBool::new(false),
+        });
+        let handler = TunnelHandler::new(
+            Arc::new(TunnelConnectionManager::new()),
+            Arc::new(RouteRegistry::new()),
+            None,
+            "tunnel.test".to_string(),
+            Arc::new(PendingRequests::new()),
+        )
+        .with_port_allocator(allocator.clone());
+
+        let endpoint = Endpoint {
+            protocol: Protocol::Tcp { port: 0 },
+            public_url: "tcp://tunnel.test:0".to_string(),
+            port: Some(0),
+        };
+        let owner = PortOwner {
+            allowed_ports: Some(vec!["10000-10100".parse().unwrap()]),
+            ..Default::default()
+        };
+
+        let err = handler
+            .register_route("test-tunnel", &endpoint, IpFilter::new(), &owner)
+            .await
+            .unwrap_err();
+        assert!(err.contains("not available to this token"));
+        assert!(allocator.released.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_register_route_tls_terminated() {
+        let connection_manager = Arc::new(TunnelConnectionManager::new());
+        let route_registry = Arc::new(RouteRegistry::new());
+        let pending_requests = Arc::new(PendingRequests::new());
+
+        let handler = TunnelHandler::new(
+            connection_manager,
+            route_registry.clone(),
+            None,
+            "tunnel.test".to_string(),
+            pending_requests,
+        );
+
+        let localup_id = "test-tunnel";
+        let endpoint = Endpoint {
+            protocol: Protocol::TlsTerminated {
+                port: 443,
+                sni_patterns: vec!["mqtt.example.com".to_string()],
+                reencrypt: false,
+                alpn: vec![],
+            },
+            public_url: "tls://tunnel.test:443 (SNI: mqtt.example.com)".to_string(),
+            port: Some(443),
+        };
+
+        let result = handler
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
+            .await;
+        assert_eq!(result.unwrap(), None);
+
+        let target = route_registry
+            .lookup(&RouteKey::TlsSni("mqtt.example.com".to_string()))
+            .unwrap();
+        assert_eq!(target.target_addr, "tunnel:test-tunnel");
+        assert!(target.terminates_tls());
+
+        handler.unregister_route(localup_id, &endpoint).await;
+        assert_eq!(route_registry.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_register_route_tls_alpn() {
+        let connection_manager = Arc::new(TunnelConnectionManager::new());
+        let route_registry = Arc::new(RouteRegistry::new());
+        let pending_requests = Arc::new(PendingRequests::new());
+
+        let handler = TunnelHandler::new(
+            connection_manager,
+            route_registry.clone(),
+            None,
+            "tunnel.test".to_string(),
+            pending_requests,
+        );
+
+        let localup_id = "broker-tunnel";
+        let endpoint = Endpoint {
+            protocol: Protocol::Tls {
+                port: 443,
+                sni_patterns: vec!["*.iot.example.com".to_string()],
+                alpn: vec!["mqtt".to_string()],
+            },
+            public_url: "tls://tunnel.test:443 (SNI: *.iot.example.com, ALPN: mqtt)".to_string(),
+            port: Some(443),
+        };
+
+        let result = handler
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
+            .await;
+        assert_eq!(result.unwrap(), None);
+
+        // Only the ALPN route exists; the hostname stays free for other protocols
+        let target = route_registry
+            .lookup(&RouteKey::TlsSniAlpn(
+                "*.iot.example.com".to_string(),
+                "mqtt".to_string(),
+            ))
+            .unwrap();
+        assert_eq!(target.target_addr, "tunnel:broker-tunnel");
+        assert!(route_registry
+            .lookup(&RouteKey::TlsSni("*.iot.example.com".to_string()))
+            .is_err());
+
+        handler.unregister_route(localup_id, &endpoint).await;
+        assert_eq!(route_registry.count(), 0);
+    }
+
     #[tokio::test]
     async fn test_unregister_route_http() {
         let connection_manager = Arc::new(TunnelConnectionManager::new());
@@ -2792,7 +3551,12 @@ mod tests {
 
         // Register first
         handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await
             .unwrap();
         assert_eq!(route_registry.count(), 1);
@@ -2807,15 +3571,16 @@ mod tests {
         struct MockPortAllocator {
             deallocated: Arc<std::sync::Mutex<bool>>,
         }
+        #[async_trait]
         impl PortAllocator for MockPortAllocator {
-            fn allocate(
+            async fn allocate(
                 &self,
                 _localup_id: &str,
                 _requested_port: Option<u16>,
             ) -> Result<u16, String> {
                 Ok(9000)
             }
-            fn deallocate(&self, _localup_id: &str) {
+            async fn deallocate(&self, _localup_id: &str) {
                 *self.deallocated.lock().unwrap() = true;
             }
             fn get_allocated_port(&self, _localup_id: &str) -> Option<u16> {
@@ -2881,7 +3646,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2915,7 +3680,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2949,7 +3714,7 @@ mod tests {
 
         let mock_peer_addr = "127.0.0.1:12345".parse().unwrap();
         let endpoints = handler
-            .build_endpoints(localup_id, &protocols, &config, mock_peer_addr)
+            .build_endpoints(localup_id, None, &protocols, &config, mock_peer_addr)
             .await;
 
         assert_eq!(endpoints.len(), 1);
@@ -2986,7 +3751,12 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), None);
@@ -3024,7 +3794,12 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result.is_ok());
 
@@ -3059,7 +3834,12 @@ mod tests {
             port: None,
         };
         let result1 = handler
-            .register_route("tunnel-1", &endpoint1, IpFilter::new())
+            .register_route(
+                "tunnel-1",
+                &endpoint1,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result1.is_ok());
 
@@ -3073,7 +3853,12 @@ mod tests {
             port: None,
         };
         let result2 = handler
-            .register_route("tunnel-2", &endpoint2, IpFilter::new())
+            .register_route(
+                "tunnel-2",
+                &endpoint2,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         assert!(result2.is_err());
         assert!(result2.unwrap_err().contains("already in use"));
@@ -3105,7 +3890,12 @@ mod tests {
 
         // Register first
         handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await
             .unwrap();
         assert_eq!(route_registry.count(), 1);
@@ -3145,7 +3935,12 @@ mod tests {
         };
 
         let result = handler
-            .register_route(localup_id, &endpoint, IpFilter::new())
+            .register_route(
+                localup_id,
+                &endpoint,
+                IpFilter::new(),
+                &PortOwner::default(),
+            )
             .await;
         // This should fail because neither subdomain nor custom_domain is provided
         assert!(result.is_err());
@@ -1,22 +1,51 @@
 //! Control plane for tunnel orchestration
 pub mod agent_registry;
+pub mod cluster;
 pub mod connection;
 pub mod domain_provider;
 pub mod handler;
+pub mod metering;
 pub mod pending_requests;
+pub mod port_assignments;
+pub mod quotas;
 pub mod registry;
+pub mod revocation;
+pub mod share_links;
 pub mod task_tracker;
+pub mod tcp_limits;
+pub mod team_accounts;
+pub mod token_validator;
+pub mod tunnel_slots;
 
 pub use agent_registry::{AgentRegistry, RegisteredAgent};
+pub use cluster::{
+    ClusterConfig, ClusterError, ClusterIngress, ClusterNode, ClusterPeer, ForwardKind,
+    ForwardedStream,
+};
 pub use connection::{
     AgentConnection, AgentConnectionManager, TcpDataCallback, TunnelConnection,
-    TunnelConnectionManager,
+    TunnelConnectionManager, TunnelOwner,
 };
 pub use domain_provider::{
     DomainContext, DomainProvider, DomainProviderError, RestrictedDomainProvider,
     SimpleCounterDomainProvider,
 };
-pub use handler::{PortAllocator, TcpProxySpawner, TunnelHandler};
+pub use handler::{PortAllocator, PortOwner, TcpProxySpawner, TunnelHandler};
+pub use metering::{MeterKey, TrafficMeter, TunnelMeter};
 pub use pending_requests::PendingRequests;
+pub use port_assignments::DbPortAllocator;
+pub use quotas::{
+    AccountUsage, QuotaAccount, QuotaEnforcer, QuotaExceeded, QuotaLimits, TunnelQuota,
+    VisitorPermit,
+};
 pub use registry::ControlPlane;
+pub use revocation::{RevocationCheckingValidator, RevocationList};
+pub use share_links::{ShareAccess, ShareLinkGate};
 pub use task_tracker::TaskTracker;
+pub use tcp_limits::{TcpConnectionLimiter, TcpConnectionPermit, TcpLimitExceeded};
+pub use team_accounts::TeamAccountVerifier;
+pub use token_validator::RelayTokenValidator;
+pub use tunnel_slots::{TunnelSlot, TunnelSlots};
+
+// Re-exported for PortAllocator implementations
+pub use async_trait::async_trait;
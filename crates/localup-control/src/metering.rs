@@ -0,0 +1,575 @@
+//! Traffic metering for billing
+//!
+//! Visitor traffic is counted per series: the tunnel, the auth token it
+//! connected with, and the user and team that own it. Each series counts
+//! bytes in and out, requests, connections and connection time in hourly
+//! buckets, which [`TrafficMeter::flush`] adds to `traffic_rollups`. Metering
+//! is independent of request capture, so totals stay complete when capture is
+//! turned off.
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use localup_relay_db::entities::{prelude::TrafficRollup, traffic_rollup};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Tunnel, token, user and team that traffic is counted for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MeterKey {
+    pub localup_id: String,
+    /// SHA-256 of the auth token (matches `auth_tokens.token_hash`)
+    pub token_hash: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub team_id: Option<Uuid>,
+}
+
+impl MeterKey {
+    /// Key for a tunnel; user and team IDs that are not relay UUIDs are left out
+    pub fn for_tunnel(
+        localup_id: &str,
+        auth_token: Option<&str>,
+        user_id: Option<&str>,
+        team_id: Option<&str>,
+    ) -> Self {
+        Self {
+            localup_id: localup_id.to_string(),
+            token_hash: auth_token.map(|token| format!("{:x}", Sha256::digest(token.as_bytes()))),
+            user_id: user_id.and_then(|id| Uuid::parse_str(id).ok()),
+            team_id: team_id.and_then(|id| Uuid::parse_str(id).ok()),
+        }
+    }
+
+    /// Identifies the key in `traffic_rollups.series`
+    pub fn series(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.localup_id.as_bytes());
+        for part in [
+            self.token_hash.clone(),
+            self.user_id.map(|id| id.to_string()),
+            self.team_id.map(|id| id.to_string()),
+        ] {
+            hasher.update([0]);
+            hasher.update(part.unwrap_or_default().as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Traffic counted for a series
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MeterCounts {
+    /// Bytes from visitors to the tunnel
+    pub bytes_in: u64,
+    /// Bytes from the tunnel to visitors
+    pub bytes_out: u64,
+    /// HTTP requests handled by the relay
+    pub requests: u64,
+    /// Visitor connections opened
+    pub connections: u64,
+    /// Time visitor connections were open, summed over connections
+    pub connection_millis: u64,
+}
+
+impl MeterCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn add(&mut self, other: &MeterCounts) {
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.requests += other.requests;
+        self.connections += other.connections;
+        self.connection_millis += other.connection_millis;
+    }
+
+    fn subtract(&mut self, other: &MeterCounts) {
+        self.bytes_in = self.bytes_in.saturating_sub(other.bytes_in);
+        self.bytes_out = self.bytes_out.saturating_sub(other.bytes_out);
+        self.requests = self.requests.saturating_sub(other.requests);
+        self.connections = self.connections.saturating_sub(other.connections);
+        self.connection_millis = self
+            .connection_millis
+            .saturating_sub(other.connection_millis);
+    }
+}
+
+impl From<&traffic_rollup::Model> for MeterCounts {
+    fn from(rollup: &traffic_rollup::Model) -> Self {
+        Self {
+            bytes_in: rollup.bytes_in.max(0) as u64,
+            bytes_out: rollup.bytes_out.max(0) as u64,
+            requests: rollup.requests.max(0) as u64,
+            connections: rollup.connections.max(0) as u64,
+            connection_millis: rollup.connection_millis.max(0) as u64,
+        }
+    }
+}
+
+/// Traffic of one series in one hour
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageSample {
+    pub hour: DateTime<Utc>,
+    pub key: MeterKey,
+    pub counts: MeterCounts,
+}
+
+impl From<&traffic_rollup::Model> for UsageSample {
+    fn from(rollup: &traffic_rollup::Model) -> Self {
+        Self {
+            hour: rollup.hour,
+            key: MeterKey {
+                localup_id: rollup.localup_id.clone(),
+                token_hash: rollup.token_hash.clone(),
+                user_id: rollup.user_id,
+                team_id: rollup.team_id,
+            },
+            counts: MeterCounts::from(rollup),
+        }
+    }
+}
+
+/// Whose traffic a usage query covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageScope {
+    /// Every tunnel on the relay
+    All,
+    /// Tunnels owned by a user, including those opened for a team
+    User(Uuid),
+    /// Tunnels opened for a team
+    Team(Uuid),
+}
+
+impl UsageScope {
+    pub fn matches(&self, key: &MeterKey) -> bool {
+        match self {
+            UsageScope::All => true,
+            UsageScope::User(id) => key.user_id == Some(*id),
+            UsageScope::Team(id) => key.team_id == Some(*id),
+        }
+    }
+}
+
+/// Start of the hour that `time` falls in
+pub fn hour_of(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.duration_trunc(TimeDelta::hours(1)).unwrap_or(time)
+}
+
+/// Rollups in `traffic_rollups` for hours starting in `[from, to)`
+pub async fn load_rollups(
+    db: &DatabaseConnection,
+    scope: UsageScope,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<UsageSample>, DbErr> {
+    let scope_filter = match scope {
+        UsageScope::All => Condition::all(),
+        UsageScope::User(id) => Condition::all().add(traffic_rollup::Column::UserId.eq(id)),
+        UsageScope::Team(id) => Condition::all().add(traffic_rollup::Column::TeamId.eq(id)),
+    };
+    let rollups = TrafficRollup::find()
+        .filter(traffic_rollup::Column::Hour.gte(from))
+        .filter(traffic_rollup::Column::Hour.lt(to))
+        .filter(scope_filter)
+        .order_by_asc(traffic_rollup::Column::Hour)
+        .all(db)
+        .await?;
+    Ok(rollups.iter().map(UsageSample::from).collect())
+}
+
+/// Add counts to a series' rollup for an hour
+async fn add_rollup(
+    db: &DatabaseConnection,
+    key: &MeterKey,
+    hour: DateTime<Utc>,
+    counts: &MeterCounts,
+) -> Result<(), DbErr> {
+    let row = traffic_rollup::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        hour: Set(hour),
+        series: Set(key.series()),
+        localup_id: Set(key.localup_id.clone()),
+        token_hash: Set(key.token_hash.clone()),
+        user_id: Set(key.user_id),
+        team_id: Set(key.team_id),
+        bytes_in: Set(counts.bytes_in as i64),
+        bytes_out: Set(counts.bytes_out as i64),
+        requests: Set(counts.requests as i64),
+        connections: Set(counts.connections as i64),
+        connection_millis: Set(counts.connection_millis as i64),
+        updated_at: Set(Utc::now()),
+    };
+
+    let add = |column: traffic_rollup::Column, value: u64| {
+        Expr::col((TrafficRollup, column)).add(value as i64)
+    };
+    TrafficRollup::insert(row)
+        .on_conflict(
+            OnConflict::columns([traffic_rollup::Column::Hour, traffic_rollup::Column::Series])
+                .value(
+                    traffic_rollup::Column::BytesIn,
+                    add(traffic_rollup::Column::BytesIn, counts.bytes_in),
+                )
+                .value(
+                    traffic_rollup::Column::BytesOut,
+                    add(traffic_rollup::Column::BytesOut, counts.bytes_out),
+                )
+                .value(
+                    traffic_rollup::Column::Requests,
+                    add(traffic_rollup::Column::Requests, counts.requests),
+                )
+                .value(
+                    traffic_rollup::Column::Connections,
+                    add(traffic_rollup::Column::Connections, counts.connections),
+                )
+                .value(
+                    traffic_rollup::Column::ConnectionMillis,
+                    add(
+                        traffic_rollup::Column::ConnectionMillis,
+                        counts.connection_millis,
+                    ),
+                )
+                .update_column(traffic_rollup::Column::UpdatedAt)
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+struct SeriesState {
+    /// Open visitor connections
+    open: u32,
+    /// Connection time is counted up to here
+    accrued_at: DateTime<Utc>,
+    /// Counts not yet written to the database, by hour
+    hours: BTreeMap<DateTime<Utc>, MeterCounts>,
+}
+
+impl SeriesState {
+    fn bucket(&mut self, now: DateTime<Utc>) -> &mut MeterCounts {
+        self.hours.entry(hour_of(now)).or_default()
+    }
+
+    /// Count the time open connections spent since the last call, split by hour
+    fn accrue(&mut self, now: DateTime<Utc>) {
+        if self.open == 0 {
+            self.accrued_at = now;
+            return;
+        }
+        let mut from = self.accrued_at;
+        while from < now {
+            let hour = hour_of(from);
+            let until = (hour + TimeDelta::hours(1)).min(now);
+            let millis = (until - from).num_milliseconds().max(0) as u64;
+            self.hours.entry(hour).or_default().connection_millis += millis * self.open as u64;
+            from = until;
+        }
+        self.accrued_at = self.accrued_at.max(now);
+    }
+}
+
+struct Series {
+    key: MeterKey,
+    state: Mutex<SeriesState>,
+}
+
+impl Series {
+    fn record_at(&self, now: DateTime<Utc>, inbound: u64, outbound: u64) {
+        let mut state = self.state.lock().unwrap();
+        let bucket = state.bucket(now);
+        bucket.bytes_in += inbound;
+        bucket.bytes_out += outbound;
+    }
+
+    fn record_request_at(&self, now: DateTime<Utc>) {
+        self.state.lock().unwrap().bucket(now).requests += 1;
+    }
+
+    fn open_at(&self, now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        state.accrue(now);
+        state.open += 1;
+        state.bucket(now).connections += 1;
+    }
+
+    fn close_at(&self, now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        state.accrue(now);
+        state.open = state.open.saturating_sub(1);
+    }
+
+    /// Unwritten counts, with connection time up to `now`
+    fn pending_at(&self, now: DateTime<Utc>) -> Vec<(DateTime<Utc>, MeterCounts)> {
+        let mut state = self.state.lock().unwrap();
+        state.accrue(now);
+        state
+            .hours
+            .iter()
+            .filter(|(_, counts)| !counts.is_empty())
+            .map(|(hour, counts)| (*hour, *counts))
+            .collect()
+    }
+
+    /// Forget counts that were written to the database
+    fn written(&self, hour: DateTime<Utc>, counts: &MeterCounts) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(bucket) = state.hours.get_mut(&hour) {
+            bucket.subtract(counts);
+            if bucket.is_empty() {
+                state.hours.remove(&hour);
+            }
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.open == 0 && state.hours.is_empty()
+    }
+}
+
+/// Counts the traffic of one tunnel
+#[derive(Clone)]
+pub struct TunnelMeter {
+    series: Arc<Series>,
+}
+
+impl TunnelMeter {
+    pub fn key(&self) -> &MeterKey {
+        &self.series.key
+    }
+
+    /// Count traffic (inbound = visitor to tunnel)
+    pub fn record(&self, inbound: u64, outbound: u64) {
+        self.series.record_at(Utc::now(), inbound, outbound);
+    }
+
+    /// Count an HTTP request handled by the relay
+    pub fn record_request(&self) {
+        self.series.record_request_at(Utc::now());
+    }
+
+    /// Start timing a visitor connection, stopped when the result is dropped
+    pub fn open_connection(&self) -> MeteredConnection {
+        self.series.open_at(Utc::now());
+        MeteredConnection {
+            meter: self.clone(),
+        }
+    }
+}
+
+/// A visitor connection opened with [`TunnelMeter::open_connection`]
+pub struct MeteredConnection {
+    meter: TunnelMeter,
+}
+
+impl MeteredConnection {
+    pub fn meter(&self) -> &TunnelMeter {
+        &self.meter
+    }
+}
+
+impl Drop for MeteredConnection {
+    fn drop(&mut self) {
+        self.meter.series.close_at(Utc::now());
+    }
+}
+
+/// Meters tunnel traffic and writes hourly rollups to the database
+pub struct TrafficMeter {
+    db: DatabaseConnection,
+    series: Mutex<HashMap<MeterKey, Arc<Series>>>,
+}
+
+impl TrafficMeter {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            series: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Meter for a connected tunnel
+    ///
+    /// A tunnel reconnecting with the same token continues its series.
+    pub fn tunnel(&self, key: MeterKey) -> TunnelMeter {
+        let series = self
+            .series
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(Series {
+                    key,
+                    state: Mutex::new(SeriesState {
+                        open: 0,
+                        accrued_at: Utc::now(),
+                        hours: BTreeMap::new(),
+                    }),
+                })
+            })
+            .clone();
+        TunnelMeter { series }
+    }
+
+    /// Counts not yet written to the database
+    pub fn pending(&self) -> Vec<UsageSample> {
+        let now = Utc::now();
+        let series: Vec<Arc<Series>> = self.series.lock().unwrap().values().cloned().collect();
+        series
+            .iter()
+            .flat_map(|series| {
+                series
+                    .pending_at(now)
+                    .into_iter()
+                    .map(|(hour, counts)| UsageSample {
+                        hour,
+                        key: series.key.clone(),
+                        counts,
+                    })
+            })
+            .collect()
+    }
+
+    /// Add counted traffic to `traffic_rollups` and forget finished tunnels
+    pub async fn flush(&self) -> Result<(), DbErr> {
+        let now = Utc::now();
+        let series: Vec<Arc<Series>> = self.series.lock().unwrap().values().cloned().collect();
+
+        for series in series {
+            for (hour, counts) in series.pending_at(now) {
+                add_rollup(&self.db, &series.key, hour, &counts).await?;
+                series.written(hour, &counts);
+            }
+        }
+
+        // Series of disconnected tunnels are dropped once everything is written
+        self.series
+            .lock()
+            .unwrap()
+            .retain(|_, series| Arc::strong_count(series) > 1 || !series.is_idle());
+        Ok(())
+    }
+
+    /// Flush traffic every `interval` until the meter is dropped
+    pub fn spawn_flush_task(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let meter = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(meter) = meter.upgrade() else {
+                    break;
+                };
+                if let Err(e) = meter.flush().await {
+                    warn!("Failed to record traffic rollups: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn key(localup_id: &str) -> MeterKey {
+        MeterKey::for_tunnel(
+            localup_id,
+            Some("token"),
+            Some(&Uuid::new_v4().to_string()),
+            None,
+        )
+    }
+
+    fn series() -> Series {
+        Series {
+            key: key("tunnel-a"),
+            state: Mutex::new(SeriesState {
+                open: 0,
+                accrued_at: Utc::now(),
+                hours: BTreeMap::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_connection_time_is_split_by_hour() {
+        let series = series();
+        let ten = Utc.with_ymd_and_hms(2026, 10, 18, 10, 0, 0).unwrap();
+        let eleven = ten + TimeDelta::hours(1);
+
+        series.open_at(ten + TimeDelta::minutes(50));
+        series.open_at(ten + TimeDelta::minutes(55));
+        series.close_at(eleven + TimeDelta::minutes(5));
+        series.record_at(eleven + TimeDelta::minutes(6), 100, 200);
+
+        let pending = series.pending_at(eleven + TimeDelta::minutes(10));
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].0, ten);
+        assert_eq!(pending[0].1.connections, 2);
+        // 10 + 5 minutes before 11:00
+        assert_eq!(pending[0].1.connection_millis, 15 * 60_000);
+        assert_eq!(pending[1].0, eleven);
+        // 5 + 10 minutes after 11:00
+        assert_eq!(pending[1].1.connection_millis, 15 * 60_000);
+        assert_eq!((pending[1].1.bytes_in, pending[1].1.bytes_out), (100, 200));
+    }
+
+    #[test]
+    fn test_series_identifies_key() {
+        let a = key("tunnel-a");
+        let mut b = a.clone();
+        assert_eq!(a.series(), b.series());
+        b.team_id = Some(Uuid::new_v4());
+        assert_ne!(a.series(), b.series());
+        assert_eq!(a.series().len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_flush_adds_to_rollups() {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+        let meter = TrafficMeter::new(db.clone());
+        let tunnel = meter.tunnel(key("tunnel-a"));
+
+        let connection = tunnel.open_connection();
+        tunnel.record(10, 20);
+        tunnel.record_request();
+        meter.flush().await.unwrap();
+        tunnel.record(5, 0);
+        drop(connection);
+        meter.flush().await.unwrap();
+        assert!(meter.pending().is_empty());
+
+        let now = Utc::now();
+        let rollups = load_rollups(
+            &db,
+            UsageScope::All,
+            now - TimeDelta::hours(2),
+            now + TimeDelta::hours(1),
+        )
+        .await
+        .unwrap();
+        let total = rollups.iter().fold(MeterCounts::default(), |mut total, s| {
+            total.add(&s.counts);
+            total
+        });
+        assert_eq!((total.bytes_in, total.bytes_out), (15, 20));
+        assert_eq!((total.requests, total.connections), (1, 1));
+
+        // Series are dropped once the tunnel is gone and all traffic is written
+        drop(tunnel);
+        meter.flush().await.unwrap();
+        assert!(meter.series.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,459 @@
+//! Database-backed TCP port allocation
+//!
+//! Ports are recorded in `tcp_port_assignments` so a tunnel gets the same public port
+//! after the relay restarts. Assignments are keyed on the token's user and the tunnel's
+//! local port (or on the tunnel ID when the token carries no user). Sticky assignments
+//! that go unused for the retention period are handed out again; explicit reservations
+//! made through the API are kept until deleted.
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use localup_relay_db::entities::{prelude::TcpPortAssignment, tcp_port_assignment};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::handler::{PortAllocator, PortOwner};
+
+/// How long an unused sticky assignment keeps its port by default
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Port allocator that persists port ownership in the relay database
+pub struct DbPortAllocator {
+    db: DatabaseConnection,
+    range_start: u16,
+    range_end: u16,
+    retention: Duration,
+    /// Ports held by connected tunnels (localup_id -> port)
+    active: Mutex<HashMap<String, u16>>,
+    /// Serializes allocations so two tunnels cannot pick the same free port
+    allocation_lock: tokio::sync::Mutex<()>,
+}
+
+impl DbPortAllocator {
+    pub fn new(db: DatabaseConnection, range_start: u16, range_end: u16) -> Self {
+        Self {
+            db,
+            range_start,
+            range_end,
+            retention: Duration::days(DEFAULT_RETENTION_DAYS),
+            active: Mutex::new(HashMap::new()),
+            allocation_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Set how long an unused sticky assignment keeps its port
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Check if a port is actually available at the OS level
+    fn is_port_available(port: u16) -> bool {
+        std::net::TcpListener::bind(("0.0.0.0", port)).is_ok()
+    }
+
+    fn in_range(&self, port: u16) -> bool {
+        (self.range_start..=self.range_end).contains(&port)
+    }
+
+    fn is_active(&self, port: u16) -> bool {
+        self.active.lock().unwrap().values().any(|&p| p == port)
+    }
+
+    /// Whether an assignment still belongs to its owner
+    fn is_held(&self, assignment: &tcp_port_assignment::Model) -> bool {
+        let last_used = assignment.last_used_at.unwrap_or(assignment.created_at);
+        assignment.reserved || last_used + self.retention > Utc::now()
+    }
+
+    /// Whether `owner` (connecting as `localup_id`) owns an assignment
+    fn is_owned_by(
+        assignment: &tcp_port_assignment::Model,
+        localup_id: &str,
+        owner: &PortOwner,
+    ) -> bool {
+        match owner_user_id(owner) {
+            Some(user_id) => assignment.user_id == Some(user_id),
+            None => {
+                assignment.user_id.is_none() && assignment.localup_id.as_deref() == Some(localup_id)
+            }
+        }
+    }
+
+    /// Find the port `owner` held last time, if it is still theirs
+    fn find_sticky<'a>(
+        &self,
+        assignments: &'a [tcp_port_assignment::Model],
+        localup_id: &str,
+        owner: &PortOwner,
+    ) -> Option<&'a tcp_port_assignment::Model> {
+        let local_port = owner.local_port.map(i32::from);
+        assignments
+            .iter()
+            .filter(|a| Self::is_owned_by(a, localup_id, owner))
+            .filter(|a| self.in_range(a.port as u16) && !self.is_active(a.port as u16))
+            .filter(|a| owner.allows(a.port as u16))
+            .find(|a| match (owner_user_id(owner), local_port) {
+                (Some(_), Some(local_port)) => a.local_port == Some(local_port),
+                // Without a local port, fall back to the tunnel ID
+                _ => a.localup_id.as_deref() == Some(localup_id),
+            })
+    }
+
+    /// Pick a port nobody holds, starting from one derived from the owner
+    fn find_free(
+        &self,
+        assignments: &[tcp_port_assignment::Model],
+        localup_id: &str,
+        owner: &PortOwner,
+    ) -> Option<u16> {
+        let held: HashSet<u16> = assignments
+            .iter()
+            .filter(|a| self.is_held(a))
+            .map(|a| a.port as u16)
+            .collect();
+
+        let range_size = (self.range_end - self.range_start) as u32 + 1;
+        let start = hash_owner(localup_id, owner) % range_size;
+        (0..range_size)
+            .map(|offset| self.range_start + ((start + offset) % range_size) as u16)
+            .find(|&port| {
+                owner.allows(port)
+                    && !held.contains(&port)
+                    && !self.is_active(port)
+                    && Self::is_port_available(port)
+            })
+    }
+
+    /// Record that `owner` holds `port`, taking over any stale assignment of it
+    async fn record(
+        &self,
+        existing: Option<&tcp_port_assignment::Model>,
+        port: u16,
+        localup_id: &str,
+        owner: &PortOwner,
+    ) -> Result<(), String> {
+        let now = Utc::now();
+        let result = match existing {
+            Some(assignment) if Self::is_owned_by(assignment, localup_id, owner) => {
+                let mut model: tcp_port_assignment::ActiveModel = assignment.clone().into();
+                model.localup_id = Set(Some(localup_id.to_string()));
+                if assignment.local_port.is_none() && !assignment.reserved {
+                    model.local_port = Set(owner.local_port.map(i32::from));
+                }
+                model.last_used_at = Set(Some(now));
+                model.update(&self.db).await.map(|_| ())
+            }
+            Some(assignment) => {
+                // Stale sticky assignment of another owner
+                let mut model: tcp_port_assignment::ActiveModel = assignment.clone().into();
+                model.user_id = Set(owner_user_id(owner));
+                model.team_id = Set(None);
+                model.local_port = Set(owner.local_port.map(i32::from));
+                model.localup_id = Set(Some(localup_id.to_string()));
+                model.name = Set(None);
+                model.last_used_at = Set(Some(now));
+                model.created_at = Set(now);
+                model.update(&self.db).await.map(|_| ())
+            }
+            None => tcp_port_assignment::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                port: Set(port as i32),
+                user_id: Set(owner_user_id(owner)),
+                team_id: Set(None),
+                local_port: Set(owner.local_port.map(i32::from)),
+                localup_id: Set(Some(localup_id.to_string())),
+                reserved: Set(false),
+                name: Set(None),
+                last_used_at: Set(Some(now)),
+                created_at: Set(now),
+            }
+            .insert(&self.db)
+            .await
+            .map(|_| ()),
+        };
+        result.map_err(|e| format!("Failed to record TCP port {}: {}", port, e))
+    }
+}
+
+#[async_trait]
+impl PortAllocator for DbPortAllocator {
+    async fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String> {
+        self.allocate_for(localup_id, requested_port, &PortOwner::default())
+            .await
+    }
+
+    async fn allocate_for(
+        &self,
+        localup_id: &str,
+        requested_port: Option<u16>,
+        owner: &PortOwner,
+    ) -> Result<u16, String> {
+        let _guard = self.allocation_lock.lock().await;
+
+        // Reconnecting before the old connection was cleaned up
+        if let Some(port) = self.active.lock().unwrap().get(localup_id) {
+            return Ok(*port);
+        }
+
+        let assignments = TcpPortAssignment::find()
+            .order_by_asc(tcp_port_assignment::Column::Port)
+            .all(&self.db)
+            .await
+            .map_err(|e| format!("Failed to load TCP port assignments: {}", e))?;
+
+        let port = if let Some(req_port) = requested_port {
+            if !self.in_range(req_port) {
+                return Err(format!(
+                    "Requested port {} is not available (out of range {}-{})",
+                    req_port, self.range_start, self.range_end
+                ));
+            }
+            let existing = assignments.iter().find(|a| a.port == req_port as i32);
+            if let Some(assignment) = existing {
+                if !Self::is_owned_by(assignment, localup_id, owner) && self.is_held(assignment) {
+                    return Err(format!(
+                        "Requested port {} is not available (reserved by another user)",
+                        req_port
+                    ));
+                }
+            }
+            if self.is_active(req_port) {
+                return Err(format!(
+                    "Requested port {} is not available (already allocated)",
+                    req_port
+                ));
+            }
+            if !Self::is_port_available(req_port) {
+                return Err(format!(
+                    "Requested port {} is in use by another process",
+                    req_port
+                ));
+            }
+            self.record(existing, req_port, localup_id, owner).await?;
+            req_port
+        } else if let Some(assignment) = self
+            .find_sticky(&assignments, localup_id, owner)
+            .filter(|a| Self::is_port_available(a.port as u16))
+        {
+            let port = assignment.port as u16;
+            self.record(Some(assignment), port, localup_id, owner)
+                .await?;
+            info!("Reusing TCP port {} for tunnel {}", port, localup_id);
+            port
+        } else {
+            let port = self
+                .find_free(&assignments, localup_id, owner)
+                .ok_or_else(|| "No available ports in range (all ports in use)".to_string())?;
+            let existing = assignments.iter().find(|a| a.port == port as i32);
+            self.record(existing, port, localup_id, owner).await?;
+            debug!("Assigned new TCP port {} to tunnel {}", port, localup_id);
+            port
+        };
+
+        self.active
+            .lock()
+            .unwrap()
+            .insert(localup_id.to_string(), port);
+        Ok(port)
+    }
+
+    async fn deallocate(&self, localup_id: &str) {
+        let Some(port) = self.active.lock().unwrap().remove(localup_id) else {
+            return;
+        };
+
+        // Refresh last use so the port stays with its owner for the retention period
+        let result = TcpPortAssignment::update_many()
+            .col_expr(
+                tcp_port_assignment::Column::LastUsedAt,
+                sea_orm::sea_query::Expr::value(Utc::now()),
+            )
+            .filter(tcp_port_assignment::Column::Port.eq(port as i32))
+            .exec(&self.db)
+            .await;
+        if let Err(e) = result {
+            warn!("Failed to update TCP port assignment {}: {}", port, e);
+        }
+    }
+
+    fn get_allocated_port(&self, localup_id: &str) -> Option<u16> {
+        self.active.lock().unwrap().get(localup_id).copied()
+    }
+}
+
+fn owner_user_id(owner: &PortOwner) -> Option<Uuid> {
+    owner
+        .user_id
+        .as_deref()
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// Stable hash of the sticky key, so a fresh database still spreads owners over the range
+fn hash_owner(localup_id: &str, owner: &PortOwner) -> u32 {
+    let key = match (&owner.user_id, owner.local_port) {
+        (Some(user_id), Some(local_port)) => format!("{}:{}", user_id, local_port),
+        _ => localup_id.to_string(),
+    };
+    // FNV-1a: unlike DefaultHasher, stable across Rust releases
+    key.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use localup_relay_db::entities::user;
+
+    async fn setup() -> DatabaseConnection {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+        db
+    }
+
+    async fn create_user(db: &DatabaseConnection) -> Uuid {
+        let id = Uuid::new_v4();
+        user::ActiveModel {
+            id: Set(id),
+            email: Set(format!("{}@example.com", id)),
+            password_hash: Set("hash".to_string()),
+            full_name: Set(None),
+            role: Set(user::UserRole::User),
+            is_active: Set(true),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    /// Find a free range of ports on this machine
+    fn free_range() -> (u16, u16) {
+        let listener = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let start = listener.local_addr().unwrap().port();
+        drop(listener);
+        (start, start.saturating_add(20))
+    }
+
+    fn owner(user_id: Uuid, local_port: u16) -> PortOwner {
+        PortOwner {
+            user_id: Some(user_id.to_string()),
+            local_port: Some(local_port),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_port_survives_restart() {
+        let db = setup().await;
+        let user_id = create_user(&db).await;
+        let (start, end) = free_range();
+
+        let allocator = DbPortAllocator::new(db.clone(), start, end);
+        let port = allocator
+            .allocate_for("tunnel-a", None, &owner(user_id, 5432))
+            .await
+            .unwrap();
+        allocator.deallocate("tunnel-a").await;
+
+        // New allocator, new tunnel ID (e.g. a rotated token), same user and local port
+        let restarted = DbPortAllocator::new(db.clone(), start, end);
+        let again = restarted
+            .allocate_for("tunnel-b", None, &owner(user_id, 5432))
+            .await
+            .unwrap();
+        assert_eq!(port, again);
+
+        // Another local port of the same user gets a different port
+        let other = restarted
+            .allocate_for("tunnel-c", None, &owner(user_id, 22))
+            .await
+            .unwrap();
+        assert_ne!(port, other);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_port_rejects_other_users() {
+        let db = setup().await;
+        let alice = create_user(&db).await;
+        let bob = create_user(&db).await;
+        let (start, end) = free_range();
+
+        tcp_port_assignment::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            port: Set(start as i32),
+            user_id: Set(Some(alice)),
+            team_id: Set(None),
+            local_port: Set(None),
+            localup_id: Set(None),
+            reserved: Set(true),
+            name: Set(Some("db".to_string())),
+            last_used_at: Set(None),
+            created_at: Set(Utc::now() - Duration::days(365)),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let allocator = DbPortAllocator::new(db, start, end);
+        assert!(allocator
+            .allocate_for("bob", Some(start), &owner(bob, 5432))
+            .await
+            .unwrap_err()
+            .contains("reserved"));
+        // Automatic allocation skips it too
+        assert_ne!(
+            allocator
+                .allocate_for("bob", None, &owner(bob, 5432))
+                .await
+                .unwrap(),
+            start
+        );
+        assert_eq!(
+            allocator
+                .allocate_for("alice", Some(start), &owner(alice, 5432))
+                .await
+                .unwrap(),
+            start
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_assignment_is_reclaimed() {
+        let db = setup().await;
+        let alice = create_user(&db).await;
+        let bob = create_user(&db).await;
+        let (start, _) = free_range();
+
+        // Single-port range: bob can only get alice's port once it goes stale
+        let allocator = DbPortAllocator::new(db.clone(), start, start);
+        allocator
+            .allocate_for("alice", None, &owner(alice, 5432))
+            .await
+            .unwrap();
+        allocator.deallocate("alice").await;
+        assert!(allocator
+            .allocate_for("bob", None, &owner(bob, 5432))
+            .await
+            .is_err());
+
+        let allocator = allocator.with_retention(Duration::zero());
+        assert_eq!(
+            allocator
+                .allocate_for("bob", None, &owner(bob, 5432))
+                .await
+                .unwrap(),
+            start
+        );
+        let assignment = TcpPortAssignment::find().one(&db).await.unwrap().unwrap();
+        assert_eq!(assignment.user_id, Some(bob));
+    }
+}
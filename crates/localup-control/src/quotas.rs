@@ -0,0 +1,817 @@
+//! Per-user and per-team quotas
+//!
+//! Tunnels opened with a team auth token count against the team, all others
+//! against the token's user. An account's limits come from its row in
+//! `quotas`, falling back limit by limit to the row's plan and then to the
+//! plan named "default". Limits are checked when a tunnel connects and, for
+//! visitor connections and traffic, by the data-plane servers through
+//! [`TunnelConnectionManager::admit_visitor`](crate::TunnelConnectionManager::admit_visitor).
+//!
+//! Traffic is counted in memory and added to `quota_usage` by
+//! [`QuotaEnforcer::flush`], so the monthly total survives relay restarts.
+
+use crate::metering::MeteredConnection;
+use chrono::Utc;
+use localup_relay_db::entities::{
+    plan::{self, DEFAULT_PLAN},
+    prelude::{Plan, Quota, QuotaUsage},
+    quota::{self, QuotaSubject},
+    quota_usage,
+};
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Limits that apply to one account (None = unlimited)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaLimits {
+    /// Concurrently connected tunnels
+    pub max_tunnels: Option<u32>,
+    /// Custom domains in use by connected tunnels
+    pub max_custom_domains: Option<u32>,
+    /// Reserved TCP ports
+    pub max_reserved_ports: Option<u32>,
+    /// Bytes in and out per calendar month (UTC)
+    pub monthly_bytes: Option<u64>,
+    /// Concurrent visitor connections across all tunnels
+    pub max_connections: Option<u32>,
+}
+
+impl QuotaLimits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill limits left unset from `fallback`
+    fn or(self, fallback: QuotaLimits) -> Self {
+        Self {
+            max_tunnels: self.max_tunnels.or(fallback.max_tunnels),
+            max_custom_domains: self.max_custom_domains.or(fallback.max_custom_domains),
+            max_reserved_ports: self.max_reserved_ports.or(fallback.max_reserved_ports),
+            monthly_bytes: self.monthly_bytes.or(fallback.monthly_bytes),
+            max_connections: self.max_connections.or(fallback.max_connections),
+        }
+    }
+}
+
+fn limit(value: Option<i32>) -> Option<u32> {
+    value.map(|v| v.max(0) as u32)
+}
+
+impl From<&plan::Model> for QuotaLimits {
+    fn from(plan: &plan::Model) -> Self {
+        Self {
+            max_tunnels: limit(plan.max_tunnels),
+            max_custom_domains: limit(plan.max_custom_domains),
+            max_reserved_ports: limit(plan.max_reserved_ports),
+            monthly_bytes: plan.monthly_bytes.map(|v| v.max(0) as u64),
+            max_connections: limit(plan.max_connections),
+        }
+    }
+}
+
+impl From<&quota::Model> for QuotaLimits {
+    fn from(quota: &quota::Model) -> Self {
+        Self {
+            max_tunnels: limit(quota.max_tunnels),
+            max_custom_domains: limit(quota.max_custom_domains),
+            max_reserved_ports: limit(quota.max_reserved_ports),
+            monthly_bytes: quota.monthly_bytes.map(|v| v.max(0) as u64),
+            max_connections: limit(quota.max_connections),
+        }
+    }
+}
+
+/// User or team whose quota a tunnel counts against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QuotaAccount {
+    pub subject: QuotaSubject,
+    pub id: Uuid,
+}
+
+impl QuotaAccount {
+    pub fn user(id: Uuid) -> Self {
+        Self {
+            subject: QuotaSubject::User,
+            id,
+        }
+    }
+
+    pub fn team(id: Uuid) -> Self {
+        Self {
+            subject: QuotaSubject::Team,
+            id,
+        }
+    }
+
+    /// Account for a tunnel: its team if the token has one, otherwise its user
+    ///
+    /// Returns None for identities that are not relay users (anonymous tunnels,
+    /// custom validators with their own IDs).
+    pub fn for_tunnel(user_id: Option<&str>, team_id: Option<&str>) -> Option<Self> {
+        if let Some(team_id) = team_id.and_then(|id| Uuid::parse_str(id).ok()) {
+            return Some(Self::team(team_id));
+        }
+        user_id
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .map(Self::user)
+    }
+}
+
+impl fmt::Display for QuotaAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.subject {
+            QuotaSubject::User => write!(f, "user {}", self.id),
+            QuotaSubject::Team => write!(f, "team {}", self.id),
+        }
+    }
+}
+
+/// Why a tunnel or visitor connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum QuotaExceeded {
+    #[error("quota allows {0} connected tunnels")]
+    Tunnels(u32),
+
+    #[error("quota allows {0} custom domains")]
+    CustomDomains(u32),
+
+    #[error("quota allows {0} reserved TCP ports")]
+    ReservedPorts(u32),
+
+    #[error("monthly traffic quota of {0} bytes is used up")]
+    MonthlyBytes(u64),
+
+    #[error("quota allows {0} concurrent visitor connections")]
+    Connections(u32),
+}
+
+impl QuotaExceeded {
+    /// Short reason recorded with the rejected connection
+    pub fn reason(&self) -> &'static str {
+        match self {
+            QuotaExceeded::Tunnels(_) => "quota_tunnels",
+            QuotaExceeded::CustomDomains(_) => "quota_custom_domains",
+            QuotaExceeded::ReservedPorts(_) => "quota_reserved_ports",
+            QuotaExceeded::MonthlyBytes(_) => "quota_monthly_bytes",
+            QuotaExceeded::Connections(_) => "quota_connections",
+        }
+    }
+
+    /// Response for HTTP visitors refused by the quota
+    pub fn http_response(&self) -> Vec<u8> {
+        let body = format!("Quota exceeded: {}\n", self);
+        format!(
+            "HTTP/1.1 429 Too Many Requests\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+}
+
+/// Calendar month that traffic is counted in ("2026-10")
+pub fn current_period() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
+
+/// Resolve an account's limits from its quota row, its plan and the default plan
+pub async fn load_limits(
+    db: &DatabaseConnection,
+    account: &QuotaAccount,
+) -> Result<QuotaLimits, DbErr> {
+    let quota = Quota::find()
+        .filter(quota::Column::SubjectType.eq(account.subject))
+        .filter(quota::Column::SubjectId.eq(account.id))
+        .one(db)
+        .await?;
+
+    let own = quota.as_ref().map(QuotaLimits::from).unwrap_or_default();
+    let plan = match quota.as_ref().and_then(|q| q.plan_id) {
+        Some(plan_id) => Plan::find_by_id(plan_id).one(db).await?,
+        None => {
+            Plan::find()
+                .filter(plan::Column::Name.eq(DEFAULT_PLAN))
+                .one(db)
+                .await?
+        }
+    };
+
+    Ok(match plan {
+        Some(plan) => own.or(QuotaLimits::from(&plan)),
+        None => own,
+    })
+}
+
+/// Bytes in and out recorded for an account in a month
+pub async fn load_usage(
+    db: &DatabaseConnection,
+    account: &QuotaAccount,
+    period: &str,
+) -> Result<(u64, u64), DbErr> {
+    let usage = QuotaUsage::find()
+        .filter(quota_usage::Column::SubjectType.eq(account.subject))
+        .filter(quota_usage::Column::SubjectId.eq(account.id))
+        .filter(quota_usage::Column::Period.eq(period))
+        .one(db)
+        .await?;
+    Ok(usage
+        .map(|u| (u.bytes_in.max(0) as u64, u.bytes_out.max(0) as u64))
+        .unwrap_or_default())
+}
+
+/// Add traffic to an account's monthly usage row
+async fn add_usage(
+    db: &DatabaseConnection,
+    account: &QuotaAccount,
+    period: &str,
+    bytes_in: u64,
+    bytes_out: u64,
+) -> Result<(), DbErr> {
+    let row = quota_usage::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        subject_type: Set(account.subject),
+        subject_id: Set(account.id),
+        period: Set(period.to_string()),
+        bytes_in: Set(bytes_in as i64),
+        bytes_out: Set(bytes_out as i64),
+        updated_at: Set(Utc::now()),
+    };
+
+    QuotaUsage::insert(row)
+        .on_conflict(
+            OnConflict::columns([
+                quota_usage::Column::SubjectType,
+                quota_usage::Column::SubjectId,
+                quota_usage::Column::Period,
+            ])
+            .value(
+                quota_usage::Column::BytesIn,
+                Expr::col((QuotaUsage, quota_usage::Column::BytesIn)).add(bytes_in as i64),
+            )
+            .value(
+                quota_usage::Column::BytesOut,
+                Expr::col((QuotaUsage, quota_usage::Column::BytesOut)).add(bytes_out as i64),
+            )
+            .update_column(quota_usage::Column::UpdatedAt)
+            .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+struct Traffic {
+    period: String,
+    /// Bytes already written to `quota_usage` for `period`
+    flushed: u64,
+}
+
+/// Live usage of one account, shared by its connected tunnels
+pub struct AccountUsage {
+    account: QuotaAccount,
+    limits: Mutex<QuotaLimits>,
+    /// Connected tunnels and the custom domains they use, one entry per connection
+    tunnels: Mutex<Vec<(String, Vec<String>)>>,
+    connections: AtomicU32,
+    traffic: Mutex<Traffic>,
+    pending_in: AtomicU64,
+    pending_out: AtomicU64,
+}
+
+impl AccountUsage {
+    fn new(account: QuotaAccount, limits: QuotaLimits, period: String, flushed: u64) -> Self {
+        Self {
+            account,
+            limits: Mutex::new(limits),
+            tunnels: Mutex::new(Vec::new()),
+            connections: AtomicU32::new(0),
+            traffic: Mutex::new(Traffic { period, flushed }),
+            pending_in: AtomicU64::new(0),
+            pending_out: AtomicU64::new(0),
+        }
+    }
+
+    pub fn account(&self) -> QuotaAccount {
+        self.account
+    }
+
+    pub fn limits(&self) -> QuotaLimits {
+        *self.limits.lock().unwrap()
+    }
+
+    /// Number of connected tunnels
+    pub fn tunnels(&self) -> usize {
+        let tunnels = self.tunnels.lock().unwrap();
+        tunnels
+            .iter()
+            .map(|(id, _)| id)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Custom domains in use by connected tunnels
+    pub fn custom_domains(&self) -> Vec<String> {
+        let tunnels = self.tunnels.lock().unwrap();
+        let mut domains: Vec<String> = tunnels
+            .iter()
+            .flat_map(|(_, domains)| domains.iter().cloned())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        domains.sort();
+        domains
+    }
+
+    /// Open visitor connections across all tunnels
+    pub fn connections(&self) -> u32 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Bytes in and out this month, including traffic not yet written to the database
+    pub fn bytes_used(&self) -> u64 {
+        let flushed = self.traffic.lock().unwrap().flushed;
+        flushed + self.pending_in.load(Ordering::Relaxed) + self.pending_out.load(Ordering::Relaxed)
+    }
+
+    /// Traffic in and out not yet written to the database
+    pub fn pending_bytes(&self) -> (u64, u64) {
+        (
+            self.pending_in.load(Ordering::Relaxed),
+            self.pending_out.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Whether the monthly traffic quota is used up
+    pub fn bytes_exhausted(&self) -> bool {
+        self.limits()
+            .monthly_bytes
+            .is_some_and(|limit| self.bytes_used() >= limit)
+    }
+
+    /// Count traffic between visitors and the account's tunnels
+    pub fn record_bytes(&self, inbound: u64, outbound: u64) {
+        self.pending_in.fetch_add(inbound, Ordering::Relaxed);
+        self.pending_out.fetch_add(outbound, Ordering::Relaxed);
+    }
+
+    /// Admit a visitor connection, released when the permit is dropped
+    pub fn try_connection(self: &Arc<Self>) -> Result<VisitorPermit, QuotaExceeded> {
+        let limits = self.limits();
+        if let Some(limit) = limits.monthly_bytes {
+            if self.bytes_used() >= limit {
+                return Err(QuotaExceeded::MonthlyBytes(limit));
+            }
+        }
+
+        let admitted = self
+            .connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                match limits.max_connections {
+                    Some(max) if open >= max => None,
+                    _ => Some(open + 1),
+                }
+            });
+        match admitted {
+            Ok(_) => Ok(VisitorPermit {
+                usage: Some(self.clone()),
+                metered: None,
+            }),
+            Err(_) => Err(QuotaExceeded::Connections(
+                limits.max_connections.unwrap_or_default(),
+            )),
+        }
+    }
+
+    /// Check a connecting tunnel against the tunnel, domain and traffic limits
+    fn try_add_tunnel(
+        &self,
+        localup_id: &str,
+        custom_domains: Vec<String>,
+    ) -> Result<(), QuotaExceeded> {
+        let limits = self.limits();
+        if let Some(limit) = limits.monthly_bytes {
+            if self.bytes_used() >= limit {
+                return Err(QuotaExceeded::MonthlyBytes(limit));
+            }
+        }
+
+        let mut tunnels = self.tunnels.lock().unwrap();
+        // A tunnel reconnecting under the same ID does not count twice
+        let others = tunnels.iter().filter(|(id, _)| id != localup_id);
+
+        if let Some(max) = limits.max_tunnels {
+            let connected: HashSet<&String> = others.clone().map(|(id, _)| id).collect();
+            if connected.len() >= max as usize {
+                return Err(QuotaExceeded::Tunnels(max));
+            }
+        }
+        if let Some(max) = limits.max_custom_domains {
+            let domains: HashSet<&String> = others
+                .flat_map(|(_, domains)| domains.iter())
+                .chain(custom_domains.iter())
+                .collect();
+            if domains.len() > max as usize {
+                return Err(QuotaExceeded::CustomDomains(max));
+            }
+        }
+
+        tunnels.push((localup_id.to_string(), custom_domains));
+        Ok(())
+    }
+
+    fn remove_tunnel(&self, localup_id: &str) {
+        let mut tunnels = self.tunnels.lock().unwrap();
+        if let Some(pos) = tunnels.iter().position(|(id, _)| id == localup_id) {
+            tunnels.remove(pos);
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.tunnels.lock().unwrap().is_empty()
+            && self.connections() == 0
+            && self.pending_in.load(Ordering::Relaxed) == 0
+            && self.pending_out.load(Ordering::Relaxed) == 0
+    }
+}
+
+/// A visitor connection admitted to a tunnel
+///
+/// Counts against the tunnel owner's quota (see [`AccountUsage::try_connection`])
+/// and is metered when the relay meters traffic.
+pub struct VisitorPermit {
+    usage: Option<Arc<AccountUsage>>,
+    metered: Option<MeteredConnection>,
+}
+
+impl VisitorPermit {
+    /// A connection to a tunnel without a quota that is only metered
+    pub fn metered(connection: MeteredConnection) -> Self {
+        Self {
+            usage: None,
+            metered: Some(connection),
+        }
+    }
+
+    /// Also meter the connection
+    pub fn with_meter(mut self, connection: MeteredConnection) -> Self {
+        self.metered = Some(connection);
+        self
+    }
+
+    /// Count traffic of this connection (inbound = visitor to tunnel)
+    pub fn record(&self, inbound: u64, outbound: u64) {
+        if let Some(ref usage) = self.usage {
+            usage.record_bytes(inbound, outbound);
+        }
+        if let Some(ref metered) = self.metered {
+            metered.meter().record(inbound, outbound);
+        }
+    }
+
+    /// Count an HTTP request handled on this connection
+    pub fn record_request(&self) {
+        if let Some(ref metered) = self.metered {
+            metered.meter().record_request();
+        }
+    }
+
+    /// Whether the account ran out of traffic and the connection should be closed
+    pub fn is_exhausted(&self) -> bool {
+        self.usage
+            .as_ref()
+            .is_some_and(|usage| usage.bytes_exhausted())
+    }
+}
+
+impl Drop for VisitorPermit {
+    fn drop(&mut self) {
+        if let Some(ref usage) = self.usage {
+            usage.connections.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+/// A connected tunnel counted against its account, released when dropped
+pub struct TunnelQuota {
+    usage: Arc<AccountUsage>,
+    localup_id: String,
+}
+
+impl TunnelQuota {
+    pub fn usage(&self) -> Arc<AccountUsage> {
+        self.usage.clone()
+    }
+}
+
+impl Drop for TunnelQuota {
+    fn drop(&mut self) {
+        self.usage.remove_tunnel(&self.localup_id);
+    }
+}
+
+/// Checks tunnels against their account's quota and records traffic
+pub struct QuotaEnforcer {
+    db: DatabaseConnection,
+    accounts: Mutex<HashMap<QuotaAccount, Arc<AccountUsage>>>,
+}
+
+impl QuotaEnforcer {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Live usage of an account with connected tunnels or unflushed traffic
+    pub fn usage(&self, account: &QuotaAccount) -> Option<Arc<AccountUsage>> {
+        self.accounts.lock().unwrap().get(account).cloned()
+    }
+
+    /// Admit a connecting tunnel that uses `custom_domains`
+    ///
+    /// Limits are re-read from the database on every connect. If the database
+    /// is unreachable the tunnel is admitted with the limits last seen.
+    pub async fn admit(
+        &self,
+        account: QuotaAccount,
+        localup_id: &str,
+        custom_domains: Vec<String>,
+    ) -> Result<TunnelQuota, QuotaExceeded> {
+        let limits = match load_limits(&self.db, &account).await {
+            Ok(limits) => Some(limits),
+            Err(e) => {
+                warn!("Failed to load quota for {}: {}", account, e);
+                None
+            }
+        };
+
+        let existing = self.usage(&account);
+        let usage = match existing {
+            Some(usage) => usage,
+            None => {
+                let period = current_period();
+                let (bytes_in, bytes_out) = load_usage(&self.db, &account, &period)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to load traffic usage for {}: {}", account, e);
+                        (0, 0)
+                    });
+                let usage = Arc::new(AccountUsage::new(
+                    account,
+                    limits.unwrap_or_default(),
+                    period,
+                    bytes_in + bytes_out,
+                ));
+                self.accounts
+                    .lock()
+                    .unwrap()
+                    .entry(account)
+                    .or_insert(usage)
+                    .clone()
+            }
+        };
+        if let Some(limits) = limits {
+            *usage.limits.lock().unwrap() = limits;
+        }
+
+        usage.try_add_tunnel(localup_id, custom_domains)?;
+        debug!("Tunnel {} counted against {}", localup_id, account);
+        Ok(TunnelQuota {
+            usage,
+            localup_id: localup_id.to_string(),
+        })
+    }
+
+    /// Write counted traffic to the database and forget idle accounts
+    pub async fn flush(&self) -> Result<(), DbErr> {
+        let accounts: Vec<Arc<AccountUsage>> =
+            self.accounts.lock().unwrap().values().cloned().collect();
+        let period = current_period();
+
+        for usage in accounts {
+            let bytes_in = usage.pending_in.swap(0, Ordering::AcqRel);
+            let bytes_out = usage.pending_out.swap(0, Ordering::AcqRel);
+            let written_period = usage.traffic.lock().unwrap().period.clone();
+
+            if bytes_in + bytes_out > 0 {
+                if let Err(e) = add_usage(
+                    &self.db,
+                    &usage.account,
+                    &written_period,
+                    bytes_in,
+                    bytes_out,
+                )
+                .await
+                {
+                    // Keep the traffic for the next flush
+                    usage.record_bytes(bytes_in, bytes_out);
+                    return Err(e);
+                }
+            }
+
+            let mut traffic = usage.traffic.lock().unwrap();
+            if traffic.period != period {
+                // A new month starts with a clean slate
+                traffic.period = period.clone();
+                traffic.flushed = 0;
+            } else {
+                traffic.flushed += bytes_in + bytes_out;
+            }
+        }
+
+        self.accounts
+            .lock()
+            .unwrap()
+            .retain(|_, usage| !usage.is_idle());
+        Ok(())
+    }
+
+    /// Flush traffic every `interval` until the enforcer is dropped
+    pub fn spawn_flush_task(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let enforcer = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(enforcer) = enforcer.upgrade() else {
+                    break;
+                };
+                if let Err(e) = enforcer.flush().await {
+                    warn!("Failed to record quota usage: {}", e);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::ActiveModelTrait;
+
+    async fn test_db() -> DatabaseConnection {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+        db
+    }
+
+    async fn create_plan(db: &DatabaseConnection, name: &str, max_tunnels: i32) -> Uuid {
+        let id = Uuid::new_v4();
+        plan::ActiveModel {
+            id: Set(id),
+            name: Set(name.to_string()),
+            max_tunnels: Set(Some(max_tunnels)),
+            max_custom_domains: Set(Some(1)),
+            max_reserved_ports: Set(None),
+            monthly_bytes: Set(Some(1000)),
+            max_connections: Set(Some(2)),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    #[tokio::test]
+    async fn test_limits_fall_back_to_plan() {
+        let db = test_db().await;
+        let account = QuotaAccount::user(Uuid::new_v4());
+        assert!(load_limits(&db, &account).await.unwrap().is_unlimited());
+
+        create_plan(&db, DEFAULT_PLAN, 1).await;
+        assert_eq!(
+            load_limits(&db, &account).await.unwrap().max_tunnels,
+            Some(1)
+        );
+
+        let pro = create_plan(&db, "pro", 10).await;
+        quota::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            subject_type: Set(QuotaSubject::User),
+            subject_id: Set(account.id),
+            plan_id: Set(Some(pro)),
+            max_tunnels: Set(None),
+            max_custom_domains: Set(Some(5)),
+            max_reserved_ports: Set(None),
+            monthly_bytes: Set(None),
+            max_connections: Set(None),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let limits = load_limits(&db, &account).await.unwrap();
+        assert_eq!(limits.max_tunnels, Some(10));
+        assert_eq!(limits.max_custom_domains, Some(5));
+        assert_eq!(limits.monthly_bytes, Some(1000));
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_and_domain_limits() {
+        let db = test_db().await;
+        create_plan(&db, DEFAULT_PLAN, 2).await;
+        let enforcer = QuotaEnforcer::new(db);
+        let account = QuotaAccount::user(Uuid::new_v4());
+
+        let a = enforcer
+            .admit(account, "tunnel-a", vec!["a.example.com".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            enforcer
+                .admit(account, "tunnel-b", vec!["b.example.com".to_string()])
+                .await
+                .err(),
+            Some(QuotaExceeded::CustomDomains(1))
+        );
+        let _b = enforcer.admit(account, "tunnel-b", vec![]).await.unwrap();
+        assert_eq!(
+            enforcer.admit(account, "tunnel-c", vec![]).await.err(),
+            Some(QuotaExceeded::Tunnels(2))
+        );
+        // Reconnecting under the same ID does not count twice
+        let _a2 = enforcer
+            .admit(account, "tunnel-a", vec!["a.example.com".to_string()])
+            .await
+            .unwrap();
+
+        drop(a);
+        let usage = enforcer.usage(&account).unwrap();
+        assert_eq!(usage.tunnels(), 2);
+        assert_eq!(usage.custom_domains(), vec!["a.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_connections_and_traffic() {
+        let db = test_db().await;
+        create_plan(&db, DEFAULT_PLAN, 2).await;
+        let enforcer = QuotaEnforcer::new(db.clone());
+        let account = QuotaAccount::team(Uuid::new_v4());
+
+        let tunnel = enforcer.admit(account, "tunnel-a", vec![]).await.unwrap();
+        let usage = tunnel.usage();
+
+        let first = usage.try_connection().unwrap();
+        let _second = usage.try_connection().unwrap();
+        assert_eq!(
+            usage.try_connection().err(),
+            Some(QuotaExceeded::Connections(2))
+        );
+        drop(first);
+
+        let visitor = usage.try_connection().unwrap();
+        visitor.record(600, 300);
+        assert!(!visitor.is_exhausted());
+        enforcer.flush().await.unwrap();
+        assert_eq!(
+            load_usage(&db, &account, &current_period()).await.unwrap(),
+            (600, 300)
+        );
+
+        visitor.record(100, 0);
+        assert!(visitor.is_exhausted());
+        drop(visitor);
+        assert_eq!(
+            usage.try_connection().err(),
+            Some(QuotaExceeded::MonthlyBytes(1000))
+        );
+        enforcer.flush().await.unwrap();
+        drop(tunnel);
+        drop(usage);
+
+        // Usage is reloaded from the database when the account reconnects
+        assert_eq!(
+            enforcer.admit(account, "tunnel-a", vec![]).await.err(),
+            Some(QuotaExceeded::MonthlyBytes(1000))
+        );
+    }
+
+    #[test]
+    fn test_account_for_tunnel() {
+        let user = Uuid::new_v4();
+        let team = Uuid::new_v4();
+        assert_eq!(
+            QuotaAccount::for_tunnel(Some(&user.to_string()), Some(&team.to_string())),
+            Some(QuotaAccount::team(team))
+        );
+        assert_eq!(
+            QuotaAccount::for_tunnel(Some(&user.to_string()), None),
+            Some(QuotaAccount::user(user))
+        );
+        assert_eq!(QuotaAccount::for_tunnel(Some("anonymous"), None), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Revoked tokens
+//!
+//! Tokens are revoked through the API by their `jti` claim, which stays
+//! valid for stateless JWTs until they expire. The relay refuses revoked
+//! tokens when tunnels connect, and `TunnelHandler` re-validates the tokens
+//! of connected tunnels periodically so that revoking a token also
+//! disconnects the tunnels using it.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use localup_auth::{AuthError, AuthResult, AuthValidator};
+use localup_relay_db::entities::{prelude::RevokedToken, revoked_token};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Looks up revoked tokens in the relay database
+pub struct RevocationList {
+    db: DatabaseConnection,
+}
+
+impl RevocationList {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// The revocation of the token with this `jti`, if it was revoked
+    pub async fn find(&self, jti: &str) -> Result<Option<revoked_token::Model>, DbErr> {
+        RevokedToken::find()
+            .filter(revoked_token::Column::Jti.eq(jti))
+            .one(&self.db)
+            .await
+    }
+
+    /// Remove revocations of tokens that have expired anyway
+    pub async fn prune_expired(&self) -> Result<u64, DbErr> {
+        let result = RevokedToken::delete_many()
+            .filter(revoked_token::Column::ExpiresAt.lt(Utc::now()))
+            .exec(&self.db)
+            .await?;
+        if result.rows_affected > 0 {
+            debug!("Pruned {} expired token revocations", result.rows_affected);
+        }
+        Ok(result.rows_affected)
+    }
+}
+
+/// Refuses revoked tokens that another validator accepted
+///
+/// This is the validation tunnels get when they connect, so ingresses that
+/// accept the same tokens (the CONNECT/SOCKS5 proxy) should use it as well.
+pub struct RevocationCheckingValidator {
+    inner: Arc<dyn AuthValidator>,
+    revocations: Option<Arc<RevocationList>>,
+}
+
+impl RevocationCheckingValidator {
+    pub fn new(inner: Arc<dyn AuthValidator>) -> Self {
+        Self {
+            inner,
+            revocations: None,
+        }
+    }
+
+    /// Refuse tokens whose `jti` is in this revocation list
+    pub fn with_revocations(mut self, revocations: Arc<RevocationList>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+}
+
+#[async_trait]
+impl AuthValidator for RevocationCheckingValidator {
+    async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
+        let auth = self.inner.validate(token).await?;
+
+        if let (Some(revocations), Some(jti)) = (&self.revocations, &auth.token_id) {
+            let revocation = revocations.find(jti).await.map_err(|e| {
+                AuthError::InternalError(format!("Failed to check token revocation: {}", e))
+            })?;
+            if let Some(revocation) = revocation {
+                return Err(AuthError::Unauthorized(revoked_reason(&revocation)));
+            }
+        }
+
+        Ok(auth)
+    }
+}
+
+/// Reason sent to tunnels whose token was revoked
+pub(crate) fn revoked_reason(revocation: &revoked_token::Model) -> String {
+    match revocation.reason.as_deref() {
+        Some(reason) if !reason.is_empty() => format!("Token revoked: {}", reason),
+        _ => "Token revoked".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use sea_orm::{ActiveModelTrait, Set};
+    use uuid::Uuid;
+
+    async fn revoke(db: &DatabaseConnection, jti: &str, expires_in: Duration) {
+        revoked_token::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            jti: Set(jti.to_string()),
+            user_id: Set(None),
+            reason: Set(Some("laptop stolen".to_string())),
+            revoked_by: Set(None),
+            expires_at: Set(Some(Utc::now() + expires_in)),
+            revoked_at: Set(Utc::now()),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_revocations_are_found_until_pruned() {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+        let revocations = RevocationList::new(db.clone());
+
+        revoke(&db, "live", Duration::hours(1)).await;
+        revoke(&db, "expired", Duration::hours(-1)).await;
+
+        let revocation = revocations.find("live").await.unwrap().unwrap();
+        assert_eq!(revoked_reason(&revocation), "Token revoked: laptop stolen");
+        assert!(revocations.find("unknown").await.unwrap().is_none());
+
+        assert_eq!(revocations.prune_expired().await.unwrap(), 1);
+        assert!(revocations.find("expired").await.unwrap().is_none());
+        assert!(revocations.find("live").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_validator_refuses_revoked_tokens() {
+        use localup_auth::{JwtClaims, JwtValidator};
+
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+        let validator = RevocationCheckingValidator::new(Arc::new(JwtValidator::new(b"secret")))
+            .with_revocations(Arc::new(RevocationList::new(db.clone())));
+
+        let token = |jti: &str| {
+            let claims = JwtClaims::new(
+                "tunnel".to_string(),
+                "localup".to_string(),
+                "localup".to_string(),
+                Duration::hours(1),
+            )
+            .with_jti(jti.to_string());
+            JwtValidator::encode(b"secret", &claims).unwrap()
+        };
+
+        revoke(&db, "revoked", Duration::hours(1)).await;
+        assert!(validator.validate(&token("active")).await.is_ok());
+        match validator.validate(&token("revoked")).await {
+            Err(AuthError::Unauthorized(reason)) => {
+                assert_eq!(reason, "Token revoked: laptop stolen")
+            }
+            other => panic!(
+                "expected a revoked token error, got {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Share link checks for the HTTP relays
+//!
+//! Share links are minted by the API (see `localup-api`) and checked here
+//! before a tunnel's own authentication runs. The first request with a link
+//! redeems it: the signature and scope are checked, one use is counted in
+//! the `share_links` table, and the client is redirected with a cookie that
+//! grants access to the shared path until the link expires. Requests with
+//! that cookie skip the tunnel's authentication for as long as the link has
+//! not been revoked.
+
+use chrono::Utc;
+use localup_http_auth::{
+    share_link_redirect, share_link_rejected, take_share_token, AuthRequest, ShareLinkClaims,
+    ShareLinkSigner, SHARE_COOKIE,
+};
+use localup_relay_db::entities::{prelude::ShareLink, share_link};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// How long a link's revocation status is cached before it is checked again
+pub const SHARE_LINK_RECHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of cached link statuses
+const STATUS_CACHE_CAPACITY: usize = 1024;
+
+/// Outcome of checking a request for share link access
+#[derive(Debug)]
+pub enum ShareAccess {
+    /// The request carries no share link or cookie for this tunnel
+    None,
+    /// The request carries a valid share cookie; skip tunnel authentication
+    Granted,
+    /// Send this response to the client instead of forwarding the request
+    Respond(Vec<u8>),
+}
+
+/// Validates share links and share cookies against the relay database
+pub struct ShareLinkGate {
+    signer: ShareLinkSigner,
+    db: DatabaseConnection,
+    /// Link ID -> (active, when it was checked)
+    statuses: Mutex<HashMap<Uuid, (bool, Instant)>>,
+}
+
+impl ShareLinkGate {
+    /// Create a gate using the secret the API signs share links with
+    pub fn new(secret: impl AsRef<[u8]>, db: DatabaseConnection) -> Self {
+        Self {
+            signer: ShareLinkSigner::new(secret),
+            db,
+            statuses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check a request to `localup_id` for a share link or share cookie
+    pub async fn check(&self, localup_id: &str, request: &AuthRequest) -> ShareAccess {
+        if let Some((token, location)) = take_share_token(&request.path) {
+            return ShareAccess::Respond(
+                self.redeem(localup_id, &token, &location, request.tls)
+                    .await,
+            );
+        }
+
+        let Some(claims) = request
+            .cookie(SHARE_COOKIE)
+            .and_then(|value| self.signer.verify_cookie(value))
+        else {
+            return ShareAccess::None;
+        };
+        if !claims.allows(localup_id, &request.path) {
+            return ShareAccess::None;
+        }
+
+        match Uuid::parse_str(&claims.id) {
+            Ok(id) if self.is_active(id).await => ShareAccess::Granted,
+            _ => {
+                debug!("Share cookie for revoked link {} ignored", claims.id);
+                ShareAccess::None
+            }
+        }
+    }
+
+    /// Redeem a share link and build the response for it
+    async fn redeem(&self, localup_id: &str, token: &str, location: &str, tls: bool) -> Vec<u8> {
+        let Some((id, claims)) = self.verify_link(localup_id, token, location) else {
+            debug!("Invalid share link for tunnel {}", localup_id);
+            return share_link_rejected();
+        };
+
+        match self.consume(id).await {
+            Ok(true) => {
+                info!("Share link {} redeemed for tunnel {}", id, localup_id);
+                share_link_redirect(location, self.signer.cookie_header(&claims, tls))
+            }
+            Ok(false) => {
+                debug!("Share link {} is revoked or used up", id);
+                share_link_rejected()
+            }
+            Err(e) => {
+                error!("Database error redeeming share link {}: {}", id, e);
+                share_link_rejected()
+            }
+        }
+    }
+
+    fn verify_link(
+        &self,
+        localup_id: &str,
+        token: &str,
+        location: &str,
+    ) -> Option<(Uuid, ShareLinkClaims)> {
+        let claims = self.signer.verify_link(token)?;
+        if !claims.allows(localup_id, location) {
+            return None;
+        }
+        let id = Uuid::parse_str(&claims.id).ok()?;
+        Some((id, claims))
+    }
+
+    /// Count one use of a link, if it is not revoked or used up
+    ///
+    /// The check and the increment are a single UPDATE, so concurrent
+    /// redemptions cannot exceed the link's maximum use count.
+    async fn consume(&self, id: Uuid) -> Result<bool, DbErr> {
+        let result = ShareLink::update_many()
+            .col_expr(
+                share_link::Column::UseCount,
+                Expr::col(share_link::Column::UseCount).add(1),
+            )
+            .col_expr(share_link::Column::LastUsedAt, Expr::value(Utc::now()))
+            .filter(share_link::Column::Id.eq(id))
+            .filter(share_link::Column::RevokedAt.is_null())
+            .filter(
+                Condition::any()
+                    .add(share_link::Column::MaxUses.is_null())
+                    .add(
+                        Expr::col(share_link::Column::UseCount)
+                            .lt(Expr::col(share_link::Column::MaxUses)),
+                    ),
+            )
+            .exec(&self.db)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    /// Whether a link exists and has not been revoked, using the status cache
+    async fn is_active(&self, id: Uuid) -> bool {
+        if let Some((active, checked)) = self.statuses.lock().unwrap().get(&id) {
+            if checked.elapsed() < SHARE_LINK_RECHECK_INTERVAL {
+                return *active;
+            }
+        }
+
+        let active = match ShareLink::find_by_id(id).one(&self.db).await {
+            Ok(link) => link.is_some_and(|link| link.is_active()),
+            Err(e) => {
+                error!("Database error checking share link {}: {}", id, e);
+                return false;
+            }
+        };
+
+        let mut statuses = self.statuses.lock().unwrap();
+        if statuses.len() >= STATUS_CACHE_CAPACITY {
+            statuses.retain(|_, (_, checked)| checked.elapsed() < SHARE_LINK_RECHECK_INTERVAL);
+        }
+        statuses.insert(id, (active, Instant::now()));
+        active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use localup_relay_db::entities::user;
+    use sea_orm::{ActiveModelTrait, Set};
+
+    const SECRET: &str = "share-secret";
+
+    async fn setup(max_uses: Option<i32>) -> (ShareLinkGate, DatabaseConnection, Uuid) {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+
+        let user_id = Uuid::new_v4();
+        user::ActiveModel {
+            id: Set(user_id),
+            email: Set("owner@example.com".to_string()),
+            password_hash: Set("hash".to_string()),
+            full_name: Set(None),
+            role: Set(user::UserRole::User),
+            is_active: Set(true),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let id = Uuid::new_v4();
+        share_link::ActiveModel {
+            id: Set(id),
+            user_id: Set(user_id),
+            localup_id: Set("demo".to_string()),
+            path_prefix: Set("/reports".to_string()),
+            name: Set(None),
+            max_uses: Set(max_uses),
+            use_count: Set(0),
+            last_used_at: Set(None),
+            expires_at: Set(Utc::now() + chrono::Duration::hours(1)),
+            revoked_at: Set(None),
+            created_at: Set(Utc::now()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        (ShareLinkGate::new(SECRET, db.clone()), db, id)
+    }
+
+    fn token(id: Uuid) -> String {
+        ShareLinkSigner::new(SECRET).sign_link(&ShareLinkClaims {
+            id: id.to_string(),
+            tunnel: "demo".to_string(),
+            path: "/reports".to_string(),
+            exp: Utc::now().timestamp() as u64 + 3600,
+        })
+    }
+
+    fn request(path: &str, cookie: Option<&str>) -> AuthRequest {
+        let cookie = cookie
+            .map(|c| format!("Cookie: {}\r\n", c))
+            .unwrap_or_default();
+        let raw = format!("GET {} HTTP/1.1\r\nHost: demo\r\n{}\r\n", path, cookie);
+        AuthRequest::parse(raw.as_bytes(), true)
+    }
+
+    /// Redeem a link and return the cookie from the redirect
+    async fn redeem(gate: &ShareLinkGate, token: &str) -> Option<String> {
+        let path = format!("/reports/q3?localup_share={}", token);
+        let ShareAccess::Respond(response) = gate.check("demo", &request(&path, None)).await else {
+            panic!("Expected a response for a share link");
+        };
+        let response = String::from_utf8(response).unwrap();
+        if !response.starts_with("HTTP/1.1 302") {
+            return None;
+        }
+        assert!(response.contains("Location: /reports/q3\r\n"));
+        let cookie = response.split("Set-Cookie: ").nth(1)?;
+        Some(cookie.split(';').next()?.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_redeem_sets_scoped_cookie() {
+        let (gate, db, id) = setup(None).await;
+        let cookie = redeem(&gate, &token(id)).await.expect("link redeemed");
+
+        assert!(matches!(
+            gate.check("demo", &request("/reports/q3", Some(&cookie)))
+                .await,
+            ShareAccess::Granted
+        ));
+        assert!(matches!(
+            gate.check("demo", &request("/admin", Some(&cookie))).await,
+            ShareAccess::None
+        ));
+        assert!(matches!(
+            gate.check("other", &request("/reports", Some(&cookie)))
+                .await,
+            ShareAccess::None
+        ));
+
+        let link = ShareLink::find_by_id(id).one(&db).await.unwrap().unwrap();
+        assert_eq!(link.use_count, 1);
+        assert!(link.last_used_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_max_uses_enforced() {
+        let (gate, _db, id) = setup(Some(2)).await;
+        let token = token(id);
+
+        assert!(redeem(&gate, &token).await.is_some());
+        assert!(redeem(&gate, &token).await.is_some());
+        assert!(redeem(&gate, &token).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_forged_and_foreign_links_rejected() {
+        let (gate, _db, id) = setup(None).await;
+        let forged = ShareLinkSigner::new("wrong").sign_link(&ShareLinkClaims {
+            id: id.to_string(),
+            tunnel: "demo".to_string(),
+            path: "/reports".to_string(),
+            exp: Utc::now().timestamp() as u64 + 3600,
+        });
+        assert!(redeem(&gate, &forged).await.is_none());
+
+        // A valid link for this tunnel cannot be used on another one
+        let path = format!("/reports?localup_share={}", token(id));
+        let ShareAccess::Respond(response) = gate.check("other", &request(&path, None)).await
+        else {
+            panic!("Expected a response for a share link");
+        };
+        assert!(response.starts_with(b"HTTP/1.1 403"));
+    }
+
+    #[tokio::test]
+    async fn test_revoked_link_stops_working() {
+        let (gate, db, id) = setup(None).await;
+        let cookie = redeem(&gate, &token(id)).await.expect("link redeemed");
+
+        let link = ShareLink::find_by_id(id).one(&db).await.unwrap().unwrap();
+        let mut link: share_link::ActiveModel = link.into();
+        link.revoked_at = Set(Some(Utc::now()));
+        link.update(&db).await.unwrap();
+
+        // A fresh gate has no cached status, like the relay after the recheck interval
+        let gate = ShareLinkGate::new(SECRET, db);
+        assert!(redeem(&gate, &token(id)).await.is_none());
+        assert!(matches!(
+            gate.check("demo", &request("/reports", Some(&cookie)))
+                .await,
+            ShareAccess::None
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+//! Concurrent connection caps for TCP tunnels
+
+use localup_proto::TcpLimits;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Why a connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum TcpLimitExceeded {
+    #[error("tunnel already has {0} connections")]
+    MaxConnections(u32),
+
+    #[error("source address already has {0} connections")]
+    MaxConnectionsPerIp(u32),
+}
+
+impl TcpLimitExceeded {
+    /// Short reason recorded with the rejected connection
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TcpLimitExceeded::MaxConnections(_) => "max_connections",
+            TcpLimitExceeded::MaxConnectionsPerIp(_) => "max_connections_per_ip",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    total: u32,
+    per_ip: HashMap<IpAddr, u32>,
+}
+
+/// Counts open connections to one tunnel and enforces its [`TcpLimits`]
+pub struct TcpConnectionLimiter {
+    limits: TcpLimits,
+    counts: Mutex<Counts>,
+}
+
+impl TcpConnectionLimiter {
+    pub fn new(limits: TcpLimits) -> Self {
+        Self {
+            limits,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    pub fn limits(&self) -> &TcpLimits {
+        &self.limits
+    }
+
+    /// Reserve a slot for a connection from `ip`, released when the permit is dropped
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        ip: IpAddr,
+    ) -> Result<TcpConnectionPermit, TcpLimitExceeded> {
+        let mut counts = self.counts.lock().unwrap();
+
+        if let Some(max) = self.limits.max_connections {
+            if counts.total >= max {
+                return Err(TcpLimitExceeded::MaxConnections(max));
+            }
+        }
+        let from_ip = counts.per_ip.get(&ip).copied().unwrap_or(0);
+        if let Some(max) = self.limits.max_connections_per_ip {
+            if from_ip >= max {
+                return Err(TcpLimitExceeded::MaxConnectionsPerIp(max));
+            }
+        }
+
+        counts.total += 1;
+        counts.per_ip.insert(ip, from_ip + 1);
+        Ok(TcpConnectionPermit {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    /// Number of connections currently holding a permit
+    pub fn active(&self) -> u32 {
+        self.counts.lock().unwrap().total
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total = counts.total.saturating_sub(1);
+        if let Some(from_ip) = counts.per_ip.get_mut(&ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                counts.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+/// A connection slot reserved with [`TcpConnectionLimiter::try_acquire`]
+pub struct TcpConnectionPermit {
+    limiter: Arc<TcpConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for TcpConnectionPermit {
+    fn drop(&mut self) {
+        self.limiter.release(self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_connections() {
+        let limiter = Arc::new(TcpConnectionLimiter::new(TcpLimits {
+            max_connections: Some(2),
+            ..Default::default()
+        }));
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limiter.try_acquire(a).unwrap();
+        let _second = limiter.try_acquire(b).unwrap();
+        assert_eq!(
+            limiter.try_acquire(a).err(),
+            Some(TcpLimitExceeded::MaxConnections(2))
+        );
+
+        drop(first);
+        assert_eq!(limiter.active(), 1);
+        assert!(limiter.try_acquire(a).is_ok());
+    }
+
+    #[test]
+    fn test_max_connections_per_ip() {
+        let limiter = Arc::new(TcpConnectionLimiter::new(TcpLimits {
+            max_connections_per_ip: Some(1),
+            ..Default::default()
+        }));
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let _first = limiter.try_acquire(a).unwrap();
+        let err = limiter.try_acquire(a).err().unwrap();
+        assert_eq!(err.reason(), "max_connections_per_ip");
+        assert!(limiter.try_acquire(b).is_ok());
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = Arc::new(TcpConnectionLimiter::new(TcpLimits::default()));
+        let ip: IpAddr = "::1".parse().unwrap();
+        let permits: Vec<_> = (0..100).map(|_| limiter.try_acquire(ip).unwrap()).collect();
+        assert_eq!(limiter.active(), 100);
+        drop(permits);
+        assert_eq!(limiter.active(), 0);
+    }
+}
@@ -0,0 +1,173 @@
+//! Relay accounts for tunnel login forms
+//!
+//! Login forms with `team_accounts` enabled let people sign in to a tunnel
+//! with their relay account (email and password) instead of credentials
+//! stored in the tunnel configuration. Only the tunnel owner and members of
+//! the owner's teams are accepted.
+
+use async_trait::async_trait;
+use localup_auth::verify_password;
+use localup_http_auth::AccountVerifier;
+use localup_relay_db::entities::{
+    prelude::{TeamMember, User},
+    team_member, user,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+/// Checks relay accounts of a tunnel owner's teams
+pub struct TeamAccountVerifier {
+    db: DatabaseConnection,
+    owner_id: Uuid,
+}
+
+impl TeamAccountVerifier {
+    /// Create a verifier for tunnels owned by `owner_id`
+    pub fn new(db: DatabaseConnection, owner_id: Uuid) -> Self {
+        Self { db, owner_id }
+    }
+
+    /// Whether `user_id` is the owner or shares a team with the owner
+    async fn is_teammate(&self, user_id: Uuid) -> Result<bool, DbErr> {
+        if user_id == self.owner_id {
+            return Ok(true);
+        }
+
+        let owner_teams: Vec<Uuid> = TeamMember::find()
+            .filter(team_member::Column::UserId.eq(self.owner_id))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|membership| membership.team_id)
+            .collect();
+        if owner_teams.is_empty() {
+            return Ok(false);
+        }
+
+        let shared = TeamMember::find()
+            .filter(team_member::Column::UserId.eq(user_id))
+            .filter(team_member::Column::TeamId.is_in(owner_teams))
+            .count(&self.db)
+            .await?;
+        Ok(shared > 0)
+    }
+}
+
+#[async_trait]
+impl AccountVerifier for TeamAccountVerifier {
+    async fn verify(&self, username: &str, password: &str) -> bool {
+        let account = match User::find()
+            .filter(user::Column::Email.eq(username))
+            .filter(user::Column::IsActive.eq(true))
+            .one(&self.db)
+            .await
+        {
+            Ok(Some(account)) => account,
+            Ok(None) => return false,
+            Err(e) => {
+                error!("Database error looking up account {}: {}", username, e);
+                return false;
+            }
+        };
+
+        match self.is_teammate(account.id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!("Account {} is not in the tunnel owner's teams", username);
+                return false;
+            }
+            Err(e) => {
+                error!("Database error checking teams of {}: {}", username, e);
+                return false;
+            }
+        }
+
+        // Password hashing is CPU-bound, keep it off the async workers
+        let password = password.to_string();
+        tokio::task::spawn_blocking(move || {
+            verify_password(&password, &account.password_hash).unwrap_or(false)
+        })
+        .await
+        .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use localup_auth::hash_password;
+    use localup_relay_db::entities::team;
+    use sea_orm::{ActiveModelTrait, Set};
+
+    async fn create_user(db: &DatabaseConnection, email: &str, active: bool) -> Uuid {
+        let id = Uuid::new_v4();
+        user::ActiveModel {
+            id: Set(id),
+            email: Set(email.to_string()),
+            password_hash: Set(hash_password("correct horse").unwrap()),
+            full_name: Set(None),
+            role: Set(user::UserRole::User),
+            is_active: Set(active),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+        id
+    }
+
+    async fn join(db: &DatabaseConnection, team_id: Uuid, user_id: Uuid) {
+        team_member::ActiveModel {
+            team_id: Set(team_id),
+            user_id: Set(user_id),
+            role: Set(team_member::TeamRole::Member),
+            joined_at: Set(Utc::now()),
+        }
+        .insert(db)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_only_owner_and_teammates_accepted() {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+
+        let owner = create_user(&db, "owner@example.com", true).await;
+        let teammate = create_user(&db, "mate@example.com", true).await;
+        create_user(&db, "stranger@example.com", true).await;
+        let disabled = create_user(&db, "gone@example.com", false).await;
+
+        let team_id = Uuid::new_v4();
+        team::ActiveModel {
+            id: Set(team_id),
+            name: Set("Acme".to_string()),
+            slug: Set("acme".to_string()),
+            owner_id: Set(owner),
+            tcp_port_quota: Set(None),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        for member in [owner, teammate, disabled] {
+            join(&db, team_id, member).await;
+        }
+
+        let verifier = TeamAccountVerifier::new(db, owner);
+        assert!(verifier.verify("owner@example.com", "correct horse").await);
+        assert!(verifier.verify("mate@example.com", "correct horse").await);
+        assert!(!verifier.verify("mate@example.com", "wrong").await);
+        assert!(
+            !verifier
+                .verify("stranger@example.com", "correct horse")
+                .await
+        );
+        assert!(!verifier.verify("gone@example.com", "correct horse").await);
+        assert!(!verifier.verify("nobody@example.com", "correct horse").await);
+    }
+}
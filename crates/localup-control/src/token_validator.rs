@@ -0,0 +1,224 @@
+//! Validation of relay-issued auth tokens
+//!
+//! Auth tokens are JWTs created through the relay API. Besides the signature
+//! and expiry, the relay checks that the token is an "auth" token (not a web
+//! session), that it carries a user ID and, when a database is configured,
+//! that the token is still stored and active.
+
+use async_trait::async_trait;
+use localup_auth::{AuthError, AuthResult, AuthValidator, JwtValidator};
+use localup_relay_db::entities::{auth_token, prelude::AuthToken as AuthTokenEntity};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Validates auth tokens issued by this relay
+pub struct RelayTokenValidator {
+    jwt: Arc<JwtValidator>,
+    db: Option<DatabaseConnection>,
+}
+
+impl RelayTokenValidator {
+    pub fn new(jwt: Arc<JwtValidator>) -> Self {
+        Self { jwt, db: None }
+    }
+
+    /// Require tokens to be stored and active in the relay database
+    pub fn with_database(mut self, db: DatabaseConnection) -> Self {
+        self.db = Some(db);
+        self
+    }
+}
+
+#[async_trait]
+impl AuthValidator for RelayTokenValidator {
+    /// Validate an auth token
+    ///
+    /// 1. Validates the JWT signature and expiration
+    /// 2. Verifies the token type is "auth" (not "session")
+    /// 3. Hashes the token and looks it up in the database
+    /// 4. Verifies the token is active (not revoked)
+    /// 5. Updates the last_used_at timestamp
+    async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
+        // Step 1: Validate JWT signature and expiration
+        let claims = self.jwt.validate(token)?;
+
+        // Step 2: Verify token type is "auth" (not "session")
+        match &claims.token_type {
+            Some(token_type) if token_type == "auth" => {
+                // Valid auth token, continue
+            }
+            Some(token_type) => {
+                return Err(AuthError::Unauthorized(format!(
+                    "Invalid token type '{}'. Expected 'auth' token for tunnel authentication",
+                    token_type
+                )));
+            }
+            None => {
+                // Legacy token without token_type - allow for backward compatibility
+                debug!("Token missing 'token_type' claim, treating as legacy auth token");
+            }
+        }
+
+        // Extract user_id from claims (will be verified against database)
+        let claimed_user_id = claims.user_id.clone().ok_or_else(|| {
+            AuthError::Unauthorized(
+                "Token missing 'user_id' claim. Auth tokens must include user_id".to_string(),
+            )
+        })?;
+
+        // Step 3-5: Database validation (if database is available)
+        let Some(ref db) = self.db else {
+            // No database configured - rely only on JWT validation
+            debug!("Database not configured, skipping token database validation");
+            return Ok(claims.to_auth_result());
+        };
+
+        // Hash the token using SHA-256 (same as when storing)
+        let token_hash = format!("{:x}", Sha256::digest(token.as_bytes()));
+
+        // Look up token in database by hash
+        let token_record = AuthTokenEntity::find()
+            .filter(auth_token::Column::TokenHash.eq(&token_hash))
+            .one(db)
+            .await
+            .map_err(|e| {
+                AuthError::InternalError(format!("Database error during authentication: {}", e))
+            })?
+            .ok_or_else(|| {
+                AuthError::Unauthorized("Auth token not found or has been revoked".to_string())
+            })?;
+
+        // Verify token is active
+        if !token_record.is_active {
+            return Err(AuthError::Unauthorized(
+                "Auth token has been deactivated".to_string(),
+            ));
+        }
+
+        // Check if token is expired
+        if let Some(expires_at) = token_record.expires_at {
+            if expires_at < chrono::Utc::now() {
+                return Err(AuthError::TokenExpired);
+            }
+        }
+
+        // Verify user_id matches (ensure JWT wasn't tampered with)
+        if token_record.user_id.to_string() != claimed_user_id {
+            return Err(AuthError::Unauthorized(
+                "Token user_id mismatch - possible JWT tampering".to_string(),
+            ));
+        }
+
+        // Update last_used_at timestamp
+        let mut active_model: auth_token::ActiveModel = token_record.into();
+        active_model.last_used_at = Set(Some(chrono::Utc::now()));
+        if let Err(e) = ActiveModelTrait::update(active_model, db).await {
+            // Log error but don't fail authentication
+            warn!("Failed to update last_used_at for token: {}", e);
+        }
+
+        Ok(claims.to_auth_result())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use localup_auth::JwtClaims;
+    use localup_relay_db::entities::user;
+    use uuid::Uuid;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn auth_token(user_id: Uuid, token_type: &str) -> String {
+        let claims = JwtClaims::new(
+            "token-1".to_string(),
+            "localup".to_string(),
+            "localup".to_string(),
+            Duration::hours(1),
+        )
+        .with_user_id(user_id.to_string())
+        .with_token_type(token_type.to_string())
+        .with_protocols(vec!["http".to_string()]);
+        JwtValidator::encode(SECRET, &claims).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_relay_token_validation() {
+        let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+        localup_relay_db::migrate(&db).await.unwrap();
+
+        let user_id = Uuid::new_v4();
+        user::ActiveModel {
+            id: Set(user_id),
+            email: Set("owner@example.com".to_string()),
+            password_hash: Set("x".to_string()),
+            full_name: Set(None),
+            role: Set(user::UserRole::User),
+            is_active: Set(true),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let token = auth_token(user_id, "auth");
+        let validator =
+            RelayTokenValidator::new(Arc::new(JwtValidator::new(SECRET))).with_database(db.clone());
+
+        // Not stored in the database yet
+        assert!(matches!(
+            validator.validate(&token).await,
+            Err(AuthError::Unauthorized(_))
+        ));
+
+        let record = auth_token::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            user_id: Set(user_id),
+            team_id: Set(None),
+            name: Set("ci".to_string()),
+            description: Set(None),
+            token_hash: Set(format!("{:x}", Sha256::digest(token.as_bytes()))),
+            last_used_at: Set(None),
+            expires_at: Set(None),
+            is_active: Set(true),
+            created_at: Set(Utc::now()),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let result = validator.validate(&token).await.unwrap();
+        assert_eq!(result.identity(), user_id.to_string());
+        assert_eq!(result.allowed_protocols, vec!["http"]);
+
+        let mut record: auth_token::ActiveModel = record.into();
+        record.is_active = Set(false);
+        record.update(&db).await.unwrap();
+        assert!(matches!(
+            validator.validate(&token).await,
+            Err(AuthError::Unauthorized(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_session_and_foreign_tokens() {
+        let validator = RelayTokenValidator::new(Arc::new(JwtValidator::new(SECRET)));
+
+        let session = auth_token(Uuid::new_v4(), "session");
+        assert!(matches!(
+            validator.validate(&session).await,
+            Err(AuthError::Unauthorized(_))
+        ));
+
+        // Tokens that are not our JWTs are left to other validators in a chain
+        assert!(matches!(
+            validator.validate("sk_live_123").await,
+            Err(AuthError::InvalidToken(_))
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+//! Per-token cap on concurrently connected tunnels
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Tunnels connected per auth token (token key -> tunnel IDs, one entry per connection)
+#[derive(Default)]
+pub struct TunnelSlots {
+    tunnels: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl TunnelSlots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Claim a slot for `localup_id` under `token_key`, released when the slot is dropped
+    ///
+    /// A tunnel reconnecting under the same ID does not count against `max` twice.
+    pub fn try_claim(
+        self: &Arc<Self>,
+        token_key: &str,
+        localup_id: &str,
+        max: u32,
+    ) -> Result<TunnelSlot, usize> {
+        let mut tunnels = self.tunnels.lock().unwrap();
+        let ids = tunnels.entry(token_key.to_string()).or_default();
+
+        let others: HashSet<&String> = ids.iter().filter(|id| *id != localup_id).collect();
+        if others.len() >= max as usize {
+            return Err(others.len());
+        }
+
+        ids.push(localup_id.to_string());
+        Ok(TunnelSlot {
+            slots: self.clone(),
+            token_key: token_key.to_string(),
+            localup_id: localup_id.to_string(),
+        })
+    }
+
+    /// Number of tunnels connected under a token
+    pub fn count(&self, token_key: &str) -> usize {
+        self.tunnels
+            .lock()
+            .unwrap()
+            .get(token_key)
+            .map(|ids| ids.iter().collect::<HashSet<_>>().len())
+            .unwrap_or(0)
+    }
+
+    fn release(&self, token_key: &str, localup_id: &str) {
+        let mut tunnels = self.tunnels.lock().unwrap();
+        if let Some(ids) = tunnels.get_mut(token_key) {
+            if let Some(pos) = ids.iter().position(|id| id == localup_id) {
+                ids.remove(pos);
+            }
+            if ids.is_empty() {
+                tunnels.remove(token_key);
+            }
+        }
+    }
+}
+
+/// A tunnel slot claimed with [`TunnelSlots::try_claim`]
+pub struct TunnelSlot {
+    slots: Arc<TunnelSlots>,
+    token_key: String,
+    localup_id: String,
+}
+
+impl Drop for TunnelSlot {
+    fn drop(&mut self) {
+        self.slots.release(&self.token_key, &self.localup_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_tunnels_per_token() {
+        let slots = Arc::new(TunnelSlots::new());
+
+        let a = slots.try_claim("token", "tunnel-a", 2).unwrap();
+        let _b = slots.try_claim("token", "tunnel-b", 2).unwrap();
+        assert_eq!(slots.try_claim("token", "tunnel-c", 2).err(), Some(2));
+
+        // Other tokens are counted separately
+        assert!(slots.try_claim("other", "tunnel-c", 2).is_ok());
+
+        drop(a);
+        assert_eq!(slots.count("token"), 1);
+        assert!(slots.try_claim("token", "tunnel-c", 2).is_ok());
+    }
+
+    #[test]
+    fn test_reconnect_does_not_count_twice() {
+        let slots = Arc::new(TunnelSlots::new());
+
+        let old = slots.try_claim("token", "tunnel-a", 1).unwrap();
+        // Reconnect arrives before the old connection is cleaned up
+        let _new = slots.try_claim("token", "tunnel-a", 1).unwrap();
+        drop(old);
+        assert_eq!(slots.count("token"), 1);
+        assert!(slots.try_claim("token", "tunnel-b", 1).is_err());
+    }
+}
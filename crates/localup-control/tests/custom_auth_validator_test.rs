@@ -0,0 +1,183 @@
+//! Integration tests for tunnel handlers using a custom AuthValidator
+
+use localup_auth::{
+    async_trait, AuthError, AuthResult, AuthValidator, AuthValidatorChain, JwtClaims, JwtValidator,
+};
+use localup_control::{PendingRequests, TunnelConnectionManager, TunnelHandler};
+use localup_proto::{Protocol, TunnelConfig, TunnelMessage};
+use localup_router::RouteRegistry;
+use localup_transport::{
+    TransportConnection, TransportConnector, TransportListener, TransportStream,
+};
+use localup_transport_quic::{QuicConfig, QuicConnector, QuicListener};
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::time::timeout;
+
+static CRYPTO_PROVIDER_INIT: OnceLock<()> = OnceLock::new();
+
+fn init_crypto_provider() {
+    CRYPTO_PROVIDER_INIT.get_or_init(|| {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+    });
+}
+
+fn create_test_server_config() -> Arc<QuicConfig> {
+    let temp_dir = std::env::temp_dir().join("localup-custom-auth-test");
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let cert_path = temp_dir.join("cert.pem");
+    let key_path = temp_dir.join("key.pem");
+    let cert_data = localup_cert::generate_self_signed_cert().unwrap();
+    std::fs::write(&cert_path, cert_data.pem_cert).unwrap();
+    std::fs::write(&key_path, cert_data.pem_key).unwrap();
+
+    Arc::new(
+        QuicConfig::server_default(cert_path.to_str().unwrap(), key_path.to_str().unwrap())
+            .unwrap(),
+    )
+}
+
+/// API keys from an application's own user system
+struct ApiKeyValidator;
+
+#[async_trait]
+impl AuthValidator for ApiKeyValidator {
+    async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
+        match token {
+            "sk_http_only" => Ok(AuthResult::new("key-1".to_string())
+                .with_user_id("customer-42".to_string())
+                .with_protocols(vec!["http".to_string()])
+                .with_allowed_subdomains(vec!["customer-42-*".to_string()])),
+            "sk_suspended" => Err(AuthError::Unauthorized("account suspended".to_string())),
+            _ => Err(AuthError::InvalidToken("unknown API key".to_string())),
+        }
+    }
+}
+
+async fn start_relay(validator: Arc<dyn AuthValidator>) -> SocketAddr {
+    init_crypto_provider();
+
+    let handler = Arc::new(
+        TunnelHandler::new(
+            Arc::new(TunnelConnectionManager::new()),
+            Arc::new(RouteRegistry::new()),
+            None,
+            "localhost".to_string(),
+            Arc::new(PendingRequests::new()),
+        )
+        .with_auth_validator(validator),
+    );
+
+    let listener =
+        QuicListener::new("127.0.0.1:0".parse().unwrap(), create_test_server_config()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((conn, peer_addr)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                handler.handle_connection(Arc::new(conn), peer_addr).await;
+            });
+        }
+    });
+    addr
+}
+
+async fn connect(addr: SocketAddr, token: &str, protocol: Protocol) -> TunnelMessage {
+    let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure())).unwrap();
+    let connection = connector.connect(addr, "localhost").await.unwrap();
+    let mut control_stream = connection.open_stream().await.unwrap();
+
+    control_stream
+        .send_message(&TunnelMessage::Connect {
+            localup_id: format!("tunnel-{}", uuid::Uuid::new_v4()),
+            auth_token: token.to_string(),
+            protocols: vec![protocol],
+            config: TunnelConfig::default(),
+        })
+        .await
+        .unwrap();
+
+    timeout(Duration::from_secs(5), control_stream.recv_message())
+        .await
+        .expect("Timeout waiting for response")
+        .unwrap()
+        .expect("Stream closed without a response")
+}
+
+fn http(subdomain: &str) -> Protocol {
+    Protocol::Http {
+        subdomain: Some(subdomain.to_string()),
+        custom_domain: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_custom_validator_drives_permissions() {
+    let addr = start_relay(Arc::new(ApiKeyValidator)).await;
+
+    match connect(addr, "sk_http_only", http("customer-42-web")).await {
+        TunnelMessage::Connected { endpoints, .. } => {
+            assert!(endpoints[0].public_url.contains("customer-42-web"));
+        }
+        other => panic!("Expected Connected, got {:?}", other),
+    }
+
+    match connect(addr, "sk_http_only", http("someone-else")).await {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("subdomain 'someone-else'"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+
+    match connect(addr, "sk_http_only", Protocol::Tcp { port: 0 }).await {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("does not allow tcp"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+
+    match connect(addr, "sk_unknown", http("customer-42-web")).await {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("Authentication failed"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_validator_chain_accepts_api_keys_and_jwts() {
+    let secret = b"chain-secret";
+    let addr = start_relay(Arc::new(
+        AuthValidatorChain::new()
+            .with(Arc::new(ApiKeyValidator))
+            .with(Arc::new(JwtValidator::new(secret))),
+    ))
+    .await;
+
+    let claims = JwtClaims::new(
+        "client-1".to_string(),
+        "localup".to_string(),
+        "localup".to_string(),
+        chrono::Duration::hours(1),
+    );
+    let jwt = JwtValidator::encode(secret, &claims).unwrap();
+
+    assert!(matches!(
+        connect(addr, &jwt, http("from-jwt")).await,
+        TunnelMessage::Connected { .. }
+    ));
+    assert!(matches!(
+        connect(addr, "sk_http_only", http("customer-42-api")).await,
+        TunnelMessage::Connected { .. }
+    ));
+
+    // Rejections other than "unknown token" are final
+    match connect(addr, "sk_suspended", http("customer-42-api")).await {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("account suspended"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+}
@@ -0,0 +1,155 @@
+//! Integration tests for quota enforcement when tunnels connect
+
+use chrono::Utc;
+use localup_auth::{async_trait, AuthError, AuthResult, AuthValidator};
+use localup_control::{PendingRequests, QuotaEnforcer, TunnelConnectionManager, TunnelHandler};
+use localup_proto::{Protocol, TunnelConfig, TunnelMessage};
+use localup_relay_db::entities::plan;
+use localup_router::RouteRegistry;
+use localup_transport::{
+    TransportConnection, TransportConnector, TransportListener, TransportStream,
+};
+use localup_transport_quic::{QuicConfig, QuicConnection, QuicConnector, QuicListener, QuicStream};
+use sea_orm::{ActiveModelTrait, Set};
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::time::timeout;
+
+static CRYPTO_PROVIDER_INIT: OnceLock<()> = OnceLock::new();
+
+fn init_crypto_provider() {
+    CRYPTO_PROVIDER_INIT.get_or_init(|| {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+    });
+}
+
+fn create_test_server_config() -> Arc<QuicConfig> {
+    let temp_dir = std::env::temp_dir().join("localup-quotas-test");
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let cert_path = temp_dir.join("cert.pem");
+    let key_path = temp_dir.join("key.pem");
+    let cert_data = localup_cert::generate_self_signed_cert().unwrap();
+    std::fs::write(&cert_path, cert_data.pem_cert).unwrap();
+    std::fs::write(&key_path, cert_data.pem_key).unwrap();
+
+    Arc::new(
+        QuicConfig::server_default(cert_path.to_str().unwrap(), key_path.to_str().unwrap())
+            .unwrap(),
+    )
+}
+
+/// Every token belongs to the same relay user
+struct SingleUserValidator(String);
+
+#[async_trait]
+impl AuthValidator for SingleUserValidator {
+    async fn validate(&self, token: &str) -> Result<AuthResult, AuthError> {
+        Ok(AuthResult::new(token.to_string()).with_user_id(self.0.clone()))
+    }
+}
+
+async fn start_relay() -> SocketAddr {
+    init_crypto_provider();
+
+    let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+    localup_relay_db::migrate(&db).await.unwrap();
+    plan::ActiveModel {
+        id: Set(uuid::Uuid::new_v4()),
+        name: Set(plan::DEFAULT_PLAN.to_string()),
+        max_tunnels: Set(Some(1)),
+        max_custom_domains: Set(Some(0)),
+        max_reserved_ports: Set(None),
+        monthly_bytes: Set(None),
+        max_connections: Set(None),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+
+    let handler = Arc::new(
+        TunnelHandler::new(
+            Arc::new(TunnelConnectionManager::new()),
+            Arc::new(RouteRegistry::new()),
+            None,
+            "localhost".to_string(),
+            Arc::new(PendingRequests::new()),
+        )
+        .with_auth_validator(Arc::new(SingleUserValidator(
+            uuid::Uuid::new_v4().to_string(),
+        )))
+        .with_quotas(Arc::new(QuotaEnforcer::new(db))),
+    );
+
+    let listener =
+        QuicListener::new("127.0.0.1:0".parse().unwrap(), create_test_server_config()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((conn, peer_addr)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                handler.handle_connection(Arc::new(conn), peer_addr).await;
+            });
+        }
+    });
+    addr
+}
+
+/// Connect a tunnel, returning the relay's answer and the stream keeping it open
+async fn connect(
+    addr: SocketAddr,
+    protocol: Protocol,
+) -> (TunnelMessage, (QuicConnection, QuicStream)) {
+    let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure())).unwrap();
+    let connection = connector.connect(addr, "localhost").await.unwrap();
+    let mut control_stream = connection.open_stream().await.unwrap();
+
+    control_stream
+        .send_message(&TunnelMessage::Connect {
+            localup_id: format!("tunnel-{}", uuid::Uuid::new_v4()),
+            auth_token: "token".to_string(),
+            protocols: vec![protocol],
+            config: TunnelConfig::default(),
+        })
+        .await
+        .unwrap();
+
+    let message = timeout(Duration::from_secs(5), control_stream.recv_message())
+        .await
+        .expect("Timeout waiting for response")
+        .unwrap()
+        .expect("Stream closed without a response");
+    (message, (connection, control_stream))
+}
+
+fn http(subdomain: &str, custom_domain: Option<&str>) -> Protocol {
+    Protocol::Http {
+        subdomain: Some(subdomain.to_string()),
+        custom_domain: custom_domain.map(str::to_string),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_quota_limits_tunnels_and_custom_domains() {
+    let addr = start_relay().await;
+
+    match connect(addr, http("web", Some("app.example.com"))).await.0 {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("Quota exceeded"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+
+    let (first, _tunnel) = connect(addr, http("web", None)).await;
+    assert!(matches!(first, TunnelMessage::Connected { .. }));
+
+    match connect(addr, http("api", None)).await.0 {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("1 connected tunnels"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+}
@@ -0,0 +1,181 @@
+//! Integration tests for disconnecting live tunnels whose token was revoked
+
+use chrono::{Duration as ChronoDuration, Utc};
+use localup_auth::{JwtClaims, JwtValidator};
+use localup_control::{PendingRequests, RevocationList, TunnelConnectionManager, TunnelHandler};
+use localup_proto::{Protocol, TunnelConfig, TunnelMessage};
+use localup_relay_db::entities::revoked_token;
+use localup_router::RouteRegistry;
+use localup_transport::{
+    TransportConnection, TransportConnector, TransportListener, TransportStream,
+};
+use localup_transport_quic::{QuicConfig, QuicConnection, QuicConnector, QuicListener, QuicStream};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::time::timeout;
+
+const SECRET: &[u8] = b"test-secret";
+
+static CRYPTO_PROVIDER_INIT: OnceLock<()> = OnceLock::new();
+
+fn init_crypto_provider() {
+    CRYPTO_PROVIDER_INIT.get_or_init(|| {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+    });
+}
+
+fn create_test_server_config() -> Arc<QuicConfig> {
+    let temp_dir = std::env::temp_dir().join("localup-revocation-test");
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    let cert_path = temp_dir.join("cert.pem");
+    let key_path = temp_dir.join("key.pem");
+    let cert_data = localup_cert::generate_self_signed_cert().unwrap();
+    std::fs::write(&cert_path, cert_data.pem_cert).unwrap();
+    std::fs::write(&key_path, cert_data.pem_key).unwrap();
+
+    Arc::new(
+        QuicConfig::server_default(cert_path.to_str().unwrap(), key_path.to_str().unwrap())
+            .unwrap(),
+    )
+}
+
+async fn start_relay() -> (SocketAddr, Arc<TunnelHandler>, DatabaseConnection) {
+    init_crypto_provider();
+
+    let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+    localup_relay_db::migrate(&db).await.unwrap();
+
+    let handler = Arc::new(
+        TunnelHandler::new(
+            Arc::new(TunnelConnectionManager::new()),
+            Arc::new(RouteRegistry::new()),
+            Some(Arc::new(JwtValidator::new(SECRET))),
+            "localhost".to_string(),
+            Arc::new(PendingRequests::new()),
+        )
+        .with_revocations(Arc::new(RevocationList::new(db.clone()))),
+    );
+
+    let listener =
+        QuicListener::new("127.0.0.1:0".parse().unwrap(), create_test_server_config()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accept_handler = handler.clone();
+    tokio::spawn(async move {
+        while let Ok((conn, peer_addr)) = listener.accept().await {
+            let handler = accept_handler.clone();
+            tokio::spawn(async move {
+                handler.handle_connection(Arc::new(conn), peer_addr).await;
+            });
+        }
+    });
+    (addr, handler, db)
+}
+
+fn token(jti: &str) -> String {
+    let claims = JwtClaims::new(
+        "client".to_string(),
+        "localup".to_string(),
+        "localup".to_string(),
+        ChronoDuration::hours(1),
+    )
+    .with_jti(jti.to_string())
+    .with_user_id(uuid::Uuid::new_v4().to_string());
+    JwtValidator::encode(SECRET, &claims).unwrap()
+}
+
+async fn revoke(db: &DatabaseConnection, jti: &str, reason: &str) {
+    revoked_token::ActiveModel {
+        id: Set(uuid::Uuid::new_v4()),
+        jti: Set(jti.to_string()),
+        user_id: Set(None),
+        reason: Set(Some(reason.to_string())),
+        revoked_by: Set(None),
+        expires_at: Set(Some(Utc::now() + ChronoDuration::hours(1))),
+        revoked_at: Set(Utc::now()),
+    }
+    .insert(db)
+    .await
+    .unwrap();
+}
+
+/// Connect a tunnel, returning the relay's answer and the stream keeping it open
+async fn connect(addr: SocketAddr, token: String) -> (TunnelMessage, (QuicConnection, QuicStream)) {
+    let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure())).unwrap();
+    let connection = connector.connect(addr, "localhost").await.unwrap();
+    let mut control_stream = connection.open_stream().await.unwrap();
+
+    control_stream
+        .send_message(&TunnelMessage::Connect {
+            localup_id: format!("tunnel-{}", uuid::Uuid::new_v4()),
+            auth_token: token,
+            protocols: vec![Protocol::Http {
+                subdomain: None,
+                custom_domain: None,
+            }],
+            config: TunnelConfig::default(),
+        })
+        .await
+        .unwrap();
+
+    let message = timeout(Duration::from_secs(5), control_stream.recv_message())
+        .await
+        .expect("Timeout waiting for response")
+        .unwrap()
+        .expect("Stream closed without a response");
+    (message, (connection, control_stream))
+}
+
+/// Wait for the relay to disconnect a tunnel, answering its pings
+async fn disconnect_reason(stream: &mut QuicStream) -> String {
+    loop {
+        let message = timeout(Duration::from_secs(5), stream.recv_message())
+            .await
+            .expect("Timeout waiting for Disconnect")
+            .unwrap()
+            .expect("Stream closed without a Disconnect");
+        match message {
+            TunnelMessage::Ping { timestamp } => {
+                let _ = stream
+                    .send_message(&TunnelMessage::Pong { timestamp })
+                    .await;
+            }
+            TunnelMessage::Disconnect { reason } => return reason,
+            other => panic!("Expected Disconnect, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_revoked_token_disconnects_live_tunnel() {
+    let (addr, handler, db) = start_relay().await;
+
+    let (message, (_revoked_conn, mut revoked_stream)) = connect(addr, token("revoked")).await;
+    assert!(matches!(message, TunnelMessage::Connected { .. }));
+    let (message, _kept) = connect(addr, token("kept")).await;
+    assert!(matches!(message, TunnelMessage::Connected { .. }));
+
+    // Nothing has changed yet
+    assert_eq!(handler.revalidate_sessions().await, 0);
+
+    revoke(&db, "revoked", "laptop stolen").await;
+    assert_eq!(handler.revalidate_sessions().await, 1);
+    assert_eq!(
+        disconnect_reason(&mut revoked_stream).await,
+        "Token revoked: laptop stolen"
+    );
+
+    // The token can't be used to reconnect either
+    match connect(addr, token("revoked")).await.0 {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(
+                reason.contains("Token revoked: laptop stolen"),
+                "{}",
+                reason
+            )
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+}
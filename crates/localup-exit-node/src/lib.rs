@@ -1,3 +1,5 @@
 //! Exit node orchestrator library
 pub mod orchestrator;
-pub use orchestrator::ExitNode;
+pub use orchestrator::{
+    Orchestrator, OrchestratorClient, OrchestratorConfig, OrchestratorError, RegistrationResponse,
+};
@@ -11,15 +11,19 @@ use tokio::signal;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use localup_auth::{JwtClaims, JwtValidator};
+use localup_auth::{JwksFile, JwtClaims, JwtSigner, JwtValidator, VerificationKey};
 use localup_cert::{AcmeClient, AcmeConfig};
 use localup_control::{
-    AgentRegistry, PortAllocator as PortAllocatorTrait, TunnelConnectionManager, TunnelHandler,
+    AgentRegistry, ClusterConfig, ClusterNode, DbPortAllocator, ForwardKind,
+    PortAllocator as PortAllocatorTrait, QuotaEnforcer, RevocationList, TrafficMeter,
+    TunnelConnectionManager, TunnelHandler,
 };
+use localup_exit_node::{Orchestrator, OrchestratorClient, OrchestratorConfig};
+use localup_proto::relay_directory::{RelayConfig, RelayInfo, RelayLoad};
 use localup_router::RouteRegistry;
-use localup_server_https::{HttpsServer, HttpsServerConfig};
+use localup_server_https::{CustomCertResolver, HttpsServer, HttpsServerConfig};
 use localup_server_tcp::{TcpServer, TcpServerConfig};
-use localup_server_tls::{TlsServer, TlsServerConfig};
+use localup_server_tls::{TlsPreamble, TlsServer, TlsServerConfig};
 use localup_transport_quic::QuicConfig;
 
 /// Tunnel exit node - accepts public connections and routes to tunnels
@@ -67,6 +71,40 @@ enum Commands {
         #[arg(long = "address")]
         allowed_addresses: Vec<String>,
     },
+
+    /// Run the orchestrator: a registry of relays served as a live relay directory
+    Orchestrator {
+        /// Bind address of the orchestrator API
+        #[arg(long, default_value = "0.0.0.0:3090")]
+        bind_addr: String,
+
+        /// Relay directory (relays.yaml format) with the settings, selection
+        /// policies and relays that don't register themselves
+        #[arg(long, value_name = "PATH")]
+        directory: Option<String>,
+
+        /// Token relays must present to register
+        #[arg(long, env = "LOCALUP_ORCHESTRATOR_TOKEN")]
+        token: Option<String>,
+
+        /// Private key (PEM: Ed25519, P-256, P-384 or RSA) signing the directory
+        /// served at /relays.jws; its public key is published at /.well-known/jwks.json.
+        /// Requires --token
+        #[arg(long, value_name = "PATH")]
+        signing_key: Option<String>,
+
+        /// Seconds between relay heartbeats
+        #[arg(long, default_value = "10")]
+        heartbeat_interval: u64,
+
+        /// Seconds without a heartbeat before a relay is marked down
+        #[arg(long, default_value = "30")]
+        heartbeat_timeout: u64,
+
+        /// Log level (trace, debug, info, warn, error)
+        #[arg(long, default_value = "info")]
+        log_level: String,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -87,6 +125,15 @@ struct ServerArgs {
     #[arg(long)]
     tls_addr: Option<String>,
 
+    /// Tunnel that receives TLS connections whose SNI matches no route (or that send no SNI)
+    #[arg(long, requires = "tls_addr")]
+    tls_default_tunnel: Option<String>,
+
+    /// PostgreSQL listener address shared by TLS tunnels (e.g., "0.0.0.0:5432")
+    /// Clients must connect with sslmode=require so their SNI can be routed
+    #[arg(long)]
+    postgres_addr: Option<String>,
+
     /// TLS certificate file path (PEM format, for HTTPS server and custom QUIC certs)
     /// If not specified for QUIC, a self-signed certificate is auto-generated
     #[arg(long)]
@@ -107,6 +154,23 @@ struct ServerArgs {
     #[arg(long, env)]
     jwt_secret: Option<String>,
 
+    /// Previous JWT secret whose tokens are still accepted (repeatable)
+    #[arg(long = "jwt-previous-secret", value_name = "SECRET")]
+    jwt_previous_secrets: Vec<String>,
+
+    /// Private key (PEM: Ed25519, P-256, P-384 or RSA) that signs tokens issued by the API
+    /// instead of --jwt-secret; its public key is published at /.well-known/jwks.json
+    #[arg(long, value_name = "PATH")]
+    jwt_signing_key: Option<String>,
+
+    /// Previous signing key whose tokens are still accepted and published (repeatable)
+    #[arg(long = "jwt-previous-signing-key", value_name = "PATH")]
+    jwt_previous_signing_keys: Vec<String>,
+
+    /// JWKS file with public keys of other token issuers, re-read when it changes
+    #[arg(long, value_name = "PATH")]
+    jwt_jwks_file: Option<String>,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, default_value = "info")]
     log_level: String,
@@ -115,6 +179,15 @@ struct ServerArgs {
     #[arg(long)]
     tcp_port_range: Option<String>,
 
+    /// Default number of TCP ports each team (or user) may reserve through the API
+    /// (teams can override it with their own quota)
+    #[arg(long)]
+    tcp_port_quota: Option<u32>,
+
+    /// HTTP CONNECT / SOCKS5 proxy bind address for hostname-routed TCP tunnels
+    #[arg(long)]
+    tcp_connect_addr: Option<String>,
+
     /// API server bind address (for dashboard/management UI)
     #[arg(long, default_value = "127.0.0.1:3080")]
     api_addr: String,
@@ -150,6 +223,35 @@ struct ServerArgs {
     /// Directory to store ACME certificates
     #[arg(long, default_value = "/opt/localup/certs/acme")]
     acme_cert_dir: String,
+
+    /// Join a relay cluster under this unique node ID (requires a shared --database-url)
+    #[arg(long, env = "LOCALUP_CLUSTER_NODE_ID", requires = "cluster_secret")]
+    cluster_node_id: Option<String>,
+
+    /// Bind address of the inter-node QUIC link
+    #[arg(long, default_value = "0.0.0.0:4450")]
+    cluster_bind_addr: String,
+
+    /// Address other nodes dial for the inter-node link (defaults to the bind address)
+    #[arg(long)]
+    cluster_advertise_addr: Option<String>,
+
+    /// Secret shared by all nodes of the cluster, authenticating inter-node links
+    #[arg(long, env = "LOCALUP_CLUSTER_SECRET")]
+    cluster_secret: Option<String>,
+
+    /// Orchestrator to register this relay with (e.g. "https://orchestrator.example.com")
+    #[arg(long, requires = "relay_info")]
+    orchestrator_url: Option<String>,
+
+    /// Token presented to the orchestrator
+    #[arg(long, env = "LOCALUP_ORCHESTRATOR_TOKEN")]
+    orchestrator_token: Option<String>,
+
+    /// This relay's directory entry (YAML, as in relays.yaml: id, name, region,
+    /// location, endpoints, status, tags)
+    #[arg(long, value_name = "PATH")]
+    relay_info: Option<String>,
 }
 
 fn generate_token(
@@ -260,6 +362,26 @@ async fn main() -> Result<()> {
                 allowed_agents,
                 allowed_addresses,
             ),
+            Commands::Orchestrator {
+                bind_addr,
+                directory,
+                token,
+                signing_key,
+                heartbeat_interval,
+                heartbeat_timeout,
+                log_level,
+            } => {
+                init_logging(&log_level)?;
+                run_orchestrator(
+                    &bind_addr,
+                    directory.as_deref(),
+                    token,
+                    signing_key.as_deref(),
+                    heartbeat_interval,
+                    heartbeat_timeout,
+                )
+                .await
+            }
         };
     }
 
@@ -301,23 +423,22 @@ async fn main() -> Result<()> {
             end,
             end - start + 1
         );
-        Some(Arc::new(PortAllocator::new(start, end)))
+        // Assignments live in the database so ports stay with their owners across restarts
+        Some(Arc::new(DbPortAllocator::new(db.clone(), start, end)))
     } else {
         None
     };
-
-    // Start cleanup task for expired port reservations
-    if let Some(ref allocator) = port_allocator {
-        let allocator_clone = allocator.clone();
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60)); // Check every minute
-            loop {
-                interval.tick().await;
-                allocator_clone.cleanup_expired();
-            }
-        });
-        info!("✅ Port reservation cleanup task started (checks every 60s)");
-    }
+    let tcp_port_policy = match args.tcp_port_range {
+        Some(ref tcp_range) => {
+            let (range_start, range_end) = parse_port_range(tcp_range)?;
+            Some(localup_api::TcpPortPolicy {
+                range_start,
+                range_end,
+                default_quota: args.tcp_port_quota,
+            })
+        }
+        None => None,
+    };
 
     // Create shared route registry
     let registry = Arc::new(RouteRegistry::new());
@@ -327,12 +448,42 @@ async fn main() -> Result<()> {
     // Clone JWT secret before moving it (needed for both JWT validator and API server config)
     let jwt_secret_for_api = args.jwt_secret.clone();
 
+    // Tokens signed with previous secrets and keys stay valid while they are configured
+    let mut jwt_keys: Vec<VerificationKey> = args
+        .jwt_secret
+        .iter()
+        .chain(&args.jwt_previous_secrets)
+        .map(|secret| VerificationKey::hmac(None, secret.as_bytes()))
+        .collect();
+    for path in &args.jwt_previous_signing_keys {
+        jwt_keys.push(read_signer(path)?.verification_key());
+    }
+    let jwt_signer = match &args.jwt_signing_key {
+        Some(path) => {
+            let signer = read_signer(path)?;
+            jwt_keys.push(signer.verification_key());
+            Some(Arc::new(signer))
+        }
+        None => None,
+    };
+    let jwks_file = match &args.jwt_jwks_file {
+        Some(path) => {
+            let file = Arc::new(JwksFile::load(path)?);
+            file.spawn_reload_task(std::time::Duration::from_secs(10));
+            Some(file)
+        }
+        None => None,
+    };
+
     // Create JWT validator for tunnel authentication
     // Note: Only validates signature and expiration (no issuer/audience validation)
-    let jwt_validator = if let Some(jwt_secret) = args.jwt_secret {
-        let validator = Arc::new(JwtValidator::new(jwt_secret.as_bytes()));
+    let jwt_validator = if !jwt_keys.is_empty() || jwks_file.is_some() {
+        let mut validator = JwtValidator::from_keys(jwt_keys);
+        if let Some(file) = jwks_file {
+            validator = validator.with_jwks_file(file);
+        }
         info!("✅ JWT authentication enabled (signature only)");
-        Some(validator)
+        Some(Arc::new(validator))
     } else {
         info!("⚠️  Running without JWT authentication (not recommended for production)");
         None
@@ -348,15 +499,39 @@ async fn main() -> Result<()> {
     // Create pending requests tracker (shared between HTTP server and tunnel handler)
     let pending_requests = Arc::new(localup_control::PendingRequests::new());
 
+    // Relays sharing the database claim routes cluster-wide and forward visitors
+    // of tunnels connected to other nodes
+    let cluster = match args.cluster_node_id {
+        Some(ref node_id) => {
+            if args.database_url.starts_with("sqlite::memory:") {
+                anyhow::bail!("Cluster mode requires a --database-url shared by all nodes");
+            }
+            let secret = args
+                .cluster_secret
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("--cluster-secret is required in cluster mode"))?;
+            let mut config = ClusterConfig::new(node_id, args.cluster_bind_addr.parse()?, secret);
+            if let Some(ref addr) = args.cluster_advertise_addr {
+                config = config.with_advertise_addr(addr.parse()?);
+            }
+            Some(Arc::new(ClusterNode::new(config, db.clone())))
+        }
+        None => None,
+    };
+
     // Start HTTP server with tunnel manager and pending requests
     let http_addr: SocketAddr = args.http_addr.parse()?;
     let http_config = TcpServerConfig {
         bind_addr: http_addr,
     };
-    let http_server = TcpServer::new(http_config, registry.clone())
+    let mut http_server = TcpServer::new(http_config, registry.clone())
         .with_localup_manager(localup_manager.clone())
         .with_pending_requests(pending_requests.clone())
         .with_database(db.clone());
+    if let Some(ref cluster) = cluster {
+        http_server = http_server.with_cluster(cluster.clone());
+        cluster.set_ingress(ForwardKind::Http, http_server.cluster_ingress());
+    }
 
     let http_handle = tokio::spawn(async move {
         info!("Starting HTTP relay server");
@@ -365,6 +540,18 @@ async fn main() -> Result<()> {
         }
     });
 
+    // Certificates shared by the HTTPS server and TLS termination on the SNI server
+    let cert_resolver = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert_path), Some(key_path))
+            if args.https_addr.is_some() || args.tls_addr.is_some() =>
+        {
+            Some(Arc::new(CustomCertResolver::from_files(
+                cert_path, key_path,
+            )?))
+        }
+        _ => None,
+    };
+
     // Start HTTPS server if configured
     let https_handle = if let Some(ref https_addr) = args.https_addr {
         // HTTPS requires cert/key files
@@ -384,9 +571,16 @@ async fn main() -> Result<()> {
             key_path: key_path.clone(),
         };
 
-        let https_server = HttpsServer::new(https_config, registry.clone())
+        let mut https_server = HttpsServer::new(https_config, registry.clone())
             .with_localup_manager(localup_manager.clone())
             .with_pending_requests(pending_requests.clone());
+        if let Some(ref resolver) = cert_resolver {
+            https_server = https_server.with_cert_resolver(resolver.clone());
+        }
+        if let Some(ref cluster) = cluster {
+            https_server = https_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Https, https_server.cluster_ingress());
+        }
 
         Some(tokio::spawn(async move {
             info!("Starting HTTPS relay server");
@@ -405,9 +599,20 @@ async fn main() -> Result<()> {
             bind_addr: tls_addr,
         };
 
-        let tls_server = TlsServer::new(tls_config, registry.clone())
+        let mut tls_server = TlsServer::new(tls_config, registry.clone())
             .with_localup_manager(localup_manager.clone())
             .with_database(db.clone());
+        if let Some(ref resolver) = cert_resolver {
+            tls_server = tls_server.with_cert_resolver(resolver.clone());
+        }
+        if let Some(ref default_tunnel) = args.tls_default_tunnel {
+            info!("Unmatched SNI connections go to tunnel {}", default_tunnel);
+            tls_server = tls_server.with_default_tunnel(default_tunnel);
+        }
+        if let Some(ref cluster) = cluster {
+            tls_server = tls_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Tls, tls_server.cluster_ingress());
+        }
         info!("✅ TLS/SNI server configured (routes based on Server Name Indication)");
 
         Some(tokio::spawn(async move {
@@ -420,6 +625,31 @@ async fn main() -> Result<()> {
         None
     };
 
+    // Start PostgreSQL listener sharing the TLS tunnels' SNI routes
+    let postgres_handle = if let Some(ref postgres_addr) = args.postgres_addr {
+        let bind_addr: SocketAddr = postgres_addr.parse()?;
+        let mut postgres_server = TlsServer::new(TlsServerConfig { bind_addr }, registry.clone())
+            .with_localup_manager(localup_manager.clone())
+            .with_database(db.clone())
+            .with_preamble(TlsPreamble::Postgres);
+        if let Some(ref resolver) = cert_resolver {
+            postgres_server = postgres_server.with_cert_resolver(resolver.clone());
+        }
+        if let Some(ref cluster) = cluster {
+            postgres_server = postgres_server.with_cluster(cluster.clone());
+            cluster.set_ingress(ForwardKind::Postgres, postgres_server.cluster_ingress());
+        }
+
+        Some(tokio::spawn(async move {
+            info!("Starting PostgreSQL SNI relay server on {}", bind_addr);
+            if let Err(e) = postgres_server.start().await {
+                error!("PostgreSQL server error: {}", e);
+            }
+        }))
+    } else {
+        None
+    };
+
     // Start tunnel listener (QUIC by default, TCP if --insecure)
     info!(
         "🔧 Attempting to bind tunnel control to {}",
@@ -438,10 +668,18 @@ async fn main() -> Result<()> {
     .with_database(db.clone())
     .with_agent_registry(agent_registry.clone());
 
+    if let Some(port) = args
+        .tcp_connect_addr
+        .as_ref()
+        .and_then(|addr| addr.parse::<SocketAddr>().ok())
+    {
+        localup_handler = localup_handler.with_tcp_connect_port(port.port());
+    }
+
     // Add port allocator if TCP range was provided
     if let Some(ref allocator) = port_allocator {
-        localup_handler = localup_handler
-            .with_port_allocator(allocator.clone() as Arc<dyn localup_control::PortAllocator>);
+        localup_handler =
+            localup_handler.with_port_allocator(allocator.clone() as Arc<dyn PortAllocatorTrait>);
         info!("✅ TCP port allocator configured");
 
         // Add TCP proxy spawner
@@ -489,7 +727,51 @@ async fn main() -> Result<()> {
         info!("✅ TCP proxy spawner configured");
     }
 
+    // Quotas are stored per user, team and plan in the relay database
+    let quotas = Arc::new(QuotaEnforcer::new(db.clone()));
+    quotas.spawn_flush_task(std::time::Duration::from_secs(30));
+    localup_handler = localup_handler.with_quotas(quotas);
+
+    // Traffic is metered into hourly rollups for billing, independent of request capture
+    let traffic_meter = Arc::new(TrafficMeter::new(db.clone()));
+    traffic_meter.spawn_flush_task(std::time::Duration::from_secs(60));
+    localup_handler = localup_handler.with_traffic_meter(traffic_meter.clone());
+
+    // Tokens revoked through the API are refused, and tunnels connected with
+    // revoked, expired or deactivated tokens are disconnected
+    localup_handler = localup_handler.with_revocations(Arc::new(RevocationList::new(db.clone())));
+
+    if let Some(ref cluster) = cluster {
+        localup_handler = localup_handler.with_cluster(cluster.clone());
+    }
+
     let localup_handler = Arc::new(localup_handler);
+    localup_handler.spawn_session_revalidation_task(std::time::Duration::from_secs(30));
+
+    // Start CONNECT/SOCKS5 proxy for hostname-routed TCP tunnels
+    let tcp_connect_handle = if let Some(ref tcp_connect_addr) = args.tcp_connect_addr {
+        use localup_server_tcp_proxy::{TcpConnectServer, TcpConnectServerConfig};
+
+        let bind_addr: SocketAddr = tcp_connect_addr.parse()?;
+        let mut connect_server = TcpConnectServer::new(
+            TcpConnectServerConfig { bind_addr },
+            registry.clone(),
+            localup_manager.clone(),
+        )
+        .with_database(db.clone());
+        // Proxy clients are held to the same token checks as tunnels
+        if let Some(validator) = localup_handler.token_validator() {
+            connect_server = connect_server.with_auth_validator(validator);
+        }
+
+        Some(tokio::spawn(async move {
+            if let Err(e) = connect_server.start().await {
+                error!("TCP CONNECT proxy error: {}", e);
+            }
+        }))
+    } else {
+        None
+    };
 
     // Start API server for dashboard/management
     let api_handle = if !args.no_api {
@@ -500,6 +782,14 @@ async fn main() -> Result<()> {
             )
         })?;
 
+        // Without a signing key the API keeps signing with the JWT secret
+        let api_jwt_keys = jwt_validator.clone().map(|validator| {
+            let signer = jwt_signer
+                .clone()
+                .unwrap_or_else(|| Arc::new(JwtSigner::hmac(api_jwt_secret.as_bytes())));
+            (signer, validator)
+        });
+
         let api_addr: SocketAddr = args.api_addr.parse()?;
         let api_localup_manager = localup_manager.clone();
         let api_db = db.clone();
@@ -563,6 +853,15 @@ async fn main() -> Result<()> {
                 info!("ACME disabled (no --acme-email provided)");
                 ApiServer::new(config, api_localup_manager, api_db, true) // allow_signup = true
             };
+            let server = match tcp_port_policy {
+                Some(policy) => server.with_tcp_port_policy(policy),
+                None => server,
+            };
+            let server = server.with_traffic_meter(traffic_meter);
+            let server = match api_jwt_keys {
+                Some((signer, validator)) => server.with_jwt_keys(signer, validator),
+                None => server,
+            };
 
             if let Err(e) = server.start().await {
                 error!("API server error: {}", e);
@@ -589,6 +888,17 @@ async fn main() -> Result<()> {
             config
         };
 
+        // The inter-node link reuses the control plane's certificate; nodes
+        // authenticate each other with the cluster secret
+        if let Some(ref cluster) = cluster {
+            let cluster_addr = cluster.start(quic_config.clone()).await?;
+            info!(
+                "🔗 Relay node {} joined the cluster (inter-node link on {})",
+                cluster.node_id(),
+                cluster_addr
+            );
+        }
+
         let localup_addr: std::net::SocketAddr = args.localup_addr.parse()?;
         let quic_listener = QuicListener::new(localup_addr, quic_config)?;
 
@@ -639,6 +949,26 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     };
 
+    // Register with the orchestrator once everything is accepting connections
+    let orchestrator = match (&args.orchestrator_url, &args.relay_info) {
+        (Some(url), Some(relay_info)) => {
+            let (client, handle) = register_with_orchestrator(
+                url,
+                args.orchestrator_token.clone(),
+                relay_info,
+                localup_manager.clone(),
+                agent_registry.clone(),
+            )?;
+            info!(
+                "🗺️  Registering relay {} with orchestrator {}",
+                client.relay_id(),
+                url
+            );
+            Some((client, handle))
+        }
+        _ => None,
+    };
+
     info!("✅ Tunnel exit node is running");
     info!("Ready to accept incoming connections");
     info!("  - HTTP traffic: {}", args.http_addr);
@@ -672,295 +1002,135 @@ async fn main() -> Result<()> {
     if let Some(handle) = tls_handle {
         handle.abort();
     }
+    if let Some(handle) = postgres_handle {
+        handle.abort();
+    }
+    if let Some(handle) = tcp_connect_handle {
+        handle.abort();
+    }
     if let Some(handle) = api_handle {
         handle.abort();
     }
     localup_handle.abort();
+    if let Some((client, handle)) = orchestrator {
+        handle.abort();
+        if let Err(e) = client.deregister().await {
+            warn!("Failed to deregister from the orchestrator: {}", e);
+        }
+    }
+    if let Some(cluster) = cluster {
+        if let Err(e) = cluster.leave().await {
+            warn!("Failed to leave the relay cluster: {}", e);
+        }
+    }
     info!("✅ Tunnel exit node stopped");
 
     Ok(())
 }
 
-fn init_logging(log_level: &str) -> Result<()> {
-    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
-        .or_else(|_| tracing_subscriber::EnvFilter::try_new(log_level))?;
-
-    tracing_subscriber::registry()
-        .with(filter)
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    Ok(())
-}
-
-use chrono::{DateTime, Utc};
-/// TCP Proxy Manager and Port Allocator with reconnection support
-use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
-
-/// Allocation state for a port
-#[derive(Debug, Clone)]
-struct PortAllocation {
-    port: u16,
-    state: AllocationState,
-}
-
-#[derive(Debug, Clone)]
-enum AllocationState {
-    Active,
-    Reserved { until: DateTime<Utc> },
-}
-
-pub struct PortAllocator {
-    range_start: u16,
-    range_end: u16,
-    available_ports: Mutex<HashSet<u16>>,
-    allocated_ports: Mutex<HashMap<String, PortAllocation>>, // localup_id -> allocation
-    reservation_ttl_seconds: i64,
+/// Read a PEM private key to sign tokens or the relay directory with
+fn read_signer(path: &str) -> Result<JwtSigner> {
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read signing key {}: {}", path, e))?;
+    Ok(JwtSigner::from_pem(&pem)?)
 }
 
-impl PortAllocator {
-    pub fn new(range_start: u16, range_end: u16) -> Self {
-        Self::with_reservation_ttl(range_start, range_end, 300) // Default 5 minute reservation
-    }
-
-    pub fn with_reservation_ttl(range_start: u16, range_end: u16, ttl_seconds: i64) -> Self {
-        let mut available = HashSet::new();
-        for port in range_start..=range_end {
-            available.insert(port);
+async fn run_orchestrator(
+    bind_addr: &str,
+    directory: Option<&str>,
+    token: Option<String>,
+    signing_key: Option<&str>,
+    heartbeat_interval: u64,
+    heartbeat_timeout: u64,
+) -> Result<()> {
+    let base: RelayConfig = match directory {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read relay directory {}: {}", path, e))?;
+            serde_yaml::from_str(&contents)
+                .map_err(|e| anyhow::anyhow!("Invalid relay directory {}: {}", path, e))?
         }
-
-        Self {
-            range_start,
-            range_end,
-            available_ports: Mutex::new(available),
-            allocated_ports: Mutex::new(HashMap::new()),
-            reservation_ttl_seconds: ttl_seconds,
+        None => Orchestrator::default_directory(),
+    };
+    if token.is_none() {
+        if signing_key.is_some() {
+            // Unauthenticated heartbeats would end up in the signed directory
+            anyhow::bail!("--signing-key requires --token so only trusted relays are signed");
         }
+        warn!("⚠️  No --token set: anyone reaching the orchestrator can register relays");
     }
-
-    /// Check if a port is actually available at the OS level
-    fn is_port_available(port: u16) -> bool {
-        use std::net::{SocketAddr, TcpListener};
-
-        // Try to bind to 0.0.0.0:port
-        let addr: SocketAddr = format!("0.0.0.0:{}", port).parse().unwrap();
-        TcpListener::bind(addr).is_ok()
-    }
-
-    /// Generate a deterministic port number from localup_id hash
-    /// This ensures the same localup_id always gets the same port (if available)
-    fn hash_to_port(&self, localup_id: &str) -> u16 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-
-        let mut hasher = DefaultHasher::new();
-        localup_id.hash(&mut hasher);
-        let hash = hasher.finish();
-
-        let range_size = (self.range_end - self.range_start + 1) as u64;
-        let port_offset = (hash % range_size) as u16;
-        self.range_start + port_offset
+    let signer = signing_key.map(read_signer).transpose()?.map(Arc::new);
+    if let Some(ref signer) = signer {
+        info!(
+            "🔏 Signing the relay directory with {:?} key {}",
+            signer.algorithm(),
+            signer.kid().unwrap_or_default()
+        );
     }
 
-    /// Clean up expired reservations (should be called periodically)
-    pub fn cleanup_expired(&self) {
-        let mut available = self.available_ports.lock().unwrap();
-        let mut allocated = self.allocated_ports.lock().unwrap();
-        let now = Utc::now();
-
-        let expired: Vec<String> = allocated
-            .iter()
-            .filter_map(|(localup_id, allocation)| match &allocation.state {
-                AllocationState::Reserved { until } if *until < now => Some(localup_id.clone()),
-                _ => None,
-            })
-            .collect();
-
-        for localup_id in expired {
-            if let Some(allocation) = allocated.remove(&localup_id) {
-                available.insert(allocation.port);
-                info!(
-                    "Cleaned up expired port reservation for tunnel {} (port {})",
-                    localup_id, allocation.port
-                );
-            }
-        }
-    }
+    let orchestrator = Arc::new(Orchestrator::new(
+        base,
+        OrchestratorConfig {
+            heartbeat_interval: std::time::Duration::from_secs(heartbeat_interval),
+            heartbeat_timeout: std::time::Duration::from_secs(heartbeat_timeout),
+            token,
+            signer,
+            ..Default::default()
+        },
+    ));
+    orchestrator.spawn_heartbeat_check_task();
+
+    let listener = tokio::net::TcpListener::bind(bind_addr).await?;
+    info!(
+        "🗺️  Orchestrator listening on {} (relay directory at /relays)",
+        listener.local_addr()?
+    );
+    axum::serve(listener, orchestrator.router())
+        .with_graceful_shutdown(async {
+            let _ = signal::ctrl_c().await;
+        })
+        .await?;
+    info!("✅ Orchestrator stopped");
+    Ok(())
 }
 
-impl PortAllocatorTrait for PortAllocator {
-    fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String> {
-        let mut available = self.available_ports.lock().unwrap();
-        let mut allocated = self.allocated_ports.lock().unwrap();
-
-        // Check if already allocated (active or reserved)
-        if let Some(allocation) = allocated.get(localup_id) {
-            let port = allocation.port;
-            // Reactivate if it was reserved
-            if matches!(allocation.state, AllocationState::Reserved { .. }) {
-                info!(
-                    "Reusing reserved port {} for reconnecting tunnel {}",
-                    port, localup_id
-                );
-                allocated.insert(
-                    localup_id.to_string(),
-                    PortAllocation {
-                        port,
-                        state: AllocationState::Active,
-                    },
-                );
-            }
-            return Ok(port);
-        }
-
-        // If user requested a specific port, try to allocate it
-        if let Some(req_port) = requested_port {
-            if available.contains(&req_port) && Self::is_port_available(req_port) {
-                // Requested port is available!
-                available.remove(&req_port);
-                allocated.insert(
-                    localup_id.to_string(),
-                    PortAllocation {
-                        port: req_port,
-                        state: AllocationState::Active,
-                    },
-                );
-                info!(
-                    "✅ Allocated requested port {} for tunnel {}",
-                    req_port, localup_id
-                );
-                return Ok(req_port);
-            } else if available.contains(&req_port) && !Self::is_port_available(req_port) {
-                // Port in our pool but in use by another process
-                available.remove(&req_port);
-                return Err(format!(
-                    "Requested port {} is in use by another process",
-                    req_port
-                ));
-            } else {
-                // Port not in our allocation range
-                return Err(format!(
-                    "Requested port {} is not available (already allocated or out of range)",
-                    req_port
-                ));
-            }
-        }
-
-        // No specific port requested, try to allocate deterministic port based on localup_id hash
-        let preferred_port = self.hash_to_port(localup_id);
-
-        if available.contains(&preferred_port) && Self::is_port_available(preferred_port) {
-            // Preferred port is available in our tracking AND at OS level!
-            available.remove(&preferred_port);
-            allocated.insert(
-                localup_id.to_string(),
-                PortAllocation {
-                    port: preferred_port,
-                    state: AllocationState::Active,
-                },
-            );
-            info!(
-                "🎯 Allocated deterministic port {} for tunnel {} (hash-based)",
-                preferred_port, localup_id
-            );
-            return Ok(preferred_port);
-        } else if available.contains(&preferred_port) && !Self::is_port_available(preferred_port) {
-            // Port was in our available set but is actually in use - remove it from tracking
-            warn!("Port {} was marked available but is in use by another process, removing from available pool", preferred_port);
-            available.remove(&preferred_port);
-        }
-
-        // Preferred port not available, try nearby ports (within ±10 range)
-        for offset in 1..=10 {
-            for &port in &[
-                preferred_port.saturating_add(offset),
-                preferred_port.saturating_sub(offset),
-            ] {
-                if port >= self.range_start && port <= self.range_end && available.contains(&port) {
-                    // Verify port is actually available at OS level
-                    if Self::is_port_available(port) {
-                        available.remove(&port);
-                        allocated.insert(
-                            localup_id.to_string(),
-                            PortAllocation {
-                                port,
-                                state: AllocationState::Active,
-                            },
-                        );
-                        info!(
-                            "Allocated nearby port {} for tunnel {} (preferred {} was taken)",
-                            port, localup_id, preferred_port
-                        );
-                        return Ok(port);
-                    } else {
-                        // Port in use by another process, remove from available pool
-                        warn!(
-                            "Port {} was marked available but is in use, removing from pool",
-                            port
-                        );
-                        available.remove(&port);
-                    }
-                }
-            }
-        }
-
-        // Fallback: allocate any available port, checking OS-level availability
-        let available_ports: Vec<u16> = available.iter().copied().collect();
-        for &port in &available_ports {
-            if Self::is_port_available(port) {
-                available.remove(&port);
-                allocated.insert(
-                    localup_id.to_string(),
-                    PortAllocation {
-                        port,
-                        state: AllocationState::Active,
-                    },
-                );
-                info!(
-                    "Allocated fallback port {} for tunnel {} (preferred {} was taken)",
-                    port, localup_id, preferred_port
-                );
-                return Ok(port);
-            } else {
-                // Port in use by another process, remove from available pool
-                warn!(
-                    "Port {} was marked available but is in use, removing from pool",
-                    port
-                );
-                available.remove(&port);
+/// Register this relay with the orchestrator, heartbeating its load
+fn register_with_orchestrator(
+    url: &str,
+    token: Option<String>,
+    relay_info: &str,
+    localup_manager: Arc<TunnelConnectionManager>,
+    agent_registry: Arc<AgentRegistry>,
+) -> Result<(Arc<OrchestratorClient>, tokio::task::JoinHandle<()>)> {
+    let contents = std::fs::read_to_string(relay_info)
+        .map_err(|e| anyhow::anyhow!("Failed to read relay info {}: {}", relay_info, e))?;
+    let info: RelayInfo = serde_yaml::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("Invalid relay info {}: {}", relay_info, e))?;
+
+    let client = Arc::new(OrchestratorClient::new(url, token, info));
+    let handle = client.clone().spawn(move || {
+        let localup_manager = localup_manager.clone();
+        let agent_registry = agent_registry.clone();
+        async move {
+            RelayLoad {
+                tunnels: localup_manager.list_tunnels().await.len() as u32,
+                agents: agent_registry.count() as u32,
             }
         }
+    });
+    Ok((client, handle))
+}
 
-        Err("No available ports in range (all ports in use)".to_string())
-    }
+fn init_logging(log_level: &str) -> Result<()> {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .or_else(|_| tracing_subscriber::EnvFilter::try_new(log_level))?;
 
-    fn deallocate(&self, localup_id: &str) {
-        let mut allocated = self.allocated_ports.lock().unwrap();
-
-        // Instead of immediately freeing, mark as reserved for reconnection
-        if let Some(allocation) = allocated.get_mut(localup_id) {
-            if matches!(allocation.state, AllocationState::Active) {
-                let until = Utc::now() + chrono::Duration::seconds(self.reservation_ttl_seconds);
-                allocation.state = AllocationState::Reserved { until };
-                info!(
-                    "Port {} for tunnel {} marked as reserved until {} (TTL: {}s)",
-                    allocation.port,
-                    localup_id,
-                    until.format("%Y-%m-%d %H:%M:%S"),
-                    self.reservation_ttl_seconds
-                );
-            }
-        }
-    }
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
 
-    fn get_allocated_port(&self, localup_id: &str) -> Option<u16> {
-        self.allocated_ports
-            .lock()
-            .unwrap()
-            .get(localup_id)
-            .map(|alloc| alloc.port)
-    }
+    Ok(())
 }
 
 fn parse_port_range(range_str: &str) -> Result<(u16, u16)> {
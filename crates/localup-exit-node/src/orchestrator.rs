@@ -1,15 +1,677 @@
 //! Exit node orchestrator
+//!
+//! The orchestrator keeps a registry of the relays (exit nodes) of a
+//! deployment and serves it as a live relay directory, in the same
+//! [`RelayConfig`] schema clients embed as `relays.yaml`. Relays register
+//! their region, location, endpoints (with capacity) and tags, then report
+//! their load in heartbeats. A relay whose heartbeats stop is listed as
+//! `down` until it heartbeats again, and is forgotten after a while.
+//!
+//! Relays talk to the orchestrator with [`OrchestratorClient`]:
+//!
+//! - `POST /relays` registers a relay (body: [`RelayInfo`])
+//! - `POST /relays/{id}/heartbeat` reports its load (body: [`RelayLoad`])
+//! - `DELETE /relays/{id}` removes it on shutdown
+//! - `GET /relays` returns the directory
+//! - `GET /relays.jws` returns the directory signed with the orchestrator's
+//!   signing key, and `GET /.well-known/jwks.json` the key to verify it with
+//!
+//! Registration, heartbeats and removal require the orchestrator token as a
+//! bearer token when one is configured.
 
-pub struct ExitNode;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use localup_auth::{JwtSigner, JwtValidator};
+use localup_proto::relay_directory::{
+    GlobalConfig, RelayConfig, RelayInfo, RelayLoad, SelectionPolicy, SignedRelayDirectory,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use thiserror::Error;
+use tracing::{debug, info, warn};
 
-impl ExitNode {
-    pub fn new() -> Self {
-        Self
-    }
+/// Status of relays that heartbeat
+pub const STATUS_ACTIVE: &str = "active";
+
+/// Status of relays whose heartbeats stopped
+pub const STATUS_DOWN: &str = "down";
+
+/// Orchestrator errors
+#[derive(Debug, Error)]
+pub enum OrchestratorError {
+    #[error("Relay {0} is not registered")]
+    UnknownRelay(String),
+
+    #[error("Invalid relay registration: {0}")]
+    InvalidRegistration(String),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Orchestrator answered {0}")]
+    Rejected(reqwest::StatusCode),
 }
 
-impl Default for ExitNode {
+/// Orchestrator settings
+#[derive(Clone)]
+pub struct OrchestratorConfig {
+    /// How often relays are asked to heartbeat
+    pub heartbeat_interval: Duration,
+    /// How long without a heartbeat before a relay is marked down
+    pub heartbeat_timeout: Duration,
+    /// How long a relay stays listed as down before it's forgotten
+    pub forget_after: Duration,
+    /// Bearer token relays must present
+    pub token: Option<String>,
+    /// Key signing the directory served at `/relays.jws`
+    pub signer: Option<Arc<JwtSigner>>,
+    /// How long clients may use a signed directory, e.g. from their cache
+    pub signed_validity: Duration,
+}
+
+impl Default for OrchestratorConfig {
     fn default() -> Self {
-        Self::new()
+        Self {
+            heartbeat_interval: Duration::from_secs(10),
+            heartbeat_timeout: Duration::from_secs(30),
+            forget_after: Duration::from_secs(3600),
+            token: None,
+            signer: None,
+            signed_validity: Duration::from_secs(24 * 3600),
+        }
+    }
+}
+
+/// Answer to a registration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationResponse {
+    /// Seconds between heartbeats
+    pub heartbeat_interval_secs: u64,
+}
+
+struct RegisteredRelay {
+    info: RelayInfo,
+    load: RelayLoad,
+    last_heartbeat: Instant,
+    down: bool,
+}
+
+/// Registry of relays, served as a relay directory
+pub struct Orchestrator {
+    config: OrchestratorConfig,
+    /// Directory settings and relays that don't register (e.g. from `relays.yaml`)
+    base: RelayConfig,
+    relays: RwLock<HashMap<String, RegisteredRelay>>,
+}
+
+impl Orchestrator {
+    pub fn new(base: RelayConfig, config: OrchestratorConfig) -> Self {
+        Self {
+            config,
+            base,
+            relays: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Directory settings used when none are configured: an `auto` policy
+    /// picking active relays, nearest and least loaded first
+    pub fn default_directory() -> RelayConfig {
+        RelayConfig {
+            version: 1,
+            config: GlobalConfig {
+                default_protocol: "https".to_string(),
+                connection_timeout: 30,
+                health_check_interval: 60,
+            },
+            relays: Vec::new(),
+            region_groups: Vec::new(),
+            selection_policies: HashMap::from([(
+                "auto".to_string(),
+                SelectionPolicy {
+                    prefer_same_region: true,
+                    fallback_to_nearest: true,
+                    consider_capacity: true,
+                    only_active: true,
+                    include_tags: Vec::new(),
+                    exclude_tags: Vec::new(),
+                },
+            )]),
+        }
+    }
+
+    /// Register a relay, replacing any registration with the same ID
+    pub fn register(&self, mut info: RelayInfo) -> Result<(), OrchestratorError> {
+        if info.id.is_empty() {
+            return Err(OrchestratorError::InvalidRegistration(
+                "relay ID is empty".to_string(),
+            ));
+        }
+        if info.endpoints.is_empty() {
+            return Err(OrchestratorError::InvalidRegistration(format!(
+                "relay {} has no endpoints",
+                info.id
+            )));
+        }
+        info.status = STATUS_ACTIVE.to_string();
+        info.load = None;
+
+        let replaced = self.relays.write().unwrap().insert(
+            info.id.clone(),
+            RegisteredRelay {
+                info: info.clone(),
+                load: RelayLoad::default(),
+                last_heartbeat: Instant::now(),
+                down: false,
+            },
+        );
+        if replaced.is_some() {
+            info!("Relay {} re-registered", info.id);
+        } else {
+            info!(
+                "Relay {} registered in {} with {} endpoints",
+                info.id,
+                info.region,
+                info.endpoints.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Record a heartbeat of a registered relay
+    ///
+    /// Relays that aren't registered (e.g. after the orchestrator restarted)
+    /// get [`OrchestratorError::UnknownRelay`] and register again.
+    pub fn heartbeat(&self, id: &str, load: RelayLoad) -> Result<(), OrchestratorError> {
+        let mut relays = self.relays.write().unwrap();
+        let relay = relays
+            .get_mut(id)
+            .ok_or_else(|| OrchestratorError::UnknownRelay(id.to_string()))?;
+        if relay.down {
+            info!("Relay {} is back up", id);
+        }
+        relay.load = load;
+        relay.last_heartbeat = Instant::now();
+        relay.down = false;
+        Ok(())
+    }
+
+    /// Remove a relay, returning whether it was registered
+    pub fn deregister(&self, id: &str) -> bool {
+        let removed = self.relays.write().unwrap().remove(id).is_some();
+        if removed {
+            info!("Relay {} deregistered", id);
+        }
+        removed
+    }
+
+    /// Mark relays that stopped heartbeating as down and forget long-dead ones
+    ///
+    /// Returns the IDs of relays newly marked down.
+    pub fn check_heartbeats(&self) -> Vec<String> {
+        let mut relays = self.relays.write().unwrap();
+        let mut marked_down = Vec::new();
+        relays.retain(|id, relay| {
+            let silent_for = relay.last_heartbeat.elapsed();
+            if silent_for > self.config.heartbeat_timeout + self.config.forget_after {
+                info!("Forgetting relay {} (down for too long)", id);
+                return false;
+            }
+            if !relay.down && silent_for > self.config.heartbeat_timeout {
+                warn!(
+                    "Relay {} missed its heartbeats for {:?}, marking it down",
+                    id, silent_for
+                );
+                relay.down = true;
+                marked_down.push(id.clone());
+            }
+            true
+        });
+        marked_down
+    }
+
+    /// Check heartbeats every heartbeat interval until the orchestrator is dropped
+    pub fn spawn_heartbeat_check_task(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let orchestrator = Arc::downgrade(self);
+        let interval = self.config.heartbeat_interval;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(orchestrator) = orchestrator.upgrade() else {
+                    break;
+                };
+                orchestrator.check_heartbeats();
+            }
+        })
+    }
+
+    /// The live relay directory
+    ///
+    /// Registered relays are listed with their status and load, after the
+    /// configured relays they don't replace.
+    pub fn directory(&self) -> RelayConfig {
+        let relays = self.relays.read().unwrap();
+        let mut directory = self.base.clone();
+        directory
+            .relays
+            .retain(|relay| !relays.contains_key(&relay.id));
+
+        let mut registered: Vec<RelayInfo> = relays
+            .values()
+            .map(|relay| {
+                let mut info = relay.info.clone();
+                info.status = if relay.down {
+                    STATUS_DOWN.to_string()
+                } else {
+                    STATUS_ACTIVE.to_string()
+                };
+                info.load = Some(relay.load);
+                info
+            })
+            .collect();
+        registered.sort_by(|a, b| a.id.cmp(&b.id));
+        directory.relays.extend(registered);
+        directory
+    }
+
+    /// The live relay directory as a compact JWS, if a signing key is configured
+    pub fn signed_directory(&self) -> Option<Result<String, localup_auth::JwtError>> {
+        let signer = self.config.signer.as_ref()?;
+        let now = chrono::Utc::now();
+        let validity = chrono::Duration::from_std(self.config.signed_validity)
+            .unwrap_or_else(|_| chrono::Duration::days(1));
+        Some(signer.sign(&SignedRelayDirectory {
+            directory: self.directory(),
+            iat: now.timestamp(),
+            exp: (now + validity).timestamp(),
+        }))
+    }
+
+    /// HTTP API for relays and clients
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/relays", get(get_directory).post(register_relay))
+            .route("/relays.jws", get(get_signed_directory))
+            .route("/.well-known/jwks.json", get(get_jwks))
+            .route("/relays/{id}", axum::routing::delete(deregister_relay))
+            .route("/relays/{id}/heartbeat", post(relay_heartbeat))
+            .with_state(self)
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let Some(ref token) = self.config.token else {
+            return true;
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| bool::from(presented.as_bytes().ct_eq(token.as_bytes())))
+    }
+}
+
+async fn get_directory(State(orchestrator): State<Arc<Orchestrator>>) -> Json<RelayConfig> {
+    Json(orchestrator.directory())
+}
+
+async fn get_signed_directory(State(orchestrator): State<Arc<Orchestrator>>) -> Response {
+    match orchestrator.signed_directory() {
+        Some(Ok(signed)) => ([(header::CONTENT_TYPE, "application/jose")], signed).into_response(),
+        Some(Err(e)) => {
+            warn!("Failed to sign the relay directory: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        None => (StatusCode::NOT_FOUND, "No directory signing key configured").into_response(),
+    }
+}
+
+async fn get_jwks(State(orchestrator): State<Arc<Orchestrator>>) -> impl IntoResponse {
+    let keys = orchestrator
+        .config
+        .signer
+        .iter()
+        .map(|signer| signer.verification_key())
+        .collect();
+    Json(JwtValidator::from_keys(keys).jwks())
+}
+
+async fn register_relay(
+    State(orchestrator): State<Arc<Orchestrator>>,
+    headers: HeaderMap,
+    Json(info): Json<RelayInfo>,
+) -> Response {
+    if !orchestrator.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    match orchestrator.register(info) {
+        Ok(()) => Json(RegistrationResponse {
+            heartbeat_interval_secs: orchestrator.config.heartbeat_interval.as_secs().max(1),
+        })
+        .into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+async fn relay_heartbeat(
+    State(orchestrator): State<Arc<Orchestrator>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(load): Json<RelayLoad>,
+) -> StatusCode {
+    if !orchestrator.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    match orchestrator.heartbeat(&id, load) {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(_) => StatusCode::NOT_FOUND,
+    }
+}
+
+async fn deregister_relay(
+    State(orchestrator): State<Arc<Orchestrator>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> StatusCode {
+    if !orchestrator.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    if orchestrator.deregister(&id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Registers a relay with an orchestrator and keeps it alive with heartbeats
+pub struct OrchestratorClient {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+    info: RelayInfo,
+}
+
+impl OrchestratorClient {
+    /// `url` is the orchestrator's base URL, e.g. `https://orchestrator.example.com`
+    pub fn new(url: impl Into<String>, token: Option<String>, info: RelayInfo) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url: url.into().trim_end_matches('/').to_string(),
+            token,
+            info,
+        }
+    }
+
+    pub fn relay_id(&self) -> &str {
+        &self.info.id
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.url, path));
+        match self.token {
+            Some(ref token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Register the relay, returning how often it should heartbeat
+    pub async fn register(&self) -> Result<Duration, OrchestratorError> {
+        let response = self
+            .request(reqwest::Method::POST, "/relays")
+            .json(&self.info)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(OrchestratorError::Rejected(response.status()));
+        }
+        let registration: RegistrationResponse = response.json().await?;
+        Ok(Duration::from_secs(registration.heartbeat_interval_secs))
+    }
+
+    /// Report the relay's load
+    pub async fn heartbeat(&self, load: RelayLoad) -> Result<(), OrchestratorError> {
+        let response = self
+            .request(
+                reqwest::Method::POST,
+                &format!("/relays/{}/heartbeat", self.info.id),
+            )
+            .json(&load)
+            .send()
+            .await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::NOT_FOUND => {
+                Err(OrchestratorError::UnknownRelay(self.info.id.clone()))
+            }
+            status => Err(OrchestratorError::Rejected(status)),
+        }
+    }
+
+    /// Remove the relay from the directory
+    pub async fn deregister(&self) -> Result<(), OrchestratorError> {
+        let response = self
+            .request(
+                reqwest::Method::DELETE,
+                &format!("/relays/{}", self.info.id),
+            )
+            .send()
+            .await?;
+        match response.status() {
+            status if status.is_success() || status == reqwest::StatusCode::NOT_FOUND => Ok(()),
+            status => Err(OrchestratorError::Rejected(status)),
+        }
+    }
+
+    /// Register and heartbeat with the load returned by `load` until aborted
+    ///
+    /// Failed registrations are retried, and the relay registers again when
+    /// the orchestrator no longer knows it.
+    pub fn spawn<F, Fut>(self: Arc<Self>, load: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = RelayLoad> + Send,
+    {
+        tokio::spawn(async move {
+            let retry = Duration::from_secs(10);
+            loop {
+                let interval = match self.register().await {
+                    Ok(interval) => {
+                        info!("Registered relay {} with the orchestrator", self.info.id);
+                        interval
+                    }
+                    Err(e) => {
+                        warn!("Failed to register with the orchestrator: {}", e);
+                        tokio::time::sleep(retry).await;
+                        continue;
+                    }
+                };
+                loop {
+                    tokio::time::sleep(interval).await;
+                    match self.heartbeat(load().await).await {
+                        Ok(()) => debug!("Heartbeat sent to the orchestrator"),
+                        Err(OrchestratorError::UnknownRelay(_)) => {
+                            warn!("The orchestrator forgot this relay, registering again");
+                            break;
+                        }
+                        Err(e) => warn!("Orchestrator heartbeat failed: {}", e),
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use localup_proto::relay_directory::{Location, RelayEndpoint};
+
+    fn relay(id: &str) -> RelayInfo {
+        RelayInfo {
+            id: id.to_string(),
+            name: format!("Relay {}", id),
+            region: "eu-west".to_string(),
+            location: Location {
+                city: "Madrid".to_string(),
+                state: "Madrid".to_string(),
+                country: "ES".to_string(),
+                continent: "Europe".to_string(),
+            },
+            endpoints: vec![RelayEndpoint {
+                protocol: "https".to_string(),
+                address: format!("{}.example.com:4443", id),
+                capacity: 1000,
+                priority: 1,
+            }],
+            status: "active".to_string(),
+            tags: vec!["production".to_string()],
+            load: None,
+        }
+    }
+
+    #[test]
+    fn test_relays_are_marked_down_when_heartbeats_stop() {
+        let orchestrator = Orchestrator::new(
+            Orchestrator::default_directory(),
+            OrchestratorConfig {
+                heartbeat_timeout: Duration::from_millis(50),
+                forget_after: Duration::from_secs(3600),
+                ..Default::default()
+            },
+        );
+        orchestrator.register(relay("relay-1")).unwrap();
+        orchestrator.register(relay("relay-2")).unwrap();
+        assert!(matches!(
+            orchestrator.heartbeat("relay-3", RelayLoad::default()),
+            Err(OrchestratorError::UnknownRelay(_))
+        ));
+
+        std::thread::sleep(Duration::from_millis(100));
+        let load = RelayLoad {
+            tunnels: 12,
+            agents: 1,
+        };
+        orchestrator.heartbeat("relay-1", load).unwrap();
+        assert_eq!(orchestrator.check_heartbeats(), vec!["relay-2".to_string()]);
+        assert!(orchestrator.check_heartbeats().is_empty());
+
+        let directory = orchestrator.directory();
+        let statuses: Vec<(&str, &str, Option<RelayLoad>)> = directory
+            .relays
+            .iter()
+            .map(|r| (r.id.as_str(), r.status.as_str(), r.load))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("relay-1", STATUS_ACTIVE, Some(load)),
+                ("relay-2", STATUS_DOWN, Some(RelayLoad::default())),
+            ]
+        );
+
+        // A heartbeat brings a relay back
+        orchestrator
+            .heartbeat("relay-2", RelayLoad::default())
+            .unwrap();
+        assert!(orchestrator
+            .directory()
+            .relays
+            .iter()
+            .all(|r| r.status == STATUS_ACTIVE));
+    }
+
+    #[tokio::test]
+    async fn test_relays_register_and_heartbeat_over_http() {
+        let mut base = Orchestrator::default_directory();
+        base.relays.push(relay("static"));
+        let orchestrator = Arc::new(Orchestrator::new(
+            base,
+            OrchestratorConfig {
+                token: Some("orchestrator-token".to_string()),
+                ..Default::default()
+            },
+        ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = orchestrator.clone().router();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let intruder = OrchestratorClient::new(&url, None, relay("relay-1"));
+        assert!(matches!(
+            intruder.register().await,
+            Err(OrchestratorError::Rejected(
+                reqwest::StatusCode::UNAUTHORIZED
+            ))
+        ));
+
+        let client = OrchestratorClient::new(
+            &url,
+            Some("orchestrator-token".to_string()),
+            relay("relay-1"),
+        );
+        assert!(matches!(
+            client.heartbeat(RelayLoad::default()).await,
+            Err(OrchestratorError::UnknownRelay(_))
+        ));
+        assert_eq!(client.register().await.unwrap(), Duration::from_secs(10));
+        client
+            .heartbeat(RelayLoad {
+                tunnels: 3,
+                agents: 0,
+            })
+            .await
+            .unwrap();
+
+        // The directory parses as a relay configuration
+        let directory: RelayConfig = reqwest::get(format!("{}/relays", url))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let ids: Vec<&str> = directory.relays.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["static", "relay-1"]);
+        assert_eq!(directory.relays[1].load.unwrap().tunnels, 3);
+        assert!(directory.selection_policies.contains_key("auto"));
+
+        client.deregister().await.unwrap();
+        assert_eq!(orchestrator.directory().relays.len(), 1);
+    }
+
+    #[test]
+    fn test_signed_directory() {
+        let unsigned = Orchestrator::new(
+            Orchestrator::default_directory(),
+            OrchestratorConfig::default(),
+        );
+        assert!(unsigned.signed_directory().is_none());
+
+        let signer = Arc::new(JwtSigner::hmac(b"directory-secret"));
+        let orchestrator = Orchestrator::new(
+            Orchestrator::default_directory(),
+            OrchestratorConfig {
+                signer: Some(signer.clone()),
+                ..Default::default()
+            },
+        );
+        orchestrator.register(relay("relay-1")).unwrap();
+
+        let signed = orchestrator.signed_directory().unwrap().unwrap();
+        let payload: SignedRelayDirectory =
+            localup_auth::decode_signed(&signed, &[signer.verification_key()]).unwrap();
+        assert_eq!(payload.directory.relays[0].id, "relay-1");
+        assert_eq!(payload.exp - payload.iat, 24 * 3600);
     }
 }
@@ -9,27 +9,69 @@
 //! Authorization: Basic <base64(username:password)>
 //! ```
 //!
+//! # Hashed Passwords
+//!
+//! The password part of a configured credential may be an Argon2 PHC string
+//! or a bcrypt hash instead of plain text:
+//!
+//! ```text
+//! admin:$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>
+//! ```
+//!
+//! Generate one with `localup config hash-password <USER>`. Verifying a hash
+//! is deliberately slow, so successful verifications are cached for
+//! [`VERIFICATION_CACHE_TTL`] and repeated requests from the same client
+//! skip it.
+//!
 //! # Security Note
 //!
 //! Basic authentication should only be used over HTTPS as credentials
 //! are transmitted in an easily reversible encoding (not encryption).
 
-use crate::{AuthResult, HttpAuthProvider};
+use crate::{AuthError, AuthRequest, AuthResult, HttpAuthProvider};
+use async_trait::async_trait;
 use base64::Engine;
-use std::collections::HashSet;
-use tracing::debug;
+use hmac::{Hmac, Mac};
+use localup_auth::password::{check_password_hash, is_password_hash, verify_password};
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// How long a successful hash verification is remembered
+pub const VERIFICATION_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Maximum number of remembered verifications
+const VERIFICATION_CACHE_CAPACITY: usize = 1024;
 
 /// HTTP Basic Authentication provider
 ///
-/// Validates credentials against a list of allowed `username:password` pairs.
-/// Credentials are compared using constant-time comparison to prevent timing attacks.
+/// Validates credentials against a list of allowed `username:password` pairs,
+/// where the password may be stored as an Argon2 or bcrypt hash.
 pub struct BasicAuthProvider {
-    /// Set of valid credentials in "username:password" format
+    /// Set of valid plain text credentials in "username:password" format
     valid_credentials: HashSet<String>,
+    /// Password hashes by username
+    hashed_credentials: HashMap<String, String>,
+    /// Recently verified hashed credentials
+    cache: Arc<VerificationCache>,
     /// Realm for the WWW-Authenticate header
     realm: String,
 }
 
+/// Result of checking credentials without verifying a hash
+enum Check {
+    Valid,
+    Invalid,
+    /// The password must be verified against this hash
+    Verify {
+        password: String,
+        hash: String,
+    },
+}
+
 impl BasicAuthProvider {
     /// Create a new Basic auth provider
     ///
@@ -46,10 +88,7 @@ impl BasicAuthProvider {
     /// ]);
     /// ```
     pub fn new(credentials: Vec<String>) -> Self {
-        Self {
-            valid_credentials: credentials.into_iter().collect(),
-            realm: "localup".to_string(),
-        }
+        Self::with_realm(credentials, "localup".to_string())
     }
 
     /// Create a new Basic auth provider with a custom realm
@@ -58,8 +97,24 @@ impl BasicAuthProvider {
     /// * `credentials` - List of valid credentials
     /// * `realm` - The realm string for the WWW-Authenticate header
     pub fn with_realm(credentials: Vec<String>, realm: String) -> Self {
+        let mut valid_credentials = HashSet::new();
+        let mut hashed_credentials = HashMap::new();
+
+        for credential in credentials {
+            match credential.split_once(':') {
+                Some((username, password)) if is_password_hash(password) => {
+                    hashed_credentials.insert(username.to_string(), password.to_string());
+                }
+                _ => {
+                    valid_credentials.insert(credential);
+                }
+            }
+        }
+
         Self {
-            valid_credentials: credentials.into_iter().collect(),
+            valid_credentials,
+            hashed_credentials,
+            cache: Arc::new(VerificationCache::new()),
             realm,
         }
     }
@@ -84,34 +139,183 @@ impl BasicAuthProvider {
         String::from_utf8(decoded).ok()
     }
 
-    /// Check if credentials are valid using constant-time comparison
-    fn validate_credentials(&self, credentials: &str) -> bool {
-        // Use simple lookup - the HashSet provides O(1) lookup
-        // For production, consider using a constant-time comparison library
-        self.valid_credentials.contains(credentials)
+    /// Check credentials against the plain text set and the cache
+    fn check_credentials(&self, credentials: &str) -> Check {
+        if self.valid_credentials.contains(credentials) {
+            return Check::Valid;
+        }
+
+        let Some((username, password)) = credentials.split_once(':') else {
+            return Check::Invalid;
+        };
+        let Some(hash) = self.hashed_credentials.get(username) else {
+            return Check::Invalid;
+        };
+
+        if self.cache.contains(credentials) {
+            return Check::Valid;
+        }
+        Check::Verify {
+            password: password.to_string(),
+            hash: hash.clone(),
+        }
     }
-}
 
-impl HttpAuthProvider for BasicAuthProvider {
-    fn authenticate(&self, headers: &[(String, String)]) -> AuthResult {
-        // Find the Authorization header
+    /// Get the decoded credentials from the first Basic Authorization header
+    fn credentials_from_headers(&self, headers: &[(String, String)]) -> Option<String> {
         for (name, value) in headers {
             if name.to_lowercase() == "authorization" {
                 if let Some(credentials) = self.extract_credentials(value) {
-                    if self.validate_credentials(&credentials) {
-                        debug!("Basic auth: valid credentials");
-                        return AuthResult::Authenticated;
-                    } else {
-                        debug!("Basic auth: invalid credentials");
-                    }
-                } else {
-                    debug!("Basic auth: could not decode credentials");
+                    return Some(credentials);
                 }
+                debug!("Basic auth: could not decode credentials");
             }
         }
 
         debug!("Basic auth: no valid Authorization header found");
-        AuthResult::Unauthorized(self.unauthorized_response())
+        None
+    }
+
+    /// Check decoded "username:password" credentials
+    ///
+    /// Hash verification runs on the blocking thread pool.
+    pub(crate) async fn verify_credentials(&self, credentials: String) -> bool {
+        match self.check_credentials(&credentials) {
+            Check::Valid => true,
+            Check::Invalid => false,
+            Check::Verify { password, hash } => {
+                // Hash verification takes milliseconds of CPU time, keep it
+                // off the async workers
+                let cache = self.cache.clone();
+                tokio::task::spawn_blocking(move || {
+                    verify_hash(&cache, &credentials, &password, &hash)
+                })
+                .await
+                .unwrap_or(false)
+            }
+        }
+    }
+
+    fn result(&self, valid: bool) -> AuthResult {
+        if valid {
+            debug!("Basic auth: valid credentials");
+            AuthResult::Authenticated
+        } else {
+            debug!("Basic auth: invalid credentials");
+            AuthResult::Unauthorized(self.unauthorized_response())
+        }
+    }
+}
+
+/// Check that the hashed passwords among `credentials` can be verified
+pub(crate) fn check_password_hashes(credentials: &[String]) -> Result<(), AuthError> {
+    for credential in credentials {
+        if let Some((username, password)) = credential.split_once(':') {
+            if is_password_hash(password) {
+                check_password_hash(password).map_err(|e| {
+                    AuthError::ConfigError(format!("password of user '{}': {}", username, e))
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Verify a password hash, remembering the credentials if it matches
+fn verify_hash(cache: &VerificationCache, credentials: &str, password: &str, hash: &str) -> bool {
+    match verify_password(password, hash) {
+        Ok(true) => {
+            cache.insert(credentials);
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            warn!("Basic auth: cannot verify password hash: {}", e);
+            false
+        }
+    }
+}
+
+/// Remembers credentials whose hash was verified recently
+///
+/// Entries are keyed by an HMAC of the credentials with a random per-provider
+/// key, so the cache never holds passwords.
+struct VerificationCache {
+    key: [u8; 32],
+    entries: Mutex<HashMap<[u8; 32], Instant>>,
+}
+
+impl VerificationCache {
+    fn new() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self {
+            key,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn digest(&self, credentials: &str) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key");
+        mac.update(credentials.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    fn contains(&self, credentials: &str) -> bool {
+        let digest = self.digest(credentials);
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&digest)
+            .is_some_and(|verified| verified.elapsed() < VERIFICATION_CACHE_TTL)
+    }
+
+    fn insert(&self, credentials: &str) {
+        let digest = self.digest(credentials);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= VERIFICATION_CACHE_CAPACITY {
+            entries.retain(|_, verified| verified.elapsed() < VERIFICATION_CACHE_TTL);
+            if entries.len() >= VERIFICATION_CACHE_CAPACITY {
+                entries.clear();
+            }
+        }
+        entries.insert(digest, Instant::now());
+    }
+}
+
+#[async_trait]
+impl HttpAuthProvider for BasicAuthProvider {
+    fn authenticate(&self, headers: &[(String, String)]) -> AuthResult {
+        let Some(credentials) = self.credentials_from_headers(headers) else {
+            return AuthResult::Unauthorized(self.unauthorized_response());
+        };
+
+        let valid = match self.check_credentials(&credentials) {
+            Check::Valid => true,
+            Check::Invalid => false,
+            Check::Verify { password, hash } => {
+                let verify = || verify_hash(&self.cache, &credentials, &password, &hash);
+                // Keep the async workers free, as `verify_credentials` does
+                match tokio::runtime::Handle::try_current() {
+                    Ok(handle)
+                        if handle.runtime_flavor()
+                            == tokio::runtime::RuntimeFlavor::MultiThread =>
+                    {
+                        tokio::task::block_in_place(verify)
+                    }
+                    _ => verify(),
+                }
+            }
+        };
+        self.result(valid)
+    }
+
+    async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        let Some(credentials) = self.credentials_from_headers(&request.headers) else {
+            return AuthResult::Unauthorized(self.unauthorized_response());
+        };
+
+        let valid = self.verify_credentials(credentials).await;
+        self.result(valid)
     }
 
     fn unauthorized_response(&self) -> Vec<u8> {
@@ -279,4 +483,95 @@ mod tests {
             AuthResult::Unauthorized(_)
         ));
     }
+
+    fn hashed_provider() -> BasicAuthProvider {
+        BasicAuthProvider::new(vec![
+            "plain:password".to_string(),
+            format!("admin:{}", localup_auth::hash_password("s3cret").unwrap()),
+            "legacy:$2b$04$Zm/1Z6tfmUG7dI.sRQWplujqvWa8NU4A8d7KbYuGT.nOeQ8FVcjFe".to_string(),
+        ])
+    }
+
+    #[test]
+    fn test_hashed_credentials() {
+        let provider = hashed_provider();
+        let auth = |user, password| {
+            provider
+                .authenticate(&[(
+                    "Authorization".to_string(),
+                    make_basic_auth_header(user, password),
+                )])
+                .is_authenticated()
+        };
+
+        assert!(auth("admin", "s3cret"));
+        assert!(auth("legacy", "hunter2"));
+        assert!(auth("plain", "password"));
+        assert!(!auth("admin", "wrong"));
+        assert!(!auth("legacy", "wrong"));
+        assert!(!auth("nobody", "s3cret"));
+    }
+
+    #[test]
+    fn test_hash_is_not_accepted_as_password() {
+        let hash = localup_auth::hash_password("s3cret").unwrap();
+        let provider = BasicAuthProvider::new(vec![format!("admin:{}", hash)]);
+        let headers = vec![(
+            "Authorization".to_string(),
+            make_basic_auth_header("admin", &hash),
+        )];
+
+        assert!(!provider.authenticate(&headers).is_authenticated());
+    }
+
+    #[test]
+    fn test_verification_cache() {
+        let provider = hashed_provider();
+        let credentials = "admin:s3cret";
+
+        assert!(!provider.cache.contains(credentials));
+        let headers = vec![(
+            "Authorization".to_string(),
+            make_basic_auth_header("admin", "s3cret"),
+        )];
+        assert!(provider.authenticate(&headers).is_authenticated());
+        assert!(provider.cache.contains(credentials));
+        assert!(matches!(
+            provider.check_credentials(credentials),
+            Check::Valid
+        ));
+
+        // Failed verifications are never cached
+        assert!(matches!(
+            provider.check_credentials("admin:wrong"),
+            Check::Verify { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_request_with_hash() {
+        let provider = hashed_provider();
+        let raw = format!(
+            "GET / HTTP/1.1\r\nHost: a\r\nAuthorization: {}\r\n\r\n",
+            make_basic_auth_header("admin", "s3cret")
+        );
+        let request = AuthRequest::parse(raw.as_bytes(), true);
+
+        assert!(provider
+            .authenticate_request(&request)
+            .await
+            .is_authenticated());
+        assert!(provider.cache.contains("admin:s3cret"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sync_authenticate_inside_runtime() {
+        let provider = hashed_provider();
+        let headers = vec![(
+            "Authorization".to_string(),
+            make_basic_auth_header("admin", "s3cret"),
+        )];
+
+        assert!(provider.authenticate(&headers).is_authenticated());
+    }
 }
@@ -0,0 +1,295 @@
+//! Request body buffering for providers that authenticate the body
+//!
+//! The relay normally forwards a request as soon as it has read the first
+//! chunk from the client. Providers that return a limit from
+//! [`HttpAuthProvider::body_limit`](crate::HttpAuthProvider::body_limit)
+//! need the whole body first, so the relay calls [`read_request_body`] before
+//! authenticating. Both `Content-Length` and chunked bodies are supported.
+
+use crate::parse_headers_from_request;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Maximum size of the request line and headers
+pub const MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// Error returned when a request body cannot be buffered
+#[derive(Error, Debug)]
+pub enum BodyError {
+    #[error("Request body exceeds {0} bytes")]
+    TooLarge(usize),
+
+    #[error("Malformed request: {0}")]
+    Malformed(String),
+
+    #[error("Connection closed before the request was complete")]
+    Incomplete,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl BodyError {
+    /// The response to send to the client for this error
+    pub fn response(&self) -> Vec<u8> {
+        match self {
+            BodyError::TooLarge(_) => {
+                crate::response::plain_text(413, "Payload Too Large", "Request body too large\n")
+            }
+            _ => crate::response::plain_text(400, "Bad Request", "Malformed request\n"),
+        }
+    }
+}
+
+/// Read from `stream` until `buffer` holds the complete request
+///
+/// `buffer` starts with the bytes already read from the client and is
+/// extended in place. Bytes after the end of the request (a pipelined
+/// request) are kept. If the client sent `Expect: 100-continue`, a
+/// `100 Continue` response is written so it starts sending the body.
+///
+/// # Arguments
+/// * `stream` - Client connection
+/// * `buffer` - Request bytes read so far
+/// * `limit` - Maximum body size in bytes (after removing chunked framing)
+pub async fn read_request_body<S>(
+    stream: &mut S,
+    buffer: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), BodyError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut sent_continue = false;
+    let mut chunk = [0u8; 8192];
+
+    loop {
+        if request_length(buffer, limit)?.is_some() {
+            return Ok(());
+        }
+
+        if !sent_continue && expects_continue(buffer) {
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+            sent_continue = true;
+        }
+
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(BodyError::Incomplete);
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Get the total length of the request in `data`
+///
+/// Returns `Ok(None)` if more bytes are needed.
+pub(crate) fn request_length(data: &[u8], limit: usize) -> Result<Option<usize>, BodyError> {
+    let Some(header_end) = header_end(data) else {
+        if data.len() > MAX_HEADER_BYTES {
+            return Err(BodyError::Malformed("headers too large".to_string()));
+        }
+        return Ok(None);
+    };
+
+    let headers = parse_headers_from_request(&data[..header_end]);
+    if is_chunked(&headers) {
+        return Ok(
+            decode_chunked(&data[header_end..], limit)?.map(|(_, consumed)| header_end + consumed)
+        );
+    }
+
+    let content_length = match header_value(&headers, "content-length") {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| BodyError::Malformed(format!("invalid Content-Length: {}", value)))?,
+        None => 0,
+    };
+    if content_length > limit {
+        return Err(BodyError::TooLarge(limit));
+    }
+
+    let total = header_end + content_length;
+    Ok((data.len() >= total).then_some(total))
+}
+
+/// Extract the body of a request, removing chunked framing
+///
+/// Returns whatever follows the headers if the body is incomplete.
+pub(crate) fn request_body(data: &[u8]) -> Vec<u8> {
+    let Some(header_end) = header_end(data) else {
+        return Vec::new();
+    };
+    let rest = &data[header_end..];
+
+    let headers = parse_headers_from_request(&data[..header_end]);
+    if is_chunked(&headers) {
+        if let Ok(Some((body, _))) = decode_chunked(rest, usize::MAX) {
+            return body;
+        }
+        return rest.to_vec();
+    }
+
+    match header_value(&headers, "content-length").and_then(|v| v.parse::<usize>().ok()) {
+        Some(length) => rest[..length.min(rest.len())].to_vec(),
+        None => rest.to_vec(),
+    }
+}
+
+/// Decode a chunked body
+///
+/// Returns the decoded body and the number of bytes consumed (including any
+/// trailers), or `Ok(None)` if the body is incomplete.
+fn decode_chunked(data: &[u8], limit: usize) -> Result<Option<(Vec<u8>, usize)>, BodyError> {
+    let mut body = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let Some(line_len) = find(&data[pos..], b"\r\n") else {
+            return Ok(None);
+        };
+        let line = String::from_utf8_lossy(&data[pos..pos + line_len]);
+        let size_str = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|_| BodyError::Malformed(format!("invalid chunk size: {}", size_str)))?;
+        pos += line_len + 2;
+
+        if size == 0 {
+            // Optional trailers end with an empty line
+            if data[pos..].starts_with(b"\r\n") {
+                return Ok(Some((body, pos + 2)));
+            }
+            return Ok(find(&data[pos..], b"\r\n\r\n").map(|end| (body, pos + end + 4)));
+        }
+
+        if body.len().saturating_add(size) > limit {
+            return Err(BodyError::TooLarge(limit));
+        }
+        let end = pos.saturating_add(size).saturating_add(2);
+        if data.len() < end {
+            return Ok(None);
+        }
+        if &data[end - 2..end] != b"\r\n" {
+            return Err(BodyError::Malformed("missing chunk terminator".to_string()));
+        }
+        body.extend_from_slice(&data[pos..end - 2]);
+        pos = end;
+    }
+}
+
+fn header_end(data: &[u8]) -> Option<usize> {
+    find(data, b"\r\n\r\n").map(|pos| pos + 4)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+fn is_chunked(headers: &[(String, String)]) -> bool {
+    header_value(headers, "transfer-encoding")
+        .is_some_and(|v| v.to_ascii_lowercase().contains("chunked"))
+}
+
+fn expects_continue(data: &[u8]) -> bool {
+    header_end(data).is_some_and(|end| {
+        header_value(&parse_headers_from_request(&data[..end]), "expect")
+            .is_some_and(|v| v.eq_ignore_ascii_case("100-continue"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_length_content_length() {
+        let request = b"POST /hook HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhel";
+        assert_eq!(request_length(request, 1024).unwrap(), None);
+
+        let request = b"POST /hook HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello";
+        assert_eq!(request_length(request, 1024).unwrap(), Some(request.len()));
+        assert!(matches!(
+            request_length(request, 4),
+            Err(BodyError::TooLarge(4))
+        ));
+    }
+
+    #[test]
+    fn test_request_length_without_body() {
+        let request = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert_eq!(request_length(request, 0).unwrap(), Some(request.len()));
+        assert_eq!(request_length(b"GET / HTTP/1.1\r\nHo", 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_chunked_body() {
+        let request =
+            b"POST /hook HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+        assert_eq!(request_length(request, 1024).unwrap(), Some(request.len()));
+        assert_eq!(request_body(request), b"hello world");
+
+        let partial = b"POST /hook HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n";
+        assert_eq!(request_length(partial, 1024).unwrap(), None);
+        assert!(matches!(
+            request_length(request, 8),
+            Err(BodyError::TooLarge(8))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_content_length() {
+        let request = b"POST / HTTP/1.1\r\nContent-Length: abc\r\n\r\n";
+        assert!(matches!(
+            request_length(request, 1024),
+            Err(BodyError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_request_body_ignores_pipelined_bytes() {
+        let request = b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\nokGET / HTTP/1.1\r\n";
+        assert_eq!(request_body(request), b"ok");
+    }
+
+    #[tokio::test]
+    async fn test_read_request_body_sends_continue() {
+        let (mut relay, mut client) = tokio::io::duplex(1024);
+        let mut buffer =
+            b"POST /hook HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n".to_vec();
+
+        let client_task = tokio::spawn(async move {
+            let mut response = [0u8; 25];
+            client.read_exact(&mut response).await.unwrap();
+            assert_eq!(&response, b"HTTP/1.1 100 Continue\r\n\r\n");
+            client.write_all(b"hello").await.unwrap();
+            client
+        });
+
+        read_request_body(&mut relay, &mut buffer, 1024)
+            .await
+            .unwrap();
+        assert!(buffer.ends_with(b"\r\n\r\nhello"));
+        client_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_request_body_connection_closed() {
+        let (mut relay, client) = tokio::io::duplex(1024);
+        drop(client);
+        let mut buffer = b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi".to_vec();
+
+        assert!(matches!(
+            read_request_body(&mut relay, &mut buffer, 1024).await,
+            Err(BodyError::Incomplete)
+        ));
+    }
+}
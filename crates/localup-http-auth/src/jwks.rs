@@ -0,0 +1,141 @@
+//! JSON Web Key Set (JWKS) fetching and caching
+//!
+//! Keys are fetched lazily and cached for a fixed TTL. When a token refers to
+//! a key ID that is not in the cached set, the set is refetched (at most once
+//! per [`MIN_REFRESH_INTERVAL`]) so signing key rotation at the identity
+//! provider is picked up without waiting for the TTL to expire.
+
+use crate::AuthError;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::DecodingKey;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::debug;
+
+/// Default time to keep a fetched key set
+pub const DEFAULT_JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum time between refetches triggered by unknown key IDs
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// Cached view of a remote JWKS document
+pub struct JwksCache {
+    url: String,
+    client: reqwest::Client,
+    ttl: Duration,
+    refresh_interval: Duration,
+    cached: RwLock<Option<CachedKeys>>,
+}
+
+impl JwksCache {
+    /// Create a cache for the JWKS document at `url`
+    pub fn new(url: impl Into<String>, client: reqwest::Client) -> Self {
+        Self {
+            url: url.into(),
+            client,
+            ttl: DEFAULT_JWKS_TTL,
+            refresh_interval: MIN_REFRESH_INTERVAL,
+            cached: RwLock::new(None),
+        }
+    }
+
+    /// Set how long a fetched key set is used before it is refetched
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the minimum time between refetches triggered by unknown key IDs
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// The JWKS document URL
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Get the decoding key for a token's `kid` header
+    ///
+    /// If the token has no `kid`, the key set must contain exactly one key.
+    pub async fn decoding_key(&self, kid: Option<&str>) -> Result<DecodingKey, AuthError> {
+        let needs_refresh = {
+            let cached = self.cached.read().await;
+            match cached.as_ref() {
+                Some(c) if c.fetched_at.elapsed() < self.ttl => {
+                    if let Some(key) = Self::find_key(&c.keys, kid)? {
+                        return Ok(key);
+                    }
+                    // Unknown key ID: the provider may have rotated keys
+                    c.fetched_at.elapsed() >= self.refresh_interval
+                }
+                _ => true,
+            }
+        };
+
+        if needs_refresh {
+            self.refresh().await?;
+        }
+
+        let cached = self.cached.read().await;
+        let keys = cached
+            .as_ref()
+            .map(|c| &c.keys)
+            .ok_or_else(|| AuthError::ProviderError("JWKS not available".to_string()))?;
+        Self::find_key(keys, kid)?
+            .ok_or_else(|| AuthError::InvalidToken(format!("No JWKS key found for kid {:?}", kid)))
+    }
+
+    /// Get the decoding key for a token's `kid` from the cache, without fetching
+    ///
+    /// Returns `None` if the key is not cached or the cache is being updated.
+    pub fn cached_key(&self, kid: Option<&str>) -> Option<DecodingKey> {
+        let cached = self.cached.try_read().ok()?;
+        let cached = cached.as_ref()?;
+        if cached.fetched_at.elapsed() >= self.ttl {
+            return None;
+        }
+        Self::find_key(&cached.keys, kid).ok().flatten()
+    }
+
+    /// Fetch the key set now, replacing the cached copy
+    pub async fn refresh(&self) -> Result<(), AuthError> {
+        debug!("Fetching JWKS from {}", self.url);
+        let keys: JwkSet = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AuthError::ProviderError(format!("Failed to fetch JWKS: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AuthError::ProviderError(format!("Invalid JWKS document: {}", e)))?;
+
+        *self.cached.write().await = Some(CachedKeys {
+            keys,
+            fetched_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    fn find_key(keys: &JwkSet, kid: Option<&str>) -> Result<Option<DecodingKey>, AuthError> {
+        let jwk = match kid {
+            Some(kid) => keys.find(kid),
+            None if keys.keys.len() == 1 => keys.keys.first(),
+            None => None,
+        };
+
+        jwk.map(|jwk| {
+            DecodingKey::from_jwk(jwk)
+                .map_err(|e| AuthError::ProviderError(format!("Unusable JWKS key: {}", e)))
+        })
+        .transpose()
+    }
+}
@@ -9,6 +9,12 @@
 //! - **Basic**: HTTP Basic Authentication (RFC 7617)
 //! - **BearerToken**: Authorization header with Bearer token
 //! - **HeaderAuth**: Custom header-based authentication
+//! - **Oidc**: OpenID Connect login with relay-issued session cookies
+//! - **Jwt**: JWT bearer tokens verified with a shared secret, public key or JWKS
+//! - **WebhookSignature**: HMAC signatures over the request body (GitHub,
+//!   Stripe and Slack styles)
+//! - **Policy**: any-of/all-of/path/method combinations of the methods above
+//! - **LoginForm**: relay-served login page with session cookies and logout
 //!
 //! # Usage
 //!
@@ -25,10 +31,15 @@
 //!
 //! match authenticator.authenticate(&headers) {
 //!     AuthResult::Authenticated => { /* proceed */ }
+//!     AuthResult::AuthenticatedWithHeaders(extra) => { /* proceed, adding `extra` headers */ }
 //!     AuthResult::Unauthorized(response) => { /* return 401 */ }
 //! }
 //! ```
 //!
+//! The relay's HTTP paths call `authenticate_request` instead, which gives
+//! providers the full request so they can serve their own endpoints (such as
+//! the OIDC callback) and redirect browsers to a login page.
+//!
 //! # Extensibility
 //!
 //! To add a new authentication method:
@@ -39,13 +50,38 @@
 
 mod basic;
 mod bearer;
+mod body;
 mod header;
+mod jwks;
+mod jwt;
+mod login;
+mod oidc;
+mod policy;
+mod response;
+mod session;
+mod share;
+mod webhook;
 
 pub use basic::BasicAuthProvider;
 pub use bearer::BearerTokenProvider;
+pub use body::{read_request_body, BodyError, MAX_HEADER_BYTES};
 pub use header::HeaderAuthProvider;
+pub use jwks::JwksCache;
+pub use jwt::JwtAuthProvider;
+pub use login::{AccountVerifier, LoginFormProvider, LOGIN_SESSION_COOKIE};
+pub use oidc::OidcAuthProvider;
+pub use policy::PolicyAuthProvider;
+pub use session::SessionSigner;
+pub use share::{
+    share_link_redirect, share_link_rejected, take_share_token, ShareLinkClaims, ShareLinkSigner,
+    SHARE_COOKIE, SHARE_LINK_PARAM,
+};
+pub use webhook::WebhookSignatureProvider;
 
-use localup_proto::HttpAuthConfig;
+use async_trait::async_trait;
+use localup_proto::{AuthPolicy, HttpAuthConfig};
+use std::net::IpAddr;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// Authentication result
@@ -53,10 +89,111 @@ use thiserror::Error;
 pub enum AuthResult {
     /// Request is authenticated (no auth required or valid credentials)
     Authenticated,
-    /// Request requires authentication - includes the 401 response bytes
+    /// Request is authenticated and the provider supplies identity headers
+    /// (e.g. `X-Forwarded-Email`) to forward to the local service
+    AuthenticatedWithHeaders(Vec<(String, String)>),
+    /// Request must not be forwarded - includes the response bytes to send
+    /// instead (a 401, or a redirect to a login page)
     Unauthorized(Vec<u8>),
 }
 
+impl AuthResult {
+    /// Whether the request should be forwarded to the tunnel
+    pub fn is_authenticated(&self) -> bool {
+        !matches!(self, AuthResult::Unauthorized(_))
+    }
+}
+
+/// An incoming HTTP request as seen by authentication providers
+///
+/// Header-only providers just look at `headers`; interactive providers such
+/// as OIDC also need the request line, and whether the client connected over
+/// TLS to build redirect URLs and cookie attributes.
+#[derive(Debug, Clone, Default)]
+pub struct AuthRequest {
+    /// Request method (e.g. "GET")
+    pub method: String,
+    /// Request target including the query string (e.g. "/callback?code=abc")
+    pub path: String,
+    /// List of (header_name, header_value) pairs
+    pub headers: Vec<(String, String)>,
+    /// Whether the request arrived over TLS
+    pub tls: bool,
+    /// Request body with any chunked framing removed
+    ///
+    /// Only complete if the relay buffered it because the provider has a
+    /// [`HttpAuthProvider::body_limit`].
+    pub body: Vec<u8>,
+    /// Address of the client, if known
+    pub client_ip: Option<IpAddr>,
+}
+
+impl AuthRequest {
+    /// Parse the request line, headers and body from raw request bytes
+    pub fn parse(data: &[u8], tls: bool) -> Self {
+        let request_str = String::from_utf8_lossy(data);
+        let mut parts = request_str
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+        let method = parts.next().unwrap_or("GET").to_string();
+        let path = parts.next().unwrap_or("/").to_string();
+
+        Self {
+            method,
+            path,
+            headers: parse_headers_from_request(data),
+            tls,
+            body: body::request_body(data),
+            client_ip: None,
+        }
+    }
+
+    /// Set the client address (used by IP allowlist policies)
+    pub fn with_client_ip(mut self, ip: IpAddr) -> Self {
+        self.client_ip = Some(ip);
+        self
+    }
+
+    /// Get the first value of a header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Get the Host header value (including the port, if any)
+    pub fn host(&self) -> Option<&str> {
+        self.header("host")
+    }
+
+    /// Get the request path without the query string
+    pub fn path_only(&self) -> &str {
+        self.path.split('?').next().unwrap_or("/")
+    }
+
+    /// Get a decoded query string parameter
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let query = self.path.split_once('?')?.1;
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    /// Get a cookie value from the Cookie header(s)
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case("cookie"))
+            .flat_map(|(_, v)| v.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v)
+    }
+}
+
 /// Error type for authentication operations
 #[derive(Error, Debug)]
 pub enum AuthError {
@@ -68,6 +205,12 @@ pub enum AuthError {
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
+
+    #[error("Identity provider error: {0}")]
+    ProviderError(String),
+
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
 }
 
 /// Trait for implementing HTTP authentication providers
@@ -76,6 +219,9 @@ pub enum AuthError {
 /// The trait is designed to be simple and stateless - each call to `authenticate`
 /// should be independent.
 ///
+/// Providers that need more than the headers (the request path, cookies, or
+/// calls to an external service) override `authenticate_request` instead.
+///
 /// # Example Implementation
 ///
 /// ```ignore
@@ -107,6 +253,7 @@ pub enum AuthError {
 ///     }
 /// }
 /// ```
+#[async_trait]
 pub trait HttpAuthProvider: Send + Sync {
     /// Authenticate the request based on headers
     ///
@@ -118,6 +265,34 @@ pub trait HttpAuthProvider: Send + Sync {
     /// - `AuthResult::Unauthorized(response)` if auth failed (with HTTP 401 response)
     fn authenticate(&self, headers: &[(String, String)]) -> AuthResult;
 
+    /// Authenticate a full request
+    ///
+    /// The default implementation delegates to `authenticate` with the
+    /// request headers.
+    async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        self.authenticate(&request.headers)
+    }
+
+    /// Maximum request body size the provider needs to see
+    ///
+    /// Providers that authenticate the body return a limit; the relay then
+    /// buffers the whole body (rejecting larger ones) before calling
+    /// `authenticate_request`. The default is `None`, which forwards requests
+    /// without waiting for the body.
+    fn body_limit(&self) -> Option<usize> {
+        None
+    }
+
+    /// Maximum request body size the provider needs to see for `request`
+    ///
+    /// `request` only holds what has been read so far (at least the request
+    /// line and headers). Override this instead of `body_limit` when only
+    /// some requests need their body, such as a login form submission. The
+    /// default returns `body_limit`.
+    fn body_limit_for(&self, _request: &AuthRequest) -> Option<usize> {
+        self.body_limit()
+    }
+
     /// Generate the 401 Unauthorized response for this auth type
     fn unauthorized_response(&self) -> Vec<u8>;
 
@@ -160,6 +335,17 @@ impl HttpAuthenticator {
     /// # Returns
     /// An `HttpAuthenticator` configured with the appropriate provider
     pub fn from_config(config: &HttpAuthConfig) -> Self {
+        Self::from_config_with_accounts(config, None)
+    }
+
+    /// Create a new authenticator that can also check relay accounts
+    ///
+    /// `accounts` is used by login forms with `team_accounts` enabled,
+    /// including ones nested in a policy.
+    pub fn from_config_with_accounts(
+        config: &HttpAuthConfig,
+        accounts: Option<Arc<dyn AccountVerifier>>,
+    ) -> Self {
         let provider: Box<dyn HttpAuthProvider> = match config {
             HttpAuthConfig::None => Box::new(NoAuthProvider),
             HttpAuthConfig::Basic { credentials } => {
@@ -172,11 +358,57 @@ impl HttpAuthenticator {
                 header_name,
                 values,
             } => Box::new(HeaderAuthProvider::new(header_name.clone(), values.clone())),
+            HttpAuthConfig::Oidc(oidc) => Box::new(OidcAuthProvider::new((**oidc).clone())),
+            HttpAuthConfig::Jwt(jwt) => Box::new(JwtAuthProvider::new((**jwt).clone())),
+            HttpAuthConfig::WebhookSignature(webhook) => {
+                Box::new(WebhookSignatureProvider::new((**webhook).clone()))
+            }
+            HttpAuthConfig::Policy(policy) => {
+                Box::new(PolicyAuthProvider::with_accounts(policy, accounts))
+            }
+            HttpAuthConfig::LoginForm(login) => {
+                let provider = LoginFormProvider::new((**login).clone());
+                Box::new(match accounts {
+                    Some(accounts) => provider.with_accounts(accounts),
+                    None => provider,
+                })
+            }
         };
 
         Self { provider }
     }
 
+    /// Check a tunnel's configuration before the tunnel is registered
+    ///
+    /// Configured password hashes, including those nested in policies and
+    /// login forms, must be within the cost limits of
+    /// [`check_password_hash`](localup_auth::password::check_password_hash),
+    /// since the relay verifies them on every request.
+    pub fn validate_config(config: &HttpAuthConfig) -> Result<(), AuthError> {
+        match config {
+            HttpAuthConfig::Basic { credentials } => basic::check_password_hashes(credentials),
+            HttpAuthConfig::LoginForm(login) => basic::check_password_hashes(&login.credentials),
+            HttpAuthConfig::Policy(policy) => Self::validate_policy(policy),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_policy(policy: &AuthPolicy) -> Result<(), AuthError> {
+        match policy {
+            AuthPolicy::Auth(config) => Self::validate_config(config),
+            AuthPolicy::AnyOf(policies) | AuthPolicy::AllOf(policies) => {
+                policies.iter().try_for_each(Self::validate_policy)
+            }
+            AuthPolicy::PathMatch(rules) => rules
+                .iter()
+                .try_for_each(|rule| Self::validate_policy(&rule.policy)),
+            AuthPolicy::MethodMatch(rules) => rules
+                .iter()
+                .try_for_each(|rule| Self::validate_policy(&rule.policy)),
+            AuthPolicy::Allow | AuthPolicy::Deny | AuthPolicy::IpAllowlist(_) => Ok(()),
+        }
+    }
+
     /// Create a new authenticator with a custom provider
     ///
     /// Use this method when you have a custom authentication provider
@@ -196,11 +428,32 @@ impl HttpAuthenticator {
         self.provider.authenticate(headers)
     }
 
+    /// Authenticate a full HTTP request
+    ///
+    /// Use this from the relay's HTTP paths: unlike `authenticate`, it lets
+    /// providers handle their own endpoints (such as an OIDC callback) and
+    /// issue redirects.
+    pub async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        self.provider.authenticate_request(request).await
+    }
+
     /// Get the authentication type name
     pub fn auth_type(&self) -> &'static str {
         self.provider.auth_type()
     }
 
+    /// Maximum request body size to buffer before authenticating, if the
+    /// provider needs the body
+    pub fn body_limit(&self) -> Option<usize> {
+        self.provider.body_limit()
+    }
+
+    /// Maximum request body size to buffer before authenticating `request`,
+    /// which holds at least the request line and headers
+    pub fn body_limit_for(&self, request: &AuthRequest) -> Option<usize> {
+        self.provider.body_limit_for(request)
+    }
+
     /// Check if authentication is required
     pub fn requires_auth(&self) -> bool {
         self.provider.auth_type() != "none"
@@ -242,6 +495,52 @@ pub fn parse_headers_from_request(data: &[u8]) -> Vec<(String, String)> {
     headers
 }
 
+/// Add headers to a raw HTTP request before it is forwarded
+///
+/// Any existing headers with the same names are removed first, so clients
+/// cannot spoof identity headers set by an authentication provider. Headers
+/// with an empty value are only removed, which lets providers strip headers
+/// they have no value for.
+///
+/// # Arguments
+/// * `data` - Raw HTTP request bytes (request line, headers and any body)
+/// * `headers` - Headers to add
+///
+/// # Returns
+/// The rewritten request bytes. If `data` does not contain a complete header
+/// block it is returned unchanged.
+pub fn inject_request_headers(data: &[u8], headers: &[(String, String)]) -> Vec<u8> {
+    let Some(header_end) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return data.to_vec();
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]);
+    let mut lines = head.split("\r\n");
+    let mut out = String::with_capacity(header_end + 128);
+    out.push_str(lines.next().unwrap_or_default());
+    out.push_str("\r\n");
+
+    for line in lines {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        if headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+    for (name, value) in headers.iter().filter(|(_, v)| !v.is_empty()) {
+        out.push_str(name);
+        out.push_str(": ");
+        out.push_str(value);
+        out.push_str("\r\n");
+    }
+    out.push_str("\r\n");
+
+    let mut bytes = out.into_bytes();
+    bytes.extend_from_slice(&data[header_end + 4..]);
+    bytes
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -264,6 +563,29 @@ mod tests {
         assert!(!auth.requires_auth());
     }
 
+    #[test]
+    fn test_validate_config_rejects_expensive_hashes() {
+        let expensive = "admin:$argon2id$v=19$m=4194304,t=100,p=1$c2FsdHNhbHQ$aGFzaGhhc2g";
+        let basic = |credential: &str| HttpAuthConfig::Basic {
+            credentials: vec![credential.to_string()],
+        };
+
+        assert!(HttpAuthenticator::validate_config(&basic("admin:plain")).is_ok());
+        assert!(matches!(
+            HttpAuthenticator::validate_config(&basic(expensive)),
+            Err(AuthError::ConfigError(_))
+        ));
+
+        // Also when nested in a policy
+        let policy = HttpAuthConfig::Policy(Box::new(AuthPolicy::PathMatch(vec![
+            localup_proto::PathRule {
+                paths: vec!["/admin/*".to_string()],
+                policy: AuthPolicy::AnyOf(vec![AuthPolicy::Auth(basic(expensive))]),
+            },
+        ])));
+        assert!(HttpAuthenticator::validate_config(&policy).is_err());
+    }
+
     #[test]
     fn test_parse_headers_from_request() {
         let request =
@@ -287,4 +609,47 @@ mod tests {
         let headers = parse_headers_from_request(request);
         assert!(headers.is_empty());
     }
+
+    #[test]
+    fn test_auth_request_parse() {
+        let request = b"GET /cb?code=abc%2F1&state=xyz HTTP/1.1\r\nHost: app.example.com\r\nCookie: a=1; b=two\r\n\r\n";
+        let req = AuthRequest::parse(request, true);
+
+        assert_eq!(req.method, "GET");
+        assert_eq!(req.path_only(), "/cb");
+        assert_eq!(req.query_param("code").as_deref(), Some("abc/1"));
+        assert_eq!(req.query_param("missing"), None);
+        assert_eq!(req.host(), Some("app.example.com"));
+        assert_eq!(req.cookie("b"), Some("two"));
+        assert_eq!(req.cookie("c"), None);
+        assert!(req.tls);
+    }
+
+    #[test]
+    fn test_inject_request_headers_replaces_spoofed_values() {
+        let request = b"POST /api HTTP/1.1\r\nHost: a\r\nx-forwarded-email: evil@x.com\r\n\r\nbody";
+        let rewritten = inject_request_headers(
+            request,
+            &[(
+                "X-Forwarded-Email".to_string(),
+                "alice@example.com".to_string(),
+            )],
+        );
+        let text = String::from_utf8(rewritten).unwrap();
+
+        assert!(text.starts_with("POST /api HTTP/1.1\r\nHost: a\r\n"));
+        assert!(!text.contains("evil@x.com"));
+        assert!(text.ends_with("X-Forwarded-Email: alice@example.com\r\n\r\nbody"));
+    }
+
+    #[test]
+    fn test_inject_request_headers_empty_value_only_strips() {
+        let request = b"GET / HTTP/1.1\r\nHost: a\r\nX-Forwarded-Groups: admins\r\n\r\n";
+        let rewritten = inject_request_headers(
+            request,
+            &[("X-Forwarded-Groups".to_string(), String::new())],
+        );
+
+        assert_eq!(rewritten, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+    }
 }
@@ -0,0 +1,533 @@
+//! Login form provider with relay-issued session cookies
+//!
+//! A friendlier alternative to Basic auth for browsers: instead of the
+//! browser's credential prompt, the relay serves a login page on the tunnel
+//! host and issues a signed session cookie once the credentials check out.
+//!
+//! ```text
+//! GET  /dashboard               -> 302 Location: /_localup/login?next=/dashboard
+//! POST /_localup/login          -> 302 Location: /dashboard
+//!      username=..&password=..     Set-Cookie: _localup_session=...; HttpOnly
+//! GET  /_localup/logout         -> 302 Location: /_localup/login
+//!                                  Set-Cookie: _localup_session=; Max-Age=0
+//! ```
+//!
+//! Credentials are checked against the configured `username:password` pairs
+//! (plain or hashed) and, if the relay supplies an [`AccountVerifier`],
+//! against relay accounts. Non-browser clients can skip the form and send
+//! `Authorization: Basic` with the same credentials, or a configured bearer
+//! token. Logged-in requests are forwarded with `X-Forwarded-User`.
+
+use crate::bearer::BearerTokenProvider;
+use crate::oidc::USER_HEADER;
+use crate::response;
+use crate::session::{now_secs, set_cookie, SessionSigner};
+use crate::{AuthRequest, AuthResult, BasicAuthProvider, HttpAuthProvider};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use localup_proto::LoginFormConfig;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Name of the session cookie issued after a successful login
+pub const LOGIN_SESSION_COOKIE: &str = "_localup_session";
+
+/// Largest login form submission the relay buffers
+const MAX_FORM_BYTES: usize = 16 * 1024;
+
+/// Verifies usernames and passwords against accounts outside the tunnel
+/// configuration, such as the relay's user database
+#[async_trait]
+pub trait AccountVerifier: Send + Sync {
+    /// Whether `password` is valid for the account named `username`
+    async fn verify(&self, username: &str, password: &str) -> bool;
+}
+
+/// Logged-in user, stored in the session cookie
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Session {
+    user: String,
+    exp: u64,
+}
+
+/// Login form authentication provider
+pub struct LoginFormProvider {
+    config: LoginFormConfig,
+    signer: SessionSigner,
+    credentials: BasicAuthProvider,
+    bearer: Option<BearerTokenProvider>,
+    accounts: Option<Arc<dyn AccountVerifier>>,
+}
+
+impl LoginFormProvider {
+    /// Create a new login form provider
+    ///
+    /// # Example
+    /// ```
+    /// use localup_http_auth::LoginFormProvider;
+    /// use localup_proto::LoginFormConfig;
+    ///
+    /// let provider = LoginFormProvider::new(LoginFormConfig::new(vec![
+    ///     "admin:secret".to_string(),
+    /// ]));
+    /// ```
+    pub fn new(config: LoginFormConfig) -> Self {
+        let signer = config
+            .cookie_secret
+            .as_deref()
+            .map(SessionSigner::new)
+            .unwrap_or_else(SessionSigner::random);
+        let bearer = (!config.bearer_tokens.is_empty())
+            .then(|| BearerTokenProvider::new(config.bearer_tokens.clone()));
+
+        Self {
+            credentials: BasicAuthProvider::new(config.credentials.clone()),
+            config,
+            signer,
+            bearer,
+            accounts: None,
+        }
+    }
+
+    /// Also accept accounts checked by `accounts`
+    ///
+    /// Ignored unless `team_accounts` is enabled in the configuration.
+    pub fn with_accounts(mut self, accounts: Arc<dyn AccountVerifier>) -> Self {
+        if self.config.team_accounts {
+            self.accounts = Some(accounts);
+        }
+        self
+    }
+
+    /// Check a username and password against every configured source
+    async fn verify(&self, username: &str, password: &str) -> bool {
+        if self
+            .credentials
+            .verify_credentials(format!("{}:{}", username, password))
+            .await
+        {
+            return true;
+        }
+        match &self.accounts {
+            Some(accounts) => accounts.verify(username, password).await,
+            None => false,
+        }
+    }
+
+    /// Get the session from the request cookies, if present and valid
+    fn session(&self, request: &AuthRequest) -> Option<Session> {
+        let session: Session = self.signer.verify(request.cookie(LOGIN_SESSION_COOKIE)?)?;
+        if session.exp <= now_secs() {
+            debug!("Login form: session expired for {}", session.user);
+            return None;
+        }
+        Some(session)
+    }
+
+    fn identity_headers(user: &str) -> AuthResult {
+        AuthResult::AuthenticatedWithHeaders(vec![(USER_HEADER.to_string(), user.to_string())])
+    }
+
+    /// Authenticate an `Authorization` header from a non-browser client
+    async fn authenticate_header(&self, request: &AuthRequest, header: &str) -> AuthResult {
+        if let Some(encoded) = strip_scheme(header, "basic") {
+            let credentials = BASE64
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok());
+            if let Some((username, password)) =
+                credentials.as_deref().and_then(|c| c.split_once(':'))
+            {
+                if self.verify(username, password).await {
+                    debug!("Login form: Basic credentials accepted for {}", username);
+                    return Self::identity_headers(username);
+                }
+            }
+        } else if let Some(bearer) = &self.bearer {
+            if bearer.authenticate(&request.headers).is_authenticated() {
+                return AuthResult::Authenticated;
+            }
+        }
+
+        debug!("Login form: invalid Authorization header");
+        AuthResult::Unauthorized(self.unauthorized_response())
+    }
+
+    /// Serve the login page or handle a submitted form
+    async fn handle_login(&self, request: &AuthRequest) -> AuthResult {
+        let next = request.query_param("next").unwrap_or_default();
+        match request.method.as_str() {
+            "GET" | "HEAD" => AuthResult::Unauthorized(self.login_page(200, "OK", &next, None)),
+            "POST" => {
+                let form: Vec<(String, String)> = url::form_urlencoded::parse(&request.body)
+                    .into_owned()
+                    .collect();
+                let field = |name: &str| {
+                    form.iter()
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.as_str())
+                        .unwrap_or_default()
+                };
+                let (username, password) = (field("username"), field("password"));
+                let next = match field("next") {
+                    "" => next.as_str(),
+                    next => next,
+                };
+
+                if username.is_empty() || !self.verify(username, password).await {
+                    debug!("Login form: failed login for {:?}", username);
+                    return AuthResult::Unauthorized(self.login_page(
+                        401,
+                        "Unauthorized",
+                        next,
+                        Some("Invalid username or password"),
+                    ));
+                }
+
+                debug!("Login form: login successful for {}", username);
+                let session = Session {
+                    user: username.to_string(),
+                    exp: now_secs() + self.config.session_ttl_secs,
+                };
+                let cookie = set_cookie(
+                    LOGIN_SESSION_COOKIE,
+                    &self.signer.sign(&session),
+                    self.config.session_ttl_secs,
+                    request.tls,
+                );
+                AuthResult::Unauthorized(response::redirect(response::local_path(next), &[cookie]))
+            }
+            _ => AuthResult::Unauthorized(response::plain_text(
+                405,
+                "Method Not Allowed",
+                "Method not allowed\n",
+            )),
+        }
+    }
+
+    fn login_page(&self, status: u16, reason: &str, next: &str, error: Option<&str>) -> Vec<u8> {
+        let title = escape_html(&self.config.title);
+        let error = error
+            .map(|e| format!("<p class=\"error\">{}</p>", escape_html(e)))
+            .unwrap_or_default();
+        let body = format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; background: #f4f4f5; display: flex; justify-content: center; padding-top: 12vh; }}
+form {{ background: #fff; padding: 2rem; border-radius: 8px; box-shadow: 0 1px 3px rgba(0,0,0,.15); width: 20rem; }}
+h1 {{ font-size: 1.25rem; margin-top: 0; }}
+label {{ display: block; margin: .75rem 0 .25rem; font-size: .875rem; }}
+input {{ width: 100%; box-sizing: border-box; padding: .5rem; border: 1px solid #d4d4d8; border-radius: 4px; }}
+button {{ margin-top: 1.25rem; width: 100%; padding: .6rem; border: 0; border-radius: 4px; background: #18181b; color: #fff; cursor: pointer; }}
+.error {{ color: #b91c1c; font-size: .875rem; }}
+</style>
+</head>
+<body>
+<form method="post" action="{action}">
+<h1>{title}</h1>
+{error}
+<input type="hidden" name="next" value="{next}">
+<label for="username">Username</label>
+<input id="username" name="username" autocomplete="username" required autofocus>
+<label for="password">Password</label>
+<input id="password" name="password" type="password" autocomplete="current-password" required>
+<button type="submit">Sign in</button>
+</form>
+</body>
+</html>
+"#,
+            title = title,
+            action = escape_html(&self.config.login_path),
+            error = error,
+            next = escape_html(response::local_path(next)),
+        );
+        response::html(status, reason, &body, &[])
+    }
+
+    /// End the session and go back to the login page
+    fn handle_logout(&self, request: &AuthRequest) -> AuthResult {
+        let clear = set_cookie(LOGIN_SESSION_COOKIE, "", 0, request.tls);
+        AuthResult::Unauthorized(response::redirect(&self.config.login_path, &[clear]))
+    }
+
+    /// Redirect a browser to the login page, or reject an API client
+    fn require_login(&self, request: &AuthRequest) -> AuthResult {
+        let is_navigation = matches!(request.method.as_str(), "GET" | "HEAD")
+            && request
+                .header("accept")
+                .is_some_and(|accept| accept.contains("text/html"));
+        if !is_navigation {
+            return AuthResult::Unauthorized(self.unauthorized_response());
+        }
+
+        let mut location = self.config.login_path.clone();
+        if request.path != "/" {
+            location.push_str("?next=");
+            location.extend(url::form_urlencoded::byte_serialize(
+                request.path.as_bytes(),
+            ));
+        }
+        AuthResult::Unauthorized(response::redirect(&location, &[]))
+    }
+}
+
+/// Strip an authorization scheme (case-insensitive) from a header value
+fn strip_scheme<'a>(header: &'a str, scheme: &str) -> Option<&'a str> {
+    let (name, value) = header.split_once(' ')?;
+    name.eq_ignore_ascii_case(scheme).then_some(value)
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[async_trait]
+impl HttpAuthProvider for LoginFormProvider {
+    fn authenticate(&self, headers: &[(String, String)]) -> AuthResult {
+        let request = AuthRequest {
+            headers: headers.to_vec(),
+            ..Default::default()
+        };
+        match self.session(&request) {
+            Some(session) => Self::identity_headers(&session.user),
+            None => AuthResult::Unauthorized(self.unauthorized_response()),
+        }
+    }
+
+    async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        let path = request.path_only();
+        if path == self.config.logout_path {
+            return self.handle_logout(request);
+        }
+        if path == self.config.login_path {
+            return self.handle_login(request).await;
+        }
+
+        if let Some(session) = self.session(request) {
+            return Self::identity_headers(&session.user);
+        }
+        match request.header("authorization") {
+            Some(header) => self.authenticate_header(request, header).await,
+            None => self.require_login(request),
+        }
+    }
+
+    fn body_limit_for(&self, request: &AuthRequest) -> Option<usize> {
+        // Only login submissions need the body
+        (request.method == "POST" && request.path_only() == self.config.login_path)
+            .then_some(MAX_FORM_BYTES)
+    }
+
+    fn unauthorized_response(&self) -> Vec<u8> {
+        // No WWW-Authenticate header: browsers should not show their own prompt
+        response::plain_text(401, "Unauthorized", "Authentication required\n")
+    }
+
+    fn auth_type(&self) -> &'static str {
+        "login-form"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TeamAccounts;
+
+    #[async_trait]
+    impl AccountVerifier for TeamAccounts {
+        async fn verify(&self, username: &str, password: &str) -> bool {
+            username == "alice@example.com" && password == "team-pass"
+        }
+    }
+
+    fn provider(configure: impl FnOnce(&mut LoginFormConfig)) -> LoginFormProvider {
+        let mut config = LoginFormConfig::new(vec!["admin:secret".to_string()]);
+        config.cookie_secret = Some("cookie-secret".to_string());
+        configure(&mut config);
+        LoginFormProvider::new(config).with_accounts(Arc::new(TeamAccounts))
+    }
+
+    fn request(raw: &str) -> AuthRequest {
+        AuthRequest::parse(raw.as_bytes(), true)
+    }
+
+    fn login(body: &str) -> AuthRequest {
+        request(&format!(
+            "POST /_localup/login HTTP/1.1\r\nHost: app\r\n\
+             Content-Type: application/x-www-form-urlencoded\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+    }
+
+    fn response_text(result: AuthResult) -> String {
+        match result {
+            AuthResult::Unauthorized(response) => String::from_utf8(response).unwrap(),
+            other => panic!("Expected a response, got {:?}", other),
+        }
+    }
+
+    /// Log in and return the session cookie
+    async fn session_cookie(provider: &LoginFormProvider, body: &str) -> Option<String> {
+        let response = response_text(provider.authenticate_request(&login(body)).await);
+        if !response.starts_with("HTTP/1.1 302") {
+            return None;
+        }
+        let cookie = response.split("Set-Cookie: ").nth(1)?;
+        Some(cookie.split(';').next()?.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_browser_redirected_to_login_page() {
+        let provider = provider(|_| {});
+        let response = response_text(
+            provider
+                .authenticate_request(&request(
+                    "GET /reports?q=1 HTTP/1.1\r\nHost: app\r\nAccept: text/html\r\n\r\n",
+                ))
+                .await,
+        );
+        assert!(response.contains("Location: /_localup/login?next=%2Freports%3Fq%3D1\r\n"));
+
+        let page = response_text(
+            provider
+                .authenticate_request(&request(
+                    "GET /_localup/login?next=%2Freports HTTP/1.1\r\nHost: app\r\n\r\n",
+                ))
+                .await,
+        );
+        assert!(page.starts_with("HTTP/1.1 200 OK"));
+        assert!(page.contains(r#"name="next" value="/reports""#));
+
+        let api = provider
+            .authenticate_request(&request("GET /api HTTP/1.1\r\nHost: app\r\n\r\n"))
+            .await;
+        assert!(response_text(api).starts_with("HTTP/1.1 401"));
+    }
+
+    #[tokio::test]
+    async fn test_login_issues_session_cookie() {
+        let provider = provider(|_| {});
+        let response = response_text(
+            provider
+                .authenticate_request(&login("username=admin&password=secret&next=%2Freports"))
+                .await,
+        );
+        assert!(response.starts_with("HTTP/1.1 302"));
+        assert!(response.contains("Location: /reports\r\n"));
+        assert!(response.contains("HttpOnly"));
+
+        let cookie = session_cookie(&provider, "username=admin&password=secret")
+            .await
+            .unwrap();
+        let result = provider
+            .authenticate_request(&request(&format!(
+                "GET /reports HTTP/1.1\r\nHost: app\r\nCookie: {}\r\n\r\n",
+                cookie
+            )))
+            .await;
+        match result {
+            AuthResult::AuthenticatedWithHeaders(headers) => {
+                assert_eq!(
+                    headers,
+                    vec![(USER_HEADER.to_string(), "admin".to_string())]
+                );
+            }
+            other => panic!("Expected authenticated result, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_login_and_open_redirect() {
+        let provider = provider(|_| {});
+        let failed = response_text(
+            provider
+                .authenticate_request(&login("username=admin&password=wrong"))
+                .await,
+        );
+        assert!(failed.starts_with("HTTP/1.1 401"));
+        assert!(failed.contains("Invalid username or password"));
+
+        let response = response_text(
+            provider
+                .authenticate_request(&login("username=admin&password=secret&next=%2F%2Fevil.com"))
+                .await,
+        );
+        assert!(response.contains("Location: /\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_team_accounts_only_when_enabled() {
+        let body = "username=alice%40example.com&password=team-pass";
+        assert!(session_cookie(&provider(|_| {}), body).await.is_none());
+        assert!(session_cookie(&provider(|c| c.team_accounts = true), body)
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_basic_and_bearer_for_api_clients() {
+        let provider = provider(|c| c.bearer_tokens = vec!["ci-token".to_string()]);
+        let basic = format!("Basic {}", BASE64.encode("admin:secret"));
+
+        let with_auth = |value: &str| {
+            request(&format!(
+                "GET /api HTTP/1.1\r\nHost: app\r\nAuthorization: {}\r\n\r\n",
+                value
+            ))
+        };
+        assert!(provider
+            .authenticate_request(&with_auth(&basic))
+            .await
+            .is_authenticated());
+        assert!(provider
+            .authenticate_request(&with_auth("Bearer ci-token"))
+            .await
+            .is_authenticated());
+        assert!(!provider
+            .authenticate_request(&with_auth("Bearer wrong"))
+            .await
+            .is_authenticated());
+    }
+
+    #[tokio::test]
+    async fn test_logout_clears_cookie() {
+        let provider = provider(|_| {});
+        let response = response_text(
+            provider
+                .authenticate_request(&request(
+                    "GET /_localup/logout HTTP/1.1\r\nHost: app\r\n\r\n",
+                ))
+                .await,
+        );
+        assert!(response.contains("Location: /_localup/login\r\n"));
+        assert!(response.contains("Set-Cookie: _localup_session=; Path=/; Max-Age=0"));
+    }
+
+    #[test]
+    fn test_body_limit_only_for_login_submissions() {
+        let provider = provider(|_| {});
+        assert_eq!(
+            provider.body_limit_for(&login("username=a")),
+            Some(MAX_FORM_BYTES)
+        );
+        assert_eq!(
+            provider.body_limit_for(&request("POST /upload HTTP/1.1\r\n\r\n")),
+            None
+        );
+    }
+}
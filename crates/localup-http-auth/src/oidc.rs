@@ -0,0 +1,671 @@
+//! OpenID Connect login provider
+//!
+//! Puts a tunnel behind an OIDC identity provider using the authorization-code
+//! flow with PKCE, handled entirely by the relay on the tunnel host:
+//!
+//! 1. A browser without a valid session is redirected to the provider's
+//!    authorization endpoint
+//! 2. The provider redirects back to `callback_path` on the tunnel host
+//! 3. The relay exchanges the code for an ID token, validates it (signature,
+//!    `iss`, `aud`, `exp`, `nonce`), applies the email/domain/group allowlists
+//!    and issues a signed session cookie
+//!
+//! Requests with a valid session are forwarded with identity headers:
+//!
+//! ```text
+//! X-Forwarded-User: <sub>
+//! X-Forwarded-Email: <email>
+//! X-Forwarded-Groups: <group1>,<group2>
+//! ```
+//!
+//! Provider metadata is discovered from
+//! `{issuer_url}/.well-known/openid-configuration` and cached, as are the
+//! provider's signing keys (see [`JwksCache`]).
+
+use crate::jwks::JwksCache;
+use crate::response;
+use crate::session::{now_secs, random_token, set_cookie, SessionSigner};
+use crate::{AuthError, AuthRequest, AuthResult, HttpAuthProvider};
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use localup_proto::OidcConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, warn};
+
+/// Name of the session cookie issued after a successful login
+pub const SESSION_COOKIE: &str = "_localup_oidc";
+
+/// Name of the short-lived cookie that binds a login attempt to the browser
+const STATE_COOKIE: &str = "_localup_oidc_state";
+
+/// How long a user has to complete the login at the identity provider
+const STATE_TTL_SECS: u64 = 10 * 60;
+
+/// How long discovered provider metadata is reused
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Header carrying the authenticated user's subject
+pub const USER_HEADER: &str = "X-Forwarded-User";
+/// Header carrying the authenticated user's email
+pub const EMAIL_HEADER: &str = "X-Forwarded-Email";
+/// Header carrying the authenticated user's groups (comma-separated)
+pub const GROUPS_HEADER: &str = "X-Forwarded-Groups";
+
+/// Subset of the OpenID provider metadata used by the relay
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Discovered {
+    metadata: ProviderMetadata,
+    jwks: Arc<JwksCache>,
+    fetched_at: Instant,
+}
+
+/// Login attempt in progress, stored in the state cookie
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingLogin {
+    nonce: String,
+    verifier: String,
+    return_to: String,
+    exp: u64,
+}
+
+/// Authenticated user, stored in the session cookie
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Session {
+    sub: String,
+    email: Option<String>,
+    groups: Vec<String>,
+    exp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// OpenID Connect authentication provider
+pub struct OidcAuthProvider {
+    config: OidcConfig,
+    client: reqwest::Client,
+    signer: SessionSigner,
+    discovered: RwLock<Option<Discovered>>,
+}
+
+impl OidcAuthProvider {
+    /// Create a new OIDC provider
+    ///
+    /// No network requests are made until the first login.
+    pub fn new(config: OidcConfig) -> Self {
+        let signer = config
+            .cookie_secret
+            .as_deref()
+            .map(SessionSigner::new)
+            .unwrap_or_else(SessionSigner::random);
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            config,
+            client,
+            signer,
+            discovered: RwLock::new(None),
+        }
+    }
+
+    /// Get the session from the request cookies, if present and valid
+    fn session_from_headers(&self, headers: &[(String, String)]) -> Option<Session> {
+        let request = AuthRequest {
+            headers: headers.to_vec(),
+            ..Default::default()
+        };
+        let session: Session = self.signer.verify(request.cookie(SESSION_COOKIE)?)?;
+        if session.exp <= now_secs() {
+            debug!("OIDC auth: session expired for {}", session.sub);
+            return None;
+        }
+        Some(session)
+    }
+
+    /// Identity headers for the local service
+    ///
+    /// Always includes every header (empty if unknown) so that client-supplied
+    /// values are stripped before forwarding.
+    fn identity_headers(session: &Session) -> Vec<(String, String)> {
+        vec![
+            (USER_HEADER.to_string(), session.sub.clone()),
+            (
+                EMAIL_HEADER.to_string(),
+                session.email.clone().unwrap_or_default(),
+            ),
+            (GROUPS_HEADER.to_string(), session.groups.join(",")),
+        ]
+    }
+
+    /// Get the provider metadata and key cache, discovering them if needed
+    async fn discover(&self) -> Result<(ProviderMetadata, Arc<JwksCache>), AuthError> {
+        if let Some(d) = self.discovered.read().await.as_ref() {
+            if d.fetched_at.elapsed() < DISCOVERY_TTL {
+                return Ok((d.metadata.clone(), d.jwks.clone()));
+            }
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer_url.trim_end_matches('/')
+        );
+        debug!("Fetching OIDC discovery document from {}", url);
+        let metadata: ProviderMetadata = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AuthError::ProviderError(format!("Discovery failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AuthError::ProviderError(format!("Invalid discovery document: {}", e)))?;
+
+        if metadata.issuer.trim_end_matches('/') != self.config.issuer_url.trim_end_matches('/') {
+            return Err(AuthError::ConfigError(format!(
+                "Discovered issuer {} does not match configured issuer {}",
+                metadata.issuer, self.config.issuer_url
+            )));
+        }
+
+        let mut discovered = self.discovered.write().await;
+        // Keep the existing key cache if the JWKS location did not change
+        let jwks = match discovered.as_ref() {
+            Some(d) if d.jwks.url() == metadata.jwks_uri => d.jwks.clone(),
+            _ => Arc::new(JwksCache::new(
+                metadata.jwks_uri.clone(),
+                self.client.clone(),
+            )),
+        };
+        *discovered = Some(Discovered {
+            metadata: metadata.clone(),
+            jwks: jwks.clone(),
+            fetched_at: Instant::now(),
+        });
+
+        Ok((metadata, jwks))
+    }
+
+    /// Redirect URI registered with the identity provider for this host
+    fn redirect_uri(&self, request: &AuthRequest) -> Option<String> {
+        let scheme = if request.tls { "https" } else { "http" };
+        Some(format!(
+            "{}://{}{}",
+            scheme,
+            request.host()?,
+            self.config.callback_path
+        ))
+    }
+
+    /// Redirect the browser to the identity provider
+    async fn start_login(&self, request: &AuthRequest) -> AuthResult {
+        // Only navigations can follow a login redirect
+        if !matches!(request.method.as_str(), "GET" | "HEAD") {
+            return AuthResult::Unauthorized(self.unauthorized_response());
+        }
+
+        let Some(redirect_uri) = self.redirect_uri(request) else {
+            return AuthResult::Unauthorized(response::plain_text(
+                400,
+                "Bad Request",
+                "Missing Host header",
+            ));
+        };
+
+        let metadata = match self.discover().await {
+            Ok((metadata, _)) => metadata,
+            Err(e) => {
+                warn!("OIDC auth: {}", e);
+                return AuthResult::Unauthorized(response::plain_text(
+                    502,
+                    "Bad Gateway",
+                    "Identity provider unavailable",
+                ));
+            }
+        };
+
+        let pending = PendingLogin {
+            nonce: random_token(16),
+            verifier: random_token(32),
+            return_to: request.path.clone(),
+            exp: now_secs() + STATE_TTL_SECS,
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.verifier.as_bytes()));
+
+        let mut scopes = self.config.scopes.clone();
+        if !scopes.iter().any(|s| s == "openid") {
+            scopes.insert(0, "openid".to_string());
+        }
+
+        let mut location = match url::Url::parse(&metadata.authorization_endpoint) {
+            Ok(url) => url,
+            Err(e) => {
+                warn!("OIDC auth: invalid authorization endpoint: {}", e);
+                return AuthResult::Unauthorized(response::plain_text(
+                    502,
+                    "Bad Gateway",
+                    "Identity provider misconfigured",
+                ));
+            }
+        };
+        location
+            .query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &redirect_uri)
+            .append_pair("scope", &scopes.join(" "))
+            .append_pair("state", &pending.nonce)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        debug!("OIDC auth: redirecting to identity provider");
+        let state_cookie = set_cookie(
+            STATE_COOKIE,
+            &self.signer.sign(&pending),
+            STATE_TTL_SECS,
+            request.tls,
+        );
+        AuthResult::Unauthorized(response::redirect(location.as_str(), &[state_cookie]))
+    }
+
+    /// Handle the authorization response from the identity provider
+    async fn handle_callback(&self, request: &AuthRequest) -> AuthResult {
+        if let Some(error) = request.query_param("error") {
+            debug!("OIDC auth: provider returned error {}", error);
+            return AuthResult::Unauthorized(response::plain_text(
+                403,
+                "Forbidden",
+                "Login was not completed",
+            ));
+        }
+
+        let pending: Option<PendingLogin> = request
+            .cookie(STATE_COOKIE)
+            .and_then(|value| self.signer.verify(value));
+        let (Some(pending), Some(code), Some(state)) = (
+            pending,
+            request.query_param("code"),
+            request.query_param("state"),
+        ) else {
+            return AuthResult::Unauthorized(response::plain_text(
+                400,
+                "Bad Request",
+                "Invalid or expired login attempt, please retry",
+            ));
+        };
+        if pending.nonce != state || pending.exp <= now_secs() {
+            return AuthResult::Unauthorized(response::plain_text(
+                400,
+                "Bad Request",
+                "Invalid or expired login attempt, please retry",
+            ));
+        }
+
+        let session = match self.complete_login(request, &pending, &code).await {
+            Ok(session) => session,
+            Err(e) => {
+                warn!("OIDC auth: login failed: {}", e);
+                return AuthResult::Unauthorized(response::plain_text(
+                    502,
+                    "Bad Gateway",
+                    "Login failed",
+                ));
+            }
+        };
+
+        if !self.is_allowed(&session) {
+            debug!("OIDC auth: {} is not in the allowlist", session.sub);
+            return AuthResult::Unauthorized(response::plain_text(
+                403,
+                "Forbidden",
+                "Access denied",
+            ));
+        }
+
+        debug!("OIDC auth: login successful for {}", session.sub);
+        let clear_state = set_cookie(STATE_COOKIE, "", 0, request.tls);
+        let session_cookie = set_cookie(
+            SESSION_COOKIE,
+            &self.signer.sign(&session),
+            self.config.session_ttl_secs,
+            request.tls,
+        );
+        // Only redirect back to a path on this host
+        AuthResult::Unauthorized(response::redirect(
+            response::local_path(&pending.return_to),
+            &[clear_state, session_cookie],
+        ))
+    }
+
+    /// Exchange the authorization code and validate the returned ID token
+    async fn complete_login(
+        &self,
+        request: &AuthRequest,
+        pending: &PendingLogin,
+        code: &str,
+    ) -> Result<Session, AuthError> {
+        let (metadata, jwks) = self.discover().await?;
+        let redirect_uri = self
+            .redirect_uri(request)
+            .ok_or_else(|| AuthError::InvalidFormat("Missing Host header".to_string()))?;
+
+        let tokens: TokenResponse = self
+            .client
+            .post(&metadata.token_endpoint)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("code_verifier", pending.verifier.as_str()),
+            ])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| AuthError::ProviderError(format!("Token exchange failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AuthError::ProviderError(format!("Invalid token response: {}", e)))?;
+
+        let claims = self
+            .validate_id_token(&tokens.id_token, &metadata.issuer, &jwks)
+            .await?;
+
+        if claims.get("nonce").and_then(Value::as_str) != Some(pending.nonce.as_str()) {
+            return Err(AuthError::InvalidToken("nonce mismatch".to_string()));
+        }
+
+        self.session_from_claims(&claims)
+    }
+
+    /// Validate an ID token's signature, issuer, audience and expiry
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        issuer: &str,
+        jwks: &JwksCache,
+    ) -> Result<Map<String, Value>, AuthError> {
+        let header = jsonwebtoken::decode_header(id_token)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+
+        let key = match header.alg {
+            // Symmetric ID tokens are signed with the client secret (OIDC Core 10.1)
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                DecodingKey::from_secret(self.config.client_secret.as_bytes())
+            }
+            _ => jwks.decoding_key(header.kid.as_deref()).await?,
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        jsonwebtoken::decode::<Map<String, Value>>(id_token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))
+    }
+
+    fn session_from_claims(&self, claims: &Map<String, Value>) -> Result<Session, AuthError> {
+        let sub = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| AuthError::InvalidToken("missing sub claim".to_string()))?
+            .to_string();
+
+        // Unverified addresses must not satisfy the email/domain allowlists
+        let email_verified = claims
+            .get("email_verified")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        let email = claims
+            .get("email")
+            .and_then(Value::as_str)
+            .filter(|_| email_verified)
+            .map(str::to_string);
+
+        let groups = match claims.get(&self.config.groups_claim) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+
+        Ok(Session {
+            sub,
+            email,
+            groups,
+            exp: now_secs() + self.config.session_ttl_secs,
+        })
+    }
+
+    /// Check the user against the configured allowlists
+    ///
+    /// With no allowlists configured every authenticated user is allowed.
+    /// Otherwise matching any one of the lists is sufficient.
+    fn is_allowed(&self, session: &Session) -> bool {
+        let config = &self.config;
+        if config.allowed_emails.is_empty()
+            && config.allowed_domains.is_empty()
+            && config.allowed_groups.is_empty()
+        {
+            return true;
+        }
+
+        if let Some(email) = &session.email {
+            if config
+                .allowed_emails
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(email))
+            {
+                return true;
+            }
+            if let Some((_, domain)) = email.rsplit_once('@') {
+                if config
+                    .allowed_domains
+                    .iter()
+                    .any(|allowed| allowed.trim_start_matches('@').eq_ignore_ascii_case(domain))
+                {
+                    return true;
+                }
+            }
+        }
+
+        session
+            .groups
+            .iter()
+            .any(|group| config.allowed_groups.contains(group))
+    }
+}
+
+#[async_trait]
+impl HttpAuthProvider for OidcAuthProvider {
+    fn authenticate(&self, headers: &[(String, String)]) -> AuthResult {
+        match self.session_from_headers(headers) {
+            Some(session) => AuthResult::AuthenticatedWithHeaders(Self::identity_headers(&session)),
+            None => AuthResult::Unauthorized(self.unauthorized_response()),
+        }
+    }
+
+    async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        if request.path_only() == self.config.callback_path {
+            return self.handle_callback(request).await;
+        }
+
+        match self.session_from_headers(&request.headers) {
+            Some(session) => AuthResult::AuthenticatedWithHeaders(Self::identity_headers(&session)),
+            None => self.start_login(request).await,
+        }
+    }
+
+    fn unauthorized_response(&self) -> Vec<u8> {
+        b"HTTP/1.1 401 Unauthorized\r\n\
+          Content-Type: text/plain\r\n\
+          Content-Length: 30\r\n\
+          \r\n\
+          Authentication required (OIDC)"
+            .to_vec()
+    }
+
+    fn auth_type(&self) -> &'static str {
+        "oidc"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(configure: impl FnOnce(&mut OidcConfig)) -> OidcAuthProvider {
+        let mut config = OidcConfig::new("https://idp.example.com", "client", "secret");
+        config.cookie_secret = Some("cookie-secret".to_string());
+        configure(&mut config);
+        OidcAuthProvider::new(config)
+    }
+
+    fn session(email: Option<&str>, groups: &[&str]) -> Session {
+        Session {
+            sub: "user-1".to_string(),
+            email: email.map(str::to_string),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            exp: now_secs() + 60,
+        }
+    }
+
+    fn cookie_headers(value: &str) -> Vec<(String, String)> {
+        vec![(
+            "Cookie".to_string(),
+            format!("other=1; {}={}", SESSION_COOKIE, value),
+        )]
+    }
+
+    #[test]
+    fn test_no_allowlists_allows_everyone() {
+        let provider = provider(|_| {});
+        assert!(provider.is_allowed(&session(None, &[])));
+    }
+
+    #[test]
+    fn test_email_and_domain_allowlists() {
+        let provider = provider(|c| {
+            c.allowed_emails = vec!["Boss@Partner.com".to_string()];
+            c.allowed_domains = vec!["example.com".to_string()];
+        });
+
+        assert!(provider.is_allowed(&session(Some("alice@example.com"), &[])));
+        assert!(provider.is_allowed(&session(Some("boss@partner.com"), &[])));
+        assert!(!provider.is_allowed(&session(Some("eve@partner.com"), &[])));
+        assert!(!provider.is_allowed(&session(Some("alice@example.com.evil"), &[])));
+        assert!(!provider.is_allowed(&session(None, &[])));
+    }
+
+    #[test]
+    fn test_group_allowlist() {
+        let provider = provider(|c| c.allowed_groups = vec!["engineering".to_string()]);
+
+        assert!(provider.is_allowed(&session(None, &["sales", "engineering"])));
+        assert!(!provider.is_allowed(&session(Some("a@example.com"), &["sales"])));
+    }
+
+    #[test]
+    fn test_unverified_email_is_ignored() {
+        let provider = provider(|c| c.allowed_domains = vec!["example.com".to_string()]);
+        let claims: Map<String, Value> = serde_json::from_value(serde_json::json!({
+            "sub": "user-1",
+            "email": "alice@example.com",
+            "email_verified": false,
+        }))
+        .unwrap();
+
+        let session = provider.session_from_claims(&claims).unwrap();
+        assert_eq!(session.email, None);
+        assert!(!provider.is_allowed(&session));
+    }
+
+    #[test]
+    fn test_valid_session_cookie_forwards_identity() {
+        let provider = provider(|_| {});
+        let cookie = provider
+            .signer
+            .sign(&session(Some("alice@example.com"), &["a", "b"]));
+
+        match provider.authenticate(&cookie_headers(&cookie)) {
+            AuthResult::AuthenticatedWithHeaders(headers) => {
+                assert!(headers.contains(&(USER_HEADER.to_string(), "user-1".to_string())));
+                assert!(
+                    headers.contains(&(EMAIL_HEADER.to_string(), "alice@example.com".to_string()))
+                );
+                assert!(headers.contains(&(GROUPS_HEADER.to_string(), "a,b".to_string())));
+            }
+            other => panic!("Expected authenticated result, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_expired_or_foreign_session_rejected() {
+        let provider = provider(|_| {});
+        let mut expired = session(Some("alice@example.com"), &[]);
+        expired.exp = now_secs() - 1;
+        let expired_cookie = provider.signer.sign(&expired);
+        let foreign_cookie = SessionSigner::new("other").sign(&session(None, &[]));
+
+        assert!(!provider
+            .authenticate(&cookie_headers(&expired_cookie))
+            .is_authenticated());
+        assert!(!provider
+            .authenticate(&cookie_headers(&foreign_cookie))
+            .is_authenticated());
+    }
+
+    #[tokio::test]
+    async fn test_non_navigation_request_gets_401() {
+        let provider = provider(|_| {});
+        let request = AuthRequest::parse(b"POST /api HTTP/1.1\r\nHost: app\r\n\r\n", true);
+
+        match provider.authenticate_request(&request).await {
+            AuthResult::Unauthorized(response) => {
+                assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 401"));
+            }
+            other => panic!("Expected 401, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_callback_without_state_cookie_rejected() {
+        let provider = provider(|_| {});
+        let request = AuthRequest::parse(
+            b"GET /_localup/oidc/callback?code=abc&state=xyz HTTP/1.1\r\nHost: app\r\n\r\n",
+            true,
+        );
+
+        match provider.authenticate_request(&request).await {
+            AuthResult::Unauthorized(response) => {
+                assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 400"));
+            }
+            other => panic!("Expected 400, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//! Composable authentication policies
+//!
+//! An [`AuthPolicy`] tree is compiled once per tunnel into nodes holding the
+//! providers it references, so stateful providers (OIDC sessions, JWKS
+//! caches) are shared between requests like they are for a single method.
+//!
+//! Path rules are matched against the normalized request path (percent-
+//! decoded, with `.`/`..` segments resolved and repeated slashes collapsed),
+//! so `/x/../admin` and `/%61dmin` are both subject to an `/admin/*` rule.
+//!
+//! # Example
+//!
+//! Open webhooks, Basic auth from an allowed network for `/admin`, and a
+//! bearer token for everything else, as written in `.localup.yml`:
+//!
+//! ```yaml
+//! http_auth:
+//!   Policy:
+//!     PathMatch:
+//!       - paths: ["/webhooks/*"]
+//!         policy: Allow
+//!       - paths: ["/admin/*"]
+//!         policy:
+//!           AllOf:
+//!             - Auth: { Basic: { credentials: ["admin:secret"] } }
+//!             - IpAllowlist: ["10.0.0.0/8"]
+//!       - paths: ["*"]
+//!         policy:
+//!           Auth: { BearerToken: { tokens: ["token"] } }
+//! ```
+
+use crate::response::plain_text;
+use crate::{AccountVerifier, AuthRequest, AuthResult, HttpAuthProvider, HttpAuthenticator};
+use async_trait::async_trait;
+use localup_proto::{AuthPolicy, IpFilter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+type NodeFuture<'a> = Pin<Box<dyn Future<Output = AuthResult> + Send + 'a>>;
+
+enum Node {
+    Allow,
+    Deny,
+    Auth(HttpAuthenticator),
+    /// `None` if the allowlist could not be parsed (denies every request)
+    IpAllowlist(Option<IpFilter>),
+    AnyOf(Vec<Node>),
+    AllOf(Vec<Node>),
+    PathMatch(Vec<(Vec<String>, Node)>),
+    MethodMatch(Vec<(Vec<String>, Node)>),
+}
+
+impl Node {
+    fn compile(policy: &AuthPolicy, accounts: &Option<Arc<dyn AccountVerifier>>) -> Self {
+        let compile = |policy| Self::compile(policy, accounts);
+        match policy {
+            AuthPolicy::Allow => Node::Allow,
+            AuthPolicy::Deny => Node::Deny,
+            AuthPolicy::Auth(config) => Node::Auth(HttpAuthenticator::from_config_with_accounts(
+                config,
+                accounts.clone(),
+            )),
+            AuthPolicy::IpAllowlist(entries) => match IpFilter::from_allowlist(entries.clone()) {
+                Ok(filter) => Node::IpAllowlist(Some(filter)),
+                Err(e) => {
+                    warn!("Invalid IP allowlist in auth policy, denying: {}", e);
+                    Node::IpAllowlist(None)
+                }
+            },
+            AuthPolicy::AnyOf(policies) => Node::AnyOf(policies.iter().map(compile).collect()),
+            AuthPolicy::AllOf(policies) => Node::AllOf(policies.iter().map(compile).collect()),
+            AuthPolicy::PathMatch(rules) => Node::PathMatch(
+                rules
+                    .iter()
+                    .map(|rule| (rule.paths.clone(), compile(&rule.policy)))
+                    .collect(),
+            ),
+            AuthPolicy::MethodMatch(rules) => Node::MethodMatch(
+                rules
+                    .iter()
+                    .map(|rule| (rule.methods.clone(), compile(&rule.policy)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn evaluate<'a>(&'a self, request: &'a AuthRequest, path: &'a str) -> NodeFuture<'a> {
+        Box::pin(async move {
+            match self {
+                Node::Allow => AuthResult::Authenticated,
+                Node::Deny => AuthResult::Unauthorized(forbidden()),
+                Node::Auth(authenticator) => authenticator.authenticate_request(request).await,
+                Node::IpAllowlist(filter) => {
+                    let allowed = match (filter, request.client_ip) {
+                        (Some(filter), Some(ip)) => filter.is_allowed(&ip),
+                        _ => false,
+                    };
+                    if allowed {
+                        AuthResult::Authenticated
+                    } else {
+                        debug!("Auth policy: client IP {:?} not allowed", request.client_ip);
+                        AuthResult::Unauthorized(forbidden())
+                    }
+                }
+                Node::AnyOf(nodes) => {
+                    let mut last_failure = None;
+                    for node in nodes {
+                        match node.evaluate(request, path).await {
+                            AuthResult::Unauthorized(response) => last_failure = Some(response),
+                            passed => return passed,
+                        }
+                    }
+                    AuthResult::Unauthorized(last_failure.unwrap_or_else(forbidden))
+                }
+                Node::AllOf(nodes) => {
+                    let mut headers = Vec::new();
+                    for node in nodes {
+                        match node.evaluate(request, path).await {
+                            AuthResult::Authenticated => {}
+                            AuthResult::AuthenticatedWithHeaders(extra) => headers.extend(extra),
+                            failed => return failed,
+                        }
+                    }
+                    if headers.is_empty() {
+                        AuthResult::Authenticated
+                    } else {
+                        AuthResult::AuthenticatedWithHeaders(headers)
+                    }
+                }
+                Node::PathMatch(rules) => {
+                    match rules
+                        .iter()
+                        .find(|(patterns, _)| patterns.iter().any(|p| path_matches(p, path)))
+                    {
+                        Some((_, node)) => node.evaluate(request, path).await,
+                        None => AuthResult::Unauthorized(forbidden()),
+                    }
+                }
+                Node::MethodMatch(rules) => {
+                    match rules.iter().find(|(methods, _)| {
+                        methods
+                            .iter()
+                            .any(|m| m == "*" || m.eq_ignore_ascii_case(&request.method))
+                    }) {
+                        Some((_, node)) => node.evaluate(request, path).await,
+                        None => AuthResult::Unauthorized(forbidden()),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Largest body limit of the providers in the tree
+    ///
+    /// With a request, providers report the limit for that request.
+    fn body_limit(&self, request: Option<&AuthRequest>) -> Option<usize> {
+        let children: Box<dyn Iterator<Item = &Node>> = match self {
+            Node::Auth(authenticator) => {
+                return match request {
+                    Some(request) => authenticator.body_limit_for(request),
+                    None => authenticator.body_limit(),
+                }
+            }
+            Node::AnyOf(nodes) | Node::AllOf(nodes) => Box::new(nodes.iter()),
+            Node::PathMatch(rules) | Node::MethodMatch(rules) => {
+                Box::new(rules.iter().map(|(_, node)| node))
+            }
+            _ => return None,
+        };
+        children.filter_map(|node| node.body_limit(request)).max()
+    }
+}
+
+fn forbidden() -> Vec<u8> {
+    plain_text(403, "Forbidden", "Access denied\n")
+}
+
+/// Check a path pattern against a normalized path
+fn path_matches(pattern: &str, path: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_suffix('*') {
+        // "/admin/*" also matches "/admin" itself
+        Some(prefix) => {
+            path.starts_with(prefix)
+                || prefix
+                    .strip_suffix('/')
+                    .is_some_and(|dir| !dir.is_empty() && path == dir)
+        }
+        None => path == pattern,
+    }
+}
+
+/// Normalize a request path for matching
+///
+/// Resolves `.` and `..` segments on the raw path (RFC 3986 §5.2.4) and
+/// collapses repeated slashes. Only unreserved characters other than `.` are
+/// percent-decoded, so the result names the same resource for backends that
+/// decode more. The result always starts with `/`.
+///
+/// Returns `None` for paths with an encoded slash, backslash or dot: backends
+/// disagree on whether those separate or traverse segments.
+pub(crate) fn normalize_path(path: &str) -> Option<String> {
+    let lower = path.to_ascii_lowercase();
+    if ["%2f", "%5c", "%2e"].iter().any(|e| lower.contains(e)) {
+        return None;
+    }
+
+    let mut segments: Vec<String> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(decode_unreserved(segment)),
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    let trailing_slash = path.ends_with('/') || path.ends_with("/.") || path.ends_with("/..");
+    if trailing_slash && normalized.len() > 1 {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// Decode percent-encoded letters, digits, `-`, `_` and `~`
+///
+/// Other escapes are kept, with their hex digits uppercased.
+fn decode_unreserved(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit));
+        if let (b'%', Some(hex)) = (bytes[i], escape) {
+            let hex = std::str::from_utf8(hex).expect("hex digits are ASCII");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'~') {
+                    decoded.push(byte);
+                } else {
+                    decoded.extend_from_slice(format!("%{:02X}", byte).as_bytes());
+                }
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    // Only ASCII bytes were substituted, so the input's UTF-8 is intact
+    String::from_utf8(decoded).unwrap_or_else(|_| input.to_string())
+}
+
+/// Authentication provider that evaluates an [`AuthPolicy`] tree
+pub struct PolicyAuthProvider {
+    root: Node,
+}
+
+impl PolicyAuthProvider {
+    /// Create a provider for the given policy
+    ///
+    /// # Example
+    /// ```
+    /// use localup_http_auth::PolicyAuthProvider;
+    /// use localup_proto::{AuthPolicy, HttpAuthConfig, PathRule};
+    ///
+    /// let provider = PolicyAuthProvider::new(&AuthPolicy::PathMatch(vec![
+    ///     PathRule {
+    ///         paths: vec!["/webhooks/*".to_string()],
+    ///         policy: AuthPolicy::Allow,
+    ///     },
+    ///     PathRule {
+    ///         paths: vec!["*".to_string()],
+    ///         policy: AuthPolicy::Auth(HttpAuthConfig::BearerToken {
+    ///             tokens: vec!["secret".to_string()],
+    ///         }),
+    ///     },
+    /// ]));
+    /// ```
+    pub fn new(policy: &AuthPolicy) -> Self {
+        Self::with_accounts(policy, None)
+    }
+
+    /// Compile a policy whose login forms can also check relay accounts
+    pub fn with_accounts(policy: &AuthPolicy, accounts: Option<Arc<dyn AccountVerifier>>) -> Self {
+        Self {
+            root: Node::compile(policy, &accounts),
+        }
+    }
+}
+
+#[async_trait]
+impl HttpAuthProvider for PolicyAuthProvider {
+    fn authenticate(&self, _headers: &[(String, String)]) -> AuthResult {
+        // Policies depend on the path, method and client address
+        debug!("Auth policy evaluated without a request, denying");
+        AuthResult::Unauthorized(self.unauthorized_response())
+    }
+
+    async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        let Some(path) = normalize_path(request.path_only()) else {
+            debug!("Denying request with an encoded slash or dot in its path");
+            return AuthResult::Unauthorized(self.unauthorized_response());
+        };
+        self.root.evaluate(request, &path).await
+    }
+
+    fn body_limit(&self) -> Option<usize> {
+        self.root.body_limit(None)
+    }
+
+    fn body_limit_for(&self, request: &AuthRequest) -> Option<usize> {
+        self.root.body_limit(Some(request))
+    }
+
+    fn unauthorized_response(&self) -> Vec<u8> {
+        forbidden()
+    }
+
+    fn auth_type(&self) -> &'static str {
+        "policy"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use localup_proto::{HttpAuthConfig, MethodRule, PathRule, WebhookSignatureConfig};
+
+    fn rule(path: &str, policy: AuthPolicy) -> PathRule {
+        PathRule {
+            paths: vec![path.to_string()],
+            policy,
+        }
+    }
+
+    fn basic() -> AuthPolicy {
+        AuthPolicy::Auth(HttpAuthConfig::Basic {
+            credentials: vec!["admin:secret".to_string()],
+        })
+    }
+
+    fn bearer() -> AuthPolicy {
+        AuthPolicy::Auth(HttpAuthConfig::BearerToken {
+            tokens: vec!["token".to_string()],
+        })
+    }
+
+    /// The example from the request: open webhooks, Basic + IP for admin,
+    /// bearer token for everything else
+    fn example_policy() -> PolicyAuthProvider {
+        PolicyAuthProvider::new(&AuthPolicy::PathMatch(vec![
+            rule("/webhooks/*", AuthPolicy::Allow),
+            rule(
+                "/admin/*",
+                AuthPolicy::AllOf(vec![
+                    basic(),
+                    AuthPolicy::IpAllowlist(vec!["10.0.0.0/8".to_string()]),
+                ]),
+            ),
+            rule("*", bearer()),
+        ]))
+    }
+
+    fn request(method: &str, path: &str, headers: &[&str], ip: &str) -> AuthRequest {
+        let mut raw = format!("{} {} HTTP/1.1\r\nHost: app.localup.test\r\n", method, path);
+        for header in headers {
+            raw.push_str(header);
+            raw.push_str("\r\n");
+        }
+        raw.push_str("\r\n");
+        AuthRequest::parse(raw.as_bytes(), true).with_client_ip(ip.parse().unwrap())
+    }
+
+    fn basic_header() -> String {
+        format!("Authorization: Basic {}", BASE64.encode("admin:secret"))
+    }
+
+    fn status(result: &AuthResult) -> Option<u16> {
+        match result {
+            AuthResult::Unauthorized(response) => String::from_utf8_lossy(response)
+                .split_whitespace()
+                .nth(1)
+                .and_then(|s| s.parse().ok()),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_path_rules() {
+        let policy = example_policy();
+        let basic = basic_header();
+
+        let open = policy
+            .authenticate_request(&request("POST", "/webhooks/github", &[], "1.2.3.4"))
+            .await;
+        assert!(open.is_authenticated());
+
+        let admin = policy
+            .authenticate_request(&request("GET", "/admin/users", &[&basic], "10.1.2.3"))
+            .await;
+        assert!(admin.is_authenticated());
+
+        let admin_outside = policy
+            .authenticate_request(&request("GET", "/admin", &[&basic], "1.2.3.4"))
+            .await;
+        assert_eq!(status(&admin_outside), Some(403));
+
+        let api = policy
+            .authenticate_request(&request("GET", "/api", &[], "10.1.2.3"))
+            .await;
+        assert_eq!(status(&api), Some(401));
+
+        let api = policy
+            .authenticate_request(&request(
+                "GET",
+                "/api",
+                &["Authorization: Bearer token"],
+                "1.2.3.4",
+            ))
+            .await;
+        assert!(api.is_authenticated());
+    }
+
+    #[tokio::test]
+    async fn test_path_traversal_does_not_bypass_rules() {
+        let policy = example_policy();
+        for path in [
+            "/webhooks/../admin/users",
+            "/webhooks/%2e%2e/admin",
+            "//admin/users",
+            "/%61dmin/users",
+            // Would resolve to the public webhook path if decoded first
+            "/admin/..%2Fwebhooks/x",
+            "/admin/%2e%2e/webhooks/x",
+            "/admin/..%252Fwebhooks/x",
+        ] {
+            let result = policy
+                .authenticate_request(&request("GET", path, &[], "1.2.3.4"))
+                .await;
+            assert!(!result.is_authenticated(), "{} was allowed", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_any_of_returns_last_failure() {
+        let policy = PolicyAuthProvider::new(&AuthPolicy::AnyOf(vec![bearer(), basic()]));
+
+        let result = policy
+            .authenticate_request(&request("GET", "/", &[], "1.2.3.4"))
+            .await;
+        match result {
+            AuthResult::Unauthorized(response) => {
+                assert!(String::from_utf8_lossy(&response).contains("WWW-Authenticate: Basic"));
+            }
+            other => panic!("Expected failure, got {:?}", other),
+        }
+
+        let result = policy
+            .authenticate_request(&request("GET", "/", &[&basic_header()], "1.2.3.4"))
+            .await;
+        assert!(result.is_authenticated());
+    }
+
+    #[tokio::test]
+    async fn test_method_match_and_empty_combinators() {
+        let policy = PolicyAuthProvider::new(&AuthPolicy::MethodMatch(vec![
+            MethodRule {
+                methods: vec!["get".to_string(), "HEAD".to_string()],
+                policy: AuthPolicy::AllOf(vec![]),
+            },
+            MethodRule {
+                methods: vec!["POST".to_string()],
+                policy: AuthPolicy::AnyOf(vec![]),
+            },
+        ]));
+
+        for (method, allowed) in [
+            ("GET", true),
+            ("HEAD", true),
+            ("POST", false),
+            ("PUT", false),
+        ] {
+            let result = policy
+                .authenticate_request(&request(method, "/", &[], "1.2.3.4"))
+                .await;
+            assert_eq!(result.is_authenticated(), allowed, "{}", method);
+        }
+    }
+
+    #[test]
+    fn test_body_limit_from_nested_provider() {
+        let policy = PolicyAuthProvider::new(&AuthPolicy::PathMatch(vec![
+            rule(
+                "/hooks/*",
+                AuthPolicy::Auth(HttpAuthConfig::WebhookSignature(Box::new(
+                    WebhookSignatureConfig::github("secret"),
+                ))),
+            ),
+            rule("*", bearer()),
+        ]));
+        assert_eq!(policy.body_limit(), Some(1024 * 1024));
+        assert_eq!(example_policy().body_limit(), None);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let normalize = |path| normalize_path(path).unwrap();
+        assert_eq!(normalize("/"), "/");
+        assert_eq!(normalize(""), "/");
+        assert_eq!(normalize("/a//b/./c/"), "/a/b/c/");
+        assert_eq!(normalize("/a/../../b"), "/b");
+        assert_eq!(normalize("/%61dmin/b%20c"), "/admin/b%20c");
+        assert_eq!(normalize("/100%"), "/100%");
+        // Double-encoded slashes stay a single segment
+        assert_eq!(
+            normalize("/admin/..%252Fwebhooks/x"),
+            "/admin/..%252Fwebhooks/x"
+        );
+
+        // Encoded slashes, backslashes and dots are refused
+        assert_eq!(normalize_path("/admin/..%2Fwebhooks/x"), None);
+        assert_eq!(normalize_path("/admin/%2e%2e/webhooks/x"), None);
+        assert_eq!(normalize_path("/admin/..%5cwebhooks"), None);
+    }
+
+    #[test]
+    fn test_path_matches() {
+        assert!(path_matches("*", "/anything"));
+        assert!(path_matches("/admin/*", "/admin"));
+        assert!(path_matches("/admin/*", "/admin/users"));
+        assert!(!path_matches("/admin/*", "/administrator"));
+        assert!(path_matches("/api*", "/api-v2"));
+        assert!(path_matches("/health", "/health"));
+        assert!(!path_matches("/health", "/health/live"));
+    }
+}
@@ -0,0 +1,65 @@
+//! Helpers for building raw HTTP responses returned by providers
+
+/// Build a `302 Found` redirect, optionally setting cookies
+pub(crate) fn redirect(location: &str, set_cookies: &[String]) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 302 Found\r\nLocation: {}\r\n", location);
+    for cookie in set_cookies {
+        response.push_str("Set-Cookie: ");
+        response.push_str(cookie);
+        response.push_str("\r\n");
+    }
+    response.push_str("Cache-Control: no-store\r\nContent-Length: 0\r\n\r\n");
+    response.into_bytes()
+}
+
+/// Build a plain-text response with the given status line
+pub(crate) fn plain_text(status: u16, reason: &str, body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: text/plain\r\n\
+         Cache-Control: no-store\r\n\
+         Content-Length: {}\r\n\
+         \r\n\
+         {}",
+        status,
+        reason,
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+/// Build an HTML response with the given status line, optionally setting cookies
+pub(crate) fn html(status: u16, reason: &str, body: &str, set_cookies: &[String]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/html; charset=utf-8\r\n",
+        status, reason
+    );
+    for cookie in set_cookies {
+        response.push_str("Set-Cookie: ");
+        response.push_str(cookie);
+        response.push_str("\r\n");
+    }
+    response.push_str(&format!(
+        "Cache-Control: no-store\r\n\
+         X-Frame-Options: DENY\r\n\
+         Content-Length: {}\r\n\
+         \r\n\
+         {}",
+        body.len(),
+        body
+    ));
+    response.into_bytes()
+}
+
+/// Return `target` if it is a path on the same host, `/` otherwise
+///
+/// Used for post-login redirects, which must not send users to another site
+/// (`//evil.com` and `/\evil.com` are treated as hosts by browsers).
+pub(crate) fn local_path(target: &str) -> &str {
+    if target.starts_with('/') && !target.starts_with("//") && !target.starts_with("/\\") {
+        target
+    } else {
+        "/"
+    }
+}
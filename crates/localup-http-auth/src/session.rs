@@ -0,0 +1,169 @@
+//! Signed cookie values for relay-issued sessions
+//!
+//! Values are encoded as `base64url(json).base64url(hmac_sha256(json))`, so
+//! they can be stored client-side and verified without any server state.
+//!
+//! # Format
+//!
+//! ```text
+//! Set-Cookie: <name>=eyJzdWIiOiJ1c2VyIn0.q2v...; Path=/; HttpOnly; SameSite=Lax
+//! ```
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies cookie payloads with HMAC-SHA256
+#[derive(Clone)]
+pub struct SessionSigner {
+    key: Vec<u8>,
+}
+
+impl SessionSigner {
+    /// Create a signer from a secret
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: secret.as_ref().to_vec(),
+        }
+    }
+
+    /// Create a signer with a random 256-bit secret
+    ///
+    /// Values signed by this signer cannot be verified after it is dropped.
+    pub fn random() -> Self {
+        let mut key = vec![0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self { key }
+    }
+
+    /// Serialize and sign a payload
+    pub fn sign<T: Serialize>(&self, payload: &T) -> String {
+        let json = serde_json::to_vec(payload).unwrap_or_default();
+        let signature = self.mac(&json);
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&json),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Verify a signed value and deserialize its payload
+    ///
+    /// Returns `None` if the value is malformed or the signature does not match.
+    pub fn verify<T: DeserializeOwned>(&self, value: &str) -> Option<T> {
+        let (payload, signature) = value.split_once('.')?;
+        let json = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+        let mut mac = HmacSha256::new_from_slice(&self.key).ok()?;
+        mac.update(&json);
+        // verify_slice compares in constant time
+        mac.verify_slice(&signature).ok()?;
+
+        serde_json::from_slice(&json).ok()
+    }
+
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// Current Unix time in seconds
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Generate a random URL-safe token with the given number of random bytes
+pub(crate) fn random_token(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Build a `Set-Cookie` header value
+///
+/// A `max_age` of zero expires the cookie immediately.
+pub(crate) fn set_cookie(name: &str, value: &str, max_age: u64, secure: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+        name, value, max_age
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Payload {
+        user: String,
+        exp: u64,
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let signer = SessionSigner::new("secret");
+        let payload = Payload {
+            user: "alice".to_string(),
+            exp: 42,
+        };
+
+        let signed = signer.sign(&payload);
+        assert_eq!(signer.verify::<Payload>(&signed), Some(payload));
+    }
+
+    #[test]
+    fn test_verify_rejects_other_key() {
+        let signed = SessionSigner::new("secret").sign(&Payload {
+            user: "alice".to_string(),
+            exp: 42,
+        });
+
+        assert_eq!(SessionSigner::new("other").verify::<Payload>(&signed), None);
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_payload() {
+        let signer = SessionSigner::new("secret");
+        let signed = signer.sign(&Payload {
+            user: "alice".to_string(),
+            exp: 42,
+        });
+        let (_, signature) = signed.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(br#"{"user":"mallory","exp":42}"#),
+            signature
+        );
+
+        assert_eq!(signer.verify::<Payload>(&forged), None);
+        assert_eq!(signer.verify::<Payload>("garbage"), None);
+    }
+
+    #[test]
+    fn test_set_cookie_attributes() {
+        let cookie = set_cookie("s", "v", 60, true);
+        assert_eq!(
+            cookie,
+            "s=v; Path=/; Max-Age=60; HttpOnly; SameSite=Lax; Secure"
+        );
+        assert!(!set_cookie("s", "", 0, false).contains("Secure"));
+    }
+}
@@ -0,0 +1,273 @@
+//! Signed, expiring share links
+//!
+//! A share link grants time-limited access to one tunnel (optionally a path
+//! prefix of it) without handing out credentials. The API mints the link,
+//! and the relay checks it on first use, then swaps it for a cookie scoped
+//! to the shared path:
+//!
+//! ```text
+//! GET /reports/q3?localup_share=<token>
+//!   -> 302 Location: /reports/q3
+//!      Set-Cookie: localup_share=<cookie>; Path=/reports; Max-Age=...
+//! ```
+//!
+//! Links and cookies are signed with different keys derived from the same
+//! secret, so a cookie can never be presented as a fresh link or vice versa.
+//! Use counts and revocation are tracked by the relay's database; this module
+//! only covers the signed values.
+
+use crate::policy::normalize_path;
+use crate::session::{now_secs, SessionSigner};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Query parameter carrying a share link token
+pub const SHARE_LINK_PARAM: &str = "localup_share";
+
+/// Cookie issued once a share link has been redeemed
+pub const SHARE_COOKIE: &str = "localup_share";
+
+/// What a share link grants access to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareLinkClaims {
+    /// Share link ID, as stored by the relay database
+    pub id: String,
+    /// Tunnel the link grants access to
+    pub tunnel: String,
+    /// Path prefix the link is limited to (`/` for the whole tunnel)
+    pub path: String,
+    /// Expiry as Unix seconds
+    pub exp: u64,
+}
+
+impl ShareLinkClaims {
+    /// Whether the link has expired
+    pub fn is_expired(&self) -> bool {
+        now_secs() >= self.exp
+    }
+
+    /// Whether the link covers a request for `path` on `tunnel`
+    ///
+    /// `path` may include a query string. `/docs` covers `/docs` and
+    /// `/docs/...` but not `/docs-old`.
+    pub fn allows(&self, tunnel: &str, path: &str) -> bool {
+        if self.tunnel != tunnel {
+            return false;
+        }
+
+        let prefix = self.path.trim_end_matches('/');
+        if prefix.is_empty() {
+            return true;
+        }
+
+        // Paths with encoded slashes or dots could escape the scope
+        let Some(path) = normalize_path(path.split('?').next().unwrap_or_default()) else {
+            return false;
+        };
+        path == prefix
+            || path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+
+    /// Seconds until the link expires
+    pub fn remaining_secs(&self) -> u64 {
+        self.exp.saturating_sub(now_secs())
+    }
+}
+
+/// Signs and verifies share link tokens and the cookies they are exchanged for
+#[derive(Clone)]
+pub struct ShareLinkSigner {
+    links: SessionSigner,
+    cookies: SessionSigner,
+}
+
+impl ShareLinkSigner {
+    /// Create a signer from the secret shared by the API and the relay
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        let secret = secret.as_ref();
+        Self {
+            links: SessionSigner::new(derive_key(secret, b"localup-share-link")),
+            cookies: SessionSigner::new(derive_key(secret, b"localup-share-cookie")),
+        }
+    }
+
+    /// Sign a share link token
+    pub fn sign_link(&self, claims: &ShareLinkClaims) -> String {
+        self.links.sign(claims)
+    }
+
+    /// Verify a share link token, rejecting expired links
+    pub fn verify_link(&self, token: &str) -> Option<ShareLinkClaims> {
+        self.links
+            .verify::<ShareLinkClaims>(token)
+            .filter(|claims| !claims.is_expired())
+    }
+
+    /// Sign the cookie issued when a link is redeemed
+    pub fn sign_cookie(&self, claims: &ShareLinkClaims) -> String {
+        self.cookies.sign(claims)
+    }
+
+    /// Verify a share cookie, rejecting expired ones
+    pub fn verify_cookie(&self, value: &str) -> Option<ShareLinkClaims> {
+        self.cookies
+            .verify::<ShareLinkClaims>(value)
+            .filter(|claims| !claims.is_expired())
+    }
+
+    /// Build the `Set-Cookie` header value for a redeemed link
+    ///
+    /// The cookie is limited to the shared path and expires with the link.
+    pub fn cookie_header(&self, claims: &ShareLinkClaims, secure: bool) -> String {
+        let path = match claims.path.trim_end_matches('/') {
+            "" => "/",
+            path => path,
+        };
+        let mut cookie = format!(
+            "{}={}; Path={}; Max-Age={}; HttpOnly; SameSite=Lax",
+            SHARE_COOKIE,
+            self.sign_cookie(claims),
+            path,
+            claims.remaining_secs()
+        );
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+fn derive_key(secret: &[u8], label: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(label);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Split the share link token out of a request target
+///
+/// Returns the token and the target with the parameter removed, or `None`
+/// if the target has no share link parameter.
+pub fn take_share_token(target: &str) -> Option<(String, String)> {
+    let (path, query) = target.split_once('?')?;
+
+    let mut token = None;
+    let mut rest = Vec::new();
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some((SHARE_LINK_PARAM, value)) if token.is_none() => token = Some(value.to_string()),
+            _ => rest.push(pair),
+        }
+    }
+
+    let token = token?;
+    let target = if rest.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, rest.join("&"))
+    };
+    Some((token, target))
+}
+
+/// Response for a share link that is invalid, expired, revoked or used up
+pub fn share_link_rejected() -> Vec<u8> {
+    crate::response::plain_text(
+        403,
+        "Forbidden",
+        "This share link is invalid or has expired\n",
+    )
+}
+
+/// Redirect that swaps a redeemed share link for its cookie
+pub fn share_link_redirect(location: &str, cookie: String) -> Vec<u8> {
+    crate::response::redirect(location, &[cookie])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(path: &str) -> ShareLinkClaims {
+        ShareLinkClaims {
+            id: "link-1".to_string(),
+            tunnel: "demo".to_string(),
+            path: path.to_string(),
+            exp: now_secs() + 3600,
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify_link() {
+        let signer = ShareLinkSigner::new("secret");
+        let token = signer.sign_link(&claims("/"));
+
+        assert_eq!(signer.verify_link(&token), Some(claims("/")));
+        assert!(ShareLinkSigner::new("other").verify_link(&token).is_none());
+
+        let mut expired = claims("/");
+        expired.exp = now_secs() - 1;
+        assert!(signer.verify_link(&signer.sign_link(&expired)).is_none());
+    }
+
+    #[test]
+    fn test_links_and_cookies_are_not_interchangeable() {
+        let signer = ShareLinkSigner::new("secret");
+        let token = signer.sign_link(&claims("/"));
+        let cookie = signer.sign_cookie(&claims("/"));
+
+        assert!(signer.verify_cookie(&token).is_none());
+        assert!(signer.verify_link(&cookie).is_none());
+        assert!(signer.verify_cookie(&cookie).is_some());
+    }
+
+    #[test]
+    fn test_path_scope() {
+        let scoped = claims("/reports/");
+        assert!(scoped.allows("demo", "/reports"));
+        assert!(scoped.allows("demo", "/reports/q3?page=2"));
+        assert!(!scoped.allows("demo", "/reports-old"));
+        assert!(!scoped.allows("demo", "/reports/../admin"));
+        assert!(!scoped.allows("other", "/reports/q3"));
+
+        assert!(claims("/").allows("demo", "/anything"));
+    }
+
+    #[test]
+    fn test_path_scope_rejects_encoded_separators() {
+        let scoped = claims("/reports/");
+        assert!(!scoped.allows("demo", "/reports/..%2Fadmin"));
+        assert!(!scoped.allows("demo", "/reports/%2e%2e/admin"));
+        assert!(!scoped.allows("demo", "/reports/..%5Cadmin"));
+        assert!(scoped.allows("demo", "/reports/..%252Fadmin"));
+    }
+
+    #[test]
+    fn test_take_share_token() {
+        assert_eq!(
+            take_share_token("/reports?localup_share=abc.def"),
+            Some(("abc.def".to_string(), "/reports".to_string()))
+        );
+        assert_eq!(
+            take_share_token("/r?page=2&localup_share=t&sort=asc"),
+            Some(("t".to_string(), "/r?page=2&sort=asc".to_string()))
+        );
+        assert_eq!(take_share_token("/reports?page=2"), None);
+        assert_eq!(take_share_token("/reports"), None);
+    }
+
+    #[test]
+    fn test_cookie_header_is_scoped() {
+        let signer = ShareLinkSigner::new("secret");
+        let cookie = signer.cookie_header(&claims("/reports/"), true);
+
+        assert!(cookie.starts_with("localup_share="));
+        assert!(cookie.contains("; Path=/reports;"));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.ends_with("; Secure"));
+        assert!(signer
+            .cookie_header(&claims("/"), false)
+            .contains("; Path=/;"));
+    }
+}
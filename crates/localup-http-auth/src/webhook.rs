@@ -0,0 +1,315 @@
+//! HMAC webhook signature verification
+//!
+//! Webhook senders sign the request body with a shared secret and send the
+//! signature in a header. The relay recomputes it over the buffered body and
+//! drops requests that are unsigned, incorrectly signed, or (when the sender
+//! includes a timestamp) too old to be anything but a replay.
+//!
+//! # Supported styles
+//!
+//! ```text
+//! GitHub: X-Hub-Signature-256: sha256=<hex hmac of body>
+//! Stripe: Stripe-Signature: t=<unix>,v1=<hex hmac of "{t}.{body}">
+//! Slack:  X-Slack-Signature: v0=<hex hmac of "v0:{ts}:{body}">
+//!         X-Slack-Request-Timestamp: <unix>
+//! ```
+
+use crate::response::plain_text;
+use crate::session::now_secs;
+use crate::{AuthRequest, AuthResult, HttpAuthProvider};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use localup_proto::{HmacAlgorithm, SignatureEncoding, WebhookSignatureConfig, WebhookTimestamp};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use tracing::debug;
+
+/// Webhook signature authentication provider
+pub struct WebhookSignatureProvider {
+    config: WebhookSignatureConfig,
+}
+
+impl WebhookSignatureProvider {
+    /// Create a new webhook signature provider
+    ///
+    /// # Example
+    /// ```
+    /// use localup_http_auth::WebhookSignatureProvider;
+    /// use localup_proto::WebhookSignatureConfig;
+    ///
+    /// let provider = WebhookSignatureProvider::new(WebhookSignatureConfig::github("secret"));
+    /// ```
+    pub fn new(config: WebhookSignatureConfig) -> Self {
+        Self { config }
+    }
+
+    /// Verify the signature of a request with the given headers and body
+    pub fn verify(&self, headers: &[(String, String)], body: &[u8]) -> bool {
+        let Some(header) = header_value(headers, &self.config.header_name) else {
+            debug!(
+                "Webhook signature header {} missing",
+                self.config.header_name
+            );
+            return false;
+        };
+
+        let timestamp = match &self.config.timestamp {
+            Some(source) => match self.timestamp(source, headers, header) {
+                Some(timestamp) => Some(timestamp),
+                None => return false,
+            },
+            None => None,
+        };
+
+        let payload = self.signed_payload(timestamp.unwrap_or_default(), body);
+        let signatures: Vec<Vec<u8>> = self
+            .signatures(header)
+            .into_iter()
+            .filter_map(|s| self.decode(s))
+            .collect();
+
+        let valid = self.config.secrets.iter().any(|secret| {
+            signatures
+                .iter()
+                .any(|signature| self.verify_mac(secret.as_bytes(), &payload, signature))
+        });
+        if !valid {
+            debug!("Webhook signature mismatch");
+        }
+        valid
+    }
+
+    fn authenticate_with_body(&self, headers: &[(String, String)], body: &[u8]) -> AuthResult {
+        if self.verify(headers, body) {
+            AuthResult::Authenticated
+        } else {
+            AuthResult::Unauthorized(self.unauthorized_response())
+        }
+    }
+
+    /// Read and check the signing timestamp
+    fn timestamp<'a>(
+        &self,
+        source: &WebhookTimestamp,
+        headers: &'a [(String, String)],
+        signature_header: &'a str,
+    ) -> Option<&'a str> {
+        let value = match source {
+            WebhookTimestamp::Header(name) => header_value(headers, name),
+            WebhookTimestamp::SignatureField(key) => fields(signature_header, key).first().copied(),
+        }?;
+
+        let tolerance = self.config.timestamp_tolerance_secs;
+        if tolerance > 0 {
+            let sent: u64 = value.parse().ok()?;
+            if now_secs().abs_diff(sent) > tolerance {
+                debug!("Webhook timestamp {} outside tolerance", sent);
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    /// Get the candidate signatures from the signature header
+    fn signatures<'a>(&self, header: &'a str) -> Vec<&'a str> {
+        let prefix = self.config.signature_prefix.as_str();
+        let values: Vec<&str> = match &self.config.signature_field {
+            Some(key) => fields(header, key),
+            None => vec![header.trim()],
+        };
+        values
+            .into_iter()
+            .filter_map(|v| v.strip_prefix(prefix))
+            .collect()
+    }
+
+    fn signed_payload(&self, timestamp: &str, body: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(body.len() + 32);
+        for (i, part) in self.config.signed_payload.split("{body}").enumerate() {
+            if i > 0 {
+                payload.extend_from_slice(body);
+            }
+            payload.extend_from_slice(part.replace("{timestamp}", timestamp).as_bytes());
+        }
+        payload
+    }
+
+    fn decode(&self, signature: &str) -> Option<Vec<u8>> {
+        match self.config.encoding {
+            SignatureEncoding::Hex => hex::decode(signature).ok(),
+            SignatureEncoding::Base64 => BASE64.decode(signature).ok(),
+        }
+    }
+
+    fn verify_mac(&self, key: &[u8], payload: &[u8], signature: &[u8]) -> bool {
+        // verify_slice compares in constant time
+        match self.config.algorithm {
+            HmacAlgorithm::Sha1 => mac::<Hmac<Sha1>>(key, payload).verify_slice(signature),
+            HmacAlgorithm::Sha256 => mac::<Hmac<Sha256>>(key, payload).verify_slice(signature),
+            HmacAlgorithm::Sha512 => mac::<Hmac<Sha512>>(key, payload).verify_slice(signature),
+        }
+        .is_ok()
+    }
+}
+
+fn mac<M: Mac + hmac::digest::KeyInit>(key: &[u8], payload: &[u8]) -> M {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(payload);
+    mac
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Values of `key=value` fields with the given key in a comma-separated list
+fn fields<'a>(header: &'a str, key: &str) -> Vec<&'a str> {
+    header
+        .split(',')
+        .filter_map(|field| field.trim().split_once('='))
+        .filter(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+        .collect()
+}
+
+#[async_trait]
+impl HttpAuthProvider for WebhookSignatureProvider {
+    fn authenticate(&self, headers: &[(String, String)]) -> AuthResult {
+        // Without a body, only signatures over an empty body can match
+        self.authenticate_with_body(headers, &[])
+    }
+
+    async fn authenticate_request(&self, request: &AuthRequest) -> AuthResult {
+        self.authenticate_with_body(&request.headers, &request.body)
+    }
+
+    fn body_limit(&self) -> Option<usize> {
+        Some(usize::try_from(self.config.max_body_bytes).unwrap_or(usize::MAX))
+    }
+
+    fn unauthorized_response(&self) -> Vec<u8> {
+        plain_text(401, "Unauthorized", "Invalid webhook signature\n")
+    }
+
+    fn auth_type(&self) -> &'static str {
+        "webhook-signature"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = br#"{"action":"opened"}"#;
+
+    fn sign_hex(secret: &str, payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(payload);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn headers(pairs: &[(&str, String)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_github_signature() {
+        let provider = WebhookSignatureProvider::new(WebhookSignatureConfig::github("gh-secret"));
+        let signature = format!("sha256={}", sign_hex("gh-secret", BODY));
+
+        assert!(provider.verify(&headers(&[("x-hub-signature-256", signature)]), BODY));
+        assert!(!provider.verify(
+            &headers(&[(
+                "X-Hub-Signature-256",
+                format!("sha256={}", sign_hex("other", BODY))
+            )]),
+            BODY
+        ));
+        assert!(!provider.verify(&[], BODY));
+    }
+
+    #[test]
+    fn test_tampered_body_rejected() {
+        let provider = WebhookSignatureProvider::new(WebhookSignatureConfig::github("gh-secret"));
+        let signature = format!("sha256={}", sign_hex("gh-secret", BODY));
+
+        assert!(!provider.verify(
+            &headers(&[("X-Hub-Signature-256", signature)]),
+            br#"{"action":"closed"}"#
+        ));
+    }
+
+    #[test]
+    fn test_stripe_signature_with_multiple_candidates() {
+        let provider = WebhookSignatureProvider::new(WebhookSignatureConfig::stripe("whsec"));
+        let t = now_secs().to_string();
+        let payload = [t.as_bytes(), b".", BODY].concat();
+        let header = format!(
+            "t={},v1={},v1={},v0=ignored",
+            t,
+            sign_hex("old-secret", &payload),
+            sign_hex("whsec", &payload)
+        );
+
+        assert!(provider.verify(&headers(&[("Stripe-Signature", header)]), BODY));
+    }
+
+    #[test]
+    fn test_stale_timestamp_rejected() {
+        let provider = WebhookSignatureProvider::new(WebhookSignatureConfig::slack("slack"));
+        let t = (now_secs() - 600).to_string();
+        let payload = [b"v0:", t.as_bytes(), b":", BODY].concat();
+        let signed = headers(&[
+            (
+                "X-Slack-Signature",
+                format!("v0={}", sign_hex("slack", &payload)),
+            ),
+            ("X-Slack-Request-Timestamp", t),
+        ]);
+
+        assert!(!provider.verify(&signed, BODY));
+
+        let mut config = WebhookSignatureConfig::slack("slack");
+        config.timestamp_tolerance_secs = 0;
+        assert!(WebhookSignatureProvider::new(config).verify(&signed, BODY));
+    }
+
+    #[test]
+    fn test_base64_sha1_signature() {
+        let mut config = WebhookSignatureConfig::new("X-Signature", "key");
+        config.algorithm = HmacAlgorithm::Sha1;
+        config.encoding = SignatureEncoding::Base64;
+        let provider = WebhookSignatureProvider::new(config);
+
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"key").unwrap();
+        mac.update(BODY);
+        let signature = BASE64.encode(mac.finalize().into_bytes());
+
+        assert!(provider.verify(&headers(&[("X-Signature", signature)]), BODY));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_request_uses_body() {
+        let provider = WebhookSignatureProvider::new(WebhookSignatureConfig::github("gh-secret"));
+        let raw = format!(
+            "POST /hook HTTP/1.1\r\nX-Hub-Signature-256: sha256={}\r\nContent-Length: {}\r\n\r\n",
+            sign_hex("gh-secret", BODY),
+            BODY.len()
+        );
+        let request = AuthRequest::parse(&[raw.as_bytes(), BODY].concat(), true);
+
+        assert!(provider
+            .authenticate_request(&request)
+            .await
+            .is_authenticated());
+        assert_eq!(provider.body_limit(), Some(1024 * 1024));
+    }
+}
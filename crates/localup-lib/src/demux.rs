@@ -0,0 +1,268 @@
+//! Front listener for single-port relays
+//!
+//! Accepts TCP connections on the relay's public port and peeks at the TLS ClientHello
+//! without consuming it. The untouched socket then goes to the listener its ALPN belongs
+//! to: the WebSocket (`localup-ws-v1`) or HTTP/2 (`localup-h2-v1`) control-plane
+//! transports, or the HTTPS ingress for everything else (`h2`, `http/1.1`, no ALPN).
+//!
+//! Routing has to happen on the ClientHello because everything after it, including the
+//! WebSocket upgrade path, is encrypted. The WebSocket listener still checks its path once
+//! the handshake completes.
+
+use localup_router::{ClientHelloInfo, SniRouter, SniRouterError, MAX_CLIENT_HELLO_SIZE};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// ALPN offered by the WebSocket transport
+const WEBSOCKET_ALPN: &str = "localup-ws-v1";
+
+/// ALPN offered by the HTTP/2 transport
+const H2_ALPN: &str = "localup-h2-v1";
+
+/// How long a client may take to send its ClientHello
+const CLIENT_HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay between peeks while a ClientHello is still arriving
+const PEEK_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Connections queued per listener before the front listener waits
+const LANE_CAPACITY: usize = 64;
+
+type Connection = (TcpStream, SocketAddr);
+
+/// Listener a connection is handed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// WebSocket control-plane transport
+    WebSocket,
+    /// HTTP/2 control-plane transport
+    H2,
+    /// HTTPS ingress
+    Https,
+}
+
+impl Lane {
+    /// Pick the listener for a ClientHello (`None` if the client did not speak TLS)
+    pub(crate) fn classify(hello: Option<&ClientHelloInfo>) -> Self {
+        let Some(hello) = hello else {
+            return Lane::Https;
+        };
+        if hello.alpn_protocols.iter().any(|p| p == WEBSOCKET_ALPN) {
+            Lane::WebSocket
+        } else if hello.alpn_protocols.iter().any(|p| p == H2_ALPN) {
+            Lane::H2
+        } else {
+            Lane::Https
+        }
+    }
+}
+
+/// Accepts connections on one port and dispatches them to per-protocol listeners
+#[derive(Clone)]
+pub struct FrontListener {
+    bind_addr: SocketAddr,
+    websocket: Option<mpsc::Sender<Connection>>,
+    h2: Option<mpsc::Sender<Connection>>,
+    https: Option<mpsc::Sender<Connection>>,
+}
+
+impl FrontListener {
+    /// Create a front listener for `bind_addr`; nothing is bound until [`run`](Self::run)
+    pub fn new(bind_addr: SocketAddr) -> Self {
+        Self {
+            bind_addr,
+            websocket: None,
+            h2: None,
+            https: None,
+        }
+    }
+
+    /// Address of the shared port
+    pub fn bind_addr(&self) -> SocketAddr {
+        self.bind_addr
+    }
+
+    /// Receive the connections classified as `lane`
+    pub fn lane(&mut self, lane: Lane) -> mpsc::Receiver<Connection> {
+        let (tx, rx) = mpsc::channel(LANE_CAPACITY);
+        match lane {
+            Lane::WebSocket => self.websocket = Some(tx),
+            Lane::H2 => self.h2 = Some(tx),
+            Lane::Https => self.https = Some(tx),
+        }
+        rx
+    }
+
+    fn sender(&self, lane: Lane) -> Option<&mpsc::Sender<Connection>> {
+        match lane {
+            Lane::WebSocket => self.websocket.as_ref(),
+            Lane::H2 => self.h2.as_ref(),
+            Lane::Https => self.https.as_ref(),
+        }
+        // Transports that are not enabled fall back to the HTTPS ingress
+        .or(self.https.as_ref())
+    }
+
+    /// Bind the shared port and dispatch connections until the task is cancelled
+    pub async fn run(self) -> std::io::Result<()> {
+        let listener = TcpListener::bind(self.bind_addr).await?;
+        info!("Single-port front listener on {}", self.bind_addr);
+
+        loop {
+            let (stream, peer_addr) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Front listener accept error: {}", e);
+                    continue;
+                }
+            };
+
+            let front = self.clone();
+            tokio::spawn(async move {
+                let hello = tokio::time::timeout(CLIENT_HELLO_TIMEOUT, peek_client_hello(&stream))
+                    .await
+                    .ok()
+                    .and_then(|r| r.ok())
+                    .flatten();
+                let lane = Lane::classify(hello.as_ref());
+                debug!("Front listener: {} -> {:?}", peer_addr, lane);
+
+                match front.sender(lane) {
+                    Some(sender) => {
+                        if sender.send((stream, peer_addr)).await.is_err() {
+                            debug!("Listener for {:?} is gone, dropping {}", lane, peer_addr);
+                        }
+                    }
+                    None => debug!("No listener for {:?}, dropping {}", lane, peer_addr),
+                }
+            });
+        }
+    }
+}
+
+/// Parse the ClientHello from the socket's receive buffer without consuming it
+async fn peek_client_hello(stream: &TcpStream) -> std::io::Result<Option<ClientHelloInfo>> {
+    let mut buf = vec![0u8; MAX_CLIENT_HELLO_SIZE];
+    let mut last_len = 0;
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        match SniRouter::parse_client_hello(&buf[..n]) {
+            Ok(hello) => return Ok(Some(hello)),
+            Err(SniRouterError::Incomplete) if n < MAX_CLIENT_HELLO_SIZE => {
+                // peek returns immediately while data is buffered; wait for more to arrive
+                if n == last_len {
+                    tokio::time::sleep(PEEK_RETRY_DELAY).await;
+                }
+                last_len = n;
+            }
+            Err(_) => return Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(alpn: &[&str]) -> ClientHelloInfo {
+        ClientHelloInfo {
+            server_name: Some("relay.example.com".to_string()),
+            alpn_protocols: alpn.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            Lane::classify(Some(&hello(&["localup-ws-v1"]))),
+            Lane::WebSocket
+        );
+        assert_eq!(
+            Lane::classify(Some(&hello(&["h2", "localup-h2-v1"]))),
+            Lane::H2
+        );
+        assert_eq!(
+            Lane::classify(Some(&hello(&["h2", "http/1.1"]))),
+            Lane::Https
+        );
+        assert_eq!(Lane::classify(Some(&hello(&[]))), Lane::Https);
+        assert_eq!(Lane::classify(None), Lane::Https);
+    }
+
+    #[test]
+    fn test_disabled_lane_falls_back_to_https() {
+        let mut front = FrontListener::new("127.0.0.1:0".parse().unwrap());
+        let _https = front.lane(Lane::Https);
+        assert!(front.sender(Lane::WebSocket).is_some());
+
+        let mut front = FrontListener::new("127.0.0.1:0".parse().unwrap());
+        let _ws = front.lane(Lane::WebSocket);
+        assert!(front.sender(Lane::H2).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_peek_leaves_data_unread() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        assert!(peek_client_hello(&server).await.unwrap().is_none());
+
+        let mut buf = [0u8; 3];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"GET");
+    }
+
+    #[tokio::test]
+    async fn test_websocket_client_reaches_websocket_lane() {
+        use localup_transport::{TcpIncoming, TransportConnector, TransportListener};
+        use localup_transport_websocket::{WebSocketConfig, WebSocketConnector, WebSocketListener};
+        use std::sync::Arc;
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut front = FrontListener::new(addr);
+        let ws_lane = front.lane(Lane::WebSocket);
+        let mut https_lane = front.lane(Lane::Https);
+        let listener = WebSocketListener::from_incoming(
+            TcpIncoming::channel(ws_lane, addr),
+            Arc::new(WebSocketConfig::server_self_signed().unwrap()),
+        )
+        .unwrap();
+        tokio::spawn(front.run());
+
+        let connector =
+            WebSocketConnector::new(Arc::new(WebSocketConfig::client_insecure())).unwrap();
+        let client = async {
+            // Wait for the front listener to bind
+            for _ in 0..50 {
+                if let Ok(connection) = connector.connect(addr, "localhost").await {
+                    return connection;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("front listener never accepted the WebSocket client");
+        };
+        let (accepted, _client) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(listener.accept(), client)
+        })
+        .await
+        .unwrap();
+
+        accepted.unwrap();
+        assert!(https_lane.try_recv().is_err());
+    }
+}
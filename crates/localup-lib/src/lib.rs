@@ -127,10 +127,26 @@
 //! // Use it with any validator
 //! let validator: Arc<dyn AuthValidator> = Arc::new(ApiKeyValidator::new());
 //! let auth_result = validator.validate(&token).await?;
+//!
+//! // Or let a relay authenticate tunnels with it, falling back to JWTs
+//! let relay = TcpRelayBuilder::new()
+//!     .control_plane("0.0.0.0:4443")?
+//!     .auth_validator(Arc::new(
+//!         AuthValidatorChain::new()
+//!             .with(Arc::new(ApiKeyValidator::new()))
+//!             .with(Arc::new(JwtValidator::new(b"secret"))),
+//!     ))
+//!     .build()?;
 //! ```
 //!
+//! The `AuthResult` drives what the tunnel may do: `allowed_protocols`,
+//! `allowed_subdomains`, `allowed_domains` and `allowed_ports` limit its endpoints,
+//! `user_id` (or `localup_id`) identifies the owner for sticky domains and ports,
+//! and `max_tunnels` and `tcp_limits` cap connections.
+//!
 //! Built-in validators:
 //! - **JwtValidator**: Validates JWT tokens (implements `AuthValidator`)
+//! - **AuthValidatorChain**: Tries several validators in order
 //! - Custom: API keys, database lookup, OAuth, etc. (implement `AuthValidator`)
 //!
 //! # Architecture
@@ -151,13 +167,15 @@
 
 // Re-export protocol types
 pub use localup_proto::{
-    Endpoint, HttpAuthConfig, Protocol, TunnelConfig as ProtoTunnelConfig, TunnelMessage,
+    AuthPolicy, Endpoint, HttpAuthConfig, JwtAuthConfig, JwtKeySource, MethodRule, OidcConfig,
+    PathRule, Protocol, TunnelConfig as ProtoTunnelConfig, TunnelMessage, WebhookSignatureConfig,
 };
 
 // Re-export HTTP authentication types (for incoming request authentication)
 pub use localup_http_auth::{
-    AuthResult as HttpAuthResult, BasicAuthProvider, BearerTokenProvider, HeaderAuthProvider,
-    HttpAuthProvider, HttpAuthenticator,
+    AuthRequest as HttpAuthRequest, AuthResult as HttpAuthResult, BasicAuthProvider,
+    BearerTokenProvider, HeaderAuthProvider, HttpAuthProvider, HttpAuthenticator, JwtAuthProvider,
+    OidcAuthProvider, PolicyAuthProvider, WebhookSignatureProvider,
 };
 
 // Re-export transport layer
@@ -193,8 +211,9 @@ pub use localup_router::{RouteKey, RouteRegistry, RouteTarget};
 
 // Re-export auth types (for custom authentication)
 pub use localup_auth::{
-    async_trait, Algorithm, AuthError, AuthResult, AuthValidator, DecodingKey, EncodingKey,
-    JwtClaims, JwtError, JwtValidator, Token, TokenError, TokenGenerator, Validation,
+    async_trait, Algorithm, AuthError, AuthResult, AuthValidator, AuthValidatorChain, DecodingKey,
+    EncodingKey, JwtClaims, JwtError, JwtValidator, PortRange, Token, TokenError, TokenGenerator,
+    Validation,
 };
 
 // Re-export certificate types
@@ -217,8 +236,10 @@ pub use localup_relay_db::{
 };
 
 // High-level relay builder API
+mod demux;
 pub mod relay;
 pub mod relay_config;
+pub use demux::{FrontListener, Lane};
 pub use relay::{
     generate_token, HttpsRelayBuilder, SimplePortAllocator, TcpRelayBuilder, TlsRelayBuilder,
     TransportConfigs,
@@ -25,6 +25,20 @@
 //!         .build()?;
 //!     relay.run().await?;
 //!
+//!     // HTTPS, QUIC, WebSocket and HTTP/2 all on port 443
+//!     let relay = HttpsRelayBuilder::new("0.0.0.0:443", "cert.pem", "key.pem")?
+//!         .with_all_transports()
+//!         .single_port()
+//!         .build()?;
+//!     relay.run().await?;
+//!
+//!     // TCP relay authenticating tunnels against your own user system
+//!     let relay = TcpRelayBuilder::new()
+//!         .control_plane("127.0.0.1:4443")?
+//!         .auth_validator(Arc::new(MyApiKeyValidator::new()))
+//!         .build()?;
+//!     relay.run().await?;
+//!
 //!     // TLS relay
 //!     let relay = TlsRelayBuilder::new("127.0.0.1:443")?
 //!         .control_plane("127.0.0.1:4443")?
@@ -36,15 +50,17 @@
 //! }
 //! ```
 
+use crate::demux::{FrontListener, Lane};
 use crate::{
     AgentRegistry, HttpsServer, HttpsServerConfig, JwtClaims, JwtValidator, PendingRequests,
     QuicConfig, QuicListener, RouteRegistry, TlsServer, TlsServerConfig, TransportListener,
     TunnelConnectionManager, TunnelHandler,
 };
 use chrono::Duration;
-use localup_control::{PortAllocator, TcpProxySpawner};
+use localup_control::{async_trait, PortAllocator, TcpProxySpawner};
 use localup_proto::ProtocolDiscoveryResponse;
 use localup_server_tcp_proxy::{TcpProxyServer, TcpProxyServerConfig};
+use localup_transport::TcpIncoming;
 use localup_transport_h2::{H2Config, H2Listener};
 use localup_transport_websocket::{WebSocketConfig, WebSocketListener};
 use std::collections::HashMap;
@@ -122,8 +138,9 @@ impl SimplePortAllocator {
     }
 }
 
+#[async_trait]
 impl PortAllocator for SimplePortAllocator {
-    fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String> {
+    async fn allocate(&self, localup_id: &str, requested_port: Option<u16>) -> Result<u16, String> {
         let mut allocations = self.allocations.lock().unwrap();
 
         // If already allocated for this tunnel, return existing port
@@ -157,7 +174,7 @@ impl PortAllocator for SimplePortAllocator {
         Ok(port)
     }
 
-    fn deallocate(&self, localup_id: &str) {
+    async fn deallocate(&self, localup_id: &str) {
         if let Ok(mut allocations) = self.allocations.lock() {
             allocations.remove(localup_id);
         }
@@ -281,8 +298,10 @@ pub struct RelayBuilder<P> {
     domain_provider: Option<Arc<dyn crate::DomainProvider>>,
     certificate_provider: Option<Arc<dyn crate::CertificateProvider>>,
     port_allocator: Option<Arc<dyn localup_control::PortAllocator>>,
+    auth_validator: Option<Arc<dyn crate::AuthValidator>>,
     // Transport configurations
     transport_configs: TransportConfigs,
+    single_port: bool,
     _marker: std::marker::PhantomData<P>,
 }
 
@@ -323,13 +342,65 @@ impl RelayBuilder<Https> {
             domain_provider: None,
             certificate_provider: None,
             port_allocator: None,
+            auth_validator: None,
             transport_configs: TransportConfigs::quic_only(),
+            single_port: false,
             _marker: std::marker::PhantomData,
         })
     }
 
+    /// Serve the control plane on the HTTPS address
+    ///
+    /// QUIC binds UDP on the HTTPS port, and a front listener on the TCP port splits
+    /// connections by the ALPN in their ClientHello: the WebSocket and HTTP/2 transports
+    /// get theirs, everything else goes to the HTTPS server. Replaces any address given to
+    /// [`control_plane`](Self::control_plane).
+    ///
+    /// The relay has no HTTP/3 ingress yet, so the UDP port only carries the QUIC control
+    /// plane (ALPN `localup-v1`).
+    pub fn single_port(mut self) -> Self {
+        self.single_port = true;
+        self
+    }
+
     /// Build the HTTPS relay
-    pub fn build(self) -> Result<Relay, RelayBuilderError> {
+    pub fn build(mut self) -> Result<Relay, RelayBuilderError> {
+        if self.single_port {
+            let Some(ProtocolSpecificConfig::Https(ref https_cfg)) = self.protocol_config else {
+                unreachable!("HTTPS builder always has an HTTPS config");
+            };
+            let bind_addr = https_cfg.bind_addr.clone();
+            let port = bind_addr
+                .parse::<SocketAddr>()
+                .map_err(|_| {
+                    RelayBuilderError::ParseError(format!(
+                        "Invalid HTTPS bind address: {}",
+                        bind_addr
+                    ))
+                })?
+                .port();
+
+            let transports = &self.transport_configs;
+            if [
+                transports.quic_port,
+                transports.websocket_port,
+                transports.h2_port,
+            ]
+            .iter()
+            .flatten()
+            .any(|&p| p != port)
+            {
+                return Err(RelayBuilderError::ConfigError(
+                    "Single-port relay cannot use separate transport ports".to_string(),
+                ));
+            }
+
+            self.control_plane_config = Some(ControlPlaneConfig {
+                bind_addr,
+                domain: self.domain.clone(),
+                transports: self.transport_configs.clone(),
+            });
+        }
         self.build_internal()
     }
 }
@@ -355,7 +426,9 @@ impl RelayBuilder<Tcp> {
             domain_provider: None,
             certificate_provider: None,
             port_allocator: None,
+            auth_validator: None,
             transport_configs: TransportConfigs::quic_only(),
+            single_port: false,
             _marker: std::marker::PhantomData,
         }
     }
@@ -422,7 +495,9 @@ impl RelayBuilder<Tls> {
             domain_provider: None,
             certificate_provider: None,
             port_allocator: None,
+            auth_validator: None,
             transport_configs: TransportConfigs::quic_only(),
+            single_port: false,
             _marker: std::marker::PhantomData,
         })
     }
@@ -571,6 +646,18 @@ impl<P> RelayBuilder<P> {
         self
     }
 
+    /// Authenticate tunnels with a custom validator
+    ///
+    /// Replaces the JWT secret check. The validator's [`AuthResult`](crate::AuthResult)
+    /// decides which protocols, hostnames and ports a tunnel may use, identifies its
+    /// owner for sticky domains and ports, and sets its tunnel and connection limits.
+    /// Use an [`AuthValidatorChain`](crate::AuthValidatorChain) to accept several kinds
+    /// of tokens.
+    pub fn auth_validator(mut self, validator: Arc<dyn crate::AuthValidator>) -> Self {
+        self.auth_validator = Some(validator);
+        self
+    }
+
     /// Internal build implementation shared by all protocols
     fn build_internal(self) -> Result<Relay, RelayBuilderError> {
         // Create shared infrastructure
@@ -693,7 +780,7 @@ impl<P> RelayBuilder<P> {
                     })
                 });
 
-            let handler = TunnelHandler::new(
+            let mut handler = TunnelHandler::new(
                 tunnel_manager.clone(),
                 route_registry.clone(),
                 Some(jwt_validator.clone()),
@@ -704,6 +791,9 @@ impl<P> RelayBuilder<P> {
             .with_port_allocator(port_allocator)
             .with_tcp_proxy_spawner(tcp_proxy_spawner)
             .with_domain_provider(domain_provider);
+            if let Some(validator) = self.auth_validator.clone() {
+                handler = handler.with_auth_validator(validator);
+            }
 
             let transport_configs = cp_cfg.transports.clone();
             Some((control_plane_addr, Arc::new(handler), transport_configs))
@@ -721,8 +811,16 @@ impl<P> RelayBuilder<P> {
             cp.transports.to_discovery_response(base_port)
         });
 
+        let front_addr = match (&self.protocol_config, self.single_port) {
+            (Some(ProtocolSpecificConfig::Https(https_cfg)), true) => {
+                https_cfg.bind_addr.parse().ok()
+            }
+            _ => None,
+        };
+
         Ok(Relay {
             https_server: https_server_handle,
+            front_addr,
             tls_server: tls_server_handle,
             control_plane_config,
             route_registry,
@@ -737,6 +835,8 @@ impl<P> RelayBuilder<P> {
 /// A configured and running tunnel relay
 pub struct Relay {
     https_server: Option<HttpsServer>,
+    /// Shared TCP port split between HTTPS and the transports (single-port relays)
+    front_addr: Option<SocketAddr>,
     tls_server: Option<TlsServer>,
     control_plane_config: Option<(SocketAddr, Arc<TunnelHandler>, TransportConfigs)>,
     pub route_registry: Arc<RouteRegistry>,
@@ -771,10 +871,17 @@ impl Relay {
         // Use JoinSet for automatic task cancellation on shutdown
         let mut join_set = tokio::task::JoinSet::new();
 
+        let mut front = self.front_addr.map(FrontListener::new);
+
         // Start HTTPS server if configured
         if let Some(https_server) = self.https_server {
+            let incoming = front.as_mut().map(|f| (f.lane(Lane::Https), f.bind_addr()));
             join_set.spawn(async move {
-                if let Err(e) = https_server.start().await {
+                let result = match incoming {
+                    Some((incoming, addr)) => https_server.serve(incoming, addr).await,
+                    None => https_server.start().await,
+                };
+                if let Err(e) = result {
                     eprintln!("❌ HTTPS server error: {}", e);
                 }
             });
@@ -854,6 +961,9 @@ impl Relay {
                 let cert_path = transport_configs.tls_cert_path.clone();
                 let key_path = transport_configs.tls_key_path.clone();
                 let handler_clone = handler.clone();
+                let incoming = front
+                    .as_mut()
+                    .map(|f| TcpIncoming::channel(f.lane(Lane::WebSocket), ws_addr));
 
                 join_set.spawn(async move {
                     println!(
@@ -878,7 +988,11 @@ impl Relay {
                         }
                     };
 
-                    match WebSocketListener::new(ws_addr, config) {
+                    let listener = match incoming {
+                        Some(incoming) => WebSocketListener::from_incoming(incoming, config),
+                        None => WebSocketListener::new(ws_addr, config),
+                    };
+                    match listener {
                         Ok(listener) => {
                             println!("✅ WebSocket control plane listening on {}", ws_addr);
                             loop {
@@ -914,6 +1028,9 @@ impl Relay {
                 let cert_path = transport_configs.tls_cert_path.clone();
                 let key_path = transport_configs.tls_key_path.clone();
                 let handler_clone = handler.clone();
+                let incoming = front
+                    .as_mut()
+                    .map(|f| TcpIncoming::channel(f.lane(Lane::H2), h2_addr));
 
                 join_set.spawn(async move {
                     println!("🔌 Starting HTTP/2 control plane on {}", h2_addr);
@@ -932,7 +1049,11 @@ impl Relay {
                         }
                     };
 
-                    match H2Listener::new(h2_addr, config) {
+                    let listener = match incoming {
+                        Some(incoming) => H2Listener::from_incoming(incoming, config),
+                        None => H2Listener::new(h2_addr, config),
+                    };
+                    match listener {
                         Ok(listener) => {
                             println!("✅ HTTP/2 control plane listening on {}", h2_addr);
                             loop {
@@ -962,6 +1083,16 @@ impl Relay {
             }
         }
 
+        // Start the front listener once every lane has a receiver
+        if let Some(front) = front {
+            join_set.spawn(async move {
+                println!("🔀 Single-port relay on {}", front.bind_addr());
+                if let Err(e) = front.run().await {
+                    eprintln!("❌ Front listener error: {}", e);
+                }
+            });
+        }
+
         // Wait for shutdown signal (SIGINT from Ctrl+C or SIGTERM from pkill/systemd)
         #[cfg(unix)]
         {
@@ -1083,6 +1214,31 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_single_port_relay() {
+        let relay = HttpsRelayBuilder::new("127.0.0.1:8443", "cert.pem", "key.pem")
+            .unwrap()
+            .with_all_transports()
+            .single_port()
+            .build()
+            .unwrap();
+
+        assert_eq!(relay.front_addr, Some("127.0.0.1:8443".parse().unwrap()));
+        let discovery = relay.protocol_discovery.unwrap();
+        assert!(discovery.transports.iter().all(|t| t.port == 8443));
+    }
+
+    #[test]
+    fn test_single_port_rejects_separate_transport_port() {
+        let result = HttpsRelayBuilder::new("127.0.0.1:8443", "cert.pem", "key.pem")
+            .unwrap()
+            .with_websocket(Some(9443), "/localup")
+            .single_port()
+            .build();
+
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_invalid_control_plane_addr() {
         let result = HttpsRelayBuilder::new("127.0.0.1:443", "cert.pem", "key.pem")
@@ -69,6 +69,9 @@ async fn acceptance_expose_http_server() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("✓ Created tunnel configuration:");
@@ -86,7 +89,7 @@ async fn acceptance_expose_http_server() {
             info!("  - Public URL: {:?}", client.public_url());
 
             // STEP 4: Verify endpoints are assigned
-            let endpoints = client.endpoints();
+            let endpoints = client.public_endpoints();
             assert!(!endpoints.is_empty(), "Should have at least one endpoint");
             info!("✓ Tunnel assigned {} endpoint(s)", endpoints.len());
 
@@ -145,6 +148,7 @@ async fn acceptance_expose_multiple_services() {
             ProtocolConfig::Tcp {
                 local_port: tcp_port,
                 remote_port: Some(9000),
+                hostname: None,
             },
         ],
         auth_token: "test-token-multi-service".to_string(),
@@ -153,6 +157,9 @@ async fn acceptance_expose_multiple_services() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("✓ Configured tunnel for multiple protocols:");
@@ -164,7 +171,7 @@ async fn acceptance_expose_multiple_services() {
         Ok(client) => {
             info!("✓ Multi-service tunnel connected");
             info!("  - Tunnel ID: {}", client.localup_id());
-            info!("  - Endpoints: {}", client.endpoints().len());
+            info!("  - Endpoints: {}", client.public_endpoints().len());
 
             let _ = client.disconnect().await;
         }
@@ -203,6 +210,9 @@ async fn acceptance_configuration_validation() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Testing empty auth token...");
@@ -235,6 +245,9 @@ async fn acceptance_configuration_validation() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Testing privileged port (1)...");
@@ -279,6 +292,9 @@ async fn acceptance_error_handling() {
         connection_timeout: Duration::from_secs(1), // Short timeout
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Testing connection to invalid host with short timeout...");
@@ -334,6 +350,9 @@ async fn acceptance_tunnel_lifecycle() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("  Configuration created successfully");
@@ -345,7 +364,7 @@ async fn acceptance_tunnel_lifecycle() {
             info!("  Tunnel ID: {}", client.localup_id());
 
             // Get tunnel information
-            let endpoints = client.endpoints();
+            let endpoints = client.public_endpoints();
             info!("  Assigned endpoints: {}", endpoints.len());
 
             // Access metrics store (should be empty at start)
@@ -404,6 +423,9 @@ async fn acceptance_regional_selection() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Testing auto region selection...");
@@ -430,6 +452,9 @@ async fn acceptance_regional_selection() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Testing specific region selection (eu-west)...");
@@ -474,6 +499,9 @@ async fn acceptance_subdomain_management() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Requesting subdomain 'myapp' for HTTPS service...");
@@ -527,6 +555,9 @@ async fn acceptance_metrics_monitoring() {
         connection_timeout: Duration::from_secs(30),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("Connecting and accessing metrics...");
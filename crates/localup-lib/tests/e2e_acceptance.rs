@@ -145,6 +145,9 @@ async fn acceptance_e2e_basic_http_tunnel() {
         connection_timeout: Duration::from_secs(10),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("\n✓ Phase 1: SETUP COMPLETE");
@@ -158,7 +161,7 @@ async fn acceptance_e2e_basic_http_tunnel() {
             info!("✓ Tunnel connected successfully!");
             info!("  - Tunnel ID: {}", client.localup_id());
             info!("  - Public URL: {:?}", client.public_url());
-            info!("  - Endpoints: {}", client.endpoints().len());
+            info!("  - Endpoints: {}", client.public_endpoints().len());
 
             info!("\n✓ Phase 3: TUNNEL ACTIVE");
             info!("  Local service is now exposed publicly");
@@ -232,6 +235,7 @@ async fn acceptance_e2e_multiple_protocols() {
             ProtocolConfig::Tcp {
                 local_port: tcp_port,
                 remote_port: Some(9000),
+                hostname: None,
             },
         ],
         auth_token: "test-token-multi".to_string(),
@@ -240,6 +244,9 @@ async fn acceptance_e2e_multiple_protocols() {
         connection_timeout: Duration::from_secs(10),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("\n✓ Tunnel configured for:");
@@ -250,7 +257,7 @@ async fn acceptance_e2e_multiple_protocols() {
         Ok(client) => {
             info!("\n✓ Multi-protocol tunnel connected!");
             info!("  - Tunnel ID: {}", client.localup_id());
-            info!("  - Endpoints: {}", client.endpoints().len());
+            info!("  - Endpoints: {}", client.public_endpoints().len());
 
             tokio::time::sleep(Duration::from_millis(500)).await;
 
@@ -298,6 +305,9 @@ async fn acceptance_e2e_connection_lifecycle() {
         connection_timeout: Duration::from_secs(10),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     info!("\n[1/5] INITIALIZATION");
@@ -311,7 +321,7 @@ async fn acceptance_e2e_connection_lifecycle() {
             info!("\n[3/5] VERIFYING");
             info!("      ✓ Tunnel ID: {}", client.localup_id());
             info!("      ✓ Public URL: {:?}", client.public_url());
-            info!("      ✓ Endpoints: {}", client.endpoints().len());
+            info!("      ✓ Endpoints: {}", client.public_endpoints().len());
 
             let _ = client.metrics();
             info!("      ✓ Metrics initialized");
@@ -368,6 +378,9 @@ async fn acceptance_e2e_error_recovery() {
         connection_timeout: Duration::from_secs(1),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     match TunnelClient::connect(config).await {
@@ -395,6 +408,9 @@ async fn acceptance_e2e_error_recovery() {
         connection_timeout: Duration::from_secs(5),
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
+        ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     match TunnelClient::connect(config).await {
@@ -232,12 +232,17 @@ async fn test_sni_multi_tenant_api_workflow() {
             "api-001.company.com".to_string(),
             "*.local.company.com".to_string(),
         ],
+        http_port: None,
+        terminate: false,
+        reencrypt: false,
+        alpn: vec![],
     };
 
     match tls_config {
         ProtocolConfig::Tls {
             local_port,
             sni_hostnames,
+            ..
         } => {
             assert_eq!(local_port, 3443);
             assert_eq!(sni_hostnames.len(), 2);
@@ -8,6 +8,7 @@ pub mod discovery;
 pub mod ip_filter;
 pub mod messages;
 pub mod mux;
+pub mod relay_directory;
 
 pub use codec::{CodecError, TunnelCodec};
 pub use discovery::{
@@ -1,6 +1,8 @@
 //! Protocol message types
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 /// Main tunnel protocol message enum
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -237,6 +239,10 @@ pub enum Protocol {
     Tls {
         port: u16,
         sni_patterns: Vec<String>,
+        /// ALPN protocols this tunnel serves (e.g. "h2", "mqtt")
+        /// Empty routes every connection for the SNI patterns regardless of ALPN
+        #[serde(default)]
+        alpn: Vec<String>,
     },
     /// HTTP tunnel - subdomain is optional (auto-generated if None)
     /// If custom_domain is set, it takes precedence over subdomain
@@ -254,6 +260,23 @@ pub enum Protocol {
         #[serde(default)]
         custom_domain: Option<String>,
     },
+    /// TLS tunnel with SNI routing, terminated at the relay
+    /// The relay completes the handshake with its own certificates (custom
+    /// domain or ACME) and forwards plaintext through the tunnel. With
+    /// `reencrypt` the client opens a new TLS connection to the local service.
+    TlsTerminated {
+        port: u16,
+        sni_patterns: Vec<String>,
+        #[serde(default)]
+        reencrypt: bool,
+        /// ALPN protocols this tunnel serves, see `Protocol::Tls`
+        #[serde(default)]
+        alpn: Vec<String>,
+    },
+    /// TCP tunnel reached by hostname through the relay's HTTP CONNECT /
+    /// SOCKS5 listener instead of a dedicated public port
+    /// A hostname without dots is treated as a subdomain of the relay domain.
+    TcpNamed { hostname: String },
 }
 
 /// Tunnel endpoint information
@@ -269,14 +292,18 @@ pub struct Endpoint {
 /// This is extensible to support different authentication methods:
 /// - Basic: HTTP Basic Auth (username:password)
 /// - BearerToken: Validate specific header token
-/// - OAuth/OIDC: (future) OpenID Connect
+/// - Oidc: OpenID Connect login handled by the relay
+/// - WebhookSignature: HMAC signature of the request body
+/// - Policy: per-path/per-method combinations of the above
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum HttpAuthConfig {
     /// No authentication required
     #[default]
     None,
     /// HTTP Basic Authentication
-    /// Credentials are "username:password" pairs
+    /// Credentials are "username:password" pairs; the password may be an
+    /// Argon2 or bcrypt hash (see `localup config hash-password`)
     Basic { credentials: Vec<String> },
     /// Bearer token in Authorization header
     /// Validates that the header matches one of the provided tokens
@@ -287,8 +314,480 @@ pub enum HttpAuthConfig {
         header_name: String,
         values: Vec<String>,
     },
-    // Future: OAuth/OIDC configuration would go here
-    // Oidc { provider_url: String, client_id: String, ... }
+    /// OpenID Connect login (authorization-code flow handled by the relay)
+    /// Browsers are redirected to the identity provider and receive a signed
+    /// session cookie after a successful login
+    Oidc(Box<OidcConfig>),
+    /// JWT bearer token validation
+    /// Validates the signature, `iss`, `aud`, `exp` and required claims of a
+    /// token in the Authorization header
+    Jwt(Box<JwtAuthConfig>),
+    /// HMAC webhook signature verification
+    /// The relay buffers the request body and checks its signature before
+    /// forwarding, so unsigned traffic never reaches the local service
+    WebhookSignature(Box<WebhookSignatureConfig>),
+    /// Combination of methods chosen per request (e.g. per path)
+    /// See [`AuthPolicy`]
+    Policy(Box<AuthPolicy>),
+    /// Login page served by the relay, with a signed session cookie
+    /// Browsers are redirected to the login page; clients sending Basic or
+    /// bearer credentials are checked directly
+    LoginForm(Box<LoginFormConfig>),
+}
+
+/// Authentication policy tree for [`HttpAuthConfig::Policy`]
+///
+/// Policies combine single methods, so that for example `/webhooks/*` is
+/// open, `/admin/*` requires Basic auth from an allowed IP, and everything
+/// else requires a bearer token.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum AuthPolicy {
+    /// Allow the request without authentication
+    Allow,
+    /// Reject the request with 403 Forbidden
+    Deny,
+    /// Require a single authentication method
+    Auth(HttpAuthConfig),
+    /// Require the client IP to match one of these addresses or CIDR ranges
+    IpAllowlist(Vec<String>),
+    /// Pass if any policy passes; when all fail, the response of the last
+    /// one is returned, so interactive methods (e.g. OIDC) should come last
+    AnyOf(Vec<AuthPolicy>),
+    /// Pass if every policy passes; identity headers from all are forwarded
+    AllOf(Vec<AuthPolicy>),
+    /// Apply the policy of the first rule matching the request path; requests
+    /// matching no rule are denied
+    PathMatch(Vec<PathRule>),
+    /// Apply the policy of the first rule matching the request method;
+    /// requests matching no rule are denied
+    MethodMatch(Vec<MethodRule>),
+}
+
+/// Rule of an [`AuthPolicy::PathMatch`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PathRule {
+    /// Path patterns: an exact path, a prefix ending in `*` (`/admin/*` also
+    /// matches `/admin`), or `*` for every path
+    pub paths: Vec<String>,
+    /// Policy applied to matching requests
+    pub policy: AuthPolicy,
+}
+
+/// Rule of an [`AuthPolicy::MethodMatch`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MethodRule {
+    /// HTTP methods (case-insensitive), or `*` for every method
+    pub methods: Vec<String>,
+    /// Policy applied to matching requests
+    pub policy: AuthPolicy,
+}
+
+/// HMAC hash function used for webhook signatures
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum HmacAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// Encoding of a webhook signature in its header
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+/// Where a webhook's signing timestamp is sent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum WebhookTimestamp {
+    /// A separate header (e.g. Slack's "X-Slack-Request-Timestamp")
+    Header(String),
+    /// A `key=value` field of the signature header (e.g. Stripe's "t")
+    SignatureField(String),
+}
+
+/// HMAC webhook signature configuration for [`HttpAuthConfig::WebhookSignature`]
+///
+/// Use [`WebhookSignatureConfig::github`], [`WebhookSignatureConfig::stripe`]
+/// or [`WebhookSignatureConfig::slack`] for the common providers.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct WebhookSignatureConfig {
+    /// Signing secrets; a signature made with any of them is accepted, so a
+    /// new secret can be added before the old one is retired
+    pub secrets: Vec<String>,
+    /// Header carrying the signature (e.g. "X-Hub-Signature-256")
+    pub header_name: String,
+    /// HMAC hash function
+    pub algorithm: HmacAlgorithm,
+    /// Encoding of the signature
+    pub encoding: SignatureEncoding,
+    /// Prefix before the encoded signature (e.g. "sha256=")
+    #[serde(default)]
+    pub signature_prefix: String,
+    /// When set, the signature header is a comma-separated list of
+    /// `key=value` fields and signatures are read from fields with this key
+    /// (e.g. Stripe's "v1")
+    #[serde(default)]
+    pub signature_field: Option<String>,
+    /// Timestamp included in the signed payload, if any
+    #[serde(default)]
+    pub timestamp: Option<WebhookTimestamp>,
+    /// Layout of the signed payload; `{timestamp}` and `{body}` are replaced
+    /// with the request timestamp and raw body (e.g. "v0:{timestamp}:{body}")
+    #[serde(default = "WebhookSignatureConfig::default_signed_payload")]
+    pub signed_payload: String,
+    /// Maximum age (or clock skew) of the timestamp in seconds; 0 disables
+    /// the check
+    #[serde(default = "WebhookSignatureConfig::default_timestamp_tolerance_secs")]
+    pub timestamp_tolerance_secs: u64,
+    /// Largest request body the relay buffers for verification; larger
+    /// requests are rejected with 413
+    #[serde(default = "WebhookSignatureConfig::default_max_body_bytes")]
+    pub max_body_bytes: u64,
+}
+
+impl WebhookSignatureConfig {
+    /// Create a configuration for a hex-encoded HMAC-SHA256 of the body
+    pub fn new(header_name: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            secrets: vec![secret.into()],
+            header_name: header_name.into(),
+            algorithm: HmacAlgorithm::Sha256,
+            encoding: SignatureEncoding::Hex,
+            signature_prefix: String::new(),
+            signature_field: None,
+            timestamp: None,
+            signed_payload: Self::default_signed_payload(),
+            timestamp_tolerance_secs: Self::default_timestamp_tolerance_secs(),
+            max_body_bytes: Self::default_max_body_bytes(),
+        }
+    }
+
+    /// GitHub style: `X-Hub-Signature-256: sha256=<hex>` over the body
+    pub fn github(secret: impl Into<String>) -> Self {
+        Self {
+            signature_prefix: "sha256=".to_string(),
+            ..Self::new("X-Hub-Signature-256", secret)
+        }
+    }
+
+    /// Stripe style: `Stripe-Signature: t=<unix>,v1=<hex>` over
+    /// `{timestamp}.{body}`
+    pub fn stripe(secret: impl Into<String>) -> Self {
+        Self {
+            signature_field: Some("v1".to_string()),
+            timestamp: Some(WebhookTimestamp::SignatureField("t".to_string())),
+            signed_payload: "{timestamp}.{body}".to_string(),
+            ..Self::new("Stripe-Signature", secret)
+        }
+    }
+
+    /// Slack style: `X-Slack-Signature: v0=<hex>` over
+    /// `v0:{timestamp}:{body}`, with the timestamp in
+    /// `X-Slack-Request-Timestamp`
+    pub fn slack(secret: impl Into<String>) -> Self {
+        Self {
+            signature_prefix: "v0=".to_string(),
+            timestamp: Some(WebhookTimestamp::Header(
+                "X-Slack-Request-Timestamp".to_string(),
+            )),
+            signed_payload: "v0:{timestamp}:{body}".to_string(),
+            ..Self::new("X-Slack-Signature", secret)
+        }
+    }
+
+    fn default_signed_payload() -> String {
+        "{body}".to_string()
+    }
+
+    fn default_timestamp_tolerance_secs() -> u64 {
+        300
+    }
+
+    fn default_max_body_bytes() -> u64 {
+        1024 * 1024
+    }
+}
+
+/// Source of the keys used to verify JWT signatures
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum JwtKeySource {
+    /// Shared secret for HS256/HS384/HS512
+    Secret(String),
+    /// PEM-encoded RSA, EC or Ed25519 public key
+    PublicKeyPem(String),
+    /// URL of a JWKS document; keys are cached and refetched when a token
+    /// refers to an unknown key ID
+    JwksUrl(String),
+}
+
+/// JWT bearer token configuration for [`HttpAuthConfig::Jwt`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JwtAuthConfig {
+    /// Where verification keys come from
+    pub key: JwtKeySource,
+    /// Accepted signing algorithms (e.g. "RS256"); when empty, all
+    /// algorithms of the key's family are accepted
+    #[serde(default)]
+    pub algorithms: Vec<String>,
+    /// Required `iss` claim
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Accepted `aud` values; the audience is not checked when empty
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    /// Claims that must be present in the token
+    #[serde(default)]
+    pub required_claims: Vec<String>,
+    /// Claims forwarded to the local service, as claim name -> header name
+    /// (e.g. "sub" -> "X-User-Id")
+    #[serde(default)]
+    pub forward_claims: BTreeMap<String, String>,
+    /// Allowed clock skew in seconds when checking `exp` and `nbf`
+    #[serde(default = "JwtAuthConfig::default_leeway_secs")]
+    pub leeway_secs: u64,
+}
+
+impl JwtAuthConfig {
+    /// Create a configuration that only checks the signature and expiry
+    pub fn new(key: JwtKeySource) -> Self {
+        Self {
+            key,
+            algorithms: Vec::new(),
+            issuer: None,
+            audiences: Vec::new(),
+            required_claims: Vec::new(),
+            forward_claims: BTreeMap::new(),
+            leeway_secs: Self::default_leeway_secs(),
+        }
+    }
+
+    fn default_leeway_secs() -> u64 {
+        60
+    }
+}
+
+/// OpenID Connect configuration for [`HttpAuthConfig::Oidc`]
+///
+/// The relay discovers the provider endpoints from
+/// `{issuer_url}/.well-known/openid-configuration` and handles the callback
+/// on the tunnel host itself, so `https://<tunnel-host><callback_path>` must be
+/// registered as a redirect URI with the identity provider.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OidcConfig {
+    /// Issuer URL (must match the `iss` claim of issued ID tokens)
+    pub issuer_url: String,
+    /// OAuth client ID registered with the identity provider
+    pub client_id: String,
+    /// OAuth client secret (also used to verify HS256-signed ID tokens)
+    pub client_secret: String,
+    /// Scopes to request; `openid` is always included
+    #[serde(default = "OidcConfig::default_scopes")]
+    pub scopes: Vec<String>,
+    /// Path on the tunnel host that receives the authorization response
+    #[serde(default = "OidcConfig::default_callback_path")]
+    pub callback_path: String,
+    /// Email addresses allowed to log in (case-insensitive)
+    #[serde(default)]
+    pub allowed_emails: Vec<String>,
+    /// Email domains allowed to log in (e.g. "example.com")
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    /// Groups allowed to log in (matched against `groups_claim`)
+    #[serde(default)]
+    pub allowed_groups: Vec<String>,
+    /// ID token claim carrying group membership
+    #[serde(default = "OidcConfig::default_groups_claim")]
+    pub groups_claim: String,
+    /// Lifetime of the session cookie in seconds
+    #[serde(default = "OidcConfig::default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    /// Secret used to sign session cookies
+    /// If unset, the relay generates a random secret when the tunnel registers,
+    /// so sessions do not survive a reconnect.
+    #[serde(default)]
+    pub cookie_secret: Option<String>,
+}
+
+impl OidcConfig {
+    /// Create a configuration with default scopes, callback path and session lifetime
+    ///
+    /// With no allowlists configured, any user who can log in at the identity
+    /// provider is admitted.
+    pub fn new(
+        issuer_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            issuer_url: issuer_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scopes: Self::default_scopes(),
+            callback_path: Self::default_callback_path(),
+            allowed_emails: Vec::new(),
+            allowed_domains: Vec::new(),
+            allowed_groups: Vec::new(),
+            groups_claim: Self::default_groups_claim(),
+            session_ttl_secs: Self::default_session_ttl_secs(),
+            cookie_secret: None,
+        }
+    }
+
+    fn default_scopes() -> Vec<String> {
+        vec![
+            "openid".to_string(),
+            "email".to_string(),
+            "profile".to_string(),
+        ]
+    }
+
+    fn default_callback_path() -> String {
+        "/_localup/oidc/callback".to_string()
+    }
+
+    fn default_groups_claim() -> String {
+        "groups".to_string()
+    }
+
+    fn default_session_ttl_secs() -> u64 {
+        12 * 60 * 60
+    }
+}
+
+/// Login form configuration for [`HttpAuthConfig::LoginForm`]
+///
+/// The relay serves the login page and logout endpoint on the tunnel host.
+/// After logging in, browsers hold an HttpOnly session cookie instead of
+/// resending credentials, so users can log out again.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LoginFormConfig {
+    /// Accepted "username:password" pairs; the password may be an Argon2 or
+    /// bcrypt hash (see `localup config hash-password`)
+    #[serde(default)]
+    pub credentials: Vec<String>,
+    /// Also accept relay accounts (email and password) of the tunnel owner
+    /// and members of the owner's teams
+    #[serde(default)]
+    pub team_accounts: bool,
+    /// Bearer tokens accepted from non-browser clients
+    #[serde(default)]
+    pub bearer_tokens: Vec<String>,
+    /// Title shown on the login page
+    #[serde(default = "LoginFormConfig::default_title")]
+    pub title: String,
+    /// Path on the tunnel host serving the login page
+    #[serde(default = "LoginFormConfig::default_login_path")]
+    pub login_path: String,
+    /// Path on the tunnel host that ends the session
+    #[serde(default = "LoginFormConfig::default_logout_path")]
+    pub logout_path: String,
+    /// Lifetime of the session cookie in seconds
+    #[serde(default = "LoginFormConfig::default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    /// Secret used to sign session cookies
+    /// If unset, the relay generates a random secret when the tunnel registers,
+    /// so sessions do not survive a reconnect.
+    #[serde(default)]
+    pub cookie_secret: Option<String>,
+}
+
+impl LoginFormConfig {
+    /// Create a configuration accepting the given credentials, with the
+    /// default paths and session lifetime
+    pub fn new(credentials: Vec<String>) -> Self {
+        Self {
+            credentials,
+            team_accounts: false,
+            bearer_tokens: Vec::new(),
+            title: Self::default_title(),
+            login_path: Self::default_login_path(),
+            logout_path: Self::default_logout_path(),
+            session_ttl_secs: Self::default_session_ttl_secs(),
+            cookie_secret: None,
+        }
+    }
+
+    fn default_title() -> String {
+        "Sign in".to_string()
+    }
+
+    fn default_login_path() -> String {
+        "/_localup/login".to_string()
+    }
+
+    fn default_logout_path() -> String {
+        "/_localup/logout".to_string()
+    }
+
+    fn default_session_ttl_secs() -> u64 {
+        12 * 60 * 60
+    }
+}
+
+/// Connection limits for TCP tunnels, enforced by the relay's TCP proxy
+///
+/// `None` means unlimited. Relays combine the limits requested by the client with
+/// those carried in its auth token, keeping the stricter value of each.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TcpLimits {
+    /// Maximum concurrent connections to the tunnel
+    #[serde(default)]
+    pub max_connections: Option<u32>,
+    /// Maximum concurrent connections from one source IP
+    #[serde(default)]
+    pub max_connections_per_ip: Option<u32>,
+    /// Close connections with no traffic in either direction for this long
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    /// Close connections open for longer than this, regardless of traffic
+    #[serde(default)]
+    pub max_lifetime_secs: Option<u64>,
+}
+
+impl TcpLimits {
+    /// Whether no limit is set
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Idle timeout as a `Duration`
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.map(Duration::from_secs)
+    }
+
+    /// Maximum connection lifetime as a `Duration`
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        self.max_lifetime_secs.map(Duration::from_secs)
+    }
+
+    /// Combine with `other`, keeping the stricter value of each limit
+    pub fn capped_by(&self, other: &TcpLimits) -> TcpLimits {
+        fn min<T: Ord + Copy>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        TcpLimits {
+            max_connections: min(self.max_connections, other.max_connections),
+            max_connections_per_ip: min(self.max_connections_per_ip, other.max_connections_per_ip),
+            idle_timeout_secs: min(self.idle_timeout_secs, other.idle_timeout_secs),
+            max_lifetime_secs: min(self.max_lifetime_secs, other.max_lifetime_secs),
+        }
+    }
 }
 
 /// Tunnel configuration
@@ -305,6 +804,9 @@ pub struct TunnelConfig {
     /// HTTP authentication configuration for incoming requests
     #[serde(default)]
     pub http_auth: HttpAuthConfig,
+    /// Connection limits for TCP endpoints
+    #[serde(default)]
+    pub tcp_limits: TcpLimits,
 }
 
 impl Default for TunnelConfig {
@@ -319,6 +821,7 @@ impl Default for TunnelConfig {
             enable_compression: false,
             enable_multiplexing: true,
             http_auth: HttpAuthConfig::None,
+            tcp_limits: TcpLimits::default(),
         }
     }
 }
@@ -380,10 +883,35 @@ impl Region {
     }
 }
 
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Region::UsEast,
+            Region::UsWest,
+            Region::EuWest,
+            Region::EuCentral,
+            Region::AsiaPacific,
+            Region::SouthAmerica,
+        ]
+        .into_iter()
+        .find(|region| region.as_str() == s.to_lowercase())
+        .ok_or_else(|| format!("Unknown region: {}", s))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_region_from_str() {
+        assert_eq!("eu-west".parse::<Region>().unwrap(), Region::EuWest);
+        assert_eq!("US-East".parse::<Region>().unwrap(), Region::UsEast);
+        assert!("mars".parse::<Region>().is_err());
+    }
+
     #[test]
     fn test_message_serialization() {
         let msg = TunnelMessage::Ping { timestamp: 12345 };
@@ -431,12 +959,16 @@ mod tests {
         let protocol = Protocol::Tls {
             port: 443,
             sni_patterns: vec!["api.example.com".to_string()],
+            alpn: vec![],
         };
         let serialized = bincode::serialize(&protocol).unwrap();
         let deserialized: Protocol = bincode::deserialize(&serialized).unwrap();
         assert_eq!(protocol, deserialized);
 
-        if let Protocol::Tls { port, sni_patterns } = deserialized {
+        if let Protocol::Tls {
+            port, sni_patterns, ..
+        } = deserialized
+        {
             assert_eq!(port, 443);
             assert_eq!(sni_patterns.len(), 1);
             assert_eq!(sni_patterns[0], "api.example.com");
@@ -454,12 +986,16 @@ mod tests {
                 "web.example.com".to_string(),
                 "admin.example.com".to_string(),
             ],
+            alpn: vec![],
         };
         let serialized = bincode::serialize(&protocol).unwrap();
         let deserialized: Protocol = bincode::deserialize(&serialized).unwrap();
         assert_eq!(protocol, deserialized);
 
-        if let Protocol::Tls { port, sni_patterns } = deserialized {
+        if let Protocol::Tls {
+            port, sni_patterns, ..
+        } = deserialized
+        {
             assert_eq!(port, 8443);
             assert_eq!(sni_patterns.len(), 3);
             assert_eq!(sni_patterns[0], "api.example.com");
@@ -479,6 +1015,7 @@ mod tests {
                 "*.local-abc123.myapp.dev".to_string(),
                 "specific.domain.com".to_string(),
             ],
+            alpn: vec![],
         };
         let serialized = bincode::serialize(&protocol).unwrap();
         let deserialized: Protocol = bincode::deserialize(&serialized).unwrap();
@@ -499,6 +1036,7 @@ mod tests {
         let protocol = Protocol::Tls {
             port: 443,
             sni_patterns: vec![],
+            alpn: vec![],
         };
         let serialized = bincode::serialize(&protocol).unwrap();
         let deserialized: Protocol = bincode::deserialize(&serialized).unwrap();
@@ -511,6 +1049,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tls_terminated_protocol_roundtrip() {
+        let protocol = Protocol::TlsTerminated {
+            port: 443,
+            sni_patterns: vec!["mqtt.example.com".to_string()],
+            reencrypt: true,
+            alpn: vec![],
+        };
+        let serialized = bincode::serialize(&protocol).unwrap();
+        let deserialized: Protocol = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(protocol, deserialized);
+
+        // Passthrough tunnels keep their wire format
+        let passthrough = Protocol::Tls {
+            port: 443,
+            sni_patterns: vec!["mqtt.example.com".to_string()],
+            alpn: vec![],
+        };
+        assert_ne!(
+            bincode::serialize(&passthrough).unwrap(),
+            bincode::serialize(&protocol).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tcp_named_protocol_roundtrip() {
+        let protocol = Protocol::TcpNamed {
+            hostname: "db.relay.example.com".to_string(),
+        };
+        let serialized = bincode::serialize(&protocol).unwrap();
+        let deserialized: Protocol = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(protocol, deserialized);
+    }
+
     #[test]
     fn test_connect_message_with_tls_protocol() {
         let msg = TunnelMessage::Connect {
@@ -522,6 +1094,7 @@ mod tests {
                     "*.local-rqe59t.dviejo.temps.dev".to_string(),
                     "api.production.com".to_string(),
                 ],
+                alpn: vec![],
             }],
             config: TunnelConfig::default(),
         };
@@ -543,4 +1116,138 @@ mod tests {
             panic!("Expected Connect message");
         }
     }
+
+    #[test]
+    fn test_tunnel_config_with_oidc_auth() {
+        let mut oidc = OidcConfig::new("https://idp.example.com", "client", "secret");
+        oidc.allowed_domains = vec!["example.com".to_string()];
+        let config = TunnelConfig {
+            http_auth: HttpAuthConfig::Oidc(Box::new(oidc)),
+            ..Default::default()
+        };
+
+        let serialized = bincode::serialize(&config).unwrap();
+        let deserialized: TunnelConfig = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+    }
+
+    #[test]
+    fn test_tunnel_config_with_jwt_auth() {
+        let mut jwt = JwtAuthConfig::new(JwtKeySource::JwksUrl(
+            "https://idp.example.com/jwks".to_string(),
+        ));
+        jwt.audiences = vec!["api".to_string()];
+        jwt.forward_claims
+            .insert("sub".to_string(), "X-User-Id".to_string());
+        let config = TunnelConfig {
+            http_auth: HttpAuthConfig::Jwt(Box::new(jwt)),
+            ..Default::default()
+        };
+
+        let serialized = bincode::serialize(&config).unwrap();
+        let deserialized: TunnelConfig = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+    }
+
+    #[test]
+    fn test_tunnel_config_with_login_form() {
+        let mut login = LoginFormConfig::new(vec!["admin:secret".to_string()]);
+        login.team_accounts = true;
+        login.bearer_tokens = vec!["ci-token".to_string()];
+        let config = TunnelConfig {
+            http_auth: HttpAuthConfig::LoginForm(Box::new(login)),
+            ..Default::default()
+        };
+
+        let serialized = bincode::serialize(&config).unwrap();
+        let deserialized: TunnelConfig = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+
+        let parsed: LoginFormConfig = serde_json::from_str(r#"{"credentials": ["a:b"]}"#).unwrap();
+        assert_eq!(parsed, LoginFormConfig::new(vec!["a:b".to_string()]));
+    }
+
+    #[test]
+    fn test_tcp_limits_capped_by() {
+        let requested = TcpLimits {
+            max_connections: Some(100),
+            idle_timeout_secs: Some(600),
+            ..Default::default()
+        };
+        let token = TcpLimits {
+            max_connections: Some(10),
+            max_lifetime_secs: Some(3600),
+            ..Default::default()
+        };
+        let effective = requested.capped_by(&token);
+        assert_eq!(effective.max_connections, Some(10));
+        assert_eq!(effective.max_connections_per_ip, None);
+        assert_eq!(effective.idle_timeout_secs, Some(600));
+        assert_eq!(effective.max_lifetime_secs, Some(3600));
+        assert!(TcpLimits::default().is_unlimited());
+
+        let config = TunnelConfig {
+            tcp_limits: effective,
+            ..Default::default()
+        };
+        let serialized = bincode::serialize(&config).unwrap();
+        let deserialized: TunnelConfig = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+    }
+
+    #[test]
+    fn test_webhook_signature_presets() {
+        let stripe = WebhookSignatureConfig::stripe("whsec_test");
+        assert_eq!(stripe.header_name, "Stripe-Signature");
+        assert_eq!(stripe.signature_field.as_deref(), Some("v1"));
+        assert_eq!(stripe.signed_payload, "{timestamp}.{body}");
+
+        let config = TunnelConfig {
+            http_auth: HttpAuthConfig::WebhookSignature(Box::new(WebhookSignatureConfig::slack(
+                "slack-secret",
+            ))),
+            ..Default::default()
+        };
+        let serialized = bincode::serialize(&config).unwrap();
+        let deserialized: TunnelConfig = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+    }
+
+    #[test]
+    fn test_tunnel_config_with_auth_policy() {
+        let policy = AuthPolicy::PathMatch(vec![
+            PathRule {
+                paths: vec!["/webhooks/*".to_string()],
+                policy: AuthPolicy::Allow,
+            },
+            PathRule {
+                paths: vec!["*".to_string()],
+                policy: AuthPolicy::AnyOf(vec![
+                    AuthPolicy::IpAllowlist(vec!["10.0.0.0/8".to_string()]),
+                    AuthPolicy::Auth(HttpAuthConfig::BearerToken {
+                        tokens: vec!["token".to_string()],
+                    }),
+                ]),
+            },
+        ]);
+        let config = TunnelConfig {
+            http_auth: HttpAuthConfig::Policy(Box::new(policy)),
+            ..Default::default()
+        };
+
+        let serialized = bincode::serialize(&config).unwrap();
+        let deserialized: TunnelConfig = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+    }
+
+    #[test]
+    fn test_oidc_config_defaults_from_json() {
+        let oidc: OidcConfig = serde_json::from_str(
+            r#"{"issuer_url": "https://idp.example.com", "client_id": "c", "client_secret": "s"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(oidc, OidcConfig::new("https://idp.example.com", "c", "s"));
+        assert_eq!(oidc.callback_path, "/_localup/oidc/callback");
+    }
 }
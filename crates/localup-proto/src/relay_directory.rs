@@ -0,0 +1,100 @@
+//! Relay directory types
+//!
+//! The relay directory lists the relays clients can connect to. Clients embed
+//! one at compile time (`relays.yaml`), and the exit node orchestrator serves
+//! a live one built from the relays registered with it, optionally signed as
+//! a compact JWS whose payload is a [`SignedRelayDirectory`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Root relay configuration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayConfig {
+    pub version: u32,
+    pub config: GlobalConfig,
+    pub relays: Vec<RelayInfo>,
+    pub region_groups: Vec<RegionGroup>,
+    pub selection_policies: HashMap<String, SelectionPolicy>,
+}
+
+/// Payload of a signed relay directory
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SignedRelayDirectory {
+    pub directory: RelayConfig,
+    /// When the directory was signed (Unix timestamp)
+    pub iat: i64,
+    /// When clients stop trusting the directory (Unix timestamp)
+    pub exp: i64,
+}
+
+/// Global configuration
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GlobalConfig {
+    pub default_protocol: String,
+    pub connection_timeout: u64,
+    pub health_check_interval: u64,
+}
+
+/// Relay server definition
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayInfo {
+    pub id: String,
+    pub name: String,
+    pub region: String,
+    pub location: Location,
+    pub endpoints: Vec<RelayEndpoint>,
+    pub status: String,
+    pub tags: Vec<String>,
+    /// Load last reported by the relay (only in live directories)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load: Option<RelayLoad>,
+}
+
+/// Geographic location
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Location {
+    pub city: String,
+    pub state: String,
+    pub country: String,
+    pub continent: String,
+}
+
+/// Relay endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayEndpoint {
+    pub protocol: String,
+    pub address: String,
+    pub capacity: u32,
+    pub priority: u32,
+}
+
+/// Load of a relay, reported in its heartbeats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RelayLoad {
+    /// Connected tunnels
+    pub tunnels: u32,
+    /// Connected reverse tunnel agents
+    pub agents: u32,
+}
+
+/// Region group for fallback
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegionGroup {
+    pub name: String,
+    pub regions: Vec<String>,
+    pub fallback_order: Vec<String>,
+}
+
+/// Relay selection policy
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SelectionPolicy {
+    pub prefer_same_region: bool,
+    pub fallback_to_nearest: bool,
+    pub consider_capacity: bool,
+    pub only_active: bool,
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+}
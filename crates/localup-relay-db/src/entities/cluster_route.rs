@@ -0,0 +1,39 @@
+//! ClusterRoute entity recording which relay node serves a route
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "cluster_routes")]
+pub struct Model {
+    /// Route key, e.g. `http:app.example.com` or `tls:db.example.com` (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub route_key: String,
+
+    /// Node the tunnel serving the route is connected to
+    pub node_id: String,
+
+    /// Tunnel serving the route
+    pub localup_id: String,
+
+    /// When the node claimed the route
+    pub claimed_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::relay_node::Entity",
+        from = "Column::NodeId",
+        to = "super::relay_node::Column::NodeId"
+    )]
+    RelayNode,
+}
+
+impl Related<super::relay_node::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RelayNode.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -3,28 +3,55 @@
 pub mod auth_token;
 pub mod captured_request;
 pub mod captured_tcp_connection;
+pub mod cluster_route;
 pub mod custom_domain;
 pub mod domain_challenge;
+pub mod plan;
+pub mod quota;
+pub mod quota_usage;
+pub mod relay_node;
+pub mod revoked_token;
+pub mod share_link;
+pub mod tcp_port_assignment;
 pub mod team;
 pub mod team_member;
+pub mod traffic_rollup;
 pub mod user;
 
 pub use auth_token::Entity as AuthToken;
 pub use captured_request::Entity as CapturedRequest;
 pub use captured_tcp_connection::Entity as CapturedTcpConnection;
+pub use cluster_route::Entity as ClusterRoute;
 pub use custom_domain::Entity as CustomDomain;
 pub use domain_challenge::Entity as DomainChallenge;
+pub use plan::Entity as Plan;
+pub use quota::Entity as Quota;
+pub use quota_usage::Entity as QuotaUsage;
+pub use relay_node::Entity as RelayNode;
+pub use revoked_token::Entity as RevokedToken;
+pub use share_link::Entity as ShareLink;
+pub use tcp_port_assignment::Entity as TcpPortAssignment;
 pub use team::Entity as Team;
 pub use team_member::Entity as TeamMember;
+pub use traffic_rollup::Entity as TrafficRollup;
 pub use user::Entity as User;
 
 pub mod prelude {
     pub use super::auth_token::Entity as AuthToken;
     pub use super::captured_request::Entity as CapturedRequest;
     pub use super::captured_tcp_connection::Entity as CapturedTcpConnection;
+    pub use super::cluster_route::Entity as ClusterRoute;
     pub use super::custom_domain::Entity as CustomDomain;
     pub use super::domain_challenge::Entity as DomainChallenge;
+    pub use super::plan::Entity as Plan;
+    pub use super::quota::Entity as Quota;
+    pub use super::quota_usage::Entity as QuotaUsage;
+    pub use super::relay_node::Entity as RelayNode;
+    pub use super::revoked_token::Entity as RevokedToken;
+    pub use super::share_link::Entity as ShareLink;
+    pub use super::tcp_port_assignment::Entity as TcpPortAssignment;
     pub use super::team::Entity as Team;
     pub use super::team_member::Entity as TeamMember;
+    pub use super::traffic_rollup::Entity as TrafficRollup;
     pub use super::user::Entity as User;
 }
@@ -0,0 +1,55 @@
+//! Plan entity for named sets of quota limits shared by many users and teams
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Name of the plan that applies to accounts without a quota row
+pub const DEFAULT_PLAN: &str = "default";
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "plans")]
+pub struct Model {
+    /// Plan UUID (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// Plan name (unique, e.g. "free", "pro" or "default")
+    #[sea_orm(unique)]
+    pub name: String,
+
+    /// Concurrently connected tunnels (NULL = unlimited)
+    pub max_tunnels: Option<i32>,
+
+    /// Custom domains in use by connected tunnels (NULL = unlimited)
+    pub max_custom_domains: Option<i32>,
+
+    /// Reserved TCP ports (NULL = unlimited)
+    pub max_reserved_ports: Option<i32>,
+
+    /// Bytes in and out per calendar month, UTC (NULL = unlimited)
+    pub monthly_bytes: Option<i64>,
+
+    /// Concurrent visitor connections across all tunnels (NULL = unlimited)
+    pub max_connections: Option<i32>,
+
+    /// When the plan was created
+    pub created_at: ChronoDateTimeUtc,
+
+    /// When the plan was last updated
+    pub updated_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    /// Plan is assigned to users and teams through quotas
+    #[sea_orm(has_many = "super::quota::Entity")]
+    Quotas,
+}
+
+impl Related<super::quota::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Quotas.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,78 @@
+//! Quota entity for the plan and limit overrides of a user or team
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Kind of account a quota or usage row belongs to
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter, DeriveActiveEnum,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+pub enum QuotaSubject {
+    /// Tunnels opened with a personal auth token
+    #[sea_orm(string_value = "user")]
+    User,
+
+    /// Tunnels opened with a team auth token
+    #[sea_orm(string_value = "team")]
+    Team,
+}
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "quotas")]
+pub struct Model {
+    /// Quota UUID (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// Whether `subject_id` is a user or a team
+    pub subject_type: QuotaSubject,
+
+    /// User or team the quota applies to
+    pub subject_id: Uuid,
+
+    /// Plan whose limits apply where this row leaves a limit NULL
+    pub plan_id: Option<Uuid>,
+
+    /// Concurrently connected tunnels (NULL = plan limit)
+    pub max_tunnels: Option<i32>,
+
+    /// Custom domains in use by connected tunnels (NULL = plan limit)
+    pub max_custom_domains: Option<i32>,
+
+    /// Reserved TCP ports (NULL = plan limit)
+    pub max_reserved_ports: Option<i32>,
+
+    /// Bytes in and out per calendar month, UTC (NULL = plan limit)
+    pub monthly_bytes: Option<i64>,
+
+    /// Concurrent visitor connections across all tunnels (NULL = plan limit)
+    pub max_connections: Option<i32>,
+
+    /// When the quota was created
+    pub created_at: ChronoDateTimeUtc,
+
+    /// When the quota was last updated
+    pub updated_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    /// Quota may follow a plan
+    #[sea_orm(
+        belongs_to = "super::plan::Entity",
+        from = "Column::PlanId",
+        to = "super::plan::Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    Plan,
+}
+
+impl Related<super::plan::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Plan.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,37 @@
+//! QuotaUsage entity for the traffic of a user or team in one calendar month
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub use super::quota::QuotaSubject;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "quota_usage")]
+pub struct Model {
+    /// Usage row UUID (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// Whether `subject_id` is a user or a team
+    pub subject_type: QuotaSubject,
+
+    /// User or team the traffic is counted for
+    pub subject_id: Uuid,
+
+    /// Calendar month in UTC ("2026-10")
+    pub period: String,
+
+    /// Bytes from visitors to tunnels
+    pub bytes_in: i64,
+
+    /// Bytes from tunnels to visitors
+    pub bytes_out: i64,
+
+    /// When traffic was last added
+    pub updated_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,35 @@
+//! RelayNode entity for the members of a relay cluster
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "relay_nodes")]
+pub struct Model {
+    /// Node ID chosen by the relay (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub node_id: String,
+
+    /// Address other nodes dial for the inter-node QUIC link
+    pub cluster_addr: String,
+
+    /// When the node joined the cluster
+    pub started_at: ChronoDateTimeUtc,
+
+    /// Last heartbeat; nodes that stop heartbeating are removed with their routes
+    pub heartbeat_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::cluster_route::Entity")]
+    ClusterRoutes,
+}
+
+impl Related<super::cluster_route::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ClusterRoutes.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
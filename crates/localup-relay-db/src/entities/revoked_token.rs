@@ -0,0 +1,36 @@
+//! RevokedToken entity for JWTs that are no longer accepted before they expire
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "revoked_tokens")]
+pub struct Model {
+    /// Revocation UUID (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// The token's `jti` claim
+    #[sea_orm(unique)]
+    pub jti: String,
+
+    /// User the token was issued to, if known
+    pub user_id: Option<Uuid>,
+
+    /// Why the token was revoked (sent to tunnels using it)
+    pub reason: Option<String>,
+
+    /// User who revoked the token
+    pub revoked_by: Option<Uuid>,
+
+    /// When the token expires anyway; the row can be removed after this
+    pub expires_at: Option<ChronoDateTimeUtc>,
+
+    /// When the token was revoked
+    pub revoked_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,71 @@
+//! ShareLink entity for signed, expiring links that grant access to a tunnel
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "share_links")]
+pub struct Model {
+    /// Share link UUID (primary key, embedded in the signed token)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// User who created the link
+    pub user_id: Uuid,
+
+    /// Tunnel the link grants access to
+    #[sea_orm(indexed)]
+    pub localup_id: String,
+
+    /// Path prefix the link is limited to ("/" for the whole tunnel)
+    pub path_prefix: String,
+
+    /// Optional label shown when listing links
+    pub name: Option<String>,
+
+    /// How many times the link may be redeemed (NULL = unlimited)
+    pub max_uses: Option<i32>,
+
+    /// How many times the link has been redeemed
+    pub use_count: i32,
+
+    /// When the link was last redeemed
+    pub last_used_at: Option<ChronoDateTimeUtc>,
+
+    /// When the link expires
+    pub expires_at: ChronoDateTimeUtc,
+
+    /// When the link was revoked (NULL = not revoked)
+    pub revoked_at: Option<ChronoDateTimeUtc>,
+
+    /// When the link was created
+    pub created_at: ChronoDateTimeUtc,
+}
+
+impl Model {
+    /// Whether the link can still grant access (ignoring the use count)
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > chrono::Utc::now()
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    /// Share link belongs to the user who created it
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
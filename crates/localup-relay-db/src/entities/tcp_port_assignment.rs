@@ -0,0 +1,80 @@
+//! TcpPortAssignment entity for TCP ports that stay with their owner across relay restarts
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "tcp_port_assignments")]
+pub struct Model {
+    /// Assignment UUID (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// Public port on the relay
+    #[sea_orm(unique)]
+    pub port: i32,
+
+    /// User who owns the port (NULL for tunnels authenticated without a user)
+    #[sea_orm(indexed)]
+    pub user_id: Option<Uuid>,
+
+    /// Team the port is reserved for (counts against the team's quota)
+    pub team_id: Option<Uuid>,
+
+    /// Local port of the tunnel the port belongs to (NULL = any tunnel of the owner
+    /// that asks for this port explicitly)
+    pub local_port: Option<i32>,
+
+    /// Tunnel that last held the port
+    pub localup_id: Option<String>,
+
+    /// Explicit reservation made through the API; sticky assignments without it
+    /// are reclaimed after a period of disuse
+    pub reserved: bool,
+
+    /// Optional label shown when listing reservations
+    pub name: Option<String>,
+
+    /// When a tunnel last held the port
+    pub last_used_at: Option<ChronoDateTimeUtc>,
+
+    /// When the port was first assigned
+    pub created_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    /// Assignment belongs to a user
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    User,
+
+    /// Assignment may be reserved for a team
+    #[sea_orm(
+        belongs_to = "super::team::Entity",
+        from = "Column::TeamId",
+        to = "super::team::Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    Team,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl Related<super::team::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Team.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
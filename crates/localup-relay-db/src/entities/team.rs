@@ -21,6 +21,9 @@ pub struct Model {
     /// User ID of the team owner
     pub owner_id: Uuid,
 
+    /// How many TCP ports the team may reserve (NULL = relay default)
+    pub tcp_port_quota: Option<i32>,
+
     /// When the team was created
     pub created_at: ChronoDateTimeUtc,
 
@@ -0,0 +1,56 @@
+//! TrafficRollup entity for the metered traffic of one tunnel, token, user and team in one hour
+
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "traffic_rollups")]
+pub struct Model {
+    /// Rollup UUID (primary key)
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+
+    /// Start of the hour the traffic was counted in (UTC)
+    #[sea_orm(indexed)]
+    pub hour: ChronoDateTimeUtc,
+
+    /// SHA-256 of the tunnel, token, user and team below; unique per hour
+    pub series: String,
+
+    /// Tunnel the traffic went through
+    pub localup_id: String,
+
+    /// SHA-256 of the auth token the tunnel connected with (matches `auth_tokens.token_hash`)
+    pub token_hash: Option<String>,
+
+    /// User who owns the tunnel (kept when the user is deleted, for billing)
+    #[sea_orm(indexed)]
+    pub user_id: Option<Uuid>,
+
+    /// Team the tunnel was opened for
+    #[sea_orm(indexed)]
+    pub team_id: Option<Uuid>,
+
+    /// Bytes from visitors to the tunnel
+    pub bytes_in: i64,
+
+    /// Bytes from the tunnel to visitors
+    pub bytes_out: i64,
+
+    /// HTTP requests handled by the relay
+    pub requests: i64,
+
+    /// Visitor connections opened
+    pub connections: i64,
+
+    /// Time visitor connections were open, summed over connections (milliseconds)
+    pub connection_millis: i64,
+
+    /// When traffic was last added
+    pub updated_at: ChronoDateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -59,6 +59,10 @@ pub enum Relation {
     /// User owns auth tokens
     #[sea_orm(has_many = "super::auth_token::Entity")]
     AuthTokens,
+
+    /// User created share links
+    #[sea_orm(has_many = "super::share_link::Entity")]
+    ShareLinks,
 }
 
 impl Related<super::team::Entity> for Entity {
@@ -79,4 +83,10 @@ impl Related<super::auth_token::Entity> for Entity {
     }
 }
 
+impl Related<super::share_link::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ShareLinks.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
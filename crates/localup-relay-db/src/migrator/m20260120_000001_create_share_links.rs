@@ -0,0 +1,122 @@
+//! Migration to create share_links table for signed, expiring tunnel share links
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ShareLinks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ShareLinks::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ShareLinks::UserId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(ShareLinks::LocalupId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ShareLinks::PathPrefix)
+                            .string_len(1024)
+                            .not_null()
+                            .default("/"),
+                    )
+                    .col(ColumnDef::new(ShareLinks::Name).string_len(255))
+                    .col(ColumnDef::new(ShareLinks::MaxUses).integer())
+                    .col(
+                        ColumnDef::new(ShareLinks::UseCount)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(ShareLinks::LastUsedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(ShareLinks::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ShareLinks::RevokedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(ShareLinks::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_share_links_user_id")
+                            .from(ShareLinks::Table, ShareLinks::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Index on user for listing a user's links
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_share_links_user_id")
+                    .table(ShareLinks::Table)
+                    .col(ShareLinks::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Index on tunnel for listing a tunnel's links
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_share_links_localup_id")
+                    .table(ShareLinks::Table)
+                    .col(ShareLinks::LocalupId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ShareLinks::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ShareLinks {
+    #[sea_orm(iden = "share_links")]
+    Table,
+    Id,
+    UserId,
+    LocalupId,
+    PathPrefix,
+    Name,
+    MaxUses,
+    UseCount,
+    LastUsedAt,
+    ExpiresAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,147 @@
+//! Migration to create tcp_port_assignments table for sticky TCP ports and reservations,
+//! and add a per-team reservation quota
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TcpPortAssignments::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TcpPortAssignments::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TcpPortAssignments::Port)
+                            .integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(TcpPortAssignments::UserId).uuid())
+                    .col(ColumnDef::new(TcpPortAssignments::TeamId).uuid())
+                    .col(ColumnDef::new(TcpPortAssignments::LocalPort).integer())
+                    .col(ColumnDef::new(TcpPortAssignments::LocalupId).string_len(255))
+                    .col(
+                        ColumnDef::new(TcpPortAssignments::Reserved)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .col(ColumnDef::new(TcpPortAssignments::Name).string_len(255))
+                    .col(ColumnDef::new(TcpPortAssignments::LastUsedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(TcpPortAssignments::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_tcp_port_assignments_user_id")
+                            .from(TcpPortAssignments::Table, TcpPortAssignments::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_tcp_port_assignments_team_id")
+                            .from(TcpPortAssignments::Table, TcpPortAssignments::TeamId)
+                            .to(Teams::Table, Teams::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Index on user for finding an owner's ports
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_tcp_port_assignments_user_id")
+                    .table(TcpPortAssignments::Table)
+                    .col(TcpPortAssignments::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Index on team for quota checks
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_tcp_port_assignments_team_id")
+                    .table(TcpPortAssignments::Table)
+                    .col(TcpPortAssignments::TeamId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Reservation quota per team (NULL = relay default)
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Teams::Table)
+                    .add_column(ColumnDef::new(Teams::TcpPortQuota).integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Teams::Table)
+                    .drop_column(Teams::TcpPortQuota)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(TcpPortAssignments::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TcpPortAssignments {
+    #[sea_orm(iden = "tcp_port_assignments")]
+    Table,
+    Id,
+    Port,
+    UserId,
+    TeamId,
+    LocalPort,
+    LocalupId,
+    Reserved,
+    Name,
+    LastUsedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Teams {
+    Table,
+    Id,
+    TcpPortQuota,
+}
@@ -0,0 +1,212 @@
+//! Migration to create plans, quotas and quota_usage tables for per-user and per-team limits
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Plans::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Plans::Id).uuid().not_null().primary_key())
+                    .col(
+                        ColumnDef::new(Plans::Name)
+                            .string_len(255)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Plans::MaxTunnels).integer())
+                    .col(ColumnDef::new(Plans::MaxCustomDomains).integer())
+                    .col(ColumnDef::new(Plans::MaxReservedPorts).integer())
+                    .col(ColumnDef::new(Plans::MonthlyBytes).big_integer())
+                    .col(ColumnDef::new(Plans::MaxConnections).integer())
+                    .col(
+                        ColumnDef::new(Plans::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(Plans::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Quotas::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Quotas::Id).uuid().not_null().primary_key())
+                    .col(
+                        ColumnDef::new(Quotas::SubjectType)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Quotas::SubjectId).uuid().not_null())
+                    .col(ColumnDef::new(Quotas::PlanId).uuid())
+                    .col(ColumnDef::new(Quotas::MaxTunnels).integer())
+                    .col(ColumnDef::new(Quotas::MaxCustomDomains).integer())
+                    .col(ColumnDef::new(Quotas::MaxReservedPorts).integer())
+                    .col(ColumnDef::new(Quotas::MonthlyBytes).big_integer())
+                    .col(ColumnDef::new(Quotas::MaxConnections).integer())
+                    .col(
+                        ColumnDef::new(Quotas::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(Quotas::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_quotas_plan_id")
+                            .from(Quotas::Table, Quotas::PlanId)
+                            .to(Plans::Table, Plans::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // One quota per user or team
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_quotas_subject")
+                    .table(Quotas::Table)
+                    .col(Quotas::SubjectType)
+                    .col(Quotas::SubjectId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(QuotaUsage::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(QuotaUsage::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(QuotaUsage::SubjectType)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(QuotaUsage::SubjectId).uuid().not_null())
+                    .col(ColumnDef::new(QuotaUsage::Period).string_len(7).not_null())
+                    .col(
+                        ColumnDef::new(QuotaUsage::BytesIn)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(QuotaUsage::BytesOut)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(QuotaUsage::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // One row per account and month (target of the usage upsert)
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_quota_usage_subject_period")
+                    .table(QuotaUsage::Table)
+                    .col(QuotaUsage::SubjectType)
+                    .col(QuotaUsage::SubjectId)
+                    .col(QuotaUsage::Period)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(QuotaUsage::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Quotas::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Plans::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Plans {
+    Table,
+    Id,
+    Name,
+    MaxTunnels,
+    MaxCustomDomains,
+    MaxReservedPorts,
+    MonthlyBytes,
+    MaxConnections,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Quotas {
+    Table,
+    Id,
+    SubjectType,
+    SubjectId,
+    PlanId,
+    MaxTunnels,
+    MaxCustomDomains,
+    MaxReservedPorts,
+    MonthlyBytes,
+    MaxConnections,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum QuotaUsage {
+    #[sea_orm(iden = "quota_usage")]
+    Table,
+    Id,
+    SubjectType,
+    SubjectId,
+    Period,
+    BytesIn,
+    BytesOut,
+    UpdatedAt,
+}
@@ -0,0 +1,140 @@
+//! Migration to create traffic_rollups table for hourly traffic metering
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TrafficRollups::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TrafficRollups::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::Hour)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::Series)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::LocalupId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(TrafficRollups::TokenHash).string_len(64))
+                    .col(ColumnDef::new(TrafficRollups::UserId).uuid())
+                    .col(ColumnDef::new(TrafficRollups::TeamId).uuid())
+                    .col(
+                        ColumnDef::new(TrafficRollups::BytesIn)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::BytesOut)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::Requests)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::Connections)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::ConnectionMillis)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(TrafficRollups::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // One row per series and hour (target of the rollup upsert)
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_traffic_rollups_hour_series")
+                    .table(TrafficRollups::Table)
+                    .col(TrafficRollups::Hour)
+                    .col(TrafficRollups::Series)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_traffic_rollups_user_id")
+                    .table(TrafficRollups::Table)
+                    .col(TrafficRollups::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_traffic_rollups_team_id")
+                    .table(TrafficRollups::Table)
+                    .col(TrafficRollups::TeamId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TrafficRollups::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum TrafficRollups {
+    Table,
+    Id,
+    Hour,
+    Series,
+    LocalupId,
+    TokenHash,
+    UserId,
+    TeamId,
+    BytesIn,
+    BytesOut,
+    Requests,
+    Connections,
+    ConnectionMillis,
+    UpdatedAt,
+}
@@ -0,0 +1,71 @@
+//! Migration to create revoked_tokens table for JWT revocation
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RevokedTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RevokedTokens::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RevokedTokens::Jti)
+                            .string_len(255)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(RevokedTokens::UserId).uuid())
+                    .col(ColumnDef::new(RevokedTokens::Reason).text())
+                    .col(ColumnDef::new(RevokedTokens::RevokedBy).uuid())
+                    .col(ColumnDef::new(RevokedTokens::ExpiresAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(RevokedTokens::RevokedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_revoked_tokens_expires_at")
+                    .table(RevokedTokens::Table)
+                    .col(RevokedTokens::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RevokedTokens::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RevokedTokens {
+    Table,
+    Id,
+    Jti,
+    UserId,
+    Reason,
+    RevokedBy,
+    ExpiresAt,
+    RevokedAt,
+}
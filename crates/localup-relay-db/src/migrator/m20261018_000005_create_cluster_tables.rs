@@ -0,0 +1,112 @@
+//! Migration to create relay_nodes and cluster_routes tables for relay clustering
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RelayNodes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RelayNodes::NodeId)
+                            .string_len(255)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(RelayNodes::ClusterAddr)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RelayNodes::StartedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(RelayNodes::HeartbeatAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ClusterRoutes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ClusterRoutes::RouteKey)
+                            .string_len(512)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ClusterRoutes::NodeId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ClusterRoutes::LocalupId)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ClusterRoutes::ClaimedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_cluster_routes_node_id")
+                    .table(ClusterRoutes::Table)
+                    .col(ClusterRoutes::NodeId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ClusterRoutes::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RelayNodes::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RelayNodes {
+    Table,
+    NodeId,
+    ClusterAddr,
+    StartedAt,
+    HeartbeatAt,
+}
+
+#[derive(DeriveIden)]
+enum ClusterRoutes {
+    Table,
+    RouteKey,
+    NodeId,
+    LocalupId,
+    ClaimedAt,
+}
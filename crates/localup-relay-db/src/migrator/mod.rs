@@ -9,6 +9,12 @@ mod m20251216_000002_make_cert_paths_nullable;
 mod m20251216_000003_add_domain_id;
 mod m20260102_000001_add_cert_pem_columns;
 mod m20260108_000001_add_is_wildcard;
+mod m20260120_000001_create_share_links;
+mod m20261018_000001_create_tcp_port_assignments;
+mod m20261018_000002_create_quotas;
+mod m20261018_000003_create_traffic_rollups;
+mod m20261018_000004_create_revoked_tokens;
+mod m20261018_000005_create_cluster_tables;
 
 pub struct Migrator;
 
@@ -23,6 +29,12 @@ impl MigratorTrait for Migrator {
             Box::new(m20251216_000003_add_domain_id::Migration),
             Box::new(m20260102_000001_add_cert_pem_columns::Migration),
             Box::new(m20260108_000001_add_is_wildcard::Migration),
+            Box::new(m20260120_000001_create_share_links::Migration),
+            Box::new(m20261018_000001_create_tcp_port_assignments::Migration),
+            Box::new(m20261018_000002_create_quotas::Migration),
+            Box::new(m20261018_000003_create_traffic_rollups::Migration),
+            Box::new(m20261018_000004_create_revoked_tokens::Migration),
+            Box::new(m20261018_000005_create_cluster_tables::Migration),
         ]
     }
 }
@@ -451,3 +451,54 @@ async fn test_nullable_fields() {
     assert!(inserted.responded_at.is_none());
     assert!(inserted.latency_ms.is_none());
 }
+
+#[tokio::test]
+async fn test_share_link_lifecycle() {
+    use localup_relay_db::entities::{share_link, user};
+
+    let db = setup_test_db().await;
+    let user_id = uuid::Uuid::new_v4();
+    user::ActiveModel {
+        id: Set(user_id),
+        email: Set("owner@example.com".to_string()),
+        password_hash: Set("hash".to_string()),
+        full_name: Set(None),
+        role: Set(user::UserRole::User),
+        is_active: Set(true),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+    }
+    .insert(&db)
+    .await
+    .expect("Failed to insert user");
+
+    let link = share_link::ActiveModel {
+        id: Set(uuid::Uuid::new_v4()),
+        user_id: Set(user_id),
+        localup_id: Set("localup-9".to_string()),
+        path_prefix: Set("/reports".to_string()),
+        name: Set(Some("Customer preview".to_string())),
+        max_uses: Set(Some(3)),
+        use_count: Set(0),
+        last_used_at: Set(None),
+        expires_at: Set(Utc::now() + chrono::Duration::hours(1)),
+        revoked_at: Set(None),
+        created_at: Set(Utc::now()),
+    }
+    .insert(&db)
+    .await
+    .expect("Failed to insert share link");
+    assert!(link.is_active());
+
+    let mut revoked: share_link::ActiveModel = link.into();
+    revoked.revoked_at = Set(Some(Utc::now()));
+    let revoked = revoked.update(&db).await.expect("Failed to revoke");
+    assert!(!revoked.is_active());
+
+    let links = share_link::Entity::find()
+        .filter(share_link::Column::LocalupId.eq("localup-9"))
+        .all(&db)
+        .await
+        .expect("Failed to query");
+    assert_eq!(links.len(), 1);
+}
@@ -10,8 +10,10 @@ pub mod tcp;
 pub mod wildcard;
 
 pub use http::{HttpRoute, HttpRouter};
-pub use registry::{RouteRegistry, RouteTarget};
-pub use sni::{SniRoute, SniRouter};
+pub use registry::{RouteRegistry, RouteTarget, TLS_TERMINATE_METADATA};
+pub use sni::{
+    ClientHelloInfo, SniMatch, SniRoute, SniRouter, SniRouterError, MAX_CLIENT_HELLO_SIZE,
+};
 pub use tcp::{TcpRoute, TcpRouter};
 pub use wildcard::{extract_parent_wildcard, WildcardError, WildcardPattern};
 
@@ -22,6 +24,10 @@ pub enum RouteKey {
     TcpPort(u16),
     /// TLS routing by SNI hostname
     TlsSni(String),
+    /// TLS routing by SNI hostname (or wildcard pattern) and ALPN protocol
+    TlsSniAlpn(String, String),
     /// HTTP routing by host header
     HttpHost(String),
+    /// TCP routing by hostname (HTTP CONNECT / SOCKS5 ingress)
+    TcpHost(String),
 }
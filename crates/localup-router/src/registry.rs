@@ -28,11 +28,19 @@ pub struct RouteTarget {
     pub ip_filter: IpFilter,
 }
 
+/// Route metadata marking SNI routes whose TLS is terminated at the relay
+pub const TLS_TERMINATE_METADATA: &str = "terminate-tls";
+
 impl RouteTarget {
     /// Check if the given peer address is allowed to access this route
     pub fn is_ip_allowed(&self, peer_addr: &SocketAddr) -> bool {
         self.ip_filter.is_socket_allowed(peer_addr)
     }
+
+    /// Whether the relay terminates TLS for this route instead of passing it through
+    pub fn terminates_tls(&self) -> bool {
+        self.metadata.as_deref() == Some(TLS_TERMINATE_METADATA)
+    }
 }
 
 // Future: Route registration with state for reconnection support
@@ -1,11 +1,27 @@
 //! TLS SNI-based routing
+//!
+//! Connections are routed by the SNI hostname of the ClientHello and, for
+//! tunnels that only serve some protocols, by the offered ALPN protocols.
+//! Unmatched connections can fall back to a relay-wide default route.
 
-use crate::{RouteKey, RouteRegistry, RouteTarget};
+use crate::{extract_parent_wildcard, RouteKey, RouteRegistry, RouteTarget};
 use localup_proto::IpFilter;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use thiserror::Error;
 use tracing::{debug, trace};
 
+/// Largest ClientHello buffered while waiting for the rest of it to arrive
+pub const MAX_CLIENT_HELLO_SIZE: usize = 64 * 1024;
+
+/// TLS record type for handshake messages
+const RECORD_TYPE_HANDSHAKE: u8 = 0x16;
+/// Handshake message type for ClientHello
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 0x01;
+/// server_name extension type
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+/// application_layer_protocol_negotiation extension type
+const EXTENSION_ALPN: u16 = 0x0010;
+
 /// SNI routing errors
 #[derive(Debug, Error)]
 pub enum SniRouterError {
@@ -17,6 +33,26 @@ pub enum SniRouterError {
 
     #[error("SNI extraction failed")]
     SniExtractionFailed,
+
+    #[error("ClientHello incomplete, more data needed")]
+    Incomplete,
+}
+
+/// Routing-relevant fields of a TLS ClientHello
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    /// SNI hostname, if the client sent one
+    pub server_name: Option<String>,
+    /// ALPN protocols offered by the client, in preference order
+    pub alpn_protocols: Vec<String>,
+}
+
+/// Route selected for a ClientHello
+#[derive(Debug, Clone)]
+pub struct SniMatch {
+    pub target: RouteTarget,
+    /// ALPN protocol the route was registered for (None for plain SNI or default routes)
+    pub alpn: Option<String>,
 }
 
 /// SNI route information
@@ -32,11 +68,36 @@ pub struct SniRoute {
 /// SNI router for TLS connections
 pub struct SniRouter {
     registry: Arc<RouteRegistry>,
+    /// Catch-all route for connections without a matching (or any) SNI
+    default_route: RwLock<Option<RouteTarget>>,
 }
 
 impl SniRouter {
     pub fn new(registry: Arc<RouteRegistry>) -> Self {
-        Self { registry }
+        Self {
+            registry,
+            default_route: RwLock::new(None),
+        }
+    }
+
+    /// Set or clear the catch-all route for unmatched SNI hostnames
+    pub fn set_default_route(&self, target: Option<RouteTarget>) {
+        debug!(
+            "Default SNI route: {:?}",
+            target.as_ref().map(|t| &t.target_addr)
+        );
+        *self
+            .default_route
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = target;
+    }
+
+    /// Current catch-all route, if any
+    pub fn default_route(&self) -> Option<RouteTarget> {
+        self.default_route
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
     }
 
     /// Register an SNI route
@@ -68,6 +129,61 @@ impl SniRouter {
         Ok(target)
     }
 
+    /// Lookup the route for an SNI hostname and ALPN protocol
+    ///
+    /// Tries the exact hostname, then its parent wildcard (`*.example.com`).
+    pub fn lookup_alpn(&self, sni_hostname: &str, alpn: &str) -> Option<RouteTarget> {
+        let exact = RouteKey::TlsSniAlpn(sni_hostname.to_string(), alpn.to_string());
+        if let Ok(target) = self.registry.lookup(&exact) {
+            return Some(target);
+        }
+
+        let wildcard = extract_parent_wildcard(sni_hostname)?;
+        self.registry
+            .lookup(&RouteKey::TlsSniAlpn(wildcard, alpn.to_string()))
+            .ok()
+    }
+
+    /// Select the route for a parsed ClientHello
+    ///
+    /// Priority order:
+    /// 1. SNI + ALPN routes, in the client's ALPN preference order
+    /// 2. SNI routes (exact, then wildcard)
+    /// 3. The default route
+    pub fn route(&self, hello: &ClientHelloInfo) -> Result<SniMatch, SniRouterError> {
+        if let Some(sni_hostname) = &hello.server_name {
+            for protocol in &hello.alpn_protocols {
+                if let Some(target) = self.lookup_alpn(sni_hostname, protocol) {
+                    trace!("Matched SNI {} with ALPN {}", sni_hostname, protocol);
+                    return Ok(SniMatch {
+                        target,
+                        alpn: Some(protocol.clone()),
+                    });
+                }
+            }
+
+            match self.lookup(sni_hostname) {
+                Ok(target) => return Ok(SniMatch { target, alpn: None }),
+                Err(e) => trace!("No SNI route for {}: {}", sni_hostname, e),
+            }
+        }
+
+        if let Some(target) = self.default_route() {
+            debug!(
+                "Using default route for SNI {:?} -> {}",
+                hello.server_name, target.target_addr
+            );
+            return Ok(SniMatch { target, alpn: None });
+        }
+
+        match &hello.server_name {
+            Some(sni_hostname) => Err(SniRouterError::RouteError(
+                crate::registry::RouteError::RouteNotFound(RouteKey::TlsSni(sni_hostname.clone())),
+            )),
+            None => Err(SniRouterError::SniExtractionFailed),
+        }
+    }
+
     /// Unregister an SNI route
     pub fn unregister(&self, sni_hostname: &str) -> Result<(), SniRouterError> {
         debug!("Unregistering SNI route for hostname: {}", sni_hostname);
@@ -87,70 +203,125 @@ impl SniRouter {
     /// Extract SNI from TLS ClientHello
     /// Parses the TLS handshake to extract the Server Name Indication (SNI) extension
     pub fn extract_sni(client_hello: &[u8]) -> Result<String, SniRouterError> {
-        // Skip TLS record header (5 bytes) and handshake header (4 bytes)
-        if client_hello.len() < 43 {
-            return Err(SniRouterError::SniExtractionFailed);
-        }
+        Self::parse_client_hello(client_hello)?
+            .server_name
+            .ok_or(SniRouterError::SniExtractionFailed)
+    }
 
-        let mut offset = 9; // Skip record header (5) + handshake header (4)
+    /// Parse the SNI hostname and ALPN protocols from the bytes read so far
+    ///
+    /// The ClientHello may be split across several TCP reads or TLS records;
+    /// `SniRouterError::Incomplete` means the caller should read more data and
+    /// retry with everything received (up to `MAX_CLIENT_HELLO_SIZE`).
+    pub fn parse_client_hello(data: &[u8]) -> Result<ClientHelloInfo, SniRouterError> {
+        let handshake = Self::reassemble_handshake(data)?;
+        Self::parse_client_hello_message(&handshake)
+    }
 
-        // Skip ClientHello version (2 bytes)
-        offset += 2;
+    /// Collect the ClientHello handshake message from one or more TLS records
+    fn reassemble_handshake(data: &[u8]) -> Result<Vec<u8>, SniRouterError> {
+        let mut handshake = Vec::new();
+        let mut offset = 0;
+
+        loop {
+            // Handshake header: type (1 byte) + length (3 bytes)
+            if handshake.len() >= 4 {
+                if handshake[0] != HANDSHAKE_TYPE_CLIENT_HELLO {
+                    return Err(SniRouterError::SniExtractionFailed);
+                }
+                let message_len = 4
+                    + (u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize);
+                if message_len > MAX_CLIENT_HELLO_SIZE {
+                    return Err(SniRouterError::SniExtractionFailed);
+                }
+                if handshake.len() >= message_len {
+                    handshake.truncate(message_len);
+                    return Ok(handshake);
+                }
+            }
 
-        // Skip random (32 bytes)
-        offset += 32;
+            // Next TLS record header: type (1) + version (2) + length (2)
+            if offset + 5 > data.len() {
+                return Err(SniRouterError::Incomplete);
+            }
+            if data[offset] != RECORD_TYPE_HANDSHAKE {
+                return Err(SniRouterError::SniExtractionFailed);
+            }
+            let record_len = u16::from_be_bytes([data[offset + 3], data[offset + 4]]) as usize;
+            let record_end = offset + 5 + record_len;
+            if record_end > data.len() {
+                return Err(SniRouterError::Incomplete);
+            }
+            handshake.extend_from_slice(&data[offset + 5..record_end]);
+            offset = record_end;
+        }
+    }
+
+    /// Parse a complete ClientHello handshake message (including its 4-byte header)
+    fn parse_client_hello_message(message: &[u8]) -> Result<ClientHelloInfo, SniRouterError> {
+        let mut reader = Reader::new(message);
+
+        // Skip handshake header, ClientHello version (2 bytes) and random (32 bytes)
+        reader.skip(4 + 2 + 32)?;
 
         // Skip session ID
-        if offset >= client_hello.len() {
-            return Err(SniRouterError::SniExtractionFailed);
-        }
-        let session_id_len = client_hello[offset] as usize;
-        offset += 1 + session_id_len;
+        let session_id_len = reader.u8()? as usize;
+        reader.skip(session_id_len)?;
 
         // Skip cipher suites
-        if offset + 2 > client_hello.len() {
-            return Err(SniRouterError::SniExtractionFailed);
-        }
-        let cipher_suites_len =
-            u16::from_be_bytes([client_hello[offset], client_hello[offset + 1]]) as usize;
-        offset += 2 + cipher_suites_len;
+        let cipher_suites_len = reader.u16()? as usize;
+        reader.skip(cipher_suites_len)?;
 
         // Skip compression methods
-        if offset >= client_hello.len() {
-            return Err(SniRouterError::SniExtractionFailed);
-        }
-        let compression_methods_len = client_hello[offset] as usize;
-        offset += 1 + compression_methods_len;
+        let compression_methods_len = reader.u8()? as usize;
+        reader.skip(compression_methods_len)?;
 
-        // Parse extensions
-        if offset + 2 > client_hello.len() {
-            return Err(SniRouterError::SniExtractionFailed);
-        }
-        let extensions_len =
-            u16::from_be_bytes([client_hello[offset], client_hello[offset + 1]]) as usize;
-        offset += 2;
+        let mut info = ClientHelloInfo::default();
 
-        let extensions_end = offset + extensions_len;
-        if extensions_end > client_hello.len() {
-            return Err(SniRouterError::SniExtractionFailed);
+        // Extensions are optional
+        if reader.is_empty() {
+            return Ok(info);
+        }
+        let extensions_len = reader.u16()? as usize;
+        let mut extensions = Reader::new(reader.take(extensions_len)?);
+
+        while !extensions.is_empty() {
+            let ext_type = extensions.u16()?;
+            let ext_len = extensions.u16()? as usize;
+            let ext_data = extensions.take(ext_len)?;
+
+            match ext_type {
+                EXTENSION_SERVER_NAME => {
+                    info.server_name = Some(Self::parse_sni_extension(ext_data)?);
+                }
+                EXTENSION_ALPN => {
+                    info.alpn_protocols = Self::parse_alpn_extension(ext_data)?;
+                }
+                _ => {}
+            }
         }
 
-        // Search for server_name extension (type 0x0000)
-        while offset + 4 <= extensions_end {
-            let ext_type = u16::from_be_bytes([client_hello[offset], client_hello[offset + 1]]);
-            let ext_len =
-                u16::from_be_bytes([client_hello[offset + 2], client_hello[offset + 3]]) as usize;
-            offset += 4;
+        Ok(info)
+    }
 
-            if ext_type == 0x0000 {
-                // Found server_name extension
-                return Self::parse_sni_extension(&client_hello[offset..offset + ext_len]);
+    /// Parse the ALPN extension data into protocol names
+    fn parse_alpn_extension(data: &[u8]) -> Result<Vec<String>, SniRouterError> {
+        let mut reader = Reader::new(data);
+        let list_len = reader.u16()? as usize;
+        let mut list = Reader::new(reader.take(list_len)?);
+
+        let mut protocols = Vec::new();
+        while !list.is_empty() {
+            let len = list.u8()? as usize;
+            let name = list.take(len)?;
+            // Non-UTF-8 protocol IDs cannot match a registered route
+            if let Ok(name) = std::str::from_utf8(name) {
+                protocols.push(name.to_string());
             }
-
-            offset += ext_len;
         }
 
-        Err(SniRouterError::SniExtractionFailed)
+        trace!("Extracted ALPN protocols: {:?}", protocols);
+        Ok(protocols)
     }
 
     /// Parse the server_name extension data
@@ -188,6 +359,43 @@ impl SniRouter {
     }
 }
 
+/// Bounds-checked big-endian reader over ClientHello bytes
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SniRouterError> {
+        if len > self.data.len() {
+            return Err(SniRouterError::SniExtractionFailed);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), SniRouterError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, SniRouterError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SniRouterError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -539,4 +747,160 @@ mod tests {
             assert_eq!(target.localup_id, tunnel_id);
         }
     }
+
+    /// Build a ClientHello handshake message with SNI and ALPN extensions
+    fn client_hello_message(sni: &str, alpn: &[&str]) -> Vec<u8> {
+        let mut extensions = Vec::new();
+
+        let mut server_name = vec![0x00];
+        server_name.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+        server_name.extend_from_slice(sni.as_bytes());
+        extensions.extend_from_slice(&EXTENSION_SERVER_NAME.to_be_bytes());
+        extensions.extend_from_slice(&(server_name.len() as u16 + 2).to_be_bytes());
+        extensions.extend_from_slice(&(server_name.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&server_name);
+
+        let mut protocols = Vec::new();
+        for name in alpn {
+            protocols.push(name.len() as u8);
+            protocols.extend_from_slice(name.as_bytes());
+        }
+        extensions.extend_from_slice(&EXTENSION_ALPN.to_be_bytes());
+        extensions.extend_from_slice(&(protocols.len() as u16 + 2).to_be_bytes());
+        extensions.extend_from_slice(&(protocols.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&protocols);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0x00; 32]); // Random
+        body.push(0x00); // Session ID length
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]); // One cipher suite
+        body.extend_from_slice(&[0x01, 0x00]); // Null compression
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut message = vec![HANDSHAKE_TYPE_CLIENT_HELLO];
+        message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        message.extend_from_slice(&body);
+        message
+    }
+
+    /// Wrap handshake bytes into TLS records of at most `chunk` bytes each
+    fn records(message: &[u8], chunk: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for fragment in message.chunks(chunk) {
+            out.extend_from_slice(&[RECORD_TYPE_HANDSHAKE, 0x03, 0x01]);
+            out.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            out.extend_from_slice(fragment);
+        }
+        out
+    }
+
+    fn tunnel_target(localup_id: &str) -> RouteTarget {
+        RouteTarget {
+            localup_id: localup_id.to_string(),
+            target_addr: format!("tunnel:{}", localup_id),
+            metadata: Some("via-tunnel".to_string()),
+            ip_filter: IpFilter::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_client_hello_alpn() {
+        let data = records(
+            &client_hello_message("mqtt.example.com", &["mqtt", "h2"]),
+            16384,
+        );
+
+        let info = SniRouter::parse_client_hello(&data).unwrap();
+        assert_eq!(info.server_name.as_deref(), Some("mqtt.example.com"));
+        assert_eq!(info.alpn_protocols, vec!["mqtt", "h2"]);
+    }
+
+    #[test]
+    fn test_parse_client_hello_split_reads_and_records() {
+        // Handshake fragmented across several TLS records
+        let data = records(&client_hello_message("api.example.com", &["h2"]), 20);
+
+        // Every prefix of the stream asks for more data
+        for len in 0..data.len() {
+            assert!(matches!(
+                SniRouter::parse_client_hello(&data[..len]),
+                Err(SniRouterError::Incomplete)
+            ));
+        }
+
+        let info = SniRouter::parse_client_hello(&data).unwrap();
+        assert_eq!(info.server_name.as_deref(), Some("api.example.com"));
+        assert_eq!(info.alpn_protocols, vec!["h2"]);
+    }
+
+    #[test]
+    fn test_parse_client_hello_not_tls() {
+        let result = SniRouter::parse_client_hello(b"GET / HTTP/1.1\r\n");
+        assert!(matches!(result, Err(SniRouterError::SniExtractionFailed)));
+    }
+
+    #[test]
+    fn test_route_by_alpn() {
+        let registry = Arc::new(RouteRegistry::new());
+        registry
+            .register(
+                RouteKey::TlsSni("iot.example.com".to_string()),
+                tunnel_target("web"),
+            )
+            .unwrap();
+        registry
+            .register(
+                RouteKey::TlsSniAlpn("*.example.com".to_string(), "mqtt".to_string()),
+                tunnel_target("broker"),
+            )
+            .unwrap();
+        let router = SniRouter::new(registry);
+
+        let hello = |alpn: &[&str]| ClientHelloInfo {
+            server_name: Some("iot.example.com".to_string()),
+            alpn_protocols: alpn.iter().map(|p| p.to_string()).collect(),
+        };
+
+        let matched = router.route(&hello(&["mqtt"])).unwrap();
+        assert_eq!(matched.target.localup_id, "broker");
+        assert_eq!(matched.alpn.as_deref(), Some("mqtt"));
+
+        let matched = router.route(&hello(&["h2", "http/1.1"])).unwrap();
+        assert_eq!(matched.target.localup_id, "web");
+        assert_eq!(matched.alpn, None);
+
+        let matched = router.route(&hello(&[])).unwrap();
+        assert_eq!(matched.target.localup_id, "web");
+    }
+
+    #[test]
+    fn test_route_default() {
+        let registry = Arc::new(RouteRegistry::new());
+        let router = SniRouter::new(registry);
+
+        let unknown = ClientHelloInfo {
+            server_name: Some("unknown.example.com".to_string()),
+            alpn_protocols: vec![],
+        };
+        assert!(router.route(&unknown).is_err());
+        assert!(router.route(&ClientHelloInfo::default()).is_err());
+
+        router.set_default_route(Some(tunnel_target("fallback")));
+        assert_eq!(
+            router.route(&unknown).unwrap().target.localup_id,
+            "fallback"
+        );
+        assert_eq!(
+            router
+                .route(&ClientHelloInfo::default())
+                .unwrap()
+                .target
+                .localup_id,
+            "fallback"
+        );
+
+        router.set_default_route(None);
+        assert!(router.route(&unknown).is_err());
+    }
 }
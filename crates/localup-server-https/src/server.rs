@@ -1,11 +1,14 @@
 //! HTTPS server implementation with TLS termination
 //!
 //! Supports wildcard domain certificates (e.g., `*.example.com`) with fallback resolution.
-use localup_control::{PendingRequests, TunnelConnectionManager};
+use localup_control::{
+    ClusterIngress, ClusterNode, ForwardKind, PendingRequests, ShareAccess, ShareLinkGate,
+    TunnelConnectionManager, VisitorPermit,
+};
 use localup_proto::TunnelMessage;
 use localup_relay_db::entities::custom_domain;
 use localup_router::{extract_parent_wildcard, RouteKey, RouteRegistry};
-use localup_transport::TransportConnection;
+use localup_transport::{TcpIncoming, TransportConnection};
 use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
 use std::collections::HashMap;
 use std::fs::File;
@@ -14,9 +17,9 @@ use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::Arc;
 use thiserror::Error;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
 use tokio_rustls::rustls::{sign::CertifiedKey, ServerConfig};
@@ -65,6 +68,9 @@ pub struct HttpsServer {
     localup_manager: Option<Arc<TunnelConnectionManager>>,
     pending_requests: Option<Arc<PendingRequests>>,
     db: Option<DatabaseConnection>,
+    share_links: Option<Arc<ShareLinkGate>>,
+    cert_resolver: Option<Arc<CustomCertResolver>>,
+    cluster: Option<Arc<ClusterNode>>,
 }
 
 /// Captured response data from transparent proxy
@@ -91,6 +97,20 @@ impl CustomCertResolver {
         }
     }
 
+    /// Create a resolver whose default certificate is loaded from PEM files
+    pub fn from_files(cert_path: &str, key_path: &str) -> Result<Self, HttpsServerError> {
+        info!("Loading default TLS certificate from: {}", cert_path);
+        let certs = HttpsServer::load_certs(Path::new(cert_path))?;
+
+        info!("Loading default TLS private key from: {}", key_path);
+        let key = HttpsServer::load_private_key(Path::new(key_path))?;
+
+        let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+            .map_err(|e| HttpsServerError::TlsError(format!("Invalid key: {}", e)))?;
+
+        Ok(Self::new(Arc::new(CertifiedKey::new(certs, signing_key))))
+    }
+
     /// Add or update a custom certificate for a domain (hot-reload support)
     pub async fn add_custom_cert(&self, domain: String, cert: Arc<CertifiedKey>) {
         let mut certs = self.custom_certs.write().await;
@@ -170,6 +190,9 @@ impl HttpsServer {
             localup_manager: None,
             pending_requests: None,
             db: None,
+            share_links: None,
+            cert_resolver: None,
+            cluster: None,
         }
     }
 
@@ -188,6 +211,54 @@ impl HttpsServer {
         self
     }
 
+    /// Accept share links minted by the API
+    pub fn with_share_links(mut self, gate: Arc<ShareLinkGate>) -> Self {
+        self.share_links = Some(gate);
+        self
+    }
+
+    /// Use a shared certificate resolver instead of loading `cert_path`/`key_path`
+    /// Custom domain certificates are still loaded into it on start, so other
+    /// servers holding the resolver (e.g. TLS termination) see them too.
+    pub fn with_cert_resolver(mut self, resolver: Arc<CustomCertResolver>) -> Self {
+        self.cert_resolver = Some(resolver);
+        self
+    }
+
+    /// Forward requests for hosts served by other relay nodes of the cluster
+    pub fn with_cluster(mut self, cluster: Arc<ClusterNode>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Serve HTTPS requests forwarded by other relay nodes
+    ///
+    /// The forwarding node terminates TLS, so forwarded requests arrive decrypted.
+    pub fn cluster_ingress(&self) -> ClusterIngress {
+        let route_registry = self.route_registry.clone();
+        let localup_manager = self.localup_manager.clone();
+        let pending_requests = self.pending_requests.clone();
+        let db = self.db.clone();
+        let share_links = self.share_links.clone();
+        Arc::new(move |stream, peer_addr| {
+            let handled = Self::handle_https_stream(
+                stream,
+                peer_addr,
+                route_registry.clone(),
+                localup_manager.clone(),
+                pending_requests.clone(),
+                db.clone(),
+                share_links.clone(),
+                None,
+            );
+            Box::pin(async move {
+                if let Err(e) = handled.await {
+                    debug!("Forwarded HTTPS connection error from {}: {}", peer_addr, e);
+                }
+            })
+        })
+    }
+
     /// Load TLS certificates from PEM files
     fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, HttpsServerError> {
         let file = File::open(path)
@@ -362,27 +433,40 @@ impl HttpsServer {
     pub async fn start(self) -> Result<(), HttpsServerError> {
         let local_addr = self.config.bind_addr;
 
-        // Load default TLS certificate
-        info!(
-            "Loading default TLS certificate from: {}",
-            self.config.cert_path
-        );
-        let certs = Self::load_certs(Path::new(&self.config.cert_path))?;
-
-        info!(
-            "Loading default TLS private key from: {}",
-            self.config.key_path
-        );
-        let key = Self::load_private_key(Path::new(&self.config.key_path))?;
+        // Bind TCP listener
+        let listener = TcpListener::bind(local_addr).await.map_err(|e| {
+            let port = local_addr.port();
+            let address = local_addr.ip().to_string();
+            let reason = e.to_string();
+            HttpsServerError::BindError {
+                address,
+                port,
+                reason,
+            }
+        })?;
 
-        // Create CertifiedKey for default certificate
-        let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
-            .map_err(|e| HttpsServerError::TlsError(format!("Invalid key: {}", e)))?;
+        self.run(TcpIncoming::Listener(listener)).await
+    }
 
-        let default_cert = Arc::new(CertifiedKey::new(certs, signing_key));
+    /// Serve connections accepted by a front listener on `local_addr` (e.g. a relay
+    /// sharing one port between HTTPS and tunnel transports) instead of binding a socket
+    pub async fn serve(
+        self,
+        incoming: mpsc::Receiver<(TcpStream, SocketAddr)>,
+        local_addr: SocketAddr,
+    ) -> Result<(), HttpsServerError> {
+        self.run(TcpIncoming::channel(incoming, local_addr)).await
+    }
 
-        // Create custom cert resolver with default certificate
-        let cert_resolver = Arc::new(CustomCertResolver::new(default_cert));
+    async fn run(self, incoming: TcpIncoming) -> Result<(), HttpsServerError> {
+        // Create custom cert resolver with the default certificate
+        let cert_resolver = match self.cert_resolver.clone() {
+            Some(resolver) => resolver,
+            None => Arc::new(CustomCertResolver::from_files(
+                &self.config.cert_path,
+                &self.config.key_path,
+            )?),
+        };
 
         // Load custom domain certificates from database if available
         if let Some(ref db) = self.db {
@@ -400,18 +484,7 @@ impl HttpsServer {
 
         let acceptor = TlsAcceptor::from(Arc::new(tls_config));
 
-        // Bind TCP listener
-        let listener = TcpListener::bind(local_addr).await.map_err(|e| {
-            let port = local_addr.port();
-            let address = local_addr.ip().to_string();
-            let reason = e.to_string();
-            HttpsServerError::BindError {
-                address,
-                port,
-                reason,
-            }
-        })?;
-        let bound_addr = listener.local_addr()?;
+        let bound_addr = incoming.local_addr().unwrap_or(self.config.bind_addr);
 
         info!("HTTPS server listening on {}", bound_addr);
 
@@ -419,20 +492,32 @@ impl HttpsServer {
         let localup_manager = self.localup_manager.clone();
         let pending_requests = self.pending_requests.clone();
         let db = self.db.clone();
+        let share_links = self.share_links.clone();
+        let cluster = self.cluster.clone();
 
         // Accept connections
         loop {
-            match listener.accept().await {
+            match incoming.accept().await {
                 Ok((stream, peer_addr)) => {
                     let acceptor = acceptor.clone();
                     let registry = route_registry.clone();
                     let manager = localup_manager.clone();
                     let pending = pending_requests.clone();
                     let db = db.clone();
+                    let share_links = share_links.clone();
+                    let cluster = cluster.clone();
 
                     tokio::spawn(async move {
                         if let Err(e) = Self::handle_connection(
-                            stream, peer_addr, acceptor, registry, manager, pending, db,
+                            stream,
+                            peer_addr,
+                            acceptor,
+                            registry,
+                            manager,
+                            pending,
+                            db,
+                            share_links,
+                            cluster,
                         )
                         .await
                         {
@@ -441,12 +526,17 @@ impl HttpsServer {
                     });
                 }
                 Err(e) => {
+                    if let TcpIncoming::Channel { .. } = incoming {
+                        info!("HTTPS front listener closed");
+                        return Ok(());
+                    }
                     error!("Failed to accept HTTPS connection: {}", e);
                 }
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_connection(
         stream: TcpStream,
         peer_addr: SocketAddr,
@@ -455,11 +545,13 @@ impl HttpsServer {
         localup_manager: Option<Arc<TunnelConnectionManager>>,
         pending_requests: Option<Arc<PendingRequests>>,
         db: Option<DatabaseConnection>,
+        share_links: Option<Arc<ShareLinkGate>>,
+        cluster: Option<Arc<ClusterNode>>,
     ) -> Result<(), HttpsServerError> {
         debug!("New HTTPS connection from {}", peer_addr);
 
         // TLS handshake
-        let mut tls_stream = match acceptor.accept(stream).await {
+        let tls_stream = match acceptor.accept(stream).await {
             Ok(s) => s,
             Err(e) => {
                 warn!("TLS handshake failed from {}: {}", peer_addr, e);
@@ -472,6 +564,37 @@ impl HttpsServer {
 
         debug!("TLS handshake completed for {}", peer_addr);
 
+        Self::handle_https_stream(
+            tls_stream,
+            peer_addr,
+            route_registry,
+            localup_manager,
+            pending_requests,
+            db,
+            share_links,
+            cluster,
+        )
+        .await
+    }
+
+    /// Route a decrypted HTTPS connection
+    ///
+    /// Requests for hosts claimed by another relay node are forwarded to it when
+    /// `cluster` is set; forwarded requests are handled without it.
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_https_stream<S>(
+        mut tls_stream: S,
+        peer_addr: SocketAddr,
+        route_registry: Arc<RouteRegistry>,
+        localup_manager: Option<Arc<TunnelConnectionManager>>,
+        pending_requests: Option<Arc<PendingRequests>>,
+        db: Option<DatabaseConnection>,
+        share_links: Option<Arc<ShareLinkGate>>,
+        cluster: Option<Arc<ClusterNode>>,
+    ) -> Result<(), HttpsServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         // Read HTTP request
         let mut buffer = vec![0u8; 8192];
         let n = tls_stream.read(&mut buffer).await?;
@@ -551,6 +674,22 @@ impl HttpsServer {
         let target = match route_registry.lookup(&route_key) {
             Ok(t) => t,
             Err(_) => {
+                if let Some(ref cluster) = cluster {
+                    if let Some(owner) = cluster.owner_of(&[route_key]).await {
+                        debug!("Host {} is served by relay node {}", host, owner.node_id);
+                        if let Err(e) = cluster
+                            .forward(&owner, ForwardKind::Https, peer_addr, &buffer, tls_stream)
+                            .await
+                        {
+                            warn!(
+                                "Failed to forward HTTPS request for {} to relay node {}: {}",
+                                host, owner.node_id, e
+                            );
+                        }
+                        return Ok(());
+                    }
+                }
+
                 warn!("No HTTPS route found for host: {}", host);
                 let response = b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nNot Found";
                 tls_stream.write_all(response).await?;
@@ -585,7 +724,15 @@ impl HttpsServer {
         // Forward through tunnel (same as HTTP server)
         if let (Some(manager), Some(pending)) = (localup_manager, pending_requests) {
             Self::handle_localup_request(
-                tls_stream, manager, pending, localup_id, &request, &buffer, db,
+                tls_stream,
+                peer_addr,
+                manager,
+                pending,
+                localup_id,
+                &request,
+                &buffer,
+                db,
+                share_links,
             )
             .await?;
         } else {
@@ -597,51 +744,90 @@ impl HttpsServer {
         Ok(())
     }
 
-    async fn handle_localup_request(
-        mut tls_stream: tokio_rustls::server::TlsStream<TcpStream>,
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_localup_request<S>(
+        mut tls_stream: S,
+        peer_addr: SocketAddr,
         localup_manager: Arc<TunnelConnectionManager>,
         _pending_requests: Arc<PendingRequests>,
         localup_id: &str,
         request: &str,
         request_bytes: &[u8],
         db: Option<DatabaseConnection>,
-    ) -> Result<(), HttpsServerError> {
+        share_links: Option<Arc<ShareLinkGate>>,
+    ) -> Result<(), HttpsServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         // Record start time and generate request ID for database capture
         let request_start = chrono::Utc::now();
         let request_id = uuid::Uuid::new_v4().to_string();
 
-        // Parse request for database capture
-        let (method, uri, headers) = Self::parse_http_request(request);
-        let host = Self::extract_host_from_request(request);
-
-        // Extract body from request bytes (after \r\n\r\n)
-        let body = if let Some(pos) = request.find("\r\n\r\n") {
-            let body_offset = pos + 4;
-            if body_offset < request_bytes.len() {
-                Some(request_bytes[body_offset..].to_vec())
-            } else {
-                None
+        // Visitor connections and traffic count against the tunnel owner's quota and meter
+        let visitor = match localup_manager.admit_visitor(localup_id).await {
+            Ok(permit) => permit,
+            Err(exceeded) => {
+                debug!("Rejecting request for tunnel {}: {}", localup_id, exceeded);
+                tls_stream.write_all(&exceeded.http_response()).await?;
+                return Ok(());
             }
-        } else {
-            None
         };
+        if let Some(ref visitor) = visitor {
+            visitor.record_request();
+        }
 
-        // Check if this is a WebSocket upgrade request
-        let is_websocket = headers.iter().any(|(name, value)| {
-            name.to_lowercase() == "upgrade" && value.to_lowercase() == "websocket"
-        });
+        // Share links grant access without the tunnel's own credentials
+        let mut share_granted = false;
+        if let Some(gate) = share_links {
+            let share_request = localup_http_auth::AuthRequest::parse(request_bytes, true);
+            match gate.check(localup_id, &share_request).await {
+                ShareAccess::Granted => share_granted = true,
+                ShareAccess::Respond(response) => {
+                    tls_stream.write_all(&response).await?;
+                    return Ok(());
+                }
+                ShareAccess::None => {}
+            }
+        }
 
         // Check HTTP authentication if configured for this tunnel
+        let mut buffered_request: Option<Vec<u8>> = None;
+        let mut authenticated_request: Option<Vec<u8>> = None;
         if let Some(authenticator) = localup_manager.get_http_authenticator(localup_id).await {
-            if authenticator.requires_auth() {
-                // Parse headers from request
-                let auth_headers = localup_http_auth::parse_headers_from_request(request_bytes);
+            if authenticator.requires_auth() && !share_granted {
+                // Providers that verify the body (e.g. webhook signatures,
+                // login forms) need all of it
+                let head = localup_http_auth::AuthRequest::parse(request_bytes, true);
+                if let Some(limit) = authenticator.body_limit_for(&head) {
+                    let mut buffer = request_bytes.to_vec();
+                    match localup_http_auth::read_request_body(&mut tls_stream, &mut buffer, limit)
+                        .await
+                    {
+                        Ok(()) => buffered_request = Some(buffer),
+                        Err(localup_http_auth::BodyError::Io(e)) => return Err(e.into()),
+                        Err(e) => {
+                            debug!("Rejecting request for tunnel {}: {}", localup_id, e);
+                            tls_stream.write_all(&e.response()).await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                let auth_bytes = buffered_request.as_deref().unwrap_or(request_bytes);
+                let auth_request = localup_http_auth::AuthRequest::parse(auth_bytes, true)
+                    .with_client_ip(peer_addr.ip());
 
                 // Authenticate
-                match authenticator.authenticate(&auth_headers) {
+                match authenticator.authenticate_request(&auth_request).await {
                     localup_http_auth::AuthResult::Authenticated => {
                         debug!("HTTP auth successful for tunnel: {}", localup_id);
                     }
+                    localup_http_auth::AuthResult::AuthenticatedWithHeaders(auth_headers) => {
+                        debug!("HTTP auth successful for tunnel: {}", localup_id);
+                        authenticated_request = Some(localup_http_auth::inject_request_headers(
+                            auth_bytes,
+                            &auth_headers,
+                        ));
+                    }
                     localup_http_auth::AuthResult::Unauthorized(response) => {
                         debug!(
                             "HTTP auth failed for tunnel: {} (type: {})",
@@ -655,6 +841,38 @@ impl HttpsServer {
             }
         }
 
+        // Forward the buffered request with any identity headers added by the authenticator
+        let forwarded_request = authenticated_request.or(buffered_request);
+        let authenticated_text;
+        let (request, request_bytes) = match forwarded_request.as_deref() {
+            Some(bytes) => {
+                authenticated_text = String::from_utf8_lossy(bytes);
+                (authenticated_text.as_ref(), bytes)
+            }
+            None => (request, request_bytes),
+        };
+
+        // Parse request for database capture
+        let (method, uri, headers) = Self::parse_http_request(request);
+        let host = Self::extract_host_from_request(request);
+
+        // Extract body from request bytes (after \r\n\r\n)
+        let body = if let Some(pos) = request.find("\r\n\r\n") {
+            let body_offset = pos + 4;
+            if body_offset < request_bytes.len() {
+                Some(request_bytes[body_offset..].to_vec())
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        // Check if this is a WebSocket upgrade request
+        let is_websocket = headers.iter().any(|(name, value)| {
+            name.to_lowercase() == "upgrade" && value.to_lowercase() == "websocket"
+        });
+
         // Get tunnel connection
         let connection = match localup_manager.get(localup_id).await {
             Some(c) => c,
@@ -706,8 +924,17 @@ impl HttpsServer {
             }
 
             // Bidirectional streaming for WebSocket
-            let response_capture =
-                Self::proxy_transparent_stream(tls_stream, quic_send, quic_recv, stream_id).await?;
+            if let Some(ref visitor) = visitor {
+                visitor.record(request_bytes.len() as u64, 0);
+            }
+            let response_capture = Self::proxy_transparent_stream(
+                tls_stream,
+                quic_send,
+                quic_recv,
+                stream_id,
+                visitor.as_ref(),
+            )
+            .await?;
 
             // Save to database
             if let Some(ref db_conn) = db {
@@ -800,6 +1027,15 @@ impl HttpsServer {
                 let resp_headers_clone = resp_headers.clone();
                 let resp_body_clone = resp_body.clone();
 
+                if let Some(ref visitor) = visitor {
+                    let response_len = resp_body.as_ref().map_or(0, |b| b.len())
+                        + resp_headers
+                            .iter()
+                            .map(|(n, v)| n.len() + v.len() + 4)
+                            .sum::<usize>();
+                    visitor.record(request_bytes.len() as u64, response_len as u64);
+                }
+
                 // Build HTTP response
                 let status_text = match status {
                     200 => "OK",
@@ -979,12 +1215,16 @@ impl HttpsServer {
     }
 
     /// Bidirectional transparent streaming proxy with response capture
-    async fn proxy_transparent_stream(
-        mut tls_stream: tokio_rustls::server::TlsStream<TcpStream>,
+    async fn proxy_transparent_stream<S>(
+        mut tls_stream: S,
         mut quic_send: localup_transport_quic::QuicSendHalf,
         mut quic_recv: localup_transport_quic::QuicRecvHalf,
         stream_id: u32,
-    ) -> Result<ResponseCapture, HttpsServerError> {
+        visitor: Option<&VisitorPermit>,
+    ) -> Result<ResponseCapture, HttpsServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         let mut client_buffer = vec![0u8; 16384];
         let mut response_buffer = Vec::new();
         let mut headers_parsed = false;
@@ -1003,6 +1243,9 @@ impl HttpsServer {
                         }
                         Ok(n) => {
                             debug!("Forwarding {} bytes from client to tunnel (stream {})", n, stream_id);
+                            if let Some(visitor) = visitor {
+                                visitor.record(n as u64, 0);
+                            }
                             let data_msg = TunnelMessage::HttpStreamData {
                                 stream_id,
                                 data: client_buffer[..n].to_vec(),
@@ -1071,6 +1314,15 @@ impl HttpsServer {
                                 warn!("Failed to flush to client: {}", e);
                                 break;
                             }
+
+                            if let Some(visitor) = visitor {
+                                visitor.record(0, data.len() as u64);
+                                if visitor.is_exhausted() {
+                                    debug!("Traffic quota used up, closing stream {}", stream_id);
+                                    let _ = quic_send.send_message(&TunnelMessage::HttpStreamClose { stream_id }).await;
+                                    break;
+                                }
+                            }
                         }
                         Ok(Some(TunnelMessage::HttpStreamClose { .. })) => {
                             debug!("Tunnel closed stream {}", stream_id);
@@ -0,0 +1,714 @@
+//! Hostname-based TCP ingress via HTTP CONNECT and SOCKS5
+//!
+//! Named TCP tunnels (`Protocol::TcpNamed`) have no public port of their own.
+//! Clients reach them through this listener with `CONNECT db.relay.example.com:5432`
+//! or a SOCKS5 CONNECT to the same name; the requested port is ignored and the
+//! connection is forwarded with `TcpConnect` exactly like a port-based tunnel.
+//!
+//! Credentials are a relay token, sent as `Proxy-Authorization: Bearer <token>`,
+//! as Basic auth with the token as password (what `curl -U` and most proxy
+//! settings send), or as the SOCKS5 username/password. SOCKS5 (RFC 1929) limits
+//! each of those to 255 bytes, which auth tokens issued by the relay API exceed,
+//! so SOCKS5 only works with shorter tokens (e.g. from a custom `AuthValidator`);
+//! relay auth tokens have to use CONNECT.
+
+use base64::Engine;
+use localup_auth::AuthValidator;
+use localup_control::TunnelConnectionManager;
+use localup_router::{RouteKey, RouteRegistry, RouteTarget};
+use sea_orm::DatabaseConnection;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
+
+use crate::server::{StreamIdGenerator, TcpProxyServer, TcpProxyServerError};
+
+/// Largest CONNECT request header accepted
+const MAX_REQUEST_HEADER_SIZE: usize = 8 * 1024;
+
+/// First byte of a SOCKS5 greeting
+const SOCKS5_VERSION: u8 = 0x05;
+
+/// Longest username or password RFC 1929 can carry
+const SOCKS5_MAX_CREDENTIAL_LEN: usize = 255;
+
+#[derive(Debug, Clone)]
+pub struct TcpConnectServerConfig {
+    pub bind_addr: SocketAddr,
+}
+
+/// Why a proxy request was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Denied {
+    NoRoute,
+    Forbidden,
+}
+
+/// Authentication and route lookup shared by both proxy protocols
+struct Gatekeeper {
+    route_registry: Arc<RouteRegistry>,
+    auth_validator: Option<Arc<dyn AuthValidator>>,
+}
+
+impl Gatekeeper {
+    /// Whether `token` is a valid relay token allowed to use TCP tunnels
+    async fn authenticate(&self, token: Option<&str>) -> bool {
+        let Some(ref validator) = self.auth_validator else {
+            return true;
+        };
+        let Some(token) = token else {
+            return false;
+        };
+
+        match validator.validate(token).await {
+            Ok(result) => result.is_protocol_allowed("tcp"),
+            Err(e) => {
+                debug!("Proxy token rejected: {}", e);
+                false
+            }
+        }
+    }
+
+    fn route(&self, hostname: &str, peer_addr: &SocketAddr) -> Result<RouteTarget, Denied> {
+        let target = self
+            .route_registry
+            .lookup(&RouteKey::TcpHost(hostname.to_string()))
+            .map_err(|_| Denied::NoRoute)?;
+
+        if !target.is_ip_allowed(peer_addr) {
+            return Err(Denied::Forbidden);
+        }
+        Ok(target)
+    }
+}
+
+/// A proxy request that passed authentication and routing
+struct Accepted {
+    hostname: String,
+    target: RouteTarget,
+    /// Bytes the client sent after its request, to be forwarded first
+    initial_data: Vec<u8>,
+}
+
+/// HTTP CONNECT / SOCKS5 listener for hostname-routed TCP tunnels
+pub struct TcpConnectServer {
+    config: TcpConnectServerConfig,
+    gatekeeper: Arc<Gatekeeper>,
+    localup_manager: Arc<TunnelConnectionManager>,
+    stream_id_gen: StreamIdGenerator,
+    db: Option<DatabaseConnection>,
+}
+
+impl TcpConnectServer {
+    pub fn new(
+        config: TcpConnectServerConfig,
+        route_registry: Arc<RouteRegistry>,
+        localup_manager: Arc<TunnelConnectionManager>,
+    ) -> Self {
+        Self {
+            config,
+            gatekeeper: Arc::new(Gatekeeper {
+                route_registry,
+                auth_validator: None,
+            }),
+            localup_manager,
+            stream_id_gen: StreamIdGenerator::new(),
+            db: None,
+        }
+    }
+
+    /// Require a valid relay token on every proxy request
+    pub fn with_auth_validator(mut self, validator: Arc<dyn AuthValidator>) -> Self {
+        self.gatekeeper = Arc::new(Gatekeeper {
+            route_registry: self.gatekeeper.route_registry.clone(),
+            auth_validator: Some(validator),
+        });
+        self
+    }
+
+    pub fn with_database(mut self, db: DatabaseConnection) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    pub async fn start(self) -> Result<(), TcpProxyServerError> {
+        let listener = TcpListener::bind(self.config.bind_addr)
+            .await
+            .map_err(|e| TcpProxyServerError::BindError {
+                address: self.config.bind_addr.ip().to_string(),
+                port: self.config.bind_addr.port(),
+                reason: e.to_string(),
+            })?;
+        let listen_port = listener.local_addr()?.port();
+
+        if self.gatekeeper.auth_validator.is_none() {
+            warn!("TCP CONNECT proxy running without authentication");
+        }
+        info!(
+            "✅ TCP CONNECT/SOCKS5 proxy listening on {} (hostname-routed TCP tunnels)",
+            self.config.bind_addr
+        );
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer_addr)) => {
+                    debug!("New proxy connection from {}", peer_addr);
+
+                    let gatekeeper = self.gatekeeper.clone();
+                    let localup_manager = self.localup_manager.clone();
+                    let stream_id_gen = self.stream_id_gen.clone();
+                    let db = self.db.clone();
+
+                    tokio::spawn(async move {
+                        if let Err(e) = Self::handle_connection(
+                            stream,
+                            peer_addr,
+                            listen_port,
+                            gatekeeper,
+                            localup_manager,
+                            stream_id_gen,
+                            db,
+                        )
+                        .await
+                        {
+                            debug!("Proxy connection from {} failed: {}", peer_addr, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept proxy connection: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn handle_connection(
+        mut stream: TcpStream,
+        peer_addr: SocketAddr,
+        listen_port: u16,
+        gatekeeper: Arc<Gatekeeper>,
+        localup_manager: Arc<TunnelConnectionManager>,
+        stream_id_gen: StreamIdGenerator,
+        db: Option<DatabaseConnection>,
+    ) -> Result<(), TcpProxyServerError> {
+        let accepted = negotiate(&mut stream, &gatekeeper, &peer_addr).await?;
+
+        info!(
+            "🔀 Proxying {} to TCP hostname {} (tunnel: {})",
+            peer_addr, accepted.hostname, accepted.target.localup_id
+        );
+
+        TcpProxyServer::handle_tcp_connection(
+            stream,
+            peer_addr,
+            accepted.target.localup_id,
+            listen_port,
+            localup_manager,
+            stream_id_gen,
+            db,
+            accepted.initial_data,
+            Some(format!("host:{}", accepted.hostname)),
+        )
+        .await
+    }
+}
+
+/// Run the HTTP CONNECT or SOCKS5 handshake, picked by the first byte
+async fn negotiate<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    gatekeeper: &Gatekeeper,
+    peer_addr: &SocketAddr,
+) -> Result<Accepted, TcpProxyServerError> {
+    let first = stream.read_u8().await?;
+    if first == SOCKS5_VERSION {
+        socks5_connect(stream, gatekeeper, peer_addr).await
+    } else {
+        http_connect(stream, first, gatekeeper, peer_addr).await
+    }
+}
+
+fn handshake_error(message: impl Into<String>) -> TcpProxyServerError {
+    TcpProxyServerError::HandshakeError(message.into())
+}
+
+/// Handle `CONNECT host:port HTTP/1.1`; `first` is the already-read first byte
+async fn http_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    first: u8,
+    gatekeeper: &Gatekeeper,
+    peer_addr: &SocketAddr,
+) -> Result<Accepted, TcpProxyServerError> {
+    let mut buf = vec![first];
+    let mut chunk = [0u8; 1024];
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > MAX_REQUEST_HEADER_SIZE {
+            stream
+                .write_all(
+                    b"HTTP/1.1 431 Request Header Fields Too Large\r\nContent-Length: 0\r\n\r\n",
+                )
+                .await?;
+            return Err(handshake_error("CONNECT request header too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(handshake_error("client closed before sending a request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let initial_data = buf[header_end..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (method, authority) = (parts.next(), parts.next());
+
+    if method != Some("CONNECT") {
+        stream
+            .write_all(
+                b"HTTP/1.1 405 Method Not Allowed\r\nAllow: CONNECT\r\nContent-Length: 0\r\n\r\n",
+            )
+            .await?;
+        return Err(handshake_error(format!(
+            "unsupported request: {}",
+            request_line
+        )));
+    }
+    let Some(hostname) = authority.map(connect_hostname).filter(|h| !h.is_empty()) else {
+        stream
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
+            .await?;
+        return Err(handshake_error("CONNECT without a target host"));
+    };
+
+    let token = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("proxy-authorization"))
+        .and_then(|(_, value)| proxy_token(value));
+
+    if !gatekeeper.authenticate(token.as_deref()).await {
+        stream
+            .write_all(
+                b"HTTP/1.1 407 Proxy Authentication Required\r\n\
+                  Proxy-Authenticate: Basic realm=\"localup\"\r\n\
+                  Content-Length: 0\r\n\r\n",
+            )
+            .await?;
+        return Err(handshake_error(format!(
+            "proxy authentication failed for {}",
+            hostname
+        )));
+    }
+
+    match gatekeeper.route(&hostname, peer_addr) {
+        Ok(target) => {
+            stream
+                .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                .await?;
+            Ok(Accepted {
+                hostname,
+                target,
+                initial_data,
+            })
+        }
+        Err(denied) => {
+            let response: &[u8] = match denied {
+                Denied::Forbidden => b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n",
+                Denied::NoRoute => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+            };
+            stream.write_all(response).await?;
+            Err(handshake_error(format!(
+                "{:?} for TCP hostname {}",
+                denied, hostname
+            )))
+        }
+    }
+}
+
+/// Handle a SOCKS5 (RFC 1928) CONNECT; the version byte was already read
+async fn socks5_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    gatekeeper: &Gatekeeper,
+    peer_addr: &SocketAddr,
+) -> Result<Accepted, TcpProxyServerError> {
+    const NO_AUTH: u8 = 0x00;
+    const USERNAME_PASSWORD: u8 = 0x02;
+    const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+
+    let method_count = stream.read_u8().await? as usize;
+    let mut methods = vec![0u8; method_count];
+    stream.read_exact(&mut methods).await?;
+
+    // Token-protected relays only accept username/password (the token is the password)
+    let method = if gatekeeper.auth_validator.is_none() && methods.contains(&NO_AUTH) {
+        NO_AUTH
+    } else if methods.contains(&USERNAME_PASSWORD) {
+        USERNAME_PASSWORD
+    } else {
+        NO_ACCEPTABLE_METHOD
+    };
+    stream.write_all(&[SOCKS5_VERSION, method]).await?;
+    if method == NO_ACCEPTABLE_METHOD {
+        return Err(handshake_error("no acceptable SOCKS5 auth method"));
+    }
+
+    if method == USERNAME_PASSWORD {
+        // RFC 1929 sub-negotiation
+        let _version = stream.read_u8().await?;
+        let username = read_socks5_string(stream).await?;
+        let password = read_socks5_string(stream).await?;
+        let token = if password.is_empty() {
+            username
+        } else {
+            password
+        };
+
+        let authenticated = gatekeeper.authenticate(Some(&token)).await;
+        stream
+            .write_all(&[0x01, if authenticated { 0x00 } else { 0x01 }])
+            .await?;
+        if !authenticated {
+            // Clients truncate or refuse longer tokens, so a full field hints at that
+            if token.len() == SOCKS5_MAX_CREDENTIAL_LEN {
+                return Err(handshake_error(
+                    "SOCKS5 authentication failed: the token may have been cut to 255 bytes \
+                     (relay auth tokens only fit HTTP CONNECT)",
+                ));
+            }
+            return Err(handshake_error("SOCKS5 authentication failed"));
+        }
+    }
+
+    // Request: VER CMD RSV ATYP DST.ADDR DST.PORT
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let (command, address_type) = (header[1], header[3]);
+
+    let hostname = match address_type {
+        // Domain name: only names can match a tunnel
+        0x03 => read_socks5_string(stream).await?,
+        0x01 | 0x04 => {
+            let len = if address_type == 0x01 { 4 } else { 16 };
+            let mut addr = vec![0u8; len + 2];
+            stream.read_exact(&mut addr).await?;
+            socks5_reply(stream, 0x08).await?;
+            return Err(handshake_error(
+                "SOCKS5 request by IP address (use remote DNS, e.g. socks5h://)",
+            ));
+        }
+        other => {
+            socks5_reply(stream, 0x08).await?;
+            return Err(handshake_error(format!(
+                "unknown SOCKS5 address type {}",
+                other
+            )));
+        }
+    };
+    let _port = stream.read_u16().await?;
+
+    if command != 0x01 {
+        socks5_reply(stream, 0x07).await?;
+        return Err(handshake_error(format!(
+            "unsupported SOCKS5 command {}",
+            command
+        )));
+    }
+
+    let hostname = connect_hostname(&hostname);
+    match gatekeeper.route(&hostname, peer_addr) {
+        Ok(target) => {
+            socks5_reply(stream, 0x00).await?;
+            Ok(Accepted {
+                hostname,
+                target,
+                initial_data: Vec::new(),
+            })
+        }
+        Err(denied) => {
+            let reply = match denied {
+                Denied::Forbidden => 0x02,
+                Denied::NoRoute => 0x04,
+            };
+            socks5_reply(stream, reply).await?;
+            Err(handshake_error(format!(
+                "{:?} for TCP hostname {}",
+                denied, hostname
+            )))
+        }
+    }
+}
+
+async fn read_socks5_string<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> Result<String, TcpProxyServerError> {
+    let len = stream.read_u8().await? as usize;
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes).await?;
+    String::from_utf8(bytes).map_err(|_| handshake_error("invalid UTF-8 in SOCKS5 request"))
+}
+
+async fn socks5_reply<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    reply: u8,
+) -> Result<(), TcpProxyServerError> {
+    // Bound address is not meaningful for tunnels: report 0.0.0.0:0
+    stream
+        .write_all(&[SOCKS5_VERSION, reply, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+/// Hostname from a CONNECT target (`host:port`), lowercased without the port
+fn connect_hostname(authority: &str) -> String {
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    host.trim_end_matches('.').to_lowercase()
+}
+
+/// Token from a `Proxy-Authorization` value: Bearer token, or the Basic password
+/// (the username when the password is empty)
+fn proxy_token(value: &str) -> Option<String> {
+    let (scheme, credentials) = value.trim().split_once(' ')?;
+    let credentials = credentials.trim();
+
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return Some(credentials.to_string());
+    }
+    if scheme.eq_ignore_ascii_case("basic") {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(credentials)
+            .ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (username, password) = decoded.split_once(':').unwrap_or((&decoded, ""));
+        let token = if password.is_empty() {
+            username
+        } else {
+            password
+        };
+        return Some(token.to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use localup_auth::{JwtClaims, JwtValidator};
+    use localup_proto::IpFilter;
+
+    const SECRET: &[u8] = b"connect-proxy-secret";
+
+    fn gatekeeper(with_auth: bool) -> Gatekeeper {
+        let route_registry = Arc::new(RouteRegistry::new());
+        route_registry
+            .register(
+                RouteKey::TcpHost("db.relay.test".to_string()),
+                RouteTarget {
+                    localup_id: "db-tunnel".to_string(),
+                    target_addr: "tunnel:db-tunnel".to_string(),
+                    metadata: Some("via-tunnel".to_string()),
+                    ip_filter: IpFilter::new(),
+                },
+            )
+            .unwrap();
+
+        Gatekeeper {
+            route_registry,
+            auth_validator: with_auth
+                .then(|| Arc::new(JwtValidator::new(SECRET)) as Arc<dyn AuthValidator>),
+        }
+    }
+
+    /// An auth token with the claims `create_auth_token` in the relay API sets
+    fn api_token() -> String {
+        let claims = JwtClaims::new(
+            uuid::Uuid::new_v4().to_string(),
+            "localup-relay".to_string(),
+            "localup-tunnel".to_string(),
+            chrono::Duration::days(36500),
+        )
+        .with_user_id(uuid::Uuid::new_v4().to_string())
+        .with_token_type("auth".to_string());
+        JwtValidator::encode(SECRET, &claims).unwrap()
+    }
+
+    /// The shortest token the test validator accepts
+    fn short_token() -> String {
+        let claims = JwtClaims::new(
+            "db".to_string(),
+            "l".to_string(),
+            "l".to_string(),
+            chrono::Duration::hours(1),
+        );
+        JwtValidator::encode(SECRET, &claims).unwrap()
+    }
+
+    /// RFC 1929 username/password request
+    fn socks5_credentials(username: &str, password: &str) -> Vec<u8> {
+        let mut auth = vec![0x01];
+        for field in [username, password] {
+            auth.push(u8::try_from(field.len()).expect("SOCKS5 credential over 255 bytes"));
+            auth.extend_from_slice(field.as_bytes());
+        }
+        auth
+    }
+
+    fn peer() -> SocketAddr {
+        "203.0.113.7:40000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_proxy_token() {
+        assert_eq!(proxy_token("Bearer abc"), Some("abc".to_string()));
+        let basic = base64::engine::general_purpose::STANDARD.encode("user:abc");
+        assert_eq!(
+            proxy_token(&format!("Basic {}", basic)),
+            Some("abc".to_string())
+        );
+        let username_only = base64::engine::general_purpose::STANDARD.encode("abc:");
+        assert_eq!(
+            proxy_token(&format!("basic {}", username_only)),
+            Some("abc".to_string())
+        );
+        assert_eq!(proxy_token("Digest abc"), None);
+    }
+
+    #[test]
+    fn test_connect_hostname() {
+        assert_eq!(connect_hostname("DB.relay.test:5432"), "db.relay.test");
+        assert_eq!(connect_hostname("db.relay.test."), "db.relay.test");
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_keeps_pipelined_bytes() {
+        let gatekeeper = gatekeeper(true);
+        let (mut client, mut server) = tokio::io::duplex(4096);
+
+        let request = format!(
+            "CONNECT db.relay.test:5432 HTTP/1.1\r\nHost: db.relay.test:5432\r\n\
+             Proxy-Authorization: Bearer {}\r\n\r\nSTARTUP",
+            api_token()
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let accepted = negotiate(&mut server, &gatekeeper, &peer()).await.unwrap();
+        assert_eq!(accepted.hostname, "db.relay.test");
+        assert_eq!(accepted.target.localup_id, "db-tunnel");
+        assert_eq!(accepted.initial_data, b"STARTUP");
+
+        let mut response = [0u8; 39];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(
+            &response[..],
+            b"HTTP/1.1 200 Connection Established\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_requires_token() {
+        let gatekeeper = gatekeeper(true);
+        let (mut client, mut server) = tokio::io::duplex(4096);
+
+        client
+            .write_all(b"CONNECT db.relay.test:5432 HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(negotiate(&mut server, &gatekeeper, &peer()).await.is_err());
+
+        let mut response = String::new();
+        drop(server);
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 407"));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_unknown_host() {
+        let gatekeeper = gatekeeper(false);
+        let (mut client, mut server) = tokio::io::duplex(4096);
+
+        client
+            .write_all(b"CONNECT other.relay.test:22 HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(negotiate(&mut server, &gatekeeper, &peer()).await.is_err());
+
+        let mut response = String::new();
+        drop(server);
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_with_token_password() {
+        let gatekeeper = gatekeeper(true);
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let token = short_token();
+
+        let handshake = tokio::spawn(async move {
+            let result = negotiate(&mut server, &gatekeeper, &peer()).await;
+            result.map(|accepted| accepted.target.localup_id)
+        });
+
+        // Greeting offering no-auth and username/password
+        client.write_all(&[0x05, 0x02, 0x00, 0x02]).await.unwrap();
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [0x05, 0x02]);
+
+        client
+            .write_all(&socks5_credentials("user", &token))
+            .await
+            .unwrap();
+        let mut auth_reply = [0u8; 2];
+        client.read_exact(&mut auth_reply).await.unwrap();
+        assert_eq!(auth_reply, [0x01, 0x00]);
+
+        let hostname = b"db.relay.test";
+        let mut request = vec![0x05, 0x01, 0x00, 0x03, hostname.len() as u8];
+        request.extend_from_slice(hostname);
+        request.extend_from_slice(&5432u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[1], 0x00);
+        assert_eq!(handshake.await.unwrap().unwrap(), "db-tunnel");
+    }
+
+    #[tokio::test]
+    async fn test_socks5_cannot_carry_api_tokens() {
+        let token = api_token();
+        assert!(token.len() > SOCKS5_MAX_CREDENTIAL_LEN);
+
+        // What a client that cuts the password to fit sends
+        let gatekeeper = gatekeeper(true);
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let handshake =
+            tokio::spawn(async move { negotiate(&mut server, &gatekeeper, &peer()).await.err() });
+
+        client.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+        let mut choice = [0u8; 2];
+        client.read_exact(&mut choice).await.unwrap();
+        client
+            .write_all(&socks5_credentials(
+                "user",
+                &token[..SOCKS5_MAX_CREDENTIAL_LEN],
+            ))
+            .await
+            .unwrap();
+        let mut auth_reply = [0u8; 2];
+        client.read_exact(&mut auth_reply).await.unwrap();
+        assert_eq!(auth_reply, [0x01, 0x01]);
+
+        let error = handshake.await.unwrap().unwrap().to_string();
+        assert!(error.contains("HTTP CONNECT"), "{}", error);
+    }
+}
@@ -1,8 +1,11 @@
 //! TCP Proxy Server
 //!
 //! This crate implements a TCP proxy server that forwards raw TCP connections through tunnels.
-//! Each tunnel gets its own dedicated port on the exit node.
+//! Each tunnel gets its own dedicated port on the exit node, except hostname-routed
+//! tunnels, which share the HTTP CONNECT / SOCKS5 listener in [`connect`].
 
+pub mod connect;
 mod server;
 
+pub use connect::{TcpConnectServer, TcpConnectServerConfig};
 pub use server::{TcpProxyServer, TcpProxyServerConfig, TcpProxyServerError};
@@ -4,7 +4,7 @@
 //! Each tunnel gets its own dedicated TcpProxyServer instance.
 
 use localup_control::TunnelConnectionManager;
-use localup_proto::TunnelMessage;
+use localup_proto::{TcpLimits, TunnelMessage};
 use localup_transport::{TransportConnection, TransportStream};
 use sea_orm::DatabaseConnection;
 use socket2::{Domain, Protocol, Socket, Type};
@@ -14,6 +14,7 @@ use std::sync::Arc;
 use thiserror::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
 use tracing::{debug, error, info, warn};
 
 #[derive(Debug, Error)]
@@ -27,6 +28,12 @@ pub enum TcpProxyServerError {
     #[error("Tunnel error: {0}")]
     TunnelError(String),
 
+    #[error("Proxy handshake failed: {0}")]
+    HandshakeError(String),
+
+    #[error("Connection limit exceeded: {0}")]
+    LimitExceeded(String),
+
     #[error("Failed to bind to {address}: {reason}\n\nTroubleshooting:\n  • Check if another process is using this port: lsof -i :{port}\n  • Try using a different address or port")]
     BindError {
         address: String,
@@ -59,6 +66,37 @@ impl StreamIdGenerator {
     }
 }
 
+/// Disconnect reasons recorded in `captured_tcp_connection`
+const CLIENT_CLOSED: &str = "client_closed";
+const IDLE_TIMEOUT: &str = "idle_timeout";
+const MAX_LIFETIME: &str = "max_lifetime";
+const QUOTA_EXHAUSTED: &str = "quota_monthly_bytes";
+
+/// Check a connection's idle and lifetime limits at `now`
+///
+/// Returns the reason the connection must be closed, or when to check again
+/// (`None` if neither limit is set).
+fn check_deadlines(
+    limits: &TcpLimits,
+    opened_at: Instant,
+    last_activity: Instant,
+    now: Instant,
+) -> Result<Option<Instant>, &'static str> {
+    let lifetime_deadline = limits.max_lifetime().map(|d| opened_at + d);
+    let idle_deadline = limits.idle_timeout().map(|d| last_activity + d);
+
+    if lifetime_deadline.is_some_and(|deadline| now >= deadline) {
+        return Err(MAX_LIFETIME);
+    }
+    if idle_deadline.is_some_and(|deadline| now >= deadline) {
+        return Err(IDLE_TIMEOUT);
+    }
+    Ok(match (lifetime_deadline, idle_deadline) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    })
+}
+
 /// Tracks metrics for an individual TCP connection
 struct ConnectionMetrics {
     connection_id: String,
@@ -165,6 +203,8 @@ impl TcpProxyServer {
                             localup_manager,
                             stream_id_gen,
                             db,
+                            Vec::new(),
+                            None,
                         )
                         .await
                         {
@@ -179,7 +219,13 @@ impl TcpProxyServer {
         }
     }
 
-    async fn handle_tcp_connection(
+    /// Forward one client connection through the tunnel
+    ///
+    /// `initial_data` holds bytes already read from the client (e.g. sent right
+    /// after a CONNECT request) and `route_label` is recorded next to the client
+    /// address for hostname-routed connections.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn handle_tcp_connection(
         client_stream: TcpStream,
         peer_addr: SocketAddr,
         localup_id: String,
@@ -187,6 +233,8 @@ impl TcpProxyServer {
         localup_manager: Arc<TunnelConnectionManager>,
         stream_id_gen: StreamIdGenerator,
         db: Option<DatabaseConnection>,
+        initial_data: Vec<u8>,
+        route_label: Option<String>,
     ) -> Result<(), TcpProxyServerError> {
         // Get tunnel QUIC connection (not sender!)
         let localup_connection = match localup_manager.get(&localup_id).await {
@@ -199,6 +247,57 @@ impl TcpProxyServer {
             }
         };
 
+        let limiter = localup_manager.get_tcp_limiter(&localup_id).await;
+        let limits = limiter
+            .as_ref()
+            .map(|limiter| *limiter.limits())
+            .unwrap_or_default();
+        // Held until the connection closes
+        let _permit = match limiter.map(|limiter| limiter.try_acquire(peer_addr.ip())) {
+            Some(Err(exceeded)) => {
+                warn!(
+                    "Rejecting TCP connection from {} to tunnel {}: {}",
+                    peer_addr, localup_id, exceeded
+                );
+                if let Some(ref db_conn) = db {
+                    Self::record_rejected_connection(
+                        db_conn,
+                        &localup_id,
+                        peer_addr,
+                        route_label.as_deref(),
+                        target_port,
+                        exceeded.reason(),
+                    )
+                    .await;
+                }
+                return Err(TcpProxyServerError::LimitExceeded(exceeded.to_string()));
+            }
+            Some(Ok(permit)) => Some(permit),
+            None => None,
+        };
+        // Visitor connections and traffic count against the tunnel owner's quota and meter
+        let visitor = match localup_manager.admit_visitor(&localup_id).await {
+            Ok(permit) => permit.map(Arc::new),
+            Err(exceeded) => {
+                warn!(
+                    "Rejecting TCP connection from {} to tunnel {}: {}",
+                    peer_addr, localup_id, exceeded
+                );
+                if let Some(ref db_conn) = db {
+                    Self::record_rejected_connection(
+                        db_conn,
+                        &localup_id,
+                        peer_addr,
+                        route_label.as_deref(),
+                        target_port,
+                        exceeded.reason(),
+                    )
+                    .await;
+                }
+                return Err(TcpProxyServerError::LimitExceeded(exceeded.to_string()));
+            }
+        };
+
         // Open a NEW QUIC stream for this TCP connection
         let mut quic_stream = match localup_connection.open_stream().await {
             Ok(stream) => stream,
@@ -253,13 +352,35 @@ impl TcpProxyServer {
 
         debug!("✅ TcpConnect sent on stream {}", quic_stream.stream_id());
 
+        if !initial_data.is_empty() {
+            metrics
+                .bytes_received
+                .fetch_add(initial_data.len() as u64, Ordering::Relaxed);
+            if let Some(ref visitor) = visitor {
+                visitor.record(initial_data.len() as u64, 0);
+            }
+            let data_msg = TunnelMessage::TcpData {
+                stream_id,
+                data: initial_data,
+            };
+            if let Err(e) = quic_stream.send_message(&data_msg).await {
+                error!("Failed to send initial TcpData: {}", e);
+                return Err(TcpProxyServerError::TunnelError(format!(
+                    "Send error: {}",
+                    e
+                )));
+            }
+        }
+
+        let client_addr = Self::client_addr(peer_addr, route_label.as_deref());
+
         // Save active connection to database (with disconnected_at = NULL)
         if let Some(ref db_conn) = db {
             let active_connection =
                 localup_relay_db::entities::captured_tcp_connection::ActiveModel {
                     id: sea_orm::Set(connection_id.clone()),
                     localup_id: sea_orm::Set(localup_id.clone()),
-                    client_addr: sea_orm::Set(peer_addr.to_string()),
+                    client_addr: sea_orm::Set(client_addr),
                     target_port: sea_orm::Set(target_port as i32),
                     bytes_received: sea_orm::Set(0),
                     bytes_sent: sea_orm::Set(0),
@@ -285,6 +406,10 @@ impl TcpProxyServer {
             }
         }
 
+        // Milliseconds since `opened_at` of the last data in either direction
+        let opened_at = Instant::now();
+        let last_activity = Arc::new(AtomicU64::new(0));
+
         // Split BOTH streams for true bidirectional communication WITHOUT MUTEXES!
         let (mut client_read, mut client_write) = client_stream.into_split();
         let (mut quic_send, mut quic_recv) = quic_stream.split();
@@ -292,6 +417,8 @@ impl TcpProxyServer {
         // Task to read from TCP client and send to QUIC stream
         // Now owns quic_send exclusively - no mutex needed!
         let bytes_received_clone = metrics.bytes_received.clone();
+        let last_activity_clone = last_activity.clone();
+        let visitor_clone = visitor.clone();
         let client_to_tunnel = tokio::spawn(async move {
             let mut buffer = vec![0u8; 8192];
             loop {
@@ -309,6 +436,11 @@ impl TcpProxyServer {
 
                         // Track bytes received from client
                         bytes_received_clone.fetch_add(n as u64, Ordering::Relaxed);
+                        last_activity_clone
+                            .store(opened_at.elapsed().as_millis() as u64, Ordering::Relaxed);
+                        if let Some(ref visitor) = visitor_clone {
+                            visitor.record(n as u64, 0);
+                        }
 
                         // Send data on QUIC stream - NO MUTEX!
                         let data_msg = TunnelMessage::TcpData {
@@ -325,6 +457,13 @@ impl TcpProxyServer {
                             break;
                         }
                         debug!("✅ TcpData sent successfully (stream {})", stream_id);
+
+                        if visitor_clone.as_ref().is_some_and(|q| q.is_exhausted()) {
+                            let close_msg = TunnelMessage::TcpClose { stream_id };
+                            let _ = quic_send.send_message(&close_msg).await;
+                            let _ = quic_send.finish().await;
+                            break;
+                        }
                     }
                     Err(e) => {
                         error!("Error reading from TCP client: {}", e);
@@ -337,7 +476,9 @@ impl TcpProxyServer {
         // Task to receive from QUIC stream and send to TCP client
         // Now owns quic_recv exclusively - no mutex needed!
         let bytes_sent_clone = metrics.bytes_sent.clone();
+        let last_activity_clone = last_activity.clone();
         let client_to_localup_handle = client_to_tunnel.abort_handle();
+        let visitor_clone = visitor.clone();
         let localup_to_client = tokio::spawn(async move {
             loop {
                 // NO MUTEX - direct access to quic_recv!
@@ -360,6 +501,11 @@ impl TcpProxyServer {
 
                         // Track bytes sent to client
                         bytes_sent_clone.fetch_add(data.len() as u64, Ordering::Relaxed);
+                        last_activity_clone
+                            .store(opened_at.elapsed().as_millis() as u64, Ordering::Relaxed);
+                        if let Some(ref visitor) = visitor_clone {
+                            visitor.record(0, data.len() as u64);
+                        }
 
                         if let Err(e) = client_write.write_all(&data).await {
                             error!("Failed to write to TCP client: {}", e);
@@ -370,6 +516,11 @@ impl TcpProxyServer {
                             error!("Failed to flush TCP client stream: {}", e);
                             break;
                         }
+
+                        if visitor_clone.as_ref().is_some_and(|q| q.is_exhausted()) {
+                            client_to_localup_handle.abort();
+                            break;
+                        }
                     }
                     Ok(Some(TunnelMessage::TcpClose { stream_id: _ })) => {
                         debug!("Received TcpClose from tunnel (stream {})", stream_id);
@@ -441,8 +592,47 @@ impl TcpProxyServer {
             None
         };
 
-        // Wait for both data transfer tasks to complete
-        let _ = tokio::join!(client_to_tunnel, localup_to_client);
+        // Wait for both data transfer tasks to complete, closing the connection
+        // early if it goes idle or outlives its maximum lifetime
+        let client_to_tunnel_handle = client_to_tunnel.abort_handle();
+        let localup_to_client_handle = localup_to_client.abort_handle();
+        let transfer = async {
+            let _ = tokio::join!(client_to_tunnel, localup_to_client);
+        };
+        tokio::pin!(transfer);
+
+        let disconnect_reason = loop {
+            let last =
+                opened_at + std::time::Duration::from_millis(last_activity.load(Ordering::Relaxed));
+            match check_deadlines(&limits, opened_at, last, Instant::now()) {
+                Ok(None) => {
+                    transfer.await;
+                    break CLIENT_CLOSED;
+                }
+                Ok(Some(deadline)) => {
+                    tokio::select! {
+                        _ = &mut transfer => break CLIENT_CLOSED,
+                        _ = tokio::time::sleep_until(deadline) => {}
+                    }
+                }
+                Err(reason) => {
+                    info!(
+                        "Closing TCP connection {} to tunnel {}: {}",
+                        peer_addr, localup_id, reason
+                    );
+                    client_to_tunnel_handle.abort();
+                    localup_to_client_handle.abort();
+                    break reason;
+                }
+            }
+        };
+
+        let disconnect_reason = match visitor {
+            Some(ref visitor) if disconnect_reason == CLIENT_CLOSED && visitor.is_exhausted() => {
+                QUOTA_EXHAUSTED
+            }
+            _ => disconnect_reason,
+        };
 
         // Stop the metrics update task
         if let Some(task) = metrics_update_task {
@@ -470,7 +660,7 @@ impl TcpProxyServer {
                     connected_at: sea_orm::NotSet, // Don't update
                     disconnected_at: sea_orm::Set(Some(disconnected_at.into())),
                     duration_ms: sea_orm::Set(Some(duration_ms)),
-                    disconnect_reason: sea_orm::Set(Some("client_closed".to_string())),
+                    disconnect_reason: sea_orm::Set(Some(disconnect_reason.to_string())),
                 };
 
             use sea_orm::ActiveModelTrait;
@@ -489,6 +679,48 @@ impl TcpProxyServer {
 
         Ok(())
     }
+
+    /// Client address as recorded in `captured_tcp_connection`
+    fn client_addr(peer_addr: SocketAddr, route_label: Option<&str>) -> String {
+        match route_label {
+            Some(label) => format!("{}|{}", peer_addr, label),
+            None => peer_addr.to_string(),
+        }
+    }
+
+    /// Record a connection refused by the tunnel's connection limits
+    async fn record_rejected_connection(
+        db: &DatabaseConnection,
+        localup_id: &str,
+        peer_addr: SocketAddr,
+        route_label: Option<&str>,
+        target_port: u16,
+        reason: &str,
+    ) {
+        let now = chrono::Utc::now();
+        let rejected_connection =
+            localup_relay_db::entities::captured_tcp_connection::ActiveModel {
+                id: sea_orm::Set(uuid::Uuid::new_v4().to_string()),
+                localup_id: sea_orm::Set(localup_id.to_string()),
+                client_addr: sea_orm::Set(Self::client_addr(peer_addr, route_label)),
+                target_port: sea_orm::Set(target_port as i32),
+                bytes_received: sea_orm::Set(0),
+                bytes_sent: sea_orm::Set(0),
+                connected_at: sea_orm::Set(now.into()),
+                disconnected_at: sea_orm::Set(Some(now.into())),
+                duration_ms: sea_orm::Set(Some(0)),
+                disconnect_reason: sea_orm::Set(Some(format!("rejected: {}", reason))),
+            };
+
+        use sea_orm::EntityTrait;
+        if let Err(e) =
+            localup_relay_db::entities::prelude::CapturedTcpConnection::insert(rejected_connection)
+                .exec(db)
+                .await
+        {
+            warn!("Failed to save rejected TCP connection: {}", e);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -505,6 +737,50 @@ mod tests {
         assert_eq!(config.localup_id, "test-tunnel");
     }
 
+    #[test]
+    fn test_check_deadlines() {
+        let opened_at = Instant::now();
+        let limits = TcpLimits {
+            idle_timeout_secs: Some(30),
+            max_lifetime_secs: Some(60),
+            ..Default::default()
+        };
+        let secs = std::time::Duration::from_secs;
+
+        // Idle deadline comes first while the connection is fresh
+        assert_eq!(
+            check_deadlines(&limits, opened_at, opened_at, opened_at + secs(10)),
+            Ok(Some(opened_at + secs(30)))
+        );
+        assert_eq!(
+            check_deadlines(&limits, opened_at, opened_at, opened_at + secs(30)),
+            Err(IDLE_TIMEOUT)
+        );
+        // Activity pushes the idle deadline past the lifetime
+        assert_eq!(
+            check_deadlines(
+                &limits,
+                opened_at,
+                opened_at + secs(45),
+                opened_at + secs(50)
+            ),
+            Ok(Some(opened_at + secs(60)))
+        );
+        assert_eq!(
+            check_deadlines(
+                &limits,
+                opened_at,
+                opened_at + secs(59),
+                opened_at + secs(60)
+            ),
+            Err(MAX_LIFETIME)
+        );
+        assert_eq!(
+            check_deadlines(&TcpLimits::default(), opened_at, opened_at, opened_at),
+            Ok(None)
+        );
+    }
+
     #[test]
     fn test_stream_id_generator() {
         let gen = StreamIdGenerator::new();
@@ -1,7 +1,10 @@
 //! TCP server implementation
 
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use localup_control::{PendingRequests, TunnelConnectionManager};
+use localup_control::{
+    ClusterIngress, ClusterNode, ForwardKind, PendingRequests, ShareAccess, ShareLinkGate,
+    TunnelConnectionManager, VisitorPermit,
+};
 use localup_proto::TunnelMessage;
 use localup_router::{RouteKey, RouteRegistry};
 use localup_transport::TransportConnection;
@@ -9,7 +12,7 @@ use sea_orm::DatabaseConnection;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use thiserror::Error;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 use tracing::{debug, error, info, warn}; // For open_stream() method
 
@@ -55,6 +58,8 @@ pub struct TcpServer {
     localup_manager: Option<Arc<TunnelConnectionManager>>,
     pending_requests: Arc<PendingRequests>,
     db: Option<DatabaseConnection>,
+    share_links: Option<Arc<ShareLinkGate>>,
+    cluster: Option<Arc<ClusterNode>>,
 }
 
 impl TcpServer {
@@ -65,6 +70,8 @@ impl TcpServer {
             localup_manager: None,
             pending_requests: Arc::new(PendingRequests::new()),
             db: None,
+            share_links: None,
+            cluster: None,
         }
     }
 
@@ -83,6 +90,47 @@ impl TcpServer {
         self
     }
 
+    /// Accept share links minted by the API
+    pub fn with_share_links(mut self, gate: Arc<ShareLinkGate>) -> Self {
+        self.share_links = Some(gate);
+        self
+    }
+
+    /// Forward requests for hosts served by other relay nodes of the cluster
+    pub fn with_cluster(mut self, cluster: Arc<ClusterNode>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Serve HTTP requests forwarded by other relay nodes
+    pub fn cluster_ingress(&self) -> ClusterIngress {
+        let registry = self.registry.clone();
+        let localup_manager = self.localup_manager.clone();
+        let pending_requests = self.pending_requests.clone();
+        let db = self.db.clone();
+        let share_links = self.share_links.clone();
+        Arc::new(move |stream, peer_addr| {
+            let handled = Self::handle_http_connection(
+                stream,
+                peer_addr,
+                registry.clone(),
+                localup_manager.clone(),
+                pending_requests.clone(),
+                db.clone(),
+                share_links.clone(),
+                None,
+            );
+            Box::pin(async move {
+                if let Err(e) = handled.await {
+                    error!(
+                        "Failed to handle forwarded connection from {}: {}",
+                        peer_addr, e
+                    );
+                }
+            })
+        })
+    }
+
     /// Start the TCP server
     pub async fn start(&self) -> Result<(), TcpServerError> {
         let listener = TcpListener::bind(self.config.bind_addr)
@@ -109,6 +157,8 @@ impl TcpServer {
                     let localup_manager = self.localup_manager.clone();
                     let pending_requests = self.pending_requests.clone();
                     let db = self.db.clone();
+                    let share_links = self.share_links.clone();
+                    let cluster = self.cluster.clone();
                     tokio::spawn(async move {
                         if let Err(e) = Self::handle_http_connection(
                             socket,
@@ -117,6 +167,8 @@ impl TcpServer {
                             localup_manager,
                             pending_requests,
                             db,
+                            share_links,
+                            cluster,
                         )
                         .await
                         {
@@ -132,14 +184,23 @@ impl TcpServer {
     }
 
     /// Handle HTTP connection with routing
-    async fn handle_http_connection(
-        mut client_socket: TcpStream,
+    ///
+    /// Requests for hosts claimed by another relay node are forwarded to it when
+    /// `cluster` is set; forwarded requests are handled without it.
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_http_connection<S>(
+        mut client_socket: S,
         peer_addr: SocketAddr,
         registry: Arc<RouteRegistry>,
         localup_manager: Option<Arc<TunnelConnectionManager>>,
         pending_requests: Arc<PendingRequests>,
         db: Option<DatabaseConnection>,
-    ) -> Result<(), TcpServerError> {
+        share_links: Option<Arc<ShareLinkGate>>,
+        cluster: Option<Arc<ClusterNode>>,
+    ) -> Result<(), TcpServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         // Read HTTP request to extract Host header
         let mut buffer = vec![0u8; 4096];
         let n = client_socket.read(&mut buffer).await?;
@@ -218,6 +279,28 @@ impl TcpServer {
         let target = registry.lookup(&route_key);
 
         if target.is_err() {
+            if let Some(ref cluster) = cluster {
+                if let Some(owner) = cluster.owner_of(&[route_key]).await {
+                    debug!("Host {} is served by relay node {}", host, owner.node_id);
+                    if let Err(e) = cluster
+                        .forward(
+                            &owner,
+                            ForwardKind::Http,
+                            peer_addr,
+                            &buffer[..n],
+                            client_socket,
+                        )
+                        .await
+                    {
+                        warn!(
+                            "Failed to forward request for {} to relay node {}: {}",
+                            host, owner.node_id, e
+                        );
+                    }
+                    return Ok(());
+                }
+            }
+
             warn!("No route found for host: {}", host);
             let response = b"HTTP/1.1 404 Not Found\r\nContent-Length: 16\r\n\r\nRoute not found\n";
             client_socket.write_all(response).await?;
@@ -248,12 +331,14 @@ impl TcpServer {
                 // Forward through tunnel
                 return Self::handle_localup_request(
                     client_socket,
+                    peer_addr,
                     manager.clone(),
                     pending_requests,
                     localup_id,
                     &request,
                     &buffer[..n],
                     db,
+                    share_links,
                 )
                 .await;
             } else {
@@ -288,28 +373,92 @@ impl TcpServer {
     }
 
     /// Handle HTTP request through tunnel using multi-stream QUIC
-    async fn handle_localup_request(
-        mut client_socket: TcpStream,
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_localup_request<S>(
+        mut client_socket: S,
+        peer_addr: SocketAddr,
         localup_manager: Arc<TunnelConnectionManager>,
         _pending_requests: Arc<PendingRequests>, // Not needed with multi-stream
         localup_id: &str,
         request: &str,
         request_bytes: &[u8],
         db: Option<DatabaseConnection>,
-    ) -> Result<(), TcpServerError> {
+        share_links: Option<Arc<ShareLinkGate>>,
+    ) -> Result<(), TcpServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         debug!("Forwarding request through tunnel: {}", localup_id);
 
+        // Visitor connections and traffic count against the tunnel owner's quota and meter
+        let visitor = match localup_manager.admit_visitor(localup_id).await {
+            Ok(permit) => permit,
+            Err(exceeded) => {
+                debug!("Rejecting request for tunnel {}: {}", localup_id, exceeded);
+                client_socket.write_all(&exceeded.http_response()).await?;
+                return Ok(());
+            }
+        };
+        if let Some(ref visitor) = visitor {
+            visitor.record_request();
+        }
+
+        // Share links grant access without the tunnel's own credentials
+        let mut share_granted = false;
+        if let Some(gate) = share_links {
+            let share_request = localup_http_auth::AuthRequest::parse(request_bytes, false);
+            match gate.check(localup_id, &share_request).await {
+                ShareAccess::Granted => share_granted = true,
+                ShareAccess::Respond(response) => {
+                    client_socket.write_all(&response).await?;
+                    return Ok(());
+                }
+                ShareAccess::None => {}
+            }
+        }
+
         // Check HTTP authentication if configured for this tunnel
+        let mut buffered_request: Option<Vec<u8>> = None;
+        let mut authenticated_request: Option<Vec<u8>> = None;
         if let Some(authenticator) = localup_manager.get_http_authenticator(localup_id).await {
-            if authenticator.requires_auth() {
-                // Parse headers from request
-                let headers = localup_http_auth::parse_headers_from_request(request_bytes);
+            if authenticator.requires_auth() && !share_granted {
+                // Providers that verify the body (e.g. webhook signatures,
+                // login forms) need all of it
+                let head = localup_http_auth::AuthRequest::parse(request_bytes, false);
+                if let Some(limit) = authenticator.body_limit_for(&head) {
+                    let mut buffer = request_bytes.to_vec();
+                    match localup_http_auth::read_request_body(
+                        &mut client_socket,
+                        &mut buffer,
+                        limit,
+                    )
+                    .await
+                    {
+                        Ok(()) => buffered_request = Some(buffer),
+                        Err(localup_http_auth::BodyError::Io(e)) => return Err(e.into()),
+                        Err(e) => {
+                            debug!("Rejecting request for tunnel {}: {}", localup_id, e);
+                            client_socket.write_all(&e.response()).await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                let auth_bytes = buffered_request.as_deref().unwrap_or(request_bytes);
+                let auth_request = localup_http_auth::AuthRequest::parse(auth_bytes, false)
+                    .with_client_ip(peer_addr.ip());
 
                 // Authenticate
-                match authenticator.authenticate(&headers) {
+                match authenticator.authenticate_request(&auth_request).await {
                     localup_http_auth::AuthResult::Authenticated => {
                         debug!("HTTP auth successful for tunnel: {}", localup_id);
                     }
+                    localup_http_auth::AuthResult::AuthenticatedWithHeaders(auth_headers) => {
+                        debug!("HTTP auth successful for tunnel: {}", localup_id);
+                        authenticated_request = Some(localup_http_auth::inject_request_headers(
+                            auth_bytes,
+                            &auth_headers,
+                        ));
+                    }
                     localup_http_auth::AuthResult::Unauthorized(response) => {
                         debug!(
                             "HTTP auth failed for tunnel: {} (type: {})",
@@ -323,6 +472,17 @@ impl TcpServer {
             }
         }
 
+        // Forward the buffered request with any identity headers added by the authenticator
+        let forwarded_request = authenticated_request.or(buffered_request);
+        let authenticated_text;
+        let (request, request_bytes) = match forwarded_request.as_deref() {
+            Some(bytes) => {
+                authenticated_text = String::from_utf8_lossy(bytes);
+                (authenticated_text.as_ref(), bytes)
+            }
+            None => (request, request_bytes),
+        };
+
         // Get tunnel connection
         let connection = match localup_manager.get(localup_id).await {
             Some(c) => c,
@@ -391,8 +551,17 @@ impl TcpServer {
         );
 
         // Bidirectional transparent streaming - passes bytes through unchanged
-        let response_capture =
-            Self::proxy_transparent_stream(client_socket, quic_send, quic_recv, stream_id).await?;
+        if let Some(ref visitor) = visitor {
+            visitor.record(request_bytes.len() as u64, 0);
+        }
+        let response_capture = Self::proxy_transparent_stream(
+            client_socket,
+            quic_send,
+            quic_recv,
+            stream_id,
+            visitor.as_ref(),
+        )
+        .await?;
 
         // Save to database (metrics capture)
         if let Some(ref db_conn) = db {
@@ -483,12 +652,16 @@ impl TcpServer {
     }
 
     /// Bidirectional transparent streaming proxy with response capture
-    async fn proxy_transparent_stream(
-        mut client_socket: TcpStream,
+    async fn proxy_transparent_stream<S>(
+        mut client_socket: S,
         mut quic_send: localup_transport_quic::QuicSendHalf,
         mut quic_recv: localup_transport_quic::QuicRecvHalf,
         stream_id: u32,
-    ) -> Result<ResponseCapture, TcpServerError> {
+        visitor: Option<&VisitorPermit>,
+    ) -> Result<ResponseCapture, TcpServerError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
         let mut client_buffer = vec![0u8; 16384];
         let mut response_buffer = Vec::new();
         let mut headers_parsed = false;
@@ -507,6 +680,9 @@ impl TcpServer {
                         }
                         Ok(n) => {
                             debug!("Forwarding {} bytes from client to tunnel (stream {})", n, stream_id);
+                            if let Some(visitor) = visitor {
+                                visitor.record(n as u64, 0);
+                            }
                             let data_msg = TunnelMessage::HttpStreamData {
                                 stream_id,
                                 data: client_buffer[..n].to_vec(),
@@ -575,6 +751,15 @@ impl TcpServer {
                                 warn!("Failed to flush to client: {}", e);
                                 break;
                             }
+
+                            if let Some(visitor) = visitor {
+                                visitor.record(0, data.len() as u64);
+                                if visitor.is_exhausted() {
+                                    debug!("Traffic quota used up, closing stream {}", stream_id);
+                                    let _ = quic_send.send_message(&TunnelMessage::HttpStreamClose { stream_id }).await;
+                                    break;
+                                }
+                            }
                         }
                         Ok(Some(TunnelMessage::HttpStreamClose { .. })) => {
                             debug!("Tunnel closed stream {}", stream_id);
@@ -0,0 +1,208 @@
+//! Integration tests for relay clusters: two relays sharing a database on
+//! localhost, serving each other's HTTP tunnels
+
+use localup_control::{
+    ClusterConfig, ClusterNode, ForwardKind, PendingRequests, TunnelConnectionManager,
+    TunnelHandler,
+};
+use localup_proto::{Protocol, TunnelConfig, TunnelMessage};
+use localup_router::RouteRegistry;
+use localup_server_tcp::{TcpServer, TcpServerConfig};
+use localup_transport::{
+    TransportConnection, TransportConnector, TransportListener, TransportStream,
+};
+use localup_transport_quic::{QuicConfig, QuicConnection, QuicConnector, QuicListener, QuicStream};
+use sea_orm::DatabaseConnection;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+struct Relay {
+    /// Tunnel control address
+    control_addr: SocketAddr,
+    /// Public HTTP address
+    http_addr: SocketAddr,
+}
+
+async fn free_addr() -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap()
+}
+
+async fn start_relay(node_id: &str, db: DatabaseConnection) -> Relay {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let quic_config = Arc::new(QuicConfig::server_self_signed().unwrap());
+    let cluster = Arc::new(ClusterNode::new(
+        ClusterConfig::new(node_id, "127.0.0.1:0".parse().unwrap(), "cluster-secret"),
+        db.clone(),
+    ));
+
+    let registry = Arc::new(RouteRegistry::new());
+    let manager = Arc::new(TunnelConnectionManager::new());
+    let pending_requests = Arc::new(PendingRequests::new());
+
+    let http_addr = free_addr().await;
+    let http_server = TcpServer::new(
+        TcpServerConfig {
+            bind_addr: http_addr,
+        },
+        registry.clone(),
+    )
+    .with_localup_manager(manager.clone())
+    .with_pending_requests(pending_requests.clone())
+    .with_cluster(cluster.clone());
+    cluster.set_ingress(ForwardKind::Http, http_server.cluster_ingress());
+    tokio::spawn(async move {
+        let _ = http_server.start().await;
+    });
+
+    let handler = Arc::new(
+        TunnelHandler::new(
+            manager,
+            registry,
+            None,
+            "localhost".to_string(),
+            pending_requests,
+        )
+        .with_cluster(cluster.clone()),
+    );
+    cluster.start(quic_config.clone()).await.unwrap();
+
+    let listener = QuicListener::new("127.0.0.1:0".parse().unwrap(), quic_config).unwrap();
+    let control_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((conn, peer_addr)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                handler.handle_connection(Arc::new(conn), peer_addr).await;
+            });
+        }
+    });
+
+    // Give the public server time to bind
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Relay {
+        control_addr,
+        http_addr,
+    }
+}
+
+/// Connect an HTTP tunnel, returning the relay's answer and the connection
+async fn connect(relay: &Relay, subdomain: &str) -> (TunnelMessage, (QuicConnection, QuicStream)) {
+    let connector = QuicConnector::new(Arc::new(QuicConfig::client_insecure())).unwrap();
+    let connection = connector
+        .connect(relay.control_addr, "localhost")
+        .await
+        .unwrap();
+    let mut control_stream = connection.open_stream().await.unwrap();
+
+    control_stream
+        .send_message(&TunnelMessage::Connect {
+            localup_id: format!("tunnel-{}", uuid::Uuid::new_v4()),
+            auth_token: String::new(),
+            protocols: vec![Protocol::Http {
+                subdomain: Some(subdomain.to_string()),
+                custom_domain: None,
+            }],
+            config: TunnelConfig::default(),
+        })
+        .await
+        .unwrap();
+
+    let message = timeout(Duration::from_secs(5), control_stream.recv_message())
+        .await
+        .expect("Timeout waiting for response")
+        .unwrap()
+        .expect("Stream closed without a response");
+    (message, (connection, control_stream))
+}
+
+/// Answer every HTTP request the relay sends through the tunnel with `body`
+fn serve_tunnel(connection: QuicConnection, body: &'static str) {
+    tokio::spawn(async move {
+        while let Ok(Some(mut stream)) = connection.accept_stream().await {
+            tokio::spawn(async move {
+                let Ok(Some(TunnelMessage::HttpStreamConnect { stream_id, .. })) =
+                    stream.recv_message().await
+                else {
+                    return;
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream
+                    .send_message(&TunnelMessage::HttpStreamData {
+                        stream_id,
+                        data: response.into_bytes(),
+                    })
+                    .await;
+                let _ = stream
+                    .send_message(&TunnelMessage::HttpStreamClose { stream_id })
+                    .await;
+            });
+        }
+    });
+}
+
+async fn get(addr: SocketAddr, host: &str) -> String {
+    let mut socket = TcpStream::connect(addr).await.unwrap();
+    socket
+        .write_all(format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", host).as_bytes())
+        .await
+        .unwrap();
+    let mut response = Vec::new();
+    let _ = timeout(Duration::from_secs(5), socket.read_to_end(&mut response)).await;
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_visitors_are_forwarded_to_the_node_holding_the_tunnel() {
+    let db = localup_relay_db::connect("sqlite::memory:").await.unwrap();
+    localup_relay_db::migrate(&db).await.unwrap();
+    let relay_a = start_relay("relay-a", db.clone()).await;
+    let relay_b = start_relay("relay-b", db).await;
+
+    let (message, (connection, _control_stream)) = connect(&relay_b, "myapp").await;
+    assert!(
+        matches!(message, TunnelMessage::Connected { .. }),
+        "{:?}",
+        message
+    );
+    serve_tunnel(connection.clone(), "served by relay-b");
+
+    // Both nodes serve the tunnel
+    let response = get(relay_b.http_addr, "myapp.localhost").await;
+    assert!(response.ends_with("served by relay-b"), "{}", response);
+    let response = get(relay_a.http_addr, "myapp.localhost").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("served by relay-b"), "{}", response);
+
+    // The subdomain can't be taken on another node while relay-b serves it
+    match connect(&relay_a, "myapp").await.0 {
+        TunnelMessage::Disconnect { reason } => {
+            assert!(reason.contains("already in use"), "{}", reason)
+        }
+        other => panic!("Expected Disconnect, got {:?}", other),
+    }
+
+    // Once the tunnel disconnects, it can reconnect to another node
+    connection.close(0, "done").await;
+    let mut reconnected = None;
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if let (TunnelMessage::Connected { .. }, tunnel) = connect(&relay_a, "myapp").await {
+            reconnected = Some(tunnel);
+            break;
+        }
+    }
+    let (connection, _control_stream) = reconnected.expect("Tunnel never moved to relay-a");
+    serve_tunnel(connection, "served by relay-a");
+    let response = get(relay_b.http_addr, "myapp.localhost").await;
+    assert!(response.ends_with("served by relay-a"), "{}", response);
+}
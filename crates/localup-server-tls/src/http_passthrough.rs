@@ -12,7 +12,7 @@ use std::sync::Arc;
 use thiserror::Error;
 use tracing::{debug, error, info, warn};
 
-use localup_control::TunnelConnectionManager;
+use localup_control::{QuotaExceeded, TunnelConnectionManager, VisitorPermit};
 use localup_proto::TunnelMessage;
 use localup_router::{RouteRegistry, SniRouter};
 use localup_transport::{TransportConnection, TransportStream};
@@ -37,6 +37,9 @@ pub enum HttpPassthroughError {
     #[error("Transport error: {0}")]
     TransportError(String),
 
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(#[from] QuotaExceeded),
+
     #[error("Failed to bind to {address}: {reason}\n\nTroubleshooting:\n  • Check if another process is using this port: lsof -i :{port}\n  • Try using a different address or port")]
     BindError {
         address: String,
@@ -286,6 +289,18 @@ impl HttpPassthroughServer {
                 HttpPassthroughError::TransportError(format!("Tunnel not found: {}", localup_id))
             })?;
 
+            // Visitor connections and traffic count against the tunnel owner's quota and meter
+            let visitor = match manager.admit_visitor(localup_id).await {
+                Ok(visitor) => visitor,
+                Err(exceeded) => {
+                    let _ = client_socket.write_all(&exceeded.http_response()).await;
+                    return Err(exceeded.into());
+                }
+            };
+            if let Some(ref visitor) = visitor {
+                visitor.record(n as u64, 0);
+            }
+
             let backend_stream = connection.open_stream().await.map_err(|e| {
                 HttpPassthroughError::TransportError(format!(
                     "Failed to open stream to tunnel {}: {}",
@@ -301,6 +316,7 @@ impl HttpPassthroughServer {
                 &hostname,
                 metrics.bytes_received.clone(),
                 metrics.bytes_sent.clone(),
+                visitor,
             )
             .await
         } else {
@@ -368,6 +384,7 @@ impl HttpPassthroughServer {
 
     /// Forward stream via QUIC tunnel using TlsConnect/TlsData protocol
     /// (Uses TLS message types so TLS tunnel clients can handle HTTP passthrough)
+    #[allow(clippy::too_many_arguments)]
     async fn forward_via_tunnel(
         client_socket: tokio::net::TcpStream,
         mut tunnel_stream: localup_transport_quic::QuicStream,
@@ -376,6 +393,7 @@ impl HttpPassthroughServer {
         hostname: &str,
         bytes_received: Arc<AtomicU64>,
         bytes_sent: Arc<AtomicU64>,
+        visitor: Option<VisitorPermit>,
     ) -> Result<(), HttpPassthroughError> {
         // Generate stream ID for this connection
         static STREAM_COUNTER: AtomicU32 = AtomicU32::new(1);
@@ -415,6 +433,7 @@ impl HttpPassthroughServer {
 
         // Client to tunnel
         let bytes_received_clone = bytes_received.clone();
+        let visitor = visitor.as_ref();
         let client_to_tunnel = async move {
             let mut buf = [0u8; 8192];
             loop {
@@ -427,6 +446,9 @@ impl HttpPassthroughServer {
                     }
                     Ok(n) => {
                         bytes_received_clone.fetch_add(n as u64, Ordering::Relaxed);
+                        if let Some(visitor) = visitor {
+                            visitor.record(n as u64, 0);
+                        }
                         let data_msg = TunnelMessage::TlsData {
                             stream_id,
                             data: buf[..n].to_vec(),
@@ -467,6 +489,14 @@ impl HttpPassthroughServer {
                             debug!("Error writing HTTP data to client: {}", e);
                             break;
                         }
+                        if let Some(visitor) = visitor {
+                            visitor.record(0, data.len() as u64);
+                            if visitor.is_exhausted() {
+                                debug!("Traffic quota used up, closing HTTP stream {}", stream_id);
+                                let _ = client_write.shutdown().await;
+                                break;
+                            }
+                        }
                     }
                     Ok(Some(TunnelMessage::TlsClose {
                         stream_id: msg_stream_id,
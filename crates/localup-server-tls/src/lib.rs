@@ -1,6 +1,8 @@
 //! TLS/SNI tunnel server with HTTP passthrough support
 pub mod http_passthrough;
+mod postgres;
 pub mod server;
+mod termination;
 
 pub use http_passthrough::{HttpPassthroughConfig, HttpPassthroughError, HttpPassthroughServer};
-pub use server::{TlsServer, TlsServerConfig};
+pub use server::{TlsPreamble, TlsServer, TlsServerConfig};
@@ -0,0 +1,141 @@
+//! PostgreSQL SSL negotiation in front of SNI routing
+//!
+//! Postgres clients using `sslmode=require` (or stricter) first send an 8-byte SSLRequest
+//! and wait for a single `S` byte before starting TLS, so the relay answers that request
+//! itself, reads the ClientHello and routes on its SNI. Backends still expect the
+//! SSLRequest, so passthrough connections replay it and strip the backend's `S` reply.
+//! Clients using `sslnegotiation=direct` (PostgreSQL 17+) skip the request and are routed
+//! the same way.
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// SSLRequest packet: length 8, request code 80877103
+pub(crate) const SSL_REQUEST: [u8; 8] = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f];
+
+/// GSSENCRequest packet: length 8, request code 80877104
+const GSSENC_REQUEST: [u8; 8] = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x30];
+
+/// Backend reply accepting an SSLRequest
+pub(crate) const SSL_ACCEPTED: &[u8] = b"S";
+
+/// First byte of a TLS handshake record
+const TLS_HANDSHAKE: u8 = 0x16;
+
+/// Answer the client's SSL negotiation so it starts TLS.
+///
+/// Returns bytes already read that belong to the TLS stream (the first record byte when
+/// the client negotiated TLS directly). Clients that try to start a plaintext session
+/// receive an ErrorResponse, since there is no SNI to route them by.
+pub(crate) async fn accept_ssl_request<S>(stream: &mut S) -> std::io::Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let mut packet = [0u8; 8];
+        stream.read_exact(&mut packet[..1]).await?;
+        if packet[0] == TLS_HANDSHAKE {
+            return Ok(vec![TLS_HANDSHAKE]);
+        }
+        stream.read_exact(&mut packet[1..]).await?;
+
+        if packet == SSL_REQUEST {
+            stream.write_all(SSL_ACCEPTED).await?;
+            return Ok(Vec::new());
+        }
+        if packet == GSSENC_REQUEST {
+            // Decline GSSAPI encryption; libpq falls back to an SSLRequest
+            stream.write_all(b"N").await?;
+            continue;
+        }
+
+        stream
+            .write_all(&error_response(
+                "SSL is required to connect through this relay (use sslmode=require)",
+            ))
+            .await?;
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "client did not request SSL",
+        ));
+    }
+}
+
+/// Build a FATAL ErrorResponse message
+fn error_response(message: &str) -> Vec<u8> {
+    let mut fields = Vec::new();
+    for (code, value) in [
+        (b'S', "FATAL"),
+        (b'V', "FATAL"),
+        (b'C', "08004"),
+        (b'M', message),
+    ] {
+        fields.push(code);
+        fields.extend_from_slice(value.as_bytes());
+        fields.push(0);
+    }
+    fields.push(0);
+
+    let mut response = vec![b'E'];
+    response.extend_from_slice(&(fields.len() as u32 + 4).to_be_bytes());
+    response.extend_from_slice(&fields);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_accept_ssl_request() {
+        let (mut client, mut relay) = tokio::io::duplex(64);
+        client.write_all(&SSL_REQUEST).await.unwrap();
+
+        let leftover = accept_ssl_request(&mut relay).await.unwrap();
+        assert!(leftover.is_empty());
+
+        let mut reply = [0u8; 1];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, SSL_ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_gssenc_then_ssl_request() {
+        let (mut client, mut relay) = tokio::io::duplex(64);
+        client.write_all(&GSSENC_REQUEST).await.unwrap();
+        client.write_all(&SSL_REQUEST).await.unwrap();
+
+        accept_ssl_request(&mut relay).await.unwrap();
+
+        let mut reply = [0u8; 2];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"NS");
+    }
+
+    #[tokio::test]
+    async fn test_direct_tls() {
+        let (mut client, mut relay) = tokio::io::duplex(64);
+        client.write_all(&[0x16, 0x03, 0x01]).await.unwrap();
+
+        let leftover = accept_ssl_request(&mut relay).await.unwrap();
+        assert_eq!(leftover, vec![0x16]);
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_startup_rejected() {
+        let (mut client, mut relay) = tokio::io::duplex(256);
+        // StartupMessage header: length 8, protocol 3.0
+        client.write_all(&[0, 0, 0, 8, 0, 3, 0, 0]).await.unwrap();
+
+        assert!(accept_ssl_request(&mut relay).await.is_err());
+
+        let mut tag = [0u8; 1];
+        client.read_exact(&mut tag).await.unwrap();
+        assert_eq!(tag[0], b'E');
+    }
+
+    #[test]
+    fn test_error_response_length() {
+        let response = error_response("nope");
+        let length = u32::from_be_bytes(response[1..5].try_into().unwrap()) as usize;
+        assert_eq!(length + 1, response.len());
+    }
+}
@@ -2,24 +2,45 @@
 //!
 //! This server accepts incoming TLS connections, extracts the SNI (Server Name Indication)
 //! from the ClientHello, and routes the connection to the appropriate backend service.
+//! Tunnels may also claim an SNI hostname for specific ALPN protocols only, and a relay-wide
+//! default tunnel can receive connections whose SNI matches no route.
 //!
-//! No TLS termination is performed - the TLS stream is forwarded as-is to preserve
-//! end-to-end encryption between the client and backend service.
+//! By default no TLS termination is performed - the TLS stream is forwarded as-is to preserve
+//! end-to-end encryption between the client and backend service. Tunnel routes registered
+//! with `TLS_TERMINATE_METADATA` are decrypted here using the relay's certificates (see
+//! [`TlsServer::with_cert_resolver`]) and forwarded as plaintext.
+//!
+//! A server can also sit in front of protocols that negotiate TLS in-band (see
+//! [`TlsPreamble`]), such as PostgreSQL's SSLRequest, so that many database tunnels
+//! share one public port.
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 use thiserror::Error;
 use tracing::{debug, error, info, warn};
 
-use localup_control::TunnelConnectionManager;
+use localup_control::{
+    ClusterIngress, ClusterNode, ForwardKind, QuotaExceeded, TunnelConnectionManager, VisitorPermit,
+};
 use localup_proto::TunnelMessage;
-use localup_router::{RouteRegistry, SniRouter};
+use localup_router::{
+    RouteKey, RouteRegistry, RouteTarget, SniRouter, SniRouterError, MAX_CLIENT_HELLO_SIZE,
+};
 use localup_transport::{TransportConnection, TransportStream};
 use localup_transport_quic::QuicStream;
+use rustls::server::ResolvesServerCert;
 use sea_orm::DatabaseConnection;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
 
+use crate::postgres;
+use crate::termination::{self, PrefixedStream};
+
+/// Client side of a TLS connection: the raw socket, or the decrypted stream
+trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for T {}
+
 #[derive(Debug, Error)]
 pub enum TlsServerError {
     #[error("IO error: {0}")]
@@ -43,6 +64,9 @@ pub enum TlsServerError {
     #[error("TLS error: {0}")]
     TlsError(String),
 
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(#[from] QuotaExceeded),
+
     #[error("Failed to bind to {address}: {reason}\n\nTroubleshooting:\n  • Check if another process is using this port: lsof -i :{port}\n  • Try using a different address or port")]
     BindError {
         address: String,
@@ -64,6 +88,34 @@ impl Default for TlsServerConfig {
     }
 }
 
+/// Plaintext negotiation clients perform before sending their TLS ClientHello
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsPreamble {
+    /// Clients open with the ClientHello
+    #[default]
+    None,
+    /// PostgreSQL clients send an SSLRequest first (`sslmode=require`)
+    Postgres,
+}
+
+impl TlsPreamble {
+    /// Bytes replayed to passthrough backends, and the reply they must answer with
+    fn backend_handshake(self) -> (&'static [u8], &'static [u8]) {
+        match self {
+            TlsPreamble::None => (&[], &[]),
+            TlsPreamble::Postgres => (&postgres::SSL_REQUEST, postgres::SSL_ACCEPTED),
+        }
+    }
+
+    /// Server a connection is forwarded to on the relay node serving its SNI
+    fn forward_kind(self) -> ForwardKind {
+        match self {
+            TlsPreamble::None => ForwardKind::Tls,
+            TlsPreamble::Postgres => ForwardKind::Postgres,
+        }
+    }
+}
+
 /// Tracks metrics for an individual TLS connection
 struct TlsConnectionMetrics {
     bytes_received: Arc<AtomicU64>,
@@ -76,6 +128,9 @@ pub struct TlsServer {
     sni_router: Arc<SniRouter>,
     tunnel_manager: Option<Arc<TunnelConnectionManager>>,
     db: Option<DatabaseConnection>,
+    cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
+    preamble: TlsPreamble,
+    cluster: Option<Arc<ClusterNode>>,
 }
 
 impl TlsServer {
@@ -87,6 +142,9 @@ impl TlsServer {
             sni_router,
             tunnel_manager: None,
             db: None,
+            cert_resolver: None,
+            preamble: TlsPreamble::None,
+            cluster: None,
         }
     }
 
@@ -102,6 +160,73 @@ impl TlsServer {
         self
     }
 
+    /// Terminate TLS for routes that request it, using certificates from `resolver`
+    /// (typically the HTTPS server's `CustomCertResolver`, which holds custom domain
+    /// and ACME certificates)
+    pub fn with_cert_resolver(mut self, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        self.cert_resolver = Some(resolver);
+        self
+    }
+
+    /// Expect clients to negotiate TLS with `preamble` before sending their ClientHello
+    pub fn with_preamble(mut self, preamble: TlsPreamble) -> Self {
+        self.preamble = preamble;
+        self
+    }
+
+    /// Route connections whose SNI matches no tunnel (or that send no SNI) to `localup_id`
+    pub fn with_default_tunnel(self, localup_id: &str) -> Self {
+        self.sni_router.set_default_route(Some(RouteTarget {
+            localup_id: localup_id.to_string(),
+            target_addr: format!("tunnel:{}", localup_id),
+            metadata: Some("via-tunnel".to_string()),
+            ip_filter: localup_proto::IpFilter::new(),
+        }));
+        self
+    }
+
+    /// Forward connections for SNI names served by other relay nodes of the cluster
+    pub fn with_cluster(mut self, cluster: Arc<ClusterNode>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Serve connections forwarded by other relay nodes
+    ///
+    /// The forwarding node already answered the preamble, if any.
+    pub fn cluster_ingress(&self) -> ClusterIngress {
+        let sni_router = self.sni_router.clone();
+        let tunnel_manager = self.tunnel_manager.clone();
+        let db = self.db.clone();
+        let cert_resolver = self.cert_resolver.clone();
+        let tls_port = self.config.bind_addr.port();
+        let preamble = self.preamble;
+        Arc::new(move |stream, peer_addr| {
+            let sni_router = sni_router.clone();
+            let tunnel_manager = tunnel_manager.clone();
+            let db = db.clone();
+            let cert_resolver = cert_resolver.clone();
+            Box::pin(async move {
+                if let Err(e) = Self::forward_tls_stream(
+                    stream,
+                    &sni_router,
+                    tunnel_manager,
+                    peer_addr,
+                    db,
+                    cert_resolver,
+                    tls_port,
+                    preamble,
+                    None,
+                    true,
+                )
+                .await
+                {
+                    debug!("Error forwarding TLS stream from {}: {}", peer_addr, e);
+                }
+            })
+        })
+    }
+
     /// Get reference to SNI router for registering routes
     pub fn sni_router(&self) -> Arc<SniRouter> {
         self.sni_router.clone()
@@ -126,10 +251,17 @@ impl TlsServer {
                     reason,
                 }
             })?;
-        info!(
-            "✅ TLS server listening on {} (SNI passthrough routing, no certificate termination)",
-            self.config.bind_addr
-        );
+        if self.cert_resolver.is_some() {
+            info!(
+                "✅ TLS server listening on {} (SNI routing, passthrough or relay termination)",
+                self.config.bind_addr
+            );
+        } else {
+            info!(
+                "✅ TLS server listening on {} (SNI passthrough routing, no certificate termination)",
+                self.config.bind_addr
+            );
+        }
 
         // Accept incoming connections
         loop {
@@ -140,7 +272,10 @@ impl TlsServer {
                     let sni_router = self.sni_router.clone();
                     let tunnel_manager = self.tunnel_manager.clone();
                     let db = self.db.clone();
+                    let cert_resolver = self.cert_resolver.clone();
                     let tls_port = self.config.bind_addr.port();
+                    let preamble = self.preamble;
+                    let cluster = self.cluster.clone();
 
                     tokio::spawn(async move {
                         // Forward the raw TLS stream based on SNI extraction
@@ -150,7 +285,11 @@ impl TlsServer {
                             tunnel_manager,
                             peer_addr,
                             db,
+                            cert_resolver,
                             tls_port,
+                            preamble,
+                            cluster,
+                            false,
                         )
                         .await
                         {
@@ -166,48 +305,117 @@ impl TlsServer {
     }
 
     /// Forward TLS stream to backend based on SNI extraction
-    /// This implements SNI passthrough: no TLS termination, just routing based on SNI hostname
-    async fn forward_tls_stream(
-        mut client_socket: tokio::net::TcpStream,
+    /// This implements SNI passthrough: no TLS termination, just routing based on SNI hostname,
+    /// unless the tunnel route asks the relay to terminate TLS
+    ///
+    /// Connections for SNI names claimed by another relay node are forwarded to it when
+    /// `cluster` is set. `forwarded` connections come from another node, which already
+    /// answered the preamble.
+    #[allow(clippy::too_many_arguments)]
+    async fn forward_tls_stream<S: ClientStream + 'static>(
+        mut client_socket: S,
         sni_router: &Arc<SniRouter>,
         tunnel_manager: Option<Arc<TunnelConnectionManager>>,
         peer_addr: SocketAddr,
         db: Option<DatabaseConnection>,
+        cert_resolver: Option<Arc<dyn ResolvesServerCert>>,
         tls_port: u16,
+        preamble: TlsPreamble,
+        cluster: Option<Arc<ClusterNode>>,
+        forwarded: bool,
     ) -> Result<(), TlsServerError> {
-        // Read the ClientHello from the incoming connection
-        let mut client_hello_buf = [0u8; 16384];
-        let n = client_socket
-            .read(&mut client_hello_buf)
-            .await
-            .map_err(|e| TlsServerError::TransportError(e.to_string()))?;
-
-        if n == 0 {
-            debug!("Client closed connection before sending ClientHello");
-            return Ok(());
+        let mut client_hello_buf = Vec::with_capacity(4096);
+        if preamble == TlsPreamble::Postgres && !forwarded {
+            let leftover = postgres::accept_ssl_request(&mut client_socket)
+                .await
+                .map_err(|e| {
+                    TlsServerError::TlsError(format!(
+                        "Postgres SSL negotiation with {} failed: {}",
+                        peer_addr, e
+                    ))
+                })?;
+            client_hello_buf.extend_from_slice(&leftover);
         }
+        let (backend_request, backend_reply) = preamble.backend_handshake();
+
+        // Read the ClientHello from the incoming connection; it may span several reads
+        let mut chunk = [0u8; 16384];
+        let client_hello = loop {
+            let read = client_socket
+                .read(&mut chunk)
+                .await
+                .map_err(|e| TlsServerError::TransportError(e.to_string()))?;
+
+            if read == 0 {
+                if client_hello_buf.is_empty() {
+                    debug!("Client closed connection before sending ClientHello");
+                    return Ok(());
+                }
+                debug!("Client {} closed connection mid-ClientHello", peer_addr);
+                return Err(TlsServerError::SniExtractionFailed);
+            }
+            client_hello_buf.extend_from_slice(&chunk[..read]);
+
+            match SniRouter::parse_client_hello(&client_hello_buf) {
+                Ok(hello) => break hello,
+                Err(SniRouterError::Incomplete)
+                    if client_hello_buf.len() < MAX_CLIENT_HELLO_SIZE =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    debug!("ClientHello parsing failed from {}: {}", peer_addr, e);
+                    return Err(TlsServerError::SniExtractionFailed);
+                }
+            }
+        };
+        let n = client_hello_buf.len();
 
         debug!("Received {} bytes from TLS client", n);
 
-        // Extract SNI from the ClientHello
-        let sni_hostname = SniRouter::extract_sni(&client_hello_buf[..n]).map_err(|e| {
-            debug!("SNI extraction failed from {}: {}", peer_addr, e);
-            TlsServerError::SniExtractionFailed
-        })?;
+        let sni_hostname = client_hello.server_name.clone().unwrap_or_default();
 
         info!(
-            "📥 TLS connection from {} for SNI: {}",
-            peer_addr, sni_hostname
+            "📥 TLS connection from {} for SNI: {} (ALPN: {:?})",
+            peer_addr, sni_hostname, client_hello.alpn_protocols
         );
 
-        // Look up the route for this SNI hostname
-        let route = sni_router.lookup(&sni_hostname).map_err(|e| {
-            debug!(
-                "No route found for SNI {} from {}: {}",
-                sni_hostname, peer_addr, e
-            );
-            TlsServerError::NoRoute(sni_hostname.clone())
-        })?;
+        // Look up the route for this SNI hostname and ALPN
+        let matched = match sni_router.route(&client_hello) {
+            Ok(matched) => matched,
+            Err(e) => {
+                if let Some(ref cluster) = cluster {
+                    let mut keys: Vec<RouteKey> = client_hello
+                        .alpn_protocols
+                        .iter()
+                        .map(|alpn| RouteKey::TlsSniAlpn(sni_hostname.clone(), alpn.clone()))
+                        .collect();
+                    keys.push(RouteKey::TlsSni(sni_hostname.clone()));
+                    if let Some(owner) = cluster.owner_of(&keys).await {
+                        debug!(
+                            "SNI {} is served by relay node {}",
+                            sni_hostname, owner.node_id
+                        );
+                        return cluster
+                            .forward(
+                                &owner,
+                                preamble.forward_kind(),
+                                peer_addr,
+                                &client_hello_buf,
+                                client_socket,
+                            )
+                            .await
+                            .map_err(|e| TlsServerError::TransportError(e.to_string()));
+                    }
+                }
+                debug!(
+                    "No route found for SNI {} from {}: {}",
+                    sni_hostname, peer_addr, e
+                );
+                return Err(TlsServerError::NoRoute(sni_hostname));
+            }
+        };
+        let route = matched.target;
 
         // Check IP filtering
         if !route.is_ip_allowed(&peer_addr) {
@@ -280,6 +488,41 @@ impl TlsServer {
                 TlsServerError::TransportError(format!("Tunnel not found: {}", localup_id))
             })?;
 
+            // Visitor connections and traffic count against the tunnel owner's quota and meter
+            let visitor = manager.admit_visitor(localup_id).await?;
+            if let Some(ref visitor) = visitor {
+                visitor.record(n as u64, 0);
+            }
+
+            // Terminated routes complete the handshake here; the tunnel only sees plaintext
+            // Passthrough backends also receive the preamble, and their reply to it is
+            // swallowed since the client was already answered
+            let (client, initial_data, expected_reply): (Box<dyn ClientStream>, Vec<u8>, &[u8]) =
+                if route.terminates_tls() {
+                    let resolver = cert_resolver.ok_or_else(|| {
+                        TlsServerError::TlsError(
+                            "TLS termination requested but no certificate resolver configured"
+                                .to_string(),
+                        )
+                    })?;
+                    let prefixed = PrefixedStream::new(client_hello_buf.clone(), client_socket);
+                    let tls_stream = termination::acceptor(resolver, matched.alpn.as_deref())
+                        .accept(prefixed)
+                        .await
+                        .map_err(|e| {
+                            TlsServerError::TlsError(format!(
+                                "TLS handshake with {} failed: {}",
+                                peer_addr, e
+                            ))
+                        })?;
+                    debug!("Terminated TLS for SNI {} from {}", sni_hostname, peer_addr);
+                    (Box::new(tls_stream), Vec::new(), &[])
+                } else {
+                    let mut initial = backend_request.to_vec();
+                    initial.extend_from_slice(&client_hello_buf[..n]);
+                    (Box::new(client_socket), initial, backend_reply)
+                };
+
             // Open a new stream on the tunnel
             let backend_stream = connection.open_stream().await.map_err(|e| {
                 TlsServerError::TransportError(format!(
@@ -290,12 +533,15 @@ impl TlsServer {
 
             // Forward using TransportStream methods
             Self::forward_via_transport_stream(
-                client_socket,
+                client,
                 backend_stream,
-                &client_hello_buf[..n],
+                &sni_hostname,
+                &initial_data,
+                expected_reply,
                 peer_addr,
                 metrics.bytes_received.clone(),
                 metrics.bytes_sent.clone(),
+                visitor,
             )
             .await
         } else {
@@ -309,6 +555,24 @@ impl TlsServer {
                     ))
                 })?;
 
+            if !backend_request.is_empty() {
+                backend_socket
+                    .write_all(backend_request)
+                    .await
+                    .map_err(|e| TlsServerError::TransportError(e.to_string()))?;
+                let mut reply = vec![0u8; backend_reply.len()];
+                backend_socket
+                    .read_exact(&mut reply)
+                    .await
+                    .map_err(|e| TlsServerError::TransportError(e.to_string()))?;
+                if reply != backend_reply {
+                    return Err(TlsServerError::TlsError(format!(
+                        "Backend {} refused TLS negotiation",
+                        route.target_addr
+                    )));
+                }
+            }
+
             // Send the ClientHello to the backend
             backend_socket
                 .write_all(&client_hello_buf[..n])
@@ -376,13 +640,13 @@ impl TlsServer {
     }
 
     /// Forward bidirectional TCP streams with metrics tracking
-    async fn forward_tcp_streams(
-        client_socket: tokio::net::TcpStream,
+    async fn forward_tcp_streams<S: ClientStream>(
+        client_socket: S,
         backend_socket: tokio::net::TcpStream,
         bytes_received: Arc<AtomicU64>,
         bytes_sent: Arc<AtomicU64>,
     ) -> Result<(), TlsServerError> {
-        let (mut client_read, mut client_write) = client_socket.into_split();
+        let (mut client_read, mut client_write) = tokio::io::split(client_socket);
         let (mut backend_read, mut backend_write) = backend_socket.into_split();
 
         let bytes_received_clone = bytes_received.clone();
@@ -445,13 +709,21 @@ impl TlsServer {
     }
 
     /// Forward TLS stream through a QUIC tunnel using TransportStream trait
-    async fn forward_via_transport_stream(
-        client_socket: tokio::net::TcpStream,
+    ///
+    /// `client_hello` is the data already read from the client; it is empty for
+    /// terminated connections, whose ClientHello was consumed by the handshake.
+    /// `expected_reply` is stripped from the start of the backend's response.
+    #[allow(clippy::too_many_arguments)]
+    async fn forward_via_transport_stream<S: AsyncRead + AsyncWrite + Send>(
+        client: S,
         mut tunnel_stream: QuicStream,
+        sni: &str,
         client_hello: &[u8],
+        mut expected_reply: &[u8],
         peer_addr: SocketAddr,
         bytes_received: Arc<AtomicU64>,
         bytes_sent: Arc<AtomicU64>,
+        visitor: Option<VisitorPermit>,
     ) -> Result<(), TlsServerError> {
         // Generate stream ID for this tunnel connection
         static STREAM_COUNTER: AtomicU32 = AtomicU32::new(1);
@@ -462,13 +734,10 @@ impl TlsServer {
             stream_id, peer_addr
         );
 
-        // Extract SNI from ClientHello for informational purposes
-        let sni = "unknown".to_string(); // SNI was already extracted and routed; we know this is a valid tunnel
-
         // Send initial TlsConnect message with ClientHello
         let connect_msg = TunnelMessage::TlsConnect {
             stream_id,
-            sni,
+            sni: sni.to_string(),
             client_hello: client_hello.to_vec(),
         };
 
@@ -486,13 +755,14 @@ impl TlsServer {
         );
 
         // Split the client socket for bidirectional forwarding
-        let (mut client_read, mut client_write) = client_socket.into_split();
+        let (mut client_read, mut client_write) = tokio::io::split(client);
 
         // Split the QUIC stream for concurrent send/receive without mutexes
         let (mut tunnel_send, mut tunnel_recv) = tunnel_stream.split();
 
         // Bidirectional forwarding: client to tunnel
         let bytes_received_clone = bytes_received.clone();
+        let visitor = visitor.as_ref();
         let client_to_tunnel = async move {
             let mut buf = [0u8; 8192];
             loop {
@@ -505,6 +775,9 @@ impl TlsServer {
                     }
                     Ok(n) => {
                         bytes_received_clone.fetch_add(n as u64, Ordering::Relaxed);
+                        if let Some(visitor) = visitor {
+                            visitor.record(n as u64, 0);
+                        }
                         let data_msg = TunnelMessage::TlsData {
                             stream_id,
                             data: buf[..n].to_vec(),
@@ -542,11 +815,33 @@ impl TlsServer {
                             );
                             continue;
                         }
+                        let mut data = &data[..];
+                        if !expected_reply.is_empty() {
+                            let len = expected_reply.len().min(data.len());
+                            if data[..len] != expected_reply[..len] {
+                                debug!("Backend refused TLS negotiation (stream {})", stream_id);
+                                let _ = client_write.shutdown().await;
+                                break;
+                            }
+                            expected_reply = &expected_reply[len..];
+                            data = &data[len..];
+                            if data.is_empty() {
+                                continue;
+                            }
+                        }
                         bytes_sent_clone.fetch_add(data.len() as u64, Ordering::Relaxed);
-                        if let Err(e) = client_write.write_all(&data).await {
+                        if let Err(e) = client_write.write_all(data).await {
                             debug!("Error writing TLS data to client: {}", e);
                             break;
                         }
+                        if let Some(visitor) = visitor {
+                            visitor.record(0, data.len() as u64);
+                            if visitor.is_exhausted() {
+                                debug!("Traffic quota used up, closing TLS stream {}", stream_id);
+                                let _ = client_write.shutdown().await;
+                                break;
+                            }
+                        }
                     }
                     Ok(Some(TunnelMessage::TlsClose {
                         stream_id: msg_stream_id,
@@ -633,4 +928,20 @@ mod tests {
         assert!(server.sni_router().has_route("example.com"));
         assert!(!server.sni_router().has_route("unknown.com"));
     }
+
+    #[test]
+    fn test_default_tunnel() {
+        let server = TlsServer::new(TlsServerConfig::default(), Arc::new(RouteRegistry::new()))
+            .with_default_tunnel("catch-all");
+
+        let matched = server
+            .sni_router()
+            .route(&localup_router::ClientHelloInfo {
+                server_name: Some("unknown.example.com".to_string()),
+                alpn_protocols: vec!["h2".to_string()],
+            })
+            .unwrap();
+        assert_eq!(matched.target.localup_id, "catch-all");
+        assert_eq!(matched.target.target_addr, "tunnel:catch-all");
+    }
 }
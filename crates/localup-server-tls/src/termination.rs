@@ -0,0 +1,205 @@
+//! TLS termination for SNI routes served with relay certificates
+//!
+//! The server reads the ClientHello to pick a route before it knows whether the
+//! route terminates TLS, so those bytes are replayed in front of the socket
+//! before the stream is handed to rustls.
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use rustls::server::ResolvesServerCert;
+use rustls::ServerConfig;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::TlsAcceptor;
+
+/// Build an acceptor that serves certificates from `resolver`
+///
+/// `alpn` is the protocol the route was matched for; it is negotiated with the
+/// client so the tunnel sees the same protocol the client asked for.
+pub(crate) fn acceptor(resolver: Arc<dyn ResolvesServerCert>, alpn: Option<&str>) -> TlsAcceptor {
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    if let Some(alpn) = alpn {
+        config.alpn_protocols = vec![alpn.as_bytes().to_vec()];
+    }
+    TlsAcceptor::from(Arc::new(config))
+}
+
+/// Stream that yields already-consumed bytes before reading from `inner`
+pub(crate) struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    pub(crate) fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            pos: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefixedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos < this.prefix.len() {
+            let remaining = &this.prefix[this.pos..];
+            let n = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..n]);
+            this.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use rustls::sign::CertifiedKey;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[derive(Debug)]
+    struct SingleCert(Arc<CertifiedKey>);
+
+    impl ResolvesServerCert for SingleCert {
+        fn resolve(&self, _hello: rustls::server::ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+            Some(self.0.clone())
+        }
+    }
+
+    #[derive(Debug)]
+    struct AcceptAny;
+
+    impl rustls::client::danger::ServerCertVerifier for AcceptAny {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &rustls::pki_types::CertificateDer<'_>,
+            _intermediates: &[rustls::pki_types::CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: rustls::pki_types::UnixTime,
+        ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+            Ok(rustls::client::danger::ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &rustls::pki_types::CertificateDer<'_>,
+            _dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &rustls::pki_types::CertificateDer<'_>,
+            _dss: &rustls::DigitallySignedStruct,
+        ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+            Ok(rustls::client::danger::HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+            rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefixed_stream_replays_prefix() {
+        let (mut remote, local) = tokio::io::duplex(64);
+        let mut stream = PrefixedStream::new(b"hello ".to_vec(), local);
+
+        remote.write_all(b"world").await.unwrap();
+        drop(remote);
+
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_terminates_after_client_hello_was_read() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let cert = localup_cert::generate_self_signed_cert_with_domains(
+            "mqtt.example.com",
+            &["mqtt.example.com"],
+        )
+        .unwrap();
+        let key = rustls::crypto::ring::sign::any_supported_type(&cert.key_der).unwrap();
+        let resolver = Arc::new(SingleCert(Arc::new(CertifiedKey::new(
+            vec![cert.cert_der],
+            key,
+        ))));
+
+        let (client_io, mut server_io) = tokio::io::duplex(16 * 1024);
+
+        let client = tokio::spawn(async move {
+            let mut config = rustls::ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAny))
+                .with_no_client_auth();
+            config.alpn_protocols = vec![b"mqtt".to_vec()];
+            let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+            let name = ServerName::try_from("mqtt.example.com").unwrap();
+            let mut tls = connector.connect(name, client_io).await.unwrap();
+            tls.write_all(b"PING").await.unwrap();
+            let mut reply = [0u8; 4];
+            tls.read_exact(&mut reply).await.unwrap();
+            reply
+        });
+
+        // Consume the ClientHello like the SNI router does
+        let mut hello = vec![0u8; 16 * 1024];
+        let n = server_io.read(&mut hello).await.unwrap();
+        hello.truncate(n);
+        let info = localup_router::SniRouter::parse_client_hello(&hello).unwrap();
+        assert_eq!(info.server_name.as_deref(), Some("mqtt.example.com"));
+        assert_eq!(info.alpn_protocols, vec!["mqtt"]);
+
+        let mut tls = acceptor(resolver, Some("mqtt"))
+            .accept(PrefixedStream::new(hello, server_io))
+            .await
+            .unwrap();
+        assert_eq!(tls.get_ref().1.alpn_protocol(), Some(&b"mqtt"[..]));
+        let mut plaintext = [0u8; 4];
+        tls.read_exact(&mut plaintext).await.unwrap();
+        assert_eq!(&plaintext, b"PING");
+        tls.write_all(b"PONG").await.unwrap();
+        tls.flush().await.unwrap();
+
+        assert_eq!(&client.await.unwrap(), b"PONG");
+    }
+}
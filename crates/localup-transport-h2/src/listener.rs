@@ -2,12 +2,13 @@
 
 use async_trait::async_trait;
 use localup_transport::{
-    TransportConfig, TransportConnector, TransportError, TransportListener, TransportResult,
+    TcpIncoming, TransportConfig, TransportConnector, TransportError, TransportListener,
+    TransportResult,
 };
 use rustls::pki_types::ServerName;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpStream;
 use tracing::{debug, info, warn};
 
 use crate::config::H2Config;
@@ -15,7 +16,7 @@ use crate::connection::{H2ClientConnection, H2Connection, H2ServerConnection};
 
 /// HTTP/2 listener for accepting incoming connections
 pub struct H2Listener {
-    tcp_listener: TcpListener,
+    tcp_listener: TcpIncoming,
     tls_acceptor: tokio_rustls::TlsAcceptor,
     _config: Arc<H2Config>,
 }
@@ -30,28 +31,19 @@ impl std::fmt::Debug for H2Listener {
 
 impl H2Listener {
     pub fn new(bind_addr: SocketAddr, config: Arc<H2Config>) -> TransportResult<Self> {
+        Self::from_incoming(TcpIncoming::bind(bind_addr)?, config)
+    }
+
+    /// Accept HTTP/2 connections from `tcp_listener` instead of binding a socket
+    pub fn from_incoming(
+        tcp_listener: TcpIncoming,
+        config: Arc<H2Config>,
+    ) -> TransportResult<Self> {
         TransportConfig::validate(&*config)?;
 
         let tls_acceptor = config.build_tls_acceptor()?;
 
-        // Create TCP listener synchronously using std
-        let std_listener = std::net::TcpListener::bind(bind_addr).map_err(|e| {
-            let port = bind_addr.port();
-            let address = bind_addr.ip().to_string();
-            TransportError::BindError {
-                address,
-                port,
-                reason: e.to_string(),
-            }
-        })?;
-
-        std_listener.set_nonblocking(true).map_err(|e| {
-            TransportError::ConfigurationError(format!("Failed to set nonblocking: {}", e))
-        })?;
-
-        let tcp_listener = TcpListener::from_std(std_listener).map_err(TransportError::IoError)?;
-
-        let local_addr = tcp_listener.local_addr().map_err(TransportError::IoError)?;
+        let local_addr = tcp_listener.local_addr()?;
         info!("HTTP/2 listener bound to {}", local_addr);
 
         Ok(Self {
@@ -69,11 +61,7 @@ impl TransportListener for H2Listener {
     async fn accept(&self) -> TransportResult<(Self::Connection, SocketAddr)> {
         loop {
             // Accept TCP connection
-            let (tcp_stream, remote_addr) = self
-                .tcp_listener
-                .accept()
-                .await
-                .map_err(TransportError::IoError)?;
+            let (tcp_stream, remote_addr) = self.tcp_listener.accept().await?;
 
             debug!("Incoming TCP connection from {}", remote_addr);
 
@@ -103,9 +91,7 @@ impl TransportListener for H2Listener {
     }
 
     fn local_addr(&self) -> TransportResult<SocketAddr> {
-        self.tcp_listener
-            .local_addr()
-            .map_err(TransportError::IoError)
+        self.tcp_listener.local_addr()
     }
 
     async fn close(&self) {
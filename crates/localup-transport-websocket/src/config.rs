@@ -165,7 +165,7 @@ impl WebSocketConfig {
             }
         }
 
-        let client_crypto = if self.security.verify_server_cert {
+        let mut client_crypto = if self.security.verify_server_cert {
             rustls::ClientConfig::builder()
                 .with_root_certificates(roots)
                 .with_no_client_auth()
@@ -176,6 +176,14 @@ impl WebSocketConfig {
                 .with_no_client_auth()
         };
 
+        // Set ALPN so a single-port relay can route the connection to its WebSocket listener
+        client_crypto.alpn_protocols = self
+            .security
+            .alpn_protocols
+            .iter()
+            .map(|s| s.as_bytes().to_vec())
+            .collect();
+
         Ok(tokio_rustls::TlsConnector::from(Arc::new(client_crypto)))
     }
 
@@ -193,11 +201,18 @@ impl WebSocketConfig {
         let certs = load_certs(Path::new(cert_path))?;
         let key = load_private_key(Path::new(key_path))?;
 
-        let server_crypto = rustls::ServerConfig::builder()
+        let mut server_crypto = rustls::ServerConfig::builder()
             .with_no_client_auth()
             .with_single_cert(certs, key)
             .map_err(|e| TransportError::TlsError(format!("Invalid cert/key: {}", e)))?;
 
+        server_crypto.alpn_protocols = self
+            .security
+            .alpn_protocols
+            .iter()
+            .map(|s| s.as_bytes().to_vec())
+            .collect();
+
         Ok(tokio_rustls::TlsAcceptor::from(Arc::new(server_crypto)))
     }
 }
@@ -2,12 +2,13 @@
 
 use async_trait::async_trait;
 use localup_transport::{
-    TransportConfig, TransportConnector, TransportError, TransportListener, TransportResult,
+    TcpIncoming, TransportConfig, TransportConnector, TransportError, TransportListener,
+    TransportResult,
 };
 use rustls::pki_types::ServerName;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpStream;
 use tokio_rustls::TlsConnector;
 use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
 use tokio_tungstenite::tungstenite::http::StatusCode;
@@ -19,7 +20,7 @@ use crate::connection::WebSocketConnection;
 
 /// WebSocket listener for accepting incoming connections
 pub struct WebSocketListener {
-    tcp_listener: TcpListener,
+    tcp_listener: TcpIncoming,
     tls_acceptor: tokio_rustls::TlsAcceptor,
     config: Arc<WebSocketConfig>,
 }
@@ -34,28 +35,19 @@ impl std::fmt::Debug for WebSocketListener {
 
 impl WebSocketListener {
     pub fn new(bind_addr: SocketAddr, config: Arc<WebSocketConfig>) -> TransportResult<Self> {
+        Self::from_incoming(TcpIncoming::bind(bind_addr)?, config)
+    }
+
+    /// Accept WebSocket connections from `tcp_listener` instead of binding a socket
+    pub fn from_incoming(
+        tcp_listener: TcpIncoming,
+        config: Arc<WebSocketConfig>,
+    ) -> TransportResult<Self> {
         TransportConfig::validate(&*config)?;
 
         let tls_acceptor = config.build_tls_acceptor()?;
 
-        // Create TCP listener synchronously using std
-        let std_listener = std::net::TcpListener::bind(bind_addr).map_err(|e| {
-            let port = bind_addr.port();
-            let address = bind_addr.ip().to_string();
-            TransportError::BindError {
-                address,
-                port,
-                reason: e.to_string(),
-            }
-        })?;
-
-        std_listener.set_nonblocking(true).map_err(|e| {
-            TransportError::ConfigurationError(format!("Failed to set nonblocking: {}", e))
-        })?;
-
-        let tcp_listener = TcpListener::from_std(std_listener).map_err(TransportError::IoError)?;
-
-        let local_addr = tcp_listener.local_addr().map_err(TransportError::IoError)?;
+        let local_addr = tcp_listener.local_addr()?;
         info!(
             "WebSocket listener bound to wss://{}{}",
             local_addr, config.path
@@ -76,11 +68,7 @@ impl TransportListener for WebSocketListener {
     async fn accept(&self) -> TransportResult<(Self::Connection, SocketAddr)> {
         loop {
             // Accept TCP connection
-            let (tcp_stream, remote_addr) = self
-                .tcp_listener
-                .accept()
-                .await
-                .map_err(TransportError::IoError)?;
+            let (tcp_stream, remote_addr) = self.tcp_listener.accept().await?;
 
             debug!("Incoming TCP connection from {}", remote_addr);
 
@@ -131,9 +119,7 @@ impl TransportListener for WebSocketListener {
     }
 
     fn local_addr(&self) -> TransportResult<SocketAddr> {
-        self.tcp_listener
-            .local_addr()
-            .map_err(TransportError::IoError)
+        self.tcp_listener.local_addr()
     }
 
     async fn close(&self) {
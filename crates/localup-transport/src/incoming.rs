@@ -0,0 +1,97 @@
+//! Sources of accepted TCP connections for TCP-based transport listeners
+//!
+//! Listeners usually own their socket, but a relay serving several protocols on one port
+//! accepts connections in a front listener and hands each one to the listener it belongs
+//! to over a channel.
+
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{TransportError, TransportResult};
+
+/// Where a TCP-based listener gets its connections from
+#[derive(Debug)]
+pub enum TcpIncoming {
+    /// A socket bound by the listener itself
+    Listener(TcpListener),
+    /// Connections accepted elsewhere on `local_addr` and forwarded over a channel
+    Channel {
+        receiver: Mutex<mpsc::Receiver<(TcpStream, SocketAddr)>>,
+        local_addr: SocketAddr,
+    },
+}
+
+impl TcpIncoming {
+    /// Bind a TCP socket on `bind_addr`
+    pub fn bind(bind_addr: SocketAddr) -> TransportResult<Self> {
+        // Create TCP listener synchronously using std
+        let std_listener = std::net::TcpListener::bind(bind_addr).map_err(|e| {
+            let port = bind_addr.port();
+            let address = bind_addr.ip().to_string();
+            TransportError::BindError {
+                address,
+                port,
+                reason: e.to_string(),
+            }
+        })?;
+
+        std_listener.set_nonblocking(true).map_err(|e| {
+            TransportError::ConfigurationError(format!("Failed to set nonblocking: {}", e))
+        })?;
+
+        let tcp_listener = TcpListener::from_std(std_listener).map_err(TransportError::IoError)?;
+        Ok(Self::Listener(tcp_listener))
+    }
+
+    /// Receive connections accepted by a front listener bound to `local_addr`
+    pub fn channel(
+        receiver: mpsc::Receiver<(TcpStream, SocketAddr)>,
+        local_addr: SocketAddr,
+    ) -> Self {
+        Self::Channel {
+            receiver: Mutex::new(receiver),
+            local_addr,
+        }
+    }
+
+    /// Wait for the next connection
+    pub async fn accept(&self) -> TransportResult<(TcpStream, SocketAddr)> {
+        match self {
+            Self::Listener(listener) => listener.accept().await.map_err(TransportError::IoError),
+            Self::Channel { receiver, .. } => receiver.lock().await.recv().await.ok_or_else(|| {
+                TransportError::ConnectionError("Front listener closed".to_string())
+            }),
+        }
+    }
+
+    /// Address connections arrive on
+    pub fn local_addr(&self) -> TransportResult<SocketAddr> {
+        match self {
+            Self::Listener(listener) => listener.local_addr().map_err(TransportError::IoError),
+            Self::Channel { local_addr, .. } => Ok(*local_addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_channel_incoming() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel(1);
+        let incoming = TcpIncoming::channel(rx, addr);
+        assert_eq!(incoming.local_addr().unwrap(), addr);
+
+        let _client = TcpStream::connect(addr).await.unwrap();
+        tx.send(listener.accept().await.unwrap()).await.unwrap();
+        let (_stream, peer) = incoming.accept().await.unwrap();
+        assert!(peer.ip().is_loopback());
+
+        drop(tx);
+        assert!(incoming.accept().await.is_err());
+    }
+}
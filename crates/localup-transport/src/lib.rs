@@ -36,6 +36,10 @@
 //! └──────────────┴──────────────┴──────────────┴────────────┘
 //! ```
 
+pub mod incoming;
+
+pub use incoming::TcpIncoming;
+
 use async_trait::async_trait;
 use bytes::Bytes;
 use localup_proto::TunnelMessage;
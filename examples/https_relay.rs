@@ -133,6 +133,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
         ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     match TunnelClient::connect(tunnel_config).await {
@@ -128,6 +128,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         protocols: vec![ProtocolConfig::Tcp {
             local_port: echo_port,
             remote_port: None, // Control plane will allocate a port dynamically
+            hostname: None,
         }],
         auth_token,
         exit_node: ExitNodeConfig::Custom("127.0.0.1:4443".to_string()),
@@ -136,6 +137,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
         ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     match TunnelClient::connect(tunnel_config).await {
@@ -191,6 +191,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             local_port: api_port,
             sni_hostnames: vec!["api.localho.st".to_string()],
             http_port: None,
+            terminate: false,
+            reencrypt: false,
+            alpn: vec![],
         }],
         auth_token,
         exit_node: ExitNodeConfig::Custom("127.0.0.1:4443".to_string()),
@@ -199,6 +202,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         preferred_transport: None,
         http_auth: HttpAuthConfig::None,
         ip_allowlist: Vec::new(),
+        tcp_limits: Default::default(),
+        relay_directory: None,
     };
 
     match TunnelClient::connect(tunnel_config).await {